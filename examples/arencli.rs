@@ -18,8 +18,9 @@ extern crate serde_derive;
 extern crate serde;
 extern crate flame;
 use arendur::prelude::*;
-use clap::{Arg, App};
-use std::path::Path;
+use arendur::component::ComponentPointer;
+use clap::{Arg, App, AppSettings, SubCommand};
+use std::path::{Path, PathBuf};
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::io::Read;
@@ -30,6 +31,7 @@ fn main() {
     env_logger::init().unwrap();
     let matches = App::new("The Arendur CLI"
     ).version("0.1").author("Luxko<luxko@qq.com>")
+    .setting(AppSettings::SubcommandsNegateReqs)
     .arg(
         Arg::with_name("INPUT")
             .help("The scene description input file")
@@ -41,15 +43,217 @@ fn main() {
             .long("thread")
             .value_name("NUM")
             .takes_value(true)
+    ).arg(
+        Arg::with_name("resolution")
+            .help("Override the film resolution")
+            .long("resolution")
+            .value_name("WxH")
+            .takes_value(true)
+    ).arg(
+        Arg::with_name("spp")
+            .help("Override the number of samples per pixel")
+            .long("spp")
+            .value_name("N")
+            .takes_value(true)
+    ).arg(
+        Arg::with_name("crop")
+            .help("Render only the given region of the film, in NDC")
+            .long("crop")
+            .value_name("x0,y0,x1,y1")
+            .takes_value(true)
+    ).arg(
+        Arg::with_name("output")
+            .help("Override the output file")
+            .short("o")
+            .long("output")
+            .value_name("PATH")
+            .takes_value(true)
+    ).arg(
+        Arg::with_name("validate")
+            .help("Only check the scene description, reporting every problem found")
+            .long("validate")
+    ).arg(
+        Arg::with_name("cache-dir")
+            .help("Cache the BVH of each mesh in the given directory, rebuilding stale ones")
+            .long("cache-dir")
+            .value_name("DIR")
+            .takes_value(true)
+    ).arg(
+        Arg::with_name("quiet")
+            .help("Do not report the progress of loading the scene")
+            .short("q")
+            .long("quiet")
+    ).arg(
+        Arg::with_name("dump-samples")
+            .help("Stream the raw samples of the render into the given file, for `refilter`")
+            .long("dump-samples")
+            .value_name("PATH")
+            .takes_value(true)
+    ).subcommand(
+        SubCommand::with_name("refilter")
+            .about("Reconstruct an image from dumped samples with another filter")
+            .arg(
+                Arg::with_name("DUMP")
+                    .help("The sample dump input file")
+                    .required(true)
+            ).arg(
+                Arg::with_name("filter")
+                    .help("The reconstruction filter")
+                    .long("filter")
+                    .possible_values(&["box", "triangle", "gaussian", "mitchell", "lanczos"])
+                    .default_value("mitchell")
+            ).arg(
+                Arg::with_name("radius")
+                    .help("Radius of the filter in pixels")
+                    .long("radius")
+                    .value_name("R")
+                    .takes_value(true)
+            ).arg(
+                Arg::with_name("resolution")
+                    .help("Resolution of the image, that of the dumped film by default")
+                    .long("resolution")
+                    .value_name("WxH")
+                    .takes_value(true)
+            ).arg(
+                Arg::with_name("output")
+                    .help("The output file")
+                    .short("o")
+                    .long("output")
+                    .value_name("PATH")
+                    .default_value("refiltered.png")
+            )
+    ).subcommand(
+        SubCommand::with_name("pick")
+            .about("Print what is seen through the center of a pixel")
+            .arg(
+                Arg::with_name("INPUT")
+                    .help("The scene description input file")
+                    .required(true)
+            ).arg(
+                Arg::with_name("X")
+                    .help("Column of the pixel")
+                    .required(true)
+            ).arg(
+                Arg::with_name("Y")
+                    .help("Row of the pixel")
+                    .required(true)
+            ).arg(
+                Arg::with_name("resolution")
+                    .help("Override the film resolution the pixel lies in")
+                    .long("resolution")
+                    .value_name("WxH")
+                    .takes_value(true)
+            )
     ).get_matches();
 
+    if let Some(matches) = matches.subcommand_matches("refilter") {
+        if let Err(e) = refilter_dump(matches) {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(matches) = matches.subcommand_matches("pick") {
+        if let Err(e) = pick(matches) {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let input_filename = matches.value_of("INPUT").unwrap();
     if let Some(threads) = matches.value_of("thread") {
         let threads = usize::from_str(threads.as_ref()).expect("Invalid input: thread needs to be a number");
         rayon::initialize(rayon::Configuration::new().num_threads(threads)).unwrap();
     }
 
-    let (scene, mut renderer) = parse_input(input_filename.as_ref()).expect("");
+    let overrides = match Overrides::from_matches(&matches) {
+        Ok(overrides) => overrides,
+        Err(e) => {
+            println!("Invalid input: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let buf = match read_input(input_filename.as_ref()) {
+        Ok(buf) => buf,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let (scenedesc, report) = validate(&buf, &overrides);
+    if !report.issues.is_empty() {
+        println!("{}", report);
+    }
+    if matches.is_present("validate") {
+        if !report.has_errors() { println!("{} is valid", input_filename); }
+        std::process::exit(if report.has_errors() { 1 } else { 0 });
+    }
+    let scenedesc = match scenedesc {
+        Some(scenedesc) if !report.has_errors() => scenedesc,
+        _ => std::process::exit(1),
+    };
+
+    if !scenedesc.sweeps.is_empty() {
+        let value: serde_json::Value = serde_json::from_str(&buf).expect("validated description");
+        let mut overrides = overrides;
+        if overrides.dump_samples.take().is_some() {
+            println!("samples of sweeps are not dumped");
+        }
+        let cache = Arc::new(SceneCache::new());
+        overrides.cache = Some(cache.clone());
+        let count = scenedesc.sweeps[0].values().len();
+        println!("Start rendering {} values", count);
+        let sudato = Instant::now();
+        for i in 0..count {
+            match render_sweep(&value, &scenedesc.sweeps, i, &overrides) {
+                Ok(output) => println!("saved {}", output),
+                Err(e) => {
+                    println!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        let duration = sudato.elapsed();
+        println!(
+            "Done! {} values rendered, {:?}. Time used: {:.4}s", count, cache.stats(),
+            duration.as_secs() as f64 + (duration.subsec_nanos() as f64/1_000_000_000.0f64)
+        );
+        return;
+    }
+    if let Some(animation) = scenedesc.animation.clone() {
+        let mut scenedesc = scenedesc;
+        let scene = load_scene(&mut scenedesc, &overrides);
+        println!("{}", scene.summary());
+        if let Err(e) = std::fs::create_dir_all(&animation.directory) {
+            println!("failed creating {}: {}", animation.directory, e);
+            std::process::exit(1);
+        }
+        if overrides.dump_samples.is_some() {
+            println!("samples of animations are not dumped");
+        }
+        let driver = AnimationDriver::new(scenedesc.camera.clone(), animation.path, &animation.directory);
+        println!("Start rendering {} frames", driver.frames());
+        let sudato = Instant::now();
+        let saved = driver.render(&scene, |camera, path, _| {
+            build_renderer(&scenedesc, camera, path, None).expect("validated configuration")
+        });
+        let duration = sudato.elapsed();
+        println!(
+            "Done! {} frames saved. Time used: {:.4}s", saved,
+            duration.as_secs() as f64 + (duration.subsec_nanos() as f64/1_000_000_000.0f64)
+        );
+        return;
+    }
+    let (scene, mut renderer) = match build_scene(scenedesc, &overrides) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    println!("{}", scene.summary());
     println!("Start rendering");
     let sudato = Instant::now();
     renderer.render(&scene);
@@ -61,41 +265,270 @@ fn main() {
     );
 }
 
+/// reconstruct an image from the sample dump given to the `refilter` subcommand
+fn refilter_dump(matches: &clap::ArgMatches) -> Result<(), String> {
+    let input = matches.value_of("DUMP").unwrap();
+    let dump = SampleDump::load(input).map_err(|e| format!("Reading {} failed: {}", input, e))?;
+    let resolution = match matches.value_of("resolution") {
+        Some(resolution) => parse_resolution(resolution)?,
+        None => dump.resolution,
+    };
+    let kind = matches.value_of("filter").unwrap();
+    let radius = match matches.value_of("radius") {
+        Some(radius) => match Float::from_str(radius) {
+            Ok(r) if r > 0. as Float => r,
+            _ => return Err(format!("radius needs to be a positive number, got `{}`", radius)),
+        },
+        None => if kind == "box" { 0.5 as Float } else { 2. as Float },
+    };
+    let radius = Vector2f::new(radius, radius);
+    let filter: Arc<Filter> = match kind {
+        "box" => Arc::new(BoxFilter::new(radius)),
+        "triangle" => Arc::new(TriangleFilter::new(radius)),
+        "gaussian" => Arc::new(GaussianFilter::new(2. as Float, radius)),
+        "mitchell" => Arc::new(MitchellFilter::new(radius, 1. as Float / 3. as Float, 1. as Float / 3. as Float)),
+        _ => Arc::new(LanczosSincFilter::new(radius, 3. as Float)),
+    };
+    println!("Refiltering {} samples", dump.samples.len());
+    let output = matches.value_of("output").unwrap();
+    refilter(&dump, filter, resolution).save(output)
+        .map_err(|e| format!("Saving {} failed: {}", output, e))?;
+    println!("Refiltered image saved at {}", output);
+    Ok(())
+}
+
+/// print the component, position, normals, uv and material seen through
+/// the center of the pixel given to the `pick` subcommand
+fn pick(matches: &clap::ArgMatches) -> Result<(), String> {
+    let coordinate = |name: &str| {
+        let value = matches.value_of(name).unwrap();
+        usize::from_str(value).map_err(|_| format!("{} needs to be a pixel index, got `{}`", name, value))
+    };
+    let (x, y) = (coordinate("X")?, coordinate("Y")?);
+    let mut overrides = Overrides::default();
+    if let Some(resolution) = matches.value_of("resolution") {
+        overrides.resolution = Some(parse_resolution(resolution)?);
+    }
+    let input = matches.value_of("INPUT").unwrap();
+    let buf = read_input(input.as_ref()).map_err(|e| e.to_string())?;
+    let (scenedesc, report) = validate(&buf, &overrides);
+    let mut scenedesc = match scenedesc {
+        Some(scenedesc) if !report.has_errors() => scenedesc,
+        _ => return Err(report.to_string()),
+    };
+    let (scene, named) = load_named_scene(&mut scenedesc, &overrides);
+    let camera: Arc<Camera> = if let Some(interocular) = scenedesc.interocular {
+        Arc::new(StereoCam::new(scenedesc.camera.clone(), interocular))
+    } else {
+        Arc::new(scenedesc.camera.clone())
+    };
+    let resolution = camera.get_film().resolution();
+    if x >= resolution.x || y >= resolution.y {
+        return Err(format!("pixel ({}, {}) lies outside the {}x{} film", x, y, resolution.x, resolution.y));
+    }
+    let pfilm = Point2f::new(x as Float + 0.5 as Float, y as Float + 0.5 as Float);
+    let ray = camera.ray_for_film_point(pfilm, Point2f::new(0.5 as Float, 0.5 as Float));
+    let mut hit_ray = ray.clone();
+    let si = match scene.aggregate.intersect_ray(&mut hit_ray) {
+        Some(si) => si,
+        None => {
+            println!("nothing seen through pixel ({}, {})", x, y);
+            return Ok(());
+        },
+    };
+    // the component hit first on its own
+    let mut nearest: Option<(Float, &str)> = None;
+    for &(ref name, ref ptrs) in &named {
+        for ptr in ptrs {
+            let mut ray = ray.clone();
+            if ptr.intersect_ray(&mut ray).is_some() {
+                let t = ray.max_extend();
+                if nearest.map_or(true, |(tn, _)| t < tn) {
+                    nearest = Some((t, name));
+                }
+            }
+        }
+    }
+    let id = si.primitive_hit.map_or(0, |primitive| scene.primitive_id(primitive));
+    match nearest {
+        Some((_, name)) => {
+            println!("component: {} (primitive id {})", name, id);
+            println!("material: {}", material_name(&scenedesc, name));
+        },
+        None => println!("component: unknown (primitive id {})", id),
+    }
+    println!("distance: {}", hit_ray.max_extend());
+    println!("position: {:?}", si.basic.pos);
+    println!("normal: {:?}", si.basic.norm);
+    println!("shading normal: {:?}", si.shading_norm);
+    println!("uv: {:?}", si.uv);
+    Ok(())
+}
+
+/// name of the material of component `name`, or where it comes from
+fn material_name(scenedesc: &SceneDesc, name: &str) -> String {
+    let component = scenedesc.components.iter().find(|c| c.name == name).and_then(|c| c.value.as_ref());
+    match component {
+        Some(&ComponentDesc::Shaped{ref material, ..}) => material.name.clone(),
+        Some(&ComponentDesc::Mesh{ref filename, ..}) => format!("from {}", filename),
+        Some(&ComponentDesc::Transformed{ref original, ..}) => material_name(scenedesc, original),
+        Some(&ComponentDesc::Group{..}) => format!("one of group {}", name),
+        None => "unknown".to_owned(),
+    }
+}
+
+fn parse_resolution(resolution: &str) -> Result<Point2<usize>, String> {
+    let dims: Vec<_> = resolution.split('x').map(usize::from_str).collect();
+    match dims.as_slice() {
+        &[Ok(w), Ok(h)] if w > 0 && h > 0 => Ok(Point2::new(w, h)),
+        _ => Err(format!("resolution needs to be WxH with positive integers, got `{}`", resolution)),
+    }
+}
+
+/// Command-line overrides of the scene description
+#[derive(Default)]
+struct Overrides {
+    resolution: Option<Point2<usize>>,
+    spp: Option<usize>,
+    crop: Option<BBox2f>,
+    output: Option<String>,
+    cache_dir: Option<String>,
+    dump_samples: Option<String>,
+    progress: bool,
+    /// meshes, their hierarchies and images shared with other scenes
+    cache: Option<Arc<SceneCache>>,
+}
+
+impl Overrides {
+    fn from_matches(matches: &clap::ArgMatches) -> Result<Overrides, String> {
+        let mut ret = Overrides::default();
+        if let Some(resolution) = matches.value_of("resolution") {
+            ret.resolution = Some(parse_resolution(resolution)?);
+        }
+        if let Some(spp) = matches.value_of("spp") {
+            match usize::from_str(spp) {
+                Ok(spp) if spp > 0 => ret.spp = Some(spp),
+                _ => return Err(format!("spp needs to be a positive integer, got `{}`", spp)),
+            }
+        }
+        if let Some(crop) = matches.value_of("crop") {
+            let coords: Vec<_> = crop.split(',').map(Float::from_str).collect();
+            match coords.as_slice() {
+                &[Ok(x0), Ok(y0), Ok(x1), Ok(y1)]
+                    if 0. as Float <= x0 && x0 < x1 && x1 <= 1. as Float
+                    && 0. as Float <= y0 && y0 < y1 && y1 <= 1. as Float =>
+                {
+                    ret.crop = Some(BBox2f::new(Point2f::new(x0, y0), Point2f::new(x1, y1)));
+                },
+                _ => return Err(format!(
+                    "crop needs to be x0,y0,x1,y1 with 0 <= x0 < x1 <= 1 and 0 <= y0 < y1 <= 1, got `{}`", crop
+                )),
+            }
+        }
+        ret.output = matches.value_of("output").map(|s| s.to_owned());
+        ret.cache_dir = matches.value_of("cache-dir").map(|s| s.to_owned());
+        ret.dump_samples = matches.value_of("dump-samples").map(|s| s.to_owned());
+        ret.progress = !matches.is_present("quiet");
+        Ok(ret)
+    }
+
+    fn apply(&self, scenedesc: &mut SceneDesc) {
+        if let Some(resolution) = self.resolution {
+            scenedesc.camera.set_resolution(resolution);
+        }
+        if let Some(crop) = self.crop {
+            scenedesc.camera.set_crop_window(crop);
+        }
+        if let Some(spp) = self.spp {
+            scenedesc.sampler.set_sample_per_pixel(spp);
+        }
+        if let Some(ref output) = self.output {
+            scenedesc.outputfilename = output.clone();
+        }
+    }
+}
+
 #[derive(Debug)]
 enum ParsingError {
     IOError(std::io::Error),
-    DecodeError(serde_json::error::Error),
-}
-
-fn parse_input(filename: &Path) -> Result<(Scene, StdPTRenderer), ParsingError> {
-    let buf = {
-        let mut file = std::fs::File::open(filename).map_err(|e| 
-            ParsingError::IOError(e)
-        )?;
-        let mut buf = String::new();
-        let _ = file.read_to_string(&mut buf).map_err(|e|
-            ParsingError::IOError(e)
-        )?;
-        buf
-    };
-    let scenedesc: SceneDesc = serde_json::from_str(buf.as_ref()).map_err(|e|
-        ParsingError::DecodeError(e)
+    ConfigError(ConfigError),
+}
+
+impl std::fmt::Display for ParsingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            ParsingError::IOError(ref e) => write!(f, "failed reading the scene file: {}", e),
+            ParsingError::ConfigError(ref e) => write!(f, "invalid renderer configuration: {}", e),
+        }
+    }
+}
+
+fn read_input(filename: &Path) -> Result<String, ParsingError> {
+    let mut file = std::fs::File::open(filename).map_err(|e| 
+        ParsingError::IOError(e)
     )?;
+    let mut buf = String::new();
+    let _ = file.read_to_string(&mut buf).map_err(|e|
+        ParsingError::IOError(e)
+    )?;
+    Ok(buf)
+}
 
+/// build the scene and the renderer out of a validated description,
+/// with `overrides` applied
+fn build_scene(mut scenedesc: SceneDesc, overrides: &Overrides) -> Result<(Scene, Box<Renderer>), ParsingError> {
+    let scene = load_scene(&mut scenedesc, overrides);
+    let camera: Arc<Camera> = if let Some(interocular) = scenedesc.interocular {
+        Arc::new(StereoCam::new(scenedesc.camera.clone(), interocular))
+    } else {
+        Arc::new(scenedesc.camera.clone())
+    };
+    let renderer = build_renderer(&scenedesc, camera, &scenedesc.outputfilename, overrides.dump_samples.as_ref())?;
+    Ok((scene, renderer))
+}
+
+/// build the scene out of a validated description, focusing its camera
+/// if asked to
+fn load_scene(scenedesc: &mut SceneDesc, overrides: &Overrides) -> Scene {
+    load_named_scene(scenedesc, overrides).0
+}
+
+/// build the scene as `load_scene` does, along with the primitives
+/// making up each top-level component, by name
+fn load_named_scene(
+    scenedesc: &mut SceneDesc, overrides: &Overrides
+) -> (Scene, Vec<(String, Vec<ComponentPointer>)>) {
+    if scenedesc.camera_relative {
+        scenedesc.recenter();
+    }
     let mut meshes = HashMap::new();
     let mut primitives: HashMap<_, Arc<Composable>> = HashMap::new();
     // let mut transformed =  HashMap::new();
     // let mut shapes = HashMap::new();
     let mut materials = HashMap::new();
-    let mut rgbtextures = HashMap::new();
-    let mut graytextures = HashMap::new();
-    let mut rgbrefs = HashMap::new();
-    let mut grayrefs = HashMap::new();
+    let mut textures = Textures::default();
+    textures.fallback.set_mode(scenedesc.texture_fallback);
+    textures.cache = overrides.cache.clone();
+    for component in scenedesc.components.iter() {
+        if let Some(ComponentDesc::Shaped{ref material, ref light, ..}) = component.value {
+            if let Some(ref material) = material.value {
+                material.declare_textures(&mut textures);
+            }
+            if let Some(ref light) = *light {
+                textures.declare_rgb(light);
+            }
+        }
+    }
 
-    let mut lights = Vec::new();
+    let mut lights: Vec<Arc<Light>> = Vec::new();
+    // light group of each light
+    let mut groups = Vec::new();
+    // indices into `lights` of each emissive component
+    let mut component_lights: HashMap<String, Vec<usize>> = HashMap::new();
 
     for light in scenedesc.lights.iter() {
         lights.push(light.to_arc());
+        groups.push(light.group());
     }
 
     for component in scenedesc.components.iter() {
@@ -107,57 +540,86 @@ fn parse_input(filename: &Path) -> Result<(Scene, StdPTRenderer), ParsingError>
         let component = component.value.as_ref().unwrap();
         match *component {
             ComponentDesc::Mesh{
-                ref filename, transform
+                ref filename, transform, generate_normals, normal_angle, shading, visibility,
+                light_group, cull_backfaces, offset_shadows, emission_side, light_profile_resolution
             } => {
                 let transform = transform.unwrap_or(Matrix4f::identity());
-                if let Ok(ptrs) = arendur::component::load_obj(
-                    filename.as_ref(), transform
-                ) {
-                    meshes.insert(name, ptrs);
+                let options = arendur::component::ObjOptions{
+                    generate_normals: if generate_normals { Some(normal_angle) } else { None },
+                    shading: shading,
+                    visibility: visibility.to_flags(),
+                    cull_backfaces: cull_backfaces,
+                    offset_shadows: offset_shadows,
+                    emission_side: emission_side,
+                    light_profile_resolution: light_profile_resolution,
+                    fallback: scenedesc.texture_fallback,
+                };
+                let loaded = if let Some(ref cache) = overrides.cache {
+                    load_obj_cached(cache, filename.as_ref(), transform, options)
                 } else {
-                    println!("load mesh {} from {} failed.", name, filename);
+                    let mut bar = ProgressBar::new(&name, overrides.progress);
+                    arendur::component::load_obj_with_progress(
+                        filename.as_ref(), transform, options, &mut |p| bar.update(p)
+                    )
+                };
+                match loaded {
+                    Ok((ptrs, meshlights)) => {
+                        let mut indices = Vec::new();
+                        for light in meshlights {
+                            indices.push(lights.len());
+                            lights.push(light);
+                            groups.push(light_group);
+                        }
+                        component_lights.insert(name.clone(), indices);
+                        let ptrs = if let Some(ref cache) = overrides.cache {
+                            let bvh: Arc<Composable> = Arc::new(build_bvh_cached(cache, &ptrs, BVHStrategy::SAH));
+                            vec![bvh.into()]
+                        } else if let Some(ref dir) = overrides.cache_dir {
+                            vec![cached_bvh(dir.as_ref(), &name, &ptrs).into()]
+                        } else {
+                            ptrs
+                        };
+                        meshes.insert(name, ptrs);
+                    },
+                    Err(e) => println!("load mesh {} from {} failed: {}", name, filename, e),
                 }
             },
             ComponentDesc::Shaped{
-                ref shape, ref material, ref light,ref transform
+                ref shape, ref material, ref light, emission_side, ref transform, shadow_catcher,
+                visibility, light_group
             } => {
                 let material = material.find_or_insert_with(&mut materials, |m| {
-                    m.to_arc(&mut rgbtextures, &mut graytextures, &mut rgbrefs, &mut grayrefs)
+                    m.to_arc(&mut textures)
                 });
-                let lt = light.clone().and_then(|l| l.to_arc(&mut rgbtextures, &mut rgbrefs));
+                let lt = light.clone().and_then(|l| l.to_arc(&mut textures));
                 if let Some(material) = material {
+                    let lt = lt.or_else(|| material.emission());
+                    let visibility = visibility.to_flags();
                     let sp = match *shape {
-                        ShapeDesc::Sphere(ref s) => {
-                            ShapedPrimitive::new(
-                                s.clone(), material.clone(), lt
+                        ShapeDesc::Sphere(ref s) => shaped_component(
+                            s.clone(), material.clone(), lt, emission_side,
+                            shadow_catcher, visibility, *transform
+                        ),
+                        ShapeDesc::Dome(ref d) => shaped_component(
+                            d.clone(), material.clone(), lt, emission_side,
+                            shadow_catcher, visibility, *transform
+                        ),
+                        ShapeDesc::Sdf{ref node, max_steps, epsilon} => {
+                            let mut sdf = SdfShape::from_node(node);
+                            if let Some(max_steps) = max_steps { sdf.max_steps = max_steps; }
+                            if let Some(epsilon) = epsilon { sdf.epsilon = epsilon; }
+                            shaped_component(
+                                sdf, material.clone(), lt, emission_side,
+                                shadow_catcher, visibility, *transform
                             )
-                        }
-                    };
-                    let sp: Arc<Composable> = if let Some(transform) = *transform {
-                        if let Some(inv) = transform.invert() {
-                            let sp = Arc::new(TransformedComposable::new(
-                                sp, Arc::new(transform), Arc::new(inv)
-                            ));
-                            if sp.is_emissive() {
-                                lights.push(sp.clone());
-                            }
-                            sp
-                        } else {
-                            let sp = Arc::new(sp);
-                            if sp.is_emissive() {
-                                lights.push(sp.clone());
-                            }
-                            sp
-                        }
-                    } else {
-                        let sp = Arc::new(sp);
-                        if sp.is_emissive() {
-                            lights.push(sp.clone());
-                        }
-                        sp
+                        },
                     };
-                    
-                    
+                    if let Some(light) = sp.1 {
+                        component_lights.insert(name.clone(), vec![lights.len()]);
+                        lights.push(light);
+                        groups.push(light_group);
+                    }
+                    let sp = sp.0;
                     primitives.insert(name, sp);
                 } else {
                     println!("load shape {} failed", name);
@@ -172,35 +634,207 @@ fn parse_input(filename: &Path) -> Result<(Scene, StdPTRenderer), ParsingError>
                     continue;
                 }
                 let inv = inv.unwrap();
+                let (transform, inv) = (Arc::new(transform), Arc::new(inv));
                 let t = if let Some(orishape) = primitives.get(original) {
                     Arc::new(TransformedComposable::new(
-                        orishape.clone(), Arc::new(transform), Arc::new(inv))
+                        orishape.clone(), transform.clone(), inv.clone())
                     )
                 } else {
                     println!("load transformed {} fialed, original doesn't exists", name);
                     continue;
                 };
+                // the copy emits as the original does
+                let originals = component_lights.get(original).cloned().unwrap_or_default();
+                let mut indices = Vec::with_capacity(originals.len());
+                for i in originals {
+                    indices.push(lights.len());
+                    let light = lights[i].clone();
+                    lights.push(Arc::new(TransformedComposable::new(light, transform.clone(), inv.clone())));
+                    let group = groups[i];
+                    groups.push(group);
+                }
+                component_lights.insert(name.clone(), indices);
                 primitives.insert(name, t);
-            }
+            },
+            ComponentDesc::Group{
+                ref children, transform
+            } => {
+                let inv = match transform {
+                    Some(transform) => match transform.invert() {
+                        Some(inv) => Some((transform, inv)),
+                        None => {
+                            println!("load group {} failed, invalid matrix invert", name);
+                            continue;
+                        },
+                    },
+                    None => None,
+                };
+                if children.is_empty() {
+                    println!("load group {} failed, no children", name);
+                    continue;
+                }
+                if let Some(child) = children.iter().find(|c| !meshes.contains_key(*c) && !primitives.contains_key(*c)) {
+                    println!("load group {} failed, child {} doesn't exist or is already grouped", name, child);
+                    continue;
+                }
+                // children are moved into the group
+                let mut ptrs = Vec::new();
+                let mut indices = Vec::new();
+                for child in children {
+                    if let Some(mut mesh) = meshes.remove(child) {
+                        ptrs.append(&mut mesh);
+                    } else if let Some(primitive) = primitives.remove(child) {
+                        ptrs.push(primitive.into());
+                    }
+                    indices.append(&mut component_lights.remove(child).unwrap_or_default());
+                }
+                let mut group = Group::new(ptrs, indices.iter().map(|&i| lights[i].clone()).collect());
+                if let Some((transform, inv)) = inv {
+                    group.set_transform(Arc::new(transform), Arc::new(inv));
+                }
+                for (&i, light) in indices.iter().zip(group.collect_lights(&Matrix4f::identity())) {
+                    lights[i] = light;
+                }
+                component_lights.insert(name.clone(), indices);
+                primitives.insert(name, Arc::new(group));
+            },
+        }
+    }
+
+    let mut named: Vec<(String, Vec<ComponentPointer>)> = meshes.into_iter().collect();
+    named.extend(primitives.into_iter().map(|(name, primitive)| (name, vec![primitive.into()])));
+    let components: Vec<ComponentPointer> = named.iter().flat_map(|n| n.1.iter().cloned()).collect();
+    let aggregate: Arc<Composable> = match scenedesc.acceleration {
+        Acceleration::BVH => {
+            let mut bar = ProgressBar::new("scene", overrides.progress);
+            Arc::new(BVH::with_progress(
+                &components, BVHStrategy::SAH, &CostModel::default(), &mut |p| bar.update(p)
+            ))
+        },
+        Acceleration::Grid => Arc::new(UniformGrid::new(&components)),
+    };
+
+    let mut scene = Scene::new(lights, aggregate);
+    for (i, group) in groups.into_iter().enumerate() {
+        if group as usize >= MAX_LIGHT_GROUPS {
+            println!("light group {} out of range, merged into {}", group, MAX_LIGHT_GROUPS - 1);
+        }
+        scene.set_light_group(i, group.min(MAX_LIGHT_GROUPS as u8 - 1));
+    }
+    if let Some(pndc) = scenedesc.autofocus {
+        let resolution = scenedesc.camera.get_film().resolutionf();
+        let pfilm = Point2f::new(pndc.x * resolution.x, pndc.y * resolution.y);
+        match scenedesc.camera.autofocus(&scene, pfilm) {
+            Ok(focal_distance) => println!("autofocused at distance {}", focal_distance),
+            Err(e) => println!("autofocus failed: {}", e),
         }
     }
+    if scenedesc.alpha_background {
+        scenedesc.camera.get_film_mut().set_alpha(true);
+    }
+    (scene, named)
+}
 
-    let mut components = Vec::new();
-    for mut mesh in meshes {
-        components.append(&mut mesh.1);
+/// build the renderer of a loaded description, viewing through `camera`
+/// and saving into `output`. Path tracing unless ambient occlusion is asked for
+fn build_renderer<P: AsRef<Path> + ?Sized>(
+    scenedesc: &SceneDesc, camera: Arc<Camera>, output: &P, dump_samples: Option<&String>
+) -> Result<Box<Renderer>, ParsingError> {
+    let mut builder = RendererBuilder::new(scenedesc.sampler.clone(), camera)
+        .output(output)
+        .max_depth(scenedesc.max_depth)
+        .multithreaded(scenedesc.multithreaded)
+        .regularization(scenedesc.regularization)
+        .light_groups(scenedesc.output_light_groups)
+        .id_pass(scenedesc.output_id_pass)
+        .albedo_pass(scenedesc.output_albedo_pass)
+        .path_channels(scenedesc.output_path_channels.clone())
+        .path_filter(scenedesc.path_filter)
+        .preview_interval(scenedesc.preview_interval.map(|secs| {
+            Duration::from_millis((secs.max(0. as Float) * 1000. as Float) as u64)
+        }))
+        .sample_dump(dump_samples.map(PathBuf::from))
+        .bloom(scenedesc.bloom)
+        .adaptive(scenedesc.adaptive)
+        .prepass(scenedesc.prepass);
+    if let Some(depths) = scenedesc.lobe_depths {
+        builder = builder.lobe_depths(depths);
     }
-    for primitive in primitives {
-        components.push(primitive.1.into());
+    if let Some(ao) = scenedesc.ambient_occlusion {
+        let renderer = builder.ambient_occlusion(ao).build_ao().map_err(|e| ParsingError::ConfigError(e))?;
+        return Ok(Box::new(renderer));
     }
-    let bvh = BVH::new(&components, BVHStrategy::SAH);
+    let renderer = builder.build_pt().map_err(|e| ParsingError::ConfigError(e))?;
+    Ok(Box::new(renderer))
+}
 
-    let scene = Scene::new(lights, Arc::new(bvh));
-    let renderer = StdPTRenderer::new(
-        scenedesc.sampler, Arc::new(scenedesc.camera),
-        &scenedesc.outputfilename, scenedesc.max_depth,
-        scenedesc.multithreaded
-    );
-    Ok((scene, renderer))
+/// render the `i`th value of each of `sweeps` into its own output.
+///
+/// Meshes, their hierarchies and images are shared with the renders
+/// of other values through the cache of `overrides`, if any, so that
+/// only materials, lights, shapes and the top-level hierarchy over the
+/// components are rebuilt. Returns the path saved into
+fn render_sweep(
+    value: &serde_json::Value, sweeps: &[SweepDesc], i: usize, overrides: &Overrides
+) -> Result<String, String> {
+    let scenedesc = swept_scene(value, sweeps, i, overrides)?;
+    let output = scenedesc.outputfilename.clone();
+    let (scene, mut renderer) = build_scene(scenedesc, overrides).map_err(|e| e.to_string())?;
+    renderer.render(&scene);
+    Ok(output)
+}
+
+/// the description `value` with the numbers bound by `sweeps` set to
+/// their `i`th values, and `overrides` applied
+fn swept_scene(
+    value: &serde_json::Value, sweeps: &[SweepDesc], i: usize, overrides: &Overrides
+) -> Result<SceneDesc, String> {
+    let mut value = value.clone();
+    for sweep in sweeps {
+        let x = sweep.values().get(i).cloned()
+            .ok_or_else(|| format!("{} has no value #{}", sweep.path, i))?;
+        let bound = json_pointer(&sweep.path).and_then(|pointer| value.pointer_mut(&pointer));
+        match bound {
+            Some(bound) if bound.is_number() => {
+                *bound = serde_json::to_value(x).map_err(|e| e.to_string())?;
+            },
+            _ => return Err(format!("{} doesn't refer to a number", sweep.path)),
+        }
+    }
+    let mut scenedesc: SceneDesc = serde_json::from_value(value).map_err(|e| e.to_string())?;
+    overrides.apply(&mut scenedesc);
+    for sweep in sweeps {
+        let x = sweep.values()[i];
+        scenedesc.outputfilename = scenedesc.outputfilename
+            .replace(&format!("{{{}}}", sweep.name), &x.to_string());
+    }
+    Ok(scenedesc)
+}
+
+/// the json pointer to the same value as the json `path`, made of
+/// `.key` and `[index]` following `$`
+fn json_pointer(path: &str) -> Option<String> {
+    if !path.starts_with('$') { return None; }
+    let mut pointer = String::new();
+    let mut rest = &path[1..];
+    while !rest.is_empty() {
+        if rest.starts_with('.') {
+            let end = rest[1..].find(|c| c == '.' || c == '[').map_or(rest.len(), |e| e + 1);
+            let key = &rest[1..end];
+            if key.is_empty() { return None; }
+            pointer.push('/');
+            pointer.push_str(&key.replace('~', "~0").replace('/', "~1"));
+            rest = &rest[end..];
+        } else if rest.starts_with('[') {
+            let end = rest.find(']')?;
+            let index = usize::from_str(&rest[1..end]).ok()?;
+            pointer.push_str(&format!("/{}", index));
+            rest = &rest[end + 1..];
+        } else {
+            return None;
+        }
+    }
+    Some(pointer)
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -209,9 +843,163 @@ struct SceneDesc {
     components: Vec<Named<ComponentDesc>>,
     sampler: StdStrataSampler,
     camera: PerspecCam,
+    /// film position, in NDC, to focus the camera's lens on
+    #[serde(default)]
+    autofocus: Option<Point2f>,
+    /// render a side-by-side stereo pair with eyes this far apart
+    #[serde(default)]
+    interocular: Option<Float>,
     multithreaded: bool,
     max_depth: usize,
+    #[serde(default)]
+    lobe_depths: Option<LobeDepths>,
+    /// minimum roughness of surfaces met after a non-specular bounce
+    #[serde(default)]
+    regularization: Option<Float>,
     outputfilename: String,
+    /// also save the contribution of each light group
+    #[serde(default)]
+    output_light_groups: bool,
+    /// also save the id of the primitive seen through each pixel
+    #[serde(default)]
+    output_id_pass: bool,
+    /// also save the albedo of the surface seen through each pixel
+    #[serde(default)]
+    output_albedo_pass: bool,
+    /// also save these components of the beauty, which sum up to it
+    #[serde(default)]
+    output_path_channels: Vec<PathChannel>,
+    /// filter indirect lighting over neighboring pixels, for previews
+    #[serde(default)]
+    path_filter: Option<PathFilter>,
+    /// save completed tiles into the output at most this often, in seconds
+    #[serde(default)]
+    preview_interval: Option<Float>,
+    /// leave the background transparent, saving the output as RGBA
+    #[serde(default)]
+    alpha_background: bool,
+    /// structure the components are accelerated by
+    #[serde(default)]
+    acceleration: Acceleration,
+    /// make pixels brighter than a threshold glow, before saving
+    #[serde(default)]
+    bloom: Option<Bloom>,
+    /// give more samples to noisy pixels and those on geometric edges
+    #[serde(default)]
+    adaptive: Option<AdaptiveSampling>,
+    /// render the costliest tiles first, as estimated by a quick prepass
+    #[serde(default)]
+    prepass: Option<Prepass>,
+    /// move the whole scene so the camera sits at the origin, for precision
+    /// with large coordinates
+    #[serde(default)]
+    camera_relative: bool,
+    /// render a sequence of frames with the camera moving along a path,
+    /// instead of a single image
+    #[serde(default)]
+    animation: Option<AnimationDesc>,
+    /// what becomes of image textures failing to load
+    #[serde(default)]
+    texture_fallback: FallbackMode,
+    /// render the scene once for each value of these numbers in the
+    /// description, instead of a single image
+    #[serde(default)]
+    sweeps: Vec<SweepDesc>,
+    /// render the ambient occlusion of the surfaces seen, with these
+    /// `max_distance`, `samples` and `falloff`, instead of path tracing
+    #[serde(default)]
+    ambient_occlusion: Option<AmbientOcclusion>,
+}
+
+/// Frames rendered with the camera moving through the scene
+#[derive(Serialize, Deserialize, Clone)]
+struct AnimationDesc {
+    path: CameraPath,
+    /// directory the frames are saved into, as `frame_0000.png`, ...
+    directory: String,
+}
+
+/// A number in the scene description taking each of a list of values,
+/// one render each. Numbers swept together take their values in lockstep
+#[derive(Serialize, Deserialize, Clone)]
+struct SweepDesc {
+    /// json path of the number, as `$.lights[0].Point.intensity.inner[0]`
+    path: String,
+    /// `{name}` in `outputfilename` is replaced by the value rendered
+    name: String,
+    #[serde(default)]
+    values: Vec<Float>,
+    /// values taken if no `values` are given
+    #[serde(default)]
+    range: Option<SweepRange>,
+}
+
+/// Values from `start` by `step`, up to `end` inclusive
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct SweepRange {
+    start: Float,
+    end: Float,
+    step: Float,
+}
+
+impl SweepDesc {
+    /// values taken, in order
+    fn values(&self) -> Vec<Float> {
+        match self.range {
+            Some(range) if self.values.is_empty() && range.step != 0. as Float => {
+                // tolerating rounding of the last step
+                let steps = ((range.end - range.start) / range.step + 1e-3 as Float).floor();
+                if !(steps >= 0. as Float) { return Vec::new(); }
+                (0..steps as usize + 1).map(|i| range.start + range.step * i as Float).collect()
+            },
+            _ => self.values.clone(),
+        }
+    }
+}
+
+impl SceneDesc {
+    /// translate the whole description so the camera sits at the origin,
+    /// by a `Recentering`, returning the offset subtracted from its positions
+    fn recenter(&mut self) -> Vector3f {
+        let view_parent = self.camera.view_to_parent();
+        let recentering = Recentering::around_camera(view_parent);
+        let offset = recentering.offset();
+        self.camera.set_transform(recentering.camera(view_parent));
+        for light in &mut self.lights {
+            match *light {
+                LightDesc::Point(ref mut p) => p.light.posw = recentering.point(p.light.posw),
+                LightDesc::Spot(ref mut p) => p.light.translate(-offset),
+                // placed around the scene when preprocessed
+                LightDesc::Distant(_) => {},
+            }
+        }
+        for component in &mut self.components {
+            match component.value {
+                Some(ComponentDesc::Mesh{ref mut transform, ..})
+                | Some(ComponentDesc::Shaped{ref mut transform, ..}) => {
+                    let m = transform.unwrap_or(Matrix4f::identity());
+                    *transform = Some(recentering.transform(m));
+                },
+                // children have been recentered already
+                Some(ComponentDesc::Transformed{ref mut transform, ..}) => {
+                    *transform = recentering.conjugate(*transform);
+                },
+                Some(ComponentDesc::Group{transform: Some(ref mut transform), ..}) => {
+                    *transform = recentering.conjugate(*transform);
+                },
+                _ => {},
+            }
+        }
+        if let Some(ref mut animation) = self.animation {
+            match animation.path {
+                CameraPath::Orbit(ref mut orbit) => orbit.target = recentering.point(orbit.target),
+                CameraPath::Transforms(ref mut transforms) => for transform in transforms {
+                    *transform = *transform * Matrix4f::from_translation(offset);
+                },
+            }
+        }
+        offset
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -219,17 +1007,117 @@ enum ComponentDesc {
     Mesh{
         filename: String,
         transform: Option<Matrix4f>,
+        /// generate vertex normals for meshes lacking them
+        #[serde(default)]
+        generate_normals: bool,
+        /// angle in degrees above which generated normals are split
+        #[serde(default="default_normal_angle")]
+        normal_angle: Float,
+        /// how shading normals are chosen
+        #[serde(default)]
+        shading: ShadingMode,
+        #[serde(default)]
+        visibility: VisibilityDesc,
+        /// light group of the mesh's emissive parts
+        #[serde(default)]
+        light_group: u8,
+        /// skip back faces for camera and indirect rays, for closed opaque meshes
+        #[serde(default)]
+        cull_backfaces: bool,
+        /// shift shadow ray origins onto the smooth surface, for coarse meshes
+        #[serde(default)]
+        offset_shadows: bool,
+        /// which side(s) the emissive parts emit from
+        #[serde(default)]
+        emission_side: EmissionSide,
+        /// sample the emissive parts by their emission rasterized at this
+        /// resolution per triangle, for mostly dark textured emission
+        #[serde(default)]
+        light_profile_resolution: Option<usize>,
     },
     Shaped{
         shape: ShapeDesc,
         material: Named<MaterialDesc>,
         light: Option<Named<RGBTextureDesc>>,
+        #[serde(default)]
+        emission_side: EmissionSide,
         transform: Option<Matrix4f>,
+        /// only receive shadows, for compositing over photographs
+        #[serde(default)]
+        shadow_catcher: bool,
+        #[serde(default)]
+        visibility: VisibilityDesc,
+        /// light group if emissive
+        #[serde(default)]
+        light_group: u8,
     },
     Transformed{
         transform: Matrix4f,
         original: String,
     },
+    /// previously defined components moved into one, along with their lights
+    Group{
+        children: Vec<String>,
+        transform: Option<Matrix4f>,
+    },
+}
+
+fn default_normal_angle() -> Float {
+    arendur::component::DEFAULT_NORMAL_ANGLE
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Kinds of rays a component can be hit by, all by default
+#[derive(Serialize, Deserialize, Clone, Copy)]
+struct VisibilityDesc {
+    #[serde(default="default_true")]
+    camera: bool,
+    #[serde(default="default_true")]
+    shadow: bool,
+    #[serde(default="default_true")]
+    diffuse_indirect: bool,
+    #[serde(default="default_true")]
+    specular_indirect: bool,
+}
+
+/// Acceleration structure over the components of a scene
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+enum Acceleration {
+    /// bounding volume hierarchy, by surface area heuristics
+    BVH,
+    /// uniform grid, for many components of similar sizes
+    Grid,
+}
+
+impl Default for Acceleration {
+    fn default() -> Acceleration {
+        Acceleration::BVH
+    }
+}
+
+impl Default for VisibilityDesc {
+    fn default() -> VisibilityDesc {
+        VisibilityDesc{
+            camera: true,
+            shadow: true,
+            diffuse_indirect: true,
+            specular_indirect: true,
+        }
+    }
+}
+
+impl VisibilityDesc {
+    fn to_flags(&self) -> VisibilityFlags {
+        let mut flags = VisibilityFlags::empty();
+        if self.camera { flags |= VISIBLE_CAMERA; }
+        if self.shadow { flags |= VISIBLE_SHADOW; }
+        if self.diffuse_indirect { flags |= VISIBLE_DIFFUSE_INDIRECT; }
+        if self.specular_indirect { flags |= VISIBLE_SPECULAR_INDIRECT; }
+        flags
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -257,6 +1145,89 @@ impl<T> Named<T> {
 #[derive(Serialize, Deserialize, Clone)]
 enum ShapeDesc {
     Sphere(Sphere),
+    Dome(Dome),
+    /// a signed distance field, sphere traced
+    Sdf{
+        node: SdfNode,
+        #[serde(default)]
+        max_steps: Option<usize>,
+        #[serde(default)]
+        epsilon: Option<Float>,
+    },
+}
+
+/// A bar on stderr showing the progress of loading `label`
+struct ProgressBar<'a> {
+    label: &'a str,
+    enabled: bool,
+    // phase and percentage last drawn
+    last: Option<(LoadPhase, usize)>,
+}
+
+impl<'a> ProgressBar<'a> {
+    const WIDTH: usize = 30;
+
+    fn new(label: &'a str, enabled: bool) -> ProgressBar<'a> {
+        ProgressBar{ label: label, enabled: enabled, last: None }
+    }
+
+    fn update(&mut self, progress: LoadProgress) {
+        use std::io::Write;
+        if !self.enabled { return; }
+        let percent = (progress.fraction() * 100. as Float) as usize;
+        if self.last == Some((progress.phase, percent)) { return; }
+        self.last = Some((progress.phase, percent));
+        let filled = percent * ProgressBar::WIDTH / 100;
+        let mut line = format!(
+            "\r{} {:?} [{}{}] {:3}%", self.label, progress.phase,
+            "=".repeat(filled), " ".repeat(ProgressBar::WIDTH - filled), percent
+        );
+        if progress.bytes > 0 {
+            line.push_str(&format!(" {:.1} MB", progress.bytes as f64 / (1 << 20) as f64));
+        }
+        if progress.is_complete() { line.push('\n'); }
+        let mut stderr = std::io::stderr();
+        let _ = stderr.write_all(line.as_bytes()).and_then(|_| stderr.flush());
+    }
+}
+
+/// a sub-hierarchy over the triangles of mesh component `name`,
+/// loaded from or saved to `dir`
+fn cached_bvh(dir: &Path, name: &str, triangles: &[ComponentPointer]) -> Arc<Composable> {
+    let mut meshes: Vec<Arc<TriangleMesh>> = Vec::new();
+    for triangle in triangles {
+        if let ComponentPointer::Triangle(ref t) = *triangle {
+            if !meshes.iter().any(|m| Arc::ptr_eq(m, t.mesh())) {
+                meshes.push(t.mesh().clone());
+            }
+        }
+    }
+    if let Err(e) = std::fs::create_dir_all(dir) {
+        println!("failed creating cache directory {}: {}", dir.display(), e);
+    }
+    Arc::new(BVH::load_or_build(&dir.join(format!("{}.bvh", name)), &meshes, BVHStrategy::SAH))
+}
+
+/// a component out of `shape`, transformed by `transform` if presented
+/// and invertible, along with its light if emissive
+fn shaped_component<S: Shape + 'static>(
+    shape: S, material: Arc<Material>, light: Option<Arc<Texture<Texel=RGBSpectrumf>>>,
+    emission_side: EmissionSide, shadow_catcher: bool, visibility: VisibilityFlags,
+    transform: Option<Matrix4f>
+) -> (Arc<Composable>, Option<Arc<Light>>) {
+    let mut sp = ShapedPrimitive::new(shape, material, light);
+    sp.emission_side = emission_side;
+    sp.shadow_catcher = shadow_catcher;
+    sp.visibility = visibility;
+    if let Some((transform, inv)) = transform.and_then(|t| t.invert().map(|inv| (t, inv))) {
+        let sp = Arc::new(TransformedComposable::new(sp, Arc::new(transform), Arc::new(inv)));
+        let light = if sp.is_emissive() { Some(sp.clone() as Arc<Light>) } else { None };
+        (sp, light)
+    } else {
+        let sp = Arc::new(sp);
+        let light = if sp.is_emissive() { Some(sp.clone() as Arc<Light>) } else { None };
+        (sp, light)
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -272,12 +1243,22 @@ enum MaterialDesc {
         roughness: Named<GrayTextureDesc>,
         bump: Option<Named<GrayTextureDesc>>,
         eta: Float,
+        #[serde(default)]
+        sigma_a: Option<RGBSpectrumf>,
     },
     Plastic{
         diffuse: Named<RGBTextureDesc>,
         specular: Named<RGBTextureDesc>,
         roughness: Named<GrayTextureDesc>,
         bump: Option<Named<GrayTextureDesc>>,
+        #[serde(default)]
+        eta: Option<Float>,
+        /// roughness along the rotated v direction, for anisotropic highlights
+        #[serde(default)]
+        roughness_v: Option<Named<GrayTextureDesc>>,
+        /// rotation of the anisotropy about the normal, `[0, 1]` mapped to `[0, pi]`
+        #[serde(default)]
+        anisotropy_rotation: Option<Named<GrayTextureDesc>>,
     },
     Translucent{
         diffuse: Named<RGBTextureDesc>,
@@ -285,25 +1266,78 @@ enum MaterialDesc {
         roughness: Named<GrayTextureDesc>,
         bump: Option<Named<GrayTextureDesc>>,
         dissolve: Float,
-    }
+        #[serde(default)]
+        eta: Option<Float>,
+    },
+    /// a single sheet of glass, passing light through without refraction
+    ThinGlass{
+        reflectance: Named<RGBTextureDesc>,
+        transmittance: Named<RGBTextureDesc>,
+        bump: Option<Named<GrayTextureDesc>>,
+        eta: Float,
+    },
+    /// metallic-roughness, with textures left out defaulting to glTF's:
+    /// white, fully metallic and fully rough
+    Pbr{
+        #[serde(default)]
+        base_color: Option<Named<RGBTextureDesc>>,
+        #[serde(default)]
+        metallic: Option<Named<GrayTextureDesc>>,
+        #[serde(default)]
+        roughness: Option<Named<GrayTextureDesc>>,
+        /// tangent space normal map
+        #[serde(default)]
+        normal: Option<Named<RGBTextureDesc>>,
+        /// emission of shapes without a light of their own
+        #[serde(default)]
+        emissive: Option<Named<RGBTextureDesc>>,
+        #[serde(default)]
+        eta: Option<Float>,
+    },
 }
 
 impl MaterialDesc {
-    fn to_arc(
-        &self, 
-        rgbs: &mut HashMap<String, Arc<Texture<Texel=RGBSpectrumf>>>,
-        grays: &mut HashMap<String, Arc<Texture<Texel=Float>>>,
-        rgb_refs: &mut RGBMipMapHashTable<Float>,
-        gray_refs: &mut LumaMipMapHashTable<Float>
-    ) -> Option<Arc<Material>> {
+    /// register the textures defined inline in `textures`
+    fn declare_textures(&self, textures: &mut Textures) {
+        match *self {
+            MaterialDesc::Matte{ref kd, ref sigma, ref bump} => {
+                textures.declare_rgb(kd);
+                textures.declare_gray(sigma);
+                if let Some(ref bump) = *bump { textures.declare_gray(bump); }
+            },
+            MaterialDesc::Glass{ref diffuse, ref specular, ref roughness, ref bump, ..}
+            | MaterialDesc::Plastic{ref diffuse, ref specular, ref roughness, ref bump, ..}
+            | MaterialDesc::Translucent{ref diffuse, ref specular, ref roughness, ref bump, ..} => {
+                textures.declare_rgb(diffuse);
+                textures.declare_rgb(specular);
+                textures.declare_gray(roughness);
+                if let Some(ref bump) = *bump { textures.declare_gray(bump); }
+                if let MaterialDesc::Plastic{ref roughness_v, ref anisotropy_rotation, ..} = *self {
+                    if let Some(ref r) = *roughness_v { textures.declare_gray(r); }
+                    if let Some(ref r) = *anisotropy_rotation { textures.declare_gray(r); }
+                }
+            },
+            MaterialDesc::ThinGlass{ref reflectance, ref transmittance, ref bump, ..} => {
+                textures.declare_rgb(reflectance);
+                textures.declare_rgb(transmittance);
+                if let Some(ref bump) = *bump { textures.declare_gray(bump); }
+            },
+            MaterialDesc::Pbr{ref base_color, ref metallic, ref roughness, ref normal, ref emissive, ..} => {
+                for rgb in base_color.iter().chain(normal).chain(emissive) { textures.declare_rgb(rgb); }
+                for gray in metallic.iter().chain(roughness) { textures.declare_gray(gray); }
+            },
+        }
+    }
+
+    fn to_arc(&self, textures: &mut Textures) -> Option<Arc<Material>> {
         match *self {
             MaterialDesc::Matte{
                 ref kd, ref sigma, ref bump
             } => {
-                let kdt = kd.to_arc(rgbs, rgb_refs);
-                let sigmat = sigma.to_arc(grays, gray_refs);
+                let kdt = kd.to_arc(textures);
+                let sigmat = sigma.to_arc(textures);
                 let bumpt = bump.clone().and_then(|bn| {
-                    bn.to_arc(grays, gray_refs)
+                    bn.to_arc(textures)
                 });
                 if kdt.is_some() && sigmat.is_some() {
                     Some(Arc::new(MatteMaterial::new(
@@ -314,59 +1348,104 @@ impl MaterialDesc {
                 }
             },
             MaterialDesc::Glass{
-                ref diffuse, ref specular, ref roughness, ref bump, eta
+                ref diffuse, ref specular, ref roughness, ref bump, eta, sigma_a
             } => {
-                let diffuse = diffuse.to_arc(rgbs, rgb_refs);
-                let specular = specular.to_arc(rgbs, rgb_refs);
-                let roughness = roughness.to_arc(grays, gray_refs);
+                let diffuse = diffuse.to_arc(textures);
+                let specular = specular.to_arc(textures);
+                let roughness = roughness.to_arc(textures);
                 let bump = bump.clone().and_then(
-                    |b| b.to_arc(grays, gray_refs)
+                    |b| b.to_arc(textures)
                 );
                 if diffuse.is_some() && specular.is_some() && roughness.is_some() {
-                    Some(Arc::new(GlassMaterial::new(
+                    let mut glass = GlassMaterial::new(
                         diffuse.unwrap(), specular.unwrap(), 
                         roughness.unwrap(), eta, bump
-                    )))
+                    );
+                    if let Some(sigma_a) = sigma_a {
+                        glass.sigma_a = sigma_a;
+                    }
+                    Some(Arc::new(glass))
                 } else {
                     None
                 }
             },
             MaterialDesc::Plastic{
-                ref diffuse, ref specular, ref roughness, ref bump,
+                ref diffuse, ref specular, ref roughness, ref bump, eta,
+                ref roughness_v, ref anisotropy_rotation
             } => {
-                let diffuse = diffuse.to_arc(rgbs, rgb_refs);
-                let specular = specular.to_arc(rgbs, rgb_refs);
-                let roughness = roughness.to_arc(grays, gray_refs);
+                let diffuse = diffuse.to_arc(textures);
+                let specular = specular.to_arc(textures);
+                let roughness = roughness.to_arc(textures);
                 let bump = bump.clone().and_then(
-                    |b| b.to_arc(grays, gray_refs)
+                    |b| b.to_arc(textures)
                 );
                 if diffuse.is_some() && specular.is_some() && roughness.is_some() {
-                    Some(Arc::new(PlasticMaterial::new(
+                    let mut plastic = PlasticMaterial::new(
                         diffuse.unwrap(), specular.unwrap(), 
                         roughness.unwrap(), bump
-                    )))
+                    );
+                    if let Some(eta) = eta {
+                        plastic.eta = eta;
+                    }
+                    plastic.roughness_v = roughness_v.as_ref().and_then(|r| r.to_arc(textures));
+                    plastic.anisotropy_rotation = anisotropy_rotation.as_ref().and_then(|r| r.to_arc(textures));
+                    Some(Arc::new(plastic))
                 } else {
                     None
                 }
             },
             MaterialDesc::Translucent{
-                ref diffuse, ref specular, ref roughness, ref bump, dissolve
+                ref diffuse, ref specular, ref roughness, ref bump, dissolve, eta
             } => {
-                let diffuse = diffuse.to_arc(rgbs, rgb_refs);
-                let specular = specular.to_arc(rgbs, rgb_refs);
-                let roughness = roughness.to_arc(grays, gray_refs);
+                let diffuse = diffuse.to_arc(textures);
+                let specular = specular.to_arc(textures);
+                let roughness = roughness.to_arc(textures);
                 let bump = bump.clone().and_then(
-                    |b| b.to_arc(grays, gray_refs)
+                    |b| b.to_arc(textures)
                 );
                 if diffuse.is_some() && specular.is_some() && roughness.is_some() {
-                    Some(Arc::new(TranslucentMaterial::new(
+                    let mut translucent = TranslucentMaterial::new(
                         diffuse.unwrap(), specular.unwrap(), 
                         roughness.unwrap(), dissolve, bump
+                    );
+                    if let Some(eta) = eta {
+                        translucent.eta = eta;
+                    }
+                    Some(Arc::new(translucent))
+                } else {
+                    None
+                }
+            },
+            MaterialDesc::ThinGlass{
+                ref reflectance, ref transmittance, ref bump, eta
+            } => {
+                let reflectance = reflectance.to_arc(textures);
+                let transmittance = transmittance.to_arc(textures);
+                let bump = bump.clone().and_then(
+                    |b| b.to_arc(textures)
+                );
+                if reflectance.is_some() && transmittance.is_some() {
+                    Some(Arc::new(ThinGlassMaterial::new(
+                        reflectance.unwrap(), transmittance.unwrap(), eta, bump
                     )))
                 } else {
                     None
                 }
             },
+            MaterialDesc::Pbr{
+                ref base_color, ref metallic, ref roughness, ref normal, ref emissive, eta
+            } => {
+                let mut pbr = PbrMaterial::default();
+                if let Some(ref t) = *base_color { pbr.base_color = t.to_arc(textures)?; }
+                if let Some(ref t) = *metallic { pbr.metallic = t.to_arc(textures)?; }
+                if let Some(ref t) = *roughness { pbr.roughness = t.to_arc(textures)?; }
+                if let Some(ref t) = *normal { pbr.normal = Some(t.to_arc(textures)?); }
+                if let Some(ref t) = *emissive { pbr.emissive = Some(t.to_arc(textures)?); }
+                if let Some(eta) = eta {
+                    pbr.eta = eta;
+                }
+                Some(Arc::new(pbr))
+            },
         }
         
     }
@@ -385,49 +1464,37 @@ enum RGBTextureDesc{
         ta: String,
         tb: String,
     },
+    Scale{
+        texture: String,
+        scale: Float,
+    },
+    /// lerps from `ta` to `tb` by the gray texture `amount`
+    Mix{
+        ta: String,
+        tb: String,
+        amount: String,
+    },
+    /// looks `texture` up at uv scaled, rotated counterclockwise
+    /// by `rotation` in degrees, then shifted
+    UvTransform{
+        texture: String,
+        scaling: Vector2f,
+        shifting: Vector2f,
+        rotation: Float,
+    },
+    Clamp{
+        texture: String,
+        min: Float,
+        max: Float,
+    },
+    /// maps the gray texture `driver` through a gradient of
+    /// positions and colors
+    Ramp{
+        driver: String,
+        stops: Vec<(Float, RGBSpectrumf)>,
+    },
 }
 
-impl Named<RGBTextureDesc> {
-    fn to_arc(
-        &self,
-        textures: &mut HashMap<String, Arc<Texture<Texel=RGBSpectrumf>>>,
-        refs: &mut RGBMipMapHashTable<Float>
-    ) -> Option<Arc<Texture<Texel=RGBSpectrumf>>> {
-        let value = if let Some(t) = textures.get(&self.name) {
-            return Some(t.clone());
-        } else {
-            if self.value.is_none() { return None; }
-            self.value.as_ref().unwrap()
-        };
-        match *value {
-            RGBTextureDesc::Image{
-                ref info, ref mapping
-            } => {
-                RGBImageTexture::new_as_arc(info.clone(), mapping.clone(), refs)
-            }
-            RGBTextureDesc::Constant{
-                value
-            } => {
-                Some(Arc::new(ConstantTexture{value}))
-            }
-            RGBTextureDesc::Product{
-                ref ta, ref tb
-            } => {
-                let ta = textures.get(ta);
-                let tb = textures.get(tb);
-                if ta.is_some() && tb.is_some() {
-                    Some(Arc::new(ProductTexture{
-                        t0: ta.unwrap().clone(),
-                        t1: tb.unwrap().clone(),
-                    }))
-                } else {
-                    None
-                }
-            }
-        }
-    }
-}
-
 #[derive(Serialize, Deserialize, Clone)]
 enum GrayTextureDesc{
     Image{
@@ -441,69 +1508,1314 @@ enum GrayTextureDesc{
         ta: String,
         tb: String,
     },
+    Scale{
+        texture: String,
+        scale: Float,
+    },
+    /// lerps from `ta` to `tb` by the gray texture `amount`
+    Mix{
+        ta: String,
+        tb: String,
+        amount: String,
+    },
+    /// looks `texture` up at uv scaled, rotated counterclockwise
+    /// by `rotation` in degrees, then shifted
+    UvTransform{
+        texture: String,
+        scaling: Vector2f,
+        shifting: Vector2f,
+        rotation: Float,
+    },
+    Clamp{
+        texture: String,
+        min: Float,
+        max: Float,
+    },
+    /// remaps `texture` through a curve of control points
+    Curve{
+        texture: String,
+        #[serde(default)]
+        mode: SplineMode,
+        points: Vec<(Float, Float)>,
+    },
 }
 
-impl Named<GrayTextureDesc> {
-    fn to_arc(
-        &self,
-        textures: &mut HashMap<String, Arc<Texture<Texel=Float>>>,
-        refs: &mut LumaMipMapHashTable<Float>
-    ) -> Option<Arc<Texture<Texel=Float>>> {
-        let value = if let Some(t) = textures.get(&self.name) {
-            return Some(t.clone());
+/// Named textures of a scene.
+///
+/// Textures defined inline anywhere in the scene are declared before any
+/// of them is built, and built on first use, so references by name to
+/// other textures resolve regardless of the order of their definitions.
+#[derive(Default)]
+struct Textures {
+    rgb_descs: HashMap<String, RGBTextureDesc>,
+    gray_descs: HashMap<String, GrayTextureDesc>,
+    rgbs: HashMap<String, Arc<Texture<Texel=RGBSpectrumf>>>,
+    grays: HashMap<String, Arc<Texture<Texel=Float>>>,
+    rgb_refs: RGBMipMapHashTable<Float>,
+    gray_refs: LumaMipMapHashTable<Float>,
+    fallback: TextureFallback,
+    /// images are loaded through this instead, if any
+    cache: Option<Arc<SceneCache>>,
+    /// names being built, to break reference cycles
+    building: Vec<String>,
+}
+
+impl Textures {
+    fn declare_rgb(&mut self, named: &Named<RGBTextureDesc>) {
+        if let Some(ref desc) = named.value {
+            self.rgb_descs.entry(named.name.clone()).or_insert_with(|| desc.clone());
+        }
+    }
+
+    fn declare_gray(&mut self, named: &Named<GrayTextureDesc>) {
+        if let Some(ref desc) = named.value {
+            self.gray_descs.entry(named.name.clone()).or_insert_with(|| desc.clone());
+        }
+    }
+
+    // guard against `name` referencing itself, directly or not
+    fn enter(&mut self, name: &str) -> bool {
+        if self.building.iter().any(|n| n == name) {
+            println!("texture {} references itself", name);
+            false
         } else {
-            if self.value.is_none() { return None; }
-            self.value.as_ref().unwrap()
-        };
-        match *value {
-            GrayTextureDesc::Image{
-                ref info, ref mapping
-            } => {
-                LumaImageTexture::new_as_arc(info.clone(), mapping.clone(), refs)
+            self.building.push(name.to_owned());
+            true
+        }
+    }
+
+    /// the rgb texture named `name`, built if not yet
+    fn rgb(&mut self, name: &str) -> Option<Arc<Texture<Texel=RGBSpectrumf>>> {
+        if let Some(t) = self.rgbs.get(name) {
+            return Some(t.clone());
+        }
+        let desc = match self.rgb_descs.get(name) {
+            Some(desc) => desc.clone(),
+            None => {
+                println!("texture {} is not defined", name);
+                return None;
             }
-            GrayTextureDesc::Constant{
-                value
-            } => {
+        };
+        if !self.enter(name) { return None; }
+        let ret: Option<Arc<Texture<Texel=RGBSpectrumf>>> = match desc {
+            RGBTextureDesc::Image{info, mapping} => {
+                let loaded = match self.cache {
+                    Some(ref cache) => cache.rgb_image(info, mapping),
+                    None => RGBImageTexture::new_as_arc(info, mapping, &mut self.rgb_refs),
+                };
+                loaded.map(Some).or_else(|e| self.fallback.rgb(e, None).map_err(|e| {
+                        println!("texture {}: {}", name, e);
+                    })).unwrap_or(None)
+            },
+            RGBTextureDesc::Constant{value} => {
                 Some(Arc::new(ConstantTexture{value}))
-            }
-            GrayTextureDesc::Product{
-                ref ta, ref tb
-            } => {
-                let ta = textures.get(ta);
-                let tb = textures.get(tb);
-                if ta.is_some() && tb.is_some() {
-                    Some(Arc::new(ProductTexture{
-                        t0: ta.unwrap().clone(),
-                        t1: tb.unwrap().clone(),
-                    }))
-                } else {
-                    None
+            },
+            RGBTextureDesc::Product{ta, tb} => {
+                match (self.rgb(&ta), self.rgb(&tb)) {
+                    (Some(t0), Some(t1)) => Some(Arc::new(ProductTexture{t0, t1})),
+                    _ => None,
                 }
+            },
+            RGBTextureDesc::Scale{texture, scale} => {
+                self.rgb(&texture).map(|inner| {
+                    let t: Arc<Texture<Texel=RGBSpectrumf>> = Arc::new(ScaleTexture{inner, scale});
+                    t
+                })
+            },
+            RGBTextureDesc::Mix{ta, tb, amount} => {
+                match (self.rgb(&ta), self.rgb(&tb), self.gray(&amount)) {
+                    (Some(t0), Some(t1), Some(l)) => Some(Arc::new(MixTexture{t0, t1, l})),
+                    _ => None,
+                }
+            },
+            RGBTextureDesc::UvTransform{texture, scaling, shifting, rotation} => {
+                self.rgb(&texture).map(|inner| {
+                    let t: Arc<Texture<Texel=RGBSpectrumf>> = Arc::new(UvTransformTexture{
+                        inner, scaling, shifting, rotation: rotation.to_radians(),
+                    });
+                    t
+                })
+            },
+            RGBTextureDesc::Clamp{texture, min, max} => {
+                self.rgb(&texture).map(|inner| {
+                    let t: Arc<Texture<Texel=RGBSpectrumf>> = Arc::new(ClampTexture{inner, min, max});
+                    t
+                })
+            },
+            RGBTextureDesc::Ramp{driver, stops} => {
+                self.gray(&driver).map(|driver| {
+                    let t: Arc<Texture<Texel=RGBSpectrumf>> = Arc::new(RampTexture{
+                        driver, ramp: ColorRamp::new(stops),
+                    });
+                    t
+                })
+            },
+        };
+        self.building.pop();
+        if let Some(ref t) = ret {
+            self.rgbs.insert(name.to_owned(), t.clone());
+        }
+        ret
+    }
+
+    /// the gray texture named `name`, built if not yet
+    fn gray(&mut self, name: &str) -> Option<Arc<Texture<Texel=Float>>> {
+        if let Some(t) = self.grays.get(name) {
+            return Some(t.clone());
+        }
+        let desc = match self.gray_descs.get(name) {
+            Some(desc) => desc.clone(),
+            None => {
+                println!("texture {} is not defined", name);
+                return None;
             }
+        };
+        if !self.enter(name) { return None; }
+        let ret: Option<Arc<Texture<Texel=Float>>> = match desc {
+            GrayTextureDesc::Image{info, mapping} => {
+                let loaded = match self.cache {
+                    Some(ref cache) => cache.gray_image(info, mapping),
+                    None => LumaImageTexture::new_as_arc(info, mapping, &mut self.gray_refs),
+                };
+                loaded.map(Some).or_else(|e| self.fallback.gray(e, None).map_err(|e| {
+                        println!("texture {}: {}", name, e);
+                    })).unwrap_or(None)
+            },
+            GrayTextureDesc::Constant{value} => {
+                Some(Arc::new(ConstantTexture{value}))
+            },
+            GrayTextureDesc::Product{ta, tb} => {
+                match (self.gray(&ta), self.gray(&tb)) {
+                    (Some(t0), Some(t1)) => Some(Arc::new(ProductTexture{t0, t1})),
+                    _ => None,
+                }
+            },
+            GrayTextureDesc::Scale{texture, scale} => {
+                self.gray(&texture).map(|inner| {
+                    let t: Arc<Texture<Texel=Float>> = Arc::new(ScaleTexture{inner, scale});
+                    t
+                })
+            },
+            GrayTextureDesc::Mix{ta, tb, amount} => {
+                match (self.gray(&ta), self.gray(&tb), self.gray(&amount)) {
+                    (Some(t0), Some(t1), Some(l)) => Some(Arc::new(MixTexture{t0, t1, l})),
+                    _ => None,
+                }
+            },
+            GrayTextureDesc::UvTransform{texture, scaling, shifting, rotation} => {
+                self.gray(&texture).map(|inner| {
+                    let t: Arc<Texture<Texel=Float>> = Arc::new(UvTransformTexture{
+                        inner, scaling, shifting, rotation: rotation.to_radians(),
+                    });
+                    t
+                })
+            },
+            GrayTextureDesc::Clamp{texture, min, max} => {
+                self.gray(&texture).map(|inner| {
+                    let t: Arc<Texture<Texel=Float>> = Arc::new(ClampTexture{inner, min, max});
+                    t
+                })
+            },
+            GrayTextureDesc::Curve{texture, mode, points} => {
+                self.gray(&texture).map(|inner| {
+                    let t: Arc<Texture<Texel=Float>> = Arc::new(CurveTexture{
+                        inner, curve: Spline::new(mode, points),
+                    });
+                    t
+                })
+            },
+        };
+        self.building.pop();
+        if let Some(ref t) = ret {
+            self.grays.insert(name.to_owned(), t.clone());
+        }
+        ret
+    }
+}
+
+impl Named<RGBTextureDesc> {
+    fn to_arc(&self, textures: &mut Textures) -> Option<Arc<Texture<Texel=RGBSpectrumf>>> {
+        textures.declare_rgb(self);
+        textures.rgb(&self.name)
+    }
+}
+
+impl Named<GrayTextureDesc> {
+    fn to_arc(&self, textures: &mut Textures) -> Option<Arc<Texture<Texel=Float>>> {
+        textures.declare_gray(self);
+        textures.gray(&self.name)
+    }
+}
+
+/// a spectrum given either as rgb, or as `{ "kelvin": 3200, "scale": 10.0 }`
+/// for blackbody emission
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(untagged)]
+enum SpectrumDesc {
+    Blackbody{
+        kelvin: Float,
+        #[serde(default = "default_scale")]
+        scale: Float,
+    },
+    RGB(RGBSpectrumf),
+}
+
+fn default_scale() -> Float {
+    1. as Float
+}
+
+impl SpectrumDesc {
+    fn to_rgb(&self) -> RGBSpectrumf {
+        match *self {
+            SpectrumDesc::Blackbody{kelvin, scale} => RGBSpectrumf::from_blackbody(kelvin, scale),
+            SpectrumDesc::RGB(rgb) => rgb,
         }
     }
 }
 
+/// a light whose `intensity` can be given as a `SpectrumDesc`,
+/// with an optional light `group`
+#[derive(Clone)]
+struct LightSpec<L> {
+    light: L,
+    group: u8,
+}
+
+impl<L: serde::Serialize> serde::Serialize for LightSpec<L> {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::Error;
+        let mut value = serde_json::to_value(&self.light).map_err(S::Error::custom)?;
+        if let Some(map) = value.as_object_mut() {
+            map.insert("group".to_owned(), self.group.into());
+        }
+        value.serialize(s)
+    }
+}
+
+impl<'de, L: serde::de::DeserializeOwned> serde::Deserialize<'de> for LightSpec<L> {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        let mut value = serde_json::Value::deserialize(d)?;
+        let group = match value.as_object_mut().and_then(|map| map.remove("group")) {
+            Some(group) => serde_json::from_value(group).map_err(D::Error::custom)?,
+            None => 0,
+        };
+        if let Some(intensity) = value.get_mut("intensity") {
+            let spectrum: SpectrumDesc = serde_json::from_value(intensity.take()).map_err(D::Error::custom)?;
+            *intensity = serde_json::to_value(spectrum.to_rgb()).map_err(D::Error::custom)?;
+        }
+        let light = serde_json::from_value(value).map_err(D::Error::custom)?;
+        Ok(LightSpec{light, group})
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 enum LightDesc {
-    Point(PointLight),
-    Spot(SpotLight),
-    Distant(DistantLight),
+    Point(LightSpec<PointLight>),
+    Spot(LightSpec<SpotLight>),
+    Distant(LightSpec<DistantLight>),
     // Area(String),
 }
 
 impl LightDesc {
     fn to_arc(&self) -> Arc<Light> {
         match *self {
-            LightDesc::Point(p) => {
-                Arc::new(p)
+            LightDesc::Point(ref p) => {
+                Arc::new(p.light)
             },
-            LightDesc::Spot(p) => {
-                Arc::new(p)
+            LightDesc::Spot(ref p) => {
+                Arc::new(p.light)
             },
-            LightDesc::Distant(d) => {
-                Arc::new(d)
+            LightDesc::Distant(ref d) => {
+                Arc::new(d.light)
+            }
+        }
+    }
+
+    fn group(&self) -> u8 {
+        match *self {
+            LightDesc::Point(ref p) => p.group,
+            LightDesc::Spot(ref p) => p.group,
+            LightDesc::Distant(ref d) => d.group,
+        }
+    }
+}
+/// Kinds of problems found in a scene description
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum IssueKind {
+    /// not json, or not in the expected structure
+    Syntax,
+    /// a name referring to nothing defined
+    UndefinedReference,
+    /// textures referencing themselves
+    ReferenceCycle,
+    /// a transform without inverse
+    SingularTransform,
+    /// a value out of its valid range
+    InvalidValue,
+    /// a file that can't be found
+    MissingFile,
+}
+
+impl IssueKind {
+    fn name(&self) -> &'static str {
+        match *self {
+            IssueKind::Syntax => "syntax",
+            IssueKind::UndefinedReference => "undefined-reference",
+            IssueKind::ReferenceCycle => "reference-cycle",
+            IssueKind::SingularTransform => "singular-transform",
+            IssueKind::InvalidValue => "invalid-value",
+            IssueKind::MissingFile => "missing-file",
+        }
+    }
+}
+
+/// A problem found at the json `path` of a scene description
+struct Issue {
+    /// whether the scene can't be rendered because of it
+    error: bool,
+    kind: IssueKind,
+    path: String,
+    message: String,
+}
+
+/// Problems found in a scene description
+#[derive(Default)]
+struct Report {
+    issues: Vec<Issue>,
+}
+
+impl Report {
+    fn error(&mut self, kind: IssueKind, path: &str, message: String) {
+        self.issues.push(Issue{error: true, kind, path: path.to_owned(), message});
+    }
+
+    fn warning(&mut self, kind: IssueKind, path: &str, message: String) {
+        self.issues.push(Issue{error: false, kind, path: path.to_owned(), message});
+    }
+
+    fn has_errors(&self) -> bool {
+        self.issues.iter().any(|i| i.error)
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for issue in self.issues.iter() {
+            writeln!(
+                f, "{}[{}] at {}: {}",
+                if issue.error { "error" } else { "warning" },
+                issue.kind.name(), issue.path, issue.message
+            )?;
+        }
+        let errors = self.issues.iter().filter(|i| i.error).count();
+        write!(f, "{} error(s), {} warning(s)", errors, self.issues.len() - errors)
+    }
+}
+
+/// decode the scene description in `buf` with `overrides` applied,
+/// reporting every problem found along the way. The description is
+/// returned if it could be decoded at all.
+fn validate(buf: &str, overrides: &Overrides) -> (Option<SceneDesc>, Report) {
+    let mut report = Report::default();
+    let value: serde_json::Value = match serde_json::from_str(buf) {
+        Ok(value) => value,
+        Err(e) => {
+            report.error(IssueKind::Syntax, "$", e.to_string());
+            return (None, report);
+        }
+    };
+    // decoding cameras and spheres panics on these
+    check_camera_value(&value["camera"], "$.camera", &mut report);
+    if let Some(components) = value["components"].as_array() {
+        for (i, component) in components.iter().enumerate() {
+            let path = format!("$.components[{}].value.Shaped.shape", i);
+            check_shape_value(&component["value"]["Shaped"]["shape"], &path, &mut report);
+        }
+    }
+    if report.has_errors() { return (None, report); }
+
+    let mut scenedesc: SceneDesc = match serde_json::from_value(value.clone()) {
+        Ok(scenedesc) => scenedesc,
+        Err(e) => {
+            locate_decode_errors(&value, &mut report);
+            if !report.has_errors() {
+                report.error(IssueKind::Syntax, "$", e.to_string());
+            }
+            return (None, report);
+        }
+    };
+    overrides.apply(&mut scenedesc);
+    check_scene(&scenedesc, &mut report);
+    check_sweeps(&value, &scenedesc, overrides, &mut report);
+    (Some(scenedesc), report)
+}
+
+/// report decoding errors of each part of `value` at its path
+fn locate_decode_errors(value: &serde_json::Value, report: &mut Report) {
+    fn check<T: serde::de::DeserializeOwned>(value: &serde_json::Value, path: &str, report: &mut Report) {
+        if let Err(e) = serde_json::from_value::<T>(value.clone()) {
+            report.error(IssueKind::Syntax, path, e.to_string());
+        }
+    }
+    if let Some(lights) = value["lights"].as_array() {
+        for (i, light) in lights.iter().enumerate() {
+            check::<LightDesc>(light, &format!("$.lights[{}]", i), report);
+        }
+    }
+    if let Some(components) = value["components"].as_array() {
+        for (i, component) in components.iter().enumerate() {
+            check::<Named<ComponentDesc>>(component, &format!("$.components[{}]", i), report);
+        }
+    }
+    check::<StdStrataSampler>(&value["sampler"], "$.sampler", report);
+    check::<PerspecCam>(&value["camera"], "$.camera", report);
+}
+
+fn check_camera_value(camera: &serde_json::Value, path: &str, report: &mut Report) {
+    if let Ok(transform) = serde_json::from_value::<Matrix4f>(camera["transform"].clone()) {
+        check_transform(&transform, &format!("{}.transform", path), report);
+    }
+    if let Some(fov) = camera["fov"].as_f64() {
+        if !(fov > 0. && fov < std::f64::consts::PI) {
+            report.error(IssueKind::InvalidValue, &format!("{}.fov", path), format!(
+                "field of view should be in (0, pi) radians, got {}", fov
+            ));
+        }
+    }
+    if let (Some(znear), Some(zfar)) = (camera["znear"].as_f64(), camera["zfar"].as_f64()) {
+        if !(znear > 0. && znear < zfar) {
+            report.error(IssueKind::InvalidValue, &format!("{}.znear", path), format!(
+                "near plane should be positive and closer than the far plane, got {} and {}", znear, zfar
+            ));
+        }
+    }
+}
+
+fn check_shape_value(shape: &serde_json::Value, path: &str, report: &mut Report) {
+    for &kind in &["Sphere", "Dome"] {
+        if let Some(radius) = shape[kind]["radius"].as_f64() {
+            if !(radius > 0.) {
+                report.error(IssueKind::InvalidValue, &format!("{}.{}.radius", path, kind), format!(
+                    "radius should be positive, got {}", radius
+                ));
             }
         }
     }
-}
\ No newline at end of file
+    if let (Some(zmin), Some(zmax)) = (shape["Sphere"]["zmin"].as_f64(), shape["Sphere"]["zmax"].as_f64()) {
+        if !(zmin < zmax) {
+            report.error(IssueKind::InvalidValue, &format!("{}.Sphere.zmin", path), format!(
+                "zmin should be lower than zmax, got {} and {}", zmin, zmax
+            ));
+        }
+    }
+}
+
+/// compiling sdf nodes panics on singular transforms
+fn check_sdf(node: &SdfNode, max_steps: Option<usize>, epsilon: Option<Float>, path: &str, report: &mut Report) {
+    if max_steps == Some(0) {
+        report.error(IssueKind::InvalidValue, &format!("{}.max_steps", path), "sphere tracing needs at least one step".to_owned());
+    }
+    if let Some(epsilon) = epsilon {
+        if !(epsilon > 0. as Float) {
+            report.error(IssueKind::InvalidValue, &format!("{}.epsilon", path), format!(
+                "epsilon should be positive, got {}", epsilon
+            ));
+        }
+    }
+    check_sdf_node(node, &format!("{}.node", path), report);
+}
+
+fn check_sdf_node(node: &SdfNode, path: &str, report: &mut Report) {
+    let positive = |value: Float, name: &str, report: &mut Report| if !(value > 0. as Float) {
+        report.error(IssueKind::InvalidValue, &format!("{}.{}", path, name), format!(
+            "{} should be positive, got {}", name, value
+        ));
+    };
+    match *node {
+        SdfNode::Sphere{radius} => positive(radius, "Sphere.radius", report),
+        SdfNode::Box{half_extents} => for i in 0..3 {
+            positive(half_extents[i], "Box.half_extents", report);
+        },
+        SdfNode::Torus{major_radius, minor_radius} => {
+            positive(major_radius, "Torus.major_radius", report);
+            positive(minor_radius, "Torus.minor_radius", report);
+        },
+        SdfNode::SmoothUnion{ref a, ref b, ..} => {
+            check_sdf_node(a, &format!("{}.SmoothUnion.a", path), report);
+            check_sdf_node(b, &format!("{}.SmoothUnion.b", path), report);
+        },
+        SdfNode::Transform{ref transform, ref node} => {
+            check_transform(transform, &format!("{}.Transform.transform", path), report);
+            check_sdf_node(node, &format!("{}.Transform.node", path), report);
+        },
+    }
+}
+
+fn check_transform(transform: &Matrix4f, path: &str, report: &mut Report) {
+    if transform.invert().is_none() {
+        let matrix = serde_json::to_string(transform).unwrap_or_else(|_| format!("{:?}", transform));
+        report.error(IssueKind::SingularTransform, path, format!("matrix {} is not invertible", matrix));
+    }
+}
+
+fn check_file(filename: &str, path: &str, report: &mut Report) {
+    if !Path::new(filename).is_file() {
+        report.error(IssueKind::MissingFile, path, format!("file {} not found", filename));
+    }
+}
+
+/// check the references and values of a decoded scene description
+fn check_scene(scenedesc: &SceneDesc, report: &mut Report) {
+    let builder = RendererBuilder::new(scenedesc.sampler.clone(), Arc::new(scenedesc.camera.clone()))
+        .output(&scenedesc.outputfilename)
+        .max_depth(scenedesc.max_depth)
+        .regularization(scenedesc.regularization)
+        .path_filter(scenedesc.path_filter)
+        .bloom(scenedesc.bloom)
+        .adaptive(scenedesc.adaptive)
+        .prepass(scenedesc.prepass);
+    for e in builder.tracing_errors() {
+        let path = match e {
+            ConfigError::ZeroDepth => "$.max_depth",
+            ConfigError::ZeroResolution => "$.camera.film.resolution",
+            ConfigError::ZeroSamples | ConfigError::ZeroLightSamples => "$.sampler",
+            ConfigError::UnsupportedOutput(_) => "$.outputfilename",
+            ConfigError::InvalidRegularization(_) => "$.regularization",
+            ConfigError::InvalidPathFilter(_) => "$.path_filter",
+            ConfigError::InvalidBloom(_) => "$.bloom",
+            ConfigError::InvalidAdaptive(_) => "$.adaptive",
+            ConfigError::InvalidPrepass(_) => "$.prepass",
+            ConfigError::InvalidAmbientOcclusion(_) => "$.ambient_occlusion",
+        };
+        report.error(IssueKind::InvalidValue, path, e.to_string());
+    }
+    if let Some(ao) = scenedesc.ambient_occlusion {
+        if !ao.is_valid() {
+            report.error(
+                IssueKind::InvalidValue, "$.ambient_occlusion",
+                ConfigError::InvalidAmbientOcclusion(ao).to_string()
+            );
+        }
+    }
+    if let Some(interocular) = scenedesc.interocular {
+        if !(interocular > 0. as Float) {
+            report.error(IssueKind::InvalidValue, "$.interocular", format!(
+                "interocular distance should be positive, got {}", interocular
+            ));
+        }
+    }
+    if let Some(ref animation) = scenedesc.animation {
+        if !animation.path.is_valid() {
+            report.error(IssueKind::InvalidValue, "$.animation.path", "camera path needs frames, a positive orbit radius and invertible transforms".to_owned());
+        }
+        if scenedesc.interocular.is_some() {
+            report.error(IssueKind::InvalidValue, "$.animation", "stereo pairs can't be animated".to_owned());
+        }
+    }
+    if let Some(exposure) = scenedesc.camera.get_film().exposure() {
+        if let Some((name, value)) = exposure.invalid_setting() {
+            report.error(IssueKind::InvalidValue, &format!("$.camera.film.exposure.{}", name), format!(
+                "{} should be positive, got {}", name, value
+            ));
+        }
+    }
+    if let Some(interval) = scenedesc.preview_interval {
+        if !(interval >= 0. as Float) {
+            report.error(IssueKind::InvalidValue, "$.preview_interval", format!(
+                "preview interval should be non-negative, got {}", interval
+            ));
+        }
+    }
+    for (i, light) in scenedesc.lights.iter().enumerate() {
+        if light.group() as usize >= MAX_LIGHT_GROUPS {
+            report.warning(IssueKind::InvalidValue, &format!("$.lights[{}]", i), format!(
+                "light group {} out of range, merged into {}", light.group(), MAX_LIGHT_GROUPS - 1
+            ));
+        }
+    }
+
+    let mut textures = TextureDecls::default();
+    for (i, component) in scenedesc.components.iter().enumerate() {
+        let path = format!("$.components[{}].value.Shaped", i);
+        if let Some(ComponentDesc::Shaped{ref material, ref light, ..}) = component.value {
+            if let Some(ref desc) = material.value {
+                desc.declare_texture_paths(&mut textures, &format!("{}.material.value", path));
+            }
+            if let Some(ref light) = *light {
+                textures.declare_rgb(light, &format!("{}.light", path));
+            }
+        }
+    }
+    textures.check(report);
+
+    let mut materials = Vec::new();
+    let mut primitives = Vec::new();
+    let mut meshes = Vec::new();
+    let mut names = Vec::new();
+    for (i, component) in scenedesc.components.iter().enumerate() {
+        let path = format!("$.components[{}]", i);
+        if names.contains(&&component.name) {
+            report.warning(IssueKind::InvalidValue, &format!("{}.name", path), format!(
+                "component {} defined more than once, only the last one is kept", component.name
+            ));
+        }
+        names.push(&component.name);
+        let component_value = match component.value {
+            Some(ref value) => value,
+            None => {
+                report.warning(IssueKind::InvalidValue, &path, format!("empty component {} ignored", component.name));
+                continue;
+            }
+        };
+        match *component_value {
+            ComponentDesc::Mesh{ref filename, transform, normal_angle, light_group, light_profile_resolution, ..} => {
+                let path = format!("{}.value.Mesh", path);
+                check_file(filename, &format!("{}.filename", path), report);
+                if let Some(ref transform) = transform {
+                    check_transform(transform, &format!("{}.transform", path), report);
+                }
+                if !(normal_angle >= 0. as Float && normal_angle <= 180. as Float) {
+                    report.error(IssueKind::InvalidValue, &format!("{}.normal_angle", path), format!(
+                        "normal angle should be in [0, 180] degrees, got {}", normal_angle
+                    ));
+                }
+                if light_profile_resolution == Some(0) {
+                    report.error(IssueKind::InvalidValue, &format!("{}.light_profile_resolution", path),
+                        "light profile resolution should be positive".to_owned()
+                    );
+                }
+                check_light_group(light_group, &path, report);
+                meshes.push(&component.name);
+            },
+            ComponentDesc::Shaped{ref shape, ref material, ref light, ref transform, light_group, ..} => {
+                let path = format!("{}.value.Shaped", path);
+                if let ShapeDesc::Sdf{ref node, max_steps, epsilon} = *shape {
+                    check_sdf(node, max_steps, epsilon, &format!("{}.shape.Sdf", path), report);
+                }
+                let material_path = format!("{}.material", path);
+                if let Some(ref desc) = material.value {
+                    desc.check(&textures, &format!("{}.value", material_path), report);
+                    materials.push(&material.name);
+                } else if !materials.contains(&&material.name) {
+                    report.error(IssueKind::UndefinedReference, &material_path, format!(
+                        "material {} is not defined by any component before", material.name
+                    ));
+                }
+                if let Some(ref light) = *light {
+                    textures.check_rgb_ref(light, &format!("{}.light", path), report);
+                }
+                if let Some(ref transform) = *transform {
+                    check_transform(transform, &format!("{}.transform", path), report);
+                }
+                check_light_group(light_group, &path, report);
+                primitives.push(&component.name);
+            },
+            ComponentDesc::Transformed{ref transform, ref original} => {
+                let path = format!("{}.value.Transformed", path);
+                check_transform(transform, &format!("{}.transform", path), report);
+                if !primitives.contains(&original) {
+                    report.error(IssueKind::UndefinedReference, &format!("{}.original", path), format!(
+                        "original {} is not a shaped, transformed or group component defined before", original
+                    ));
+                }
+                primitives.push(&component.name);
+            },
+            ComponentDesc::Group{ref children, ref transform} => {
+                let path = format!("{}.value.Group", path);
+                if let Some(ref transform) = *transform {
+                    check_transform(transform, &format!("{}.transform", path), report);
+                }
+                if children.is_empty() {
+                    report.error(IssueKind::InvalidValue, &format!("{}.children", path), format!(
+                        "group {} has no children", component.name
+                    ));
+                }
+                for (j, child) in children.iter().enumerate() {
+                    if let Some(k) = primitives.iter().position(|p| *p == child) {
+                        primitives.remove(k);
+                    } else if let Some(k) = meshes.iter().position(|m| *m == child) {
+                        meshes.remove(k);
+                    } else {
+                        report.error(IssueKind::UndefinedReference, &format!("{}.children[{}]", path, j), format!(
+                            "child {} is not a component defined before, or is already grouped", child
+                        ));
+                    }
+                }
+                primitives.push(&component.name);
+            },
+        }
+    }
+}
+
+/// check the sweeps of `scenedesc`, decoded from `value`, decoding
+/// the description of each of their values
+fn check_sweeps(value: &serde_json::Value, scenedesc: &SceneDesc, overrides: &Overrides, report: &mut Report) {
+    let sweeps = &scenedesc.sweeps;
+    if sweeps.is_empty() { return; }
+    if scenedesc.animation.is_some() {
+        report.error(IssueKind::InvalidValue, "$.sweeps", "sweeps can't be combined with an animation".to_owned());
+    }
+    let mut counts = Vec::with_capacity(sweeps.len());
+    for (i, sweep) in sweeps.iter().enumerate() {
+        let path = format!("$.sweeps[{}]", i);
+        let bound = json_pointer(&sweep.path).and_then(|pointer| value.pointer(&pointer).cloned());
+        if !bound.map_or(false, |bound| bound.is_number()) {
+            report.error(
+                IssueKind::InvalidValue, &format!("{}.path", path),
+                format!("`{}` doesn't refer to a number of the description", sweep.path)
+            );
+        }
+        let values = sweep.values();
+        if values.is_empty() {
+            report.error(
+                IssueKind::InvalidValue, &path,
+                "no values, give `values` or a `range` stepping from `start` toward `end`".to_owned()
+            );
+        } else if values.iter().any(|v| !v.is_finite()) {
+            report.error(IssueKind::InvalidValue, &path, "values need to be finite".to_owned());
+        }
+        counts.push(values.len());
+        if sweep.name.is_empty() || !scenedesc.outputfilename.contains(&format!("{{{}}}", sweep.name)) {
+            report.error(
+                IssueKind::InvalidValue, "$.outputfilename",
+                format!("needs `{{{}}}` for the values of `{}` to be told apart", sweep.name, sweep.path)
+            );
+        }
+    }
+    if counts.iter().any(|&c| c != counts[0]) {
+        report.error(
+            IssueKind::InvalidValue, "$.sweeps",
+            format!("sweeps take their values together, but have {:?} of them", counts)
+        );
+    }
+    if report.has_errors() { return; }
+    for i in 0..counts[0] {
+        if let Err(e) = swept_scene(value, sweeps, i, overrides) {
+            report.error(IssueKind::Syntax, "$.sweeps", format!("value #{}: {}", i, e));
+        }
+    }
+}
+
+fn check_light_group(group: u8, path: &str, report: &mut Report) {
+    if group as usize >= MAX_LIGHT_GROUPS {
+        report.warning(IssueKind::InvalidValue, &format!("{}.light_group", path), format!(
+            "light group {} out of range, merged into {}", group, MAX_LIGHT_GROUPS - 1
+        ));
+    }
+}
+
+/// Textures of a scene declared by name, with where they are declared
+#[derive(Default)]
+struct TextureDecls {
+    rgbs: HashMap<String, (RGBTextureDesc, String)>,
+    grays: HashMap<String, (GrayTextureDesc, String)>,
+}
+
+/// references a texture description makes to other textures
+trait TextureRefs {
+    /// name of the variant, as in json
+    fn variant(&self) -> &'static str;
+
+    /// field, name and whether it's gray, of each texture referenced
+    fn references(&self) -> Vec<(&'static str, &str, bool)>;
+
+    /// the image file read, if any
+    fn image(&self) -> Option<&str>;
+
+    /// bounds clamped to, if any
+    fn clamping(&self) -> Option<(Float, Float)>;
+
+    /// whether the curve or gradient remapped through is well defined, if any
+    fn remapping(&self) -> Option<bool>;
+}
+
+macro_rules! impl_texture_refs {
+    ($desc: ident, $gray: expr, $remap: ident, $input: ident) => {
+        impl TextureRefs for $desc {
+            fn variant(&self) -> &'static str {
+                match *self {
+                    $desc::Image{..} => "Image",
+                    $desc::Constant{..} => "Constant",
+                    $desc::Product{..} => "Product",
+                    $desc::Scale{..} => "Scale",
+                    $desc::Mix{..} => "Mix",
+                    $desc::UvTransform{..} => "UvTransform",
+                    $desc::Clamp{..} => "Clamp",
+                    $desc::$remap{..} => stringify!($remap),
+                }
+            }
+
+            fn references(&self) -> Vec<(&'static str, &str, bool)> {
+                match *self {
+                    $desc::Image{..} | $desc::Constant{..} => Vec::new(),
+                    $desc::Product{ref ta, ref tb} => vec![("ta", ta, $gray), ("tb", tb, $gray)],
+                    $desc::Mix{ref ta, ref tb, ref amount} => vec![
+                        ("ta", ta, $gray), ("tb", tb, $gray), ("amount", amount, true)
+                    ],
+                    $desc::Scale{ref texture, ..}
+                    | $desc::UvTransform{ref texture, ..}
+                    | $desc::Clamp{ref texture, ..} => vec![("texture", texture, $gray)],
+                    $desc::$remap{ref $input, ..} => vec![(stringify!($input), $input, true)],
+                }
+            }
+
+            fn image(&self) -> Option<&str> {
+                if let $desc::Image{ref info, ..} = *self { Some(&info.name) } else { None }
+            }
+
+            fn clamping(&self) -> Option<(Float, Float)> {
+                if let $desc::Clamp{min, max, ..} = *self { Some((min, max)) } else { None }
+            }
+
+            fn remapping(&self) -> Option<bool> {
+                self.remapping_valid()
+            }
+        }
+    }
+}
+
+impl RGBTextureDesc {
+    fn remapping_valid(&self) -> Option<bool> {
+        if let RGBTextureDesc::Ramp{ref stops, ..} = *self {
+            Some(ColorRamp::new(stops.clone()).is_valid())
+        } else {
+            None
+        }
+    }
+}
+
+impl GrayTextureDesc {
+    fn remapping_valid(&self) -> Option<bool> {
+        if let GrayTextureDesc::Curve{mode, ref points, ..} = *self {
+            Some(Spline::new(mode, points.clone()).is_valid())
+        } else {
+            None
+        }
+    }
+}
+
+impl_texture_refs!(RGBTextureDesc, false, Ramp, driver);
+impl_texture_refs!(GrayTextureDesc, true, Curve, texture);
+
+impl TextureDecls {
+    fn declare_rgb(&mut self, named: &Named<RGBTextureDesc>, path: &str) {
+        if let Some(ref desc) = named.value {
+            self.rgbs.entry(named.name.clone()).or_insert_with(|| (desc.clone(), path.to_owned()));
+        }
+    }
+
+    fn declare_gray(&mut self, named: &Named<GrayTextureDesc>, path: &str) {
+        if let Some(ref desc) = named.value {
+            self.grays.entry(named.name.clone()).or_insert_with(|| (desc.clone(), path.to_owned()));
+        }
+    }
+
+    fn get(&self, name: &str, gray: bool) -> Option<(&TextureRefs, &str)> {
+        if gray {
+            self.grays.get(name).map(|&(ref d, ref p)| (d as &TextureRefs, &**p))
+        } else {
+            self.rgbs.get(name).map(|&(ref d, ref p)| (d as &TextureRefs, &**p))
+        }
+    }
+
+    fn check_rgb_ref(&self, named: &Named<RGBTextureDesc>, path: &str, report: &mut Report) {
+        self.check_ref(&named.name, false, path, report);
+    }
+
+    fn check_gray_ref(&self, named: &Named<GrayTextureDesc>, path: &str, report: &mut Report) {
+        self.check_ref(&named.name, true, path, report);
+    }
+
+    fn check_ref(&self, name: &str, gray: bool, path: &str, report: &mut Report) {
+        if self.get(name, gray).is_none() {
+            report.error(IssueKind::UndefinedReference, path, format!(
+                "{} texture {} is not defined", if gray { "gray" } else { "rgb" }, name
+            ));
+        }
+    }
+
+    /// check every declared texture, once each
+    fn check(&self, report: &mut Report) {
+        let mut names: Vec<_> = self.rgbs.keys().map(|n| (n, false))
+            .chain(self.grays.keys().map(|n| (n, true))).collect();
+        // report in order of declaration
+        names.sort_by_key(|&(n, gray)| self.get(n, gray).unwrap().1.to_owned());
+        for (name, gray) in names {
+            let (desc, path) = self.get(name, gray).unwrap();
+            let path = format!("{}.value.{}", path, desc.variant());
+            for (field, reference, gray) in desc.references() {
+                self.check_ref(reference, gray, &format!("{}.{}", path, field), report);
+            }
+            if let Some(filename) = desc.image() {
+                check_file(filename, &format!("{}.info.name", path), report);
+            }
+            if let Some((min, max)) = desc.clamping() {
+                if !(min <= max) {
+                    report.error(IssueKind::InvalidValue, &format!("{}.min", path), format!(
+                        "clamping bounds should be ordered, got {} and {}", min, max
+                    ));
+                }
+            }
+            if desc.remapping() == Some(false) {
+                let field = if gray { "points" } else { "stops" };
+                report.error(IssueKind::InvalidValue, &format!("{}.{}", path, field), format!(
+                    "{} should be non-empty, finite and ordered", field
+                ));
+            }
+            if self.in_cycle(name, gray, name, gray, &mut Vec::new()) {
+                report.error(IssueKind::ReferenceCycle, &path, format!("texture {} references itself", name));
+            }
+        }
+    }
+
+    // if `start` can be reached from the texture `name`
+    fn in_cycle<'a>(
+        &'a self, name: &'a str, gray: bool, start: &str, start_gray: bool,
+        visited: &mut Vec<(&'a str, bool)>
+    ) -> bool {
+        if visited.contains(&(name, gray)) { return false; }
+        visited.push((name, gray));
+        let desc = match self.get(name, gray) {
+            Some((desc, _)) => desc,
+            None => return false,
+        };
+        desc.references().into_iter().any(|(_, reference, g)| {
+            (reference == start && g == start_gray) || self.in_cycle(reference, g, start, start_gray, visited)
+        })
+    }
+}
+
+impl MaterialDesc {
+    /// register the textures defined inline in `textures`,
+    /// with the material at `path`
+    fn declare_texture_paths(&self, textures: &mut TextureDecls, path: &str) {
+        let path = format!("{}.{}", path, self.variant());
+        let field = |f: &str| format!("{}.{}", path, f);
+        match *self {
+            MaterialDesc::Matte{ref kd, ref sigma, ref bump} => {
+                textures.declare_rgb(kd, &field("kd"));
+                textures.declare_gray(sigma, &field("sigma"));
+                if let Some(ref bump) = *bump { textures.declare_gray(bump, &field("bump")); }
+            },
+            MaterialDesc::Glass{ref diffuse, ref specular, ref roughness, ref bump, ..}
+            | MaterialDesc::Plastic{ref diffuse, ref specular, ref roughness, ref bump, ..}
+            | MaterialDesc::Translucent{ref diffuse, ref specular, ref roughness, ref bump, ..} => {
+                textures.declare_rgb(diffuse, &field("diffuse"));
+                textures.declare_rgb(specular, &field("specular"));
+                textures.declare_gray(roughness, &field("roughness"));
+                if let Some(ref bump) = *bump { textures.declare_gray(bump, &field("bump")); }
+                if let MaterialDesc::Plastic{ref roughness_v, ref anisotropy_rotation, ..} = *self {
+                    if let Some(ref r) = *roughness_v { textures.declare_gray(r, &field("roughness_v")); }
+                    if let Some(ref r) = *anisotropy_rotation { textures.declare_gray(r, &field("anisotropy_rotation")); }
+                }
+            },
+            MaterialDesc::ThinGlass{ref reflectance, ref transmittance, ref bump, ..} => {
+                textures.declare_rgb(reflectance, &field("reflectance"));
+                textures.declare_rgb(transmittance, &field("transmittance"));
+                if let Some(ref bump) = *bump { textures.declare_gray(bump, &field("bump")); }
+            },
+            MaterialDesc::Pbr{ref base_color, ref metallic, ref roughness, ref normal, ref emissive, ..} => {
+                for (rgb, f) in [(base_color, "base_color"), (normal, "normal"), (emissive, "emissive")].iter() {
+                    if let Some(ref rgb) = **rgb { textures.declare_rgb(rgb, &field(f)); }
+                }
+                for (gray, f) in [(metallic, "metallic"), (roughness, "roughness")].iter() {
+                    if let Some(ref gray) = **gray { textures.declare_gray(gray, &field(f)); }
+                }
+            },
+        }
+    }
+
+    fn variant(&self) -> &'static str {
+        match *self {
+            MaterialDesc::Matte{..} => "Matte",
+            MaterialDesc::Glass{..} => "Glass",
+            MaterialDesc::Plastic{..} => "Plastic",
+            MaterialDesc::Translucent{..} => "Translucent",
+            MaterialDesc::ThinGlass{..} => "ThinGlass",
+            MaterialDesc::Pbr{..} => "Pbr",
+        }
+    }
+
+    /// check the references and values of the material at `path`
+    fn check(&self, textures: &TextureDecls, path: &str, report: &mut Report) {
+        let path = format!("{}.{}", path, self.variant());
+        let field = |f: &str| format!("{}.{}", path, f);
+        let check_eta = |eta: Float, report: &mut Report| {
+            if !(eta > 0. as Float) {
+                report.error(IssueKind::InvalidValue, &field("eta"), format!(
+                    "index of refraction should be positive, got {}", eta
+                ));
+            }
+        };
+        match *self {
+            MaterialDesc::Matte{ref kd, ref sigma, ref bump} => {
+                textures.check_rgb_ref(kd, &field("kd"), report);
+                textures.check_gray_ref(sigma, &field("sigma"), report);
+                if let Some(ref bump) = *bump { textures.check_gray_ref(bump, &field("bump"), report); }
+            },
+            MaterialDesc::Glass{ref diffuse, ref specular, ref roughness, ref bump, eta, ..}
+            | MaterialDesc::Translucent{ref diffuse, ref specular, ref roughness, ref bump, eta: Some(eta), ..}
+            | MaterialDesc::Plastic{ref diffuse, ref specular, ref roughness, ref bump, eta: Some(eta), ..} => {
+                textures.check_rgb_ref(diffuse, &field("diffuse"), report);
+                textures.check_rgb_ref(specular, &field("specular"), report);
+                textures.check_gray_ref(roughness, &field("roughness"), report);
+                if let Some(ref bump) = *bump { textures.check_gray_ref(bump, &field("bump"), report); }
+                check_eta(eta, report);
+            },
+            MaterialDesc::Translucent{ref diffuse, ref specular, ref roughness, ref bump, ..}
+            | MaterialDesc::Plastic{ref diffuse, ref specular, ref roughness, ref bump, ..} => {
+                textures.check_rgb_ref(diffuse, &field("diffuse"), report);
+                textures.check_rgb_ref(specular, &field("specular"), report);
+                textures.check_gray_ref(roughness, &field("roughness"), report);
+                if let Some(ref bump) = *bump { textures.check_gray_ref(bump, &field("bump"), report); }
+            },
+            MaterialDesc::ThinGlass{ref reflectance, ref transmittance, ref bump, eta} => {
+                textures.check_rgb_ref(reflectance, &field("reflectance"), report);
+                textures.check_rgb_ref(transmittance, &field("transmittance"), report);
+                if let Some(ref bump) = *bump { textures.check_gray_ref(bump, &field("bump"), report); }
+                check_eta(eta, report);
+            },
+            MaterialDesc::Pbr{ref base_color, ref metallic, ref roughness, ref normal, ref emissive, eta} => {
+                for (rgb, f) in [(base_color, "base_color"), (normal, "normal"), (emissive, "emissive")].iter() {
+                    if let Some(ref rgb) = **rgb { textures.check_rgb_ref(rgb, &field(f), report); }
+                }
+                for (gray, f) in [(metallic, "metallic"), (roughness, "roughness")].iter() {
+                    if let Some(ref gray) = **gray { textures.check_gray_ref(gray, &field(f), report); }
+                }
+                if let Some(eta) = eta { check_eta(eta, report); }
+            },
+        }
+        match *self {
+            MaterialDesc::Plastic{ref roughness_v, ref anisotropy_rotation, ..} => {
+                if let Some(ref r) = *roughness_v { textures.check_gray_ref(r, &field("roughness_v"), report); }
+                if let Some(ref r) = *anisotropy_rotation {
+                    textures.check_gray_ref(r, &field("anisotropy_rotation"), report);
+                }
+            },
+            MaterialDesc::Translucent{dissolve, ..} => {
+                if !(dissolve >= 0. as Float && dissolve <= 1. as Float) {
+                    report.error(IssueKind::InvalidValue, &field("dissolve"), format!(
+                        "dissolve should be in [0, 1], got {}", dissolve
+                    ));
+                }
+            },
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+    use super::*;
+    use self::rand::{SeedableRng, StdRng};
+
+    fn kinds(filename: &str) -> Vec<IssueKind> {
+        let buf = read_input(format!("examples/validation/{}", filename).as_ref()).unwrap();
+        let (_, report) = validate(&buf, &Overrides::default());
+        assert!(report.has_errors(), "{} passed validation", filename);
+        report.issues.iter().filter(|i| i.error).map(|i| i.kind).collect()
+    }
+
+    #[test]
+    fn test_valid() {
+        for filename in &["examples/validation/valid.json", "examples/cornellbox/cb.json"] {
+            let buf = read_input(filename.as_ref()).unwrap();
+            let (scenedesc, report) = validate(&buf, &Overrides::default());
+            assert!(scenedesc.is_some() && report.issues.is_empty(), "{}:\n{}", filename, report);
+        }
+    }
+
+    #[test]
+    fn test_broken() {
+        use IssueKind::*;
+        let corpus = [
+            ("syntax.json", vec![Syntax]),
+            ("unknown_variant.json", vec![Syntax]),
+            ("undefined_reference.json", vec![UndefinedReference; 3]),
+            ("reference_cycle.json", vec![ReferenceCycle; 2]),
+            ("singular_transform.json", vec![SingularTransform; 3]),
+            ("camera.json", vec![SingularTransform, InvalidValue]),
+            ("negative_radius.json", vec![InvalidValue]),
+            ("invalid_values.json", vec![InvalidValue; 5]),
+            ("missing_file.json", vec![MissingFile]),
+        ];
+        for &(filename, ref expected) in corpus.iter() {
+            assert_eq!(&kinds(filename), expected, "{}", filename);
+        }
+    }
+
+    #[test]
+    fn test_overrides_validated() {
+        let buf = read_input("examples/validation/valid.json".as_ref()).unwrap();
+        let mut overrides = Overrides::default();
+        overrides.output = Some("render.exr".to_owned());
+        let (_, report) = validate(&buf, &overrides);
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].path, "$.outputfilename");
+    }
+
+    // a sphere on a ground sphere lit by a point light, seen from `origin`
+    fn offset_scene(origin: Float, camera_relative: bool) -> SceneDesc {
+        let sphere = |name: &str, radius: Float, center: (Float, Float, Float)| format!(r#"{{
+            "name": "{}",
+            "value": {{"Shaped": {{
+                "shape": {{"Sphere": {{"radius": {}, "zmin": {}, "zmax": {}, "phimax": 6.2831}}}},
+                "material": {{"name": "{}_matte", "value": {{"Matte": {{
+                    "kd": {{"name": "{}_kd", "value": {{"Constant": {{"value": {{"inner": [0.5, 0.5, 0.5]}}}}}}}},
+                    "sigma": {{"name": "{}_sigma", "value": {{"Constant": {{"value": 0.0}}}}}}
+                }}}}}},
+                "light": null,
+                "transform": [[1, 0, 0, 0], [0, 1, 0, 0], [0, 0, 1, 0], [{}, {}, {}, 1]]
+            }}}}
+        }}"#, name, radius, -radius, radius, name, name, name,
+            origin + center.0, origin + center.1, origin + center.2);
+        let buf = format!(r#"{{
+            "lights": [{{"Point": {{"posw": [{l}, {ly}, {l}], "intensity": {{"inner": [20.0, 20.0, 20.0]}}}}}}],
+            "components": [{}, {}],
+            "sampler": {{"sampledx": 2, "sampledy": 2, "ndim": 4}},
+            "camera": {{
+                "transform": [[1, 0, 0, 0], [0, 1, 0, 0], [0, 0, 1, 0], [{c}, {c}, {c}, 1]],
+                "screen": {{"pmin": {{"x": -1.0, "y": -1.0}}, "pmax": {{"x": 1.0, "y": 1.0}}}},
+                "znear": 0.1, "zfar": 100.0, "fov": 1.0, "lens": null,
+                "film": {{
+                    "resolution": {{"x": 16, "y": 16}},
+                    "crop_window": {{"pmin": {{"x": 0, "y": 0}}, "pmax": {{"x": 16, "y": 16}}}},
+                    "filter_radius": {{"x": 1.0, "y": 1.0}}
+                }}
+            }},
+            "multithreaded": false,
+            "max_depth": 2,
+            "outputfilename": "render.png",
+            "camera_relative": {}
+        }}"#,
+            sphere("ball", 1., (0.25, 0., 3.)), sphere("ground", 64., (0., -65., 3.)),
+            camera_relative, l = origin, ly = origin + 3., c = -origin);
+        let mut scenedesc: SceneDesc = serde_json::from_str(&buf).unwrap();
+        scenedesc.sampler = StdStrataSampler::new(2, 2, 4, StdRng::from_seed(&[7usize][..]));
+        scenedesc
+    }
+
+    fn render_offset(origin: Float, camera_relative: bool) -> Vec<Float> {
+        let (scene, mut renderer) = build_scene(
+            offset_scene(origin, camera_relative), &Overrides::default()
+        ).unwrap();
+        renderer.render_image(&scene).as_raw().0.to_vec()
+    }
+
+    #[test]
+    fn test_camera_relative() {
+        let reference = render_offset(0., false);
+        assert!(reference.iter().any(|&v| v > 0.));
+        assert_eq!(render_offset(1e6, true), reference);
+        assert!(render_offset(1e6, false) != reference);
+    }
+
+    #[test]
+    fn test_ambient_occlusion() {
+        let mut scenedesc = offset_scene(0., false);
+        scenedesc.ambient_occlusion = Some(AmbientOcclusion{
+            max_distance: 2., samples: 8, falloff: OcclusionFalloff::Smooth
+        });
+        let (scene, mut renderer) = build_scene(scenedesc, &Overrides::default()).unwrap();
+        let pixels = renderer.render_image(&scene).as_raw().0.to_vec();
+        // gray, and occluded around where the ball meets the ground
+        assert!(pixels.chunks(3).all(|p| p[0] == p[1] && p[1] == p[2] && p[0] >= 0. && p[0] <= 1.));
+        assert!(pixels.iter().any(|&v| v < 0.9) && pixels.iter().any(|&v| v == 1.));
+
+        let buf = read_input("examples/validation/valid.json".as_ref()).unwrap();
+        let mut value: serde_json::Value = serde_json::from_str(&buf).unwrap();
+        value["ambient_occlusion"] = serde_json::from_str(r#"{"max_distance": 1.0, "samples": 0}"#).unwrap();
+        let (_, report) = validate(&value.to_string(), &Overrides::default());
+        let paths: Vec<_> = report.issues.iter().map(|i| &*i.path).collect();
+        assert_eq!(paths, vec!["$.ambient_occlusion"]);
+    }
+
+    // a glossy ball swept over `roughness`, above a ground loaded from
+    // `ground` for the cache to reuse
+    fn sweep_scene(ground: &Path, output: &Path, roughness: &[Float]) -> String {
+        format!(r#"{{
+            "lights": [{{"Point": {{"posw": [0.0, 2.0, 0.0], "intensity": {{"inner": [40.0, 40.0, 40.0]}}}}}}],
+            "components": [
+                {{"name": "ground", "value": {{"Mesh": {{"filename": {:?}}}}}}},
+                {{"name": "ball", "value": {{"Shaped": {{
+                    "shape": {{"Sphere": {{"radius": 1.0, "zmin": -1.0, "zmax": 1.0, "phimax": 6.2831}}}},
+                    "material": {{"name": "glossy", "value": {{"Plastic": {{
+                        "diffuse": {{"name": "kd", "value": {{"Constant": {{"value": {{"inner": [0.0, 0.0, 0.0]}}}}}}}},
+                        "specular": {{"name": "ks", "value": {{"Constant": {{"value": {{"inner": [1.0, 1.0, 1.0]}}}}}}}},
+                        "roughness": {{"name": "r", "value": {{"Constant": {{"value": 0.5}}}}}},
+                        "bump": null
+                    }}}}}},
+                    "light": null,
+                    "transform": [[1, 0, 0, 0], [0, 1, 0, 0], [0, 0, 1, 0], [0, 0, 3, 1]]
+                }}}}}}
+            ],
+            "sampler": {{"sampledx": 2, "sampledy": 2, "ndim": 4}},
+            "camera": {{
+                "transform": [[1, 0, 0, 0], [0, 1, 0, 0], [0, 0, 1, 0], [0, 0, 0, 1]],
+                "screen": {{"pmin": {{"x": -1.0, "y": -1.0}}, "pmax": {{"x": 1.0, "y": 1.0}}}},
+                "znear": 0.1, "zfar": 100.0, "fov": 0.8, "lens": null,
+                "film": {{
+                    "resolution": {{"x": 24, "y": 24}},
+                    "crop_window": {{"pmin": {{"x": 0, "y": 0}}, "pmax": {{"x": 24, "y": 24}}}},
+                    "filter_radius": {{"x": 1.0, "y": 1.0}}
+                }}
+            }},
+            "multithreaded": false,
+            "max_depth": 2,
+            "outputfilename": {:?},
+            "sweeps": [{{
+                "path": "$.components[1].value.Shaped.material.value.Plastic.roughness.value.Constant.value",
+                "name": "roughness",
+                "values": {:?}
+            }}]
+        }}"#, ground.to_str().unwrap(), output.join("ball_{roughness}.png").to_str().unwrap(), roughness)
+    }
+
+    #[test]
+    fn test_sweeps() {
+        let dir = std::env::temp_dir().join("arendur_test_sweeps");
+        std::fs::create_dir_all(&dir).unwrap();
+        let ground = dir.join("ground.obj");
+        std::fs::write(&ground, "v -8 -20 -8\nv 8 -20 -8\nv 8 -20 8\nv -8 -20 8\nf 1 2 3\nf 1 3 4\n").unwrap();
+        let roughness = [0.02 as Float, 0.1 as Float, 0.3 as Float];
+        let buf = sweep_scene(&ground, &dir, &roughness);
+        let (scenedesc, report) = validate(&buf, &Overrides::default());
+        assert!(report.issues.is_empty(), "{}", report);
+        let sweeps = scenedesc.unwrap().sweeps;
+        let value: serde_json::Value = serde_json::from_str(&buf).unwrap();
+        let cache = Arc::new(SceneCache::new());
+        let mut overrides = Overrides::default();
+        overrides.cache = Some(cache.clone());
+        let mut peaks = Vec::new();
+        for i in 0..roughness.len() {
+            let output = render_sweep(&value, &sweeps, i, &overrides).unwrap();
+            assert_eq!(output, dir.join(format!("ball_{}.png", roughness[i])).to_str().unwrap());
+            assert!(Path::new(&output).exists());
+            // the highlight spreads out as the ball roughens
+            let (scene, mut renderer) = build_scene(
+                swept_scene(&value, &sweeps, i, &overrides).unwrap(), &overrides
+            ).unwrap();
+            let image = renderer.render_image(&scene);
+            peaks.push(image.as_raw().0.iter().cloned().fold(0. as Float, Float::max));
+        }
+        assert!(peaks[0] > peaks[1] && peaks[1] > peaks[2], "{:?}", peaks);
+        let stats = cache.stats();
+        assert_eq!((stats.mesh_loads, stats.bvh_builds), (1, 1), "{:?}", stats);
+    }
+
+    #[test]
+    fn test_sweeps_validated() {
+        let dir = std::env::temp_dir();
+        let mut value: serde_json::Value = serde_json::from_str(
+            &sweep_scene(&dir.join("ground.obj"), &dir, &[0.1 as Float, 0.2 as Float])
+        ).unwrap();
+        value["sweeps"][0]["path"] = serde_json::to_value("$.components[1].value.Shaped.material").unwrap();
+        value["outputfilename"] = serde_json::to_value("ball.png").unwrap();
+        value["sweeps"][0]["values"] = serde_json::to_value(Vec::<Float>::new()).unwrap();
+        let (_, report) = validate(&value.to_string(), &Overrides::default());
+        let paths: Vec<_> = report.issues.iter().filter(|i| i.error).map(|i| &*i.path).collect();
+        assert_eq!(paths, vec!["$.components[0].value.Mesh.filename", "$.sweeps[0].path", "$.sweeps[0]", "$.outputfilename"]);
+    }
+}
@@ -0,0 +1,52 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! whitted renderer usage example, with the scene built in code
+
+extern crate arendur;
+extern crate rand;
+
+use arendur::prelude::*;
+use arendur::renderer::scene_builder::*;
+use std::time::Instant;
+
+fn main() {
+    println!("Whitted example");
+    let sudato = Instant::now();
+
+    let mut builder = SceneBuilder::new();
+    builder.sphere(6. as Float).at(2., 0., 20.)
+        .material(matte(RGBSpectrumf::new(0.5 as Float, 0.6, 0.7), 10. as Float));
+    builder.sphere(2. as Float).at(-5., 2., 12.)
+        .material(plastic(RGBSpectrumf::new(0.6 as Float, 0.2, 0.2), RGBSpectrumf::grey_scale(0.4 as Float), 0.1));
+    builder.sphere(1.5 as Float).at(-1., -3., 10.).material(glass(1.5 as Float));
+    builder.sphere(2. as Float).at(6., 4., 12.).material(mirror(RGBSpectrumf::grey_scale(0.9 as Float)));
+    builder.sphere(1. as Float).at(0., 8., 14.)
+        .emissive(RGBSpectrumf::new(15.5 as Float, 13.2, 10.3));
+    builder.sphere(1000. as Float).at(0., -1006., 0.)
+        .material(matte(RGBSpectrumf::grey_scale(0.6 as Float), 0. as Float));
+    builder.point_light(Point3f::new(0. as Float, 20., 0.), RGBSpectrumf::new(1500. as Float, 1500., 1500.));
+    builder.camera(
+        Point3f::new(0. as Float, 2., -4.), Point3f::new(0. as Float, 0., 15.),
+        float::frac_pi_3(), (400, 300)
+    );
+    let (scene, camera) = match builder.build() {
+        Ok(built) => built,
+        Err(e) => {
+            println!("building the scene failed: {}", e);
+            return;
+        }
+    };
+
+    let sampler = StrataSampler::new(2, 2, 8, rand::StdRng::new().unwrap());
+    let mut renderer = WhittedRenderer::new(sampler, camera, "whitted.png", 0. as Float);
+    renderer.render(&scene);
+
+    let duration = sudato.elapsed();
+    println!("Done! Time used: {:.4}s", duration.as_secs() as f64 + (duration.subsec_nanos() as f64/1_000_000_000.0f64));
+}
@@ -11,6 +11,7 @@ use geometry::prelude::*;
 use std::mem;
 use spectrum::{Spectrum, RGBSpectrumf};
 use super::*;
+use super::microfacet::{Trowbridge, TorranceSparrowRBxdf, TorranceSparrowTBxdf};
 
 /// compute fresnel reflectance for dielectrics
 fn fresnel_dielectric(mut cos_theta_i: Float, mut etai: Float, mut etat: Float) -> Float {
@@ -112,6 +113,26 @@ impl Dielectric {
             eta0: etai, eta1: etat
         }
     }
+
+    /// reflectance given the cosine of the incident angle
+    #[inline]
+    pub fn reflectance(&self, cos_theta_i: Float) -> Float {
+        fresnel_dielectric(cos_theta_i, self.eta0, self.eta1)
+    }
+
+    /// cosine-weighted hemispherical average of the reflectance
+    /// for light incident from the `eta0` side
+    pub fn average_reflectance(&self) -> Float {
+        // integrated over the squared cosine, over which
+        // the reflectance varies more evenly
+        const STEPS: usize = 64;
+        let mut sum = 0. as Float;
+        for i in 0..STEPS {
+            let cos2 = (i as Float + 0.5 as Float) / STEPS as Float;
+            sum += self.reflectance(cos2.sqrt());
+        }
+        sum / STEPS as Float
+    }
 }
 
 impl Fresnel for Dielectric {
@@ -121,7 +142,24 @@ impl Fresnel for Dielectric {
     }
 }
 
+/// Schlick's approximation of the fresnel reflectance,
+/// tinted by the reflectance at normal incidence `r0`
+#[derive(Copy, Clone, Debug)]
+pub struct Schlick {
+    pub r0: RGBSpectrumf,
+}
+
+impl Fresnel for Schlick {
+    #[inline]
+    fn evaluate(&self, cos_theta_i: Float) -> RGBSpectrumf {
+        let m = (1. as Float - cos_theta_i.abs()).max(0. as Float).min(1. as Float);
+        let m5 = m * m * m * m * m;
+        self.r0 + (RGBSpectrumf::grey_scale(1. as Float) - self.r0) * m5
+    }
+}
+
 /// This interface always returns `Spectrum::gray_scale(1)`.
+#[derive(Copy, Clone, Debug)]
 pub struct Noop;
 
 impl Fresnel for Noop {
@@ -158,12 +196,28 @@ impl Bxdf for FresnelBxdf {
         BXDF_REFLECTION | BXDF_TRANSMISSION | BXDF_SPECULAR
     }
 
+    /// replaced by a pair of glossy reflection and transmission lobes
+    /// of roughness `min_alpha`, as in a rough dielectric
+    fn regularize<'a>(
+        &mut self, min_alpha: Float, alloc: &'a Allocator,
+        replace: &mut FnMut(Pointer<'a, Bxdf>)
+    ) -> bool {
+        let distribution = Trowbridge{ ax: min_alpha, ay: min_alpha };
+        replace(alloc.alloc(TorranceSparrowRBxdf::new(
+            self.reflectance, distribution, Dielectric::new(self.eta0, self.eta1)
+        )));
+        replace(alloc.alloc(TorranceSparrowTBxdf::new(
+            self.transmittance, distribution, self.eta0, self.eta1
+        )));
+        false
+    }
+
     #[inline]
     fn evaluate(&self, _wo: Vector3f, _wi: Vector3f) -> RGBSpectrumf {
         RGBSpectrumf::black()
     }
 
-    fn evaluate_sampled(&self, wo: Vector3f, u: Point2f) -> (RGBSpectrumf, Vector3f, Float, BxdfType) {
+    fn evaluate_sampled(&self, wo: Vector3f, u: Point2f) -> BsdfSample {
         let cos_theta = normal::cos_theta(wo);
         let f = fresnel_dielectric(cos_theta, self.eta0, self.eta1);
         if u.x < f {
@@ -172,8 +226,8 @@ impl Bxdf for FresnelBxdf {
             let pdf = f;
             debug_assert!(pdf <= 1. as Float);
             debug_assert!(pdf > 0. as Float);
-            let f = pdf * self.reflectance / cos_theta.abs();
-            (f, wi, pdf, BXDF_REFLECTION | BXDF_SPECULAR)
+            let f = self.reflectance * float::safe_div(pdf, cos_theta.abs());
+            BsdfSample::new(f, wi, pdf, BXDF_REFLECTION | BXDF_SPECULAR)
         } else {
             // transmition
             let pdf = 1. as Float - f;
@@ -187,11 +241,13 @@ impl Bxdf for FresnelBxdf {
             let eta = etai/etao;
             let wt = normal::refract(wo, n, eta);
             if let Some(wt) = wt {
-                let f = self.transmittance *eta * eta* pdf/wt.z.abs();
+                let f = self.transmittance * float::safe_div(eta * eta * pdf, wt.z.abs());
                 // println!("{:?}, {:?}, {}", f, wt, pdf);
-                (f, wt, pdf, BXDF_TRANSMISSION | BXDF_SPECULAR)
+                BsdfSample::refracted(f, wt, pdf, BXDF_TRANSMISSION | BXDF_SPECULAR, etao/etai)
             } else {
-                (RGBSpectrumf::black(), Vector3f::zero(), pdf, BXDF_TRANSMISSION | BXDF_SPECULAR)
+                BsdfSample::refracted(
+                    RGBSpectrumf::black(), Vector3f::zero(), pdf, BXDF_TRANSMISSION | BXDF_SPECULAR, etao/etai
+                )
             }
         }
     }
@@ -202,6 +258,72 @@ impl Bxdf for FresnelBxdf {
     }
 }
 
+/// A perfect scattering model of a thin dielectric slab, such as a
+/// window pane modelled as a single sheet.
+///
+/// Light bounces between the slab's two interfaces, summing up to a
+/// reflectance of $2R/(1+R)$ for a single interface's reflectance $R$.
+/// The rest passes straight through, without being refracted.
+#[derive(Copy, Clone, Debug)]
+pub struct ThinDielectricBxdf {
+    /// The reflectance term
+    pub reflectance: RGBSpectrumf,
+    /// The transmittance term
+    pub transmittance: RGBSpectrumf,
+    /// index of refraction of the slab, relative to its surroundings
+    pub eta: Float,
+}
+
+impl ThinDielectricBxdf {
+    /// construction
+    #[inline]
+    pub fn new(reflectance: RGBSpectrumf, transmittance: RGBSpectrumf, eta: Float) -> ThinDielectricBxdf {
+        ThinDielectricBxdf{
+            reflectance, transmittance, eta
+        }
+    }
+
+    /// total reflectance of the slab given the cosine of the incident angle
+    #[inline]
+    pub fn slab_reflectance(&self, cos_theta_i: Float) -> Float {
+        let r = fresnel_dielectric(cos_theta_i.abs(), 1. as Float, self.eta);
+        if r < 1. as Float { 2. as Float * r / (1. as Float + r) } else { 1. as Float }
+    }
+}
+
+impl Bxdf for ThinDielectricBxdf {
+    #[inline]
+    fn kind(&self) -> BxdfType {
+        BXDF_REFLECTION | BXDF_TRANSMISSION | BXDF_SPECULAR
+    }
+
+    #[inline]
+    fn evaluate(&self, _wo: Vector3f, _wi: Vector3f) -> RGBSpectrumf {
+        RGBSpectrumf::black()
+    }
+
+    fn evaluate_sampled(&self, wo: Vector3f, u: Point2f) -> BsdfSample {
+        let cos_theta = normal::cos_theta(wo);
+        let r = self.slab_reflectance(cos_theta);
+        if u.x < r {
+            // reflection
+            let wi = Vector3f::new(-wo.x, -wo.y, wo.z);
+            let f = self.reflectance * float::safe_div(r, cos_theta.abs());
+            BsdfSample::new(f, wi, r, BXDF_REFLECTION | BXDF_SPECULAR)
+        } else {
+            // transmission, straight through
+            let pdf = 1. as Float - r;
+            let f = self.transmittance * float::safe_div(pdf, cos_theta.abs());
+            BsdfSample::new(f, -wo, pdf, BXDF_TRANSMISSION | BXDF_SPECULAR)
+        }
+    }
+
+    #[inline]
+    fn pdf(&self, _wo: Vector3f, _wi: Vector3f) -> Float {
+        0. as Float
+    }
+}
+
 /// A refracting model
 #[derive(Copy, Clone, Debug)]
 pub struct FresnelTBxdf {
@@ -216,12 +338,25 @@ impl Bxdf for FresnelTBxdf {
         BXDF_TRANSMISSION | BXDF_SPECULAR
     }
 
+    /// replaced by a glossy transmission lobe of roughness `min_alpha`
+    fn regularize<'a>(
+        &mut self, min_alpha: Float, alloc: &'a Allocator,
+        replace: &mut FnMut(Pointer<'a, Bxdf>)
+    ) -> bool {
+        replace(alloc.alloc(TorranceSparrowTBxdf::new(
+            self.transmittance,
+            Trowbridge{ ax: min_alpha, ay: min_alpha },
+            self.eta0, self.eta1
+        )));
+        false
+    }
+
     #[inline]
     fn evaluate(&self, _wo: Vector3f, _wi: Vector3f) -> RGBSpectrumf {
         RGBSpectrumf::black()
     }
 
-    fn evaluate_sampled(&self, wo: Vector3f, _u: Point2f) -> (RGBSpectrumf, Vector3f, Float, BxdfType) {
+    fn evaluate_sampled(&self, wo: Vector3f, _u: Point2f) -> BsdfSample {
         let (etai, etao, n) = if wo.z > 0. as Float {
                 (self.eta0, self.eta1, Vector3f::new(0. as Float, 0., 1.))
         } else {
@@ -230,13 +365,16 @@ impl Bxdf for FresnelTBxdf {
         let eta = etai/etao;
         let wt = normal::refract(wo, n, eta);
         if let Some(wt) = wt {
-            let f = self.transmittance *eta * eta*(
-                1. as Float - fresnel_dielectric(wo.z, self.eta0, self.eta1)
-            ) /wt.z.abs();
+            let f = self.transmittance * float::safe_div(
+                eta * eta * (1. as Float - fresnel_dielectric(wo.z, self.eta0, self.eta1)),
+                wt.z.abs()
+            );
             // println!("{:?}, {:?}, {}", f, wt, pdf);
-            (f, wt, 1. as Float, BXDF_TRANSMISSION | BXDF_SPECULAR)
+            BsdfSample::refracted(f, wt, 1. as Float, BXDF_TRANSMISSION | BXDF_SPECULAR, etao/etai)
         } else {
-            (RGBSpectrumf::black(), Vector3f::zero(), 1. as Float, BXDF_TRANSMISSION | BXDF_SPECULAR)
+            BsdfSample::refracted(
+                RGBSpectrumf::black(), Vector3f::zero(), 1. as Float, BXDF_TRANSMISSION | BXDF_SPECULAR, etao/etai
+            )
         }
     }
 
@@ -9,6 +9,7 @@
 //! defines Lambertian bxdf
 
 use super::*;
+use super::fresnel::Dielectric;
 
 /// A lambertian reflection bxdf.
 #[derive(Copy, Clone, Debug)]
@@ -47,6 +48,60 @@ impl Bxdf for LambertianRBxdf {
     }
 }
 
+/// A lambertian reflection bxdf underneath a smooth dielectric coating.
+///
+/// Light is attenuated by the coating's transmittance on its way in and
+/// out, renormalized such that the albedo towards `wo` is `reflectance`
+/// times the transmittance along `wo`. Averaged over the hemisphere,
+/// this is `reflectance` scaled by one minus the average reflectance of
+/// the coating, leaving room for a specular lobe on top.
+#[derive(Copy, Clone, Debug)]
+pub struct CoatedLambertianRBxdf {
+    pub reflectance: RGBSpectrumf,
+    pub coating: Dielectric,
+    average_transmittance: Float,
+}
+
+impl CoatedLambertianRBxdf {
+    /// construction
+    pub fn new(reflectance: RGBSpectrumf, coating: Dielectric) -> CoatedLambertianRBxdf {
+        CoatedLambertianRBxdf{
+            reflectance: reflectance,
+            coating: coating,
+            average_transmittance: 1. as Float - coating.average_reflectance(),
+        }
+    }
+
+    #[inline]
+    fn transmittance(&self, w: Vector3f) -> Float {
+        1. as Float - self.coating.reflectance(normal::cos_theta(w).abs())
+    }
+}
+
+impl Bxdf for CoatedLambertianRBxdf {
+    #[inline]
+    fn kind(&self) -> BxdfType {
+        BXDF_REFLECTION | BXDF_DIFFUSE
+    }
+
+    #[inline]
+    fn evaluate(&self, wo: Vector3f, wi: Vector3f) -> RGBSpectrumf {
+        self.reflectance * float::frac_1_pi() * float::safe_div(
+            self.transmittance(wo) * self.transmittance(wi), self.average_transmittance
+        )
+    }
+
+    #[inline]
+    fn rho_hd(&self, wo: Vector3f, _samples: &[Point2f]) -> RGBSpectrumf {
+        self.reflectance * self.transmittance(wo)
+    }
+
+    #[inline]
+    fn rho_hh(&self, _samples0: &[Point2f], _samples1: &[Point2f]) -> RGBSpectrumf {
+        self.reflectance * self.average_transmittance
+    }
+}
+
 /// A lambertian transmission bxdf
 #[derive(Copy, Clone, Debug)]
 pub struct LambertianTBxdf {
@@ -85,12 +140,12 @@ impl Bxdf for LambertianTBxdf {
 
     #[inline]
     fn evaluate_sampled(&self, wo: Vector3f, u: Point2f
-    ) -> (RGBSpectrumf, Vector3f, Float, BxdfType) {
+    ) -> BsdfSample {
         let mut wi = sample::sample_cosw_hemisphere(u);
         if wo.z > 0.0 as Float {wi.z = -wi.z;}
         let pdf = self.pdf(wo, wi);
         let spectrum = self.evaluate(wo, wi);
-        (spectrum, wi, pdf, self.kind())
+        BsdfSample::new(spectrum, wi, pdf, self.kind())
     }
 
     #[inline]
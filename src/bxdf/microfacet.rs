@@ -45,10 +45,15 @@ pub trait MicrofacetDistribution {
     /// given a uniform sample, return a sampled macro normal `wh`
     fn sample_wh(&self, wo: Vector3f, u: Point2f) -> Vector3f;
 
+    /// widen the distribution to a roughness of at least `min_alpha`
+    fn regularize(&mut self, min_alpha: Float);
+
     /// given `wo` and a sampled `wh`, returns the pdf of this sample
     fn pdf(&self, wo: Vector3f, wh: Vector3f) -> Float {
-        self.distribution(wh) * self.visible(wo) * wo.dot(wh).abs()
-         /normal::cos_theta(wo).abs()
+        float::safe_div(
+            self.distribution(wh) * self.visible(wo) * wo.dot(wh).abs(),
+            normal::cos_theta(wo).abs()
+        )
     }
 }
 
@@ -79,6 +84,12 @@ pub struct Beckmann {
 }
 
 impl MicrofacetDistribution for Beckmann {
+    #[inline]
+    fn regularize(&mut self, min_alpha: Float) {
+        self.ax = self.ax.max(min_alpha);
+        self.ay = self.ay.max(min_alpha);
+    }
+
     fn distribution(&self, wh: Vector3f) -> Float {
         let cos2_theta = normal::cos2_theta(wh);
         let tan2_theta = normal::tan2_theta(wh);
@@ -142,6 +153,12 @@ pub struct Trowbridge {
 }
 
 impl MicrofacetDistribution for Trowbridge {
+    #[inline]
+    fn regularize(&mut self, min_alpha: Float) {
+        self.ax = self.ax.max(min_alpha);
+        self.ay = self.ay.max(min_alpha);
+    }
+
     fn distribution(&self, wh: Vector3f) -> Float {
         let cos2_theta = normal::cos2_theta(wh);
         let tan2_theta = normal::tan2_theta(wh);
@@ -204,7 +221,7 @@ fn _sample_wh_beckmann(wo: Vector3f, u: Point2f, ax: Float, ay: Float) -> Vector
     let wo_stretched = Vector3f::new(ax*wo.x, ay*wo.y, wo.z).normalize();
     let cos_theta = normal::cos_theta(wo_stretched).abs();
     let (mut sx, mut sy) = if cos_theta > 0.9999 as Float {
-        let r = (-u.x.ln()).sqrt();
+        let r = (-u.x.max(float::consts::SAMPLE_CLAMP_MIN).ln()).sqrt();
         let phi = 2.0 as Float * u.y * float::pi();
         (r*phi.cos(), r*phi.sin())
     } else {
@@ -213,7 +230,7 @@ fn _sample_wh_beckmann(wo: Vector3f, u: Point2f, ax: Float, ay: Float) -> Vector
         let cot_theta = cos_theta/sin_theta;
         let mut a = -1.0 as Float;
         let mut c = erf(cot_theta);
-        let ux = u.x.max(1e-6 as Float);
+        let ux = u.x.max(float::consts::SAMPLE_CLAMP_MIN);
         let theta = cos_theta.acos();
         let fit = 1.0 as Float + theta * (
             -0.876 as Float + theta * (
@@ -232,7 +249,7 @@ fn _sample_wh_beckmann(wo: Vector3f, u: Point2f, ax: Float, ay: Float) -> Vector
                 1.0 as Float + b + sqrt_pi_inv * tan_theta * (-inv*inv).exp()
             ) - ux;
             
-            if value.abs() < 1e-5 as Float { break; }
+            if value.abs() < float::consts::NEWTON_TOLERANCE { break; }
 
             let derivation = norm * (1.0 as Float - inv*tan_theta);
             
@@ -244,7 +261,7 @@ fn _sample_wh_beckmann(wo: Vector3f, u: Point2f, ax: Float, ay: Float) -> Vector
             b -= value / derivation;
         }
         (erf_inv(b), erf_inv(
-            2.0 as Float * (u.y).max(1e-6 as Float) - 1.0 as Float
+            2.0 as Float * u.y.max(float::consts::SAMPLE_CLAMP_MIN) - 1.0 as Float
         ))
     };
     let cos_phi = normal::cos_phi(wo_stretched);
@@ -271,7 +288,7 @@ fn _sample_wh_trowbridge(wo: Vector3f, u: Point2f, ax: Float, ay: Float) -> Vect
         let g1 = 2.0 as Float / (1.0 as Float + (
             1.0 as Float + 1.0 as Float / (cot_theta*cot_theta)
         ).sqrt());
-        let a = 2.0 as Float * u.y / g1 - 1.0 as Float;
+        let a = 2.0 as Float * u.x / g1 - 1.0 as Float;
         let tmp = (1.0 as Float / (a*a - 1.0 as Float)).min(1e10 as Float);
         let d = (tan_theta*tan_theta*tmp*tmp - (
             a * a - tan_theta * tan_theta
@@ -295,7 +312,7 @@ fn _sample_wh_trowbridge(wo: Vector3f, u: Point2f, ax: Float, ay: Float) -> Vect
         ) + 0.46341 as Float)) / (uy*(uy*(
             uy * 0.093073 as Float + 0.309420 as Float
         ) - 1.000000 as Float) + 0.597999 as Float);
-        let sy = s * z * (1. as Float + sx*sx);
+        let sy = s * z * (1. as Float + sx*sx).sqrt();
         (sx, sy)
     };
     let cos_phi = normal::cos_phi(wo_stretched);
@@ -393,28 +410,41 @@ impl<M: MicrofacetDistribution, F: Fresnel> Bxdf for TorranceSparrowRBxdf<M, F>
         BXDF_REFLECTION | BXDF_GLOSSY
     }
 
+    #[inline]
+    fn regularize<'a>(
+        &mut self, min_alpha: Float, _alloc: &'a Allocator,
+        _replace: &mut FnMut(Pointer<'a, Bxdf>)
+    ) -> bool {
+        self.distribution.regularize(min_alpha);
+        true
+    }
+
     fn evaluate(&self, wo: Vector3f, wi: Vector3f) -> RGBSpectrumf {
         let wh = (wo+wi).normalize();
         if wh.x.is_nan() || wh.y.is_nan() || wh.z.is_nan() {
             RGBSpectrumf::black()
         } else {
-            self.reflectance * self.distribution.distribution(wh)
-             * self.distribution.visible_both(wo, wi)
-             * self.fresnel.evaluate(wi.dot(wh))
-             / (4. as Float * wo.z.abs() * wi.z.abs())
+            self.reflectance * self.fresnel.evaluate(wi.dot(wh)) * float::safe_div(
+                self.distribution.distribution(wh) * self.distribution.visible_both(wo, wi),
+                4. as Float * wo.z.abs() * wi.z.abs()
+            )
         }
     }
 
     fn evaluate_sampled(&self, wo: Vector3f, u: Point2f
-    ) -> (RGBSpectrumf, Vector3f, Float, BxdfType) {
+    ) -> BsdfSample {
         let wh = self.distribution.sample_wh(wo, u);
-        let pdf = self.distribution.pdf(wo, wh)/(4. as Float * wo.dot(wh));
+        // back-facing, or degenerate for grazing `wo`
+        if !(wo.dot(wh) > 0. as Float) {
+            return BsdfSample::new(RGBSpectrumf::black(), Vector3f::zero(), 0. as Float, self.kind());
+        }
+        let pdf = float::safe_div(self.distribution.pdf(wo, wh), 4. as Float * wo.dot(wh));
         let wi = (2. as Float * wh * wo.dot(wh)- wo).normalize();
         if wo.z * wi.z <= 0. as Float {
             trace!("not samehemisphere for TSR, blacking");
-            (RGBSpectrumf::black(), wi, pdf, self.kind())
+            BsdfSample::new(RGBSpectrumf::black(), wi, pdf, self.kind())
         } else {
-            let ret = (self.evaluate(wo, wi), wi, pdf, self.kind());
+            let ret = BsdfSample::new(self.evaluate(wo, wi), wi, pdf, self.kind());
             trace!("samehemisphere for TSR, {:?}", ret);
             ret
         }
@@ -423,7 +453,7 @@ impl<M: MicrofacetDistribution, F: Fresnel> Bxdf for TorranceSparrowRBxdf<M, F>
     fn pdf(&self, wo: Vector3f, wi: Vector3f) -> Float {
         if wo.z *wi.z <= 0. as Float { return 0. as Float; }
         let wh = (wo + wi).normalize();
-        let pdf = self.distribution.pdf(wo, wh)/(4. as Float * wo.dot(wh));
+        let pdf = float::safe_div(self.distribution.pdf(wo, wh), 4. as Float * wo.dot(wh));
         // pdf.max(0. as Float)
         pdf
     }
@@ -456,6 +486,15 @@ impl<M: MicrofacetDistribution> Bxdf for TorranceSparrowTBxdf<M> {
         BXDF_TRANSMISSION | BXDF_GLOSSY
     }
 
+    #[inline]
+    fn regularize<'a>(
+        &mut self, min_alpha: Float, _alloc: &'a Allocator,
+        _replace: &mut FnMut(Pointer<'a, Bxdf>)
+    ) -> bool {
+        self.distribution.regularize(min_alpha);
+        true
+    }
+
     fn evaluate(&self, wo: Vector3f, wi: Vector3f) -> RGBSpectrumf {
         // reject reflectance
         if wo.z * wi.z > 0. as Float { return RGBSpectrumf::black(); }
@@ -476,11 +515,13 @@ impl<M: MicrofacetDistribution> Bxdf for TorranceSparrowTBxdf<M> {
         let cosih = wi.dot(wh);
         let sqrt_denom = cosoh + eta * cosih;
 
-        let ret = self.transmittance * self.distribution.distribution(wh)
-            * self.distribution.visible_both(wo, wi)
+        let ret = self.transmittance
             * (RGBSpectrumf::grey_scale(1. as Float) - f)
-            * cosih.abs() * cosoh.abs()//  * 2.5 as Float
-            / (normal::cos_theta(wo).abs() * normal::cos_theta(wi).abs()*sqrt_denom*sqrt_denom);
+            * float::safe_div(
+                self.distribution.distribution(wh) * self.distribution.visible_both(wo, wi)
+                 * cosih.abs() * cosoh.abs(),
+                normal::cos_theta(wo).abs() * normal::cos_theta(wi).abs()*sqrt_denom*sqrt_denom
+            );
         if ret.r() < 0. as Float {
             warn!("negative f:");
             warn!("\tdis:{}, v:{}, cih: {}, coh:{}, ",self.distribution.distribution(wh), self.distribution.visible_both(wo, wi), cosih.abs(), cosoh.abs());
@@ -490,8 +531,12 @@ impl<M: MicrofacetDistribution> Bxdf for TorranceSparrowTBxdf<M> {
     }
 
     fn evaluate_sampled(&self, wo: Vector3f, u: Point2f
-    ) -> (RGBSpectrumf, Vector3f, Float, BxdfType) {
+    ) -> BsdfSample {
         let wh = self.distribution.sample_wh(wo, u);
+        // back-facing, or degenerate for grazing `wo`
+        if !(wo.dot(wh) > 0. as Float) {
+            return BsdfSample::new(RGBSpectrumf::black(), Vector3f::zero(), 0. as Float, self.kind());
+        }
         let eta = if wo.z > 0. as Float {
             self.fresnel.eta0 / self.fresnel.eta1
         } else {
@@ -500,12 +545,12 @@ impl<M: MicrofacetDistribution> Bxdf for TorranceSparrowTBxdf<M> {
         if let Some(wi) = normal::refract(wo, wh, eta) {
             let pdf = self.pdf(wo, wi);
             let f = self.evaluate(wo, wi);
-            let ret = (f, wi, pdf, self.kind());
+            let ret = BsdfSample::refracted(f, wi, pdf, self.kind(), 1. as Float / eta);
             trace!("refraction found {:?}", ret);
             ret
         } else {
             trace!("total reflection, no refraction");
-            (RGBSpectrumf::black(), Vector3f::zero(), 0. as Float, self.kind())
+            BsdfSample::refracted(RGBSpectrumf::black(), Vector3f::zero(), 0. as Float, self.kind(), 1. as Float / eta)
         }
     }
 
@@ -524,7 +569,7 @@ impl<M: MicrofacetDistribution> Bxdf for TorranceSparrowTBxdf<M> {
             return 1. as Float;
         }
         let sqrt_denom = wo.dot(wh) + eta*wi.dot(wh);
-        let dhdi = eta*eta*wi.dot(wh).abs() / (sqrt_denom*sqrt_denom);
+        let dhdi = float::safe_div(eta*eta*wi.dot(wh).abs(), sqrt_denom*sqrt_denom);
         trace!("wo: {:?}, wi: {:?}, wh: {:?}, sqrtdenom: {}", wo, wi, wh, sqrt_denom);
         let pdf = self.distribution.pdf(wo, wh) * dhdi;
         // pdf.max(0. as Float)
@@ -556,6 +601,19 @@ impl<M> AshikhminShirleyBxdf<M> {
             distribution
         }
     }
+
+    /// probabilities of sampling the specular and the diffuse lobe,
+    /// proportional to estimations of their albedos
+    #[inline]
+    pub fn lobe_weights(&self) -> (Float, Float) {
+        let rs = self.specular.to_xyz().y.min(1. as Float).max(0. as Float);
+        let rd = self.diffuse.to_xyz().y.min(1. as Float).max(0. as Float);
+        // hemispherical average of the schlick fresnel term
+        let specular = rs + (1. as Float - rs) / 21. as Float;
+        let diffuse = rd * (1. as Float - rs);
+        let ps = specular / (specular + diffuse);
+        (ps, 1. as Float - ps)
+    }
 }
 
 impl<M: MicrofacetDistribution> Bxdf for AshikhminShirleyBxdf<M> {
@@ -564,6 +622,15 @@ impl<M: MicrofacetDistribution> Bxdf for AshikhminShirleyBxdf<M> {
         BXDF_REFLECTION | BXDF_GLOSSY
     }
 
+    #[inline]
+    fn regularize<'a>(
+        &mut self, min_alpha: Float, _alloc: &'a Allocator,
+        _replace: &mut FnMut(Pointer<'a, Bxdf>)
+    ) -> bool {
+        self.distribution.regularize(min_alpha);
+        true
+    }
+
     fn evaluate(&self, wo: Vector3f, wi: Vector3f) -> RGBSpectrumf {
         let wh = wo+wi;
         if relative_eq!(wh.magnitude2(), 0. as Float) {
@@ -578,9 +645,9 @@ impl<M: MicrofacetDistribution> Bxdf for AshikhminShirleyBxdf<M> {
             let diffuse = (28. as Float / (23. as Float * float::pi()))
              * self.diffuse * (RGBSpectrumf::grey_scale(1. as Float) - self.specular)
              * term(wo) * term(wi);
-            let specular = self.distribution.distribution(wh)
-             * schlick_fresnel(wi.dot(wh), self.specular)
-             / (
+            let specular = schlick_fresnel(wi.dot(wh), self.specular)
+             * float::safe_div(
+                 self.distribution.distribution(wh),
                  4. as Float * wi.dot(wh).abs()
                   * normal::cos_theta(wi).abs().max(normal::cos_theta(wo).abs())
              );
@@ -589,35 +656,34 @@ impl<M: MicrofacetDistribution> Bxdf for AshikhminShirleyBxdf<M> {
     }
 
     fn evaluate_sampled(&self, wo: Vector3f, mut u: Point2f
-    ) -> (RGBSpectrumf, Vector3f, Float, BxdfType) {
+    ) -> BsdfSample {
         // sample according to specular distribution
-        // or according to the diffuse term, both with
-        // probability of 1/2
-        let wi = if u.x < 0.5 as Float {
-            u.x *= 2. as Float;
+        // or according to the diffuse term, picked by `lobe_weights`
+        let (ps, pd) = self.lobe_weights();
+        let wi = if u.x < ps {
+            u.x = (u.x / ps).min(float::one_minus_epsilon());
             let wh = self.distribution.sample_wh(wo, u);
             let wi = (2. as Float * wh * wo.dot(wh)- wo).normalize();
             if wo.z * wi.z <= 0. as Float {
-                return (RGBSpectrumf::black(), wi, self.pdf(wo, wi), self.kind());
+                return BsdfSample::new(RGBSpectrumf::black(), wi, self.pdf(wo, wi), self.kind());
             } else {
                 wi
             }
         } else {
-            u.x = (1. as Float - u.x) * 2. as Float;
+            u.x = ((u.x - ps) / pd).min(float::one_minus_epsilon());
             let mut wi = sample::sample_cosw_hemisphere(u);
             if wi.z < 0.0 as Float {wi.z = -wi.z;}
             wi
         };
-        (self.evaluate(wo, wi), wi, self.pdf(wo, wi), self.kind())
+        BsdfSample::new(self.evaluate(wo, wi), wi, self.pdf(wo, wi), self.kind())
     }
 
     fn pdf(&self, wo: Vector3f, wi: Vector3f) -> Float {
         if wo.z * wi.z < 0. as Float { return 0. as Float; }
         let wh = (wo + wi).normalize();
-        let pdf = 0.5 as Float * (
-            self.distribution.pdf(wo, wh)/(4. as Float * wo.dot(wh))
-             + normal::cos_theta(wi).abs() * float::frac_1_pi()
-        );
+        let (ps, pd) = self.lobe_weights();
+        let pdf = ps * float::safe_div(self.distribution.pdf(wo, wh), 4. as Float * wo.dot(wh))
+         + pd * normal::cos_theta(wi).abs() * float::frac_1_pi();
         // pdf.max(0. as Float)
         pdf
     }
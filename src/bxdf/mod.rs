@@ -12,6 +12,7 @@ use geometry::prelude::*;
 use spectrum::{Spectrum, RGBSpectrumf};
 use sample;
 use std::cmp;
+use aren_alloc::{Allocator, Pointer};
 
 /// A bidirectional distribution function
 pub trait Bxdf {
@@ -31,7 +32,8 @@ pub trait Bxdf {
     /// `u` from $[0,1)^2$, sample an incoming direction `wi`,
     /// and returns it with function value evaluated as `f(wo, wi)`,
     /// as well as the pdf associated with the incoming direction,
-    /// as well as the type of the scattering event.
+    /// the type of the scattering event and the relative index
+    /// of refraction across it.
     ///
     /// The default implementation samples the incoming direction
     /// with a cos-weighted distribution above the hemisphere,
@@ -39,12 +41,12 @@ pub trait Bxdf {
     /// to `evaluate` and `pdf`. Bxdfs having better distribution descriptions
     /// should overwrite the behavior when needed.
     #[inline]
-    fn evaluate_sampled(&self, wo: Vector3f, u: Point2f) -> (RGBSpectrumf, Vector3f, Float, BxdfType) {
+    fn evaluate_sampled(&self, wo: Vector3f, u: Point2f) -> BsdfSample {
         let mut wi = sample::sample_cosw_hemisphere(u);
         if wo.z < 0.0 as Float {wi.z = -wi.z;}
         let pdf = self.pdf(wo, wi);
         let spectrum = self.evaluate(wo, wi);
-        (spectrum, wi, pdf, self.kind())
+        BsdfSample::new(spectrum, wi, pdf, self.kind())
     }
 
     /// evaluate the function given two normalized directions,
@@ -62,13 +64,14 @@ pub trait Bxdf {
     /// `u` from $[0,1)^2$, sample an incoming direction `wi`,
     /// and returns it with function value evaluated as `f(wo, wi)`,
     /// as well as the pdf associated with the incoming direction，
-    /// as well as the type of the scattering event.
+    /// the type of the scattering event and the relative index
+    /// of refraction across it.
     /// The particles being traced is camera-ray importance, rather tan light radiance
     ///
     /// default implementation assumes bxdf have symmetrical scattering 
     /// properties and just forwards the call to `self.evaluate_sampled`
     #[inline]
-    fn evaluate_importance_sampled(&self, wo: Vector3f, u: Point2f) -> (RGBSpectrumf, Vector3f, Float, BxdfType) {
+    fn evaluate_importance_sampled(&self, wo: Vector3f, u: Point2f) -> BsdfSample {
         self.evaluate_sampled(wo, u)
     }
 
@@ -87,9 +90,9 @@ pub trait Bxdf {
     fn rho_hd(&self, wo: Vector3f, samples: &[Point2f]) -> RGBSpectrumf {
         let mut ret = RGBSpectrumf::black();
         for sample in samples {
-            let (spec, wi, pdf, _) = self.evaluate_sampled(wo, *sample);
-            if pdf > 0.0 as Float {
-                ret += spec * normal::cos_theta(wi).abs() / pdf;
+            let s = self.evaluate_sampled(wo, *sample);
+            if s.pdf > 0.0 as Float {
+                ret += s.f * normal::cos_theta(s.wi).abs() / s.pdf;
             }
         }
         ret/(samples.len() as Float)
@@ -105,15 +108,86 @@ pub trait Bxdf {
             let wo = unsafe {
                 sample::sample_uniform_hemisphere(*samples0.get_unchecked(i))
             };
-            let (spec, wi, pdfi, _) = unsafe {
+            let s = unsafe {
                 self.evaluate_sampled(wo, *samples1.get_unchecked(i))
             };
-            if pdfi > 0.0 as Float {
-                ret += spec * (normal::cos_theta(wi)*normal::cos_theta(wo)).abs() / (pdfi * pdfo);
+            if s.pdf > 0.0 as Float {
+                ret += s.f * (normal::cos_theta(s.wi)*normal::cos_theta(wo)).abs() / (s.pdf * pdfo);
             }
         }
         ret / (nsamples as Float)
     }
+
+    /// make this bxdf no sharper than a microfacet lobe of roughness
+    /// `min_alpha`, either by widening it in place, or by handing
+    /// replacement lobes allocated from `alloc` to `replace`.
+    /// Returns whether this bxdf should be kept.
+    ///
+    /// default implementation leaves the bxdf as is
+    #[inline]
+    fn regularize<'a>(
+        &mut self, _min_alpha: Float, _alloc: &'a Allocator,
+        _replace: &mut FnMut(Pointer<'a, Bxdf>)
+    ) -> bool {
+        true
+    }
+}
+
+/// An incoming direction sampled from a bxdf or a bsdf,
+/// along with what's evaluated for it
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BsdfSample {
+    /// the function value `f(wo, wi)`
+    pub f: RGBSpectrumf,
+    /// the incoming direction sampled
+    pub wi: Vector3f,
+    /// pdf associated with `wi`
+    pub pdf: Float,
+    /// type of the scattering event
+    pub kind: BxdfType,
+    /// relative index of refraction of the sampled lobe, namely the
+    /// index on the side of `wi` over the one on the side of `wo`.
+    /// Always 1 for reflection.
+    ///
+    /// Radiance transmitted is scaled by its inverse square.
+    pub eta: Float,
+}
+
+impl BsdfSample {
+    /// construction of a sample not refracted
+    #[inline]
+    pub fn new(f: RGBSpectrumf, wi: Vector3f, pdf: Float, kind: BxdfType) -> BsdfSample {
+        BsdfSample::refracted(f, wi, pdf, kind, 1. as Float)
+    }
+
+    /// construction of a sample refracted with relative index `eta`
+    #[inline]
+    pub fn refracted(f: RGBSpectrumf, wi: Vector3f, pdf: Float, kind: BxdfType, eta: Float) -> BsdfSample {
+        BsdfSample{
+            f: f, wi: wi, pdf: pdf, kind: kind, eta: eta,
+        }
+    }
+
+    /// a sample carrying nothing
+    #[inline]
+    pub fn black() -> BsdfSample {
+        BsdfSample::new(
+            RGBSpectrumf::black(), Vector3f::new(0. as Float, 1. as Float, 0. as Float),
+            0. as Float, BxdfType::empty()
+        )
+    }
+
+    /// check if the scattering event is a transmission
+    #[inline]
+    pub fn is_transmission(&self) -> bool {
+        self.kind.intersects(BXDF_TRANSMISSION)
+    }
+
+    /// check if the scattering event is perfectly specular
+    #[inline]
+    pub fn is_specular(&self) -> bool {
+        self.kind.intersects(BXDF_SPECULAR)
+    }
 }
 
 bitflags! {
@@ -132,9 +206,13 @@ bitflags! {
 }
 
 pub mod scaled;
+pub mod two_sided;
 pub mod fresnel;
 pub mod specular;
 pub mod lambertian;
 pub mod oren_nayar;
 pub mod prelude;
 pub mod microfacet;
+
+#[cfg(test)]
+mod tests;
@@ -52,9 +52,9 @@ impl Bxdf for OrenNayer {
         let ci = normal::cos_theta(wi).abs();
         let co = normal::cos_theta(wo).abs();
         let (sin_a, tan_b) = if ci > co {
-            (sin_theta_o, sin_theta_i/ci)
+            (sin_theta_o, float::safe_div(sin_theta_i, ci))
         } else {
-            (sin_theta_i, sin_theta_o/co)
+            (sin_theta_i, float::safe_div(sin_theta_o, co))
         };
         self.reflectance * float::frac_1_pi() * (self.coef_a + self.coef_b * max_cos * sin_a * tan_b)
     }
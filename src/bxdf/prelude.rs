@@ -6,10 +6,11 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-pub use super::{Bxdf, BxdfType, BXDF_REFLECTION, BXDF_TRANSMISSION, BXDF_DIFFUSE, BXDF_GLOSSY, BXDF_SPECULAR, BXDF_ALL};
-pub use super::fresnel::{Conductor, Dielectric, Noop as NoopFresnel, Fresnel, FresnelBxdf, FresnelTBxdf};
-pub use super::lambertian::{LambertianRBxdf, LambertianTBxdf};
+pub use super::{Bxdf, BsdfSample, BxdfType, BXDF_REFLECTION, BXDF_TRANSMISSION, BXDF_DIFFUSE, BXDF_GLOSSY, BXDF_SPECULAR, BXDF_ALL};
+pub use super::fresnel::{Conductor, Dielectric, Noop as NoopFresnel, Schlick, Fresnel, FresnelBxdf, FresnelTBxdf, ThinDielectricBxdf};
+pub use super::lambertian::{LambertianRBxdf, CoatedLambertianRBxdf, LambertianTBxdf};
 pub use super::oren_nayar::OrenNayer as OrenNayerBxdf;
 pub use super::scaled::ScaledBxdf;
+pub use super::two_sided::TwoSidedBxdf;
 pub use super::specular::{SpecularRBxdf, SpecularTBxdf};
 pub use super::microfacet::{MicrofacetDistribution, Beckmann, Trowbridge, TorranceSparrowRBxdf, TorranceSparrowTBxdf, AshikhminShirleyBxdf};
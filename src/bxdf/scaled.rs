@@ -29,9 +29,10 @@ impl<T: Bxdf> Bxdf for ScaledBxdf<T> {
     }
 
     #[inline]
-    fn evaluate_sampled(&self, wo: Vector3f, sample: Point2f) -> (RGBSpectrumf, Vector3f, Float, BxdfType) {
-        let (s, v, f, t) = self.inner.evaluate_sampled(wo, sample);
-        (s * self.scale, v, f, t)
+    fn evaluate_sampled(&self, wo: Vector3f, sample: Point2f) -> BsdfSample {
+        let mut ret = self.inner.evaluate_sampled(wo, sample);
+        ret.f = ret.f * self.scale;
+        ret
     }
 
     #[inline]
@@ -10,6 +10,7 @@
 
 use super::*;
 use super::fresnel::*;
+use super::microfacet::{Trowbridge, TorranceSparrowRBxdf, TorranceSparrowTBxdf};
 use spectrum::Spectrum;
 
 /// A specular reflectional bxdf
@@ -29,12 +30,25 @@ impl<F: Fresnel> SpecularRBxdf<F> {
     }
 }
 
-impl<T: Fresnel> Bxdf for SpecularRBxdf<T> {
+impl<T: Fresnel + Copy + 'static> Bxdf for SpecularRBxdf<T> {
     #[inline]
     fn kind(&self) -> BxdfType {
         BXDF_REFLECTION | BXDF_SPECULAR
     }
 
+    /// replaced by a glossy reflection lobe of roughness `min_alpha`
+    fn regularize<'a>(
+        &mut self, min_alpha: Float, alloc: &'a Allocator,
+        replace: &mut FnMut(Pointer<'a, Bxdf>)
+    ) -> bool {
+        replace(alloc.alloc(TorranceSparrowRBxdf::new(
+            self.reflectance,
+            Trowbridge{ ax: min_alpha, ay: min_alpha },
+            self.fresnel
+        )));
+        false
+    }
+
     /// evaluate the function given two normalized directions.
     ///
     /// As specular surfaces are `totally` specular, this method
@@ -53,11 +67,11 @@ impl<T: Fresnel> Bxdf for SpecularRBxdf<T> {
     /// with pdf always equals to one.
     /// Evaluation behavior are described by the fresnel factor
     #[inline]
-    fn evaluate_sampled(&self, wo: Vector3f, _sample: Point2f) -> (RGBSpectrumf, Vector3f, Float, BxdfType) {
+    fn evaluate_sampled(&self, wo: Vector3f, _sample: Point2f) -> BsdfSample {
         let r = Vector3f::new(-wo.x, -wo.y, wo.z);
         let cos = normal::cos_theta(r);
-        let s = self.fresnel.evaluate(cos) * self.reflectance / cos.abs();
-        (s, r, 1.0 as Float, self.kind())
+        let s = self.fresnel.evaluate(cos) * self.reflectance * float::safe_div(1. as Float, cos.abs());
+        BsdfSample::new(s, r, 1.0 as Float, self.kind())
     }
 }
 
@@ -84,18 +98,38 @@ impl Bxdf for SpecularTBxdf {
         BXDF_TRANSMISSION | BXDF_SPECULAR
     }
 
+    /// replaced by a glossy transmission lobe of roughness `min_alpha`
+    fn regularize<'a>(
+        &mut self, min_alpha: Float, alloc: &'a Allocator,
+        replace: &mut FnMut(Pointer<'a, Bxdf>)
+    ) -> bool {
+        replace(alloc.alloc(TorranceSparrowTBxdf::new(
+            self.transmittance,
+            Trowbridge{ ax: min_alpha, ay: min_alpha },
+            self.fresnel.eta0, self.fresnel.eta1
+        )));
+        false
+    }
+
     #[inline]
     fn evaluate(&self, _wo: Vector3f, _wi: Vector3f) -> RGBSpectrumf {
         RGBSpectrumf::black()
     }
 
     #[inline]
-    fn evaluate_sampled(&self, wo: Vector3f, _sample: Point2f) -> (RGBSpectrumf, Vector3f, Float, BxdfType) {
+    fn evaluate_sampled(&self, wo: Vector3f, _sample: Point2f) -> BsdfSample {
         let r = Vector3f::new(-wo.x, -wo.y, wo.z);
         let cos = normal::cos_theta(r);
         let t = RGBSpectrumf::grey_scale(1.0 as Float) - self.fresnel.evaluate(cos);
+        let eta = if wo.z > 0. as Float {
+            self.fresnel.eta1 / self.fresnel.eta0
+        } else {
+            self.fresnel.eta0 / self.fresnel.eta1
+        };
         // TODO: Double check
-        (t*self.transmittance/cos.abs(), r, 1.0 as Float, self.kind())
+        BsdfSample::refracted(
+            t*self.transmittance*float::safe_div(1. as Float, cos.abs()), r, 1.0 as Float, self.kind(), eta
+        )
     }
 
     #[inline]
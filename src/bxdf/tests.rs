@@ -0,0 +1,378 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// tests
+use super::*;
+extern crate rand;
+use self::rand::*;
+
+#[cfg(test)]
+mod test_ashikhmin {
+    use super::*;
+    use super::microfacet::*;
+
+    fn bxdf(diffuse: Float, specular: Float, alpha: Float) -> AshikhminShirleyBxdf<Trowbridge> {
+        AshikhminShirleyBxdf::new(
+            RGBSpectrumf::grey_scale(diffuse),
+            RGBSpectrumf::grey_scale(specular),
+            Trowbridge{ax: alpha, ay: alpha}
+        )
+    }
+
+    fn wo() -> Vector3f {
+        let theta = 0.5 as Float;
+        Vector3f::new(theta.sin(), 0. as Float, theta.cos())
+    }
+
+    fn direction(cos_theta: Float, phi: Float) -> Vector3f {
+        let sin_theta = (1. as Float - cos_theta * cos_theta).max(0. as Float).sqrt();
+        Vector3f::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+    }
+
+    #[test]
+    fn test_pdf_chi2() {
+        const NCOS: usize = 10;
+        const NPHI: usize = 20;
+        const SUBDIV: usize = 8;
+        let n = 200000;
+        let mut rng = StdRng::from_seed(&[12usize][..]);
+        for &(d, s, alpha) in &[(0.8, 0.04, 0.3), (0.05, 0.9, 0.2), (0.5, 0.5, 0.5)] {
+            let bxdf = bxdf(d as Float, s as Float, alpha as Float);
+            let wo = wo();
+            // expected counts, integrating the pdf over each bin
+            // with cosine and azimuth being uniform in solid angle
+            let dcos = 1. as Float / NCOS as Float;
+            let dphi = float::pi() * 2. as Float / NPHI as Float;
+            let mut expected = vec![0. as Float; NCOS * NPHI];
+            let mut integral = 0. as Float;
+            for (i, e) in expected.iter_mut().enumerate() {
+                let (ic, ip) = (i / NPHI, i % NPHI);
+                for sc in 0..SUBDIV {
+                    for sp in 0..SUBDIV {
+                        let c = (ic as Float + (sc as Float + 0.5 as Float) / SUBDIV as Float) * dcos;
+                        let p = (ip as Float + (sp as Float + 0.5 as Float) / SUBDIV as Float) * dphi;
+                        *e += bxdf.pdf(wo, direction(c, p)) * dcos * dphi / (SUBDIV * SUBDIV) as Float;
+                    }
+                }
+                integral += *e;
+            }
+
+            let mut observed = vec![0usize; NCOS * NPHI];
+            let mut valid = 0;
+            for _ in 0..n {
+                let u = Point2f::new(rng.gen(), rng.gen());
+                let BsdfSample{f, wi, pdf, ..} = bxdf.evaluate_sampled(wo, u);
+                if pdf == 0. as Float || f.is_black() || wi.z <= 0. as Float { continue; }
+                assert_relative_eq!(pdf, bxdf.pdf(wo, wi), max_relative = 1e-3);
+                let ic = ((wi.z / dcos) as usize).min(NCOS - 1);
+                let mut phi = wi.y.atan2(wi.x);
+                if phi < 0. as Float { phi += float::pi() * 2. as Float; }
+                let ip = ((phi / dphi) as usize).min(NPHI - 1);
+                observed[ic * NPHI + ip] += 1;
+                valid += 1;
+            }
+            // the pdf integrates to the probability of getting a direction
+            // at all, as those reflected below the horizon are lost
+            assert_relative_eq!(integral, valid as Float / n as Float, epsilon = 1e-2);
+            // pool bins with too few expected samples
+            let mut chi2 = 0. as Float;
+            let mut dof = 0;
+            let (mut pooled_e, mut pooled_o) = (0. as Float, 0. as Float);
+            for (e, &o) in expected.iter().zip(observed.iter()) {
+                let e = e * n as Float;
+                if e < 5. as Float {
+                    pooled_e += e;
+                    pooled_o += o as Float;
+                } else {
+                    chi2 += (o as Float - e) * (o as Float - e) / e;
+                    dof += 1;
+                }
+            }
+            if pooled_e > 5. as Float {
+                chi2 += (pooled_o - pooled_e) * (pooled_o - pooled_e) / pooled_e;
+                dof += 1;
+            }
+            let bound = dof as Float + 5. as Float * (2. as Float * dof as Float).sqrt();
+            assert!(chi2 < bound, "chi2 {} over {} bins for {:?}", chi2, dof, (d, s, alpha));
+        }
+    }
+
+    // variance of the albedo estimation with sampling probability
+    // of the specular lobe given by `ps`
+    fn albedo_variance(bxdf: &AshikhminShirleyBxdf<Trowbridge>, ps: Option<Float>) -> Float {
+        let wo = wo();
+        let mut rng = StdRng::from_seed(&[13usize][..]);
+        let n = 20000;
+        let (mut sum, mut sum2) = (0. as Float, 0. as Float);
+        for _ in 0..n {
+            let mut u = Point2f::new(rng.gen(), rng.gen());
+            let (f, wi, pdf) = if let Some(ps) = ps {
+                let wi = if u.x < ps {
+                    u.x /= ps;
+                    let wh = bxdf.distribution.sample_wh(wo, u);
+                    2. as Float * wh * wo.dot(wh) - wo
+                } else {
+                    u.x = (u.x - ps) / (1. as Float - ps);
+                    sample::sample_cosw_hemisphere(u)
+                };
+                if wi.z <= 0. as Float { (RGBSpectrumf::black(), wi, 0. as Float) } else {
+                    let wh = (wo + wi).normalize();
+                    let pdf = ps * bxdf.distribution.pdf(wo, wh) / (4. as Float * wo.dot(wh))
+                     + (1. as Float - ps) * wi.z * float::frac_1_pi();
+                    (bxdf.evaluate(wo, wi), wi, pdf)
+                }
+            } else {
+                let BsdfSample{f, wi, pdf, ..} = bxdf.evaluate_sampled(wo, u);
+                (f, wi, pdf)
+            };
+            let x = if pdf > 0. as Float && wi.z > 0. as Float {
+                f.to_xyz().y * wi.z / pdf
+            } else {
+                0. as Float
+            };
+            sum += x;
+            sum2 += x * x;
+        }
+        let mean = sum / n as Float;
+        sum2 / n as Float - mean * mean
+    }
+
+    #[test]
+    fn test_lobe_weights_reduce_variance() {
+        let mostly_diffuse = bxdf(0.9 as Float, 0.02 as Float, 0.4 as Float);
+        let (ps, pd) = mostly_diffuse.lobe_weights();
+        assert!(ps < pd);
+        assert!(albedo_variance(&mostly_diffuse, None) < albedo_variance(&mostly_diffuse, Some(0.5 as Float)));
+
+        let mostly_specular = bxdf(0.05 as Float, 0.9 as Float, 0.1 as Float);
+        let (ps, pd) = mostly_specular.lobe_weights();
+        assert!(ps > pd);
+        assert!(albedo_variance(&mostly_specular, None) < albedo_variance(&mostly_specular, Some(0.5 as Float)));
+    }
+}
+
+#[cfg(test)]
+mod test_pathological {
+    use super::*;
+    use super::prelude::*;
+    use spectrum::{RGBSpectrumf, Spectrum};
+
+    fn bxdfs() -> Vec<(&'static str, Box<Bxdf>)> {
+        let white = RGBSpectrumf::grey_scale(0.8 as Float);
+        let rough = 0.3 as Float;
+        vec![
+            ("lambertian", Box::new(LambertianRBxdf::new(white))),
+            ("lambertian transmission", Box::new(LambertianTBxdf::new(white))),
+            ("oren-nayar", Box::new(OrenNayerBxdf::new(white, 0.5 as Float))),
+            ("specular", Box::new(SpecularRBxdf::new(white, Dielectric::new(1. as Float, 1.5 as Float)))),
+            ("specular transmission", Box::new(SpecularTBxdf::new(white, 1. as Float, 1.5 as Float))),
+            ("fresnel", Box::new(FresnelBxdf::new(white, white, 1. as Float, 1.5 as Float))),
+            ("fresnel transmission", Box::new(FresnelTBxdf{transmittance: white, eta0: 1. as Float, eta1: 1.5 as Float})),
+            ("beckmann", Box::new(TorranceSparrowRBxdf::new(white, Beckmann{ax: rough, ay: rough}, Dielectric::new(1. as Float, 1.5 as Float)))),
+            ("trowbridge", Box::new(TorranceSparrowRBxdf::new(white, Trowbridge{ax: rough, ay: rough}, NoopFresnel{}))),
+            ("microfacet transmission", Box::new(TorranceSparrowTBxdf::new(white, Trowbridge{ax: rough, ay: rough}, 1. as Float, 1.5 as Float))),
+            ("ashikhmin", Box::new(AshikhminShirleyBxdf::new(white, white * 0.5 as Float, Beckmann{ax: rough, ay: rough}))),
+        ]
+    }
+
+    fn check(name: &str, bxdf: &Bxdf, wo: Vector3f, wi: Vector3f, u: Point2f) {
+        let finite = |s: RGBSpectrumf| s.r().is_finite() && s.g().is_finite() && s.b().is_finite();
+        let f = bxdf.evaluate(wo, wi);
+        assert!(finite(f), "{}: f({:?}, {:?}) = {:?}", name, wo, wi, f);
+        let pdf = bxdf.pdf(wo, wi);
+        assert!(pdf.is_finite(), "{}: pdf({:?}, {:?}) = {}", name, wo, wi, pdf);
+        let BsdfSample{f, pdf, ..} = bxdf.evaluate_sampled(wo, u);
+        assert!(finite(f) && pdf.is_finite(), "{}: sampled {:?} with pdf {} from {:?}", name, f, pdf, wo);
+        let BsdfSample{f, pdf, ..} = bxdf.evaluate_importance_sampled(wo, u);
+        assert!(finite(f) && pdf.is_finite(), "{}: importance sampled {:?} with pdf {} from {:?}", name, f, pdf, wo);
+    }
+
+    #[test]
+    fn test_degenerate_directions() {
+        let oblique = Vector3f::new(0.6 as Float, 0. as Float, 0.8 as Float);
+        let grazing = Vector3f::new(1. as Float, 0. as Float, 0. as Float);
+        let normal = Vector3f::new(0. as Float, 0. as Float, 1. as Float);
+        let cases = [
+            // `wo == wh`
+            (oblique, oblique),
+            (normal, normal),
+            // `wo` perpendicular to `n`
+            (grazing, oblique),
+            (oblique, grazing),
+            (grazing, grazing),
+            (grazing, -grazing),
+            // `wo + wi` of zero length
+            (oblique, -oblique),
+            (normal, -normal),
+            (oblique, Vector3f::zero()),
+        ];
+        let us = [
+            Point2f::new(0. as Float, 0. as Float),
+            Point2f::new(0.5 as Float, 0.5 as Float),
+            Point2f::new(float::one_minus_epsilon(), float::one_minus_epsilon()),
+        ];
+        for (name, bxdf) in bxdfs() {
+            for &(wo, wi) in &cases {
+                for &u in &us {
+                    check(name, &*bxdf, wo, wi, u);
+                    check(name, &*bxdf, -wo, wi, u);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_random_directions() {
+        let mut rng = StdRng::from_seed(&[14usize][..]);
+        let direction = |rng: &mut StdRng| sample::sample_uniform_sphere(Point2f::new(rng.gen(), rng.gen()));
+        for (name, bxdf) in bxdfs() {
+            for _ in 0..4096 {
+                let wo = direction(&mut rng);
+                let wi = direction(&mut rng);
+                check(name, &*bxdf, wo, wi, Point2f::new(rng.gen(), rng.gen()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_regularize {
+    use super::*;
+    use super::prelude::*;
+    use spectrum::{RGBSpectrumf, Spectrum};
+    use aren_alloc::{Allocator, Pointer};
+
+    fn regularize<'a, B: Bxdf>(
+        bxdf: &mut B, min_alpha: Float, alloc: &'a Allocator
+    ) -> (bool, Vec<Pointer<'a, Bxdf>>) {
+        let mut lobes = Vec::new();
+        let keep = bxdf.regularize(min_alpha, alloc, &mut |lobe| lobes.push(lobe));
+        (keep, lobes)
+    }
+
+    #[test]
+    fn test_specular_replaced() {
+        let alloc = Allocator::new();
+        let white = RGBSpectrumf::grey_scale(1. as Float);
+        let mut glass = FresnelBxdf::new(white, white, 1. as Float, 1.5 as Float);
+        let (keep, lobes) = regularize(&mut glass, 0.1 as Float, &alloc);
+        assert!(!keep);
+        assert_eq!(lobes.len(), 2);
+        let wo = Vector3f::new(0.3 as Float, 0. as Float, 1. as Float).normalize();
+        let mut rng = StdRng::new().unwrap();
+        for lobe in &lobes {
+            assert!(lobe.is(BXDF_GLOSSY) && !lobe.is(BXDF_SPECULAR));
+            // glossy lobes can be both sampled and evaluated
+            let mut hits = 0;
+            for _ in 0..64 {
+                let BsdfSample{f, wi, pdf, ..} = lobe.evaluate_sampled(wo, Point2f::new(rng.gen(), rng.gen()));
+                assert!(pdf.is_finite() && pdf >= 0. as Float);
+                if pdf > 0. as Float && !f.is_black() {
+                    assert_relative_eq!(pdf, lobe.pdf(wo, wi), max_relative = 1e-3 as Float);
+                    hits += 1;
+                }
+            }
+            assert!(hits > 0);
+        }
+    }
+
+    #[test]
+    fn test_microfacet_widened() {
+        let alloc = Allocator::new();
+        let white = RGBSpectrumf::grey_scale(1. as Float);
+        let mut sharp = TorranceSparrowRBxdf::new(white, Trowbridge{ax: 0.01 as Float, ay: 0.5 as Float}, NoopFresnel{});
+        let (keep, lobes) = regularize(&mut sharp, 0.1 as Float, &alloc);
+        assert!(keep && lobes.is_empty());
+        assert_eq!(sharp.distribution.ax, 0.1 as Float);
+        assert_eq!(sharp.distribution.ay, 0.5 as Float);
+    }
+}
+
+#[cfg(test)]
+mod test_eta {
+    use super::*;
+    use super::prelude::*;
+    use spectrum::{RGBSpectrumf, Spectrum};
+
+    const ETA: Float = 1.5;
+
+    fn dielectrics() -> Vec<(&'static str, Box<Bxdf>)> {
+        let white = RGBSpectrumf::grey_scale(1. as Float);
+        let rough = 0.2 as Float;
+        vec![
+            ("fresnel", Box::new(FresnelBxdf::new(white, white, 1. as Float, ETA))),
+            ("fresnel transmission", Box::new(FresnelTBxdf{transmittance: white, eta0: 1. as Float, eta1: ETA})),
+            ("microfacet transmission", Box::new(TorranceSparrowTBxdf::new(white, Trowbridge{ax: rough, ay: rough}, 1. as Float, ETA))),
+        ]
+    }
+
+    #[test]
+    fn test_transmission_eta() {
+        // picks transmission for the fresnel bxdf, reflecting less than 10%
+        let u = Point2f::new(0.5 as Float, 0.4 as Float);
+        let wo = Vector3f::new(0.3 as Float, 0.1 as Float, 0.9 as Float).normalize();
+        for (name, bxdf) in dielectrics() {
+            let entering = bxdf.evaluate_sampled(wo, u);
+            assert!(entering.is_transmission() && entering.wi.z < 0. as Float, "{}: {:?}", name, entering);
+            assert_relative_eq!(entering.eta, ETA, max_relative = 1e-5);
+            let leaving = bxdf.evaluate_sampled(-wo, u);
+            assert!(leaving.is_transmission() && leaving.wi.z > 0. as Float, "{}: {:?}", name, leaving);
+            assert_relative_eq!(leaving.eta, 1. as Float / ETA, max_relative = 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_reflection_eta() {
+        let wo = Vector3f::new(0.3 as Float, 0.1 as Float, 0.9 as Float).normalize();
+        let fresnel = FresnelBxdf::new(
+            RGBSpectrumf::grey_scale(1. as Float), RGBSpectrumf::grey_scale(1. as Float), 1. as Float, ETA
+        );
+        let reflected = fresnel.evaluate_sampled(wo, Point2f::new(0. as Float, 0.4 as Float));
+        assert!(!reflected.is_transmission());
+        assert_eq!(reflected.eta, 1. as Float);
+        let lambertian = LambertianRBxdf::new(RGBSpectrumf::grey_scale(0.5 as Float));
+        assert_eq!(lambertian.evaluate_sampled(wo, Point2f::new(0.3 as Float, 0.4 as Float)).eta, 1. as Float);
+    }
+}
+
+#[cfg(test)]
+mod test_thin_dielectric {
+    use super::*;
+    use super::prelude::*;
+    use spectrum::{RGBSpectrumf, Spectrum};
+
+    #[test]
+    fn test_normal_incidence() {
+        let white = RGBSpectrumf::grey_scale(1. as Float);
+        let thin = ThinDielectricBxdf::new(white, white, 1.5 as Float);
+        // a single interface reflects 4%, the slab 2*0.04/1.04
+        assert_relative_eq!(thin.slab_reflectance(1. as Float), 0.0769 as Float, epsilon = 1e-3);
+        assert_relative_eq!(thin.slab_reflectance(-1. as Float), 0.0769 as Float, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn test_energy() {
+        let white = RGBSpectrumf::grey_scale(1. as Float);
+        let thin = ThinDielectricBxdf::new(white, white, 1.5 as Float);
+        for &z in &[1. as Float, 0.7, 0.3, 0.05, -0.5] {
+            let wo = Vector3f::new((1. as Float - z * z).sqrt(), 0. as Float, z);
+            let reflected = thin.evaluate_sampled(wo, Point2f::new(0. as Float, 0.5 as Float));
+            let transmitted = thin.evaluate_sampled(wo, Point2f::new(0.999 as Float, 0.5 as Float));
+            assert!(!reflected.is_transmission() && transmitted.is_transmission());
+            assert_relative_eq!(reflected.wi, Vector3f::new(-wo.x, -wo.y, wo.z));
+            assert_relative_eq!(transmitted.wi, -wo);
+            assert_eq!(transmitted.eta, 1. as Float);
+            // the fraction of energy each lobe carries is its selection pdf
+            let r = reflected.f.r() * reflected.wi.z.abs();
+            let t = transmitted.f.r() * transmitted.wi.z.abs();
+            assert_relative_eq!(r, reflected.pdf, max_relative = 1e-5);
+            assert_relative_eq!(t, transmitted.pdf, max_relative = 1e-5);
+            assert_relative_eq!(r + t, 1. as Float, max_relative = 1e-5);
+        }
+    }
+}
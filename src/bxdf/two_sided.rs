@@ -0,0 +1,119 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Defines a two-sided bxdf
+use super::*;
+
+/// A two-sided bxdf, presenting reflection-only bxdfs from both
+/// sides of a surface. Directions below the surface are mirrored
+/// into the upper hemisphere before being handed to `back`, such
+/// that a bxdf defined for the upper hemisphere only looks the same
+/// from behind.
+#[derive(Copy, Clone, Debug)]
+pub struct TwoSidedBxdf<T> {
+    /// lobe seen from above the surface
+    pub front: T,
+    /// lobe seen from below the surface
+    pub back: T,
+}
+
+impl<T: Bxdf + Clone> TwoSidedBxdf<T> {
+    /// construction, with `inner` seen identically from both sides
+    #[inline]
+    pub fn new(inner: T) -> TwoSidedBxdf<T> {
+        TwoSidedBxdf::with_back(inner.clone(), inner)
+    }
+}
+
+impl<T: Bxdf> TwoSidedBxdf<T> {
+    /// construction, with different lobes on each side
+    #[inline]
+    pub fn with_back(front: T, back: T) -> TwoSidedBxdf<T> {
+        debug_assert!(!front.is(BXDF_TRANSMISSION) && !back.is(BXDF_TRANSMISSION));
+        TwoSidedBxdf{ front: front, back: back }
+    }
+}
+
+#[inline]
+fn flip(v: Vector3f) -> Vector3f {
+    Vector3f::new(v.x, v.y, -v.z)
+}
+
+impl<T: Bxdf> Bxdf for TwoSidedBxdf<T> {
+    #[inline]
+    fn kind(&self) -> BxdfType {
+        self.front.kind() | self.back.kind()
+    }
+
+    #[inline]
+    fn evaluate(&self, wo: Vector3f, wi: Vector3f) -> RGBSpectrumf {
+        if wo.z * wi.z <= 0. as Float { return RGBSpectrumf::black(); }
+        if wo.z > 0. as Float {
+            self.front.evaluate(wo, wi)
+        } else {
+            self.back.evaluate(flip(wo), flip(wi))
+        }
+    }
+
+    #[inline]
+    fn evaluate_sampled(&self, wo: Vector3f, u: Point2f) -> BsdfSample {
+        if wo.z > 0. as Float {
+            self.front.evaluate_sampled(wo, u)
+        } else {
+            let mut ret = self.back.evaluate_sampled(flip(wo), u);
+            ret.wi = flip(ret.wi);
+            ret
+        }
+    }
+
+    #[inline]
+    fn evaluate_importance(&self, wo: Vector3f, wi: Vector3f) -> RGBSpectrumf {
+        if wo.z * wi.z <= 0. as Float { return RGBSpectrumf::black(); }
+        if wo.z > 0. as Float {
+            self.front.evaluate_importance(wo, wi)
+        } else {
+            self.back.evaluate_importance(flip(wo), flip(wi))
+        }
+    }
+
+    #[inline]
+    fn evaluate_importance_sampled(&self, wo: Vector3f, u: Point2f) -> BsdfSample {
+        if wo.z > 0. as Float {
+            self.front.evaluate_importance_sampled(wo, u)
+        } else {
+            let mut ret = self.back.evaluate_importance_sampled(flip(wo), u);
+            ret.wi = flip(ret.wi);
+            ret
+        }
+    }
+
+    #[inline]
+    fn pdf(&self, wo: Vector3f, wi: Vector3f) -> Float {
+        if wo.z * wi.z <= 0. as Float { return 0. as Float; }
+        if wo.z > 0. as Float {
+            self.front.pdf(wo, wi)
+        } else {
+            self.back.pdf(flip(wo), flip(wi))
+        }
+    }
+
+    #[inline]
+    fn rho_hd(&self, wo: Vector3f, samples: &[Point2f]) -> RGBSpectrumf {
+        if wo.z > 0. as Float {
+            self.front.rho_hd(wo, samples)
+        } else {
+            self.back.rho_hd(flip(wo), samples)
+        }
+    }
+
+    /// averaged over both sides
+    #[inline]
+    fn rho_hh(&self, samples0: &[Point2f], samples1: &[Point2f]) -> RGBSpectrumf {
+        (self.front.rho_hh(samples0, samples1) + self.back.rho_hh(samples0, samples1)) * 0.5 as Float
+    }
+}
@@ -9,7 +9,11 @@
 //! Bounding volume hierarchy
 
 use super::*;
+use super::cost::CostModel;
 use std::mem;
+use std::ptr;
+use std::io::{self, Write, BufWriter};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use copy_arena::{Arena, Allocator};
 
 #[derive(Copy, Clone)]
@@ -21,14 +25,14 @@ struct ComponentInfo {
 }
 
 impl ComponentInfo {
-    fn new(components: &[ComponentPointer]) -> Vec<ComponentInfo> {
+    fn new(components: &[ComponentPointer], costs: &CostModel) -> Vec<ComponentInfo> {
         let mut ret = Vec::with_capacity(components.len());
         for (idx, c) in components.iter().enumerate() {
             let bound = c.bbox_parent();
             let centroid = (bound.pmin + bound.pmax.to_vec())/2.0 as Float;
             ret.push(ComponentInfo{
                 bound, centroid, idx,
-                cost: c.intersection_cost(),
+                cost: c.intersection_cost(costs),
             });
         }
         ret
@@ -54,21 +58,147 @@ pub struct BVH {
 }
 
 impl BVH {
+    /// distance to the nearest hit of `ray` reported by `hit`, the index
+    /// of the component hit, and the ray it was tested with as `hit` left
+    /// it. Like the ray a component reports its hit with, it carries on
+    /// to the components tested after.
+    fn nearest<'a, F>(&'a self, ray: &RawRay, mut hit: F) -> Option<(Float, usize, RawRay)>
+        where F: FnMut(&'a ComponentPointer, &mut RawRay) -> Option<Float>
+    {
+        let mut stack = vec![0];
+        let mut ret = None;
+        // (origin, inv_dir, dir_is_neg, max_extend, min_extend)
+        let mut ray_cache = BBox3f::construct_ray_cache(ray);
+        let mut iray = ray.clone();
+        while let Some(idx) = stack.pop() {
+            assert!(idx<self.nodes.len());
+            let node = unsafe {self.nodes.get_unchecked(idx)};
+            if node.bound.intersect_ray_cached(&ray_cache).is_none() { continue; }
+            if node.len > 0 {
+                for i in node.offset..node.offset+node.len {
+                    let mut candidate = iray.clone();
+                    candidate.set_max_extend(ray_cache.3);
+                    if let Some(t) = hit(&self.components[i], &mut candidate) {
+                        if t < ray_cache.3 {
+                            ray_cache.3 = t;
+                            iray = candidate.clone();
+                            ret = Some((t, i, candidate));
+                        }
+                    }
+                }
+            } else {
+                assert!(idx+node.offset < self.nodes.len());
+                if ray_cache.2[node.split_axis] {
+                    stack.push(idx+1);
+                    stack.push(idx+node.offset);
+                } else {
+                    stack.push(idx+node.offset);
+                    stack.push(idx+1);
+                }
+            }
+        }
+        ret
+    }
+
+    /// index of the first component whose bound, expanded by `epsilon`,
+    /// contains `p` and which `accept` accepts
+    pub fn locate<F>(&self, p: Point3f, epsilon: Float, mut accept: F) -> Option<usize>
+        where F: FnMut(&ComponentPointer) -> bool
+    {
+        let mut stack = vec![0];
+        while let Some(idx) = stack.pop() {
+            let node = match self.nodes.get(idx) {
+                Some(node) => node,
+                None => continue,
+            };
+            if !node.bound.expand_by(epsilon).contain(p) { continue; }
+            if node.len > 0 {
+                for i in node.offset..node.offset+node.len {
+                    let component = &self.components[i];
+                    if component.bbox_parent().expand_by(epsilon).contain(p) && accept(component) {
+                        return Some(i);
+                    }
+                }
+            } else {
+                stack.push(idx+node.offset);
+                stack.push(idx+1);
+            }
+        }
+        None
+    }
+
     /// construction from a `Compoable` slice, with `strategy`
+    /// under the default `CostModel`
+    #[inline]
     pub fn new(
         components: &[ComponentPointer], 
         strategy: BVHStrategy
+    ) -> BVH {
+        BVH::with_costs(components, strategy, &CostModel::default())
+    }
+
+    /// construction from a `Compoable` slice, with `strategy`.
+    /// Surface area heuristics weigh components by `costs`
+    #[inline]
+    pub fn with_costs(
+        components: &[ComponentPointer],
+        strategy: BVHStrategy,
+        costs: &CostModel
+    ) -> BVH {
+        BVH::with_counter(components, strategy, costs, &AtomicUsize::new(0))
+    }
+
+    /// construction as `with_costs` does, adding to `counter` the
+    /// components placed in leaves as the build goes, so that other
+    /// threads can poll it against `components.len()`
+    #[inline]
+    pub fn with_counter(
+        components: &[ComponentPointer],
+        strategy: BVHStrategy,
+        costs: &CostModel,
+        counter: &AtomicUsize
+    ) -> BVH {
+        BVH::build(components, strategy, costs, counter, &mut progress::ignore_progress)
+    }
+
+    /// construction as `with_costs` does, reporting to `progress`
+    /// the components placed in leaves out of `components.len()`
+    #[inline]
+    pub fn with_progress(
+        components: &[ComponentPointer],
+        strategy: BVHStrategy,
+        costs: &CostModel,
+        progress: &mut FnMut(LoadProgress)
+    ) -> BVH {
+        BVH::build(components, strategy, costs, &AtomicUsize::new(0), progress)
+    }
+
+    fn build(
+        components: &[ComponentPointer],
+        strategy: BVHStrategy,
+        costs: &CostModel,
+        counter: &AtomicUsize,
+        progress: &mut FnMut(LoadProgress)
     ) -> BVH {
         let mut arena = Arena::new();
         let mut alloc = arena.allocator();
-        let mut cinfo = ComponentInfo::new(&components);
+        let mut cinfo = ComponentInfo::new(&components, costs);
         let mut ordered = cinfo.clone();
-        let mut node_count = 0;
+        let mut build = BuildProgress{
+            node_count: 0,
+            placed: 0,
+            reported: 0,
+            total: components.len(),
+            counter: counter,
+            progress: progress,
+        };
+        build.report();
         let root = recursive_build(
-            &mut alloc, &mut cinfo, 0, &mut node_count,
-            &mut ordered, strategy
+            &mut alloc, &mut cinfo, 0, &mut build,
+            &mut ordered, strategy, costs
         );
-        let nodes = root.flatten(node_count);
+        build.report();
+        let nodes = root.flatten(build.node_count);
         let mut sorted = Vec::with_capacity(components.len());
         for info in ordered {
             sorted.push(components[info.idx].clone());
@@ -78,9 +208,67 @@ impl BVH {
         }
     }
 
+    /// number of nodes in the hierarchy
+    #[inline]
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// expected cost of intersecting a ray hitting the root bounds
+    /// under `costs`, by surface area heuristics over the nodes
+    pub fn sah_cost(&self, costs: &CostModel) -> Float {
+        let root_area = self.nodes[0].bound.surface_area();
+        let mut ret = 0. as Float;
+        for node in &self.nodes {
+            let area = if root_area > 0. as Float {
+                node.bound.surface_area() / root_area
+            } else {
+                1. as Float
+            };
+            ret += area * if node.len > 0 {
+                self.components[node.offset..node.offset+node.len].iter()
+                    .map(|c| c.intersection_cost(costs)).sum()
+            } else {
+                costs.traversal
+            };
+        }
+        ret
+    }
+
+    /// check that the nodes bound their children, and that the leaves
+    /// cover each component exactly once. Returns the `sah_cost` under
+    /// `costs` if so.
+    pub fn validate(&self, costs: &CostModel) -> Option<Float> {
+        let encloses = |outer: &BBox3f, inner: &BBox3f| outer.contain(inner.pmin) && outer.contain(inner.pmax);
+        let mut covered = vec![false; self.components.len()];
+        let mut visited = 0;
+        let mut stack = vec![0];
+        while let Some(idx) = stack.pop() {
+            let node = self.nodes.get(idx)?;
+            visited += 1;
+            if node.len > 0 {
+                if node.offset + node.len > self.components.len() { return None; }
+                for i in node.offset..node.offset+node.len {
+                    if covered[i] || !encloses(&node.bound, &self.components[i].bbox_parent()) {
+                        return None;
+                    }
+                    covered[i] = true;
+                }
+            } else {
+                for &child in &[idx + 1, idx + node.offset] {
+                    if !encloses(&node.bound, &self.nodes.get(child)?.bound) { return None; }
+                    stack.push(child);
+                }
+            }
+            if visited > self.nodes.len() { return None; }
+        }
+        if visited != self.nodes.len() || covered.iter().any(|&c| !c) { return None; }
+        Some(self.sah_cost(costs))
+    }
+
     /// constructs from an .obj file
     #[inline]
-    pub fn load_obj<P>(path: &P, transform: Matrix4f) -> Result<BVH, tobj::LoadError>
+    pub fn load_obj<P>(path: &P, transform: Matrix4f) -> Result<BVH, ObjError>
         where P: AsRef<Path> + ?Sized
     {
         load_obj(path.as_ref(), transform).map(|shapes| {
@@ -89,29 +277,308 @@ impl BVH {
     }
 }
 
+/// leading bytes of a saved hierarchy
+const MAGIC: &[u8; 4] = b"ABVH";
+/// version of the saved layout
+const VERSION: u32 = 1;
+/// `split_axis` marking leaf nodes
+const LEAF_AXIS: usize = 4;
+
+/// Saving to and loading from disk.
+///
+/// The layout is little-endian: `MAGIC`, `VERSION`, then the content hash
+/// and triangle count of each mesh, the flattened nodes, and each component
+/// as its mesh's position in the list and its index in the mesh.
+impl BVH {
+    /// save the hierarchy to `path`. All components should be
+    /// triangles of `meshes`, otherwise an `InvalidInput` error is returned
+    pub fn save<P: AsRef<Path> + ?Sized>(&self, path: &P, meshes: &[Arc<TriangleMesh>]) -> io::Result<()> {
+        let layout = self.layout(meshes).ok_or_else(|| io::Error::new(
+            io::ErrorKind::InvalidInput, "only hierarchies over triangles of the given meshes can be saved"
+        ))?;
+        let mut w = BufWriter::new(File::create(path)?);
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        w.write_all(&(meshes.len() as u64).to_le_bytes())?;
+        for mesh in meshes {
+            w.write_all(&mesh.content_hash().to_le_bytes())?;
+            w.write_all(&(mesh.triangle_count() as u64).to_le_bytes())?;
+        }
+        w.write_all(&(layout.nodes.len() as u64).to_le_bytes())?;
+        for node in &layout.nodes {
+            for i in 0..3 {
+                w.write_all(&node.bound.pmin[i].to_bits().to_le_bytes())?;
+            }
+            for i in 0..3 {
+                w.write_all(&node.bound.pmax[i].to_bits().to_le_bytes())?;
+            }
+            w.write_all(&(node.len as u64).to_le_bytes())?;
+            w.write_all(&(node.offset as u64).to_le_bytes())?;
+            w.write_all(&[node.split_axis as u8])?;
+        }
+        w.write_all(&(layout.indices.len() as u64).to_le_bytes())?;
+        for &(m, t) in &layout.indices {
+            w.write_all(&(m as u64).to_le_bytes())?;
+            w.write_all(&(t as u64).to_le_bytes())?;
+        }
+        w.flush()
+    }
+
+    /// load a hierarchy saved by `save` over `meshes`. Returns `None` if
+    /// the file is unreadable or malformed, or if `meshes` differ in number
+    /// or geometry from the ones saved with, in which case it is stale.
+    ///
+    /// Components are rebuilt as triangles sharing `meshes`,
+    /// so nothing is allocated per triangle.
+    pub fn load<P: AsRef<Path> + ?Sized>(path: &P, meshes: &[Arc<TriangleMesh>]) -> Option<BVH> {
+        let mut buf = Vec::new();
+        File::open(path).and_then(|mut f| f.read_to_end(&mut buf)).ok()?;
+        let mut r = Reader(&buf);
+        if r.take(4)? != MAGIC || r.u32()? != VERSION { return None; }
+        if r.len()? != meshes.len() { return None; }
+        for mesh in meshes {
+            if r.u64()? != mesh.content_hash() || r.len()? != mesh.triangle_count() {
+                return None;
+            }
+        }
+        let node_count = r.len()?;
+        let mut nodes = Vec::with_capacity(node_count.min(buf.len()));
+        for _ in 0..node_count {
+            let pmin = Point3f::new(r.float()?, r.float()?, r.float()?);
+            let pmax = Point3f::new(r.float()?, r.float()?, r.float()?);
+            nodes.push(LinearNode{
+                bound: BBox3f::new(pmin, pmax),
+                len: r.len()?,
+                offset: r.len()?,
+                split_axis: r.take(1)?[0] as usize,
+            });
+        }
+        let component_count = r.len()?;
+        let mut indices = Vec::with_capacity(component_count.min(buf.len()));
+        for _ in 0..component_count {
+            indices.push((r.len()?, r.len()?));
+        }
+        if !r.0.is_empty() { return None; }
+        BVHLayout{nodes, indices}.instantiate(meshes)
+    }
+
+    /// load the hierarchy over triangles of `meshes` from `path`, or
+    /// build it with `strategy` and save it there if that fails
+    pub fn load_or_build<P: AsRef<Path> + ?Sized>(
+        path: &P, meshes: &[Arc<TriangleMesh>], strategy: BVHStrategy
+    ) -> BVH {
+        let path = path.as_ref();
+        if let Some(bvh) = BVH::load(path, meshes) {
+            return bvh;
+        }
+        let mut components = Vec::new();
+        for mesh in meshes {
+            components.extend(TriangleMesh::instances(mesh).map(ComponentPointer::from));
+        }
+        let bvh = BVH::new(&components, strategy);
+        if let Err(e) = bvh.save(path, meshes) {
+            warn!("failed caching bvh to {}: {}", path.display(), e);
+        }
+        bvh
+    }
+}
+
+/// Node layout of a hierarchy over triangles of some meshes, kept apart
+/// from the meshes themselves, so that it can be laid over other meshes
+/// of the same geometry, e.g. ones with other materials
+#[derive(Clone)]
+pub struct BVHLayout {
+    nodes: Vec<LinearNode>,
+    // mesh position and triangle index of each component
+    indices: Vec<(usize, usize)>,
+}
+
+impl BVH {
+    /// the layout of this hierarchy. All components should be
+    /// triangles of `meshes`, otherwise `None` is returned
+    pub fn layout(&self, meshes: &[Arc<TriangleMesh>]) -> Option<BVHLayout> {
+        let mut indices = Vec::with_capacity(self.components.len());
+        for component in &self.components {
+            if let ComponentPointer::Triangle(ref t) = *component {
+                let m = meshes.iter().position(|m| ptr::eq(&**m, &**t.mesh()))?;
+                indices.push((m, t.index()));
+            } else {
+                return None;
+            }
+        }
+        Some(BVHLayout{
+            nodes: self.nodes.clone(),
+            indices,
+        })
+    }
+}
+
+impl BVHLayout {
+    /// rebuild the hierarchy over triangles of `meshes`, which are assumed
+    /// to be of the same geometry as the ones the layout was taken over.
+    /// Returns `None` if the layout is malformed for `meshes`.
+    pub fn instantiate(&self, meshes: &[Arc<TriangleMesh>]) -> Option<BVH> {
+        let mut components = Vec::with_capacity(self.indices.len());
+        for &(m, t) in &self.indices {
+            components.push(TriangleMesh::instance(meshes.get(m)?, t)?.into());
+        }
+        let nodes = &self.nodes;
+        if nodes.is_empty() { return None; }
+        for (idx, node) in nodes.iter().enumerate() {
+            let valid = if node.len > 0 {
+                node.split_axis == LEAF_AXIS && node.offset + node.len <= components.len()
+            } else {
+                node.split_axis < 3 && node.offset > 1 && idx + node.offset < nodes.len()
+            };
+            if !valid { return None; }
+        }
+        Some(BVH{
+            components, nodes: nodes.clone()
+        })
+    }
+
+    /// number of components laid out
+    #[inline]
+    pub fn component_count(&self) -> usize {
+        self.indices.len()
+    }
+}
+
+// little-endian cursor over a saved hierarchy
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    #[inline]
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n { return None; }
+        let (ret, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(ret)
+    }
+
+    #[inline]
+    fn u32(&mut self) -> Option<u32> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Some(u32::from_le_bytes(bytes))
+    }
+
+    #[inline]
+    fn u64(&mut self) -> Option<u64> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Some(u64::from_le_bytes(bytes))
+    }
+
+    #[inline]
+    fn len(&mut self) -> Option<usize> {
+        self.u64().map(|v| v as usize)
+    }
+
+    #[inline]
+    fn float(&mut self) -> Option<Float> {
+        self.u32().map(Float::from_bits)
+    }
+}
+
 impl Composable for BVH {
     fn bbox_parent(&self) -> BBox3f {
         self.nodes[0].bound
     }
 
+    // Triangles only report their distance, the winning one computing its
+    // interaction once at the end, re-intersected by the ray it was first
+    // tested with so that it's found as is. Nested components hand theirs
+    // up, as re-intersecting them would mean traversing them again. `ray`
+    // ends up as the winner left it, as if each had been intersected in turn
     fn intersect_ray(&self, ray: &mut RawRay) -> Option<SurfaceInteraction> {
-        let mut stack = vec![0];
-        let mut final_ret = None;
-        // (origin, inv_dir, dir_is_neg, max_extend)
-        let mut ray_cache = BBox3f::construct_ray_cache(ray);
+        let mut nested = None;
+        let (_, idx, hit_ray) = self.nearest(ray, |component, iray| match *component {
+            ComponentPointer::Triangle(ref triangle) => Composable::intersect_t(triangle, iray),
+            ComponentPointer::Arc(ref arc) => {
+                let tmax = iray.max_extend();
+                let ret = arc.intersect_ray(iray);
+                if ret.is_some() && iray.max_extend() < tmax {
+                    nested = ret;
+                    Some(iray.max_extend())
+                } else {
+                    None
+                }
+            },
+        })?;
+        *ray = hit_ray;
+        match self.components[idx] {
+            ComponentPointer::Triangle(ref triangle) => {
+                let ret = Composable::intersect_ray(triangle, ray);
+                debug_assert!(ret.is_some(), "re-intersection missed");
+                ret
+            },
+            ComponentPointer::Arc(_) => nested,
+        }
+    }
+
+    #[inline]
+    fn intersect_t(&self, ray: &RawRay) -> Option<Float> {
+        self.nearest(ray, |component, iray| component.intersect_t(iray)).map(|hit| hit.0)
+    }
+
+    #[inline]
+    fn can_intersect(&self, ray: &RawRay) -> bool {
+        let mut stack = Vec::new();
+        self.any_hit(ray, &mut stack)
+    }
+
+    fn occluded_batch(&self, rays: &mut [RawRay], results: &mut [bool]) {
+        assert_eq!(rays.len(), results.len(), "mismatched batch lengths");
+        // visit rays in the order of their origins' morton codes, such
+        // that neighboring rays tend to walk the same nodes
+        let bound = self.bbox_parent();
+        let mut order: Vec<(u32, usize)> = rays.iter().enumerate()
+            .map(|(i, ray)| (morton_code(ray.origin(), &bound), i))
+            .collect();
+        order.sort_unstable();
+        let mut stack = Vec::with_capacity(64);
+        for (_, i) in order {
+            results[i] = self.any_hit(&rays[i], &mut stack);
+        }
+    }
+
+    #[inline]
+    fn intersection_cost(&self, costs: &CostModel) -> Float {
+        self.sah_cost(costs)
+    }
+
+    fn component_count(&self) -> usize {
+        self.components.iter().map(|c| c.component_count()).sum()
+    }
+
+    fn visit_primitives(&self, f: &mut FnMut(&Primitive)) {
+        for component in &self.components {
+            component.visit_primitives(f);
+        }
+    }
+
+    fn memory_estimate(&self) -> usize {
+        mem::size_of::<BVH>() + self.nodes.capacity() * mem::size_of::<LinearNode>()
+            + self.components.capacity().saturating_sub(self.components.len()) * mem::size_of::<ComponentPointer>()
+            + self.components.iter().map(|c| c.memory_estimate()).sum::<usize>()
+    }
+}
+
+impl BVH {
+    // test if `ray` hits anything, stopping at the first hit found,
+    // with `stack` reused across calls
+    fn any_hit(&self, ray: &RawRay, stack: &mut Vec<usize>) -> bool {
+        stack.clear();
+        stack.push(0);
+        let ray_cache = BBox3f::construct_ray_cache(ray);
         while let Some(idx) = stack.pop() {
             assert!(idx<self.nodes.len());
             let node = unsafe {self.nodes.get_unchecked(idx)};
             if node.bound.intersect_ray_cached(&ray_cache).is_none() { continue; }
             if node.len > 0 {
                 for element in &self.components[node.offset..node.offset+node.len] {
-                    let mut iray = ray.clone();
-                    let ret = element.intersect_ray(&mut iray);
-                    if ray.max_extend() > iray.max_extend() {
-                        *ray = iray;
-                        ray_cache.3 = ray.max_extend();
-                        final_ret = ret;
-                    }
+                    if element.can_intersect(ray) { return true; }
                 }
             } else {
                 assert!(idx+node.offset < self.nodes.len());
@@ -124,13 +591,30 @@ impl Composable for BVH {
                 }
             }
         }
-        final_ret
+        false
     }
+}
 
-    fn intersection_cost(&self) -> Float {
-        // FIXME: this is silly
-        (self.nodes.len() as Float).log2()
-    }
+/// 30-bit morton code of `p`'s position within `bound`,
+/// clamped onto it, with 10 bits per axis
+fn morton_code(p: Point3f, bound: &BBox3f) -> u32 {
+    let diagonal = bound.diagonal();
+    let quantize = |x: Float, min: Float, extent: Float| {
+        let t = if extent > 0. as Float { (x - min) / extent } else { 0. as Float };
+        // NaNs fall onto zero
+        (t.max(0. as Float).min(1. as Float) * 1023. as Float) as u32
+    };
+    // spread the lower 10 bits of `x` to every third bit
+    let spread = |x: u32| {
+        let mut x = x & 0x3ff;
+        x = (x | (x << 16)) & 0x30000ff;
+        x = (x | (x << 8)) & 0x300f00f;
+        x = (x | (x << 4)) & 0x30c30c3;
+        (x | (x << 2)) & 0x9249249
+    };
+    (spread(quantize(p.z, bound.pmin.z, diagonal.z)) << 2)
+        | (spread(quantize(p.y, bound.pmin.y, diagonal.y)) << 1)
+        | spread(quantize(p.x, bound.pmin.x, diagonal.x))
 }
 
 #[derive(Copy, Clone)]
@@ -234,7 +718,7 @@ impl<'a> BuildNode<'a> {
                     bound: node.bound,
                     len: node.len,
                     offset: node.offset,
-                    split_axis: 4,
+                    split_axis: LEAF_AXIS,
                 });
             }
         }
@@ -243,16 +727,45 @@ impl<'a> BuildNode<'a> {
     }
 }
 
+// bookkeeping of a build, threaded through `recursive_build`
+struct BuildProgress<'p> {
+    node_count: usize,
+    // components placed in leaves
+    placed: usize,
+    reported: usize,
+    total: usize,
+    counter: &'p AtomicUsize,
+    progress: &'p mut FnMut(LoadProgress),
+}
+
+impl<'p> BuildProgress<'p> {
+    #[inline]
+    fn place(&mut self, n: usize) {
+        self.placed += n;
+        self.counter.fetch_add(n, Ordering::Relaxed);
+        if self.placed - self.reported >= progress::PROGRESS_STRIDE {
+            self.report();
+        }
+    }
+
+    fn report(&mut self) {
+        self.reported = self.placed;
+        (self.progress)(LoadProgress::new(LoadPhase::BVHBuild, self.placed, self.total, 0));
+    }
+}
+
 fn recursive_build<'a>(
     alloc: &mut Allocator<'a>, components: &mut [ComponentInfo], offset: usize,
-    node_count: &mut usize, ordered: &mut [ComponentInfo], strategy: BVHStrategy
+    build: &mut BuildProgress, ordered: &mut [ComponentInfo], strategy: BVHStrategy,
+    costs: &CostModel
 ) -> &'a mut BuildNode<'a> {
     assert!(components.len()==ordered.len());
     assert!(components.len()!=0);
-    *node_count += 1;
+    build.node_count += 1;
     let mut ret: &'a mut BuildNode<'a> = alloc.alloc_default();
     if components.len() == 1 { unsafe {
         ret.to_leaf(offset, 1, components.get_unchecked(0).bound);
+        build.place(1);
         *ordered.get_unchecked_mut(0) = *components.get_unchecked(0);
     }} else {
         let (bound, centroid_bound) = {
@@ -272,31 +785,42 @@ fn recursive_build<'a>(
         let split_axis = centroid_bound.max_extent();
         if centroid_bound.pmin[split_axis] == centroid_bound.pmax[split_axis] {
             ret.to_leaf(offset, components.len(), bound);
+            build.place(components.len());
         }
         else {
             match strategy {
                 BVHStrategy::SAH => {
                     if components.len() <= 4 {
-                        ret = recursive_build(
-                            alloc, components, offset, 
-                            node_count, ordered, BVHStrategy::MidPoint
-                        );
+                        let mid = (
+                            centroid_bound.pmax[split_axis]
+                             + centroid_bound.pmin[split_axis]
+                        )/2.0 as Float;
+                        let leaf_cost: Float = components.iter().map(|c| c.cost).sum();
+                        if split_cost(components, split_axis, mid, bound, costs) < leaf_cost {
+                            ret = recursive_build(
+                                alloc, components, offset, 
+                                build, ordered, BVHStrategy::MidPoint, costs
+                            );
+                        } else {
+                            ret.to_leaf(offset, components.len(), bound);
+                            build.place(components.len());
+                        }
                     } else {
                         let inv_area = 1.0 as Float / bound.surface_area();
                         let midpoint = sah_midpoint(
-                            components, split_axis, centroid_bound, inv_area
+                            components, split_axis, centroid_bound, inv_area, costs
                         );
                         sort_mid(
-                            alloc, components, offset, node_count, ordered,
-                            strategy, midpoint[split_axis], split_axis, &mut ret, bound
+                            alloc, components, offset, build, ordered,
+                            strategy, costs, midpoint[split_axis], split_axis, &mut ret, bound
                         );
                     }
                 },
                 BVHStrategy::MiddleCount => {
                     let mid_idx = components.len() >> 1;
                     handle_tails(
-                        alloc, components, offset, node_count, ordered,
-                        strategy, mid_idx, split_axis, ret, bound
+                        alloc, components, offset, build, ordered,
+                        strategy, costs, mid_idx, split_axis, ret, bound
                     );
                 },
                 BVHStrategy::MidPoint => {
@@ -305,8 +829,8 @@ fn recursive_build<'a>(
                          + centroid_bound.pmin[split_axis]
                     )/2.0 as Float;
                     sort_mid(
-                        alloc, components, offset, node_count, ordered,
-                        strategy, mid, split_axis, &mut ret, bound
+                        alloc, components, offset, build, ordered,
+                        strategy, costs, mid, split_axis, &mut ret, bound
                     );
                 }
             }
@@ -374,8 +898,29 @@ impl Bucket {
     }
 }
 
+/// cost of splitting `components` within `bound` at `mid` along `axis`,
+/// taking both sides as leaves. Both sides should be non-empty.
+fn split_cost(
+    components: &[ComponentInfo], axis: usize, mid: Float, bound: BBox3f,
+    costs: &CostModel
+) -> Float {
+    let mut left = Bucket::default();
+    let mut right = Bucket::default();
+    for c in components {
+        let side = if c.centroid[axis] < mid { &mut left } else { &mut right };
+        *side = side.union(&Bucket{
+            count: 1, cost: c.cost, bound: c.bound, initialized: true,
+        });
+    }
+    costs.traversal + (
+        left.cost * left.bound.surface_area()
+        + right.cost * right.bound.surface_area()
+    ) / bound.surface_area()
+}
+
 fn sah_midpoint(
-    components: &[ComponentInfo], split_axis: usize, cb: BBox3f, inv_area: Float
+    components: &[ComponentInfo], split_axis: usize, cb: BBox3f, inv_area: Float,
+    costs: &CostModel
 ) -> Point3f {
     const BUCKETS: usize = 32;
     let mut buckets = [Bucket::default(); BUCKETS];
@@ -400,7 +945,7 @@ fn sah_midpoint(
     for i in 0..BUCKETS-1 {
         debug_assert!(inv_area<= 1.0 as Float / accum[i].bound.surface_area());
         debug_assert!(inv_area<= 1.0 as Float / accum_rev[i+1].bound.surface_area());
-        let cost = 0.125 as Float + (
+        let cost = costs.traversal + (
             accum[i].cost * accum[i].bound.surface_area()
             + accum_rev[i+1].cost * accum_rev[i+1].bound.surface_area()
         ) * inv_area;
@@ -416,8 +961,8 @@ fn sah_midpoint(
 
 fn sort_mid<'a>(
     alloc: &mut Allocator<'a>, components: &mut [ComponentInfo], offset: usize,
-    node_count: &mut usize, ordered: &mut [ComponentInfo], strategy: BVHStrategy,
-    mid: Float, split_axis: usize, ret: &mut BuildNode<'a>, bound: BBox3f
+    build: &mut BuildProgress, ordered: &mut [ComponentInfo], strategy: BVHStrategy,
+    costs: &CostModel, mid: Float, split_axis: usize, ret: &mut BuildNode<'a>, bound: BBox3f
 ) {
     assert!(components.len()==ordered.len());
     let mut j = ordered.len();
@@ -436,27 +981,28 @@ fn sort_mid<'a>(
     }
     components.copy_from_slice(ordered);
     handle_tails(
-        alloc, components, offset, node_count, ordered,
-        strategy, i, split_axis, ret, bound
+        alloc, components, offset, build, ordered,
+        strategy, costs, i, split_axis, ret, bound
     );
 }
 
 #[inline]
 fn handle_tails<'a>(
     alloc: &mut Allocator<'a>, components: &mut [ComponentInfo], offset: usize,
-    node_count: &mut usize, ordered: &mut [ComponentInfo], strategy: BVHStrategy,
-    i: usize, split_axis: usize, ret: &mut BuildNode<'a>, bound: BBox3f
+    build: &mut BuildProgress, ordered: &mut [ComponentInfo], strategy: BVHStrategy,
+    costs: &CostModel, i: usize, split_axis: usize, ret: &mut BuildNode<'a>, bound: BBox3f
 ) {
     if i == 0 || i == components.len() {
         ret.to_leaf(offset, components.len(), bound);
+        build.place(components.len());
     } else {
         let child0 = recursive_build(
             alloc, &mut components[0..i], offset,
-            node_count, &mut ordered[0..i], strategy
+            build, &mut ordered[0..i], strategy, costs
         );
         let child1 = recursive_build(
             alloc, &mut components[i..], offset+i,
-            node_count, &mut ordered[i..], strategy
+            build, &mut ordered[i..], strategy, costs
         );
         ret.to_interior(
             child0, child1, split_axis
@@ -0,0 +1,298 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reusing loaded textures, meshes and built hierarchies across
+//! scenes constructed in one process, e.g. many variants of a product
+//! differing only in materials.
+
+use super::*;
+use super::bvh::{BVH, BVHLayout, BVHStrategy};
+use image::Luma;
+use std::collections::HashSet;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts of the work done and saved by a `SceneCache`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// mipmaps loaded from disk
+    pub mipmap_loads: usize,
+    /// `.obj` files loaded from disk
+    pub mesh_loads: usize,
+    /// `.obj` files taken from the cache
+    pub mesh_hits: usize,
+    /// hierarchies built
+    pub bvh_builds: usize,
+    /// hierarchies laid out from the cache
+    pub bvh_hits: usize,
+    /// texture paths failing to load, substituted or left out
+    pub texture_substitutions: usize,
+}
+
+/// Long-lived tables of loaded textures, meshes and hierarchy layouts,
+/// shared by `load_obj_cached` and `build_bvh_cached`.
+///
+/// Everything is kept alive until evicted. Hierarchies are keyed by
+/// the geometry of the meshes they are built over, so that they are
+/// reused by meshes of other materials. The cache can be shared
+/// across threads, though loading `.obj` files through it is serialized.
+#[derive(Default)]
+pub struct SceneCache {
+    textures: Mutex<Textures>,
+    meshes: Mutex<HashMap<u64, CachedObj>>,
+    bvhs: Mutex<HashMap<u64, BVHLayout>>,
+    mipmap_loads: AtomicUsize,
+    mesh_loads: AtomicUsize,
+    mesh_hits: AtomicUsize,
+    bvh_builds: AtomicUsize,
+    bvh_hits: AtomicUsize,
+    texture_substitutions: AtomicUsize,
+}
+
+#[derive(Default)]
+struct Textures {
+    rgb: RGBMipMapHashTable<Float>,
+    luma: LumaMipMapHashTable<Float>,
+    // strong references keeping the mipmaps alive until evicted
+    rgb_pinned: Vec<Arc<MipMap<Float, RGBSpectrumf>>>,
+    luma_pinned: Vec<Arc<MipMap<Float, Luma<Float>>>>,
+    // paths failing to load, warned about once
+    fallback: TextureFallback,
+}
+
+impl Textures {
+    fn alive(&self) -> HashSet<ImageInfo> {
+        self.rgb.iter().filter(|&(_, w)| w.upgrade().is_some()).map(|(k, _)| k.clone())
+            .chain(self.luma.iter().filter(|&(_, w)| w.upgrade().is_some()).map(|(k, _)| k.clone()))
+            .collect()
+    }
+
+    fn pin(&mut self) {
+        self.rgb_pinned = self.rgb.values().filter_map(|w| w.upgrade()).collect();
+        self.luma_pinned = self.luma.values().filter_map(|w| w.upgrade()).collect();
+    }
+}
+
+struct CachedObj {
+    path: PathBuf,
+    transform: Matrix4f,
+    options: ObjOptions,
+    shapes: Vec<ComponentPointer>,
+    lights: Vec<Arc<MeshLight>>,
+}
+
+impl SceneCache {
+    /// an empty cache
+    #[inline]
+    pub fn new() -> SceneCache {
+        Default::default()
+    }
+
+    /// counts of the work done and saved so far
+    pub fn stats(&self) -> CacheStats {
+        CacheStats{
+            mipmap_loads: self.mipmap_loads.load(Ordering::Relaxed),
+            mesh_loads: self.mesh_loads.load(Ordering::Relaxed),
+            mesh_hits: self.mesh_hits.load(Ordering::Relaxed),
+            bvh_builds: self.bvh_builds.load(Ordering::Relaxed),
+            bvh_hits: self.bvh_hits.load(Ordering::Relaxed),
+            texture_substitutions: self.texture_substitutions.load(Ordering::Relaxed),
+        }
+    }
+
+    /// zero the counts of `stats`
+    pub fn reset_stats(&self) {
+        for counter in &[&self.mipmap_loads, &self.mesh_loads, &self.mesh_hits, &self.bvh_builds, &self.bvh_hits, &self.texture_substitutions] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// number of mipmaps kept alive
+    pub fn mipmap_count(&self) -> usize {
+        let textures = self.textures.lock().unwrap();
+        textures.rgb_pinned.len() + textures.luma_pinned.len()
+    }
+
+    /// number of `.obj` files kept
+    pub fn mesh_count(&self) -> usize {
+        self.meshes.lock().unwrap().len()
+    }
+
+    /// number of hierarchy layouts kept
+    pub fn bvh_count(&self) -> usize {
+        self.bvhs.lock().unwrap().len()
+    }
+
+    /// stop keeping mipmaps alive. Ones still used by
+    /// textures elsewhere are shared until dropped. Paths failing
+    /// to load are tried and warned about again
+    pub fn evict_textures(&self) {
+        let mut textures = self.textures.lock().unwrap();
+        textures.fallback = TextureFallback::default();
+        textures.rgb_pinned.clear();
+        textures.luma_pinned.clear();
+        textures.rgb.retain(|_, w| w.upgrade().is_some());
+        textures.luma.retain(|_, w| w.upgrade().is_some());
+    }
+
+    /// drop the kept `.obj` files
+    pub fn evict_meshes(&self) {
+        self.meshes.lock().unwrap().clear();
+    }
+
+    /// drop the kept hierarchy layouts
+    pub fn evict_bvhs(&self) {
+        self.bvhs.lock().unwrap().clear();
+    }
+
+    /// evict everything
+    pub fn clear(&self) {
+        self.evict_textures();
+        self.evict_meshes();
+        self.evict_bvhs();
+    }
+
+    /// an rgb image texture as `RGBImageTexture::new_as_arc` constructs,
+    /// sharing the mipmaps loaded through the cache
+    pub fn rgb_image<M>(
+        &self, info: ImageInfo, mapping: M
+    ) -> Result<Arc<Texture<Texel=RGBSpectrumf>>, TextureError>
+        where M: Mapping2D + Send + Sync + 'static
+    {
+        let mut textures = self.textures.lock().unwrap();
+        let before = textures.alive();
+        let texture = RGBImageTexture::new_as_arc(info, mapping, &mut textures.rgb)?;
+        self.note_loads(&mut textures, &before);
+        Ok(texture)
+    }
+
+    /// a gray image texture as `LumaImageTexture::new_as_arc` constructs,
+    /// sharing the mipmaps loaded through the cache
+    pub fn gray_image<M>(
+        &self, info: ImageInfo, mapping: M
+    ) -> Result<Arc<Texture<Texel=Float>>, TextureError>
+        where M: Mapping2D + Send + Sync + 'static
+    {
+        let mut textures = self.textures.lock().unwrap();
+        let before = textures.alive();
+        let texture = LumaImageTexture::new_as_arc(info, mapping, &mut textures.luma)?;
+        self.note_loads(&mut textures, &before);
+        Ok(texture)
+    }
+
+    fn note_loads(&self, textures: &mut Textures, before: &HashSet<ImageInfo>) {
+        let loads = textures.alive().difference(before).count();
+        self.mipmap_loads.fetch_add(loads, Ordering::Relaxed);
+        textures.pin();
+    }
+}
+
+/// Load an `.obj` file as `load_obj_with_options` does, through `cache`.
+///
+/// Files loaded before under the same transform and `options` are
+/// taken from the cache as is, sharing their meshes and materials.
+/// Otherwise, textures already loaded are shared, and the ones failing
+/// to load are only warned about the first time.
+pub fn load_obj_cached(
+    cache: &SceneCache, path: &Path, transform: Matrix4f, options: ObjOptions
+) -> Result<(Vec<ComponentPointer>, Vec<Arc<MeshLight>>), ObjError> {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    let columns: &[[Float; 4]; 4] = transform.as_ref();
+    for column in columns {
+        for v in column {
+            v.to_bits().hash(&mut hasher);
+        }
+    }
+    let key = hasher.finish();
+    if let Some(cached) = cache.meshes.lock().unwrap().get(&key) {
+        if cached.path == path && cached.transform == transform && cached.options == options {
+            cache.mesh_hits.fetch_add(1, Ordering::Relaxed);
+            return Ok((cached.shapes.clone(), cached.lights.clone()));
+        }
+    }
+    let (shapes, lights) = {
+        let mut textures = cache.textures.lock().unwrap();
+        let before = textures.alive();
+        let substituted = textures.fallback.substitutions();
+        let loaded = {
+            let textures = &mut *textures;
+            textures.fallback.set_mode(options.fallback);
+            load_obj_with_tables(
+                path, transform, options, &mut progress::ignore_progress,
+                &mut textures.rgb, &mut textures.luma, &mut textures.fallback
+            )
+        };
+        let loads = textures.alive().difference(&before).count();
+        cache.mipmap_loads.fetch_add(loads, Ordering::Relaxed);
+        let substitutions = textures.fallback.substitutions() - substituted;
+        cache.texture_substitutions.fetch_add(substitutions, Ordering::Relaxed);
+        textures.pin();
+        loaded?
+    };
+    cache.mesh_loads.fetch_add(1, Ordering::Relaxed);
+    cache.meshes.lock().unwrap().insert(key, CachedObj{
+        path: path.to_owned(),
+        transform,
+        options,
+        shapes: shapes.clone(),
+        lights: lights.clone(),
+    });
+    Ok((shapes, lights))
+}
+
+/// Build a `BVH` over `components` with `strategy`, through `cache`.
+///
+/// If all components are triangles, the hierarchy is keyed by the
+/// geometry of their meshes, so that it's laid over the triangles
+/// of other meshes of the same geometry instead of being rebuilt.
+/// Hierarchies over other components are always built.
+pub fn build_bvh_cached(
+    cache: &SceneCache, components: &[ComponentPointer], strategy: BVHStrategy
+) -> BVH {
+    let mut meshes: Vec<Arc<TriangleMesh>> = Vec::new();
+    let mut indices = Vec::with_capacity(components.len());
+    for component in components {
+        if let ComponentPointer::Triangle(ref t) = *component {
+            let m = match meshes.iter().position(|m| ptr::eq(&**m, &**t.mesh())) {
+                Some(m) => m,
+                None => {
+                    meshes.push(t.mesh().clone());
+                    meshes.len() - 1
+                }
+            };
+            indices.push((m, t.index()));
+        } else {
+            cache.bvh_builds.fetch_add(1, Ordering::Relaxed);
+            return BVH::new(components, strategy);
+        }
+    }
+    let mut hasher = DefaultHasher::new();
+    (strategy as u64).hash(&mut hasher);
+    for mesh in &meshes {
+        mesh.content_hash().hash(&mut hasher);
+        mesh.triangle_count().hash(&mut hasher);
+    }
+    indices.hash(&mut hasher);
+    let key = hasher.finish();
+    let layout = cache.bvhs.lock().unwrap().get(&key).cloned();
+    if let Some(bvh) = layout.and_then(|layout| layout.instantiate(&meshes)) {
+        cache.bvh_hits.fetch_add(1, Ordering::Relaxed);
+        return bvh;
+    }
+    cache.bvh_builds.fetch_add(1, Ordering::Relaxed);
+    let bvh = BVH::new(components, strategy);
+    if let Some(layout) = bvh.layout(&meshes) {
+        cache.bvhs.lock().unwrap().insert(key, layout);
+    }
+    bvh
+}
@@ -0,0 +1,115 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Relative costs of intersection tests, which `BVH`s are built by
+
+use super::*;
+use super::shape::ShapedPrimitive;
+use super::transformed::TransformedComposable;
+use sample::sample_uniform_sphere;
+use rand::{Rng, SeedableRng, StdRng};
+use std::time::Instant;
+
+/// Costs of intersecting rays against components, relative to
+/// one another. Only their ratios matter.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CostModel {
+    /// testing a ray against the bounds of a node
+    pub traversal: Float,
+    /// intersecting a triangle
+    pub triangle: Float,
+    /// intersecting any other shape
+    pub shape: Float,
+    /// moving a ray into the frame of an instance, and its hit back out
+    pub transform: Float,
+}
+
+impl CostModel {
+    /// the constants used before any calibration
+    pub fn uncalibrated() -> CostModel {
+        CostModel{
+            traversal: 0.125 as Float,
+            triangle: 3. as Float,
+            shape: 1. as Float,
+            transform: 2. as Float,
+        }
+    }
+}
+
+impl Default for CostModel {
+    /// as measured by `calibrate_costs` in release builds
+    fn default() -> CostModel {
+        CostModel{
+            traversal: 0.08 as Float,
+            triangle: 0.5 as Float,
+            shape: 1. as Float,
+            transform: 2. as Float,
+        }
+    }
+}
+
+/// measure the cost of each kind of component by timing `n` synthetic
+/// rays against it, relative to the cost of a sphere. Meant for
+/// development, with results only meaningful in release builds.
+pub fn calibrate_costs(n: usize) -> CostModel {
+    let mut rng = StdRng::from_seed(&[7usize][..]);
+    // rays from around the unit cube towards points within it, about
+    // half of which hit the unit sphere
+    let rays: Vec<RawRay> = (0..n).map(|_| {
+        let origin = Point3f::from_vec(sample_uniform_sphere(Point2f::new(rng.gen(), rng.gen())) * 3. as Float);
+        let target = Point3f::new(rng.gen(), rng.gen(), rng.gen()) * 2. as Float
+            + Vector3f::new(-1. as Float, -1. as Float, -1. as Float);
+        RawRay::from_od(origin, target - origin)
+    }).collect();
+
+    let material: Arc<Material> = Arc::new(MatteMaterial::new(
+        Arc::new(ConstantTexture{value: RGBSpectrumf::grey_scale(0.5 as Float)}),
+        Arc::new(ConstantTexture{value: 0. as Float}),
+        None
+    ));
+    let sphere = Arc::new(ShapedPrimitive::new(Sphere::full(1. as Float), material.clone(), None));
+    let m = Matrix4f::from_translation(Vector3f::new(0.1 as Float, 0. as Float, 0. as Float));
+    let transformed = TransformedComposable::new(
+        sphere.clone(), Arc::new(m), Arc::new(m.invert().unwrap())
+    );
+    let model = tobj::Model::new(
+        tobj::Mesh::new(
+            vec![-1., -1., 0., 1., -1., 0., 0., 1., 0.], Vec::new(), Vec::new(), vec![0, 1, 2], None
+        ),
+        "calibration".to_owned()
+    );
+    let mesh = Arc::new(TriangleMesh::from_model(model, material, None));
+    let triangle = TriangleMesh::instance(&mesh, 0).unwrap();
+
+    let mut hits = 0;
+    let mut time = |test: &mut FnMut(&RawRay) -> bool| {
+        let start = Instant::now();
+        for ray in &rays {
+            if test(ray) { hits += 1; }
+        }
+        let elapsed = start.elapsed();
+        elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9
+    };
+    let intersect = |c: &Composable, ray: &RawRay| c.intersect_ray(&mut ray.clone()).is_some();
+    let t_sphere = time(&mut |ray| intersect(&*sphere, ray));
+    let t_transformed = time(&mut |ray| intersect(&transformed, ray));
+    let t_triangle = time(&mut |ray| intersect(&triangle, ray));
+    let bound = BBox3f::new(Point3f::new(-1. as Float, -1., -1.), Point3f::new(1. as Float, 1., 1.));
+    let caches: Vec<_> = rays.iter().map(|ray| BBox3f::construct_ray_cache(ray)).collect();
+    let mut caches = caches.iter();
+    let t_traversal = time(&mut |_| bound.intersect_ray_cached(caches.next().unwrap()).is_some());
+
+    let ret = CostModel{
+        traversal: (t_traversal / t_sphere) as Float,
+        triangle: (t_triangle / t_sphere) as Float,
+        shape: 1. as Float,
+        transform: ((t_transformed - t_sphere) / t_sphere).max(0.) as Float,
+    };
+    info!("calibrated {:?} over {} rays, {} hits", ret, n, hits);
+    ret
+}
@@ -0,0 +1,262 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Uniform grid, for many densely packed components of similar sizes
+
+use super::*;
+use super::cost::CostModel;
+use super::shape::ShapedPrimitive;
+use super::transformed::TransformedComposable;
+use std::mem;
+
+/// most cells along an axis
+pub const MAX_GRID_RESOLUTION: usize = 256;
+
+/// A uniform grid of cells over the bounds of its components, each
+/// listing the components overlapping it. Much quicker to build than a
+/// `BVH`, and competitive for many components of similar sizes, e.g.
+/// particles, but slow for components of widely varying sizes.
+pub struct UniformGrid {
+    components: Vec<ComponentPointer>,
+    bound: BBox3f,
+    resolution: [usize; 3],
+    // width of a cell along each axis
+    width: Vector3f,
+    // components overlapping cell `i` are
+    // `indices[offsets[i] as usize..offsets[i+1] as usize]`
+    offsets: Vec<u32>,
+    indices: Vec<u32>,
+}
+
+impl UniformGrid {
+    /// construction from a `Composable` slice, with about three cells per
+    /// cubic root of the number of components along the longest axis of
+    /// their bounds, and cubic cells otherwise
+    pub fn new(components: &[ComponentPointer]) -> UniformGrid {
+        assert!(!components.is_empty(), "empty grid");
+        assert!(components.len() < u32::max_value() as usize, "too many components");
+        let bounds: Vec<BBox3f> = components.iter().map(|c| c.bbox_parent()).collect();
+        let mut bound = bounds[0];
+        for b in &bounds[1..] {
+            bound = bound.union(b);
+        }
+        let diagonal = bound.diagonal();
+        let max_width = diagonal.x.max(diagonal.y).max(diagonal.z);
+        let per_unit = if max_width > 0. as Float {
+            3. as Float * (components.len() as Float).cbrt() / max_width
+        } else {
+            0. as Float
+        };
+        let mut resolution = [1; 3];
+        let mut width = Vector3f::new(0. as Float, 0. as Float, 0. as Float);
+        for axis in 0..3 {
+            resolution[axis] = ((diagonal[axis] * per_unit).round() as usize).max(1).min(MAX_GRID_RESOLUTION);
+            width[axis] = diagonal[axis] / resolution[axis] as Float;
+        }
+        let mut grid = UniformGrid{
+            components: components.to_vec(),
+            bound: bound,
+            resolution: resolution,
+            width: width,
+            offsets: Vec::new(),
+            indices: Vec::new(),
+        };
+
+        // count the components overlapping each cell, then list them
+        let ncells = resolution[0] * resolution[1] * resolution[2];
+        let mut counts = vec![0u32; ncells + 1];
+        for b in &bounds {
+            grid.for_cells(b, |cell| counts[cell] += 1);
+        }
+        let mut offsets = Vec::with_capacity(ncells + 1);
+        let mut total = 0u32;
+        for count in &counts {
+            offsets.push(total);
+            total += *count;
+        }
+        let mut indices = vec![0u32; total as usize];
+        {
+            let mut next: Vec<u32> = offsets.clone();
+            for (i, b) in bounds.iter().enumerate() {
+                grid.for_cells(b, |cell| {
+                    indices[next[cell] as usize] = i as u32;
+                    next[cell] += 1;
+                });
+            }
+        }
+        grid.offsets = offsets;
+        grid.indices = indices;
+        grid
+    }
+
+    /// a grid of full spheres centered at `positions` with `radii`,
+    /// all made of `material`
+    pub fn from_spheres(positions: &[Point3f], radii: &[Float], material: Arc<Material>) -> UniformGrid {
+        assert_eq!(positions.len(), radii.len(), "mismatched sphere attributes");
+        let components: Vec<ComponentPointer> = positions.iter().zip(radii).map(|(&p, &r)| {
+            let sphere = ShapedPrimitive::new(Sphere::full(r), material.clone(), None);
+            let m = Matrix4f::from_translation(p.to_vec());
+            let inv = Matrix4f::from_translation(-p.to_vec());
+            let sphere: Arc<Composable> = Arc::new(TransformedComposable::new(
+                sphere, Arc::new(m), Arc::new(inv)
+            ));
+            sphere.into()
+        }).collect();
+        UniformGrid::new(&components)
+    }
+
+    /// number of cells along each axis
+    #[inline]
+    pub fn resolution(&self) -> [usize; 3] {
+        self.resolution
+    }
+
+    // cell of `p` along `axis`, clamped onto the grid
+    #[inline]
+    fn cell_of(&self, p: Point3f, axis: usize) -> usize {
+        if self.width[axis] <= 0. as Float { return 0; }
+        let c = ((p[axis] - self.bound.pmin[axis]) / self.width[axis]).floor();
+        if c < 0. as Float { 0 } else { (c as usize).min(self.resolution[axis] - 1) }
+    }
+
+    #[inline]
+    fn cell_index(&self, x: usize, y: usize, z: usize) -> usize {
+        (z * self.resolution[1] + y) * self.resolution[0] + x
+    }
+
+    // call `f` on the index of each cell overlapping `b`
+    fn for_cells<F: FnMut(usize)>(&self, b: &BBox3f, mut f: F) {
+        let (x0, x1) = (self.cell_of(b.pmin, 0), self.cell_of(b.pmax, 0));
+        let (y0, y1) = (self.cell_of(b.pmin, 1), self.cell_of(b.pmax, 1));
+        let (z0, z1) = (self.cell_of(b.pmin, 2), self.cell_of(b.pmax, 2));
+        for z in z0..z1 + 1 {
+            for y in y0..y1 + 1 {
+                for x in x0..x1 + 1 {
+                    f(self.cell_index(x, y, z));
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn cell(&self, index: usize) -> &[u32] {
+        &self.indices[self.offsets[index] as usize..self.offsets[index + 1] as usize]
+    }
+
+    // walk the cells pierced by `ray` in order with a 3D-DDA, calling
+    // `f` on each along with the `t` the ray leaves it at, until `f`
+    // returns true. `f` may shorten the ray
+    fn walk<F>(&self, ray: &mut RawRay, mut f: F)
+        where F: FnMut(&mut RawRay, &[u32], Float) -> bool
+    {
+        let t0 = match self.bound.intersect_ray(ray) {
+            Some((t0, _)) => t0,
+            None => return,
+        };
+        let origin = ray.origin();
+        let dir = ray.direction();
+        let pentry = origin + dir * t0;
+        let mut cell = [0; 3];
+        let mut next = [0. as Float; 3];
+        let mut delta = [0. as Float; 3];
+        let mut step = [0isize; 3];
+        for axis in 0..3 {
+            cell[axis] = self.cell_of(pentry, axis) as isize;
+            if dir[axis] > 0. as Float {
+                let boundary = self.bound.pmin[axis] + (cell[axis] + 1) as Float * self.width[axis];
+                next[axis] = t0 + (boundary - pentry[axis]) / dir[axis];
+                delta[axis] = self.width[axis] / dir[axis];
+                step[axis] = 1;
+            } else if dir[axis] < 0. as Float {
+                let boundary = self.bound.pmin[axis] + cell[axis] as Float * self.width[axis];
+                next[axis] = t0 + (boundary - pentry[axis]) / dir[axis];
+                delta[axis] = -self.width[axis] / dir[axis];
+                step[axis] = -1;
+            } else {
+                next[axis] = float::infinity();
+                step[axis] = 0;
+            }
+        }
+        loop {
+            let axis = if next[0] < next[1] {
+                if next[0] < next[2] { 0 } else { 2 }
+            } else if next[1] < next[2] { 1 } else { 2 };
+            let index = self.cell_index(cell[0] as usize, cell[1] as usize, cell[2] as usize);
+            if f(ray, self.cell(index), next[axis]) { return; }
+            if ray.max_extend() < next[axis] || step[axis] == 0 { return; }
+            cell[axis] += step[axis];
+            if cell[axis] < 0 || cell[axis] >= self.resolution[axis] as isize { return; }
+            next[axis] += delta[axis];
+        }
+    }
+}
+
+impl Composable for UniformGrid {
+    #[inline]
+    fn bbox_parent(&self) -> BBox3f {
+        self.bound
+    }
+
+    /// components overlapping several cells are tested once per cell,
+    /// keeping the nearest hit. Hits beyond the cell being walked are
+    /// kept as well, only ending the walk once the ray leaves the cell
+    /// they lie in
+    fn intersect_ray(&self, ray: &mut RawRay) -> Option<SurfaceInteraction> {
+        let mut final_ret = None;
+        self.walk(ray, |ray, cell, texit| {
+            for &i in cell {
+                let mut iray = ray.clone();
+                let ret = self.components[i as usize].intersect_ray(&mut iray);
+                if ray.max_extend() > iray.max_extend() {
+                    *ray = iray;
+                    final_ret = ret;
+                }
+            }
+            ray.max_extend() <= texit
+        });
+        final_ret
+    }
+
+    fn can_intersect(&self, ray: &RawRay) -> bool {
+        let mut hit = false;
+        let mut ray = ray.clone();
+        self.walk(&mut ray, |ray, cell, _| {
+            hit = cell.iter().any(|&i| self.components[i as usize].can_intersect(ray));
+            hit
+        });
+        hit
+    }
+
+    /// rough cost of walking a ray through a third of the cells along
+    /// each axis, testing the components of each
+    fn intersection_cost(&self, costs: &CostModel) -> Float {
+        let ncells = self.offsets.len() - 1;
+        let mean_cost = self.components.iter().map(|c| c.intersection_cost(costs)).sum::<Float>()
+            / self.components.len() as Float;
+        let occupancy = self.indices.len() as Float / ncells as Float;
+        let walked = (self.resolution[0] + self.resolution[1] + self.resolution[2]) as Float / 3. as Float;
+        costs.traversal + walked * (costs.traversal + occupancy * mean_cost)
+    }
+
+    fn component_count(&self) -> usize {
+        self.components.iter().map(|c| c.component_count()).sum()
+    }
+
+    fn visit_primitives(&self, f: &mut FnMut(&Primitive)) {
+        for component in &self.components {
+            component.visit_primitives(f);
+        }
+    }
+
+    fn memory_estimate(&self) -> usize {
+        mem::size_of::<UniformGrid>()
+            + (self.offsets.capacity() + self.indices.capacity()) * mem::size_of::<u32>()
+            + self.components.capacity().saturating_sub(self.components.len()) * mem::size_of::<ComponentPointer>()
+            + self.components.iter().map(|c| c.memory_estimate()).sum::<usize>()
+    }
+}
@@ -0,0 +1,178 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Groups of components moved as a unit
+
+use geometry::prelude::*;
+use super::*;
+use super::bvh::{BVH, BVHStrategy};
+use super::transformed::TransformedComposable;
+use std::sync::Arc;
+use std::mem;
+
+/// number of children above which a group builds a `BVH` over them
+pub const GROUP_BVH_THRESHOLD: usize = 8;
+
+/// A group of components, along with the lights they make up,
+/// placed into its parent frame by an optional transform
+pub struct Group {
+    children: Children,
+    bbox: BBox3f,
+    lights: Vec<Arc<Light>>,
+    transform: Option<(Arc<Matrix4f>, Arc<Matrix4f>)>,
+}
+
+enum Children {
+    Few(Vec<ComponentPointer>),
+    Many(BVH),
+}
+
+impl Group {
+    /// group `children` along with their `lights`, both in the group's
+    /// local frame. Lights of nested groups can be got by `collect_lights`
+    /// with an identity parent transform. `children` should not be empty.
+    pub fn new(children: Vec<ComponentPointer>, lights: Vec<Arc<Light>>) -> Group {
+        assert!(children.len() > 0, "empty group");
+        let mut bbox = children[0].bbox_parent();
+        for child in &children {
+            bbox = bbox.union(&child.bbox_parent());
+        }
+        let children = if children.len() > GROUP_BVH_THRESHOLD {
+            Children::Many(BVH::new(&children, BVHStrategy::SAH))
+        } else {
+            Children::Few(children)
+        };
+        Group{
+            children: children,
+            bbox: bbox,
+            lights: lights,
+            transform: None,
+        }
+    }
+
+    /// place the group into its parent frame by `local_parent`,
+    /// whose inverse is `parent_local`
+    #[inline]
+    pub fn set_transform(&mut self, local_parent: Arc<Matrix4f>, parent_local: Arc<Matrix4f>) {
+        self.transform = Some((local_parent, parent_local));
+    }
+
+    /// lights of the group, transformed into the frame `parent_transform`
+    /// maps the group's parent frame into, which should be invertible
+    pub fn collect_lights(&self, parent_transform: &Matrix4f) -> Vec<Arc<Light>> {
+        let local_world = match self.transform {
+            Some((ref local_parent, _)) => *parent_transform * **local_parent,
+            None => *parent_transform,
+        };
+        if local_world == Matrix4f::identity() {
+            return self.lights.clone();
+        }
+        let world_local = Arc::new(local_world.invert().expect("singular parent transform"));
+        let local_world = Arc::new(local_world);
+        self.lights.iter().map(|light| {
+            Arc::new(TransformedComposable::new(
+                light.clone(), local_world.clone(), world_local.clone()
+            )) as Arc<Light>
+        }).collect()
+    }
+
+    fn intersect_local(&self, min_ray: &mut RawRay) -> Option<SurfaceInteraction> {
+        let children = match self.children {
+            Children::Many(ref bvh) => return bvh.intersect_ray(min_ray),
+            Children::Few(ref children) => children,
+        };
+        if self.bbox.intersect_ray(min_ray).is_none() { return None; }
+        let mut final_ret = None;
+        for child in children {
+            if child.bbox_parent().intersect_ray(min_ray).is_none() { continue; }
+            let mut ray = min_ray.clone();
+            let ret = child.intersect_ray(&mut ray);
+            if min_ray.max_extend() > ray.max_extend() {
+                *min_ray = ray;
+                final_ret = ret;
+            }
+        }
+        final_ret
+    }
+
+    fn can_intersect_local(&self, ray: &RawRay) -> bool {
+        match self.children {
+            Children::Many(ref bvh) => bvh.can_intersect(ray),
+            Children::Few(ref children) => {
+                self.bbox.intersect_ray(ray).is_some()
+                    && children.iter().any(|child| child.can_intersect(ray))
+            },
+        }
+    }
+}
+
+impl Composable for Group {
+    #[inline]
+    fn bbox_parent(&self) -> BBox3f {
+        match self.transform {
+            Some((ref local_parent, _)) => self.bbox.apply_transform(&**local_parent),
+            None => self.bbox,
+        }
+    }
+
+    fn intersect_ray(&self, ray: &mut RawRay) -> Option<SurfaceInteraction> {
+        if let Some((ref local_parent, ref parent_local)) = self.transform {
+            *ray = ray.apply_transform(&**parent_local);
+            let mut ret = self.intersect_local(ray);
+            if let Some(ret) = ret.as_mut() {
+                *ret = ret.apply_instancing(&**local_parent, &**parent_local);
+            }
+            *ray = ray.apply_transform(&**local_parent);
+            ret
+        } else {
+            self.intersect_local(ray)
+        }
+    }
+
+    fn can_intersect(&self, ray: &RawRay) -> bool {
+        if let Some((_, ref parent_local)) = self.transform {
+            self.can_intersect_local(&ray.apply_transform(&**parent_local))
+        } else {
+            self.can_intersect_local(ray)
+        }
+    }
+
+    fn intersection_cost(&self, costs: &CostModel) -> Float {
+        let cost = match self.children {
+            Children::Many(ref bvh) => bvh.intersection_cost(costs),
+            Children::Few(ref children) => children.iter().map(|c| c.intersection_cost(costs)).sum(),
+        };
+        if self.transform.is_some() { costs.transform + cost } else { cost }
+    }
+
+    fn component_count(&self) -> usize {
+        match self.children {
+            Children::Many(ref bvh) => bvh.component_count(),
+            Children::Few(ref children) => children.iter().map(|c| c.component_count()).sum(),
+        }
+    }
+
+    fn memory_estimate(&self) -> usize {
+        let children = match self.children {
+            Children::Many(ref bvh) => bvh.memory_estimate(),
+            Children::Few(ref children) => children.iter().map(|c| c.memory_estimate()).sum(),
+        };
+        let transform = if self.transform.is_some() { 2 * mem::size_of::<Matrix4f>() } else { 0 };
+        mem::size_of_val(self) + children + transform
+            + self.lights.capacity() * mem::size_of::<Arc<Light>>()
+    }
+
+    fn visit_primitives(&self, f: &mut FnMut(&Primitive)) {
+        match self.children {
+            Children::Many(ref bvh) => bvh.visit_primitives(f),
+            Children::Few(ref children) => for child in children {
+                child.visit_primitives(f);
+            },
+        }
+    }
+}
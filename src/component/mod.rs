@@ -9,15 +9,23 @@
 //! Defines renderable components in the world.
 
 use std::path::Path;
+use std::fs::File;
+use std::io::Read;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::mem;
+use std::fmt;
 use tobj;
-use lighting::Light;
+use lighting::{Light, EmissionSide};
 use geometry::prelude::*;
+use geometry::polygon;
 use material::prelude::*;
+use material::leaf::DEFAULT_LEAF_TRANSMISSION;
 use shape::prelude::*;
 use texturing::prelude::*;
 use spectrum::prelude::*;
+use self::cost::CostModel;
+use self::progress::{LoadPhase, LoadProgress};
 
 /// A renderable composable component.
 pub trait Composable: Sync + Send {
@@ -30,6 +38,14 @@ pub trait Composable: Sync + Send {
     /// - if hit, `ray`'s `tmax` would be updated to the hitting `t`.
     fn intersect_ray(&self, ray: &mut RawRay) -> Option<SurfaceInteraction>;
 
+    /// distance along `ray`, in parent frame, to the hit `intersect_ray`
+    /// would find, without computing its interaction. Might be more efficient
+    #[inline]
+    fn intersect_t(&self, ray: &RawRay) -> Option<Float> {
+        let mut ray = ray.clone();
+        self.intersect_ray(&mut ray).map(|_| ray.max_extend())
+    }
+
     /// test if an intersection can occur. Might be more efficient
     #[inline]
     fn can_intersect(&self, ray: &RawRay) -> bool {
@@ -37,14 +53,44 @@ pub trait Composable: Sync + Send {
         self.intersect_ray(&mut ray).is_some()
     }
 
+    /// test each of `rays` for an intersection as `can_intersect` does,
+    /// writing the outcomes into `results` of the same length. Aggregates
+    /// may test the rays in any order to improve coherence.
+    fn occluded_batch(&self, rays: &mut [RawRay], results: &mut [bool]) {
+        assert_eq!(rays.len(), results.len(), "mismatched batch lengths");
+        for (ray, result) in rays.iter().zip(results.iter_mut()) {
+            *result = self.can_intersect(ray);
+        }
+    }
+
     fn as_light(&self) -> &Light {
         unimplemented!();
     }
 
+    /// expected cost of intersecting a ray against the component,
+    /// under `costs`
     #[inline]
-    fn intersection_cost(&self) -> Float {
-        1.0 as Float
+    fn intersection_cost(&self, costs: &CostModel) -> Float {
+        costs.shape
     }
+
+    /// number of primitives making up the component
+    #[inline]
+    fn component_count(&self) -> usize {
+        1
+    }
+
+    /// approximate memory taken by the component in bytes.
+    /// Shared data is counted once per reference.
+    #[inline]
+    fn memory_estimate(&self) -> usize {
+        mem::size_of_val(self)
+    }
+
+    /// call `f` on each primitive ray hits could report, in a
+    /// deterministic order. Components without any have nothing to visit.
+    #[inline]
+    fn visit_primitives(&self, _f: &mut FnMut(&Primitive)) { }
 }
 
 // /// An aggregated renderable entity
@@ -59,85 +105,226 @@ pub trait Primitive: Composable + Light {
 
     /// return the material associated with this primitive
     fn get_material(&self) -> &Material;
+
+    /// return the triangle mesh this primitive is part of, if any
+    #[inline]
+    fn parent_mesh(&self) -> Option<&TriangleMesh> {
+        None
+    }
+
+    /// return the index of this primitive among the triangles of
+    /// `parent_mesh`, if any
+    #[inline]
+    fn triangle_index(&self) -> Option<usize> {
+        None
+    }
+
+    /// return if the primitive only catches shadows for compositing,
+    /// being invisible otherwise. Honored by `PTRenderer` only.
+    #[inline]
+    fn is_shadow_catcher(&self) -> bool {
+        false
+    }
+}
+
+/// Reasons an `.obj` file can't be loaded
+#[derive(Debug)]
+pub enum ObjError {
+    /// the file or its materials can't be read or parsed
+    Load(tobj::LoadError),
+    /// a texture can't be loaded, under `FallbackMode::Error`
+    Texture(TextureError),
+}
+
+impl From<tobj::LoadError> for ObjError {
+    #[inline]
+    fn from(e: tobj::LoadError) -> ObjError {
+        ObjError::Load(e)
+    }
+}
+
+impl From<TextureError> for ObjError {
+    #[inline]
+    fn from(e: TextureError) -> ObjError {
+        ObjError::Texture(e)
+    }
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ObjError::Load(ref e) => write!(f, "{}", e),
+            ObjError::Texture(ref e) => write!(f, "{}", e),
+        }
+    }
 }
 
 /// Load an `.obj` file into a vector
-pub fn load_obj(path: &Path, transform: Matrix4f) -> Result<Vec<ComponentPointer>, tobj::LoadError> {
+pub fn load_obj(path: &Path, transform: Matrix4f) -> Result<Vec<ComponentPointer>, ObjError> {
+    load_obj_with_lights(path, transform).map(|(shapes, _)| shapes)
+}
+
+/// Load an `.obj` file into a vector, along with one `MeshLight`
+/// for each emissive mesh, whose emission is given by `Ke` in the `.mtl`
+pub fn load_obj_with_lights(
+    path: &Path, transform: Matrix4f
+) -> Result<(Vec<ComponentPointer>, Vec<Arc<MeshLight>>), ObjError> {
+    load_obj_with_options(path, transform, ObjOptions::default())
+}
+
+/// default angle in degrees above which generated normals are split
+pub const DEFAULT_NORMAL_ANGLE: Float = 30. as Float;
+
+/// Options on loading `.obj` files
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ObjOptions {
+    /// if presented, generate vertex normals for meshes without them,
+    /// keeping edges sharper than this angle in degrees hard
+    pub generate_normals: Option<Float>,
+    /// how shading normals of the loaded meshes are chosen
+    pub shading: ShadingMode,
+    /// kinds of rays the loaded meshes can be hit by
+    pub visibility: VisibilityFlags,
+    /// skip back faces of the loaded meshes, see `TriangleMesh::set_cull_backfaces`.
+    /// Meshes with transmissive materials are never culled.
+    pub cull_backfaces: bool,
+    /// shift shadow ray origins off the loaded meshes, see
+    /// `TriangleMesh::set_offset_shadows`
+    pub offset_shadows: bool,
+    /// which side(s) emissive meshes emit from
+    pub emission_side: EmissionSide,
+    /// if presented, sample lights of emissive meshes by their lighting
+    /// profile rasterized at this resolution, see `MeshLight::profile_weighted`
+    pub light_profile_resolution: Option<usize>,
+    /// what becomes of the textures of the materials failing to load
+    pub fallback: FallbackMode,
+}
+
+/// Load an `.obj` file as `load_obj_with_lights` does, with `options`
+pub fn load_obj_with_options(
+    path: &Path, transform: Matrix4f, options: ObjOptions
+) -> Result<(Vec<ComponentPointer>, Vec<Arc<MeshLight>>), ObjError> {
+    load_obj_with_progress(path, transform, options, &mut progress::ignore_progress)
+}
+
+/// Load an `.obj` file as `load_obj_with_options` does, reporting
+/// to `progress` on parsing the source, loading the materials and
+/// building each model's mesh, in that order
+pub fn load_obj_with_progress(
+    path: &Path, transform: Matrix4f, options: ObjOptions,
+    progress: &mut FnMut(LoadProgress)
+) -> Result<(Vec<ComponentPointer>, Vec<Arc<MeshLight>>), ObjError> {
+    load_obj_with_tables(
+        path, transform, options, progress, &mut HashMap::new(), &mut HashMap::new(),
+        &mut TextureFallback::new(options.fallback)
+    )
+}
+
+/// `load_obj_with_progress`, sharing the mipmaps still alive in
+/// `texturess` and `bumps`, and adding the ones loaded there.
+/// Textures failing to load are substituted through `fallback`
+fn load_obj_with_tables(
+    path: &Path, transform: Matrix4f, options: ObjOptions,
+    progress: &mut FnMut(LoadProgress),
+    texturess: &mut RGBMipMapHashTable<Float>,
+    bumps: &mut LumaMipMapHashTable<Float>,
+    fallback: &mut TextureFallback
+) -> Result<(Vec<ComponentPointer>, Vec<Arc<MeshLight>>), ObjError> {
     let parent_path = path.parent().unwrap_or("".as_ref());
-    let (models, mtls) = tobj::load_obj(path)?;
-    let mut texturess = HashMap::new();
-    let mut bumps = HashMap::new();
+    let mut source = String::new();
+    File::open(path).and_then(|mut f| f.read_to_string(&mut source))
+        .map_err(|_| tobj::LoadError::OpenFileFailed)?;
+    let (source, non_planar) = triangulate_faces_with_progress(&source, progress);
+    if non_planar > 0 {
+        warn!("{} non-planar polygon faces in {}", non_planar, path.display());
+    }
+    let (models, mtls) = tobj::load_obj_buf(&mut source.as_bytes(), |p| {
+        tobj::load_mtl(&parent_path.join(p))
+    })?;
     let mut materials: Vec<Arc<Material>> = Vec::with_capacity(mtls.len()+1);
-    for mtl in mtls {
-        let diffuse_texture_path = parent_path.join(mtl.diffuse_texture.clone());
-        let diffuse = RGBImageTexture::new_as_arc(
-            ImageInfo{
-                name: diffuse_texture_path.into_os_string().into_string().unwrap_or_default(),
-                trilinear: false,
-                max_aniso: 16. as Float,
-                wrapping: ImageWrapMode::Repeat,
-                gamma: false,
-                scale: 1. as Float,
-            },
-            UVMapping{
-                scaling: Vector2f::new(1. as Float, 1. as Float),
-                shifting: Vector2f::zero(),
-            },
-            &mut texturess
-        ).unwrap_or_else(|| {
-            if mtl.diffuse_texture != "" {
-                warn!("diffuse texture {} unfound!", mtl.diffuse_texture);
+    let mut transmissive = Vec::with_capacity(mtls.len()+1);
+    let mut emissions: Vec<Option<Arc<Texture<Texel=RGBSpectrumf>>>> = Vec::with_capacity(mtls.len()+1);
+    let nmtls = mtls.len();
+    for (i, mtl) in mtls.into_iter().enumerate() {
+        progress(LoadProgress::new(LoadPhase::TextureLoad, i, nmtls, 0));
+        let emission = mtl.unknown_param.get("Ke").and_then(|ke| {
+            let ke: Vec<Float> = ke.split_whitespace().filter_map(|v| v.parse().ok()).collect();
+            if ke.len() == 3 && ke.iter().any(|&v| v > 0. as Float) {
+                let value = RGBSpectrumf::new(ke[0], ke[1], ke[2]);
+                Some(Arc::new(ConstantTexture{value}) as Arc<Texture<Texel=RGBSpectrumf>>)
+            } else {
+                None
             }
-            Arc::new(ConstantTexture{value: RGBSpectrum::new(
-                mtl.diffuse[0], mtl.diffuse[1], mtl.diffuse[2]
-            ) })
-        });
-
-        let specular_texture_path = parent_path.join(mtl.specular_texture.clone());
-        let specular = RGBImageTexture::new_as_arc(
-            ImageInfo{
-                name: specular_texture_path.into_os_string().into_string().unwrap_or_default(),
-                trilinear: false,
-                max_aniso: 16. as Float,
-                wrapping: ImageWrapMode::Repeat,
-                gamma: false,
-                scale: 1. as Float,
-            },
-            UVMapping{
-                scaling: Vector2f::new(1. as Float, 1. as Float),
-                shifting: Vector2f::zero(),
-            },
-            &mut texturess
-        ).unwrap_or_else(|| {
-            if mtl.specular_texture != "" {
-                warn!("specular texture {} unfound!", mtl.specular_texture);
-            }
-            Arc::new(ConstantTexture{value: RGBSpectrum::new(
-                mtl.specular[0], mtl.specular[1], mtl.specular[2]
-            ) })
         });
+        emissions.push(emission);
+        let diffuse = rgb_texture(
+            &mtl.diffuse_texture, parent_path,
+            RGBSpectrum::new(mtl.diffuse[0], mtl.diffuse[1], mtl.diffuse[2]),
+            texturess, fallback
+        )?;
+        let specular = rgb_texture(
+            &mtl.specular_texture, parent_path,
+            RGBSpectrum::new(mtl.specular[0], mtl.specular[1], mtl.specular[2]),
+            texturess, fallback
+        )?;
 
         let roughness = ConstantTexture{
             value: ((1000. - mtl.shininess) / 1000.).min(1.).max(0.) as Float
         };
 
-        let bump = LumaImageTexture::new_as_arc(
-            ImageInfo{
-                name: mtl.unknown_param.get("map_bump").map_or_else(|| String::new(), |r| r.to_owned()),
-                trilinear: false,
-                max_aniso: 16. as Float,
-                wrapping: ImageWrapMode::Repeat,
-                gamma: false,
-                scale: 1. as Float,
-            },
-            UVMapping{
-                scaling: Vector2f::new(1. as Float, 1. as Float),
-                shifting: Vector2f::zero(),
+        // `map_bump` is taken as is, rather than relative to the `.obj`
+        let bump = match mtl.unknown_param.get("map_bump") {
+            Some(name) if !name.is_empty() => {
+                let info = ImageInfo{name: name.to_owned(), ..image_info(parent_path, name)};
+                match LumaImageTexture::new_as_arc(info, unit_mapping(), bumps) {
+                    Ok(bump) => Some(bump),
+                    Err(e) => fallback.gray(e, None)?,
+                }
             },
-            &mut bumps
-        );
+            _ => None,
+        };
         let illum = mtl.unknown_param.get("illum").map(|a| a.as_ref()).unwrap_or("2");
         let dissolve = mtl.dissolve.max(0.).min(1.) as Float;
+        if PBR_PARAMS.iter().any(|&p| mtl.unknown_param.contains_key(p)) {
+            // metallic-roughness, from the PBR extension of `.mtl`
+            let mut gray = |map: &str, scalar: &str, default: Float| {
+                let value = mtl.unknown_param.get(scalar)
+                    .and_then(|v| v.trim().parse().ok()).unwrap_or(default);
+                gray_texture(mtl.unknown_param.get(map), parent_path, value, bumps, fallback)
+            };
+            let metallic = gray("map_Pm", "Pm", 0. as Float)?;
+            let roughness = gray("map_Pr", "Pr", 1. as Float)?;
+            let mut pbr = PbrMaterial::new(diffuse, metallic, roughness);
+            pbr.normal = match mtl.unknown_param.get("norm") {
+                Some(m) => match RGBImageTexture::new_as_arc(image_info(parent_path, m), unit_mapping(), texturess) {
+                    Ok(normal) => Some(normal),
+                    Err(e) => fallback.rgb(e, None)?,
+                },
+                None => None,
+            };
+            pbr.emissive = emissions.last().and_then(|e| e.clone());
+            transmissive.push(false);
+            materials.push(Arc::new(pbr));
+            continue;
+        }
+        // thin foliage, marked by a `leaf` or `thin` parameter optionally
+        // giving the fraction transmitted, or by a dissolve with illum 9
+        let leaf = mtl.unknown_param.get("leaf").or_else(|| mtl.unknown_param.get("thin"));
+        if leaf.is_some() || (illum.trim() == "9" && !relative_eq!(dissolve, 1.0 as Float)) {
+            let transmission = leaf.and_then(|v| v.trim().parse().ok()).unwrap_or_else(|| {
+                if relative_eq!(dissolve, 1.0 as Float) {
+                    DEFAULT_LEAF_TRANSMISSION
+                } else {
+                    1. as Float - dissolve
+                }
+            });
+            transmissive.push(true);
+            materials.push(Arc::new(LeafMaterial::new(
+                diffuse.clone(), diffuse, transmission, bump
+            )));
+            continue;
+        }
+        transmissive.push(illum.contains("4") || !relative_eq!(dissolve, 1.0 as Float));
         // if illum == "4" {
         if illum.contains("4") {
             // specular transmittance
@@ -170,18 +357,163 @@ pub fn load_obj(path: &Path, transform: Matrix4f) -> Result<Vec<ComponentPointer
         Arc::new(ConstantTexture{value: 0. as Float}), 
         None
     )));
+    emissions.push(None);
+    transmissive.push(false);
+    progress(LoadProgress::new(LoadPhase::TextureLoad, nmtls, nmtls, 0));
     let mut shapes: Vec<ComponentPointer> = Vec::new();
-    for model in models {
+    let mut lights = Vec::new();
+    let nmodels = models.len();
+    progress(LoadProgress::new(LoadPhase::MeshBuild, 0, nmodels, 0));
+    for (i, model) in models.into_iter().enumerate() {
         let mid = model.mesh.material_id.unwrap_or(materials.len()-1);
         // let mid = materials.len()-1;
-        let mesh = TriangleMesh::from_model_transformed(model, transform, materials[mid].clone(), None);
-        for shape in mesh {
+        let mut mesh = TriangleMesh::from_model_transformed(
+            model, transform, materials[mid].clone(), emissions[mid].clone()
+        );
+        if let Some(angle) = options.generate_normals {
+            // `tobj` ignores smoothing groups, so the angle is all we have
+            if !mesh.has_normals() { mesh.generate_normals(angle); }
+        }
+        mesh.set_shading_mode(options.shading);
+        mesh.set_visibility(options.visibility);
+        mesh.set_cull_backfaces(options.cull_backfaces && !transmissive[mid]);
+        mesh.set_offset_shadows(options.offset_shadows);
+        mesh.set_emission_side(options.emission_side);
+        let mesh = Arc::new(mesh);
+        for shape in TriangleMesh::instances(&mesh) {
             shapes.push(
                 shape.into()
             );
         }
+        if mesh.is_emissive() {
+            lights.push(Arc::new(match options.light_profile_resolution {
+                Some(resolution) => MeshLight::profile_weighted(mesh, resolution),
+                None => MeshLight::new(mesh),
+            }));
+        }
+        progress(LoadProgress::new(LoadPhase::MeshBuild, i + 1, nmodels, 0));
+    }
+    Ok((shapes, lights))
+}
+
+/// parameters of the PBR extension of `.mtl`, by which
+/// materials are loaded as `PbrMaterial`s. Metallic defaults to 0
+/// and roughness to 1 if only one of them is given.
+const PBR_PARAMS: &[&str] = &["Pm", "Pr", "map_Pm", "map_Pr"];
+
+fn image_info(parent_path: &Path, name: &str) -> ImageInfo {
+    ImageInfo{
+        name: parent_path.join(name).into_os_string().into_string().unwrap_or_default(),
+        trilinear: false,
+        max_aniso: DEFAULT_MAX_ANISO,
+        wrapping: ImageWrapMode::Repeat,
+        gamma: false,
+        scale: 1. as Float,
+    }
+}
+
+// the texture `name`d relative to `parent_path`, substituted through
+// `fallback` if it fails to load, or of `parameter` if none is named
+fn rgb_texture(
+    name: &str, parent_path: &Path, parameter: RGBSpectrumf,
+    table: &mut RGBMipMapHashTable<Float>, fallback: &mut TextureFallback
+) -> Result<Arc<Texture<Texel=RGBSpectrumf>>, TextureError> {
+    if name.is_empty() {
+        return Ok(Arc::new(ConstantTexture{value: parameter}));
+    }
+    match RGBImageTexture::new_as_arc(image_info(parent_path, name), unit_mapping(), table) {
+        Ok(texture) => Ok(texture),
+        Err(e) => fallback.rgb(e, Some(parameter)).map(|t| t.unwrap()),
+    }
+}
+
+// the gray counterpart of `rgb_texture`
+fn gray_texture(
+    name: Option<&String>, parent_path: &Path, parameter: Float,
+    table: &mut LumaMipMapHashTable<Float>, fallback: &mut TextureFallback
+) -> Result<Arc<Texture<Texel=Float>>, TextureError> {
+    let name = match name {
+        Some(name) if !name.is_empty() => name,
+        _ => return Ok(Arc::new(ConstantTexture{value: parameter})),
+    };
+    match LumaImageTexture::new_as_arc(image_info(parent_path, name), unit_mapping(), table) {
+        Ok(texture) => Ok(texture),
+        Err(e) => fallback.gray(e, Some(parameter)).map(|t| t.unwrap()),
+    }
+}
+
+fn unit_mapping() -> UVMapping {
+    UVMapping{
+        scaling: Vector2f::new(1. as Float, 1. as Float),
+        shifting: Vector2f::zero(),
+    }
+}
+
+/// relative deviation from the best-fit plane above which
+/// a polygon face is reported as non-planar
+const NON_PLANAR_THRESHOLD: Float = 1e-3 as Float;
+
+/// `tobj` fan-triangulates polygon faces, which breaks concave ones.
+/// Rewrite every face with more than 3 vertices into ear-clipped triangles,
+/// keeping each corner's `v/vt/vn` indices as is. Returns the rewritten
+/// source along with the count of non-planar polygons encountered,
+/// reporting the bytes of `source` parsed to `progress`.
+fn triangulate_faces_with_progress(
+    source: &str, progress: &mut FnMut(LoadProgress)
+) -> (String, usize) {
+    let mut ret = String::with_capacity(source.len());
+    let mut positions: Vec<Point3f> = Vec::new();
+    let mut non_planar = 0;
+    let total = source.len();
+    let report = |progress: &mut FnMut(LoadProgress), done: usize| {
+        progress(LoadProgress::new(LoadPhase::Parsing, done, total, done as u64));
+    };
+    report(progress, 0);
+    let (mut parsed, mut reported) = (0, 0);
+    for line in source.lines() {
+        // counting a byte per terminator stripped by `lines`, short of `\r\n` ones
+        parsed = (parsed + line.len() + 1).min(total);
+        if parsed - reported >= progress::PROGRESS_STRIDE {
+            report(progress, parsed);
+            reported = parsed;
+        }
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("v") => {
+                let p: Vec<Float> = words.take(3).filter_map(|w| w.parse().ok()).collect();
+                if p.len() == 3 {
+                    positions.push(Point3f::new(p[0], p[1], p[2]));
+                }
+            },
+            Some("f") => {
+                let corners: Vec<&str> = words.collect();
+                let vertices: Option<Vec<Point3f>> = corners.iter().map(|c| {
+                    let i: isize = c.split('/').next()?.parse().ok()?;
+                    let i = if i < 0 { positions.len() as isize + i } else { i - 1 };
+                    if i < 0 { return None; }
+                    positions.get(i as usize).cloned()
+                }).collect();
+                if let Some(vertices) = vertices {
+                    if vertices.len() > 3 {
+                        if polygon::non_planarity(&vertices) > NON_PLANAR_THRESHOLD {
+                            non_planar += 1;
+                        }
+                        for t in polygon::triangulate(&vertices) {
+                            ret.push_str(&format!(
+                                "f {} {} {}\n", corners[t[0]], corners[t[1]], corners[t[2]]
+                            ));
+                        }
+                        continue;
+                    }
+                }
+            },
+            _ => (),
+        }
+        ret.push_str(line);
+        ret.push('\n');
     }
-    Ok(shapes)
+    report(progress, total);
+    (ret, non_planar)
 }
 
 /// A thread-safe pointer to a composable component
@@ -211,6 +543,14 @@ impl Composable for ComponentPointer {
         }
     }
 
+    #[inline]
+    fn intersect_t(&self, ray: &RawRay) -> Option<Float> {
+        match *self {
+            ComponentPointer::Arc(ref arc) => arc.intersect_t(ray),
+            ComponentPointer::Triangle(ref t) => Composable::intersect_t(t, ray),
+        }
+    }
+
     /// test if an intersection can occur. Might be more efficient
     #[inline]
     fn can_intersect(&self, ray: &RawRay) -> bool {
@@ -220,6 +560,14 @@ impl Composable for ComponentPointer {
         }
     }
 
+    #[inline]
+    fn occluded_batch(&self, rays: &mut [RawRay], results: &mut [bool]) {
+        match *self {
+            ComponentPointer::Arc(ref arc) => arc.occluded_batch(rays, results),
+            ComponentPointer::Triangle(ref t) => Composable::occluded_batch(t, rays, results),
+        }
+    }
+
     #[inline]
     fn as_light(&self) -> &Light {
         match *self {
@@ -229,10 +577,34 @@ impl Composable for ComponentPointer {
     }
 
     #[inline]
-    fn intersection_cost(&self) -> Float {
+    fn intersection_cost(&self, costs: &CostModel) -> Float {
         match *self {
-            ComponentPointer::Arc(ref arc) => arc.intersection_cost(),
-            ComponentPointer::Triangle(ref t) => t.intersection_cost(),
+            ComponentPointer::Arc(ref arc) => arc.intersection_cost(costs),
+            ComponentPointer::Triangle(ref t) => t.intersection_cost(costs),
+        }
+    }
+
+    #[inline]
+    fn component_count(&self) -> usize {
+        match *self {
+            ComponentPointer::Arc(ref arc) => arc.component_count(),
+            ComponentPointer::Triangle(ref t) => t.component_count(),
+        }
+    }
+
+    #[inline]
+    fn memory_estimate(&self) -> usize {
+        match *self {
+            ComponentPointer::Arc(ref arc) => mem::size_of::<ComponentPointer>() + arc.memory_estimate(),
+            ComponentPointer::Triangle(ref t) => mem::size_of::<ComponentPointer>() - mem::size_of_val(t) + t.memory_estimate(),
+        }
+    }
+
+    #[inline]
+    fn visit_primitives(&self, f: &mut FnMut(&Primitive)) {
+        match *self {
+            ComponentPointer::Arc(ref arc) => arc.visit_primitives(f),
+            ComponentPointer::Triangle(ref t) => f(t),
         }
     }
 }
@@ -253,6 +625,14 @@ impl From<TriangleInstance> for ComponentPointer {
 
 pub mod shape;
 pub mod transformed;
+pub mod group;
 pub mod bvh;
+pub mod grid;
+pub mod cost;
+pub mod progress;
 pub mod naive;
+pub mod cache;
 pub mod prelude;
+
+#[cfg(test)]
+mod tests;
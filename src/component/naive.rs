@@ -9,8 +9,9 @@
 //! A naively implemented linear aggregation of some other components
 
 use geometry::prelude::*;
-use super::Composable;
+use super::{Composable, Primitive};
 use std::sync::Arc;
+use std::mem;
 
 pub struct Naive {
     pub elements: Vec<Arc<Composable>>,
@@ -65,4 +66,19 @@ impl Composable for Naive {
         }
         final_ret
     }
+
+    fn component_count(&self) -> usize {
+        self.elements.iter().map(|e| e.component_count()).sum()
+    }
+
+    fn visit_primitives(&self, f: &mut FnMut(&Primitive)) {
+        for element in &self.elements {
+            element.visit_primitives(f);
+        }
+    }
+
+    fn memory_estimate(&self) -> usize {
+        mem::size_of::<Naive>() + self.elements.capacity() * mem::size_of::<Arc<Composable>>()
+            + self.elements.iter().map(|e| e.memory_estimate()).sum::<usize>()
+    }
 }
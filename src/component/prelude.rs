@@ -9,4 +9,9 @@
 pub use super::{Composable, Primitive};
 pub use super::shape::ShapedPrimitive;
 pub use super::transformed::TransformedComposable;
+pub use super::group::Group;
 pub use super::bvh::{BVHStrategy, BVH};
+pub use super::grid::UniformGrid;
+pub use super::cost::CostModel;
+pub use super::progress::{LoadPhase, LoadProgress};
+pub use super::cache::{SceneCache, CacheStats, load_obj_cached, build_bvh_cached};
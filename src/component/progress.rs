@@ -0,0 +1,71 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Progress of loading scenes, reported to callbacks.
+//!
+//! Within a phase, `done` never decreases, and the last report has it
+//! reaching `total`. Reports are made every `PROGRESS_STRIDE` items
+//! or bytes at most, so callbacks can afford to be slow.
+
+use geometry::prelude::*;
+
+/// Phases of loading a scene
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LoadPhase {
+    /// reading source files
+    Parsing,
+    /// building meshes out of the parsed data
+    MeshBuild,
+    /// loading images and building their mipmaps
+    TextureLoad,
+    /// building bounding volume hierarchies
+    BVHBuild,
+}
+
+/// Progress through a phase of loading
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LoadProgress {
+    pub phase: LoadPhase,
+    /// items done so far
+    pub done: usize,
+    /// items in the phase
+    pub total: usize,
+    /// bytes of input processed so far, zero if none read
+    pub bytes: u64,
+}
+
+impl LoadProgress {
+    /// construction
+    #[inline]
+    pub fn new(phase: LoadPhase, done: usize, total: usize, bytes: u64) -> LoadProgress {
+        LoadProgress{ phase: phase, done: done, total: total, bytes: bytes }
+    }
+
+    /// test if the phase is complete
+    #[inline]
+    pub fn is_complete(&self) -> bool {
+        self.done >= self.total
+    }
+
+    /// ratio of items done, in $[0, 1]$
+    #[inline]
+    pub fn fraction(&self) -> Float {
+        if self.total == 0 {
+            1. as Float
+        } else {
+            (self.done as Float / self.total as Float).min(1. as Float)
+        }
+    }
+}
+
+/// items or bytes between reports within a phase
+pub const PROGRESS_STRIDE: usize = 1 << 16;
+
+/// a callback ignoring progress
+#[inline]
+pub fn ignore_progress(_progress: LoadProgress) {}
@@ -13,8 +13,7 @@ use super::*;
 use std::sync::Arc;
 use texturing::Texture;
 use spectrum::*;
-use lighting::{LightFlag, LightSample, LIGHT_AREA, SampleInfo, PathInfo};
-use sample;
+use lighting::{LightFlag, LightSample, LIGHT_AREA, SampleInfo, PathInfo, EmissionSide};
 
 /// Represents a primitive made up by a single `Shape`
 #[derive(Clone)]
@@ -22,6 +21,14 @@ pub struct ShapedPrimitive<S, M> {
     pub shape: S,
     pub material: M,
     pub lighting_profile: Option<Arc<Texture<Texel=RGBSpectrumf>>>,
+    /// which side(s) of the shape emit light. The front side is
+    /// the one the normals returned by `Shape::sample` points to.
+    pub emission_side: EmissionSide,
+    /// if set, the primitive is invisible to camera rays except for
+    /// the shadows it receives, see `Primitive::is_shadow_catcher`
+    pub shadow_catcher: bool,
+    /// kinds of rays the primitive can be hit by, all by default
+    pub visibility: VisibilityFlags,
     // TODO: medium:
 }
 
@@ -36,10 +43,30 @@ impl<S, M> ShapedPrimitive<S, M>
     ) -> ShapedPrimitive<S, M> {
         ShapedPrimitive{
             shape: shape, material: material, lighting_profile: lighting_profile,
+            emission_side: Default::default(),
+            shadow_catcher: false,
+            visibility: Default::default(),
         }
     }
 }
 
+impl<S, M> ShapedPrimitive<S, M>
+    where S: Shape, M: Material
+{
+    /// radiance emitted from the sampled point `ss` along `dir`
+    fn emission_at(&self, ss: &ShapeSample, dir: Vector3f) -> RGBSpectrumf {
+        if let Some(ref lp) = self.lighting_profile {
+            if self.emission_side.emits(ss.norm, dir) {
+                let si = ss.interaction(dir.normalize());
+                // point look-up, there is no footprint to filter over
+                let dxy = DxyInfo::default();
+                return lp.evaluate(&si, &dxy);
+            }
+        }
+        RGBSpectrumf::black()
+    }
+}
+
 impl<S, M> Composable for ShapedPrimitive<S, M>
     where S: Shape, M: Material
 {
@@ -50,6 +77,7 @@ impl<S, M> Composable for ShapedPrimitive<S, M>
 
     #[inline]
     fn intersect_ray(&self, ray: &mut RawRay) -> Option<SurfaceInteraction> {
+        if !ray.kind().sees(self.visibility) { return None; }
         let r = self.shape.intersect_ray(ray);
         if let Some((t, mut si)) = r {
             ray.set_max_extend(t);
@@ -62,13 +90,18 @@ impl<S, M> Composable for ShapedPrimitive<S, M>
 
     #[inline]
     fn can_intersect(&self, ray: &RawRay) -> bool {
-        self.shape.can_intersect(ray)
+        ray.kind().sees(self.visibility) && self.shape.can_intersect(ray)
     }
 
     #[inline]
     fn as_light(&self) -> &Light {
         self
     }
+
+    #[inline]
+    fn visit_primitives(&self, f: &mut FnMut(&Primitive)) {
+        f(self)
+    }
 }
 
 impl<S, M> Light for ShapedPrimitive<S, M>
@@ -94,8 +127,11 @@ impl<S, M> Light for ShapedPrimitive<S, M>
             // match `wi` against surface normal
             let ray = RawRay::from_od(p, -dir);
             if let Some((_t, si)) = self.shape.intersect_ray(&ray) {
-                // retrive (u, v)
-                let dxy = DxyInfo::from_duv(&si.duv);
+                if !self.emission_side.emits(si.basic.norm, dir) {
+                    return RGBSpectrumf::black();
+                }
+                // point look-up, there is no footprint to filter over
+                let dxy = DxyInfo::default();
                 return lp.evaluate(&si, &dxy);
             }
         }
@@ -108,39 +144,26 @@ impl<S, M> Light for ShapedPrimitive<S, M>
     fn evaluate_sampled(
         &self, pos: Point3f, sample: Point2f
     ) -> LightSample {
-        let (l_pos, l_norm, l_pdf) = self.shape.sample_wrt(pos, sample);
-        let mut ret = LightSample{
-            radiance: RGBSpectrumf::black(),
-            pdf: l_pdf,
-            pfrom: l_pos,
+        let ss = self.shape.sample_wrt(pos, sample);
+        LightSample{
+            radiance: self.emission_at(&ss, pos - ss.pos),
+            pdf: ss.pdf,
+            pfrom: ss.pos,
             pto: pos,
-        };
-        // match against surface normal
-        if let Some(ref lp) = self.lighting_profile {
-            let ldir = pos - l_pos;
-            if ldir.dot(l_norm) > 0. as Float {
-                let ray = RawRay::from_od(pos, -ldir);
-                if let Some((_, si)) = self.shape.intersect_ray(&ray) {
-                    let dxy = DxyInfo::from_duv(&si.duv);
-                    ret.radiance = lp.evaluate(&si, &dxy);
-                }
-            }
+            normal: ss.norm,
         }
-        ret
     }
 
     #[inline]
     fn generate_path(&self, samples: SampleInfo) -> PathInfo {
-        let (pos, norm, pdfpos) = self.shape.sample(samples.pfilm);
-        let (u, v) = normal::get_basis_from(norm);
-        let dir = sample::sample_cosw_hemisphere(samples.plens);
-        let dir = dir.x * u + dir.y * v + dir.z * norm;
+        let ss = self.shape.sample(samples.pfilm);
+        let (dir, pdfdir) = self.emission_side.sample_cosw(ss.norm, samples.plens);
         PathInfo{
-            ray: RawRay::from_od(pos, dir),
-            normal: norm,
-            pdfpos: pdfpos,
-            pdfdir: sample::pdf_cosw_hemisphere(dir.z),
-            radiance: self.evaluate_path(pos, dir),
+            ray: RawRay::from_od(ss.pos, dir),
+            normal: ss.norm,
+            pdfpos: ss.pdf,
+            pdfdir: pdfdir,
+            radiance: self.emission_at(&ss, dir),
         }
     }
 
@@ -148,7 +171,7 @@ impl<S, M> Light for ShapedPrimitive<S, M>
     fn pdf_path(&self, pos: Point3f, dir: Vector3f, norm: Vector3f) -> (Float, Float) {
         (
             self.shape.pdf(pos, norm),
-            sample::pdf_cosw_hemisphere(norm.dot(dir).abs())
+            self.emission_side.pdf_cosw(norm, dir)
         )
     }
 
@@ -160,7 +183,7 @@ impl<S, M> Light for ShapedPrimitive<S, M>
     fn power(&self) -> RGBSpectrumf {
         if let Some(ref lp) = self.lighting_profile {
             debug_assert!(self.shape.surface_area() >= 0. as Float);
-            lp.mean() * self.shape.surface_area() * float::pi()
+            lp.mean() * self.shape.surface_area() * float::pi() * self.emission_side.sides()
         } else {
             RGBSpectrumf::black()
         }
@@ -180,6 +203,11 @@ impl<S, M> Primitive for ShapedPrimitive<S, M>
         self.lighting_profile.is_some()
     }
 
+    #[inline]
+    fn is_shadow_catcher(&self) -> bool {
+        self.shadow_catcher
+    }
+
     // #[inline]
     // fn get_area_light(&self) -> Option<&Light> {
     //     if let Some(ref al) = self.area_light {
@@ -0,0 +1,1356 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// tests
+#[cfg(test)]
+mod test_obj {
+    use super::super::*;
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+
+    const L_SHAPE: &str = "\
+v 0 0 0
+v 2 0 0
+v 2 1 0
+v 1 1 0
+v 1 2 0
+v 0 2 0
+vt 0 0
+vt 1 0
+vt 1 0.5
+vt 0.5 0.5
+vt 0.5 1
+vt 0 1
+f 1/1 2/2 3/3 4/4 5/5 -1/6
+";
+
+    #[test]
+    fn test_concave_face() {
+        let (source, non_planar) = triangulate_faces_with_progress(L_SHAPE, &mut progress::ignore_progress);
+        assert_eq!(non_planar, 0);
+        let faces: Vec<&str> = source.lines().filter(|l| l.starts_with("f ")).collect();
+        assert_eq!(faces.len(), 4);
+        // the concave corner is never fanned across
+        assert!(faces.iter().all(|f| f.contains("4/4") || !(f.contains("3/3") && f.contains("5/5"))));
+
+        let path = env::temp_dir().join("arendur_test_concave_face.obj");
+        fs::File::create(&path).unwrap().write_all(L_SHAPE.as_bytes()).unwrap();
+        let shapes = load_obj(&path, Matrix4f::identity()).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(shapes.len(), 4);
+
+        // cast rays through a grid, the covered area should match
+        // the polygon, with no cracks in between the triangles
+        let n = 64;
+        let mut hits = 0;
+        for i in 0..n {
+            for j in 0..n {
+                let x = (i as Float + 0.5) * 2. as Float / n as Float;
+                let y = (j as Float + 0.5) * 2. as Float / n as Float;
+                let ray = RawRay::from_od(
+                    Point3f::new(x, y, -1. as Float),
+                    Vector3f::new(0. as Float, 0. as Float, 1. as Float)
+                );
+                let hit = shapes.iter().any(|s| s.can_intersect(&ray));
+                assert_eq!(hit, x < 1. as Float || y < 1. as Float, "at ({}, {})", x, y);
+                if hit { hits += 1; }
+            }
+        }
+        assert_relative_eq!(hits as Float * 4. as Float / (n * n) as Float, 3. as Float);
+    }
+}
+
+#[cfg(test)]
+mod test_textured_light {
+    use super::super::*;
+    use super::super::shape::ShapedPrimitive;
+    use lighting::SampleInfo;
+    use spectrum::RGBSpectrumf;
+    use test_utils;
+    use rand::{Rng, SeedableRng, StdRng};
+
+    // emits 1 over the `+y` half of a sphere and 0.5 over the other
+    struct Halves;
+
+    impl Texture for Halves {
+        type Texel = RGBSpectrumf;
+
+        fn evaluate(&self, si: &SurfaceInteraction, _dxy: &DxyInfo) -> RGBSpectrumf {
+            RGBSpectrumf::grey_scale(if si.uv.x < 0.5 as Float { 1. as Float } else { 0.5 as Float })
+        }
+
+        fn mean(&self) -> RGBSpectrumf {
+            RGBSpectrumf::grey_scale(0.75 as Float)
+        }
+    }
+
+    fn light() -> ShapedPrimitive<Sphere, Arc<Material>> {
+        ShapedPrimitive::new(
+            Sphere::new(0.5 as Float, -0.5 as Float, 0.5 as Float, float::pi() * 2. as Float),
+            test_utils::matte(0. as Float, 0. as Float, 0. as Float),
+            Some(Arc::new(Halves))
+        )
+    }
+
+    // irradiance at `pos` on a surface facing the sphere's center
+    fn irradiance(light: &Light, pos: Point3f) -> Float {
+        let mut rng = StdRng::from_seed(&[15usize][..]);
+        let norm = (Point3f::new(0. as Float, 0., 0.) - pos).normalize();
+        let n = 20000;
+        let mut sum = 0. as Float;
+        for _ in 0..n {
+            let ls = light.evaluate_sampled(pos, Point2f::new(rng.gen(), rng.gen()));
+            if ls.pdf > 0. as Float {
+                sum += ls.radiance.g() * ls.wi().dot(norm).abs() / ls.pdf;
+            }
+        }
+        sum / n as Float
+    }
+
+    #[test]
+    fn test_sampled_radiance() {
+        let light = light();
+        // a uniform unit-radiance sphere yields $\pi(r/d)^2$,
+        // and only a single half is visible from either side
+        let uniform = float::pi() / 16. as Float;
+        let front = irradiance(&light, Point3f::new(0. as Float, 2., 0.));
+        let back = irradiance(&light, Point3f::new(0. as Float, -2., 0.));
+        assert_relative_eq!(front, uniform, max_relative = 0.03);
+        assert_relative_eq!(back, uniform * 0.5 as Float, max_relative = 0.03);
+        assert_relative_eq!(
+            light.power().g(),
+            0.75 as Float * light.shape.surface_area() * float::pi(),
+            max_relative = 1e-4
+        );
+    }
+
+    #[test]
+    fn test_emitted_paths() {
+        let light = light();
+        let mut rng = StdRng::from_seed(&[16usize][..]);
+        for _ in 0..256 {
+            let path = light.generate_path(SampleInfo{
+                pfilm: Point2f::new(rng.gen(), rng.gen()),
+                plens: Point2f::new(rng.gen(), rng.gen()),
+                time: 0. as Float,
+            });
+            let pos = path.ray.origin();
+            if pos.y.abs() < 1e-3 as Float { continue; }
+            let expected = if pos.y > 0. as Float { 1. as Float } else { 0.5 as Float };
+            assert_relative_eq!(path.radiance.g(), expected);
+            // the same radiance is seen by rays hitting the point
+            assert_relative_eq!(light.evaluate_path(pos, path.ray.direction()).g(), expected);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_light_pdf {
+    use super::super::*;
+    use super::super::shape::ShapedPrimitive;
+    use super::super::transformed::TransformedComposable;
+    use lighting::SampleInfo;
+    use spectrum::RGBSpectrumf;
+    use texturing::prelude::ConstantTexture;
+    use test_utils;
+    use tobj;
+    use rand::{Rng, SeedableRng, StdRng};
+
+    fn light() -> ShapedPrimitive<Sphere, Arc<Material>> {
+        ShapedPrimitive::new(
+            Sphere::full(1. as Float),
+            test_utils::matte(0. as Float, 0. as Float, 0. as Float),
+            Some(Arc::new(ConstantTexture{value: RGBSpectrumf::new(1. as Float, 1., 1.)}))
+        )
+    }
+
+    // sampled pdfs agree with the queried ones for the same directions
+    fn check(light: &Light, pos: Point3f) {
+        let mut rng = StdRng::from_seed(&[17usize][..]);
+        for _ in 0..1024 {
+            let ls = light.evaluate_sampled(pos, Point2f::new(rng.gen(), rng.gen()));
+            assert!(ls.pdf > 0. as Float);
+            assert_relative_eq!(ls.pdf, light.pdf(pos, ls.wi()), max_relative = 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_sphere_light() {
+        let light = light();
+        let pos = Point3f::new(0. as Float, 0., 5.);
+        check(&light, pos);
+        // uniform over the subtended cone
+        let cos_max = (1. as Float - 1. as Float / 25. as Float).sqrt();
+        let expected = 1. as Float / (2. as Float * float::pi() * (1. as Float - cos_max));
+        assert_relative_eq!(light.pdf(pos, Vector3f::new(0. as Float, 0., -1.)), expected, max_relative = 1e-5);
+        assert_eq!(light.pdf(pos, Vector3f::new(0. as Float, 0., 1.)), 0. as Float);
+    }
+
+    #[test]
+    fn test_transformed_sphere_light() {
+        let m = Matrix4f::from_translation(Vector3f::new(1. as Float, 2., 3.))
+            * Matrix4f::from(Matrix3f::from_angle_y(Rad(0.7 as Float)));
+        let light = TransformedComposable::new(light(), Arc::new(m), Arc::new(m.invert().unwrap()));
+        check(&light, Point3f::new(1. as Float, 2., 8.));
+        check(&light, Point3f::new(4. as Float, 6., 3.));
+    }
+
+    // a unit quad tilted around the y axis, emitting uniformly
+    fn tilted_quad(transform: Matrix4f) -> MeshLight {
+        let model = tobj::Model::new(
+            tobj::Mesh::new(
+                vec![0., 0., 0., 1., 0., 1., 1., 1., 1., 0., 1., 0.],
+                Vec::new(),
+                vec![0., 0., 1., 0., 1., 1., 0., 1.],
+                vec![0, 1, 2, 0, 2, 3],
+                None
+            ),
+            "quad".to_owned()
+        );
+        MeshLight::new(Arc::new(TriangleMesh::from_model_transformed(
+            model, transform, test_utils::matte(0. as Float, 0. as Float, 0. as Float),
+            Some(Arc::new(ConstantTexture{value: RGBSpectrumf::new(1. as Float, 1., 1.)}))
+        )))
+    }
+
+    #[test]
+    fn test_nonuniform_scale_power() {
+        let m = Matrix4f::from_translation(Vector3f::new(1. as Float, 2., 3.))
+            * Matrix4f::from_nonuniform_scale(2. as Float, 1., 1.);
+        let authored = tilted_quad(m);
+        let unscaled: Arc<Light> = Arc::new(tilted_quad(Matrix4f::identity()));
+        let scaled = TransformedComposable::new(unscaled.clone(), Arc::new(m), Arc::new(m.invert().unwrap()));
+        assert!(authored.power().g() > unscaled.power().g() * 1.5 as Float);
+        assert_relative_eq!(scaled.power().g(), authored.power().g(), max_relative = 1e-4);
+
+        // area densities of emitted paths follow
+        let mut rng = StdRng::from_seed(&[11usize][..]);
+        for _ in 0..64 {
+            let samples = SampleInfo{
+                pfilm: Point2f::new(rng.gen(), rng.gen()),
+                plens: Point2f::new(rng.gen(), rng.gen()),
+                time: 0. as Float,
+            };
+            let (a, b) = (authored.generate_path(samples), scaled.generate_path(samples));
+            assert_relative_eq!(a.pdfpos, b.pdfpos, max_relative = 1e-4);
+            let (pdfpos, pdfdir) = scaled.pdf_path(b.ray.origin(), b.ray.direction(), b.normal);
+            assert_relative_eq!(pdfpos, b.pdfpos, max_relative = 1e-4);
+            assert_relative_eq!(pdfdir, b.pdfdir, max_relative = 1e-4);
+        }
+
+        // solid angle densities match the authored light's, on either
+        // side of the plane
+        let mut hits = 0;
+        for &side in &[1. as Float, -1. as Float] {
+            let pos = Point3f::new(2. as Float, 2.5, 3.5) + Vector3f::new(1. as Float, 0., -2.) * side;
+            for _ in 0..64 {
+                let ls = scaled.evaluate_sampled(pos, Point2f::new(rng.gen(), rng.gen()));
+                if ls.pdf == 0. as Float { continue; }
+                hits += 1;
+                assert_relative_eq!(ls.pdf, authored.pdf(pos, ls.wi()), max_relative = 1e-3);
+                assert_relative_eq!(ls.pdf, scaled.pdf(pos, ls.wi()), max_relative = 1e-3);
+            }
+        }
+        assert!(hits > 0);
+
+        // a sphere stretched into a prolate spheroid, of area
+        // $2\pi(1 + \frac{a}{e}\arcsin e)$ with $e = \sqrt{1 - 1/a^2}$
+        let sphere = TransformedComposable::new(light(), Arc::new(m), Arc::new(m.invert().unwrap()));
+        let e = (0.75 as Float).sqrt();
+        let area = 2. as Float * float::pi() * (1. as Float + 2. as Float / e * e.asin());
+        assert_relative_eq!(sphere.power().g(), area * float::pi(), max_relative = 1e-2);
+        check(&sphere, Point3f::new(1. as Float, 2., 8.));
+        check(&sphere, Point3f::new(6. as Float, 2., 3.));
+    }
+
+    #[test]
+    fn test_area_conversion() {
+        use sample::{area_to_solid_angle, solid_angle_to_area};
+        let pdf = area_to_solid_angle(0.25 as Float, 4. as Float, -0.5 as Float);
+        assert_relative_eq!(pdf, 2. as Float);
+        assert_relative_eq!(solid_angle_to_area(pdf, 4. as Float, 0.5 as Float), 0.25 as Float);
+        assert_eq!(area_to_solid_angle(1. as Float, 1. as Float, 0. as Float), 0. as Float);
+    }
+}
+
+#[cfg(test)]
+mod test_bvh_cache {
+    use super::super::*;
+    use super::super::bvh::{BVH, BVHStrategy};
+    use std::env;
+    use std::fs;
+    use std::ptr;
+    use std::io::Write;
+    use rand::{Rng, SeedableRng, StdRng};
+
+    // two uv spheres as separate objects, the first one's north pole at `z`
+    fn write_obj(path: &Path, z: Float) {
+        let mut source = String::new();
+        let (nphi, ntheta) = (12, 6);
+        for (o, &cx) in [-1.5 as Float, 1.5].iter().enumerate() {
+            source.push_str(&format!("o sphere{}\n", o));
+            let base = o * (nphi * (ntheta + 1));
+            for j in 0..ntheta+1 {
+                let theta = float::pi() * j as Float / ntheta as Float;
+                for i in 0..nphi {
+                    let phi = float::pi() * 2. as Float * i as Float / nphi as Float;
+                    let pz = if o == 0 && j == 0 { z } else { theta.cos() };
+                    source.push_str(&format!(
+                        "v {} {} {}\n", cx + theta.sin() * phi.cos(), theta.sin() * phi.sin(), pz
+                    ));
+                }
+            }
+            for j in 0..ntheta {
+                for i in 0..nphi {
+                    let v = base + j * nphi + i + 1;
+                    let w = base + j * nphi + (i + 1) % nphi + 1;
+                    source.push_str(&format!("f {} {} {}\nf {} {} {}\n", v, v + nphi, w + nphi, v, w + nphi, w));
+                }
+            }
+        }
+        fs::File::create(path).unwrap().write_all(source.as_bytes()).unwrap();
+    }
+
+    // distinct meshes of the loaded triangles, in order
+    fn meshes(path: &Path) -> Vec<Arc<TriangleMesh>> {
+        let mut ret: Vec<Arc<TriangleMesh>> = Vec::new();
+        for c in load_obj(path, Matrix4f::identity()).unwrap() {
+            if let ComponentPointer::Triangle(t) = c {
+                if !ret.iter().any(|m| ptr::eq(&**m, &**t.mesh())) {
+                    ret.push(t.mesh().clone());
+                }
+            }
+        }
+        ret
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let obj = env::temp_dir().join("arendur_test_bvh_cache.obj");
+        let cache = env::temp_dir().join("arendur_test_bvh_cache.bvh");
+        let _ = fs::remove_file(&cache);
+        write_obj(&obj, 1. as Float);
+        let original = meshes(&obj);
+        assert_eq!(original.len(), 2);
+        let built = BVH::load_or_build(&cache, &original, BVHStrategy::SAH);
+        let loaded = BVH::load(&cache, &original).unwrap();
+        assert_eq!(loaded.node_count(), built.node_count());
+        let mut rng = StdRng::from_seed(&[21usize][..]);
+        let mut hits = 0;
+        for _ in 0..1024 {
+            let origin = Point3f::new(
+                rng.gen_range(-4. as Float, 4.), rng.gen_range(-4. as Float, 4.), rng.gen_range(-4. as Float, 4.)
+            );
+            let target = Point3f::new(
+                rng.gen_range(-3. as Float, 3.), rng.gen_range(-1. as Float, 1.), rng.gen_range(-1. as Float, 1.)
+            );
+            let ray = RawRay::from_od(origin, target - origin);
+            let (mut a, mut b) = (ray, ray);
+            let ha = built.intersect_ray(&mut a).map(|si| si.basic.pos);
+            let hb = loaded.intersect_ray(&mut b).map(|si| si.basic.pos);
+            assert_eq!(ha, hb);
+            assert_eq!(a.max_extend(), b.max_extend());
+            if ha.is_some() { hits += 1; }
+        }
+        assert!(hits > 0);
+
+        // the same geometry reloaded still matches
+        assert!(BVH::load(&cache, &meshes(&obj)).is_some());
+        // moving a vertex makes the cache stale, which gets rebuilt
+        write_obj(&obj, 1.5 as Float);
+        let moved = meshes(&obj);
+        assert!(BVH::load(&cache, &moved).is_none());
+        let rebuilt = BVH::load_or_build(&cache, &moved, BVHStrategy::SAH);
+        assert!(rebuilt.bbox_parent().pmax.z > 1.4 as Float);
+        assert!(BVH::load(&cache, &moved).is_some());
+        // as does a different set of meshes
+        assert!(BVH::load(&cache, &moved[..1]).is_none());
+        let _ = fs::remove_file(&obj);
+        let _ = fs::remove_file(&cache);
+    }
+}
+
+#[cfg(test)]
+mod test_occluded_batch {
+    use super::super::*;
+    use super::super::bvh::{BVH, BVHStrategy};
+    use super::super::shape::ShapedPrimitive;
+    use super::super::transformed::TransformedComposable;
+    use sample;
+    use test_utils;
+    use rand::{Rng, SeedableRng, StdRng};
+    use std::time::Instant;
+
+    // a room of `n` random spheres inside the cornell box, lit by its
+    // ceiling light
+    fn room(n: usize, rng: &mut StdRng) -> BVH {
+        let box_scene = test_utils::cornell_box(1);
+        let mut components: Vec<ComponentPointer> = vec![box_scene.scene.aggregate.clone().into()];
+        for _ in 0..n {
+            let center = Vector3f::new(rng.gen(), rng.gen(), rng.gen()) * 1.8 as Float
+                - Vector3f::new(0.9 as Float, 0.9, 0.9);
+            let m = Matrix4f::from_translation(center);
+            let sphere = ShapedPrimitive::new(
+                Sphere::full(0.02 as Float + 0.08 as Float * rng.gen::<Float>()),
+                test_utils::matte(0.5 as Float, 0.5 as Float, 0.5 as Float), None
+            );
+            let sphere = TransformedComposable::new(sphere, Arc::new(m), Arc::new(m.invert().unwrap()));
+            let sphere: Arc<Composable> = Arc::new(sphere);
+            components.push(sphere.into());
+        }
+        BVH::new(&components, BVHStrategy::SAH)
+    }
+
+    fn point(rng: &mut StdRng) -> Point3f {
+        Point3f::new(rng.gen(), rng.gen(), rng.gen()) * 1.96 as Float + Vector3f::new(-0.98 as Float, -0.98, -0.98)
+    }
+
+    // `per_point` segments from each of `n` random points to random
+    // points on the ceiling light
+    fn shadow_rays(n: usize, per_point: usize, rng: &mut StdRng) -> Vec<RawRay> {
+        let mut ret = Vec::with_capacity(n * per_point);
+        for _ in 0..n {
+            let from = point(rng);
+            for _ in 0..per_point {
+                let to = Point3f::new(
+                    0.5 as Float * rng.gen::<Float>() - 0.25 as Float, 0.97,
+                    0.5 as Float * rng.gen::<Float>() - 0.25 as Float
+                );
+                ret.push(RawRay::spawn(from, to).with_kind(RayKind::Shadow));
+            }
+        }
+        ret
+    }
+
+    #[test]
+    fn test_batch_matches_scalar() {
+        let mut rng = StdRng::from_seed(&[7usize][..]);
+        let bvh = room(200, &mut rng);
+        let mut rays = shadow_rays(256, 4, &mut rng);
+        // unbounded rays of every kind, leaving the room or not
+        for &kind in &[RayKind::Any, RayKind::Camera, RayKind::DiffuseIndirect] {
+            for _ in 0..256 {
+                let dir = sample::sample_uniform_sphere(Point2f::new(rng.gen(), rng.gen()));
+                let origin = point(&mut rng) * if rng.gen() { 1. as Float } else { 3. as Float };
+                rays.push(RawRay::from_od(origin, dir).with_kind(kind));
+            }
+        }
+        let mut results = vec![false; rays.len()];
+        bvh.occluded_batch(&mut rays, &mut results);
+        let mut hits = 0;
+        for (ray, &result) in rays.iter().zip(results.iter()) {
+            assert_eq!(result, bvh.intersect_ray(&mut ray.clone()).is_some(), "{:?}", ray);
+            assert_eq!(result, bvh.can_intersect(ray));
+            if result { hits += 1; }
+        }
+        // both outcomes are exercised
+        assert!(hits > 0 && hits < rays.len());
+        let bvh: Arc<Composable> = Arc::new(bvh);
+        let pointer: ComponentPointer = bvh.into();
+        let mut forwarded = vec![false; rays.len()];
+        pointer.occluded_batch(&mut rays, &mut forwarded);
+        assert_eq!(results, forwarded);
+    }
+
+    // run with `cargo test --release -- --ignored bench_shadow_batch --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_shadow_batch() {
+        let mut rng = StdRng::from_seed(&[7usize][..]);
+        let bvh = room(2000, &mut rng);
+        let mut rays = shadow_rays(1 << 16, 4, &mut rng);
+        let n = rays.len() as f64;
+        let rate = |start: Instant| {
+            let elapsed = start.elapsed();
+            let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+            n / secs * 1e-6
+        };
+        let start = Instant::now();
+        let nearest: Vec<bool> = rays.iter().map(|ray| bvh.intersect_ray(&mut ray.clone()).is_some()).collect();
+        println!("nearest hit: {:.2} Mrays/s", rate(start));
+        let start = Instant::now();
+        let scalar: Vec<bool> = rays.iter().map(|ray| bvh.can_intersect(ray)).collect();
+        println!("any hit: {:.2} Mrays/s", rate(start));
+        let mut batched = vec![false; rays.len()];
+        // each vertex's rays are batched on their own, as direct lighting does
+        let start = Instant::now();
+        for (rays, results) in rays.chunks_mut(4).zip(batched.chunks_mut(4)) {
+            bvh.occluded_batch(rays, results);
+        }
+        println!("batched by vertex: {:.2} Mrays/s", rate(start));
+        let start = Instant::now();
+        bvh.occluded_batch(&mut rays, &mut batched);
+        println!("batched as a whole: {:.2} Mrays/s", rate(start));
+        assert_eq!(nearest, scalar);
+        assert_eq!(scalar, batched);
+    }
+}
+
+#[cfg(test)]
+mod test_group {
+    use super::super::*;
+    use super::super::group::Group;
+    use test_utils;
+    use rand::{Rng, SeedableRng, StdRng};
+
+    // a downward-facing square lamp of side 0.5 and unit radiance at height 1
+    fn lamp() -> (Vec<ComponentPointer>, Vec<Arc<Light>>) {
+        let h = 0.25 as Float;
+        let mesh = test_utils::quad([
+            Point3f::new(-h, 1., -h), Point3f::new(h, 1., -h),
+            Point3f::new(h, 1., h), Point3f::new(-h, 1., h),
+        ], test_utils::matte(0. as Float, 0. as Float, 0. as Float), Some(RGBSpectrumf::new(1. as Float, 1., 1.)));
+        let light: Arc<Light> = Arc::new(MeshLight::new(mesh.clone()));
+        let children = TriangleMesh::instances(&mesh).into_iter().map(|t| t.into()).collect();
+        (children, vec![light])
+    }
+
+    // irradiance on an upward-facing receiver at `pos`
+    fn irradiance(light: &Light, pos: Point3f) -> Float {
+        let mut rng = StdRng::from_seed(&[7usize][..]);
+        let n = 4096;
+        let mut sum = 0. as Float;
+        for _ in 0..n {
+            let ls = light.evaluate_sampled(pos, Point2f::new(rng.gen(), rng.gen()));
+            if ls.pdf > 0. as Float {
+                sum += ls.radiance.to_xyz().y * ls.wi().y.max(0. as Float) / ls.pdf;
+            }
+        }
+        sum / n as Float
+    }
+
+    #[test]
+    fn test_moved_lamp() {
+        let (children, lights) = lamp();
+        let original = Group::new(children.clone(), lights.clone());
+        let mut inner = Group::new(children, lights);
+        let t = Matrix4f::from_translation(Vector3f::new(1. as Float, 0., 0.));
+        inner.set_transform(Arc::new(t), Arc::new(t.invert().unwrap()));
+        let inner_lights = inner.collect_lights(&Matrix4f::identity());
+        let inner: Arc<Composable> = Arc::new(inner);
+        let mut outer = Group::new(vec![inner.into()], inner_lights);
+        let m = Matrix4f::from_translation(Vector3f::new(1. as Float, 0., 2.))
+            * Matrix4f::from(Matrix3f::from_angle_y(Rad(0.7 as Float)));
+        outer.set_transform(Arc::new(m), Arc::new(m.invert().unwrap()));
+        let lights = outer.collect_lights(&Matrix4f::identity());
+        assert_eq!(lights.len(), 1);
+        let total = m * t;
+        let origin = Point3f::new(0. as Float, 0., 0.);
+        let center = total.transform_point(origin);
+        let up = Vector3f::new(0. as Float, 1., 0.);
+
+        // the lamp is hit where it's moved to, still emitting
+        let mut ray = RawRay::from_od(center, up);
+        let si = outer.intersect_ray(&mut ray).expect("moved lamp missed");
+        assert_relative_eq!(si.basic.pos.y, 1. as Float, epsilon = 1e-4);
+        assert_relative_eq!(si.le(-up).to_xyz().y, 1. as Float, epsilon = 1e-4);
+        assert!(!outer.can_intersect(&RawRay::from_od(origin, up)));
+
+        // and lights its surroundings as before
+        let original_light = original.collect_lights(&Matrix4f::identity())[0].clone();
+        for &(x, z) in &[(0. as Float, 0. as Float), (0.3, -0.2), (1.5, 0.5)] {
+            let p = Point3f::new(x, 0., z);
+            let expected = irradiance(&*original_light, p);
+            assert!(expected > 0. as Float);
+            assert_relative_eq!(irradiance(&*lights[0], total.transform_point(p)), expected, max_relative = 1e-3);
+        }
+        assert!(irradiance(&*lights[0], origin) < 0.1 as Float * irradiance(&*lights[0], center));
+    }
+}
+
+#[cfg(test)]
+mod test_cost {
+    use super::super::*;
+    use super::super::bvh::{BVH, BVHStrategy};
+    use super::super::cost::{CostModel, calibrate_costs};
+    use super::super::shape::ShapedPrimitive;
+    use super::super::transformed::TransformedComposable;
+    use test_utils;
+    use rand::{Rng, SeedableRng, StdRng};
+    use std::time::Instant;
+
+    // a unit grid of `n` by `n` quads on the xy plane
+    fn grid(n: usize) -> Arc<TriangleMesh> {
+        let mut positions = Vec::with_capacity((n+1)*(n+1)*3);
+        for j in 0..n+1 {
+            for i in 0..n+1 {
+                positions.push(i as f32 / n as f32);
+                positions.push(j as f32 / n as f32);
+                positions.push(0.);
+            }
+        }
+        let mut indices = Vec::with_capacity(n*n*6);
+        for j in 0..n {
+            for i in 0..n {
+                let v = (j*(n+1) + i) as u32;
+                let w = v + n as u32 + 1;
+                indices.extend_from_slice(&[v, v+1, w, v+1, w+1, w]);
+            }
+        }
+        let model = tobj::Model::new(
+            tobj::Mesh::new(positions, Vec::new(), Vec::new(), indices, None),
+            "grid".to_owned()
+        );
+        Arc::new(TriangleMesh::from_model(model, test_utils::matte(0.5 as Float, 0.5 as Float, 0.5 as Float), None))
+    }
+
+    fn translated(inner: Arc<Composable>, t: Vector3f) -> ComponentPointer {
+        let m = Matrix4f::from_translation(t);
+        let ret: Arc<Composable> = Arc::new(TransformedComposable::new(
+            inner, Arc::new(m), Arc::new(m.invert().unwrap())
+        ));
+        ret.into()
+    }
+
+    fn position(rng: &mut StdRng) -> Vector3f {
+        Vector3f::new(rng.gen(), rng.gen(), rng.gen()) * 10. as Float
+    }
+
+    // a few instances of a dense mesh among spheres. Hierarchies were
+    // taken to cost `log2` of their node count, far above their worth
+    // with flat meshes.
+    fn mixed_scene() -> Vec<ComponentPointer> {
+        let mut rng = StdRng::from_seed(&[7usize][..]);
+        let mut components: Vec<ComponentPointer> = Vec::new();
+        let dense = grid(128);
+        let dense: Arc<Composable> = Arc::new(BVH::new(
+            &TriangleMesh::instances(&dense).map(ComponentPointer::from).collect::<Vec<_>>(),
+            BVHStrategy::SAH
+        ));
+        for _ in 0..4 {
+            components.push(translated(dense.clone(), position(&mut rng)));
+        }
+        let sphere: Arc<Composable> = Arc::new(ShapedPrimitive::new(
+            Sphere::full(0.2 as Float), test_utils::matte(0.5 as Float, 0.5 as Float, 0.5 as Float), None
+        ));
+        for _ in 0..100 {
+            components.push(translated(sphere.clone(), position(&mut rng)));
+        }
+        components
+    }
+
+    fn rays(n: usize) -> Vec<RawRay> {
+        let mut rng = StdRng::from_seed(&[11usize][..]);
+        (0..n).map(|_| {
+            let origin = Point3f::from_vec(position(&mut rng));
+            let target = Point3f::from_vec(position(&mut rng));
+            RawRay::from_od(origin, target - origin)
+        }).collect()
+    }
+
+    #[test]
+    fn test_mixed_scene() {
+        let components = mixed_scene();
+        let costs = CostModel::default();
+        let before = BVH::with_costs(&components, BVHStrategy::SAH, &CostModel::uncalibrated());
+        let after = BVH::with_costs(&components, BVHStrategy::SAH, &costs);
+        let (sah_before, sah_after) = (before.validate(&costs).unwrap(), after.validate(&costs).unwrap());
+        assert!(sah_after < sah_before, "{} vs {}", sah_after, sah_before);
+        // same hits either way
+        for ray in rays(1000) {
+            let (mut a, mut b) = (ray.clone(), ray.clone());
+            assert_eq!(before.intersect_ray(&mut a).is_some(), after.intersect_ray(&mut b).is_some());
+            assert_eq!(a.max_extend(), b.max_extend());
+        }
+    }
+
+    // run with `cargo test --release -- --ignored bench_mixed_traversal --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_mixed_traversal() {
+        let components = mixed_scene();
+        let rays = rays(1 << 18);
+        let time = |bvh: &BVH| {
+            let start = Instant::now();
+            let hits = rays.iter().filter(|ray| bvh.intersect_ray(&mut (*ray).clone()).is_some()).count();
+            let elapsed = start.elapsed();
+            (elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9, hits)
+        };
+        let before = BVH::with_costs(&components, BVHStrategy::SAH, &CostModel::uncalibrated());
+        let after = BVH::with_costs(&components, BVHStrategy::SAH, &CostModel::default());
+        let (t_before, hits_before) = time(&before);
+        let (t_after, hits_after) = time(&after);
+        assert_eq!(hits_before, hits_after);
+        println!("uncalibrated: {:.4}s, default: {:.4}s", t_before, t_after);
+        assert!(t_after < t_before * 1.05);
+    }
+
+    // run with `cargo test --release -- --ignored bench_calibrate_costs --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_calibrate_costs() {
+        for _ in 0..3 {
+            println!("{:?}", calibrate_costs(1 << 20));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_progress {
+    use super::super::*;
+    use super::super::bvh::{BVH, BVHStrategy};
+    use super::super::progress::{LoadPhase, LoadProgress};
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use image;
+
+    // two grids of quads as separate objects, the first one textured
+    fn write_scene(dir: &Path) {
+        let mut source = String::from("mtllib scene.mtl\n");
+        let n = 16;
+        for o in 0..2 {
+            source.push_str(&format!("o grid{}\n", o));
+            if o == 0 { source.push_str("usemtl textured\n"); }
+            let base = o * (n + 1) * (n + 1);
+            for j in 0..n+1 {
+                for i in 0..n+1 {
+                    source.push_str(&format!("v {} {} {}\n", i, j, o));
+                }
+            }
+            for j in 0..n {
+                for i in 0..n {
+                    let v = base + j * (n + 1) + i + 1;
+                    source.push_str(&format!("f {} {} {} {}\n", v, v + 1, v + n + 2, v + n + 1));
+                }
+            }
+        }
+        fs::File::create(dir.join("scene.obj")).unwrap().write_all(source.as_bytes()).unwrap();
+        fs::File::create(dir.join("scene.mtl")).unwrap()
+            .write_all(b"newmtl textured\nKd 1 1 1\nmap_Kd checker.png\n").unwrap();
+        image::RgbImage::from_fn(16, 8, |x, y| {
+            image::Rgb{data: if (x + y) % 2 == 0 { [255, 255, 255] } else { [0, 0, 0] }}
+        }).save(dir.join("checker.png")).unwrap();
+    }
+
+    // the last report of `phase`, checking that those before it
+    // never went backwards
+    fn last_of(reports: &[LoadProgress], phase: LoadPhase) -> LoadProgress {
+        let reports: Vec<_> = reports.iter().filter(|p| p.phase == phase).collect();
+        assert!(!reports.is_empty(), "no reports of {:?}", phase);
+        for w in reports.windows(2) {
+            assert!(w[0].done <= w[1].done && w[0].bytes <= w[1].bytes, "{:?} went backwards", phase);
+            assert_eq!(w[0].total, w[1].total);
+        }
+        let last = *reports[reports.len() - 1];
+        assert!(last.is_complete() && last.done == last.total, "{:?} incomplete", phase);
+        last
+    }
+
+    #[test]
+    fn test_phases_complete() {
+        let dir = env::temp_dir().join("arendur_test_progress");
+        let _ = fs::create_dir(&dir);
+        write_scene(&dir);
+        let obj = dir.join("scene.obj");
+        let size = fs::metadata(&obj).unwrap().len();
+
+        let mut reports = Vec::new();
+        let (shapes, _) = load_obj_with_progress(
+            &obj, Matrix4f::identity(), ObjOptions::default(), &mut |p| reports.push(p)
+        ).unwrap();
+        assert_eq!(last_of(&reports, LoadPhase::Parsing).bytes, size);
+        assert_eq!(last_of(&reports, LoadPhase::TextureLoad).total, 1);
+        assert_eq!(last_of(&reports, LoadPhase::MeshBuild).total, 2);
+        // the phases follow one another
+        let phases: Vec<_> = reports.windows(2).filter(|w| w[0].phase != w[1].phase)
+            .map(|w| w[1].phase).collect();
+        assert_eq!(phases, vec![LoadPhase::TextureLoad, LoadPhase::MeshBuild]);
+
+        let mut reports = Vec::new();
+        BVH::with_progress(&shapes, BVHStrategy::SAH, &CostModel::default(), &mut |p| reports.push(p));
+        assert_eq!(last_of(&reports, LoadPhase::BVHBuild).total, shapes.len());
+        let counter = AtomicUsize::new(0);
+        BVH::with_counter(&shapes, BVHStrategy::MiddleCount, &CostModel::default(), &counter);
+        assert_eq!(counter.load(Ordering::Relaxed), shapes.len());
+
+        let mut reports = Vec::new();
+        let info = image_info(&dir, "checker.png");
+        let mipmap = MipMap::<Float, RGBSpectrumf>::new_with_progress(info, &mut |p| reports.push(p)).unwrap();
+        assert_eq!(last_of(&reports, LoadPhase::TextureLoad).total, mipmap.levels());
+
+        let mut reports = Vec::new();
+        let mesh = TriangleMesh::load_obj_streaming_with_progress(
+            &obj, Matrix4f::identity(), Arc::new(MatteMaterial::new(
+                Arc::new(ConstantTexture{value: RGBSpectrumf::new(0.5 as Float, 0.5 as Float, 0.5 as Float)}),
+                Arc::new(ConstantTexture{value: 0. as Float}), None
+            )), None, &mut |p| reports.push(p)
+        ).unwrap();
+        assert_eq!(last_of(&reports, LoadPhase::Parsing).bytes, 2 * size);
+        assert_eq!(mesh.triangle_count(), shapes.len());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
+
+#[cfg(test)]
+mod test_ray_min_extend {
+    use super::super::*;
+    use super::super::shape::ShapedPrimitive;
+    use super::super::bvh::{BVH, BVHStrategy};
+    use super::super::transformed::TransformedComposable;
+    use shape::triangle::TriangleMesh;
+    use test_utils;
+
+    // a sphere around `z = 1` and a quad at `z = 2`, both facing the origin
+    fn occluders() -> Vec<BVH> {
+        let sphere: Arc<Composable> = Arc::new(ShapedPrimitive::new(
+            Sphere::full(0.2 as Float), test_utils::matte(0.5 as Float, 0.5, 0.5), None
+        ));
+        let sphere = TransformedComposable::new(
+            sphere,
+            Arc::new(Matrix4f::from_translation(Vector3f::new(0. as Float, 0., 1.))),
+            Arc::new(Matrix4f::from_translation(Vector3f::new(0. as Float, 0., -1.)))
+        );
+        let sphere: Arc<Composable> = Arc::new(sphere);
+        let p = |x: Float, y: Float| Point3f::new(x, y, 2. as Float);
+        let quad = test_utils::quad(
+            [p(-1., -1.), p(1., -1.), p(1., 1.), p(-1., 1.)], test_utils::matte(0.5 as Float, 0.5, 0.5), None
+        );
+        let triangles: Vec<ComponentPointer> = TriangleMesh::instances(&quad).map(|t| t.into()).collect();
+        vec![
+            BVH::new(&[sphere.into()], BVHStrategy::SAH),
+            BVH::new(&triangles, BVHStrategy::SAH),
+        ]
+    }
+
+    #[test]
+    fn test_shadow_min_extend() {
+        let origin = Point3f::new(0. as Float, 0., 0.);
+        let shadow = RawRay::spawn(origin, Point3f::new(0. as Float, 0., 3.));
+        for (bvh, distance) in occluders().iter().zip(&[0.8 as Float, 2.]) {
+            assert!(bvh.can_intersect(&shadow));
+            assert!(!bvh.can_intersect(&shadow.with_min_extend(distance + 0.5 as Float)));
+            let mut ray = shadow;
+            let si = bvh.intersect_ray(&mut ray).unwrap();
+            assert_relative_eq!(ray.max_extend(), *distance, max_relative = 1e-4);
+            assert_relative_eq!(si.basic.pos.z, *distance, max_relative = 1e-4);
+            let mut beyond = shadow.with_min_extend(distance + 0.5 as Float);
+            assert!(bvh.intersect_ray(&mut beyond).is_none());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_grid {
+    use super::super::*;
+    use super::super::bvh::{BVH, BVHStrategy};
+    use super::super::grid::UniformGrid;
+    use super::super::naive::Naive;
+    use super::super::shape::ShapedPrimitive;
+    use super::super::transformed::TransformedComposable;
+    use sample;
+    use test_utils;
+    use rand::{Rng, SeedableRng, StdRng};
+    use std::time::Instant;
+
+    // `n` random spheres within the unit cube, with radii in `[r, 2r)`
+    fn spheres(n: usize, r: Float, rng: &mut StdRng) -> (Vec<Point3f>, Vec<Float>) {
+        let positions = (0..n).map(|_| Point3f::new(rng.gen(), rng.gen(), rng.gen())).collect();
+        let radii = (0..n).map(|_| r * (1. as Float + rng.gen::<Float>())).collect();
+        (positions, radii)
+    }
+
+    fn naive(positions: &[Point3f], radii: &[Float]) -> Naive {
+        Naive::new(positions.iter().zip(radii).map(|(&p, &r)| {
+            let m = Matrix4f::from_translation(p.to_vec());
+            let sphere = ShapedPrimitive::new(
+                Sphere::full(r), test_utils::matte(0.5 as Float, 0.5 as Float, 0.5 as Float), None
+            );
+            let sphere: Arc<Composable> = Arc::new(
+                TransformedComposable::new(sphere, Arc::new(m), Arc::new(m.invert().unwrap()))
+            );
+            sphere
+        }).collect())
+    }
+
+    // rays from within and around the unit cube, some along the axes
+    // and some cut short
+    fn rays(n: usize, rng: &mut StdRng) -> Vec<RawRay> {
+        (0..n).map(|i| {
+            let origin = Point3f::new(rng.gen(), rng.gen(), rng.gen()) * 3. as Float
+                + Vector3f::new(-1. as Float, -1. as Float, -1. as Float);
+            let mut dir = sample::sample_uniform_sphere(Point2f::new(rng.gen(), rng.gen()));
+            if i % 7 == 0 { dir.x = 0. as Float; }
+            if i % 11 == 0 { dir.y = 0. as Float; dir.z = 0. as Float; }
+            if dir.magnitude2() == 0. as Float { dir.x = 1. as Float; }
+            let ray = RawRay::from_od(origin, dir.normalize());
+            if i % 3 == 0 {
+                RawRay::new(ray.origin(), ray.direction(), rng.gen::<Float>() * 2. as Float)
+            } else {
+                ray
+            }
+        }).collect()
+    }
+
+    #[test]
+    fn test_matches_naive() {
+        let mut rng = StdRng::from_seed(&[7usize][..]);
+        // small spheres, along with a few spanning many cells
+        let (mut positions, mut radii) = spheres(500, 0.02 as Float, &mut rng);
+        let (big, big_radii) = spheres(5, 0.2 as Float, &mut rng);
+        positions.extend(big);
+        radii.extend(big_radii);
+        let grid = UniformGrid::from_spheres(
+            &positions, &radii, test_utils::matte(0.5 as Float, 0.5 as Float, 0.5 as Float)
+        );
+        assert!(grid.resolution().iter().all(|&r| r > 8));
+        let naive = naive(&positions, &radii);
+        assert_relative_eq!(grid.bbox_parent().pmin, naive.bbox_parent().pmin);
+        assert_relative_eq!(grid.bbox_parent().pmax, naive.bbox_parent().pmax);
+        let mut hits = 0;
+        for ray in rays(4096, &mut rng) {
+            let (mut gray, mut nray) = (ray.clone(), ray.clone());
+            let (g, n) = (grid.intersect_ray(&mut gray), naive.intersect_ray(&mut nray));
+            assert_eq!(g.is_some(), n.is_some(), "{:?}", ray);
+            assert_eq!(grid.can_intersect(&ray), n.is_some(), "{:?}", ray);
+            if let (Some(g), Some(n)) = (g, n) {
+                hits += 1;
+                assert_relative_eq!(gray.max_extend(), nray.max_extend(), max_relative = 1e-5);
+                assert_relative_eq!(g.basic.pos, n.basic.pos, epsilon = 1e-4);
+                assert_relative_eq!(g.basic.norm, n.basic.norm, epsilon = 1e-4);
+            } else {
+                assert_eq!(gray.max_extend(), ray.max_extend());
+            }
+        }
+        assert!(hits > 250, "only {} hits", hits);
+    }
+
+    #[test]
+    fn test_single_cell() {
+        // a single component in a single cell, with flat bounds along no axis
+        let grid = UniformGrid::from_spheres(
+            &[Point3f::new(0. as Float, 0., 0.)], &[1. as Float],
+            test_utils::matte(0.5 as Float, 0.5 as Float, 0.5 as Float)
+        );
+        assert_eq!(grid.resolution(), [3, 3, 3]);
+        let mut ray = RawRay::from_od(Point3f::new(0. as Float, 0., -3.), Vector3f::new(0. as Float, 0., 1.));
+        assert!(grid.intersect_ray(&mut ray).is_some());
+        assert_relative_eq!(ray.max_extend(), 2. as Float, epsilon = 1e-4);
+        let ray = RawRay::from_od(Point3f::new(0. as Float, 1.5, -3.), Vector3f::new(0. as Float, 0., 1.));
+        assert!(!grid.can_intersect(&ray));
+    }
+
+    fn seconds(start: Instant) -> f64 {
+        let elapsed = start.elapsed();
+        elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9
+    }
+
+    // run with `cargo test --release -- --ignored bench_grid_vs_bvh --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_grid_vs_bvh() {
+        let mut rng = StdRng::from_seed(&[7usize][..]);
+        let (positions, radii) = spheres(1000000, 0.001 as Float, &mut rng);
+        let material = test_utils::matte(0.5 as Float, 0.5 as Float, 0.5 as Float);
+        let mut components: Vec<ComponentPointer> = Vec::with_capacity(positions.len());
+        for (&p, &r) in positions.iter().zip(&radii) {
+            let m = Matrix4f::from_translation(p.to_vec());
+            let sphere = ShapedPrimitive::new(Sphere::full(r), material.clone(), None);
+            let sphere: Arc<Composable> = Arc::new(
+                TransformedComposable::new(sphere, Arc::new(m), Arc::new(m.invert().unwrap()))
+            );
+            components.push(sphere.into());
+        }
+        let start = Instant::now();
+        let grid = UniformGrid::new(&components);
+        let grid_build = seconds(start);
+        let start = Instant::now();
+        let bvh = BVH::new(&components, BVHStrategy::SAH);
+        let bvh_build = seconds(start);
+        let rays = rays(100000, &mut rng);
+        let trace = |aggregate: &Composable| {
+            let start = Instant::now();
+            let hits = rays.iter().filter(|ray| aggregate.intersect_ray(&mut (*ray).clone()).is_some()).count();
+            (seconds(start), hits)
+        };
+        let (grid_trace, grid_hits) = trace(&grid);
+        let (bvh_trace, bvh_hits) = trace(&bvh);
+        println!(
+            "grid {:?}: built in {:.3}s, traced in {:.3}s, {} MiB; bvh: built in {:.3}s, traced in {:.3}s, {} MiB",
+            grid.resolution(), grid_build, grid_trace, grid.memory_estimate() >> 20,
+            bvh_build, bvh_trace, bvh.memory_estimate() >> 20
+        );
+        assert_eq!(grid_hits, bvh_hits);
+        assert!(grid_build < bvh_build);
+    }
+}
+
+#[cfg(test)]
+mod test_scene_cache {
+    use super::super::*;
+    use super::super::cache::*;
+    use super::super::bvh::BVHStrategy;
+    use lighting::pointlights::PointLight;
+    use renderer::scene::Scene;
+    use test_utils::{self, TestScene, RendererKind};
+    use image;
+    use std::env;
+    use std::fs;
+
+    // a textured quad facing the camera, under the material of `mtl`
+    fn write_variant(dir: &Path, name: &str, mtl: &str) -> ::std::path::PathBuf {
+        fs::write(dir.join(format!("{}.mtl", name)), format!("newmtl m\nKd 1 1 1\nmap_Kd checker.png\n{}", mtl)).unwrap();
+        let obj = dir.join(format!("{}.obj", name));
+        fs::write(&obj, format!(
+            "mtllib {}.mtl\nv -1 -1 0\nv 1 -1 0\nv 1 1 0\nv -1 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nusemtl m\nf 1/1 2/2 3/3\nf 1/1 3/3 4/4\n",
+            name
+        )).unwrap();
+        obj
+    }
+
+    fn scene(cache: &SceneCache, obj: &Path) -> TestScene {
+        let (shapes, _) = load_obj_cached(cache, obj, Matrix4f::identity(), ObjOptions::default()).unwrap();
+        let bvh = build_bvh_cached(cache, &shapes, BVHStrategy::SAH);
+        let light: Arc<Light> = Arc::new(PointLight::new(
+            Point3f::new(0. as Float, 0., -3.), RGBSpectrumf::grey_scale(8. as Float)
+        ));
+        TestScene{
+            scene: Scene::new(vec![light], Arc::new(bvh)),
+            camera: test_utils::camera(
+                8, Point3f::new(0. as Float, 0., -4.), Point3f::new(0. as Float, 0., 0.), float::frac_pi_4()
+            ),
+        }
+    }
+
+    #[test]
+    fn test_material_variants() {
+        let dir = env::temp_dir().join("arendur_test_scene_cache");
+        fs::create_dir_all(&dir).unwrap();
+        image::RgbImage::from_fn(4, 4, |x, y| {
+            if (x + y) % 2 == 0 { image::Rgb([255, 255, 255]) } else { image::Rgb([64, 128, 192]) }
+        }).save(dir.join("checker.png")).unwrap();
+        let matte = write_variant(&dir, "matte", "");
+        let leaf = write_variant(&dir, "leaf", "leaf 0.9\n");
+        let cache = SceneCache::new();
+
+        let first = test_utils::render(&scene(&cache, &matte), RendererKind::Whitted, 64);
+        assert_eq!(cache.stats(), CacheStats{
+            mipmap_loads: 1, mesh_loads: 1, mesh_hits: 0, bvh_builds: 1, bvh_hits: 0,
+            texture_substitutions: 0,
+        });
+        cache.reset_stats();
+        let second = test_utils::render(&scene(&cache, &leaf), RendererKind::Whitted, 64);
+        assert_eq!(cache.stats(), CacheStats{
+            mipmap_loads: 0, mesh_loads: 1, mesh_hits: 0, bvh_builds: 0, bvh_hits: 1,
+            texture_substitutions: 0,
+        });
+        // the laid out hierarchy carries the variant's own material,
+        // transmitting most light away from the camera
+        assert!(test_utils::mean(&second).g() < 0.5 as Float * test_utils::mean(&first).g());
+
+        cache.reset_stats();
+        let _ = scene(&cache, &matte);
+        assert_eq!(cache.stats(), CacheStats{
+            mipmap_loads: 0, mesh_loads: 0, mesh_hits: 1, bvh_builds: 0, bvh_hits: 1,
+            texture_substitutions: 0,
+        });
+
+        // once evicted, textures no longer in use are loaded again
+        cache.clear();
+        cache.reset_stats();
+        let _ = scene(&cache, &leaf);
+        assert_eq!(cache.stats(), CacheStats{
+            mipmap_loads: 1, mesh_loads: 1, mesh_hits: 0, bvh_builds: 1, bvh_hits: 0,
+            texture_substitutions: 0,
+        });
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
+
+#[cfg(test)]
+mod test_texture_fallback {
+    use super::super::*;
+    use super::super::cache::*;
+    use super::super::bvh::BVHStrategy;
+    use lighting::pointlights::PointLight;
+    use renderer::scene::Scene;
+    use test_utils::{self, TestScene, RendererKind};
+    use std::env;
+    use std::fs;
+
+    // a green quad facing the camera, each half under its own material
+    // textured by the same missing image
+    fn write_quad(dir: &Path) -> ::std::path::PathBuf {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(
+            dir.join("quad.mtl"),
+            "newmtl a\nKd 0.2 0.8 0.2\nmap_Kd missing.png\nnewmtl b\nKd 0.2 0.8 0.2\nmap_Kd missing.png\n"
+        ).unwrap();
+        let obj = dir.join("quad.obj");
+        fs::write(&obj, "mtllib quad.mtl\nv -1 -1 0\nv 1 -1 0\nv 1 1 0\nv -1 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+            o a\nusemtl a\nf 1/1 2/2 3/3\no b\nusemtl b\nf 1/1 3/3 4/4\n").unwrap();
+        obj
+    }
+
+    fn options(fallback: FallbackMode) -> ObjOptions {
+        ObjOptions{fallback, ..ObjOptions::default()}
+    }
+
+    fn scene(cache: &SceneCache, obj: &Path, fallback: FallbackMode) -> TestScene {
+        let (shapes, _) = load_obj_cached(cache, obj, Matrix4f::identity(), options(fallback)).unwrap();
+        let bvh = build_bvh_cached(cache, &shapes, BVHStrategy::SAH);
+        let light: Arc<Light> = Arc::new(PointLight::new(
+            Point3f::new(0. as Float, 0., -3.), RGBSpectrumf::grey_scale(8. as Float)
+        ));
+        TestScene{
+            scene: Scene::new(vec![light], Arc::new(bvh)),
+            camera: test_utils::camera(
+                16, Point3f::new(0. as Float, 0., -4.), Point3f::new(0. as Float, 0., 0.), float::frac_pi_4()
+            ),
+        }
+    }
+
+    #[test]
+    fn test_error_names_path() {
+        let dir = env::temp_dir().join("arendur_test_fallback_error");
+        let obj = write_quad(&dir);
+        match load_obj_with_options(&obj, Matrix4f::identity(), options(FallbackMode::Error)) {
+            Err(ObjError::Texture(ref e)) => {
+                assert!(e.path().ends_with("missing.png"));
+                assert!(format!("{}", e).contains("missing.png"));
+            },
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("loaded with a missing texture"),
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_checker_placeholder() {
+        let dir = env::temp_dir().join("arendur_test_fallback_checker");
+        let obj = write_quad(&dir);
+        let cache = SceneCache::new();
+        let placeholder = test_utils::render(
+            &scene(&cache, &obj, FallbackMode::CheckerPlaceholder), RendererKind::Whitted, 4
+        );
+        // both materials share the missing path, substituted once
+        assert_eq!(cache.stats().texture_substitutions, 1);
+        let mean = test_utils::mean(&placeholder);
+        assert!(mean.r() > 4. as Float * mean.g() && mean.b() > 4. as Float * mean.g());
+
+        // the `.mtl` constants by default
+        let cache = SceneCache::new();
+        let parameter = test_utils::render(
+            &scene(&cache, &obj, FallbackMode::default()), RendererKind::Whitted, 4
+        );
+        assert_eq!(cache.stats().texture_substitutions, 1);
+        let mean = test_utils::mean(&parameter);
+        assert!(mean.g() > 2. as Float * mean.r());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
+
+#[cfg(test)]
+mod test_deferred_hit {
+    use super::super::*;
+    use super::super::bvh::{BVH, BVHStrategy};
+    use super::super::naive::Naive;
+    use super::super::transformed::TransformedComposable;
+    use test_utils;
+    use tobj;
+    use rand::{Rng, SeedableRng, StdRng};
+    use std::time::Instant;
+
+    // a rough `n` by `n` heightfield over the unit square
+    fn terrain(n: usize, rng: &mut StdRng) -> Arc<TriangleMesh> {
+        let mut positions = Vec::with_capacity((n+1)*(n+1)*3);
+        for j in 0..n+1 {
+            for i in 0..n+1 {
+                positions.push(i as f32 / n as f32);
+                positions.push(j as f32 / n as f32);
+                positions.push(0.01 * rng.gen::<f32>());
+            }
+        }
+        let mut indices = Vec::with_capacity(n*n*6);
+        for j in 0..n {
+            for i in 0..n {
+                let v = (j*(n+1) + i) as u32;
+                let w = v + n as u32 + 1;
+                indices.extend_from_slice(&[v, v+1, w, v+1, w+1, w]);
+            }
+        }
+        let model = tobj::Model::new(
+            tobj::Mesh::new(positions, Vec::new(), Vec::new(), indices, None),
+            "terrain".to_owned()
+        );
+        Arc::new(TriangleMesh::from_model(model, test_utils::matte(0.5, 0.5, 0.5), None))
+    }
+
+    // the triangles of a terrain, along with `instances` tilted and scaled
+    // instances of another one aggregated by `nested`
+    fn scene(n: usize, instances: usize, nested: &Fn(Vec<ComponentPointer>) -> Arc<Composable>) -> Vec<ComponentPointer> {
+        let mut rng = StdRng::from_seed(&[977usize][..]);
+        let mut components: Vec<ComponentPointer> = TriangleMesh::instances(&terrain(n, &mut rng))
+            .map(ComponentPointer::Triangle).collect();
+        let inner = nested(TriangleMesh::instances(&terrain(n, &mut rng)).map(ComponentPointer::Triangle).collect());
+        for i in 0..instances {
+            let m = Matrix4f::from_translation(Vector3f::new(0.3 as Float, 0.2, 0.1) + Vector3f::new(0.05 as Float, 0.05, 0.) * i as Float)
+                * Matrix4f::from_angle_x(Deg(60. as Float - 10. as Float * i as Float))
+                * Matrix4f::from_scale(0.5 as Float);
+            let instance: Arc<Composable> = Arc::new(TransformedComposable::new(inner.clone(), Arc::new(m), Arc::new(m.invert().unwrap())));
+            components.push(instance.into());
+        }
+        components
+    }
+
+    fn nested_bvh(components: Vec<ComponentPointer>) -> Arc<Composable> {
+        Arc::new(BVH::new(&components, BVHStrategy::SAH))
+    }
+
+    // rays from above towards the terrains, a few of them missing
+    fn rays(n: usize, rng: &mut StdRng) -> Vec<RawRay> {
+        (0..n).map(|_| {
+            let from = Point3f::new(rng.gen(), rng.gen(), 1.5 as Float);
+            let to = Point3f::new(
+                1.4 as Float * rng.gen::<Float>() - 0.2 as Float,
+                1.4 as Float * rng.gen::<Float>() - 0.2 as Float, 0. as Float
+            );
+            RawRay::from_od(from, to - from).with_kind(RayKind::Camera)
+        }).collect()
+    }
+
+    // primary rays of a `n` by `n` pinhole looking over the terrains
+    fn camera_rays(n: usize) -> Vec<RawRay> {
+        let eye = Point3f::new(0.5 as Float, -0.4, 0.35);
+        let mut rays = Vec::with_capacity(n*n);
+        for j in 0..n {
+            for i in 0..n {
+                let u = (i as Float + 0.5 as Float) / n as Float - 0.5 as Float;
+                let v = (j as Float + 0.5 as Float) / n as Float - 0.5 as Float;
+                let dir = Vector3f::new(u, 1. as Float, -0.35 as Float - 0.5 as Float * v);
+                rays.push(RawRay::from_od(eye, dir).with_kind(RayKind::Camera));
+            }
+        }
+        rays
+    }
+
+    // the nearest hit of `ray` and the ray as its component left it,
+    // intersecting each component in full
+    fn eager<'a>(components: &'a [ComponentPointer], ray: &RawRay) -> Option<(RawRay, SurfaceInteraction<'a>)> {
+        let mut ret = None;
+        let mut tmax = ray.max_extend();
+        for component in components {
+            let mut r = ray.clone();
+            r.set_max_extend(tmax);
+            if let Some(si) = component.intersect_ray(&mut r) {
+                if r.max_extend() < tmax {
+                    tmax = r.max_extend();
+                    ret = Some((r, si));
+                }
+            }
+        }
+        ret
+    }
+
+    #[test]
+    fn test_matches_eager() {
+        let bvh = BVH::new(&scene(24, 3, &nested_bvh), BVHStrategy::SAH);
+        let reference = scene(24, 3, &|components| {
+            Arc::new(Naive::new(components.into_iter().map(|c| Arc::new(c) as Arc<Composable>).collect()))
+        });
+        let mut rng = StdRng::from_seed(&[7usize][..]);
+        let mut hits = 0;
+        let rays = rays(2048, &mut rng);
+        for ray in &rays {
+            let mut deferred = ray.clone();
+            let hit = bvh.intersect_ray(&mut deferred);
+            let expected = eager(&reference, ray);
+            match (hit, expected) {
+                (Some(si), Some((r, expected))) => {
+                    hits += 1;
+                    // the ray carries on round-tripped through the instance
+                    // transforms of the components it hits, which happens
+                    // in another order for the reference. Up to rounding
+                    let close = |a: Vector3f, b: Vector3f| assert!((a - b).magnitude() < 1e-4 as Float, "{:?} vs {:?}", a, b);
+                    assert_relative_eq!(deferred.max_extend(), r.max_extend(), max_relative = 1e-4);
+                    assert_relative_eq!(bvh.intersect_t(ray).unwrap(), r.max_extend(), max_relative = 1e-4);
+                    close(deferred.origin().to_vec(), r.origin().to_vec());
+                    close(deferred.direction(), r.direction());
+                    close(si.basic.pos.to_vec(), expected.basic.pos.to_vec());
+                    close(si.basic.norm, expected.basic.norm);
+                    close(si.basic.wo, expected.basic.wo);
+                    close(si.duv.dpdu, expected.duv.dpdu);
+                    close(si.duv.dpdv, expected.duv.dpdv);
+                    close(si.shading_norm, expected.shading_norm);
+                    assert_relative_eq!(si.uv, expected.uv, epsilon = 1e-4);
+                    assert_eq!(si.primitive_local, expected.primitive_local);
+                    assert_eq!(si.bump_scale, expected.bump_scale);
+                    assert!(si.primitive_hit.is_some());
+                },
+                (None, None) => {
+                    assert_eq!(deferred.max_extend(), ray.max_extend());
+                    assert_eq!(bvh.intersect_t(ray), None);
+                },
+                _ => panic!("hits disagree on {:?}", ray),
+            }
+        }
+        assert!(hits > rays.len() / 2 && hits < rays.len());
+    }
+
+    // run with `cargo test --release -- --ignored bench_primary_rays --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_primary_rays() {
+        let bvh = BVH::new(&scene(256, 6, &nested_bvh), BVHStrategy::SAH);
+        let rays = camera_rays(512);
+        let n = rays.len() as f64;
+        let rate = |start: Instant| {
+            let elapsed = start.elapsed();
+            let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+            n / secs * 1e-6
+        };
+        println!("{} triangles, interactions of {} bytes", bvh.component_count(), mem::size_of::<SurfaceInteraction>());
+        let start = Instant::now();
+        let nearest = rays.iter().filter(|ray| bvh.intersect_ray(&mut (*ray).clone()).is_some()).count();
+        println!("nearest hit: {:.2} Mrays/s", rate(start));
+        let start = Instant::now();
+        let distances = rays.iter().filter(|ray| bvh.intersect_t(ray).is_some()).count();
+        println!("hit distance only: {:.2} Mrays/s", rate(start));
+        assert_eq!(nearest, distances);
+    }
+}
+
+#[cfg(test)]
+mod test_obj_emission_side {
+    use super::super::*;
+    use lighting::EmissionSide;
+    use std::env;
+    use std::fs;
+
+    // an emissive unit quad in the xy plane, facing +z
+    fn write_lamp(dir: &Path) -> ::std::path::PathBuf {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join("lamp.mtl"), "newmtl lamp\nKd 0 0 0\nKe 1 1 1\n").unwrap();
+        let obj = dir.join("lamp.obj");
+        fs::write(&obj, "mtllib lamp.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+            usemtl lamp\nf 1 2 3\nf 1 3 4\n").unwrap();
+        obj
+    }
+
+    #[test]
+    fn test_options_applied() {
+        let dir = env::temp_dir().join("arendur_test_obj_emission_side");
+        let obj = write_lamp(&dir);
+        let pos = Point3f::new(0.25 as Float, 0.5, 0.);
+        let (up, down) = (Vector3f::new(0. as Float, 0., 1.), Vector3f::new(0. as Float, 0., -1.));
+        for &(side, front, back) in &[
+            (EmissionSide::Front, true, false),
+            (EmissionSide::Back, false, true),
+            (EmissionSide::Both, true, true),
+        ] {
+            let options = ObjOptions{emission_side: side, ..ObjOptions::default()};
+            let (_, lights) = load_obj_with_options(&obj, Matrix4f::identity(), options).unwrap();
+            assert_eq!(lights.len(), 1);
+            assert_eq!(!lights[0].evaluate_path(pos, up).is_black(), front, "{:?}", side);
+            assert_eq!(!lights[0].evaluate_path(pos, down).is_black(), back, "{:?}", side);
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
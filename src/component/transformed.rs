@@ -11,9 +11,10 @@
 use geometry::prelude::*;
 use super::*;
 use std::sync::Arc;
+use std::mem;
 use spectrum::*;
-use renderer::scene::Scene;
-use lighting::{LightFlag, LightSample, SampleInfo, PathInfo};
+use renderer::scene::{Scene, same_light};
+use lighting::{LightFlag, LIGHT_DPOS, LIGHT_DDIR, LightSample, SampleInfo, PathInfo};
 
 /// Component transformed from another component
 #[derive(Clone, Debug)]
@@ -36,6 +37,109 @@ impl<T> TransformedComposable<T> {
             parent_local: parent_local,
         }
     }
+
+    // `path` of a light with `flags` out of the local frame, its
+    // densities converted along
+    #[inline]
+    fn path_to_parent(&self, flags: LightFlag, path: PathInfo) -> PathInfo {
+        let mut ret = path.apply_transform(&*self.local_parent);
+        ret.pdfpos = self.pdfpos_to_parent(flags, path.pdfpos, path.normal);
+        ret.pdfdir = self.pdfdir_to_parent(flags, path.pdfdir, path.ray.direction().normalize());
+        ret
+    }
+
+    // `ls` sampled from a light with `flags` in the local frame, its
+    // density converted to the parent one
+    #[inline]
+    fn sample_to_parent(&self, flags: LightFlag, ls: LightSample) -> LightSample {
+        let mut ret = ls.apply_transform(&*self.local_parent);
+        if !flags.is_delta() {
+            ret.pdf = ls.pdf / self.local_parent.solid_angle_scale(ls.wi());
+        }
+        ret
+    }
+
+    // `light.pdf` at a parent position and direction
+    #[inline]
+    fn pdf_to_parent<L: Light + ?Sized>(&self, light: &L, pos: Point3f, wi: Vector3f) -> Float {
+        let pos = self.parent_local.transform_point(pos);
+        let wi = self.parent_local.transform_vector(wi).normalize();
+        let pdf = light.pdf(pos, wi);
+        if light.is_delta() {
+            pdf
+        } else {
+            pdf / self.local_parent.solid_angle_scale(wi)
+        }
+    }
+
+    // `light.pdf_path` at a parent position, direction and normal
+    #[inline]
+    fn pdf_path_to_parent<L: Light + ?Sized>(&self, light: &L, pos: Point3f, dir: Vector3f, norm: Vector3f) -> (Float, Float) {
+        let pos = self.parent_local.transform_point(pos);
+        let dir = self.parent_local.transform_vector(dir).normalize();
+        let norm = self.parent_local.transform_norm(norm);
+        let (pdfpos, pdfdir) = light.pdf_path(pos, dir, norm);
+        let flags = light.flags();
+        (self.pdfpos_to_parent(flags, pdfpos, norm), self.pdfdir_to_parent(flags, pdfdir, dir))
+    }
+
+    // area density `pdfpos` of a light with `flags` at a point of local
+    // normal `norm`, in the parent frame. Delta positions are kept as 1
+    #[inline]
+    fn pdfpos_to_parent(&self, flags: LightFlag, pdfpos: Float, norm: Vector3f) -> Float {
+        if flags.contains(LIGHT_DPOS) {
+            pdfpos
+        } else {
+            pdfpos / self.local_parent.area_scale(norm)
+        }
+    }
+
+    // solid angle density `pdfdir` of a light with `flags` about unit
+    // local direction `dir`, in the parent frame. Delta directions are
+    // kept as 1
+    #[inline]
+    fn pdfdir_to_parent(&self, flags: LightFlag, pdfdir: Float, dir: Vector3f) -> Float {
+        if flags.contains(LIGHT_DDIR) {
+            pdfdir
+        } else {
+            pdfdir / self.local_parent.solid_angle_scale(dir)
+        }
+    }
+
+    // power of `light`, scaled by the ratio of its area in the parent
+    // frame to that in the local one. The ratio is exact for similarity
+    // transforms, and estimated over stratified positions otherwise
+    fn power_to_parent<L: Light + ?Sized>(&self, light: &L) -> RGBSpectrumf {
+        let power = light.power();
+        if light.is_delta() { return power; }
+        let m = &*self.local_parent;
+        let (x, y, z) = (m.x.truncate(), m.y.truncate(), m.z.truncate());
+        let s2 = x.magnitude2();
+        let tolerance = 1e-4 as Float * s2;
+        if (y.magnitude2() - s2).abs() < tolerance && (z.magnitude2() - s2).abs() < tolerance
+            && x.dot(y).abs() < tolerance && y.dot(z).abs() < tolerance && z.dot(x).abs() < tolerance {
+            return power * s2;
+        }
+        let n = 16;
+        let (mut local, mut parent) = (0. as Float, 0. as Float);
+        for i in 0..n {
+            for j in 0..n {
+                let path = light.generate_path(SampleInfo{
+                    pfilm: Point2f::new(
+                        (i as Float + 0.5 as Float) / n as Float,
+                        (j as Float + 0.5 as Float) / n as Float
+                    ),
+                    plens: Point2f::new(0.5 as Float, 0.5 as Float),
+                    time: 0. as Float,
+                });
+                if path.pdfpos > 0. as Float {
+                    local += 1. as Float / path.pdfpos;
+                    parent += m.area_scale(path.normal) / path.pdfpos;
+                }
+            }
+        }
+        if local > 0. as Float { power * (parent / local) } else { power }
+    }
 }
 
 impl<T: Composable> Composable for TransformedComposable<T>
@@ -46,8 +150,23 @@ impl<T: Composable> Composable for TransformedComposable<T>
     }
 
     #[inline]
-    fn intersection_cost(&self) -> Float {
-        1.0 as Float + self.inner.intersection_cost()
+    fn intersection_cost(&self, costs: &CostModel) -> Float {
+        costs.transform + self.inner.intersection_cost(costs)
+    }
+
+    #[inline]
+    fn component_count(&self) -> usize {
+        self.inner.component_count()
+    }
+
+    #[inline]
+    fn memory_estimate(&self) -> usize {
+        mem::size_of_val(self) + 2 * mem::size_of::<Matrix4f>() + self.inner.memory_estimate() - mem::size_of::<T>()
+    }
+
+    #[inline]
+    default fn intersect_t(&self, ray: &RawRay) -> Option<Float> {
+        self.inner.intersect_t(&ray.apply_transform(&*self.parent_local))
     }
 
     #[inline]
@@ -55,7 +174,7 @@ impl<T: Composable> Composable for TransformedComposable<T>
         *ray = ray.apply_transform(&*self.parent_local);
         let mut ret = self.inner.intersect_ray(ray);
         if let Some(ret) = ret.as_mut() {
-            *ret = ret.apply_transform(&*self.local_parent);
+            *ret = ret.apply_instancing(&*self.local_parent, &*self.parent_local);
         }
         *ray = ray.apply_transform(&*self.local_parent);
         ret
@@ -65,6 +184,11 @@ impl<T: Composable> Composable for TransformedComposable<T>
     default fn as_light(&self) -> &Light {
         unimplemented!();
     }
+
+    #[inline]
+    default fn visit_primitives(&self, f: &mut FnMut(&Primitive)) {
+        self.inner.visit_primitives(f);
+    }
 }
 
 impl<T: Primitive> Composable for TransformedComposable<T>
@@ -85,6 +209,11 @@ impl<T: Primitive> Composable for TransformedComposable<T>
     fn as_light(&self) -> &Light {
         self
     }
+
+    #[inline]
+    fn visit_primitives(&self, f: &mut FnMut(&Primitive)) {
+        f(self)
+    }
 }
 
 impl<T: Primitive> Primitive for TransformedComposable<T>
@@ -98,6 +227,11 @@ impl<T: Primitive> Primitive for TransformedComposable<T>
     fn get_material(&self) -> &Material {
         self.inner.get_material()
     }
+
+    #[inline]
+    fn is_shadow_catcher(&self) -> bool {
+        self.inner.is_shadow_catcher()
+    }
 }
 
 impl<T: Primitive> Light for TransformedComposable<T>
@@ -122,33 +256,27 @@ impl<T: Primitive> Light for TransformedComposable<T>
     #[inline]
     fn evaluate_sampled(&self, pos: Point3f, sample: Point2f) -> LightSample {
         let pos = self.parent_local.transform_point(pos);
-        let ls = self.inner.evaluate_sampled(pos, sample);
-        ls.apply_transform(&*self.local_parent)
+        self.sample_to_parent(self.inner.flags(), self.inner.evaluate_sampled(pos, sample))
     }
 
     #[inline]
     fn generate_path(&self, samples: SampleInfo) -> PathInfo {
-        self.inner.generate_path(samples).apply_transform(&*self.local_parent)
+        self.path_to_parent(self.inner.flags(), self.inner.generate_path(samples))
     }
 
     #[inline]
     fn pdf_path(&self, pos: Point3f, dir: Vector3f, norm: Vector3f) -> (Float, Float) {
-        let pos = self.parent_local.transform_point(pos);
-        let dir = self.parent_local.transform_vector(dir);
-        let norm = self.parent_local.transform_norm(norm);
-        self.inner.pdf_path(pos, dir, norm)
+        self.pdf_path_to_parent(&self.inner, pos, dir, norm)
     }
 
     #[inline]
     fn pdf(&self, pos: Point3f, wi: Vector3f) -> Float {
-        let pos = self.parent_local.transform_point(pos);
-        let wi = self.parent_local.transform_vector(wi);
-        self.inner.pdf(pos, wi)
+        self.pdf_to_parent(&self.inner, pos, wi)
     }
 
     #[inline]
     fn power(&self) -> RGBSpectrumf {
-        self.inner.power()
+        self.power_to_parent(&self.inner)
     }
 
     #[inline]
@@ -165,8 +293,23 @@ impl<T: Composable> Composable for TransformedComposable<Arc<T>>
     }
 
     #[inline]
-    fn intersection_cost(&self) -> Float {
-        2.0 as Float + self.inner.intersection_cost()
+    fn intersection_cost(&self, costs: &CostModel) -> Float {
+        costs.transform + self.inner.intersection_cost(costs)
+    }
+
+    #[inline]
+    fn component_count(&self) -> usize {
+        self.inner.component_count()
+    }
+
+    #[inline]
+    fn memory_estimate(&self) -> usize {
+        mem::size_of_val(self) + 2 * mem::size_of::<Matrix4f>() + self.inner.memory_estimate()
+    }
+
+    #[inline]
+    default fn intersect_t(&self, ray: &RawRay) -> Option<Float> {
+        self.inner.intersect_t(&ray.apply_transform(&*self.parent_local))
     }
 
     #[inline]
@@ -174,7 +317,7 @@ impl<T: Composable> Composable for TransformedComposable<Arc<T>>
         *ray = ray.apply_transform(&*self.parent_local);
         let mut ret = self.inner.intersect_ray(ray);
         if let Some(ret) = ret.as_mut() {
-            *ret = ret.apply_transform(&*self.local_parent);
+            *ret = ret.apply_instancing(&*self.local_parent, &*self.parent_local);
         }
         *ray = ray.apply_transform(&*self.local_parent);
         ret
@@ -184,6 +327,11 @@ impl<T: Composable> Composable for TransformedComposable<Arc<T>>
     default fn as_light(&self) -> &Light {
         unimplemented!();
     }
+
+    #[inline]
+    default fn visit_primitives(&self, f: &mut FnMut(&Primitive)) {
+        self.inner.visit_primitives(f);
+    }
 }
 
 impl<T: Primitive> Composable for TransformedComposable<Arc<T>>
@@ -204,6 +352,11 @@ impl<T: Primitive> Composable for TransformedComposable<Arc<T>>
     fn as_light(&self) -> &Light {
         self
     }
+
+    #[inline]
+    fn visit_primitives(&self, f: &mut FnMut(&Primitive)) {
+        f(self)
+    }
 }
 
 impl<T: Primitive> Primitive for TransformedComposable<Arc<T>>
@@ -217,6 +370,11 @@ impl<T: Primitive> Primitive for TransformedComposable<Arc<T>>
     fn get_material(&self) -> &Material {
         self.inner.get_material()
     }
+
+    #[inline]
+    fn is_shadow_catcher(&self) -> bool {
+        self.inner.is_shadow_catcher()
+    }
 }
 
 impl<T: Primitive> Light for TransformedComposable<Arc<T>>
@@ -241,33 +399,27 @@ impl<T: Primitive> Light for TransformedComposable<Arc<T>>
     #[inline]
     fn evaluate_sampled(&self, pos: Point3f, sample: Point2f) -> LightSample {
         let pos = self.parent_local.transform_point(pos);
-        let ls = self.inner.evaluate_sampled(pos, sample);
-        ls.apply_transform(&*self.local_parent)
+        self.sample_to_parent(self.inner.flags(), self.inner.evaluate_sampled(pos, sample))
     }
 
     #[inline]
     fn generate_path(&self, samples: SampleInfo) -> PathInfo {
-        self.inner.generate_path(samples).apply_transform(&*self.local_parent)
+        self.path_to_parent(self.inner.flags(), self.inner.generate_path(samples))
     }
 
     #[inline]
     fn pdf_path(&self, pos: Point3f, dir: Vector3f, norm: Vector3f) -> (Float, Float) {
-        let pos = self.parent_local.transform_point(pos);
-        let dir = self.parent_local.transform_vector(dir);
-        let norm = self.parent_local.transform_norm(norm);
-        self.inner.pdf_path(pos, dir, norm)
+        self.pdf_path_to_parent(&*self.inner, pos, dir, norm)
     }
 
     #[inline]
     fn pdf(&self, pos: Point3f, wi: Vector3f) -> Float {
-        let pos = self.parent_local.transform_point(pos);
-        let wi = self.parent_local.transform_vector(wi);
-        self.inner.pdf(pos, wi)
+        self.pdf_to_parent(&*self.inner, pos, wi)
     }
 
     #[inline]
     fn power(&self) -> RGBSpectrumf {
-        self.inner.power()
+        self.power_to_parent(&*self.inner)
     }
 }
 
@@ -279,8 +431,23 @@ impl Composable for TransformedComposable<Arc<Composable>>
     }
 
     #[inline]
-    fn intersection_cost(&self) -> Float {
-        2.0 as Float + self.inner.intersection_cost()
+    fn intersection_cost(&self, costs: &CostModel) -> Float {
+        costs.transform + self.inner.intersection_cost(costs)
+    }
+
+    #[inline]
+    fn component_count(&self) -> usize {
+        self.inner.component_count()
+    }
+
+    #[inline]
+    fn memory_estimate(&self) -> usize {
+        mem::size_of_val(self) + 2 * mem::size_of::<Matrix4f>() + self.inner.memory_estimate()
+    }
+
+    #[inline]
+    fn intersect_t(&self, ray: &RawRay) -> Option<Float> {
+        self.inner.intersect_t(&ray.apply_transform(&*self.parent_local))
     }
 
     #[inline]
@@ -288,7 +455,7 @@ impl Composable for TransformedComposable<Arc<Composable>>
         *ray = ray.apply_transform(&*self.parent_local);
         let mut ret = self.inner.intersect_ray(ray);
         if let Some(ret) = ret.as_mut() {
-            *ret = ret.apply_transform(&*self.local_parent);
+            *ret = ret.apply_instancing(&*self.local_parent, &*self.parent_local);
         }
         *ray = ray.apply_transform(&*self.local_parent);
         ret
@@ -298,6 +465,11 @@ impl Composable for TransformedComposable<Arc<Composable>>
     fn as_light(&self) -> &Light {
         unimplemented!();
     }
+
+    #[inline]
+    fn visit_primitives(&self, f: &mut FnMut(&Primitive)) {
+        self.inner.visit_primitives(f);
+    }
 }
 
 impl Composable for TransformedComposable<Arc<Primitive>>
@@ -308,8 +480,23 @@ impl Composable for TransformedComposable<Arc<Primitive>>
     }
 
     #[inline]
-    fn intersection_cost(&self) -> Float {
-        2.0 as Float + self.inner.intersection_cost()
+    fn intersection_cost(&self, costs: &CostModel) -> Float {
+        costs.transform + self.inner.intersection_cost(costs)
+    }
+
+    #[inline]
+    fn component_count(&self) -> usize {
+        self.inner.component_count()
+    }
+
+    #[inline]
+    fn memory_estimate(&self) -> usize {
+        mem::size_of_val(self) + 2 * mem::size_of::<Matrix4f>() + self.inner.memory_estimate()
+    }
+
+    #[inline]
+    fn intersect_t(&self, ray: &RawRay) -> Option<Float> {
+        self.inner.intersect_t(&ray.apply_transform(&*self.parent_local))
     }
 
     #[inline]
@@ -328,6 +515,11 @@ impl Composable for TransformedComposable<Arc<Primitive>>
     fn as_light(&self) -> &Light {
         self
     }
+
+    #[inline]
+    fn visit_primitives(&self, f: &mut FnMut(&Primitive)) {
+        f(self)
+    }
 }
 
 impl Primitive for TransformedComposable<Arc<Primitive>>
@@ -341,6 +533,11 @@ impl Primitive for TransformedComposable<Arc<Primitive>>
     fn get_material(&self) -> &Material {
         self.inner.get_material()
     }
+
+    #[inline]
+    fn is_shadow_catcher(&self) -> bool {
+        self.inner.is_shadow_catcher()
+    }
 }
 
 impl Light for TransformedComposable<Arc<Primitive>>
@@ -365,32 +562,82 @@ impl Light for TransformedComposable<Arc<Primitive>>
     #[inline]
     fn evaluate_sampled(&self, pos: Point3f, sample: Point2f) -> LightSample {
         let pos = self.parent_local.transform_point(pos);
-        let ls = self.inner.evaluate_sampled(pos, sample);
-        ls.apply_transform(&*self.local_parent)
+        self.sample_to_parent(self.inner.flags(), self.inner.evaluate_sampled(pos, sample))
     }
 
     #[inline]
     fn generate_path(&self, samples: SampleInfo) -> PathInfo {
-        self.inner.generate_path(samples).apply_transform(&*self.local_parent)
+        self.path_to_parent(self.inner.flags(), self.inner.generate_path(samples))
     }
 
     #[inline]
     fn pdf_path(&self, pos: Point3f, dir: Vector3f, norm: Vector3f) -> (Float, Float) {
+        self.pdf_path_to_parent(&*self.inner, pos, dir, norm)
+    }
+
+    #[inline]
+    fn pdf(&self, pos: Point3f, wi: Vector3f) -> Float {
+        self.pdf_to_parent(&*self.inner, pos, wi)
+    }
+
+    #[inline]
+    fn power(&self) -> RGBSpectrumf {
+        self.power_to_parent(&*self.inner)
+    }
+}
+impl Light for TransformedComposable<Arc<Light>>
+{
+    fn flags(&self) -> LightFlag {
+        self.inner.flags()
+    }
+
+    #[inline]
+    fn evaluate_ray(&self, rd: &RayDifferential) -> RGBSpectrumf {
+        let rd = rd.apply_transform(&self.parent_local);
+        self.inner.evaluate_ray(&rd)
+    }
+
+    #[inline]
+    fn evaluate_path(&self, pos: Point3f, dir: Vector3f) -> RGBSpectrumf {
         let pos = self.parent_local.transform_point(pos);
         let dir = self.parent_local.transform_vector(dir);
-        let norm = self.parent_local.transform_norm(norm);
-        self.inner.pdf_path(pos, dir, norm)
+        self.inner.evaluate_path(pos, dir)
     }
 
     #[inline]
-    fn pdf(&self, pos: Point3f, wi: Vector3f) -> Float {
+    fn evaluate_sampled(&self, pos: Point3f, sample: Point2f) -> LightSample {
         let pos = self.parent_local.transform_point(pos);
-        let wi = self.parent_local.transform_vector(wi);
-        self.inner.pdf(pos, wi)
+        self.sample_to_parent(self.inner.flags(), self.inner.evaluate_sampled(pos, sample))
+    }
+
+    #[inline]
+    fn generate_path(&self, samples: SampleInfo) -> PathInfo {
+        self.path_to_parent(self.inner.flags(), self.inner.generate_path(samples))
+    }
+
+    #[inline]
+    fn pdf_path(&self, pos: Point3f, dir: Vector3f, norm: Vector3f) -> (Float, Float) {
+        self.pdf_path_to_parent(&*self.inner, pos, dir, norm)
+    }
+
+    #[inline]
+    fn pdf(&self, pos: Point3f, wi: Vector3f) -> Float {
+        self.pdf_to_parent(&*self.inner, pos, wi)
     }
 
     #[inline]
     fn power(&self) -> RGBSpectrumf {
-        self.inner.power()
+        self.power_to_parent(&*self.inner)
+    }
+
+    // hits report the instanced primitive itself
+    #[inline]
+    fn emits_from(&self, primitive: &Primitive) -> bool {
+        same_light(&*self.inner, primitive.as_light()) || self.inner.emits_from(primitive)
     }
-}
\ No newline at end of file
+
+    #[inline]
+    fn max_luminance_in(&self, bbox: &BBox3f) -> Option<Float> {
+        self.inner.max_luminance_in(&bbox.apply_transform(&*self.parent_local))
+    }
+}
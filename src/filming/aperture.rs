@@ -0,0 +1,170 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! defines aperture shapes of thin lenses
+
+use geometry::prelude::*;
+use sample;
+use sample::distribution::Distribution2D;
+use std::sync::Arc;
+use std::path::Path;
+use image;
+
+/// Shape of a thin lens' aperture, in lens space scaled
+/// by the lens radius
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Aperture {
+    /// the unit disk
+    Circle,
+    /// a regular polygon with `count` blades inscribed in the unit disk,
+    /// with its first vertex rotated by `rotation` radians off `+x`
+    Bladed{
+        count: u32,
+        rotation: Float,
+    },
+    /// an arbitrary shape given by a mask over $[-1, 1]^2$
+    Image(Arc<ApertureMask>),
+}
+
+impl Default for Aperture {
+    #[inline]
+    fn default() -> Aperture {
+        Aperture::Circle
+    }
+}
+
+impl Aperture {
+    /// sample a point on the aperture given `u` in $[0, 1)^2$
+    pub fn sample(&self, u: Point2f) -> Point2f {
+        match *self {
+            Aperture::Circle => sample::sample_concentric_disk(u),
+            Aperture::Bladed{count, rotation} => {
+                // pick a triangle of the fan, then sample within it
+                let n = count as Float;
+                let fi = (u.x * n).min(n - 1. as Float).floor();
+                let u = Point2f::new(u.x * n - fi, u.y);
+                let b = sample::sample_uniform_triangle(u);
+                let (p0, p1) = Aperture::blade_vertices(count, rotation, fi as u32);
+                Point2f::new(b.x * p0.x + b.y * p1.x, b.x * p0.y + b.y * p1.y)
+            },
+            Aperture::Image(ref mask) => mask.sample(u),
+        }
+    }
+
+    /// pdf of `p` being sampled by `self.sample(_)` wrt area
+    pub fn pdf(&self, p: Point2f) -> Float {
+        match *self {
+            Aperture::Circle => if p.x * p.x + p.y * p.y <= 1. as Float + 1e-4 as Float {
+                1. as Float / self.area()
+            } else {
+                0. as Float
+            },
+            Aperture::Bladed{count, rotation} => {
+                let n = count as Float;
+                let sector = float::pi() * 2. as Float / n;
+                let mut phi = p.y.atan2(p.x) - rotation;
+                phi -= (phi / sector).floor() * sector;
+                // distance towards the sector's edge along its mid direction
+                let r = (p.x * p.x + p.y * p.y).sqrt();
+                let apothem = (float::pi() / n).cos();
+                if r * (phi - sector * 0.5 as Float).cos() <= apothem + 1e-4 as Float {
+                    1. as Float / self.area()
+                } else {
+                    0. as Float
+                }
+            },
+            Aperture::Image(ref mask) => mask.pdf(p),
+        }
+    }
+
+    /// area of the aperture's support
+    pub fn area(&self) -> Float {
+        match *self {
+            Aperture::Circle => float::pi(),
+            Aperture::Bladed{count, ..} => {
+                let n = count as Float;
+                0.5 as Float * n * (float::pi() * 2. as Float / n).sin()
+            },
+            Aperture::Image(ref mask) => mask.area(),
+        }
+    }
+
+    #[inline]
+    fn blade_vertices(count: u32, rotation: Float, i: u32) -> (Point2f, Point2f) {
+        let sector = float::pi() * 2. as Float / count as Float;
+        let phi0 = rotation + sector * i as Float;
+        let phi1 = phi0 + sector;
+        (
+            Point2f::new(phi0.cos(), phi0.sin()),
+            Point2f::new(phi1.cos(), phi1.sin()),
+        )
+    }
+}
+
+/// An aperture mask covering $[-1, 1]^2$, with row-major
+/// transmittance values starting from the $(-1, -1)$ corner
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApertureMask {
+    nu: usize,
+    nv: usize,
+    values: Vec<Float>,
+    distribution: Distribution2D,
+}
+
+impl ApertureMask {
+    /// construct from `values` laid out in rows of length `nu`
+    pub fn new(values: Vec<Float>, nu: usize) -> ApertureMask {
+        assert!(nu > 0 && values.len() % nu == 0, "invalid mask dimension");
+        assert!(values.iter().any(|&v| v > 0. as Float), "mask lets no light through");
+        let nv = values.len() / nu;
+        let distribution = Distribution2D::new(&values, nu);
+        ApertureMask{
+            nu: nu,
+            nv: nv,
+            values: values,
+            distribution: distribution,
+        }
+    }
+
+    /// load the mask from the luminance of an image at `path`
+    pub fn open<P: AsRef<Path> + ?Sized>(path: &P) -> image::ImageResult<ApertureMask> {
+        let luma = image::open(path)?.to_luma();
+        let (nu, nv) = luma.dimensions();
+        let mut values = Vec::with_capacity((nu * nv) as usize);
+        // images are stored top row first
+        for y in (0..nv).rev() {
+            for x in 0..nu {
+                values.push(luma.get_pixel(x, y).data[0] as Float / 255. as Float);
+            }
+        }
+        Ok(ApertureMask::new(values, nu as usize))
+    }
+
+    /// sample a point in $[-1, 1]^2$ proportional to the mask
+    #[inline]
+    pub fn sample(&self, u: Point2f) -> Point2f {
+        let (p, _) = self.distribution.sample_continuous(u);
+        Point2f::new(p.x * 2. as Float - 1. as Float, p.y * 2. as Float - 1. as Float)
+    }
+
+    /// pdf of `p` being sampled by `self.sample(_)` wrt area
+    #[inline]
+    pub fn pdf(&self, p: Point2f) -> Float {
+        if p.x.abs() > 1. as Float || p.y.abs() > 1. as Float {
+            return 0. as Float;
+        }
+        let uv = Point2f::new((p.x + 1. as Float) * 0.5 as Float, (p.y + 1. as Float) * 0.5 as Float);
+        self.distribution.pdf(uv) * 0.25 as Float
+    }
+
+    /// area of the mask's support
+    pub fn area(&self) -> Float {
+        let covered = self.values.iter().filter(|&&v| v > 0. as Float).count();
+        4. as Float * covered as Float / (self.nu * self.nv) as Float
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Bloom, making bright pixels glow over their neighbors.
+//!
+//! Radiance above a luminance threshold is blurred over the octaves of
+//! a pyramid, each halving the resolution of the last, and added back
+//! onto the image. Being applied to linear radiance, it is meant to
+//! run before images are clamped for saving.
+
+use geometry::prelude::*;
+use super::film::Image;
+use spectrum::{RGBSpectrumf, Spectrum};
+use rayon::prelude::*;
+
+/// number of octaves the glow is spread over
+pub const BLOOM_OCTAVES: usize = 3;
+
+/// Parameters of bloom
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Bloom {
+    /// luminance above which pixels glow
+    pub threshold: Float,
+    /// standard deviation of the finest octave's gaussian in pixels,
+    /// each coarser octave spreading twice as far
+    pub radius: Float,
+    /// ratio of the radiance added to that above the threshold
+    pub intensity: Float,
+}
+
+impl Bloom {
+    /// test if the parameters make sense, namely all non-negative
+    pub fn is_valid(&self) -> bool {
+        self.threshold >= 0. as Float && self.threshold.is_finite()
+            && self.radius >= 0. as Float && self.radius.is_finite()
+            && self.intensity >= 0. as Float && self.intensity.is_finite()
+    }
+
+    /// add the glow of the bright pixels of `image` onto it
+    pub fn apply(&self, image: &mut Image) {
+        let dim = image.dimension();
+        let (width, height) = (dim.x as usize, dim.y as usize);
+        let mut bright = Plane{
+            pixels: vec![RGBSpectrumf::black(); width * height],
+            width: width,
+            height: height,
+        };
+        let mut glowing = false;
+        for y in 0..height {
+            for x in 0..width {
+                let s = image[(x as u32, y as u32)];
+                let luminance = s.to_xyz().y;
+                if luminance > self.threshold {
+                    bright.pixels[y * width + x] = s * ((luminance - self.threshold) / luminance);
+                    glowing = true;
+                }
+            }
+        }
+        if !glowing || self.intensity == 0. as Float { return; }
+
+        let kernel = gaussian_kernel(self.radius);
+        let scale = self.intensity / BLOOM_OCTAVES as Float;
+        let mut levels = vec![bright];
+        for _ in 1..BLOOM_OCTAVES {
+            let next = levels.last().unwrap().downsampled();
+            levels.push(next);
+        }
+        for octave in 0..BLOOM_OCTAVES {
+            let mut glow = levels[octave].blurred(&kernel);
+            for finer in levels[..octave].iter().rev() {
+                glow = glow.upsampled(finer.width, finer.height);
+            }
+            for y in 0..height {
+                for x in 0..width {
+                    image[(x as u32, y as u32)] += glow.pixels[y * width + x] * scale;
+                }
+            }
+        }
+    }
+}
+
+/// normalized weights of a gaussian of standard deviation `sigma`,
+/// truncated at three deviations
+fn gaussian_kernel(sigma: Float) -> Vec<Float> {
+    if sigma <= 0. as Float { return vec![1. as Float]; }
+    let half = (3. as Float * sigma).ceil() as isize;
+    let mut kernel: Vec<_> = (-half..half + 1).map(|i| {
+        let i = i as Float;
+        (-i * i / (2. as Float * sigma * sigma)).exp()
+    }).collect();
+    let sum: Float = kernel.iter().sum();
+    for w in &mut kernel { *w /= sum; }
+    kernel
+}
+
+// Row-major pixels of a pyramid level, zero outside of its bounds.
+// A pixel `i` of a coarser level is centered on the pixel `2i` of
+// the finer one, keeping the octaves symmetric around even pixels
+struct Plane {
+    pixels: Vec<RGBSpectrumf>,
+    width: usize,
+    height: usize,
+}
+
+impl Plane {
+    #[inline]
+    fn at(row: &[RGBSpectrumf], i: isize) -> RGBSpectrumf {
+        if i < 0 || i as usize >= row.len() {
+            RGBSpectrumf::black()
+        } else {
+            row[i as usize]
+        }
+    }
+
+    // each row mapped by `f` into a row of `width`
+    fn map_rows<F>(&self, width: usize, f: &F) -> Plane
+        where F: Fn(&[RGBSpectrumf], &mut [RGBSpectrumf]) + Sync
+    {
+        let mut pixels = vec![RGBSpectrumf::black(); width * self.height];
+        pixels.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+            f(&self.pixels[y * self.width..(y + 1) * self.width], row)
+        });
+        Plane{ pixels: pixels, width: width, height: self.height }
+    }
+
+    fn transposed(&self) -> Plane {
+        let mut pixels = Vec::with_capacity(self.pixels.len());
+        for x in 0..self.width {
+            for y in 0..self.height {
+                pixels.push(self.pixels[y * self.width + x]);
+            }
+        }
+        Plane{ pixels: pixels, width: self.height, height: self.width }
+    }
+
+    // `f` applied along both axes, resizing to `width` by `height`
+    fn separable<F>(&self, width: usize, height: usize, f: F) -> Plane
+        where F: Fn(&[RGBSpectrumf], &mut [RGBSpectrumf]) + Sync
+    {
+        self.map_rows(width, &f).transposed().map_rows(height, &f).transposed()
+    }
+
+    fn blurred(&self, kernel: &[Float]) -> Plane {
+        let half = (kernel.len() / 2) as isize;
+        self.separable(self.width, self.height, |src, dst| {
+            for (i, d) in dst.iter_mut().enumerate() {
+                let i = i as isize;
+                let mut sum = RGBSpectrumf::black();
+                for (k, &w) in kernel.iter().enumerate() {
+                    sum += Plane::at(src, i + k as isize - half) * w;
+                }
+                *d = sum;
+            }
+        })
+    }
+
+    // half the resolution, filtered by a tent over the finer pixels
+    fn downsampled(&self) -> Plane {
+        let half = |n: usize| (n + 1) / 2;
+        self.separable(half(self.width), half(self.height), |src, dst| {
+            for (i, d) in dst.iter_mut().enumerate() {
+                let i = 2 * i as isize;
+                *d = Plane::at(src, i - 1) * 0.25 as Float
+                    + Plane::at(src, i) * 0.5 as Float
+                    + Plane::at(src, i + 1) * 0.25 as Float;
+            }
+        })
+    }
+
+    // back to the finer `width` by `height`, interpolating linearly,
+    // which keeps the sum of the finer pixels
+    fn upsampled(&self, width: usize, height: usize) -> Plane {
+        self.separable(width, height, |src, dst| {
+            for (j, d) in dst.iter_mut().enumerate() {
+                let i = (j / 2) as isize;
+                *d = if j % 2 == 0 {
+                    Plane::at(src, i)
+                } else {
+                    (Plane::at(src, i) + Plane::at(src, i + 1)) * 0.5 as Float
+                };
+            }
+        })
+    }
+}
@@ -0,0 +1,47 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! defines clipping planes, cutting geometry away from camera rays
+
+use geometry::prelude::*;
+
+/// A plane removing everything on the side its `normal` points to
+/// from the view of the camera, i.e. points `p` with
+/// `normal.dot(p) > offset`, in parent space.
+///
+/// Only camera rays skip over clipped hits; shadow rays and
+/// indirect bounces still see the whole scene.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClipPlane {
+    pub normal: Vector3f,
+    pub offset: Float,
+}
+
+impl ClipPlane {
+    /// construction from a point `p` on the plane, clipping
+    /// the side `normal` points to
+    #[inline]
+    pub fn through(p: Point3f, normal: Vector3f) -> ClipPlane {
+        ClipPlane{
+            normal: normal,
+            offset: normal.dot(p.to_vec()),
+        }
+    }
+
+    /// test if `p` is clipped away
+    #[inline]
+    pub fn clips(&self, p: Point3f) -> bool {
+        self.normal.dot(p.to_vec()) > self.offset
+    }
+}
+
+/// test if `p` is clipped away by any of `planes`
+#[inline]
+pub fn clipped(planes: &[ClipPlane], p: Point3f) -> bool {
+    planes.iter().any(|plane| plane.clips(p))
+}
@@ -0,0 +1,279 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Raw film samples, kept for reconstructing images with other filters.
+//!
+//! Dumps are stored little-endian: a header of the magic `b"ARSD"`,
+//! a `u32` version, the resolution as two `u32`s, the raster crop window
+//! as four `i32`s, a `u32` flag set to 1 with alpha and the exposure
+//! scale as an `f32`, followed by sample records till the end of file.
+//! Each record is the position and the RGB spectrum as `f32`s, plus
+//! the alpha as an `f32` if the flag is set.
+
+use geometry::prelude::*;
+use spectrum::RGBSpectrumf;
+use sample::Filter;
+use super::film::{Film, FilmTile, Image};
+use std::sync::{Arc, Mutex};
+use std::path::Path;
+use std::fs::File;
+use std::io::{self, Read, Write, BufReader, BufWriter};
+
+const MAGIC: &'static [u8; 4] = b"ARSD";
+const VERSION: u32 = 1;
+
+/// A sample as added to a film tile
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct RawSample {
+    /// raster position
+    pub pos: Point2f,
+    /// the spectrum, premultiplied by `alpha`
+    pub spectrum: RGBSpectrumf,
+    /// coverage
+    pub alpha: Float,
+}
+
+/// Raw samples of a render, along with the film settings needed
+/// to `refilter` them
+pub struct SampleDump {
+    /// resolution of the film sampled
+    pub resolution: Point2<usize>,
+    /// crop window of the film sampled, in raster space
+    pub crop_window: BBox2<isize>,
+    /// whether alpha of samples is kept
+    pub alpha: bool,
+    /// factor film values were scaled by
+    pub exposure_scale: Float,
+    /// the samples
+    pub samples: Vec<RawSample>,
+}
+
+impl SampleDump {
+    /// an empty dump for `film`
+    pub fn new(film: &Film) -> SampleDump {
+        SampleDump{
+            resolution: film.resolution(),
+            crop_window: film.crop_window_raster(),
+            alpha: film.alpha(),
+            exposure_scale: film.exposure_scale(),
+            samples: Vec::new(),
+        }
+    }
+
+    /// bytes taken by each sample stored
+    #[inline]
+    pub fn record_size(&self) -> usize {
+        if self.alpha { 24 } else { 20 }
+    }
+
+    /// write the dump into `w`
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.write_header(w)?;
+        write_samples(w, &self.samples, self.alpha)
+    }
+
+    /// read a dump written by `write_to`
+    pub fn read_from<R: Read>(r: &mut R) -> io::Result<SampleDump> {
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a sample dump"));
+        }
+        let version = read_u32(r)?;
+        if version != VERSION {
+            return Err(invalid(&format!("unsupported sample dump version {}", version)));
+        }
+        let resolution = Point2::new(read_u32(r)? as usize, read_u32(r)? as usize);
+        let pmin = Point2::new(read_u32(r)? as i32 as isize, read_u32(r)? as i32 as isize);
+        let pmax = Point2::new(read_u32(r)? as i32 as isize, read_u32(r)? as i32 as isize);
+        let alpha = read_u32(r)? == 1;
+        let exposure_scale = read_f32(r)? as Float;
+        let mut dump = SampleDump{
+            resolution: resolution,
+            crop_window: BBox2::new(pmin, pmax),
+            alpha: alpha,
+            exposure_scale: exposure_scale,
+            samples: Vec::new(),
+        };
+        let mut record = [0u8; 24];
+        let size = dump.record_size();
+        loop {
+            let mut read = 0;
+            while read < size {
+                match r.read(&mut record[read..size]) {
+                    Ok(0) => break,
+                    Ok(n) => read += n,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                    Err(e) => return Err(e),
+                }
+            }
+            if read == 0 { break; }
+            if read < size {
+                return Err(invalid("truncated sample record"));
+            }
+            let f = |i: usize| f32_from_le(&record[i*4..i*4+4]) as Float;
+            dump.samples.push(RawSample{
+                pos: Point2f::new(f(0), f(1)),
+                spectrum: RGBSpectrumf::new(f(2), f(3), f(4)),
+                alpha: if alpha { f(5) } else { 1. as Float },
+            });
+        }
+        Ok(dump)
+    }
+
+    /// save the dump to `path`
+    pub fn save<P: AsRef<Path> + ?Sized>(&self, path: &P) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write_to(&mut w)?;
+        w.flush()
+    }
+
+    /// load a dump saved to `path`
+    pub fn load<P: AsRef<Path> + ?Sized>(path: &P) -> io::Result<SampleDump> {
+        SampleDump::read_from(&mut BufReader::new(File::open(path)?))
+    }
+
+    fn write_header<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        write_u32(w, VERSION)?;
+        write_u32(w, self.resolution.x as u32)?;
+        write_u32(w, self.resolution.y as u32)?;
+        write_u32(w, self.crop_window.pmin.x as i32 as u32)?;
+        write_u32(w, self.crop_window.pmin.y as i32 as u32)?;
+        write_u32(w, self.crop_window.pmax.x as i32 as u32)?;
+        write_u32(w, self.crop_window.pmax.y as i32 as u32)?;
+        write_u32(w, if self.alpha { 1 } else { 0 })?;
+        write_f32(w, self.exposure_scale as f32)
+    }
+}
+
+/// Streams the raw samples of tiles into a file as they complete,
+/// keeping only a tile's worth of them in memory.
+///
+/// Samples are written in the order tiles complete in.
+pub struct SampleDumpWriter {
+    file: Mutex<BufWriter<File>>,
+    alpha: bool,
+}
+
+impl SampleDumpWriter {
+    /// create a dump at `path` for samples on `film`
+    pub fn create<P: AsRef<Path> + ?Sized>(path: &P, film: &Film) -> io::Result<SampleDumpWriter> {
+        let header = SampleDump::new(film);
+        let mut file = BufWriter::new(File::create(path)?);
+        header.write_header(&mut file)?;
+        Ok(SampleDumpWriter{
+            file: Mutex::new(file),
+            alpha: header.alpha,
+        })
+    }
+
+    /// write out and drop the samples recorded by `tile` so far
+    pub fn write_tile<S>(&self, tile: &mut FilmTile<S>) -> io::Result<()> {
+        let samples = tile.take_raw_samples();
+        let mut file = self.file.lock().expect("poisoned sample dump");
+        write_samples(&mut *file, &samples, self.alpha)
+    }
+
+    /// flush everything written to disk
+    pub fn finish(self) -> io::Result<()> {
+        self.file.into_inner().expect("poisoned sample dump").flush()
+    }
+}
+
+/// reconstruct an image of `resolution` from `dump` with `filter`.
+///
+/// Sample positions are scaled along with the resolution, the
+/// crop window kept in NDC. Refiltering with the filter of the
+/// film sampled reproduces the image collected from it.
+pub fn refilter(dump: &SampleDump, filter: Arc<Filter>, resolution: Point2<usize>) -> Image {
+    let scale = Vector2f::new(
+        resolution.x as Float / dump.resolution.x as Float,
+        resolution.y as Float / dump.resolution.y as Float,
+    );
+    let crop_window = BBox2f::new(
+        Point2f::new(
+            dump.crop_window.pmin.x as Float / dump.resolution.x as Float,
+            dump.crop_window.pmin.y as Float / dump.resolution.y as Float,
+        ),
+        Point2f::new(
+            dump.crop_window.pmax.x as Float / dump.resolution.x as Float,
+            dump.crop_window.pmax.y as Float / dump.resolution.y as Float,
+        ),
+    );
+    let mut film = Film::new(resolution, crop_window, filter);
+    film.set_alpha(dump.alpha);
+    // a single tile covering the crop window
+    let tile_size = resolution.x.max(resolution.y) as isize;
+    let mut tiles = film.spawn_tiles::<RGBSpectrumf>(tile_size);
+    {
+        let tile = &mut tiles[0];
+        for sample in &dump.samples {
+            let pos = Point2f::new(sample.pos.x * scale.x, sample.pos.y * scale.y);
+            tile.add_sample_with_alpha(pos, &sample.spectrum, sample.alpha);
+        }
+    }
+    let mut image = film.collect_into(tiles);
+    if dump.exposure_scale != 1. as Float {
+        let dim = image.dimension();
+        for y in 0..dim.y {
+            for x in 0..dim.x {
+                image[(x, y)] = image[(x, y)] * dump.exposure_scale;
+            }
+        }
+    }
+    image
+}
+
+fn write_samples<W: Write>(w: &mut W, samples: &[RawSample], alpha: bool) -> io::Result<()> {
+    for sample in samples {
+        write_f32(w, sample.pos.x as f32)?;
+        write_f32(w, sample.pos.y as f32)?;
+        write_f32(w, sample.spectrum.r() as f32)?;
+        write_f32(w, sample.spectrum.g() as f32)?;
+        write_f32(w, sample.spectrum.b() as f32)?;
+        if alpha {
+            write_f32(w, sample.alpha as f32)?;
+        }
+    }
+    Ok(())
+}
+
+#[inline]
+fn write_u32<W: Write>(w: &mut W, v: u32) -> io::Result<()> {
+    w.write_all(&[v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8])
+}
+
+#[inline]
+fn write_f32<W: Write>(w: &mut W, v: f32) -> io::Result<()> {
+    write_u32(w, v.to_bits())
+}
+
+#[inline]
+fn u32_from_le(b: &[u8]) -> u32 {
+    b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24
+}
+
+#[inline]
+fn f32_from_le(b: &[u8]) -> f32 {
+    f32::from_bits(u32_from_le(b))
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut b = [0u8; 4];
+    r.read_exact(&mut b)?;
+    Ok(u32_from_le(&b))
+}
+
+fn read_f32<R: Read>(r: &mut R) -> io::Result<f32> {
+    read_u32(r).map(f32::from_bits)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
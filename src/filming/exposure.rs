@@ -0,0 +1,59 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Photographic exposure of films
+
+use geometry::prelude::*;
+
+/// Exposure settings of a camera, scaling radiance into film values
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Exposure {
+    /// sensitivity of the film
+    pub iso: Float,
+    /// time the shutter stays open, in seconds
+    pub shutter_seconds: Float,
+    /// focal length over the aperture's diameter
+    pub fnumber: Float,
+}
+
+impl Exposure {
+    /// construction
+    #[inline]
+    pub fn new(iso: Float, shutter_seconds: Float, fnumber: Float) -> Exposure {
+        Exposure{
+            iso: iso,
+            shutter_seconds: shutter_seconds,
+            fnumber: fnumber,
+        }
+    }
+
+    /// ratio of film values over scene radiance, such that radiance
+    /// saturating the film by its saturation-based sensitivity,
+    /// $L_{max} = \frac{78}{qS}\frac{N^2}{t}$ with a lens transmittance
+    /// $q$ of 0.65, maps to 1
+    #[inline]
+    pub fn imaging_ratio(&self) -> Float {
+        self.iso * (self.shutter_seconds / (120. as Float * self.fnumber * self.fnumber))
+    }
+
+    /// radius of a thin lens of `focal_length` at this f-number
+    #[inline]
+    pub fn lens_radius(&self, focal_length: Float) -> Float {
+        0.5 as Float * focal_length / self.fnumber
+    }
+
+    /// first setting of this exposure not being positive, if any
+    pub fn invalid_setting(&self) -> Option<(&'static str, Float)> {
+        for &(name, value) in &[
+            ("iso", self.iso), ("shutter_seconds", self.shutter_seconds), ("fnumber", self.fnumber)
+        ] {
+            if !(value > 0. as Float) { return Some((name, value)); }
+        }
+        None
+    }
+}
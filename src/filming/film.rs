@@ -11,12 +11,17 @@
 use geometry::prelude::*;
 use spectrum::{Spectrum, RGBSpectrumf, ToNorm};
 use sample::{Filter, filters};
+use super::exposure::Exposure;
+use super::dump::{RawSample, SampleDump};
 use std::ops;
 use std::mem;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use image;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::io::Result;
+use std::fs;
+use std::time::{Duration, Instant};
+use rayon;
 // use std::marker::PhantomData;
 
 #[inline]
@@ -42,6 +47,44 @@ pub struct Film {
     filter: Arc<Filter>,
     filter_radius: Vector2f,
     // inv_filter_radius: Vector2f,
+    /// the filter tabulated, if tiles look weights up from it
+    #[serde(skip_serializing, skip_deserializing)]
+    filter_table: Option<Arc<FilterTable>>,
+    /// edge length of tiles in pixels, chosen automatically if `None`
+    #[serde(default)]
+    tile_size: Option<isize>,
+    /// whether collected images carry an alpha channel
+    #[serde(default)]
+    alpha: bool,
+    /// raster column splitting the film into independent views
+    #[serde(default)]
+    seam: Option<isize>,
+    /// photographic exposure scaling radiance into film values,
+    /// film values being radiance if `None`
+    #[serde(default)]
+    exposure: Option<Exposure>,
+    /// stops of exposure added on top of `exposure`
+    #[serde(default)]
+    ev_compensation: Float,
+    /// whether spawned tiles record every sample added for `collect_raw`
+    #[serde(default)]
+    raw_samples: bool,
+    /// whether samples are importance sampled by the filter, see `set_filter_sampling`
+    #[serde(default)]
+    filter_sampling: bool,
+    /// mean relative variance of the last collected image
+    #[serde(skip_serializing, skip_deserializing)]
+    variance: CollectedVariance,
+}
+
+/// Mean relative variance found by the last collection, cloned by value
+#[derive(Default)]
+struct CollectedVariance(Mutex<Option<Float>>);
+
+impl Clone for CollectedVariance {
+    fn clone(&self) -> CollectedVariance {
+        CollectedVariance(Mutex::new(*self.0.lock().expect("poisoned variance")))
+    }
 }
 
 fn lanczos_default() -> Arc<Filter> {
@@ -53,17 +96,7 @@ fn lanczos_default() -> Arc<Filter> {
 impl Film {
     /// construction. `crop_window` specified in NDC
     pub fn new(resolution: Point2<usize>, crop_window: BBox2f, filter: Arc<Filter>) -> Film {
-        let resf: Point2f = resolution.cast();
-        let crop_window = BBox2::new(
-            Point2::new(
-                (resf.x * crop_window.pmin.x).ceil() as isize,
-                (resf.y * crop_window.pmin.y).ceil() as isize
-            ),
-            Point2::new(
-                (resf.x * crop_window.pmax.x).ceil() as isize,
-                (resf.y * crop_window.pmax.y).ceil() as isize
-            )
-        );
+        let crop_window = Film::crop_to_raster(resolution, crop_window);
         let filter_radius = filter.radius();
         // let inv_filter_radius = Vector2f::new(
         //     1.0 as Float / filter_radius.x,
@@ -75,12 +108,66 @@ impl Film {
             filter: filter,
             filter_radius: filter_radius,
             // inv_filter_radius: inv_filter_radius,
+            filter_table: None,
+            tile_size: None,
+            alpha: false,
+            seam: None,
+            exposure: None,
+            ev_compensation: 0. as Float,
+            raw_samples: false,
+            filter_sampling: false,
+            variance: CollectedVariance::default(),
         }
     }
 
+    #[inline]
+    fn crop_to_raster(resolution: Point2<usize>, crop_window: BBox2f) -> BBox2<isize> {
+        let resf: Point2f = resolution.cast();
+        BBox2::new(
+            Point2::new(
+                (resf.x * crop_window.pmin.x).ceil() as isize,
+                (resf.y * crop_window.pmin.y).ceil() as isize
+            ),
+            Point2::new(
+                (resf.x * crop_window.pmax.x).ceil() as isize,
+                (resf.y * crop_window.pmax.y).ceil() as isize
+            )
+        )
+    }
+
+    /// get the crop window in NDC
+    #[inline]
+    pub fn crop_window(&self) -> BBox2f {
+        let resf = self.resolutionf();
+        let pmin: Point2f = self.crop_window.pmin.cast();
+        let pmax: Point2f = self.crop_window.pmax.cast();
+        BBox2f::new(
+            Point2f::new(pmin.x / resf.x, pmin.y / resf.y),
+            Point2f::new(pmax.x / resf.x, pmax.y / resf.y)
+        )
+    }
+
+    /// get the crop window in raster space
+    #[inline]
+    pub fn crop_window_raster(&self) -> BBox2<isize> {
+        self.crop_window
+    }
+
+    /// set the crop window, specified in NDC
+    pub fn set_crop_window(&mut self, crop_window: BBox2f) {
+        self.crop_window = Film::crop_to_raster(self.resolution, crop_window);
+    }
+
+    /// resize the film to `resolution`, keeping the crop window in NDC
+    pub fn resize(&mut self, resolution: Point2<usize>) {
+        let crop_window = self.crop_window();
+        self.resolution = resolution;
+        self.set_crop_window(crop_window);
+    }
+
     /// merge output from a tile into a sink
     pub fn merge_into<S>(
-        &self, tile: FilmTile<S>,
+        &self, tile: &FilmTile<S>,
         sink: &mut BoundedSink2D<TilePixel<RGBSpectrumf>>)
         where S: Spectrum<Scalar=Float>,
     {
@@ -88,98 +175,377 @@ impl Film {
         assert!(sink.bounding.contain_lb(tile.sink.bounding.pmin));
         assert!(sink.bounding.contain(tile.sink.bounding.pmax));
         for pixel_idx in tile.sink.bounding {
-            let (rgbspec, weight) = unsafe {
+            let (rgbspec, weight, alpha) = unsafe {
                 let s = tile.sink.get_pixel_unchecked(pixel_idx);
-                (s.spectrum_sum.to_srgb(), s.filter_weight_sum)
+                (s.spectrum_sum.to_srgb(), s.filter_weight_sum, s.alpha_sum)
             };
             let s = unsafe {
                 sink.get_pixel_mut_unchecked(pixel_idx)
             };
             s.spectrum_sum += rgbspec;
             s.filter_weight_sum += weight;
+            s.alpha_sum += alpha;
         }
     }
 
-    /// spawn tiles
-    pub fn spawn_tiles<S>(&self, nx: isize, ny: isize) -> Vec<FilmTile<S>>
-        where TilePixel<S>: Clone + Default
-    {
-        assert!(nx > 0);
-        assert!(ny > 0);
+    /// set whether tiles look filter weights up from a `FilterTable`
+    /// the filter is evaluated into once, in place of evaluating it
+    /// for every pixel a sample reaches. Worth it for filters costly to
+    /// evaluate, such as the Mitchell and Lanczos ones.
+    pub fn set_filter_table(&mut self, enabled: bool) {
+        self.filter_table = if enabled {
+            Some(Arc::new(FilterTable::new(&*self.filter)))
+        } else {
+            None
+        };
+    }
+
+    /// test if tiles look filter weights up from a table
+    #[inline]
+    pub fn has_filter_table(&self) -> bool {
+        self.filter_table.is_some()
+    }
+
+    /// set whether samples are distributed by the filter in place of
+    /// being filtered onto every pixel they reach. Renderers then trace
+    /// each sample of a pixel through an offset from its center sampled
+    /// by `Filter::sample`, see `FilmTile::jitter`, adding it to that pixel
+    /// alone with the sample's weight. Both converge to the same image,
+    /// filter sampling costing the same per sample regardless of the
+    /// filter's radius.
+    #[inline]
+    pub fn set_filter_sampling(&mut self, enabled: bool) {
+        self.filter_sampling = enabled;
+    }
+
+    /// test if samples are distributed by the filter
+    #[inline]
+    pub fn filter_sampling(&self) -> bool {
+        self.filter_sampling
+    }
+
+    /// override the edge length of tiles spawned by renderers,
+    /// `None` for choosing it from the resolution and thread count
+    #[inline]
+    pub fn set_tile_size(&mut self, tile_size: Option<isize>) {
+        self.tile_size = tile_size;
+    }
+
+    /// edge length of tiles renderers should spawn, in pixels
+    pub fn tile_size(&self) -> isize {
+        if let Some(tile_size) = self.tile_size {
+            return tile_size;
+        }
+        // a few tiles per thread keeps them busy till the end,
+        // while tiles too small spend more on overhead than on work
         let extend = self.crop_window.diagonal();
-        let dx = extend.x / nx;
-        let dy = extend.y / ny;
-        let lastx = dx + extend.x % dx;
-        let lasty = dy + extend.y % dy;
-        let mut ret = Vec::with_capacity((nx * ny) as usize);
-        for ix in 0..nx {
-            let cdx = if ix==nx-1 { lastx } else { dx };
-            for iy in 0..ny {
-                let cdy = if iy==ny-1 { lasty } else { dy };
-                let bbox = BBox2::new(
-                    Point2::new(ix*dx, iy*dy),
-                    Point2::new(ix*dx + cdx, iy*dy + cdy),
-                );
-                ret.push(FilmTile{
-                    filter: &*self.filter,
-                    filter_radius: self.filter_radius,
-                    bounding: bbox,
-                    sink: BoundedSink2D::with_value(
-                        Default::default(), 
-                        bbox.expand_by_vec(self.filter_radius.cast()).intersect(&self.crop_window).unwrap()
-                    ),
-                })
+        let area = (extend.x.max(1) * extend.y.max(1)) as Float;
+        let ntiles = (rayon::current_num_threads() * 8) as Float;
+        ((area / ntiles).sqrt() as isize).max(16).min(64)
+    }
+
+    /// split the film at raster column `seam` into views rendered
+    /// independently, such as the eyes of a side-by-side stereo pair.
+    /// Tiles never straddle the seam, and samples are never filtered
+    /// across it.
+    #[inline]
+    pub fn set_seam(&mut self, seam: Option<isize>) {
+        self.seam = seam;
+    }
+
+    /// raster column splitting the film into independent views, if any
+    #[inline]
+    pub fn seam(&self) -> Option<isize> {
+        self.seam
+    }
+
+    /// the part of the crop window on the same side of the seam as `bbox`
+    fn view_window(&self, bbox: BBox2<isize>) -> BBox2<isize> {
+        let mut ret = self.crop_window;
+        if let Some(seam) = self.seam {
+            if bbox.pmin.x >= seam {
+                ret.pmin.x = ret.pmin.x.max(seam);
+            } else {
+                ret.pmax.x = ret.pmax.x.min(seam);
             }
         }
         ret
     }
 
-    /// spawn flat tiles
-    pub fn spawn_flat_tiles<S>(&self, nx: isize, ny: isize) -> Vec<FilmTile<S>>
-        where TilePixel<S>: Clone + Default
-    {
-        assert!(nx > 0);
-        assert!(ny > 0);
-        let extend = self.crop_window.diagonal();
-        let dx = extend.x / nx;
-        let dy = extend.y / ny;
-        let lastx = dx + extend.x % dx;
-        let lasty = dy + extend.y % dy;
+    /// partition the crop window into tiles of about `tile_size` pixels
+    /// along each edge, with the last row and column absorbing the remainder.
+    /// The seam, if any, is always a tile boundary.
+    fn tile_bounds(&self, tile_size: isize) -> Vec<BBox2<isize>> {
+        assert!(tile_size > 0);
+        match self.seam {
+            Some(seam) if seam > self.crop_window.pmin.x && seam < self.crop_window.pmax.x => {
+                let mut left = self.crop_window;
+                left.pmax.x = seam;
+                let mut right = self.crop_window;
+                right.pmin.x = seam;
+                let mut ret = Film::partition(left, tile_size);
+                ret.append(&mut Film::partition(right, tile_size));
+                ret
+            },
+            _ => Film::partition(self.crop_window, tile_size),
+        }
+    }
+
+    fn partition(window: BBox2<isize>, tile_size: isize) -> Vec<BBox2<isize>> {
+        let pmin = window.pmin;
+        let extend = window.diagonal();
+        if extend.x <= 0 || extend.y <= 0 { return Vec::new(); }
+        let nx = (extend.x / tile_size).max(1);
+        let ny = (extend.y / tile_size).max(1);
         let mut ret = Vec::with_capacity((nx * ny) as usize);
         for ix in 0..nx {
-            let cdx = if ix==nx-1 { lastx } else { dx };
+            let x0 = pmin.x + ix * tile_size;
+            let x1 = if ix == nx - 1 { window.pmax.x } else { x0 + tile_size };
             for iy in 0..ny {
-                let cdy = if iy==ny-1 { lasty } else { dy };
-                let bbox = BBox2::new(
-                    Point2::new(ix*dx, iy*dy),
-                    Point2::new(ix*dx + cdx, iy*dy + cdy),
-                );
-                ret.push(FilmTile{
-                    filter: &*self.filter,
-                    filter_radius: self.filter_radius,
-                    bounding: bbox,
-                    sink: BoundedSink2D::with_value(
-                        Default::default(), self.crop_window
-                    ),
-                })
+                let y0 = pmin.y + iy * tile_size;
+                let y1 = if iy == ny - 1 { window.pmax.y } else { y0 + tile_size };
+                ret.push(BBox2::new(Point2::new(x0, y0), Point2::new(x1, y1)));
             }
         }
         ret
     }
 
+    /// spawn tiles of about `tile_size` pixels along each edge
+    pub fn spawn_tiles<S>(&self, tile_size: isize) -> Vec<FilmTile<S>>
+        where TilePixel<S>: Clone + Default
+    {
+        self.tile_bounds(tile_size).into_iter().map(|bbox| FilmTile{
+            filter: &*self.filter,
+            filter_table: self.filter_table.as_ref().map(|table| &**table),
+            filter_radius: self.filter_radius,
+            bounding: bbox,
+            sink: BoundedSink2D::with_value(
+                Default::default(), 
+                bbox.expand_by_vec(self.filter_radius.cast()).intersect(&self.view_window(bbox)).unwrap()
+            ),
+            stats: BoundedSink2D::with_value(SampleStats::default(), bbox),
+            raw: if self.raw_samples { Some(Vec::new()) } else { None },
+            filter_sampling: self.filter_sampling,
+        }).collect()
+    }
+
+    /// spawn flat tiles of about `tile_size` pixels along each edge
+    pub fn spawn_flat_tiles<S>(&self, tile_size: isize) -> Vec<FilmTile<S>>
+        where TilePixel<S>: Clone + Default
+    {
+        self.tile_bounds(tile_size).into_iter().map(|bbox| FilmTile{
+            filter: &*self.filter,
+            filter_table: self.filter_table.as_ref().map(|table| &**table),
+            filter_radius: self.filter_radius,
+            bounding: bbox,
+            sink: BoundedSink2D::with_value(
+                Default::default(), self.view_window(bbox)
+            ),
+            stats: BoundedSink2D::with_value(SampleStats::default(), bbox),
+            raw: if self.raw_samples { Some(Vec::new()) } else { None },
+            filter_sampling: self.filter_sampling,
+        }).collect()
+    }
+
     /// collect results into an image
     pub fn collect_into<'a, S, I>(&self, tiles: I) -> Image
         where S: Spectrum<Scalar=Float>,
               TilePixel<S>: Clone,
               I: IntoIterator<Item=FilmTile<'a, S>>,
+    {
+        let tiles: Vec<_> = tiles.into_iter().collect();
+        self.collect_tiles(&tiles)
+    }
+
+    /// take the raw samples recorded by `tiles` as a dump, to be
+    /// `refilter`ed later. Tiles spawned without `set_raw_samples`
+    /// contribute nothing.
+    ///
+    /// Each sample takes 20 bytes, 24 with alpha, so a dump of
+    /// a 1920x1080 image of 1024spp takes about 40GiB. Consider
+    /// streaming tiles to disk with a `SampleDumpWriter` instead.
+    pub fn collect_raw<S>(&self, tiles: &mut [FilmTile<S>]) -> SampleDump {
+        let mut dump = SampleDump::new(self);
+        let mut tiles: Vec<_> = tiles.iter_mut().collect();
+        tiles.sort_by_key(|tile| (tile.bounding.pmin.y, tile.bounding.pmin.x));
+        for tile in tiles {
+            dump.samples.extend(tile.take_raw_samples());
+        }
+        dump
+    }
+
+    /// collect results into an image, leaving `tiles` for more
+    /// samples to be added to, as in progressive rendering
+    pub fn collect_tiles<'a, S>(&self, tiles: &[FilmTile<'a, S>]) -> Image
+        where S: Spectrum<Scalar=Float>,
+              TilePixel<S>: Clone,
     {
         let mut tmp = BoundedSink2D::with_value(TilePixel{
             spectrum_sum: RGBSpectrumf::black(),
-            filter_weight_sum: 0.0 as Float}, self.crop_window);
+            filter_weight_sum: 0.0 as Float,
+            alpha_sum: 0.0 as Float}, self.crop_window);
+        // tiles overlap by the filter's radius, merge them in a fixed
+        // order so that results are reproducible to the last bit
+        let mut tiles: Vec<_> = tiles.iter().collect();
+        tiles.sort_by_key(|tile| (tile.bounding.pmin.y, tile.bounding.pmin.x));
+        let mut variance_sum = 0. as Float;
+        let mut estimated = 0;
         for tile in tiles {
             self.merge_into(tile, &mut tmp);
+            for p in tile.bounding {
+                if let Some(v) = tile.stats.get_pixel(p).relative_variance() {
+                    variance_sum += v;
+                    estimated += 1;
+                }
+            }
         }
-        Image::from_sink(tmp)
+        *self.variance.0.lock().expect("poisoned variance") = if estimated > 0 {
+            Some(variance_sum / estimated as Float)
+        } else {
+            None
+        };
+        Image::from_sink(tmp, self.alpha, self.exposure_scale())
+    }
+
+    /// mean over the pixels of the last collected image of their
+    /// `SampleStats::relative_variance`, measuring how noisy it is.
+    /// `None` if no pixel has taken two samples, or nothing is collected.
+    ///
+    /// Splatted contributions are not accounted for.
+    pub fn mean_relative_variance(&self) -> Option<Float> {
+        *self.variance.0.lock().expect("poisoned variance")
+    }
+
+    /// spawn a film-wide sink for splatted contributions
+    pub fn spawn_splats(&self) -> FilmSplats {
+        FilmSplats{
+            sums: Mutex::new(BoundedSink2D::with_value([0i64; 3], self.crop_window)),
+        }
+    }
+
+    /// number of samples completed within each pixel of the crop
+    /// window by `tiles`, which falls short of the sampler's count
+    /// where rendering was cut short
+    pub fn sample_counts<S>(&self, tiles: &[FilmTile<S>]) -> BoundedSink2D<u32> {
+        let mut counts = BoundedSink2D::with_value(0, self.crop_window);
+        for tile in tiles {
+            for p in tile.bounding {
+                if counts.bounding.contain_lb(p) {
+                    *counts.get_pixel_mut(p) += tile.stats.get_pixel(p).count;
+                }
+            }
+        }
+        counts
+    }
+
+    /// scale turning splats of light paths, one traced per sample
+    /// completed by `tiles`, into pixel values.
+    ///
+    /// Light paths splat anywhere onto the film, each estimating the
+    /// whole image. Splats are thus normalized by the samples completed
+    /// over the film rather than those of the pixel they land in, which
+    /// keeps them in proportion to the camera paths of completed pixels
+    /// when rendering is cut short, or confined to a crop window.
+    pub fn splat_scale<S>(&self, tiles: &[FilmTile<S>]) -> Float {
+        let samples: u64 = tiles.iter().map(|tile| tile.sample_count()).sum();
+        let pixels = (self.resolution.x * self.resolution.y) as Float;
+        if samples == 0 { 0. as Float } else { pixels / samples as Float }
+    }
+
+    /// collect results into an image, adding `splats` scaled by `scale`
+    pub fn collect_with_splats<'a, S, I>(
+        &self, tiles: I, splats: FilmSplats, scale: Float
+    ) -> Image
+        where S: Spectrum<Scalar=Float>,
+              TilePixel<S>: Clone,
+              I: IntoIterator<Item=FilmTile<'a, S>>,
+    {
+        let mut image = self.collect_into(tiles);
+        let sums = splats.sums.into_inner().expect("poisoned splats");
+        let scale = scale * self.exposure_scale();
+        for p_idx in sums.bounding {unsafe {
+            let sum = sums.get_pixel_unchecked(p_idx);
+            let splatted = RGBSpectrumf::new(
+                from_fixed(sum[0]), from_fixed(sum[1]), from_fixed(sum[2])
+            );
+            *image.inner.get_pixel_mut_unchecked(p_idx) += splatted * scale;
+        }}
+        image
+    }
+
+    /// spawn a preview of the render in progress, saved to `path`
+    /// at most once per `interval` as tiles complete
+    pub fn spawn_preview<P: AsRef<Path> + ?Sized>(&self, path: &P, interval: Duration) -> FilmPreview {
+        let mut image = Image::new(RGBSpectrumf::black(), self.crop_window.pmax.cast());
+        if self.alpha {
+            image.alpha = Some(BoundedSink2D::with_value(0. as Float, image.inner.bounding));
+        }
+        FilmPreview{
+            film: self.clone(),
+            path: path.as_ref().to_path_buf(),
+            interval: interval,
+            state: Mutex::new((image, None)),
+        }
+    }
+
+    /// set the photographic exposure, `None` for collecting radiance as is
+    #[inline]
+    pub fn set_exposure(&mut self, exposure: Option<Exposure>) {
+        self.exposure = exposure;
+    }
+
+    /// the photographic exposure, if any
+    #[inline]
+    pub fn exposure(&self) -> Option<Exposure> {
+        self.exposure
+    }
+
+    /// set the stops of exposure added on top of the photographic exposure
+    #[inline]
+    pub fn set_ev_compensation(&mut self, ev: Float) {
+        self.ev_compensation = ev;
+    }
+
+    /// stops of exposure added on top of the photographic exposure
+    #[inline]
+    pub fn ev_compensation(&self) -> Float {
+        self.ev_compensation
+    }
+
+    /// factor collected film values are scaled by
+    #[inline]
+    pub fn exposure_scale(&self) -> Float {
+        let ratio = self.exposure.map_or(1. as Float, |e| e.imaging_ratio());
+        if self.ev_compensation == 0. as Float {
+            ratio
+        } else {
+            ratio * (2. as Float).powf(self.ev_compensation)
+        }
+    }
+
+    /// request an alpha channel in collected images
+    #[inline]
+    pub fn set_alpha(&mut self, alpha: bool) {
+        self.alpha = alpha;
+    }
+
+    /// whether collected images carry an alpha channel
+    #[inline]
+    pub fn alpha(&self) -> bool {
+        self.alpha
+    }
+
+    /// make tiles spawned from now on record the samples added
+    /// to them, see `collect_raw`
+    #[inline]
+    pub fn set_raw_samples(&mut self, raw_samples: bool) {
+        self.raw_samples = raw_samples;
+    }
+
+    /// whether spawned tiles record the samples added to them
+    #[inline]
+    pub fn raw_samples(&self) -> bool {
+        self.raw_samples
     }
 
     /// get resolution
@@ -276,13 +642,68 @@ impl<S> BoundedSink2D<S> {
     }
 }
 
+/// edge length of `FilterTable`s, in entries
+pub const FILTER_TABLE_WIDTH: usize = 16;
+
+/// A filter evaluated once at `FILTER_TABLE_WIDTH` by `FILTER_TABLE_WIDTH`
+/// points evenly spaced over `[0, radius]` along both axes, looked up
+/// bilinearly. Filters are taken as symmetric about both axes.
+#[derive(Clone, Debug)]
+pub struct FilterTable {
+    values: Vec<Float>,
+    // from offsets to table coordinates
+    scale: Vector2f,
+}
+
+impl FilterTable {
+    /// tabulate `filter`
+    pub fn new(filter: &Filter) -> FilterTable {
+        let radius = filter.radius();
+        let last = (FILTER_TABLE_WIDTH - 1) as Float;
+        let mut values = Vec::with_capacity(FILTER_TABLE_WIDTH * FILTER_TABLE_WIDTH);
+        for y in 0..FILTER_TABLE_WIDTH {
+            for x in 0..FILTER_TABLE_WIDTH {
+                let p = Point2f::new(radius.x * x as Float / last, radius.y * y as Float / last);
+                values.push(unsafe { filter.evaluate_unsafe(p) });
+            }
+        }
+        FilterTable{
+            values: values,
+            scale: Vector2f::new(last / radius.x, last / radius.y),
+        }
+    }
+
+    /// weight of the filter at offset `p`, which should lie
+    /// within its support
+    #[inline]
+    pub fn evaluate(&self, p: Point2f) -> Float {
+        let last = (FILTER_TABLE_WIDTH - 1) as Float;
+        let x = (p.x.abs() * self.scale.x).min(last);
+        let y = (p.y.abs() * self.scale.y).min(last);
+        let (ix, iy) = ((x as usize).min(FILTER_TABLE_WIDTH - 2), (y as usize).min(FILTER_TABLE_WIDTH - 2));
+        let (dx, dy) = (x - ix as Float, y - iy as Float);
+        let at = |x: usize, y: usize| unsafe {
+            *self.values.get_unchecked(y * FILTER_TABLE_WIDTH + x)
+        };
+        let bottom = at(ix, iy) * (1. as Float - dx) + at(ix + 1, iy) * dx;
+        let top = at(ix, iy + 1) * (1. as Float - dx) + at(ix + 1, iy + 1) * dx;
+        bottom * (1. as Float - dy) + top * dy
+    }
+}
+
 /// A tile from the film, generated by `film.spawn_tiles()`.
 /// Basic building block for multithreaded ray-tracing.
 pub struct FilmTile<'a, S> {
     filter: &'a Filter,
+    filter_table: Option<&'a FilterTable>,
     filter_radius: Vector2f,
     bounding: BBox2<isize>,
     sink: BoundedSink2D<TilePixel<S>>,
+    /// unweighted statistics of samples taken within each pixel of `bounding`
+    stats: BoundedSink2D<SampleStats>,
+    /// samples added, if recorded
+    raw: Option<Vec<RawSample>>,
+    filter_sampling: bool,
 }
 
 use std::marker::Send;
@@ -290,30 +711,89 @@ use std::marker::Send;
 unsafe impl<'a, S> Send for FilmTile<'a, S> { }
 
 impl<'a, S> FilmTile<'a, S>
-    where S: Spectrum + ops::AddAssign,
+    where S: Spectrum<Scalar=Float> + ops::AddAssign,
           for<'b> &'b S: ops::Mul<Float, Output=S>,
 {
     /// add a sample's contribution to every related pixels
+    #[inline]
     pub fn add_sample(&mut self, pos: Point2f, spectrum: &S) {
-        let ceil = pos.to_vec() - self.filter_radius + Vector2f::new(0.5 as Float, 0.5 as Float);
-        let floor = pos.to_vec() + self.filter_radius - Vector2f::new(0.5 as Float, 0.5 as Float);
+        self.add_sample_with_alpha(pos, spectrum, 1. as Float);
+    }
 
-        let ceilidx: Vector2<isize> = ceil.cast();
-        let flooridx: Vector2<isize> = floor.cast() + Vector2::new(1, 1);
-        let filter_box = BBox2::new(Point2::from_vec(ceilidx), Point2::from_vec(flooridx));
+    /// add a sample's contribution along with its coverage `alpha`,
+    /// `spectrum` being premultiplied by `alpha`.
+    ///
+    /// Filters no wider than a pixel reach the pixel the sample lies
+    /// in only, with a weight of 1. With filter sampling, `pos` should
+    /// be the position as sampled, before being `jitter`ed, the sample
+    /// reaching the pixel it lies in only, with its weight from the filter.
+    pub fn add_sample_with_alpha(&mut self, pos: Point2f, spectrum: &S, alpha: Float) {
+        let p = Point2::new(pos.x.floor() as isize, pos.y.floor() as isize);
+        let sampled = if self.filter_sampling {
+            Some(self.filter.sample(Point2f::new(pos.x - p.x as Float, pos.y - p.y as Float)))
+        } else {
+            None
+        };
+        if let Some(ref mut raw) = self.raw {
+            raw.push(RawSample{
+                pos: match sampled {
+                    Some((offset, _)) => pidx_to_pcenter(p) + offset,
+                    None => pos,
+                },
+                spectrum: spectrum.to_srgb(),
+                alpha: alpha,
+            });
+        }
+        if self.bounding.contain_lb(p) {
+            self.stats.get_pixel_mut(p).add(spectrum.to_xyz().y);
+        }
+        if let Some((_, weight)) = sampled {
+            if self.sink.bounding.contain_lb(p) {
+                let pixel = unsafe {
+                    self.sink.get_pixel_mut_unchecked(p)
+                };
+                pixel.spectrum_sum += spectrum * weight;
+                pixel.filter_weight_sum += weight;
+                pixel.alpha_sum += alpha * weight;
+            }
+            return;
+        }
+        if self.filter_radius.x <= 0.5 as Float && self.filter_radius.y <= 0.5 as Float {
+            if self.sink.bounding.contain_lb(p) {
+                let pixel = unsafe {
+                    self.sink.get_pixel_mut_unchecked(p)
+                };
+                pixel.spectrum_sum += spectrum * 1. as Float;
+                pixel.filter_weight_sum += 1. as Float;
+                pixel.alpha_sum += alpha;
+            }
+            return;
+        }
+        // pixels whose centers lie within the filter's support
+        let half = Vector2f::new(0.5 as Float, 0.5 as Float);
+        let lo = pos.to_vec() - self.filter_radius - half;
+        let hi = pos.to_vec() + self.filter_radius - half;
+        let (x0, y0) = (lo.x.ceil() as isize, lo.y.ceil() as isize);
+        let (x1, y1) = (hi.x.floor() as isize + 1, hi.y.floor() as isize + 1);
+        if x0 >= x1 || y0 >= y1 { return; }
+        let filter_box = BBox2::new(Point2::new(x0, y0), Point2::new(x1, y1));
 
         if let Some(relavant_box) = filter_box.intersect(&self.sink.bounding) {
             for pixel_idx in relavant_box {
                 let pixel_pos = pidx_to_pcenter(pixel_idx);
                 let offset = Point2::from_vec(pixel_pos - pos);
-                let weight = unsafe {
-                    self.filter.evaluate_unsafe(offset)
+                let weight = match self.filter_table {
+                    Some(table) => table.evaluate(offset),
+                    None => unsafe {
+                        self.filter.evaluate_unsafe(offset)
+                    },
                 };
                 let pixel = unsafe {
                     self.sink.get_pixel_mut_unchecked(pixel_idx)
                 };
                 pixel.spectrum_sum += spectrum * weight;
                 pixel.filter_weight_sum += weight;
+                pixel.alpha_sum += alpha * weight;
             }
         }
     }
@@ -322,6 +802,207 @@ impl<'a, S> FilmTile<'a, S>
     pub fn bounding(&self) -> BBox2<isize> {
         self.bounding
     }
+
+    /// where a sample at `pfilm` should be traced through. With filter
+    /// sampling, that is the center of the pixel `pfilm` lies in offset by
+    /// the filter sampled with `pfilm`'s position within the pixel.
+    /// Otherwise `pfilm` itself.
+    #[inline]
+    pub fn jitter(&self, pfilm: Point2f) -> Point2f {
+        if !self.filter_sampling { return pfilm; }
+        let p = Point2::new(pfilm.x.floor() as isize, pfilm.y.floor() as isize);
+        let (offset, _) = self.filter.sample(Point2f::new(pfilm.x - p.x as Float, pfilm.y - p.y as Float));
+        pidx_to_pcenter(p) + offset
+    }
+
+    /// statistics of samples taken within pixel `p` of the tile
+    #[inline]
+    pub fn stats(&self, p: Point2<isize>) -> &SampleStats {
+        self.stats.get_pixel(p)
+    }
+}
+
+impl<'a, S> FilmTile<'a, S> {
+    /// number of samples taken within the tile
+    #[inline]
+    pub fn sample_count(&self) -> u64 {
+        self.bounding.into_iter().map(|p| self.stats.get_pixel(p).count as u64).sum()
+    }
+
+    /// start or stop recording the samples added to this tile
+    pub fn set_raw_samples(&mut self, raw_samples: bool) {
+        if raw_samples {
+            if self.raw.is_none() {
+                self.raw = Some(Vec::new());
+            }
+        } else {
+            self.raw = None;
+        }
+    }
+
+    /// take the samples recorded so far, leaving recording on
+    pub fn take_raw_samples(&mut self) -> Vec<RawSample> {
+        match self.raw {
+            Some(ref mut raw) => mem::replace(raw, Vec::new()),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// added to the squared mean luminance of a pixel when relating
+/// its variance to it, keeping dark pixels from dominating
+pub const RELATIVE_VARIANCE_EPSILON: Float = 1e-3;
+
+/// Unweighted statistics of the luminance of samples taken within a
+/// pixel, independent of how they are filtered onto the image
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SampleStats {
+    /// number of samples
+    pub count: u32,
+    /// mean luminance
+    pub mean: Float,
+    /// sum of squared deviations from the mean
+    pub m2: Float,
+}
+
+impl SampleStats {
+    /// add a sample of luminance `y`
+    #[inline]
+    pub fn add(&mut self, y: Float) {
+        self.count += 1;
+        let delta = y - self.mean;
+        self.mean += delta / self.count as Float;
+        self.m2 += delta * (y - self.mean);
+    }
+
+    /// unbiased estimate of the variance of the mean,
+    /// `None` with less than two samples
+    #[inline]
+    pub fn variance_of_mean(&self) -> Option<Float> {
+        if self.count < 2 { return None; }
+        let n = self.count as Float;
+        Some(self.m2 / ((n - 1. as Float) * n))
+    }
+
+    /// variance of the mean relative to its square
+    #[inline]
+    pub fn relative_variance(&self) -> Option<Float> {
+        self.variance_of_mean().map(|v| {
+            v / (self.mean * self.mean + RELATIVE_VARIANCE_EPSILON)
+        })
+    }
+}
+
+/// Unfiltered contributions splatted onto arbitrary positions of
+/// the film, e.g. by light paths connecting to the camera.
+/// Generated by `film.spawn_splats()`.
+///
+/// Splats are summed into a single film-sized buffer as they come in.
+/// Sums are kept in fixed point, whose additions are exact, such that
+/// they come out the same whatever order batches are added in.
+pub struct FilmSplats {
+    sums: Mutex<BoundedSink2D<[i64; 3]>>,
+}
+
+impl FilmSplats {
+    /// add a batch of `(pos, spectrum)` splats. Splats are rounded
+    /// to multiples of `2^-SPLAT_FRACTION_BITS` on their own.
+    pub fn add_splats(&self, splats: &[(Point2f, RGBSpectrumf)]) {
+        if splats.is_empty() { return; }
+        let mut sums = self.sums.lock().expect("poisoned splats");
+        for &(pos, spectrum) in splats {
+            let p: Point2<isize> = Point2::new(pos.x.floor() as isize, pos.y.floor() as isize);
+            if sums.bounding.contain_lb(p) {
+                let sum = sums.get_pixel_mut(p);
+                for (s, &v) in sum.iter_mut().zip(&[spectrum.r(), spectrum.g(), spectrum.b()]) {
+                    *s = s.saturating_add(to_fixed(v));
+                }
+            }
+        }
+    }
+}
+
+/// fractional bits of the fixed-point splat sums, resolving
+/// about `2e-10` while holding sums up to about `2e9`
+pub const SPLAT_FRACTION_BITS: i32 = 32;
+
+#[inline]
+fn to_fixed(v: Float) -> i64 {
+    // saturating, with NaNs mapped to zero
+    (v as f64 * (2. as f64).powi(SPLAT_FRACTION_BITS)) as i64
+}
+
+#[inline]
+fn from_fixed(v: i64) -> Float {
+    (v as f64 * (2. as f64).powi(-SPLAT_FRACTION_BITS)) as Float
+}
+
+/// A full-resolution image of a render in progress, filled in as
+/// tiles complete and saved periodically. Generated by `film.spawn_preview()`.
+///
+/// Only the part of a tile out of its neighbors' filter reach is
+/// written, as the rest is final only once its neighbors complete.
+/// The image collected from all tiles in the end should be saved
+/// over the preview.
+pub struct FilmPreview {
+    film: Film,
+    path: PathBuf,
+    interval: Duration,
+    state: Mutex<(Image, Option<Instant>)>,
+}
+
+impl FilmPreview {
+    /// write the finalized interior of a completed `tile`, saving
+    /// the preview if it hasn't been for the interval
+    pub fn add_tile<'a, S>(&self, tile: &FilmTile<'a, S>)
+        where S: Spectrum<Scalar=Float> + ops::Div<Float, Output=S> + PartialEq,
+              TilePixel<S>: Clone,
+    {
+        let interior = self.interior(tile.bounding);
+        let scale = self.film.exposure_scale();
+        let mut state = self.state.lock().expect("poisoned preview");
+        if let Some(interior) = interior {
+            let image = &mut state.0;
+            for p in interior {
+                let pixel = tile.sink.get_pixel(p).clone();
+                if let Some(ref mut alpha) = image.alpha {
+                    *alpha.get_pixel_mut(p) = pixel.finalize_alpha();
+                }
+                *image.inner.get_pixel_mut(p) = pixel.finalize().to_srgb() * scale;
+            }
+        }
+        let due = state.1.map_or(true, |last| last.elapsed() >= self.interval);
+        if due {
+            if let Err(e) = self.save(&state.0) {
+                warn!("Saving the preview at {:?} failed: {}", self.path, e);
+            }
+            state.1 = Some(Instant::now());
+        }
+    }
+
+    /// part of `bbox` no other tile's samples are filtered onto
+    fn interior(&self, bbox: BBox2<isize>) -> Option<BBox2<isize>> {
+        let window = self.film.view_window(bbox);
+        // samples reach pixels whose centers are closer than the radius
+        let rx = (self.film.filter_radius.x - 0.5 as Float).ceil().max(0. as Float) as isize;
+        let ry = (self.film.filter_radius.y - 0.5 as Float).ceil().max(0. as Float) as isize;
+        let mut ret = bbox;
+        if ret.pmin.x > window.pmin.x { ret.pmin.x += rx; }
+        if ret.pmin.y > window.pmin.y { ret.pmin.y += ry; }
+        if ret.pmax.x < window.pmax.x { ret.pmax.x -= rx; }
+        if ret.pmax.y < window.pmax.y { ret.pmax.y -= ry; }
+        if ret.pmin.x < ret.pmax.x && ret.pmin.y < ret.pmax.y { Some(ret) } else { None }
+    }
+
+    // save `image` next to the preview, then move it in place so
+    // that readers never see a partially written file
+    fn save(&self, image: &Image) -> Result<()> {
+        let stem = self.path.file_stem().and_then(|s| s.to_str()).unwrap_or("preview");
+        let ext = self.path.extension().and_then(|s| s.to_str()).unwrap_or("png");
+        let partial = self.path.with_file_name(format!(".{}.partial.{}", stem, ext));
+        image.save(&partial)?;
+        fs::rename(&partial, &self.path)
+    }
 }
 
 /// A pixel in film tile
@@ -329,6 +1010,7 @@ impl<'a, S> FilmTile<'a, S>
 pub struct TilePixel<S> {
     pub spectrum_sum: S,
     pub filter_weight_sum: Float,
+    pub alpha_sum: Float,
 }
 
 impl<S> TilePixel<S>
@@ -342,6 +1024,11 @@ impl<S> TilePixel<S>
             self.spectrum_sum / self.filter_weight_sum
         }
     }
+
+    /// get final coverage, transparent if no sample has been taken
+    pub fn finalize_alpha(&self) -> Float {
+        float::safe_div(self.alpha_sum, self.filter_weight_sum)
+    }
 }
 
 impl<S> Default for TilePixel<S>
@@ -351,43 +1038,172 @@ impl<S> Default for TilePixel<S>
         TilePixel{
             spectrum_sum: Default::default(),
             filter_weight_sum: 0.0 as Float,
+            alpha_sum: 0.0 as Float,
         }
     }
 }
 
+/// map `t` in $[0, 1]$ onto the viridis colormap, for false-coloring
+/// scalar quantities. `t` outside the range is clamped.
+pub fn viridis(t: Float) -> RGBSpectrumf {
+    const STOPS: [[Float; 3]; 9] = [
+        [0.267004, 0.004874, 0.329415],
+        [0.282623, 0.140926, 0.457517],
+        [0.229739, 0.322361, 0.545706],
+        [0.172719, 0.448791, 0.557885],
+        [0.127568, 0.566949, 0.550556],
+        [0.157851, 0.683765, 0.501686],
+        [0.369214, 0.788888, 0.382914],
+        [0.678489, 0.863742, 0.189503],
+        [0.993248, 0.906157, 0.143936],
+    ];
+    let t = if t.is_nan() { 0. as Float } else { float::clamp(t, 0. as Float, 1. as Float) };
+    let x = t * (STOPS.len() - 1) as Float;
+    let i = (x as usize).min(STOPS.len() - 2);
+    let f = x - i as Float;
+    let (a, b) = (STOPS[i], STOPS[i + 1]);
+    RGBSpectrumf::new(
+        a[0] + (b[0] - a[0]) * f,
+        a[1] + (b[1] - a[1]) * f,
+        a[2] + (b[2] - a[2]) * f,
+    )
+}
+
 /// A mighty image
 pub struct Image {
     inner: BoundedSink2D<RGBSpectrumf>,
+    alpha: Option<BoundedSink2D<Float>>,
 }
 
 impl Image {
     /// construct an image with default spectrum
     pub fn new(spectrum: RGBSpectrumf, dim: Point2<u32>) -> Image {
         Image{
-            inner: BoundedSink2D::with_value(spectrum, BBox2::new(Point2::new(0, 0), dim.cast()))
+            inner: BoundedSink2D::with_value(spectrum, BBox2::new(Point2::new(0, 0), dim.cast())),
+            alpha: None,
         }
     }
 
-    fn from_sink(sink: BoundedSink2D<TilePixel<RGBSpectrumf>>) -> Image {
-        let mut inner = BoundedSink2D::new(BBox2::new(Point2::new(0, 0), sink.bounding.pmax));
+    fn from_sink(sink: BoundedSink2D<TilePixel<RGBSpectrumf>>, alpha: bool, scale: Float) -> Image {
+        let bounding = BBox2::new(Point2::new(0, 0), sink.bounding.pmax);
+        let mut inner = BoundedSink2D::new(bounding);
+        let mut alpha = if alpha {
+            Some(BoundedSink2D::with_value(0. as Float, bounding))
+        } else {
+            None
+        };
         for p_idx in sink.bounding {unsafe {
-            *inner.get_pixel_mut_unchecked(p_idx) = sink.get_pixel(p_idx).finalize();
+            let pixel = sink.get_pixel(p_idx);
+            *inner.get_pixel_mut_unchecked(p_idx) = pixel.finalize() * scale;
+            if let Some(ref mut alpha) = alpha {
+                *alpha.get_pixel_mut_unchecked(p_idx) = pixel.finalize_alpha();
+            }
         }}
-        Image { inner: inner }
+        Image { inner: inner, alpha: alpha }
     }
 
-    /// save this image to `path`
-    pub fn save<P: AsRef<Path> + ?Sized>(&self, path: &P) -> Result<()> {
-        let mut support = Vec::with_capacity(self.inner.pixels.len() * 3);
-        for p in self.inner.bounding {
-            let s = unsafe {
-                self.inner.get_pixel_unchecked(p)
+    /// whether the image carries an alpha channel
+    #[inline]
+    pub fn has_alpha(&self) -> bool {
+        self.alpha.is_some()
+    }
+
+    /// coverage at `p`, opaque if the image carries no alpha channel
+    #[inline]
+    pub fn alpha(&self, p: Point2<u32>) -> Float {
+        self.alpha.as_ref().map_or(1. as Float, |alpha| *alpha.get_pixel(p.cast()))
+    }
+
+    /// dimension of the image
+    #[inline]
+    pub fn dimension(&self) -> Point2<u32> {
+        self.inner.bounding.pmax.cast()
+    }
+
+    /// an image of `width` by `height` pixels from `rgb`, holding the
+    /// RGB components of each pixel in row-major order. `None` if the
+    /// length of `rgb` does not match the dimensions
+    pub fn from_raw(width: usize, height: usize, rgb: Vec<Float>) -> Option<Image> {
+        if width == 0 || height == 0 || rgb.len() != width * height * 3 {
+            return None;
+        }
+        let pixels = rgb.chunks(3).map(|c| RGBSpectrumf::new(c[0], c[1], c[2])).collect();
+        Some(Image{
+            inner: BoundedSink2D{
+                pixels: pixels,
+                bounding: BBox2::new(Point2::new(0, 0), Point2::new(width as isize, height as isize)),
+            },
+            alpha: None,
+        })
+    }
+
+    /// the RGB components of each pixel in row-major order, along
+    /// with the width and height of the image
+    pub fn as_raw(&self) -> (&[Float], usize, usize) {
+        let dim = self.inner.bounding.pmax;
+        let raw = unsafe {
+            ::std::slice::from_raw_parts(
+                self.inner.pixels.as_ptr() as *const Float, self.inner.pixels.len() * 3
+            )
+        };
+        (raw, dim.x as usize, dim.y as usize)
+    }
+
+    /// 8-bit RGB channels in row-major order, of `tonemap` applied
+    /// to each pixel and clamped into [0, 1]
+    pub fn to_vec_rgb8<F>(&self, tonemap: F) -> Vec<u8>
+        where F: Fn(RGBSpectrumf) -> RGBSpectrumf
+    {
+        let mut ret = Vec::with_capacity(self.inner.pixels.len() * 3);
+        for &s in &self.inner.pixels {
+            let s = tonemap(s);
+            ret.push(<u8 as ToNorm>::from_norm_clamped(s.r()));
+            ret.push(<u8 as ToNorm>::from_norm_clamped(s.g()));
+            ret.push(<u8 as ToNorm>::from_norm_clamped(s.b()));
+        }
+        ret
+    }
+
+    /// 8-bit channels as saved, RGB or RGBA, with values clamped
+    /// into [0, 1]. Returns them along with the count of NaN
+    /// components, which are written as 0
+    pub fn to_ldr(&self) -> (Vec<u8>, usize) {
+        let channels = if self.alpha.is_some() { 4 } else { 3 };
+        let mut support = Vec::with_capacity(self.inner.pixels.len() * channels);
+        let mut nans = 0;
+        {
+            let mut push = |f: Float| {
+                if f.is_nan() { nans += 1; }
+                support.push(<u8 as ToNorm>::from_norm_clamped(f));
             };
-            support.push(ToNorm::from_norm(s.r()));
-            support.push(ToNorm::from_norm(s.g()));
-            support.push(ToNorm::from_norm(s.b()));
+            for p in self.inner.bounding {
+                let s = unsafe {
+                    self.inner.get_pixel_unchecked(p)
+                };
+                push(s.r());
+                push(s.g());
+                push(s.b());
+                if let Some(ref alpha) = self.alpha {
+                    push(unsafe { *alpha.get_pixel_unchecked(p) });
+                }
+            }
+        }
+        (support, nans)
+    }
+
+    /// save this image to `path`, as RGBA if it carries an alpha channel.
+    /// Values are clamped into [0, 1], NaNs are written as 0 and reported
+    pub fn save<P: AsRef<Path> + ?Sized>(&self, path: &P) -> Result<()> {
+        let (support, nans) = self.to_ldr();
+        if nans > 0 {
+            warn!("{} NaN components written as 0 to {}", nans, path.as_ref().display());
         }
-        image::save_buffer(path, support.as_slice(), self.inner.bounding.pmax.x as u32, self.inner.bounding.pmax.y as u32, image::ColorType::RGB(8))
+        let color = if self.alpha.is_some() {
+            image::ColorType::RGBA(8)
+        } else {
+            image::ColorType::RGB(8)
+        };
+        image::save_buffer(path, support.as_slice(), self.inner.bounding.pmax.x as u32, self.inner.bounding.pmax.y as u32, color)
     }
 }
 
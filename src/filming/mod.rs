@@ -10,6 +10,7 @@
 
 use geometry::prelude::*;
 use self::film::Film;
+use self::clip::ClipPlane;
 use spectrum::RGBSpectrumf;
 use lighting;
 pub type ImportanceSample = lighting::LightSample;
@@ -19,6 +20,8 @@ pub type ImportanceSample = lighting::LightSample;
 pub struct SampleInfo {
     pub pfilm: Point2f,
     pub plens: Point2f,
+    /// time within the camera's shutter interval, zero without a shutter
+    pub time: Float,
 }
 
 /// A camera!
@@ -49,6 +52,41 @@ pub trait Camera: Send + Sync {
     /// generate a camera viewing ray based on sample info
     fn generate_path(&self, sample_info: SampleInfo) -> RawRay;
 
+    /// the ray leaving the lens at `plens` towards the film point `pfilm`,
+    /// given in raster space, at time zero. `plens` is a uniform sample
+    /// in $[0, 1)^2$ mapped onto the aperture, with `(0.5, 0.5)` at the
+    /// center of circular ones.
+    ///
+    /// This is the mapping camera rays are generated with, meant for
+    /// tools such as picking; it's kept stable across releases.
+    #[inline]
+    fn ray_for_film_point(&self, pfilm: Point2f, plens: Point2f) -> RawRay {
+        self.generate_path(SampleInfo{
+            pfilm: pfilm,
+            plens: plens,
+            time: 0. as Float,
+        })
+    }
+
+    /// the film point, in raster space, that rays through the center of
+    /// the lens along `dirw` are generated for. `None` if they miss the film
+    fn film_point_for_direction(&self, dirw: Vector3f) -> Option<Point2f> {
+        let posw = self.view_to_parent().transform_point(Point3f::new(0. as Float, 0. as Float, 0. as Float));
+        self.evaluate_importance(posw, dirw).map(|(_, praster)| praster)
+    }
+
+    /// interval over which the shutter is open, if any
+    #[inline]
+    fn shutter(&self) -> Option<(Float, Float)> {
+        None
+    }
+
+    /// planes cutting geometry away from the camera's rays, none by default
+    #[inline]
+    fn clip_planes(&self) -> &[ClipPlane] {
+        &[]
+    }
+
     /// generate a differential camera viewing ray based on sample info
     fn generate_path_differential(&self, sample_info: SampleInfo) -> RayDifferential {
         let ray = self.generate_path(sample_info);
@@ -65,7 +103,7 @@ pub trait Camera: Send + Sync {
 
         RayDifferential{
             ray: ray,
-            diffs: Some((ray_dx, ray_dy)),
+            diffs: Some((ray_dx.with_time(ray.time()), ray_dy.with_time(ray.time()))),
         }
     }
 
@@ -81,7 +119,13 @@ pub trait Camera: Send + Sync {
 mod projective;
 pub mod ortho;
 pub mod perspective;
+pub mod stereo;
+pub mod aperture;
+pub mod exposure;
 pub mod film;
+pub mod dump;
+pub mod bloom;
+pub mod clip;
 pub mod prelude;
 #[cfg(test)]
 mod tests;
@@ -142,6 +142,7 @@ impl Camera for OrthoCam {
             pdf: pdf,
             pfrom: pfrom,
             pto: posw,
+            normal: if self.lens.is_some() { dir } else { Vector3f::zero() },
         }, praster)
     }
 
@@ -196,7 +197,7 @@ impl Camera for OrthoCam {
             );
         }
         // TODO: update ray medium
-        self.view_parent.transform_ray(&ray)
+        self.view_parent.transform_ray(&ray.with_kind(RayKind::Camera))
     }
 
     fn generate_path_differential(&self, sample_info: SampleInfo) -> RayDifferential {
@@ -220,7 +221,7 @@ impl Camera for OrthoCam {
         let rx = RawRay::from_od(ray.origin() + self.dx, ray.direction());
         let ry = RawRay::from_od(ray.origin() + self.dy, ray.direction());
         let ret = RayDifferential{
-            ray: ray,
+            ray: ray.with_kind(RayKind::Camera),
             diffs: Some((rx, ry)),
         };
         self.view_parent.transform_ray_differential(&ret)
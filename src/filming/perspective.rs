@@ -12,8 +12,11 @@ use geometry::prelude::*;
 use super::{Camera, SampleInfo, ImportanceSample};
 use super::projective::ProjCameraInfo;
 use super::film::Film;
+use super::aperture::Aperture;
+use super::clip::ClipPlane;
+use super::exposure::Exposure;
 use spectrum::{RGBSpectrumf, Spectrum};
-use sample;
+use renderer::scene::Scene;
 use std;
 use serde;
 use serde::{Serialize, Deserialize};
@@ -30,6 +33,13 @@ pub struct PerspecCam {
     dy: Vector3f,
     /// lens_radius, focal_distance; if presented
     lens: Option<(Float, Float)>,
+    aperture: Aperture,
+    /// shutter open and close time; if presented
+    shutter: Option<(Float, Float)>,
+    /// focal length the lens radius is derived from along with
+    /// the film's f-number; if presented
+    focal_length: Option<Float>,
+    clip_planes: Vec<ClipPlane>,
     film: Film,
     area: Float,
     znear: Float,
@@ -49,7 +59,77 @@ impl PerspecCam {
         film: Film
     ) -> PerspecCam {
         let view_parent = parent_view.inverse_transform().expect("matrix inversion failure");
-        let resolution = film.resolutionf();
+        let (proj_info, dx, dy, area) = PerspecCam::projection(
+            fov, znear, zfar, screen, film.resolutionf()
+        );
+        PerspecCam{
+            view_parent,
+            parent_view,
+            proj_info,
+            dx,
+            dy,
+            lens,
+            aperture: Aperture::Circle,
+            shutter: None,
+            focal_length: None,
+            clip_planes: Vec::new(),
+            film,
+            area,
+            znear,
+            zfar,
+            fov,
+        }
+    }
+
+    /// Construction of a thin lens camera exposing its film by `exposure`,
+    /// with a lens of `focal_length` focused at `focal_distance`.
+    ///
+    /// The lens radius follows from the focal length and the f-number,
+    /// such that changing the f-number changes the depth of field along
+    /// with the brightness.
+    pub fn physical(
+        parent_view: Matrix4f,
+        screen: BBox2f,
+        znear: Float,
+        zfar: Float,
+        fov: Float,
+        focal_length: Float,
+        focal_distance: Float,
+        exposure: Exposure,
+        film: Film
+    ) -> PerspecCam {
+        assert!(focal_length > 0. as Float, "non-positive focal length");
+        let mut ret = PerspecCam::new(
+            parent_view, screen, znear, zfar, fov,
+            Some((exposure.lens_radius(focal_length), focal_distance)), film
+        );
+        ret.focal_length = Some(focal_length);
+        ret.set_exposure(Some(exposure));
+        ret
+    }
+
+    /// set the film's photographic exposure, rederiving the lens
+    /// radius if it follows the f-number
+    pub fn set_exposure(&mut self, exposure: Option<Exposure>) {
+        self.film.set_exposure(exposure);
+        if let (Some(focal_length), Some(exposure)) = (self.focal_length, exposure) {
+            if let Some((_, focal_distance)) = self.lens {
+                self.lens = Some((exposure.lens_radius(focal_length), focal_distance));
+            }
+        }
+    }
+
+    /// focal length the lens radius follows the f-number by, if any
+    #[inline]
+    pub fn focal_length(&self) -> Option<Float> {
+        self.focal_length
+    }
+
+    /// compute the projection info, raster differentials and
+    /// image plane area for the given parameters
+    fn projection(
+        fov: Float, znear: Float, zfar: Float, screen: BBox2f, resolution: Vector2f
+    ) -> (ProjCameraInfo, Vector3f, Vector3f, Float) {
         let proj_info = ProjCameraInfo::new(
             PerspecCam::perspective_transform(fov, znear, zfar),
             screen, resolution
@@ -63,7 +143,7 @@ impl PerspecCam {
             Point3f::new(resolution.x, resolution.y, 0. as Float)
         );
         pview_max /= pview_max.z;
-        let area = (pview_max.x - pview_min.x)*(pview_max.y - pview_min.y);
+        let area = ((pview_max.x - pview_min.x)*(pview_max.y - pview_min.y)).abs();
 
         let or2v = proj_info.raster_view.transform_point(
             Point3f::new(1.0 as Float, 0.0 as Float, 0.0 as Float)
@@ -74,19 +154,20 @@ impl PerspecCam {
         let dy = proj_info.raster_view.transform_point(
             Point3f::new(0.0 as Float, 1.0 as Float, 0.0 as Float)
         ) - or2v;
-        PerspecCam{
-            view_parent,
-            parent_view,
-            proj_info,
-            dx,
-            dy,
-            lens,
-            film,
-            area,
-            znear,
-            zfar,
-            fov,
-        }
+        (proj_info, dx, dy, area)
+    }
+
+    /// resize the camera's film to `resolution`, keeping the
+    /// field of view and the crop window in NDC
+    pub fn set_resolution(&mut self, resolution: Point2<usize>) {
+        self.film.resize(resolution);
+        let (proj_info, dx, dy, area) = PerspecCam::projection(
+            self.fov, self.znear, self.zfar, self.proj_info.screen, self.film.resolutionf()
+        );
+        self.proj_info = proj_info;
+        self.dx = dx;
+        self.dy = dy;
+        self.area = area;
     }
 
     /// `fov` in radians
@@ -106,25 +187,191 @@ impl PerspecCam {
         Matrix4f::from_nonuniform_scale(inv_tan, inv_tan, one) * persp     
     }
 
+    /// set the shape of the lens' aperture
+    #[inline]
+    pub fn set_aperture(&mut self, aperture: Aperture) {
+        self.aperture = aperture;
+    }
+
+    /// get the shape of the lens' aperture
+    #[inline]
+    pub fn aperture(&self) -> &Aperture {
+        &self.aperture
+    }
+
+    /// set the interval over which the shutter is open, `None`
+    /// to trace every ray at time zero
+    #[inline]
+    pub fn set_shutter(&mut self, shutter: Option<(Float, Float)>) {
+        if let Some((open, close)) = shutter {
+            assert!(open <= close, "shutter closes before it opens");
+        }
+        self.shutter = shutter;
+    }
+
+    /// set the planes cutting geometry away from camera rays,
+    /// empty to see the whole scene
+    #[inline]
+    pub fn set_clip_planes(&mut self, clip_planes: Vec<ClipPlane>) {
+        self.clip_planes = clip_planes;
+    }
+
+    /// area density of sampling `plens` on the lens, in view space
+    #[inline]
+    fn lens_pdf(&self, plens: Point2f) -> Float {
+        if let Some((r, _)) = self.lens {
+            self.aperture.pdf(plens / r) / (r * r)
+        } else {
+            1. as Float
+        }
+    }
+
+    /// raster position of the ray leaving the lens from `pos` along `dir`,
+    /// along with `pos` in view space and the cosine of `dir` against the
+    /// viewing direction. `None` if the ray misses the film.
+    fn raster_position(&self, pos: Point3f, dir: Vector3f) -> Option<(Point2f, Point3f, Float)> {
+        let dir_view = self.parent_view.transform_vector(dir);
+        let costheta = dir_view.z;
+        if costheta <= 0. as Float { return None; }
+        // rays through the same raster position meet on the plane of focus
+        let focus_t = if let Some(lens) = self.lens {
+            lens.1 / costheta
+        } else {
+            1. as Float / costheta
+        };
+        let pos_view = self.parent_view.transform_point(pos);
+        let focus_view = pos_view + dir_view * focus_t;
+        let p_raster = (
+            self.proj_info.screen_raster*self.proj_info.view_screen
+        ).transform_point(focus_view);
+        let resolution = self.film.resolutionf();
+        if p_raster.x < 0. as Float || p_raster.y < 0. as Float
+            || p_raster.x >= resolution.x || p_raster.y >= resolution.y {
+            return None;
+        }
+        Some((Point2f::new(p_raster.x, p_raster.y), pos_view, costheta))
+    }
+
+    /// set the film's crop window, specified in NDC
+    #[inline]
+    pub fn set_crop_window(&mut self, crop_window: BBox2f) {
+        self.film.set_crop_window(crop_window);
+    }
+
+    /// set the lens radius and the focal distance. A zero `radius`
+    /// turns the camera into a pinhole. The radius no longer follows
+    /// the f-number afterwards
+    pub fn set_lens(&mut self, radius: Float, focal_distance: Float) {
+        assert!(radius >= 0. as Float, "negative lens radius");
+        assert!(focal_distance > 0. as Float, "non-positive focal distance");
+        self.focal_length = None;
+        self.lens = if radius > 0. as Float {
+            Some((radius, focal_distance))
+        } else {
+            None
+        };
+    }
+
+    /// lens radius and focal distance, if presented
+    #[inline]
+    pub fn lens(&self) -> Option<(Float, Float)> {
+        self.lens
+    }
+
+    /// focus the lens on whatever the ray through the center of the lens
+    /// towards `pfilm` hits, keeping the lens radius.
+    /// Returns the new focal distance, measured along the viewing direction
+    pub fn autofocus(&mut self, scene: &Scene, pfilm: Point2f) -> Result<Float, AutofocusError> {
+        let radius = if let Some((r, _)) = self.lens {
+            r
+        } else {
+            return Err(AutofocusError::Pinhole);
+        };
+        let pview = self.proj_info.raster_view.transform_point(
+            Point3f::new(pfilm.x, pfilm.y, 0. as Float)
+        );
+        let ray = RawRay::from_od(
+            Point3f::new(0. as Float, 0. as Float, 0. as Float), pview.to_vec().normalize()
+        );
+        let mut ray = self.view_parent.transform_ray(&ray).with_kind(RayKind::Camera);
+        let si = scene.intersect_clipped(&mut ray, &self.clip_planes).ok_or(AutofocusError::Missed)?;
+        let focal_distance = self.parent_view.transform_point(si.basic.pos).z;
+        if focal_distance <= 0. as Float {
+            return Err(AutofocusError::Missed);
+        }
+        self.lens = Some((radius, focal_distance));
+        Ok(focal_distance)
+    }
+
+    // `ray` in view space, starting at the near plane
+    #[inline]
+    fn clip_near(&self, ray: RawRay) -> RawRay {
+        let dz = ray.direction().z;
+        if dz > 0. as Float {
+            let tmin = (self.znear - ray.origin().z) / dz;
+            ray.with_min_extend(tmin.max(0. as Float))
+        } else {
+            ray
+        }
+    }
+
+    /// set the parent to view-space transform
+    pub fn set_transform(&mut self, parent_view: Matrix4f) {
+        self.view_parent = parent_view.inverse_transform().expect("matrix inversion failure");
+        self.parent_view = parent_view;
+    }
+
+    /// place the camera at `eye`, looking at `to` with `up` pointing
+    /// upwards on the film. An `up` parallel to the viewing direction
+    /// is replaced by the axis least aligned with it, as when orbiting
+    /// over a pole
     pub fn look_from(&mut self, eye: Point3f, to: Point3f, up: Vector3f) {
         let f = (to - eye).normalize();
-        let s = up.cross(f).normalize();
+        let mut s = up.cross(f);
+        if !(s.magnitude2() > 1e-12 as Float) {
+            let a = Vector3f::new(f.x.abs(), f.y.abs(), f.z.abs());
+            let axis = if a.x <= a.y && a.x <= a.z {
+                Vector3f::unit_x()
+            } else if a.y <= a.z {
+                Vector3f::unit_y()
+            } else {
+                Vector3f::unit_z()
+            };
+            s = axis.cross(f);
+        }
+        let s = s.normalize();
         let u = f.cross(s);
 
-        self.parent_view = Matrix4::new(
+        self.set_transform(Matrix4::new(
             s.x.clone(), u.x.clone(), f.x.clone(), Float::zero(),
             s.y.clone(), u.y.clone(), f.y.clone(), Float::zero(),
             s.z.clone(), u.z.clone(), f.z.clone(), Float::zero(),
             -eye.dot(s), -eye.dot(u), -eye.dot(f), Float::one()
-        );
-        self.view_parent = self.parent_view.inverse_transform().unwrap();
+        ));
     }
 }
 
+/// Reasons for `PerspecCam::autofocus` to fail
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AutofocusError {
+    /// the camera has no lens to focus
+    Pinhole,
+    /// nothing was hit towards the film point
+    Missed,
+}
+
+impl std::fmt::Display for AutofocusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            AutofocusError::Pinhole => f.write_str("a pinhole camera can't be focused"),
+            AutofocusError::Missed => f.write_str("nothing to focus on"),
+        }
+    }
+}
 
 impl Serialize for PerspecCam {
     fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
-        let mut state = s.serialize_struct("PerspecCam", 7)?;
+        let mut state = s.serialize_struct("PerspecCam", 11)?;
         state.serialize_field("transform", &self.parent_view)?;
         state.serialize_field("screen", &self.proj_info.screen)?;
         state.serialize_field("znear", &self.znear)?;
@@ -132,6 +379,10 @@ impl Serialize for PerspecCam {
         state.serialize_field("fov", &self.fov)?;
         state.serialize_field("lens", &self.lens)?;
         state.serialize_field("film", &self.film)?;
+        state.serialize_field("aperture", &self.aperture)?;
+        state.serialize_field("shutter", &self.shutter)?;
+        state.serialize_field("focal_length", &self.focal_length)?;
+        state.serialize_field("clip_planes", &self.clip_planes)?;
         state.end()
     }
 }
@@ -141,8 +392,8 @@ impl<'de> Deserialize<'de> for PerspecCam {
         where D: Deserializer<'de>
     {
         #[derive(Deserialize)]
-        #[serde(field_identifier, rename_all = "lowercase")]
-        enum Field { Transform, Screen, Znear, Zfar, Fov, Lens, Film }
+        #[serde(field_identifier, rename_all = "snake_case")]
+        enum Field { Transform, Screen, Znear, Zfar, Fov, Lens, Film, Aperture, Shutter, FocalLength, ClipPlanes }
 
         struct SamplerVisitor;
         impl<'de> Visitor<'de> for SamplerVisitor {
@@ -168,7 +419,16 @@ impl<'de> Deserialize<'de> for PerspecCam {
                     .ok_or_else(|| serde::de::Error::invalid_length(5, &self))?;
                 let film = seq.next_element()?
                     .ok_or_else(|| serde::de::Error::invalid_length(6, &self))?;
-                Ok(PerspecCam::new(transform, screen, znear, zfar, fov, lens, film))
+                let aperture = seq.next_element()?.unwrap_or_default();
+                let shutter = seq.next_element()?.unwrap_or_default();
+                let focal_length = seq.next_element()?.unwrap_or_default();
+                let clip_planes = seq.next_element()?.unwrap_or_default();
+                let mut camera = PerspecCam::new(transform, screen, znear, zfar, fov, lens, film);
+                camera.set_aperture(aperture);
+                camera.set_shutter(shutter);
+                camera.link_focal_length(focal_length)?;
+                camera.set_clip_planes(clip_planes);
+                Ok(camera)
             }
 
             fn visit_map<V>(self, mut map: V) -> Result<Self::Value, V::Error>
@@ -181,6 +441,10 @@ impl<'de> Deserialize<'de> for PerspecCam {
                 let mut fov = None;
                 let mut lens = None;
                 let mut film = None;
+                let mut aperture = None;
+                let mut shutter = None;
+                let mut focal_length = None;
+                let mut clip_planes = None;
                 while let Some(key) = map.next_key()? {
                     match key {
                         Field::Transform => {
@@ -225,6 +489,30 @@ impl<'de> Deserialize<'de> for PerspecCam {
                             }
                             film = Some(map.next_value()?);
                         }
+                        Field::Aperture => {
+                            if aperture.is_some() {
+                                return Err(serde::de::Error::duplicate_field("aperture"));
+                            }
+                            aperture = Some(map.next_value()?);
+                        }
+                        Field::Shutter => {
+                            if shutter.is_some() {
+                                return Err(serde::de::Error::duplicate_field("shutter"));
+                            }
+                            shutter = Some(map.next_value()?);
+                        }
+                        Field::FocalLength => {
+                            if focal_length.is_some() {
+                                return Err(serde::de::Error::duplicate_field("focal_length"));
+                            }
+                            focal_length = Some(map.next_value()?);
+                        }
+                        Field::ClipPlanes => {
+                            if clip_planes.is_some() {
+                                return Err(serde::de::Error::duplicate_field("clip_planes"));
+                            }
+                            clip_planes = Some(map.next_value()?);
+                        }
                     }
                 }
                 let transform = transform.ok_or_else(|| 
@@ -249,16 +537,43 @@ impl<'de> Deserialize<'de> for PerspecCam {
                     serde::de::Error::missing_field("film")
                 )?;
 
-                Ok(PerspecCam::new(
+                let mut camera = PerspecCam::new(
                     transform, screen, znear, zfar, fov, lens, film
-                ))
+                );
+                camera.set_aperture(aperture.unwrap_or_default());
+                camera.set_shutter(shutter.unwrap_or_default());
+                camera.link_focal_length(focal_length.unwrap_or_default())?;
+                camera.set_clip_planes(clip_planes.unwrap_or_default());
+                Ok(camera)
             }
         }
-        const FIELDS: &[&str] = &["transform", "screen", "znear", "zfar", "fov", "lens", "film"];
+        const FIELDS: &[&str] = &[
+            "transform", "screen", "znear", "zfar", "fov", "lens", "film", "aperture", "shutter", "focal_length",
+            "clip_planes"
+        ];
         deserializer.deserialize_struct("PerspecCam", FIELDS, SamplerVisitor)
     }
 }
 
+impl PerspecCam {
+    // derive the lens radius from `focal_length` and the film's f-number
+    // if both are presented, as deserialized
+    fn link_focal_length<E: serde::de::Error>(&mut self, focal_length: Option<Float>) -> Result<(), E> {
+        if let Some(focal_length) = focal_length {
+            if !(focal_length > 0. as Float) {
+                return Err(E::custom(format!("focal length should be positive, got {}", focal_length)));
+            }
+            if self.lens.is_none() {
+                return Err(E::custom("a focal length needs a lens to take the focal distance from"));
+            }
+            self.focal_length = Some(focal_length);
+            let exposure = self.film.exposure();
+            self.set_exposure(exposure);
+        }
+        Ok(())
+    }
+}
+
 impl Camera for PerspecCam {
     fn parent_to_view(&self) -> Matrix4f {
         self.parent_view
@@ -268,6 +583,16 @@ impl Camera for PerspecCam {
         self.view_parent
     }
 
+    #[inline]
+    fn shutter(&self) -> Option<(Float, Float)> {
+        self.shutter
+    }
+
+    #[inline]
+    fn clip_planes(&self) -> &[ClipPlane] {
+        &self.clip_planes
+    }
+
     fn generate_path(&self, sample_info: SampleInfo) -> RawRay {
         let pfilm = Point3f::new(sample_info.pfilm.x, sample_info.pfilm.y, 0.0 as Float);
         let pview = self.proj_info.raster_view.transform_point(pfilm);
@@ -276,7 +601,7 @@ impl Camera for PerspecCam {
         if let Some((r, d)) = self.lens {
             debug_assert!(r>0.0 as Float);
             debug_assert!(d>0.0 as Float);
-            let plens = r * sample::sample_concentric_disk(sample_info.plens);
+            let plens = r * self.aperture.sample(sample_info.plens);
             let ft = d/ray.direction().z;
             let pfocus = ray.evaluate(ft);
             let new_origin = Point3f::new(plens.x, plens.y, 0.0 as Float);
@@ -286,7 +611,9 @@ impl Camera for PerspecCam {
             );
         }
         // TODO: update ray medium
-        self.view_parent.transform_ray(&ray)
+        self.view_parent.transform_ray(
+            &self.clip_near(ray).with_kind(RayKind::Camera).with_time(sample_info.time)
+        )
     }
 
     fn generate_path_differential(&self, sample_info: SampleInfo) -> RayDifferential {
@@ -300,7 +627,7 @@ impl Camera for PerspecCam {
         if let Some((r, d)) = self.lens {
             debug_assert!(r>0.0 as Float);
             debug_assert!(d>0.0 as Float);
-            let plens = r * sample::sample_concentric_disk(sample_info.plens);
+            let plens = r * self.aperture.sample(sample_info.plens);
             let ft = d/ray.direction().z;
             let pfocus = ray.evaluate(ft);
             let new_origin = Point3f::new(plens.x, plens.y, 0.0 as Float);
@@ -310,10 +637,12 @@ impl Camera for PerspecCam {
             );
         }
         // TODO: account for lens
-        let rx = RawRay::from_od(ray.origin(), (pview.to_vec()+self.dx).normalize());
-        let ry = RawRay::from_od(ray.origin(), (pview.to_vec()+self.dy).normalize());
+        let rx = RawRay::from_od(ray.origin(), (pview.to_vec()+self.dx).normalize())
+            .with_time(sample_info.time);
+        let ry = RawRay::from_od(ray.origin(), (pview.to_vec()+self.dy).normalize())
+            .with_time(sample_info.time);
         let ret = RayDifferential{
-            ray: ray,
+            ray: self.clip_near(ray).with_kind(RayKind::Camera).with_time(sample_info.time),
             diffs: Some((rx, ry)),
         };
         self.view_parent.transform_ray_differential(&ret)
@@ -332,43 +661,28 @@ impl Camera for PerspecCam {
     fn evaluate_importance(
         &self, pos: Point3f, dir: Vector3f
     ) -> Option<(RGBSpectrumf, Point2f)> {
-        let p2v = self.parent_view;
-        let dir_view = p2v.transform_vector(dir);
-        let costheta = dir_view.z;
-        if costheta <= 0. as Float { return None; }
-        let focus_t = if let Some(lens) = self.lens {
-            lens.1 / costheta
-        } else {
-            1. as Float / costheta
-        };
-        let pos_view = p2v.transform_point(pos);
-        let focus_view = pos_view + dir_view * focus_t;
-        let p_raster = (
-            self.proj_info.screen_raster*self.proj_info.view_screen
-        ).transform_point(focus_view);
-        let p_raster = Point2::new(p_raster.x, p_raster.y);
-        
-        let bound: BBox2<isize> = BBox2::new(Point2::new(0, 0), self.film.resolution().cast());
-        if !bound.contain_lb(p_raster.cast()) { return None; }
-
+        let (p_raster, pos_view, costheta) = self.raster_position(pos, dir)?;
+        // $W_e=\frac{1}{A_{lens}A_{film}cos^4\theta}$, with $A_{film}$
+        // measured on the $z=1$ plane and a unit lens area for pinholes
         let costheta2 = costheta * costheta;
-        let lens_area = if let Some(lens) = self.lens {
-            float::pi() * lens.0 * lens.0
-        } else {
-            1. as Float
-        };
-        let importance = 1. as Float / (self.area * lens_area * costheta2 * costheta2);
+        let lens_pdf = self.lens_pdf(Point2f::new(pos_view.x, pos_view.y));
+        let importance = lens_pdf / (self.area * costheta2 * costheta2);
         Some((
             RGBSpectrumf::new(importance, importance, importance),
             p_raster
         ))
     }
 
+    fn film_point_for_direction(&self, dirw: Vector3f) -> Option<Point2f> {
+        let posw = self.view_parent.transform_point(Point3f::new(0. as Float, 0. as Float, 0. as Float));
+        self.raster_position(posw, dirw).map(|(p_raster, _, _)| p_raster)
+    }
+
     fn evaluate_importance_sampled(
         &self, posw: Point3f, sample: Point2f
     ) -> (ImportanceSample, Point2f) {
         let plens = if let Some((r, _)) = self.lens {
-            r* sample::sample_concentric_disk(sample)
+            r * self.aperture.sample(sample)
         } else {
             Point2f::new(0. as Float, 0. as Float)
         };
@@ -379,57 +693,35 @@ impl Camera for PerspecCam {
         let mut dir = pfrom - pto;
         let dist2 = dir.magnitude2();
         dir /= dist2.sqrt();
-        let (importance, praster) = if let Some((i, pr)) = self.evaluate_importance(pto, -dir) {
+        // nothing closer than the near plane is seen
+        let depth = self.parent_view.transform_point(pto).z;
+        let seen = if depth >= self.znear { self.evaluate_importance(pfrom, -dir) } else { None };
+        let (importance, praster) = if let Some((i, pr)) = seen {
             (i, pr)
         } else {
             (RGBSpectrumf::black(), Point2f::new(0. as Float, 0. as Float))
         };
-        let pdf = if let Some((r, _)) = self.lens {
-            let norm = self.view_parent.transform_vector(
-                Vector3f::new(0. as Float, 0. as Float, 1. as Float)
-            );
-            dist2 / (dir.dot(norm).abs()*r*r*float::pi())
-        } else {
-            1. as Float
-        };
+        let norm = self.view_parent.transform_vector(
+            Vector3f::new(0. as Float, 0. as Float, 1. as Float)
+        );
+        let pdf = dist2 * self.lens_pdf(plens) / dir.dot(norm).abs();
         (ImportanceSample{
             radiance: importance,
             pdf: pdf,
             pfrom: pfrom,
             pto: posw,
+            normal: if self.lens.is_some() { norm.normalize() } else { Vector3f::zero() },
         }, praster)
     }
 
     fn pdf(&self, pos: Point3f, dir: Vector3f) -> (Float, Float) {
-        let ret = (0. as Float, 0. as Float);
-        let p2v = self.parent_view;
-        let dir_view = p2v.transform_vector(dir);
-        let costheta = dir_view.z;
-        if costheta <= 0. as Float { return ret; }
-        let focus_t = if let Some(lens) = self.lens {
-            lens.1 / costheta
+        if let Some((_, pos_view, costheta)) = self.raster_position(pos, dir) {
+            (
+                self.lens_pdf(Point2f::new(pos_view.x, pos_view.y)), // pdfpos
+                1. as Float/(self.area * costheta * costheta * costheta) // pdfdir
+            )
         } else {
-            1. as Float / costheta
-        };
-        let pos_view = p2v.transform_point(pos);
-        let focus_view = pos_view + dir_view * focus_t;
-        let p_raster = (
-            self.proj_info.screen_raster*self.proj_info.view_screen
-        ).transform_point(focus_view);
-        let p_raster = Point2::new(p_raster.x, p_raster.y);
-        
-        let bound: BBox2<isize> = BBox2::new(Point2::new(0, 0), self.film.resolution().cast());
-        if !bound.contain_lb(p_raster.cast()) { return ret; }
-
-        let lens_area = if let Some(lens) = self.lens {
-            float::pi() * lens.0 * lens.0
-        } else {
-            1. as Float
-        };
-
-        (
-            1. as Float/lens_area, // pdfpos
-            1. as Float/(self.area * costheta * costheta * costheta) // pdfdir
-        )
+            (0. as Float, 0. as Float)
+        }
     }
 }
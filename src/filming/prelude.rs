@@ -9,6 +9,12 @@
 pub use super::Camera;
 pub use super::film::Film;
 pub use super::ortho::OrthoCam;
-pub use super::perspective::PerspecCam;
+pub use super::perspective::{PerspecCam, AutofocusError};
+pub use super::stereo::StereoCam;
+pub use super::aperture::{Aperture, ApertureMask};
+pub use super::exposure::Exposure;
+pub use super::dump::{SampleDump, SampleDumpWriter, refilter};
+pub use super::bloom::Bloom;
+pub use super::clip::ClipPlane;
 pub use super::ImportanceSample;
 
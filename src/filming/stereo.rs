@@ -0,0 +1,177 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! defines a side-by-side stereo camera
+
+use geometry::prelude::*;
+use super::{Camera, SampleInfo, ImportanceSample};
+use super::perspective::PerspecCam;
+use super::film::Film;
+use super::clip::ClipPlane;
+use spectrum::RGBSpectrumf;
+
+/// A pair of parallel perspective cameras, offset along the view-space
+/// x axis, rendering side by side onto a film of double width.
+///
+/// The left half of the film is seen by the eye at negative x, the
+/// right half by the eye at positive x. Filters never bleed across the
+/// two halves.
+#[derive(Clone)]
+pub struct StereoCam {
+    eyes: [PerspecCam; 2],
+    center: Matrix4f,
+    interocular: Float,
+    film: Film,
+}
+
+impl StereoCam {
+    /// construction from the camera at the center of the eyes,
+    /// whose film resolution is used for each eye
+    pub fn new(camera: PerspecCam, interocular: Float) -> StereoCam {
+        assert!(interocular >= 0. as Float, "negative interocular distance");
+        let center = camera.parent_to_view();
+        let mut film = camera.get_film().clone();
+        let resolution = film.resolution();
+        film.resize(Point2::new(resolution.x * 2, resolution.y));
+        film.set_seam(Some(resolution.x as isize));
+        let mut left = camera.clone();
+        let mut right = camera;
+        let offset = interocular * 0.5 as Float;
+        left.set_transform(
+            Matrix4f::from_translation(Vector3f::new(offset, 0. as Float, 0. as Float)) * center
+        );
+        right.set_transform(
+            Matrix4f::from_translation(Vector3f::new(-offset, 0. as Float, 0. as Float)) * center
+        );
+        StereoCam{
+            eyes: [left, right],
+            center: center,
+            interocular: interocular,
+            film: film,
+        }
+    }
+
+    /// the camera of the left eye, followed by the right one
+    #[inline]
+    pub fn eyes(&self) -> &[PerspecCam; 2] {
+        &self.eyes
+    }
+
+    /// distance between the eyes
+    #[inline]
+    pub fn interocular(&self) -> Float {
+        self.interocular
+    }
+
+    /// width of each eye's half of the film, in raster space
+    #[inline]
+    fn eye_width(&self) -> Float {
+        self.eyes[0].get_film().resolutionf().x
+    }
+
+    /// the eye seeing `pfilm`, with `pfilm` in that eye's raster space
+    #[inline]
+    fn eye_of(&self, pfilm: Point2f) -> (usize, Point2f) {
+        let width = self.eye_width();
+        if pfilm.x < width {
+            (0, pfilm)
+        } else {
+            (1, Point2f::new(pfilm.x - width, pfilm.y))
+        }
+    }
+
+    /// the eye whose center is the closest to `posw`
+    fn eye_near(&self, posw: Point3f) -> usize {
+        let origin = Point3f::new(0. as Float, 0. as Float, 0. as Float);
+        let d0 = (self.eyes[0].view_to_parent().transform_point(origin) - posw).magnitude2();
+        let d1 = (self.eyes[1].view_to_parent().transform_point(origin) - posw).magnitude2();
+        if d0 <= d1 { 0 } else { 1 }
+    }
+
+    /// `praster` of eye `eye` in the raster space of the whole film
+    #[inline]
+    fn to_film(&self, eye: usize, praster: Point2f) -> Point2f {
+        if eye == 0 {
+            praster
+        } else {
+            Point2f::new(praster.x + self.eye_width(), praster.y)
+        }
+    }
+}
+
+impl Camera for StereoCam {
+    #[inline]
+    fn parent_to_view(&self) -> Matrix4f {
+        self.center
+    }
+
+    fn generate_path(&self, sample_info: SampleInfo) -> RawRay {
+        let (eye, pfilm) = self.eye_of(sample_info.pfilm);
+        self.eyes[eye].generate_path(SampleInfo{
+            pfilm: pfilm,
+            ..sample_info
+        })
+    }
+
+    fn generate_path_differential(&self, sample_info: SampleInfo) -> RayDifferential {
+        let (eye, pfilm) = self.eye_of(sample_info.pfilm);
+        self.eyes[eye].generate_path_differential(SampleInfo{
+            pfilm: pfilm,
+            ..sample_info
+        })
+    }
+
+    #[inline]
+    fn shutter(&self) -> Option<(Float, Float)> {
+        self.eyes[0].shutter()
+    }
+
+    #[inline]
+    fn clip_planes(&self) -> &[ClipPlane] {
+        self.eyes[0].clip_planes()
+    }
+
+    #[inline]
+    fn get_film(&self) -> &Film {
+        &self.film
+    }
+
+    #[inline]
+    fn get_film_mut(&mut self) -> &mut Film {
+        &mut self.film
+    }
+
+    fn evaluate_importance(
+        &self, posw: Point3f, dirw: Vector3f
+    ) -> Option<(RGBSpectrumf, Point2f)> {
+        let eye = self.eye_near(posw);
+        self.eyes[eye].evaluate_importance(posw, dirw)
+            .map(|(importance, praster)| (importance, self.to_film(eye, praster)))
+    }
+
+    fn evaluate_importance_sampled(
+        &self, posw: Point3f, sample: Point2f
+    ) -> (ImportanceSample, Point2f) {
+        // either eye is chosen with equal probability
+        let (eye, u) = if sample.x < 0.5 as Float {
+            (0, sample.x * 2. as Float)
+        } else {
+            (1, sample.x * 2. as Float - 1. as Float)
+        };
+        let u = u.min(float::one_minus_epsilon());
+        let (mut is, praster) = self.eyes[eye].evaluate_importance_sampled(
+            posw, Point2f::new(u, sample.y)
+        );
+        is.pdf *= 0.5 as Float;
+        (is, self.to_film(eye, praster))
+    }
+
+    fn pdf(&self, posw: Point3f, dirw: Vector3f) -> (Float, Float) {
+        self.eyes[self.eye_near(posw)].pdf(posw, dirw)
+    }
+}
@@ -46,4 +46,1243 @@ mod test_projective {
     //     // let pixel_v = screen_view.transform_point(pixel_s);
     //     // assert_ulps_eq!(pixel_s, Point3f::new(1. as Float, -1. as Float, 1.0 as Float));
     // }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test_aperture {
+    use super::*;
+    use super::aperture::*;
+    use super::perspective::PerspecCam;
+    use sample::filters::BoxFilter;
+    use test_utils::{self, RendererKind, TestScene};
+    use prelude::*;
+    use component::ComponentPointer;
+    use std::sync::Arc;
+    use rand::{Rng, StdRng};
+
+    // sampled points must be supported, and the area estimated through
+    // their pdfs should agree with the reported area
+    fn check_aperture(aperture: &Aperture) {
+        let mut rng = StdRng::new().unwrap();
+        let n = 10000;
+        let mut sum = 0. as Float;
+        for _ in 0..n {
+            let p = aperture.sample(Point2f::new(rng.gen(), rng.gen()));
+            let pdf = aperture.pdf(p);
+            assert!(pdf > 0. as Float, "{:?} sampled unsupported {:?}", aperture, p);
+            sum += 1. as Float / pdf;
+        }
+        assert_relative_eq!(sum / n as Float, aperture.area(), max_relative = 1e-2 as Float);
+        let mut covered = 0;
+        for _ in 0..n {
+            let p = Point2f::new(rng.gen::<Float>() * 2. as Float - 1. as Float, rng.gen::<Float>() * 2. as Float - 1. as Float);
+            if aperture.pdf(p) > 0. as Float { covered += 1; }
+        }
+        assert_relative_eq!(4. as Float * covered as Float / n as Float, aperture.area(), max_relative = 5e-2 as Float);
+    }
+
+    #[test]
+    fn test_aperture_sampling() {
+        check_aperture(&Aperture::Circle);
+        check_aperture(&Aperture::Bladed{count: 6, rotation: 0.3 as Float});
+        check_aperture(&Aperture::Bladed{count: 3, rotation: 0. as Float});
+        let mut values = vec![0. as Float; 64];
+        for v in values.iter_mut().skip(32) { *v = 1. as Float; }
+        let mask = ApertureMask::new(values, 8);
+        assert_relative_eq!(mask.area(), 2. as Float);
+        check_aperture(&Aperture::Image(Arc::new(mask)));
+    }
+
+    // brightness at the center of an out-of-focus highlight
+    fn bokeh_center(aperture: Aperture) -> Float {
+        let resolution = 32;
+        let film = Film::new(
+            Point2::new(resolution, resolution),
+            BBox2f::new(Point2f::new(0. as Float, 0. as Float), Point2f::new(1. as Float, 1. as Float)),
+            Arc::new(BoxFilter::new(Vector2f::new(0.5 as Float, 0.5 as Float)))
+        );
+        let mut camera = PerspecCam::new(
+            Matrix4f::identity(),
+            BBox2f::new(Point2f::new(-1. as Float, -1. as Float), Point2f::new(1. as Float, 1. as Float)),
+            0.01 as Float, 1000. as Float, float::frac_pi_4(), Some((0.2 as Float, 1. as Float)), film
+        );
+        camera.look_from(
+            Point3f::new(0. as Float, 0. as Float, 0. as Float),
+            Point3f::new(0. as Float, 0. as Float, 1. as Float),
+            Vector3f::new(0. as Float, 1. as Float, 0. as Float)
+        );
+        camera.set_aperture(aperture);
+        let p = |x: Float, y: Float| Point3f::new(x, y, 10. as Float);
+        let light = test_utils::quad(
+            [p(-1., -1.), p(1., -1.), p(1., 1.), p(-1., 1.)],
+            test_utils::matte(0. as Float, 0. as Float, 0. as Float),
+            Some(RGBSpectrumf::new(1. as Float, 1. as Float, 1. as Float))
+        );
+        let components: Vec<ComponentPointer> = TriangleMesh::instances(&light)
+            .map(|t| t.into()).collect();
+        let lights: Vec<Arc<Light>> = vec![Arc::new(MeshLight::new(light))];
+        let scene = TestScene{
+            scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+            camera: Arc::new(camera),
+        };
+        let image = test_utils::render(&scene, RendererKind::Whitted, 256);
+        let mut sum = 0. as Float;
+        for y in 15..18 {
+            for x in 15..18 {
+                sum += image[(x, y)].g();
+            }
+        }
+        sum / 9. as Float
+    }
+
+    #[test]
+    fn test_bladed_bokeh() {
+        // highlights spread over a smaller hexagon come out brighter
+        let circle = bokeh_center(Aperture::Circle);
+        let hexagon = bokeh_center(Aperture::Bladed{count: 6, rotation: 0. as Float});
+        let expected = Aperture::Circle.area() / Aperture::Bladed{count: 6, rotation: 0. as Float}.area();
+        assert_relative_eq!(hexagon / circle, expected, max_relative = 0.08 as Float);
+    }
+}
+
+#[cfg(test)]
+mod test_resize {
+    use super::*;
+    use super::perspective::PerspecCam;
+    use super::film::FilmTile;
+    use sample::filters::BoxFilter;
+    use std::sync::Arc;
+
+    fn camera(resolution: Point2<usize>) -> PerspecCam {
+        let film = Film::new(
+            resolution,
+            BBox2f::new(Point2f::new(0. as Float, 0. as Float), Point2f::new(1. as Float, 1. as Float)),
+            Arc::new(BoxFilter::new(Vector2f::new(0.5 as Float, 0.5 as Float)))
+        );
+        let mut camera = PerspecCam::new(
+            Matrix4f::identity(),
+            BBox2f::new(Point2f::new(-1. as Float, -0.75 as Float), Point2f::new(1. as Float, 0.75 as Float)),
+            0.01 as Float, 1000. as Float, float::frac_pi_2(), None, film
+        );
+        camera.look_from(
+            Point3f::new(0. as Float, 0. as Float, 0. as Float),
+            Point3f::new(0. as Float, 0. as Float, 1. as Float),
+            Vector3f::new(0. as Float, 1. as Float, 0. as Float)
+        );
+        camera
+    }
+
+    // raster positions of a chart of dots, normalized by the resolution,
+    // along with their importance
+    fn dot_chart(camera: &PerspecCam) -> Vec<(Point2f, Float)> {
+        let resf = camera.get_film().resolutionf();
+        let mut ret = Vec::new();
+        for iy in -2..3 {
+            for ix in -3..4 {
+                let p = Point3f::new(ix as Float * 0.8 as Float, iy as Float * 0.8 as Float, 4. as Float);
+                let (sample, praster) = camera.evaluate_importance_sampled(p, Point2f::new(0.5 as Float, 0.5 as Float));
+                ret.push((Point2f::new(praster.x / resf.x, praster.y / resf.y), sample.radiance.g()));
+            }
+        }
+        ret
+    }
+
+    #[test]
+    fn test_set_resolution() {
+        let small = camera(Point2::new(32, 24));
+        let mut resized = small.clone();
+        resized.set_resolution(Point2::new(64, 48));
+        let fresh = camera(Point2::new(64, 48));
+        assert_eq!(resized.get_film().resolution(), Point2::new(64, 48));
+        let small = dot_chart(&small);
+        for (r, f) in dot_chart(&resized).into_iter().zip(dot_chart(&fresh)) {
+            assert_relative_eq!(r.0, f.0);
+            assert_relative_eq!(r.1, f.1);
+        }
+        for (r, s) in dot_chart(&resized).into_iter().zip(small) {
+            assert!(r.1 > 0. as Float);
+            assert_relative_eq!(r.0, s.0, max_relative = 1e-4 as Float);
+            assert_relative_eq!(r.1, s.1, max_relative = 1e-4 as Float);
+        }
+    }
+
+    #[test]
+    fn test_crop_tiles() {
+        let mut camera = camera(Point2::new(64, 48));
+        camera.set_crop_window(BBox2f::new(
+            Point2f::new(0.25 as Float, 0.5 as Float), Point2f::new(1. as Float, 1. as Float)
+        ));
+        camera.set_resolution(Point2::new(32, 24));
+        let film = camera.get_film();
+        assert_relative_eq!(film.crop_window().pmin, Point2f::new(0.25 as Float, 0.5 as Float));
+        let tiles: Vec<FilmTile<RGBSpectrumf>> = film.spawn_tiles(8);
+        let mut covered = 0;
+        for tile in &tiles {
+            let bbox = tile.bounding();
+            assert!(bbox.pmin.x >= 8 && bbox.pmin.y >= 12);
+            assert!(bbox.pmax.x <= 32 && bbox.pmax.y <= 24);
+            covered += (bbox.pmax.x - bbox.pmin.x) * (bbox.pmax.y - bbox.pmin.y);
+        }
+        assert_eq!(covered, 24 * 12);
+    }
+}
+
+#[cfg(test)]
+mod test_importance {
+    use super::*;
+    use super::perspective::PerspecCam;
+    use sample::{self, filters::BoxFilter};
+    use std::sync::Arc;
+    use rand::{Rng, SeedableRng, StdRng};
+
+    fn camera(lens: Option<(Float, Float)>) -> PerspecCam {
+        let film = Film::new(
+            Point2::new(32, 24),
+            BBox2f::new(Point2f::new(0. as Float, 0. as Float), Point2f::new(1. as Float, 1. as Float)),
+            Arc::new(BoxFilter::new(Vector2f::new(0.5 as Float, 0.5 as Float)))
+        );
+        let mut camera = PerspecCam::new(
+            Matrix4f::identity(),
+            BBox2f::new(Point2f::new(-1. as Float, -0.75 as Float), Point2f::new(1. as Float, 0.75 as Float)),
+            0.01 as Float, 1000. as Float, 1.2 as Float, lens, film
+        );
+        camera.look_from(
+            Point3f::new(1. as Float, 2. as Float, 3. as Float),
+            Point3f::new(0. as Float, 0. as Float, 0. as Float),
+            Vector3f::new(0. as Float, 1. as Float, 0. as Float)
+        );
+        camera
+    }
+
+    // integrate `We(p, w)cos(w)` over directions and lens positions,
+    // which should come out as 1, along with `pdfdir` over directions
+    fn integrate(camera: &PerspecCam) -> (Float, Float) {
+        let mut rng = StdRng::from_seed(&[5usize][..]);
+        let forward = camera.view_to_parent().transform_vector(Vector3f::new(0. as Float, 0., 1.));
+        let n = 200000;
+        let (mut importance, mut pdfdir) = (0. as Float, 0. as Float);
+        for _ in 0..n {
+            // a lens position as sampled by the camera, weighted by its pdf
+            let ray = camera.generate_path(SampleInfo{
+                pfilm: Point2f::new(16. as Float, 12. as Float),
+                plens: Point2f::new(rng.gen(), rng.gen()),
+                time: 0. as Float,
+            });
+            let pos = ray.origin();
+            let dir = sample::sample_uniform_sphere(Point2f::new(rng.gen(), rng.gen()));
+            let inv_pdf = float::pi() * 4. as Float;
+            let (pdfpos, pdf) = camera.pdf(pos, dir);
+            if let Some((we, _)) = camera.evaluate_importance(pos, dir) {
+                assert!(pdfpos > 0. as Float);
+                importance += we.g() * dir.dot(forward) * inv_pdf / pdfpos;
+            }
+            pdfdir += pdf * inv_pdf;
+        }
+        (importance / n as Float, pdfdir / n as Float)
+    }
+
+    #[test]
+    fn test_importance_normalized() {
+        for lens in &[None, Some((0.2 as Float, 4. as Float))] {
+            let camera = camera(*lens);
+            let (importance, pdfdir) = integrate(&camera);
+            assert_relative_eq!(importance, 1. as Float, epsilon = 0.02);
+            assert_relative_eq!(pdfdir, 1. as Float, epsilon = 0.02);
+        }
+    }
+
+    #[test]
+    fn test_importance_sampled() {
+        let mut rng = StdRng::from_seed(&[6usize][..]);
+        for lens in &[None, Some((0.2 as Float, 4. as Float))] {
+            let camera = camera(*lens);
+            for _ in 0..64 {
+                let p = Point3f::new(rng.gen_range(-1. as Float, 1.), rng.gen_range(-1. as Float, 1.), rng.gen_range(-1. as Float, 1.));
+                let (sample, praster) = camera.evaluate_importance_sampled(p, Point2f::new(rng.gen(), rng.gen()));
+                if sample.radiance.is_black() { continue; }
+                // matching the importance along the sampled direction
+                let (we, pr) = camera.evaluate_importance(sample.pfrom, -sample.wi()).unwrap();
+                assert_relative_eq!(we.g(), sample.radiance.g(), max_relative = 1e-3);
+                assert_relative_eq!(pr, praster, epsilon = 1e-2);
+                // and the area pdf of the lens converted into solid angle
+                let (pdfpos, _) = camera.pdf(sample.pfrom, -sample.wi());
+                let forward = camera.view_to_parent().transform_vector(Vector3f::new(0. as Float, 0., 1.));
+                let dist2 = (sample.pfrom - p).magnitude2();
+                assert_relative_eq!(sample.pdf, pdfpos * dist2 / sample.wi().dot(forward).abs(), max_relative = 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn test_film_point_round_trip() {
+        let center = Point2f::new(0.5 as Float, 0.5 as Float);
+        for lens in &[None, Some((0.2 as Float, 4. as Float))] {
+            let camera = camera(*lens);
+            let mut seen = 0;
+            for i in 0..17 {
+                for j in 0..17 {
+                    // a grid of directions over the frustum and beyond
+                    let dir_view = Vector3f::new(
+                        (i as Float - 8. as Float) * 0.1 as Float, (j as Float - 8. as Float) * 0.1 as Float, 1. as Float
+                    ).normalize();
+                    let dir = camera.view_to_parent().transform_vector(dir_view);
+                    let pfilm = match camera.film_point_for_direction(dir) {
+                        Some(pfilm) => pfilm,
+                        None => continue,
+                    };
+                    seen += 1;
+                    let ray = camera.ray_for_film_point(pfilm, center);
+                    assert_relative_eq!(ray.direction(), dir, epsilon = 1e-4);
+                    let back = camera.film_point_for_direction(ray.direction()).unwrap();
+                    assert!((back - pfilm).magnitude() < 1. as Float, "{:?} came back as {:?}", pfilm, back);
+                    // rays leaving elsewhere on the lens are seen at the same point
+                    let ray = camera.ray_for_film_point(pfilm, Point2f::new(0.1 as Float, 0.8 as Float));
+                    let (_, back) = camera.evaluate_importance(ray.origin(), ray.direction()).unwrap();
+                    assert!((back - pfilm).magnitude() < 1. as Float, "{:?} came back as {:?}", pfilm, back);
+                }
+            }
+            assert!(seen > 100, "only {} directions hit the film", seen);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_autofocus {
+    use super::*;
+    use super::perspective::{PerspecCam, AutofocusError};
+    use sample::filters::BoxFilter;
+    use test_utils;
+    use prelude::*;
+    use component::ComponentPointer;
+    use std::sync::Arc;
+
+    // a wall at `z = 0` viewed by a camera at `z = -depth`
+    fn setup(depth: Float, lens: Option<(Float, Float)>) -> (Scene, PerspecCam) {
+        let film = Film::new(
+            Point2::new(16, 16),
+            BBox2f::new(Point2f::new(0. as Float, 0. as Float), Point2f::new(1. as Float, 1. as Float)),
+            Arc::new(BoxFilter::new(Vector2f::new(0.5 as Float, 0.5 as Float)))
+        );
+        let mut camera = PerspecCam::new(
+            Matrix4f::identity(),
+            BBox2f::new(Point2f::new(-1. as Float, -1. as Float), Point2f::new(1. as Float, 1. as Float)),
+            0.01 as Float, 1000. as Float, float::frac_pi_3(), lens, film
+        );
+        camera.look_from(
+            Point3f::new(0. as Float, 0. as Float, -depth),
+            Point3f::new(0. as Float, 0. as Float, 0. as Float),
+            Vector3f::new(0. as Float, 1. as Float, 0. as Float)
+        );
+        let p = |x: Float, y: Float| Point3f::new(x, y, 0. as Float);
+        let wall = test_utils::quad(
+            [p(-10., -10.), p(10., -10.), p(10., 10.), p(-10., 10.)],
+            test_utils::matte(0.5 as Float, 0.5 as Float, 0.5 as Float),
+            None
+        );
+        let components: Vec<ComponentPointer> = TriangleMesh::instances(&wall)
+            .map(|t| t.into()).collect();
+        (Scene::new(Vec::new(), Arc::new(BVH::new(&components, BVHStrategy::SAH))), camera)
+    }
+
+    // spread of the points on the wall seen through `pfilm`
+    // from different positions on the lens
+    fn blur(scene: &Scene, camera: &PerspecCam, pfilm: Point2f) -> Float {
+        let mut hits = Vec::new();
+        for i in 0..8 {
+            for j in 0..8 {
+                let plens = Point2f::new((i as Float + 0.5) / 8., (j as Float + 0.5) / 8.);
+                let mut ray = camera.generate_path(SampleInfo{pfilm: pfilm, plens: plens, time: 0. as Float});
+                let si = scene.aggregate.intersect_ray(&mut ray).unwrap();
+                hits.push(si.basic.pos);
+            }
+        }
+        let mut spread = 0. as Float;
+        for a in &hits {
+            for b in &hits {
+                spread = spread.max((*a - *b).magnitude());
+            }
+        }
+        spread
+    }
+
+    #[test]
+    fn test_autofocus_depth() {
+        let depth = 3. as Float;
+        let (scene, mut camera) = setup(depth, Some((0.1 as Float, 1. as Float)));
+        // off-center, so that the hit distance differs from the depth
+        let pfilm = Point2f::new(3. as Float, 12. as Float);
+        let focal_distance = camera.autofocus(&scene, pfilm).unwrap();
+        assert_relative_eq!(focal_distance, depth, max_relative = 1e-4);
+        assert_eq!(camera.lens(), Some((0.1 as Float, focal_distance)));
+
+        let sharp = blur(&scene, &camera, pfilm);
+        assert!(sharp < 1e-3, "blur at focus: {}", sharp);
+        for &d in &[0.9 as Float, 1.1 as Float] {
+            camera.set_lens(0.1 as Float, focal_distance * d);
+            assert!(blur(&scene, &camera, pfilm) > 10. as Float * sharp.max(1e-4));
+        }
+    }
+
+    #[test]
+    fn test_autofocus_failures() {
+        let (scene, mut camera) = setup(3. as Float, None);
+        assert_eq!(camera.autofocus(&scene, Point2f::new(8. as Float, 8. as Float)), Err(AutofocusError::Pinhole));
+        camera.set_lens(0.1 as Float, 1. as Float);
+        camera.look_from(
+            Point3f::new(0. as Float, 0. as Float, -3. as Float),
+            Point3f::new(0. as Float, 0. as Float, -4. as Float),
+            Vector3f::new(0. as Float, 1. as Float, 0. as Float)
+        );
+        assert_eq!(camera.autofocus(&scene, Point2f::new(8. as Float, 8. as Float)), Err(AutofocusError::Missed));
+        assert_eq!(camera.lens(), Some((0.1 as Float, 1. as Float)));
+    }
+}
+
+#[cfg(test)]
+mod test_tiles {
+    use super::*;
+    use super::film::{Film, FilmTile};
+    use sample::filters::BoxFilter;
+    use spectrum::RGBSpectrumf;
+    use rand::{Rng, SeedableRng, StdRng};
+    use std::sync::Arc;
+
+    fn film(resolution: Point2<usize>, crop_window: BBox2f) -> Film {
+        Film::new(resolution, crop_window, Arc::new(BoxFilter::new(Vector2f::new(0.5 as Float, 0.5 as Float))))
+    }
+
+    // every pixel of the crop window covered by exactly one tile
+    fn assert_partitions(film: &Film, tile_size: isize) {
+        let tiles: Vec<FilmTile<RGBSpectrumf>> = film.spawn_tiles(tile_size);
+        let resolution = film.resolution();
+        let crop = film.crop_window();
+        let (x0, y0) = ((crop.pmin.x * resolution.x as Float).ceil() as isize, (crop.pmin.y * resolution.y as Float).ceil() as isize);
+        let (x1, y1) = ((crop.pmax.x * resolution.x as Float).ceil() as isize, (crop.pmax.y * resolution.y as Float).ceil() as isize);
+        let mut counts = vec![0; resolution.x * resolution.y];
+        for tile in &tiles {
+            let bbox = tile.bounding();
+            assert!(bbox.pmin.x < bbox.pmax.x && bbox.pmin.y < bbox.pmax.y, "empty tile {:?}", bbox);
+            assert!(bbox.pmax.x - bbox.pmin.x < 2 * tile_size || bbox.pmax.x - bbox.pmin.x == x1 - x0);
+            assert!(bbox.pmax.y - bbox.pmin.y < 2 * tile_size || bbox.pmax.y - bbox.pmin.y == y1 - y0);
+            for y in bbox.pmin.y..bbox.pmax.y {
+                for x in bbox.pmin.x..bbox.pmax.x {
+                    counts[y as usize * resolution.x + x as usize] += 1;
+                }
+            }
+        }
+        for y in 0..resolution.y as isize {
+            for x in 0..resolution.x as isize {
+                let expected = if x >= x0 && x < x1 && y >= y0 && y < y1 { 1 } else { 0 };
+                assert_eq!(
+                    counts[y as usize * resolution.x + x as usize], expected,
+                    "pixel ({}, {}) at {:?} with tile size {}", x, y, resolution, tile_size
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_awkward_resolutions() {
+        let full = BBox2f::new(Point2f::new(0. as Float, 0. as Float), Point2f::new(1. as Float, 1. as Float));
+        let cropped = BBox2f::new(Point2f::new(0.3 as Float, 0.1 as Float), Point2f::new(0.9 as Float, 0.7 as Float));
+        for &(x, y) in &[(1, 1), (7, 3), (3, 7), (64, 64), (599, 601)] {
+            for &crop in &[full, cropped] {
+                let film = film(Point2::new(x, y), crop);
+                for &tile_size in &[1, 5, 16, 32, 1000] {
+                    assert_partitions(&film, tile_size);
+                }
+                assert_partitions(&film, film.tile_size());
+            }
+        }
+    }
+
+    #[test]
+    fn test_tile_size() {
+        let full = BBox2f::new(Point2f::new(0. as Float, 0. as Float), Point2f::new(1. as Float, 1. as Float));
+        let mut film = film(Point2::new(64, 64), full);
+        let auto = film.tile_size();
+        assert!(auto >= 16 && auto <= 64);
+        let tiles: Vec<FilmTile<RGBSpectrumf>> = film.spawn_tiles(auto);
+        assert!(tiles.len() <= 16);
+        film.set_tile_size(Some(7));
+        assert_eq!(film.tile_size(), 7);
+    }
+
+    #[test]
+    fn test_splats_order_independent() {
+        let full = BBox2f::new(Point2f::new(0. as Float, 0. as Float), Point2f::new(1. as Float, 1. as Float));
+        let film = film(Point2::new(4, 4), full);
+        let mut rng = StdRng::from_seed(&[9usize][..]);
+        // values far apart in magnitude, whose float sums depend on the order
+        let splats: Vec<(Point2f, RGBSpectrumf)> = (0..4096).map(|i| {
+            let pos = Point2f::new(rng.gen_range(0. as Float, 4.), rng.gen_range(0. as Float, 4.));
+            let v = if i % 7 == 0 { 1e3 as Float } else { rng.gen_range(0. as Float, 1e-3) };
+            (pos, RGBSpectrumf::new(v, 2. as Float * v, 0.5 as Float * v))
+        }).collect();
+        let collect = |batches: Vec<&[(Point2f, RGBSpectrumf)]>| {
+            let sink = film.spawn_splats();
+            for batch in batches {
+                sink.add_splats(batch);
+            }
+            let tiles: Vec<FilmTile<RGBSpectrumf>> = film.spawn_tiles(4);
+            film.collect_with_splats(tiles, sink, 1. as Float)
+        };
+        let forward = collect(splats.chunks(100).collect());
+        let backward = collect(splats.chunks(37).rev().collect());
+        let mut total = 0. as Float;
+        for y in 0..4 {
+            for x in 0..4 {
+                assert_eq!(forward[(x, y)], backward[(x, y)]);
+                total += forward[(x, y)].r();
+            }
+        }
+        let expected: Float = splats.iter().map(|&(_, s)| s.r()).sum();
+        assert_relative_eq!(total, expected, max_relative = 1e-5);
+    }
+}
+
+#[cfg(test)]
+mod test_add_sample {
+    use super::*;
+    use super::film::{Film, FilmTile, Image};
+    use sample::Filter;
+    use sample::filters::{BoxFilter, TriangleFilter, MitchellFilter, LanczosSincFilter};
+    use spectrum::{RGBSpectrumf, Spectrum};
+    use rand::{Rng, SeedableRng, StdRng};
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    const RESOLUTION: usize = 16;
+
+    fn film(filter: Arc<Filter>) -> Film {
+        let full = BBox2f::new(Point2f::new(0. as Float, 0. as Float), Point2f::new(1. as Float, 1. as Float));
+        Film::new(Point2::new(RESOLUTION, RESOLUTION), full, filter)
+    }
+
+    // `n` samples per pixel scattered over the film, of a smooth color
+    fn samples(n: usize) -> Vec<(Point2f, RGBSpectrumf)> {
+        let mut rng = StdRng::from_seed(&[7usize][..]);
+        (0..n * RESOLUTION * RESOLUTION).map(|_| {
+            let p = Point2f::new(
+                rng.gen::<Float>() * RESOLUTION as Float, rng.gen::<Float>() * RESOLUTION as Float
+            );
+            let color = RGBSpectrumf::new(
+                (p.x * 0.1 as Float).sin() * 0.5 as Float + 0.5 as Float,
+                (p.y * 0.1 as Float).cos() * 0.5 as Float + 0.5 as Float,
+                (p.x + p.y) / (2 * RESOLUTION) as Float
+            );
+            (p, color)
+        }).collect()
+    }
+
+    // add `samples` into the tiles of `film` they fall into, returning
+    // the image along with the seconds spent adding them
+    fn render(film: &Film, samples: &[(Point2f, RGBSpectrumf)]) -> (Image, f64) {
+        let mut tiles: Vec<FilmTile<RGBSpectrumf>> = film.spawn_tiles(5);
+        let buckets: Vec<Vec<_>> = tiles.iter().map(|tile| {
+            let bounding = tile.bounding();
+            samples.iter().filter(|&&(p, _)| {
+                bounding.contain_lb(Point2::new(p.x.floor() as isize, p.y.floor() as isize))
+            }).cloned().collect()
+        }).collect();
+        let start = Instant::now();
+        for (tile, bucket) in tiles.iter_mut().zip(&buckets) {
+            for &(p, color) in bucket {
+                tile.add_sample(p, &color);
+            }
+        }
+        let elapsed = start.elapsed();
+        let seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+        (film.collect_into(tiles), seconds)
+    }
+
+    #[test]
+    fn test_box_nearest() {
+        let samples = samples(4);
+        let mut sums = vec![(RGBSpectrumf::black(), 0); RESOLUTION * RESOLUTION];
+        for &(p, color) in &samples {
+            let sum = &mut sums[p.y as usize * RESOLUTION + p.x as usize];
+            sum.0 += color;
+            sum.1 += 1;
+        }
+        // no wider than a pixel, as wide as a pixel
+        for &radius in &[0.3 as Float, 0.5] {
+            let (image, _) = render(&film(Arc::new(BoxFilter::new(Vector2f::new(radius, radius)))), &samples);
+            for y in 0..RESOLUTION {
+                for x in 0..RESOLUTION {
+                    let (sum, count) = sums[y * RESOLUTION + x];
+                    let pixel = image[(x as u32, y as u32)];
+                    if count == 0 {
+                        assert_eq!(pixel, RGBSpectrumf::black());
+                    } else {
+                        let mean = sum / count as Float;
+                        assert_relative_eq!(pixel.r(), mean.r(), max_relative = 1e-5);
+                        assert_relative_eq!(pixel.g(), mean.g(), max_relative = 1e-5);
+                        assert_relative_eq!(pixel.b(), mean.b(), max_relative = 1e-5);
+                    }
+                }
+            }
+        }
+    }
+
+    fn mitchell() -> Arc<Filter> {
+        Arc::new(MitchellFilter::new(
+            Vector2f::new(2. as Float, 2. as Float), 1. as Float / 3. as Float, 1. as Float / 3. as Float
+        ))
+    }
+
+    #[test]
+    fn test_table() {
+        let samples = samples(16);
+        let mut film = film(mitchell());
+        let (direct, _) = render(&film, &samples);
+        film.set_filter_table(true);
+        assert!(film.has_filter_table());
+        let (tabulated, _) = render(&film, &samples);
+        for y in 0..RESOLUTION as u32 {
+            for x in 0..RESOLUTION as u32 {
+                let (a, b) = (direct[(x, y)], tabulated[(x, y)]);
+                let d = a - b;
+                let d = d.r().abs().max(d.g().abs()).max(d.b().abs());
+                assert!(d < 1e-3 as Float, "{:?} vs {:?} at ({}, {})", a, b, x, y);
+            }
+        }
+    }
+
+    // run with `cargo test --release -- --ignored bench_add_sample --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_add_sample() {
+        let samples = samples(1024);
+        let time = |film: &Film| render(film, &samples).1;
+        let boxed = film(Arc::new(BoxFilter::new(Vector2f::new(0.5 as Float, 0.5 as Float))));
+        let wide = film(Arc::new(BoxFilter::new(Vector2f::new(0.51 as Float, 0.51 as Float))));
+        let mut mitchell = film(mitchell());
+        println!("box 0.5: {:.4}s, box 0.51: {:.4}s", time(&boxed), time(&wide));
+        let direct = time(&mitchell);
+        mitchell.set_filter_table(true);
+        println!("mitchell: {:.4}s direct, {:.4}s tabulated", direct, time(&mitchell));
+        let mut lanczos = film(Arc::new(LanczosSincFilter::new(Vector2f::new(2. as Float, 2. as Float), 3. as Float)));
+        let direct = time(&lanczos);
+        lanczos.set_filter_table(true);
+        println!("lanczos: {:.4}s direct, {:.4}s tabulated", direct, time(&lanczos));
+    }
+
+    // `n` samples in each pixel, traced where the tiles `jitter` them
+    // to and colored by `field`, which may be noisy
+    fn render_traced(
+        film: &Film, n: usize, field: &Fn(Point2f, &mut StdRng) -> RGBSpectrumf
+    ) -> (Image, f64) {
+        let mut rng = StdRng::from_seed(&[11usize][..]);
+        let mut tiles: Vec<FilmTile<RGBSpectrumf>> = film.spawn_tiles(5);
+        let start = Instant::now();
+        for tile in &mut tiles {
+            for p in tile.bounding() {
+                for _ in 0..n {
+                    let pfilm = Point2f::new(p.x as Float + rng.gen::<Float>(), p.y as Float + rng.gen::<Float>());
+                    let color = field(tile.jitter(pfilm), &mut rng);
+                    tile.add_sample(pfilm, &color);
+                }
+            }
+        }
+        let elapsed = start.elapsed();
+        let seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+        (film.collect_into(tiles), seconds)
+    }
+
+    // mean and variance of the red channel over the pixels of `image`
+    fn statistics(image: &Image) -> (Float, Float) {
+        let n = (RESOLUTION * RESOLUTION) as Float;
+        let mut values = Vec::new();
+        for y in 0..RESOLUTION as u32 {
+            for x in 0..RESOLUTION as u32 {
+                values.push(image[(x, y)].r());
+            }
+        }
+        let mean = values.iter().sum::<Float>() / n;
+        let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<Float>() / (n - 1. as Float);
+        (mean, variance)
+    }
+
+    #[test]
+    fn test_flat_field() {
+        // uniform noise around one half, of variance 1/12
+        let noise = |_: Point2f, rng: &mut StdRng| {
+            let v = rng.gen::<Float>();
+            RGBSpectrumf::new(v, v, v)
+        };
+        let n = 64;
+        let triangle = || Arc::new(TriangleFilter::new(Vector2f::new(2. as Float, 2. as Float)));
+        let (splatted, _) = render_traced(&film(triangle()), n, &noise);
+        let mut sampled = film(triangle());
+        sampled.set_filter_sampling(true);
+        let (sampled, _) = render_traced(&sampled, n, &noise);
+        let (splatted_mean, _) = statistics(&splatted);
+        let (sampled_mean, sampled_variance) = statistics(&sampled);
+        // each pixel averaging `n` samples of its own
+        let expected = 1. as Float / (12 * n) as Float;
+        let sigma = (expected / (RESOLUTION * RESOLUTION) as Float).sqrt();
+        assert!((sampled_mean - 0.5 as Float).abs() < 4. as Float * sigma, "{}", sampled_mean);
+        assert!((splatted_mean - sampled_mean).abs() < 4. as Float * sigma, "{} vs {}", splatted_mean, sampled_mean);
+        assert!((sampled_variance / expected - 1. as Float).abs() < 0.3 as Float, "{} vs {}", sampled_variance, expected);
+    }
+
+    // a smooth color over the film
+    fn field(p: Point2f, _: &mut StdRng) -> RGBSpectrumf {
+        RGBSpectrumf::new(
+            (p.x * 0.4 as Float).sin() * 0.5 as Float + 0.5 as Float,
+            (p.y * 0.3 as Float).cos() * 0.5 as Float + 0.5 as Float,
+            (p.x * p.y) / (RESOLUTION * RESOLUTION) as Float
+        )
+    }
+
+    #[test]
+    fn test_filter_sampling_converges() {
+        let filters: Vec<Arc<Filter>> = vec![
+            mitchell(),
+            Arc::new(LanczosSincFilter::new(Vector2f::new(2. as Float, 2. as Float), 3. as Float)),
+            Arc::new(TriangleFilter::new(Vector2f::new(1.5 as Float, 1.5 as Float))),
+        ];
+        for filter in filters {
+            let mut film = film(filter);
+            let (splatted, _) = render_traced(&film, 1024, &field);
+            film.set_filter_sampling(true);
+            let (sampled, _) = render_traced(&film, 1024, &field);
+            let mut error = 0. as Float;
+            // away from the borders, where splatting lacks samples beyond the film
+            for y in 2..RESOLUTION as u32 - 2 {
+                for x in 2..RESOLUTION as u32 - 2 {
+                    let d = splatted[(x, y)] - sampled[(x, y)];
+                    error = error.max(d.r().abs()).max(d.g().abs()).max(d.b().abs());
+                }
+            }
+            assert!(error < 0.03 as Float, "{}", error);
+        }
+    }
+
+    // run with `cargo test --release -- --ignored bench_filter_sampling --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_filter_sampling() {
+        let lanczos = || Arc::new(LanczosSincFilter::new(Vector2f::new(2. as Float, 2. as Float), 3. as Float));
+        for &(name, ref filter) in &[("mitchell", mitchell()), ("lanczos", lanczos() as Arc<Filter>)] {
+            let mut film = film(filter.clone());
+            let (_, splatted) = render_traced(&film, 1024, &field);
+            film.set_filter_sampling(true);
+            let (_, sampled) = render_traced(&film, 1024, &field);
+            println!("{}: {:.4}s splatted, {:.4}s filter sampled", name, splatted, sampled);
+            assert!(sampled < splatted);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_alpha {
+    use super::*;
+    use super::film::{Film, FilmTile};
+    use sample::filters::BoxFilter;
+    use spectrum::{RGBSpectrumf, Spectrum};
+    use image::{self, GenericImage};
+    use std::sync::Arc;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_rgba() {
+        let full = BBox2f::new(Point2f::new(0. as Float, 0. as Float), Point2f::new(1. as Float, 1. as Float));
+        let mut film = Film::new(Point2::new(2, 1), full, Arc::new(BoxFilter::new(Vector2f::new(0.5 as Float, 0.5 as Float))));
+        let white = RGBSpectrumf::grey_scale(1. as Float);
+        let collect = |film: &Film| {
+            let mut tiles: Vec<FilmTile<RGBSpectrumf>> = film.spawn_tiles(2);
+            for tile in &mut tiles {
+                tile.add_sample(Point2f::new(0.5 as Float, 0.5 as Float), &white);
+                tile.add_sample_with_alpha(Point2f::new(1.5 as Float, 0.5 as Float), &(white * 0.25 as Float), 0.25 as Float);
+                tile.add_sample_with_alpha(Point2f::new(1.5 as Float, 0.5 as Float), &RGBSpectrumf::black(), 0. as Float);
+            }
+            film.collect_into(tiles)
+        };
+        let opaque = collect(&film);
+        assert!(!opaque.has_alpha());
+        assert_eq!(opaque.alpha(Point2::new(1, 0)), 1. as Float);
+
+        film.set_alpha(true);
+        let image = collect(&film);
+        assert!(image.has_alpha());
+        assert_eq!(image.alpha(Point2::new(0, 0)), 1. as Float);
+        assert_relative_eq!(image.alpha(Point2::new(1, 0)), 0.125 as Float);
+
+        let path = env::temp_dir().join("arendur_test_rgba.png");
+        image.save(&path).unwrap();
+        let loaded = image::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.color(), image::ColorType::RGBA(8));
+        assert_eq!(loaded.get_pixel(0, 0).data[3], 255);
+        let partial = loaded.get_pixel(1, 0).data[3];
+        assert!(partial > 0 && partial < 255);
+    }
+}
+
+#[cfg(test)]
+mod test_ldr {
+    use super::*;
+    use super::film::Image;
+    use spectrum::{RGBSpectrumf, Spectrum};
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_hdr_and_nan() {
+        let values = [0. as Float, 0.5, 1., 7.3, float::nan()];
+        let mut image = Image::new(RGBSpectrumf::black(), Point2::new(values.len() as u32, 1));
+        for (x, &v) in values.iter().enumerate() {
+            image[Point2::new(x as u32, 0)] = RGBSpectrumf::new(v, v, 0.25 as Float);
+        }
+        let (bytes, nans) = image.to_ldr();
+        assert_eq!(nans, 2);
+        let reds: Vec<u8> = bytes.chunks(3).map(|c| c[0]).collect();
+        assert_eq!(reds, vec![0, 127, 255, 255, 0]);
+        assert!(bytes.chunks(3).all(|c| c[0] == c[1] && c[2] == 63));
+
+        // saving out of range values doesn't panic, in debug builds either
+        let path = env::temp_dir().join("arendur_test_hdr_and_nan.png");
+        image.save(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test_shutter {
+    use super::*;
+    use super::perspective::PerspecCam;
+    use sample::Sampler;
+    use sample::strata::StdStrataSampler;
+    use test_utils;
+    use rand::{SeedableRng, StdRng};
+
+    fn camera(shutter: Option<(Float, Float)>) -> PerspecCam {
+        let mut camera = test_utils::perspec_cam(
+            8, Point3f::new(0. as Float, 0., 0.), Point3f::new(0. as Float, 0., 1.), 1.2 as Float
+        );
+        camera.set_shutter(shutter);
+        camera
+    }
+
+    #[test]
+    fn test_times_stratified() {
+        let (open, close) = (0.25 as Float, 0.75 as Float);
+        let mut sampler = StdStrataSampler::new(4, 4, 4, StdRng::from_seed(&[7usize][..]));
+        let n = sampler.sample_per_pixel();
+        for &p in &[Point2::new(0u32, 0u32), Point2::new(5, 3)] {
+            sampler.start_pixel(p);
+            let mut times = Vec::new();
+            loop {
+                times.push(sampler.get_camera_sample(p, Some((open, close))).time);
+                if !sampler.next_sample() { break; }
+            }
+            assert_eq!(times.len(), n);
+            times.sort_by(|a, b| float::total_cmp(*a, *b));
+            assert!(times[0] >= open && times[n-1] < close);
+            // each stratum holds exactly one sample, even when rotated
+            let wrap = times[0] - open + close - times[n-1];
+            let max_gap = times.windows(2).map(|w| w[1] - w[0]).fold(wrap, Float::max);
+            assert!(max_gap < 2. as Float * (close - open) / n as Float, "{:?}", times);
+        }
+        sampler.start_pixel(Point2::new(0, 0));
+        assert_eq!(sampler.get_camera_sample(Point2::new(0, 0), None).time, 0. as Float);
+    }
+
+    #[test]
+    fn test_no_shutter_draws_nothing() {
+        // without a shutter, camera samples take the same dimensions
+        // as before shutters existed, keeping renders bit-identical
+        let mut sampler = StdStrataSampler::new(2, 2, 4, StdRng::from_seed(&[7usize][..]));
+        let p = Point2::new(2u32, 1u32);
+        let mut expected = sampler.clone();
+        sampler.start_pixel(p);
+        expected.start_pixel(p);
+        let sample_info = sampler.get_camera_sample(p, None);
+        assert_eq!(sample_info.pfilm, expected.next_2d() + Vector2f::new(2. as Float, 1. as Float));
+        assert_eq!(sample_info.plens, expected.next_2d());
+        assert_eq!(sampler.next(), expected.next());
+        assert_eq!(sampler.next_2d(), expected.next_2d());
+    }
+
+    #[test]
+    fn test_ray_time() {
+        let camera = camera(Some((0. as Float, 1. as Float)));
+        assert_eq!(camera.shutter(), Some((0. as Float, 1. as Float)));
+        let sample_info = SampleInfo{
+            pfilm: Point2f::new(3.5 as Float, 4.5 as Float),
+            plens: Point2f::new(0.5 as Float, 0.5 as Float),
+            time: 0.3 as Float,
+        };
+        assert_eq!(camera.generate_path(sample_info).time(), 0.3 as Float);
+        let ray = camera.generate_path_differential(sample_info);
+        let (rx, ry) = ray.diffs.unwrap();
+        assert_eq!(ray.ray.time(), 0.3 as Float);
+        assert_eq!(rx.time(), 0.3 as Float);
+        assert_eq!(ry.time(), 0.3 as Float);
+        let transformed = ray.apply_transform(&Matrix4f::from_translation(Vector3f::new(1. as Float, 0., 0.)));
+        assert_eq!(transformed.ray.time(), 0.3 as Float);
+        assert_eq!(transformed.diffs.unwrap().0.time(), 0.3 as Float);
+    }
+}
+
+#[cfg(test)]
+mod test_exposure {
+    use super::*;
+    use super::exposure::Exposure;
+    use super::film::{Film, FilmTile};
+    use super::perspective::PerspecCam;
+    use sample::filters::BoxFilter;
+    use test_utils::{self, RendererKind, TestScene};
+    use prelude::*;
+    use component::ComponentPointer;
+    use std::sync::Arc;
+
+    fn film() -> Film {
+        Film::new(
+            Point2::new(4, 4),
+            BBox2f::new(Point2f::new(0. as Float, 0. as Float), Point2f::new(1. as Float, 1. as Float)),
+            Arc::new(BoxFilter::new(Vector2f::new(0.5 as Float, 0.5 as Float)))
+        )
+    }
+
+    #[test]
+    fn test_iso_doubles() {
+        let collect = |film: &Film| {
+            let mut tiles: Vec<FilmTile<RGBSpectrumf>> = film.spawn_tiles(2);
+            for tile in &mut tiles {
+                for p in tile.bounding() {
+                    let value = 0.1 as Float * (p.x + 4 * p.y) as Float;
+                    tile.add_sample(Point2f::new(p.x as Float + 0.5, p.y as Float + 0.5), &RGBSpectrumf::new(value, 0.3, 1.7));
+                }
+            }
+            film.collect_into(tiles)
+        };
+        let mut film = film();
+        film.set_exposure(Some(Exposure::new(100. as Float, 1. as Float / 60. as Float, 2.8 as Float)));
+        let base = collect(&film);
+        film.set_exposure(Some(Exposure::new(200. as Float, 1. as Float / 60. as Float, 2.8 as Float)));
+        let doubled = collect(&film);
+        film.set_exposure(Some(Exposure::new(100. as Float, 1. as Float / 60. as Float, 2.8 as Float)));
+        film.set_ev_compensation(1. as Float);
+        let compensated = collect(&film);
+        for y in 0..4 {
+            for x in 0..4 {
+                assert_eq!(doubled[(x, y)], base[(x, y)] * 2. as Float);
+                assert_eq!(compensated[(x, y)], base[(x, y)] * 2. as Float);
+            }
+        }
+    }
+
+    // mean of a uniformly emitting wall seen at `fnumber`, along with the lens radius
+    fn wall(fnumber: Float) -> (Float, Float) {
+        let mut camera = PerspecCam::physical(
+            Matrix4f::identity(),
+            BBox2f::new(Point2f::new(-1. as Float, -1. as Float), Point2f::new(1. as Float, 1. as Float)),
+            0.01 as Float, 1000. as Float, float::frac_pi_4(),
+            0.05 as Float, 5. as Float, Exposure::new(100. as Float, 1. as Float, fnumber), film()
+        );
+        camera.look_from(
+            Point3f::new(0. as Float, 0. as Float, 0. as Float),
+            Point3f::new(0. as Float, 0. as Float, 1. as Float),
+            Vector3f::new(0. as Float, 1. as Float, 0. as Float)
+        );
+        let radius = camera.lens().unwrap().0;
+        let p = |x: Float, y: Float| Point3f::new(x, y, 10. as Float);
+        let light = test_utils::quad(
+            [p(-100., -100.), p(100., -100.), p(100., 100.), p(-100., 100.)],
+            test_utils::matte(0. as Float, 0. as Float, 0. as Float),
+            Some(RGBSpectrumf::new(1. as Float, 1. as Float, 1. as Float))
+        );
+        let components: Vec<ComponentPointer> = TriangleMesh::instances(&light)
+            .map(|t| t.into()).collect();
+        let lights: Vec<Arc<Light>> = vec![Arc::new(MeshLight::new(light))];
+        let scene = TestScene{
+            scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+            camera: Arc::new(camera),
+        };
+        (test_utils::mean(&test_utils::render(&scene, RendererKind::PT(2), 4)).g(), radius)
+    }
+
+    #[test]
+    fn test_fstops() {
+        let (wide, wide_radius) = wall(2.8 as Float);
+        let (narrow, narrow_radius) = wall(5.6 as Float);
+        // two stops darker, through a lens half as wide
+        assert_relative_eq!(wide / narrow, 4. as Float, max_relative = 1e-4);
+        assert_relative_eq!(wide_radius / narrow_radius, 2. as Float, max_relative = 1e-5);
+        assert_relative_eq!(wide_radius, 0.05 as Float / 5.6 as Float, max_relative = 1e-5);
+    }
+}
+
+#[cfg(test)]
+mod test_refilter {
+    use super::*;
+    use super::film::{Film, FilmTile, Image};
+    use super::dump::{SampleDump, SampleDumpWriter, refilter};
+    use sample::Filter;
+    use sample::filters::{BoxFilter, MitchellFilter};
+    use spectrum::{RGBSpectrumf, Spectrum};
+    use rand::{Rng, SeedableRng, StdRng};
+    use std::sync::Arc;
+    use std::env;
+    use std::fs;
+
+    const RESOLUTION: usize = 16;
+
+    fn mitchell() -> Arc<Filter> {
+        Arc::new(MitchellFilter::new(
+            Vector2f::new(2. as Float, 2. as Float), 1. as Float / 3. as Float, 1. as Float / 3. as Float
+        ))
+    }
+
+    fn film(filter: Arc<Filter>) -> Film {
+        let full = BBox2f::new(Point2f::new(0. as Float, 0. as Float), Point2f::new(1. as Float, 1. as Float));
+        let mut film = Film::new(Point2::new(RESOLUTION, RESOLUTION), full, filter);
+        film.set_alpha(true);
+        film.set_raw_samples(true);
+        film
+    }
+
+    // tiles of `film` with 16 samples per pixel of a vertical edge,
+    // white and opaque to the left of the middle, black and half covered
+    // to the right
+    fn render(film: &Film) -> Vec<FilmTile<RGBSpectrumf>> {
+        let mut rng = StdRng::from_seed(&[7usize][..]);
+        let mut tiles: Vec<FilmTile<RGBSpectrumf>> = film.spawn_tiles(5);
+        for _ in 0..16 * RESOLUTION * RESOLUTION {
+            let p = Point2f::new(
+                rng.gen::<Float>() * RESOLUTION as Float, rng.gen::<Float>() * RESOLUTION as Float
+            );
+            let pixel = Point2::new(p.x.floor() as isize, p.y.floor() as isize);
+            let tile = tiles.iter_mut().find(|tile| tile.bounding().contain_lb(pixel)).unwrap();
+            if p.x < (RESOLUTION / 2) as Float {
+                tile.add_sample(p, &RGBSpectrumf::grey_scale(1. as Float));
+            } else {
+                tile.add_sample_with_alpha(p, &RGBSpectrumf::black(), 0.5 as Float);
+            }
+        }
+        tiles
+    }
+
+    fn assert_close(a: &Image, b: &Image) {
+        for y in 0..RESOLUTION as u32 {
+            for x in 0..RESOLUTION as u32 {
+                let d = a[(x, y)] - b[(x, y)];
+                let d = d.r().abs().max(d.g().abs()).max(d.b().abs());
+                assert!(d < 1e-4 as Float, "{:?} vs {:?} at ({}, {})", a[(x, y)], b[(x, y)], x, y);
+                let p = Point2::new(x, y);
+                assert_relative_eq!(a.alpha(p), b.alpha(p), epsilon = 1e-4 as Float);
+            }
+        }
+    }
+
+    #[test]
+    fn test_same_filter() {
+        let film = film(mitchell());
+        let mut tiles = render(&film);
+        let dump = film.collect_raw(&mut tiles);
+        assert_eq!(dump.samples.len(), 16 * RESOLUTION * RESOLUTION);
+        let online = film.collect_into(tiles);
+
+        let mut bytes = Vec::new();
+        dump.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 40 + dump.record_size() * dump.samples.len());
+        let dump = SampleDump::read_from(&mut &bytes[..]).unwrap();
+        assert_close(&online, &refilter(&dump, mitchell(), film.resolution()));
+
+        // streamed tile by tile
+        let path = env::temp_dir().join("arendur_test_refilter.dump");
+        let writer = SampleDumpWriter::create(&path, &film).unwrap();
+        for tile in &mut render(&film) {
+            writer.write_tile(tile).unwrap();
+        }
+        writer.finish().unwrap();
+        let streamed = SampleDump::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(streamed.samples.len(), dump.samples.len());
+        assert_close(&online, &refilter(&streamed, mitchell(), film.resolution()));
+    }
+
+    #[test]
+    fn test_edge_profiles() {
+        let film = film(mitchell());
+        let dump = film.collect_raw(&mut render(&film));
+        let boxed = refilter(&dump, Arc::new(BoxFilter::new(Vector2f::new(0.5 as Float, 0.5 as Float))), film.resolution());
+        let mitchell = refilter(&dump, mitchell(), film.resolution());
+        // mean red over each column
+        let profile = |image: &Image| -> Vec<Float> {
+            (0..RESOLUTION as u32).map(|x| {
+                (0..RESOLUTION as u32).map(|y| image[(x, y)].r()).sum::<Float>() / RESOLUTION as Float
+            }).collect()
+        };
+        let (boxed, mitchell) = (profile(&boxed), profile(&mitchell));
+        let edge = RESOLUTION / 2;
+        // the box keeps the edge sharp, mitchell spreads it over neighbors
+        assert_relative_eq!(boxed[edge - 1], 1. as Float, epsilon = 1e-5 as Float);
+        assert_relative_eq!(boxed[edge], 0. as Float, epsilon = 1e-5 as Float);
+        assert!(mitchell[edge - 1] < 0.95 as Float, "{:?}", mitchell);
+        assert!(mitchell[edge] > 0.05 as Float, "{:?}", mitchell);
+        // far from the edge both see a flat color
+        for &x in &[1, RESOLUTION - 2] {
+            assert_relative_eq!(boxed[x], mitchell[x], epsilon = 1e-4 as Float);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_raw {
+    use super::*;
+    use super::film::Image;
+    use spectrum::{RGBSpectrumf, ToNorm};
+
+    #[test]
+    fn test_round_trip() {
+        let rgb: Vec<Float> = (0..3 * 5 * 3).map(|i| i as Float * 0.1 as Float - 1. as Float).collect();
+        assert!(Image::from_raw(5, 3, rgb[1..].to_vec()).is_none());
+        let image = Image::from_raw(5, 3, rgb.clone()).unwrap();
+        assert_eq!(image.dimension(), Point2::new(5, 3));
+        assert_eq!(image[(1, 2)], RGBSpectrumf::new(rgb[33], rgb[34], rgb[35]));
+        let (raw, width, height) = image.as_raw();
+        assert_eq!((width, height), (5, 3));
+        assert_eq!(raw, &rgb[..]);
+
+        let ldr = image.to_vec_rgb8(|s| s);
+        assert_eq!(ldr.len(), rgb.len());
+        assert_eq!(ldr, image.to_ldr().0);
+        let halved = image.to_vec_rgb8(|s| s * 0.5 as Float);
+        assert_eq!(halved[44], <u8 as ToNorm>::from_norm_clamped(rgb[44] * 0.5 as Float));
+    }
+}
+
+#[cfg(test)]
+mod test_bloom {
+    use super::*;
+    use super::film::Image;
+    use super::bloom::Bloom;
+    use spectrum::{RGBSpectrumf, Spectrum};
+
+    const SIZE: u32 = 128;
+    const CENTER: u32 = SIZE / 2;
+
+    // red radiance added by blooming a single bright pixel at the center
+    fn glow(radius: Float, intensity: Float) -> Vec<Float> {
+        let mut image = Image::new(RGBSpectrumf::black(), Point2::new(SIZE, SIZE));
+        image[(CENTER, CENTER)] = RGBSpectrumf::grey_scale(1000. as Float);
+        Bloom{threshold: 1. as Float, radius: radius, intensity: intensity}.apply(&mut image);
+        image[(CENTER, CENTER)] -= RGBSpectrumf::grey_scale(1000. as Float);
+        let mut ret = Vec::new();
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                ret.push(image[(x, y)].r());
+            }
+        }
+        ret
+    }
+
+    #[test]
+    fn test_single_pixel() {
+        let glow2 = glow(2. as Float, 0.5 as Float);
+        let at = |glow: &[Float], dx: i32, dy: i32| {
+            glow[((CENTER as i32 + dy) * SIZE as i32 + CENTER as i32 + dx) as usize]
+        };
+        // half of the radiance above the threshold is added
+        let sum: Float = glow2.iter().sum();
+        assert_relative_eq!(sum, 0.5 as Float * 999. as Float, max_relative = 0.01);
+        for d in 1..24 {
+            let v = at(&glow2, d, 0);
+            assert!(v > 0. as Float && v < at(&glow2, d - 1, 0));
+            for &(dx, dy) in &[(-d, 0), (0, d), (0, -d)] {
+                assert_relative_eq!(at(&glow2, dx, dy), v, max_relative = 1e-3);
+            }
+            for e in 1..d {
+                assert_relative_eq!(at(&glow2, d, e), at(&glow2, e, d), max_relative = 1e-3);
+                assert_relative_eq!(at(&glow2, d, e), at(&glow2, -d, -e), max_relative = 1e-3);
+            }
+        }
+
+        // the spread grows along with the radius
+        let spread = |glow: &[Float]| {
+            let (mut moment, mut sum) = (0. as Float, 0. as Float);
+            for y in 0..SIZE {
+                for x in 0..SIZE {
+                    let (dx, dy) = (x as Float - CENTER as Float, y as Float - CENTER as Float);
+                    let v = glow[(y * SIZE + x) as usize];
+                    moment += v * (dx * dx + dy * dy);
+                    sum += v;
+                }
+            }
+            (moment / sum).sqrt()
+        };
+        let ratio = spread(&glow(4. as Float, 0.5 as Float)) / spread(&glow2);
+        assert!(ratio > 1.6 as Float && ratio < 2.2 as Float, "{}", ratio);
+    }
+
+    #[test]
+    fn test_dark() {
+        let color = |x: u32, y: u32| RGBSpectrumf::new(
+            x as Float / SIZE as Float, y as Float / SIZE as Float, 0.5 as Float
+        );
+        let mut image = Image::new(RGBSpectrumf::black(), Point2::new(SIZE, SIZE));
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                image[(x, y)] = color(x, y);
+            }
+        }
+        Bloom{threshold: 1. as Float, radius: 2. as Float, intensity: 1. as Float}.apply(&mut image);
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                assert_eq!(image[(x, y)], color(x, y));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_near_plane {
+    use super::*;
+    use super::perspective::PerspecCam;
+    use component::ComponentPointer;
+    use component::bvh::{BVH, BVHStrategy};
+    use prelude::*;
+    use test_utils;
+    use std::sync::Arc;
+
+    fn camera(znear: Float) -> PerspecCam {
+        test_utils::perspec_cam_near(
+            8, Point3f::new(0. as Float, 0., -1.), Point3f::new(0. as Float, 0., 0.), float::frac_pi_2(), znear
+        )
+    }
+
+    #[test]
+    fn test_closer_than_near_unseen() {
+        // a sphere one unit ahead of the camera
+        let sphere: Arc<Composable> = Arc::new(ShapedPrimitive::new(
+            Sphere::full(0.25 as Float), test_utils::matte(0.5 as Float, 0.5, 0.5), None
+        ));
+        let components: Vec<ComponentPointer> = vec![sphere.into()];
+        let bvh = BVH::new(&components, BVHStrategy::SAH);
+        let sample = |pfilm: Point2f| SampleInfo{
+            pfilm: pfilm, plens: Point2f::new(0.5 as Float, 0.5), time: 0. as Float,
+        };
+        let center = Point2f::new(4. as Float, 4.);
+        for &(znear, seen) in &[(0.5 as Float, true), (1.5 as Float, false)] {
+            let camera = camera(znear);
+            let mut ray = camera.generate_path(sample(center));
+            assert_eq!(bvh.intersect_ray(&mut ray).is_some(), seen, "znear {}", znear);
+            let mut ray = camera.generate_path_differential(sample(center)).ray;
+            assert_eq!(bvh.intersect_ray(&mut ray).is_some(), seen, "znear {}", znear);
+            // nor is it splatted onto by light paths
+            let (importance, _) = camera.evaluate_importance_sampled(
+                Point3f::new(0. as Float, 0., -0.25), Point2f::new(0.5 as Float, 0.5)
+            );
+            assert_eq!(!importance.radiance.is_black(), seen, "znear {}", znear);
+        }
+        // the near plane is flat, so oblique rays start further away
+        let camera = camera(0.5 as Float);
+        let oblique = camera.generate_path(sample(Point2f::new(0.5 as Float, 4.)));
+        let p = oblique.evaluate(oblique.min_extend());
+        assert_relative_eq!(p.z, -0.5 as Float, epsilon = 1e-5);
+    }
+}
@@ -481,21 +481,15 @@ impl<T: BaseNum> BBox3<T> {
     pub fn apply_transform<Tr>(&self, t: &Tr) -> Self
         where Tr: Transform3<T>
     {
-        // let bbox = BBox3::new(
-        //     t.transform_point(Point3::new(self.pmin.x, self.pmin.y, self.pmin.z)),
-        //     t.transform_point(Point3::new(self.pmax.x, self.pmin.y, self.pmin.z))
-        // );
-        // bbox.extend(Point3::new(self.pmin.x, self.pmax.y, self.pmin.z))
-        //     .extend(Point3::new(self.pmin.x, self.pmin.y, self.pmax.z))
-        //     .extend(Point3::new(self.pmin.x, self.pmax.y, self.pmax.z))
-        //     .extend(Point3::new(self.pmax.x, self.pmin.y, self.pmax.z))
-        //     .extend(Point3::new(self.pmax.x, self.pmax.y, self.pmin.z))
-        //     .extend(Point3::new(self.pmax.x, self.pmax.y, self.pmax.z))
-        let p = t.transform_point(self.pmin);
-        let diagonal = t.transform_vector(self.diagonal());
-        BBox3::new(
-            p, p + diagonal
-        )
+        // all eight corners, as under a rotation the transformed diagonal
+        // no longer spans the box
+        let corner = |i: usize| Point3::new(
+            if i & 1 == 0 { self.pmin.x } else { self.pmax.x },
+            if i & 2 == 0 { self.pmin.y } else { self.pmax.y },
+            if i & 4 == 0 { self.pmin.z } else { self.pmax.z },
+        );
+        let p = t.transform_point(corner(0));
+        (1..8).fold(BBox3::new(p, p), |bbox, i| bbox.extend(t.transform_point(corner(i))))
     }
 
     /// Casting to another type of bbox
@@ -512,7 +506,7 @@ impl BBox3f {
     pub fn intersect_ray<R>(&self, ray: &R) -> Option<(Float, Float)>
         where R: Ray + ?Sized
     {
-        let mut t0 = 0.0 as Float;
+        let mut t0 = ray.min_extend();
         let mut t1 = ray.max_extend();
 
         let origin = ray.origin();
@@ -544,9 +538,19 @@ impl BBox3f {
         Some((t0, t1))
     }
 
+    /// Squared distance from `p` to `self`, zero if `p` is inside
+    #[inline]
+    pub fn distance2(&self, p: Point3f) -> Float {
+        let zero = 0. as Float;
+        let dx = (self.pmin.x - p.x).max(zero).max(p.x - self.pmax.x);
+        let dy = (self.pmin.y - p.y).max(zero).max(p.y - self.pmax.y);
+        let dz = (self.pmin.z - p.z).max(zero).max(p.z - self.pmax.z);
+        dx * dx + dy * dy + dz * dz
+    }
+
     /// Test if the `ray` intersects `self`, with cache
     #[inline]
-    pub fn intersect_ray_cached(&self, cache: &(Point3f, Vector3f, Vector3<bool>, Float)) -> Option<(Float, Float)>
+    pub fn intersect_ray_cached(&self, cache: &(Point3f, Vector3f, Vector3<bool>, Float, Float)) -> Option<(Float, Float)>
     {
         let mut t0 = (self.index(cache.2.x).x - cache.0.x) * cache.1.x;
         let mut t1 = (self.index(!cache.2.x).x - cache.0.x) * cache.1.x;
@@ -572,15 +576,16 @@ impl BBox3f {
         if tz0 > t0 { t0 = tz0; }
         if tz1 < t1 { t1 = tz1; }
 
-        if t0 < cache.3 && t1 > (0.0 as Float) {
+        if t0 < cache.3 && t1 > cache.4 {
             Some((t0, t1))
         } else {
             None
         }
     }
 
-    /// Construct cache for chached intersection
-    pub fn construct_ray_cache<R>(ray: &R) -> (Point3f, Vector3f, Vector3<bool>, Float)
+    /// Construct cache for chached intersection, as
+    /// `(origin, inv_dir, dir_is_neg, max_extend, min_extend)`
+    pub fn construct_ray_cache<R>(ray: &R) -> (Point3f, Vector3f, Vector3<bool>, Float, Float)
         where R: Ray + ?Sized
     {
         let origin = ray.origin();
@@ -588,7 +593,7 @@ impl BBox3f {
         let zero = 0.0 as Float;
         let dir_is_neg = Vector3::new(invert_direction.x < zero, invert_direction.y < zero, invert_direction.z < zero);
         let max_extend = ray.max_extend();
-        (origin, invert_direction, dir_is_neg, max_extend)
+        (origin, invert_direction, dir_is_neg, max_extend, ray.min_extend())
     }
 }
 
@@ -9,6 +9,60 @@
 //! Floating point number helper functions
 use num_traits;
 use super::foundamental::*;
+use super::bbox::BBox3f;
+use std::cmp::Ordering;
+
+/// Named tolerances used throughout the renderer.
+///
+/// Tolerances on positions are either relative to the magnitudes
+/// involved, bounded with `eb_term`, or relative to the extent of
+/// the scene, through `scene_epsilon`. Absolute ones are kept to
+/// quantities without units, such as samples and cosines.
+pub mod consts {
+    use super::*;
+
+    /// fraction of a shadow ray's extent left out near its destination,
+    /// covering intersection routines computing hits relative to
+    /// far-away origins, whose error bounds miss the distance traveled
+    pub const SHADOW_EPSILON: Float = 1e-4 as Float;
+
+    /// fraction of a light sample's segment its shadow ray is pulled in
+    /// by at both ends, as light samples carry no error bounds
+    pub const RAY_EPSILON_SCALE: Float = 1e-4 as Float;
+
+    /// fraction of the scene's diagonal below which distances are
+    /// taken as zero, see `scene_epsilon`
+    pub const SCENE_EPSILON_SCALE: Float = 1e-5 as Float;
+
+    /// number of rounded operations positions are assumed to have gone
+    /// through, bounding their error in `coincide`. Covers a transform
+    /// and a few arithmetic operations after the hit
+    pub const POSITION_ERROR_TERMS: Float = 8. as Float;
+
+    /// smallest uniform sample taken before its logarithm or inverse
+    /// error function, keeping both finite
+    pub const SAMPLE_CLAMP_MIN: Float = 1e-6 as Float;
+
+    /// residual of the sampled cdf at which newton iterations stop,
+    /// well below the resolution of the samples fed
+    pub const NEWTON_TOLERANCE: Float = 1e-5 as Float;
+
+    /// smallest filter width, in texture space, looked up in mipmaps,
+    /// keeping its logarithm finite. Far below any texel size
+    pub const FILTER_WIDTH_MIN: Float = 1e-8 as Float;
+
+    /// distance below which positions in a scene bounded by `bbox` are
+    /// taken as coinciding, by tolerances not bounded otherwise
+    #[inline]
+    pub fn scene_epsilon(bbox: &BBox3f) -> Float {
+        let diagonal = bbox.diagonal().magnitude();
+        if diagonal.is_finite() {
+            SCENE_EPSILON_SCALE * diagonal
+        } else {
+            0. as Float
+        }
+    }
+}
 
 #[inline]
 pub fn clamp(f: Float, min: Float, max: Float) -> Float {
@@ -40,6 +94,65 @@ pub fn one_minus_epsilon() -> Float {
     1.0 as Float - epsilon()
 }
 
+#[inline]
+/// fraction of a shadow ray's extent left out near its destination,
+/// see `consts::SHADOW_EPSILON`
+pub fn shadow_epsilon() -> Float {
+    consts::SHADOW_EPSILON
+}
+
+/// real roots `(t0, t1)` of `a t^2 + b t + c`, `t0 <= t1`, if any.
+///
+/// Computed in double precision, taking first the root whose
+/// numerator doesn't cancel, then the other one as `c / (a t0)`, which
+/// keeps both accurate for nearly tangent rays and tiny `a` or `c`.
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Option<(f64, f64)> {
+    if a == 0. {
+        if b == 0. { return None; }
+        let t = -c / b;
+        return Some((t, t));
+    }
+    let discriminant = b * b - 4. * a * c;
+    if discriminant < 0. { return None; }
+    let root = discriminant.sqrt();
+    let q = if b < 0. { -0.5 * (b - root) } else { -0.5 * (b + root) };
+    if q == 0. {
+        // `b` and `c` both vanish
+        return Some((0., 0.));
+    }
+    let (t0, t1) = (q / a, c / q);
+    if t0 <= t1 { Some((t0, t1)) } else { Some((t1, t0)) }
+}
+
+/// test if positions `a` and `b` are the same, up to the rounding error
+/// of `consts::POSITION_ERROR_TERMS` operations on each coordinate.
+///
+/// Unlike comparisons against a fixed tolerance, this holds for
+/// points far from the origin computed along different paths, and
+/// fails for distinct points near it.
+#[inline]
+pub fn coincide(a: Point3f, b: Point3f) -> bool {
+    let bound = eb_term(consts::POSITION_ERROR_TERMS);
+    let close = |x: Float, y: Float| (x - y).abs() <= bound * x.abs().max(y.abs());
+    close(a.x, b.x) && close(a.y, b.y) && close(a.z, b.z)
+}
+
+#[inline]
+/// `a / b`, or zero if the quotient is not finite
+pub fn safe_div(a: Float, b: Float) -> Float {
+    let ret = a / b;
+    if ret.is_finite() { ret } else { 0. as Float }
+}
+
+#[inline]
+/// a total order over floats, placing NaNs after everything else
+pub fn total_cmp(a: Float, b: Float) -> Ordering {
+    match a.partial_cmp(&b) {
+        Some(ord) => ord,
+        None => a.is_nan().cmp(&b.is_nan()),
+    }
+}
+
 #[inline]
 pub fn nan() -> Float {
     <Float as num_traits::Float>::nan()
@@ -100,12 +213,14 @@ pub fn pi() -> Float {
     <Float as num_traits::FloatConst>::PI()
 }
 
+/// the smallest float greater than `f`, the smallest positive
+/// subnormal for either zero
 #[inline]
 pub fn next_up(f: Float) -> Float {
-    if f.is_infinite() && f.is_sign_positive() {
+    if f.is_nan() || (f.is_infinite() && f.is_sign_positive()) {
         f
-    } else if f == -0. as Float {
-        0. as Float
+    } else if f == 0. as Float {
+        Float::from_bits(1)
     } else {
         let t = f.to_bits();
         if f.is_sign_positive() {
@@ -116,12 +231,14 @@ pub fn next_up(f: Float) -> Float {
     }
 }
 
+/// the greatest float less than `f`, the largest negative
+/// subnormal for either zero
 #[inline]
 pub fn next_down(f: Float) -> Float {
-    if f.is_infinite() && f.is_sign_negative() {
+    if f.is_nan() || (f.is_infinite() && f.is_sign_negative()) {
         f
     } else if f == 0. as Float {
-        -0. as Float
+        -Float::from_bits(1)
     } else {
         let t = f.to_bits();
         if f.is_sign_negative() {
@@ -282,7 +282,7 @@ pub mod normal {
     #[inline]
     pub fn refract(wo: Vector3f, n: Vector3f, eta: Float) -> Option<Vector3f> {
         let cos_theta = wo.dot(n);
-        debug_assert!(cos_theta > 0. as Float);
+        debug_assert!(cos_theta >= 0. as Float);
         let sin2_theta = 1. as Float - cos_theta * cos_theta;
         let sin2_thetat = eta * eta * sin2_theta.max(0. as Float);
         if sin2_thetat >= 1. as Float {
@@ -299,11 +299,25 @@ pub mod normal {
     #[inline]
     pub fn get_basis_from(dir: Vector3f) -> (Vector3f, Vector3f) {
         let mut up = Vector3f::new(0. as Float, 0. as Float, 1. as Float);
-        if relative_eq!(up, dir) {
+        if dir.z.abs() > 0.999 as Float {
             up = Vector3f::new(0. as Float, 1. as Float, 0. as Float);
         };
         let u = up.cross(dir).normalize();
         let v = dir.cross(u).normalize();
         (u, v)
     }
+
+    /// given a normalized `n`, returns `s` and `t` such that `(s, t, n)`
+    /// forms a right-handed orthonormal basis, by the branchless method
+    /// of Duff et al., "Building an Orthonormal Basis, Revisited", 2017
+    #[inline]
+    pub fn orthonormal_basis(n: Vector3f) -> (Vector3f, Vector3f) {
+        let sign = (1. as Float).copysign(n.z);
+        let a = -1. as Float / (sign + n.z);
+        let b = n.x * n.y * a;
+        (
+            Vector3f::new(1. as Float + sign * n.x * n.x * a, sign * b, -sign * n.x),
+            Vector3f::new(b, sign + n.y * n.y * a, -n.y)
+        )
+    }
 }
\ No newline at end of file
@@ -0,0 +1,61 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! defines an orthonormal coordinate frame
+
+use super::foundamental::*;
+
+/// An orthonormal frame, with `n` serving as the local `+z`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frame {
+    /// tangent, local `+x`
+    pub s: Vector3f,
+    /// bitangent, local `+y`
+    pub t: Vector3f,
+    /// normal, local `+z`
+    pub n: Vector3f,
+}
+
+impl Frame {
+    /// construct a frame around normal `n`, with its tangent
+    /// being `dpdu` Gram-Schmidt-orthogonalized against `n`.
+    /// An arbitrary tangent is chosen if `dpdu` is degenerate.
+    pub fn new(n: Vector3f, dpdu: Vector3f) -> Frame {
+        let n = n.normalize();
+        let s = dpdu - n * n.dot(dpdu);
+        if s.magnitude2() > 0. as Float && s.x.is_finite() && s.y.is_finite() && s.z.is_finite() {
+            let s = s.normalize();
+            Frame{
+                s: s, t: n.cross(s), n: n,
+            }
+        } else {
+            Frame::from_normal(n)
+        }
+    }
+
+    /// construct a frame around normal `n`, with arbitrary tangent
+    pub fn from_normal(n: Vector3f) -> Frame {
+        let n = n.normalize();
+        let (s, _) = normal::get_basis_from(n);
+        Frame{
+            s: s, t: n.cross(s), n: n,
+        }
+    }
+
+    /// transforms `v` from parent frame into this frame
+    #[inline]
+    pub fn to_local(&self, v: Vector3f) -> Vector3f {
+        Vector3f::new(v.dot(self.s), v.dot(self.t), v.dot(self.n))
+    }
+
+    /// transforms `v` from this frame into parent frame
+    #[inline]
+    pub fn to_world(&self, v: Vector3f) -> Vector3f {
+        self.s * v.x + self.t * v.y + self.n * v.z
+    }
+}
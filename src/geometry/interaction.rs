@@ -7,7 +7,7 @@
 // except according to those terms.
 
 //! Basic geometric interaction
-use super::{RayDifferential, Ray, RawRay};
+use super::{RayDifferential, Ray, RawRay, RayKind};
 use super::foundamental::*;
 use super::transform::TransformExt;
 use super::float;
@@ -32,12 +32,19 @@ pub struct InteractInfo {
 impl InteractInfo {
     pub fn apply_transform<T>(&self, t: &T) -> Self
         where T: TransformExt
+    {
+        self.apply_transform_with(t, &t.normal_matrix())
+    }
+
+    /// as `apply_transform`, given the `normal_matrix` of `t`
+    pub fn apply_transform_with<T>(&self, t: &T, normal: &Matrix4f) -> Self
+        where T: TransformExt
     {
         InteractInfo {
             pos: t.transform_point(self.pos),
             pos_err: t.transform_vector(self.pos_err),
             wo: t.transform_vector(self.wo),
-            norm: t.transform_norm(self.norm),
+            norm: normal.transform_vector(self.norm).normalize(),
         }
     }
 
@@ -70,6 +77,18 @@ impl InteractInfo {
 
         ret
     }
+
+    /// spawn a ray towards `other`, offsetting both ends by their
+    /// error bounds so that neither surface is hit by it
+    #[inline]
+    pub fn spawn_to(&self, other: &InteractInfo) -> RawRay {
+        let pfrom = self.offset_towards(other.pos - self.pos);
+        let pto = other.offset_towards(pfrom - other.pos);
+        let mut ray = RawRay::spawn(pfrom, pto).with_kind(RayKind::Shadow);
+        let tmax = ray.max_extend() * (1. as Float - float::shadow_epsilon());
+        ray.set_max_extend(tmax);
+        ray
+    }
 }
 
 /// Differential information about some $p(u, v)$, $n(u, v)$
@@ -89,17 +108,28 @@ pub struct DuvInfo {
 impl DuvInfo {
     pub fn apply_transform<T>(&self, t: &T) -> Self
         where T: TransformExt
+    {
+        self.apply_transform_with(t, &t.normal_matrix())
+    }
+
+    /// as `apply_transform`, given the `normal_matrix` of `t`
+    pub fn apply_transform_with<T>(&self, t: &T, normal: &Matrix4f) -> Self
+        where T: TransformExt
     {
         DuvInfo {
             dpdu: t.transform_vector(self.dpdu),
             dpdv: t.transform_vector(self.dpdv),
-            dndu: t.transform_norm(self.dndu),
-            dndv: t.transform_norm(self.dndv),
+            dndu: normal.transform_vector(self.dndu),
+            dndv: normal.transform_vector(self.dndv),
         }
     }
 }
 
 /// Interaction at some surface denoted as $f(u, v)$
+///
+/// With `f32` floats it takes 272 bytes, a quarter of it `primitive_local`,
+/// so the bvh finds its nearest triangle by distance first
+/// (`Composable::intersect_t`) and builds one only for that.
 #[derive(Copy, Clone)]
 #[must_use]
 pub struct SurfaceInteraction<'b> {
@@ -117,6 +147,17 @@ pub struct SurfaceInteraction<'b> {
     // pub shape_info: Option<&'a ShapeInfo>,
     /// primitive hit
     pub primitive_hit: Option<&'b Primitive>,
+    /// transform from this frame into that of `primitive_hit`, if
+    /// the primitive is instanced by a transformed aggregate
+    pub primitive_local: Option<Matrix4f>,
+    /// position shadow rays leave from, if shifted off `basic.pos`
+    /// to hide the shadow terminator of coarse smooth-shaded meshes.
+    /// Bsdfs of such surfaces take the shading normal as geometric.
+    pub shadow_pos: Option<Point3f>,
+    /// length in this frame of a unit of bump displacement along
+    /// `shading_norm`, as measured in the frame the surface is
+    /// modeled in, so that bumps scale along with instances
+    pub bump_scale: Float,
 }
 
 use std::fmt::*;
@@ -158,6 +199,9 @@ impl<'b> SurfaceInteraction<'b> {
             shading_duv: duv,
             // shape_info: shape_info,
             primitive_hit: None,
+            primitive_local: None,
+            shadow_pos: None,
+            bump_scale: 1. as Float,
         }
     }
 
@@ -166,7 +210,7 @@ impl<'b> SurfaceInteraction<'b> {
     /// according to `self.shading_norm`. Otherwise, the reverse.
     pub fn set_shading(&mut self, duv: DuvInfo, orient_norm_by_shading: bool)
     {
-        self.duv = duv;
+        self.shading_duv = duv;
         // FIXME: should update according to more cretiarias
         let mut norm = duv.dpdu.cross(duv.dpdv).normalize();
 
@@ -181,25 +225,50 @@ impl<'b> SurfaceInteraction<'b> {
         self.shading_norm = norm;
     }
 
+    /// test if the surface is hit from the back of its geometric normal,
+    /// as from inside a closed shape
+    #[inline]
+    pub fn is_backface(&self) -> bool {
+        self.basic.wo.dot(self.basic.norm) < 0. as Float
+    }
+
     pub fn set_primitive<P>(&mut self, primitive: &'b P)
         where P: Primitive
     {
-        self.primitive_hit = Some(primitive)
+        self.primitive_hit = Some(primitive);
+        self.primitive_local = None;
     }
 
     pub fn apply_transform<T>(&self, t: &T) -> SurfaceInteraction<'b>
         where T: TransformExt
     {
+        // inverted once rather than for each normal
+        let normal = t.normal_matrix();
         SurfaceInteraction{
-            basic: self.basic.apply_transform(t),
+            basic: self.basic.apply_transform_with(t, &normal),
             uv: self.uv,
-            duv: self.duv.apply_transform(t),
-            shading_norm: t.transform_norm(self.shading_norm),
-            shading_duv: self.shading_duv.apply_transform(t),
+            duv: self.duv.apply_transform_with(t, &normal),
+            shading_norm: normal.transform_vector(self.shading_norm).normalize(),
+            shading_duv: self.shading_duv.apply_transform_with(t, &normal),
             primitive_hit: self.primitive_hit,
+            primitive_local: self.primitive_local,
+            shadow_pos: self.shadow_pos.map(|p| t.transform_point(p)),
+            bump_scale: self.bump_scale * (1. as Float / normal.transform_vector(self.shading_norm).magnitude()),
         }
     }
 
+    /// transform the interaction out of an instance's local frame by
+    /// `local_parent`, whose inverse is `parent_local`, remembering how
+    /// to get back to the frame `primitive_hit` is evaluated in
+    pub fn apply_instancing(&self, local_parent: &Matrix4f, parent_local: &Matrix4f) -> SurfaceInteraction<'b> {
+        let mut ret = self.apply_transform(local_parent);
+        ret.primitive_local = Some(match self.primitive_local {
+            Some(m) => m * *parent_local,
+            None => *parent_local,
+        });
+        ret
+    }
+
     /// compute image plane differentials according to the differential ray
     pub fn compute_dxy(&self, ray_diff: &RayDifferential) -> DxyInfo {
         if let Some(ref diffs) = ray_diff.diffs {
@@ -223,6 +292,17 @@ impl<'b> SurfaceInteraction<'b> {
         }
     }
 
+    /// position a shadow ray towards `wi` should leave from, namely
+    /// `shadow_pos` if it's shifted to the side of the shading normal
+    /// `wi` lies on, `basic.pos` otherwise
+    #[inline]
+    pub fn shadow_origin(&self, wi: Vector3f) -> Point3f {
+        match self.shadow_pos {
+            Some(p) if (p - self.basic.pos).dot(self.shading_norm) * wi.dot(self.shading_norm) > 0. as Float => p,
+            _ => self.basic.pos,
+        }
+    }
+
     #[inline]
     pub fn is_emissive(&self) -> bool {
         if let Some(hit) = self.primitive_hit {
@@ -254,6 +334,9 @@ impl<'b> SurfaceInteraction<'b> {
     pub fn le(&self, dir: Vector3f) -> RGBSpectrumf {
         if let Some(hit) = self.primitive_hit {
             if hit.is_emissive() {
+                if let Some(ref m) = self.primitive_local {
+                    return hit.evaluate_path(m.transform_point(self.basic.pos), m.transform_vector(dir));
+                }
                 return hit.evaluate_path(self.basic.pos, dir);
             }
         }
@@ -14,6 +14,8 @@
 //! - `bbox` defines the bounding box interface.
 //! - `transform` defines the transform interface.
 //! - `interaction` defines the interaction interface.
+//! - `frame` defines orthonormal coordinate frames.
+//! - `polygon` triangulates simple polygons.
 
 pub mod float;
 pub mod ray;
@@ -21,13 +23,16 @@ pub mod bbox;
 pub mod transform;
 pub mod foundamental;
 pub mod interaction;
+pub mod frame;
+pub mod polygon;
 pub mod prelude;
 
 pub use self::foundamental::*;
-pub use self::ray::{Ray, RawRay, RayDifferential};
+pub use self::ray::{Ray, RawRay, RayDifferential, RayKind, VisibilityFlags};
 pub use self::transform::TransformExt;
 pub use self::bbox::{BBox2, BBox3, BBox2f, BBox3f};
 pub use self::interaction::{DuvInfo, InteractInfo, SurfaceInteraction};
+pub use self::frame::Frame;
 
 #[cfg(test)]
 mod tests;
\ No newline at end of file
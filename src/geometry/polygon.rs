@@ -0,0 +1,127 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Triangulation of simple polygons by ear clipping
+
+use super::foundamental::*;
+
+/// normal of the best-fit plane of a polygon, by Newell's method.
+/// Its direction follows the winding of `vertices`, its magnitude
+/// is twice the projected area.
+pub fn newell_normal(vertices: &[Point3f]) -> Vector3f {
+    let mut n = Vector3f::zero();
+    for (i, p) in vertices.iter().enumerate() {
+        let q = vertices[(i + 1) % vertices.len()];
+        n.x += (p.y - q.y) * (p.z + q.z);
+        n.y += (p.z - q.z) * (p.x + q.x);
+        n.z += (p.x - q.x) * (p.y + q.y);
+    }
+    n
+}
+
+/// maximum distance from `vertices` to their best-fit plane,
+/// relative to the polygon's extent
+pub fn non_planarity(vertices: &[Point3f]) -> Float {
+    if vertices.len() < 4 { return 0. as Float; }
+    let n = newell_normal(vertices);
+    let magnitude = n.magnitude();
+    if magnitude == 0. as Float { return 0. as Float; }
+    let n = n / magnitude;
+    let mut centroid = Vector3f::zero();
+    for p in vertices { centroid += p.to_vec(); }
+    let centroid = Point3f::from_vec(centroid / vertices.len() as Float);
+    let mut extent = 0. as Float;
+    let mut deviation = 0. as Float;
+    for p in vertices {
+        extent = extent.max((*p - centroid).magnitude());
+        deviation = deviation.max(n.dot(*p - centroid).abs());
+    }
+    if extent == 0. as Float { 0. as Float } else { deviation / extent }
+}
+
+/// Triangulate a simple, possibly concave polygon by ear clipping on its
+/// best-fit plane. Returns vertex indices of the triangles, wound as the
+/// polygon is. Collinear vertices are clipped without emitting triangles.
+pub fn triangulate(vertices: &[Point3f]) -> Vec<[usize; 3]> {
+    let n = vertices.len();
+    let mut ret = Vec::with_capacity(n.saturating_sub(2));
+    if n < 3 { return ret; }
+    if n == 3 {
+        ret.push([0, 1, 2]);
+        return ret;
+    }
+    let plane_norm = newell_normal(vertices);
+    if plane_norm.magnitude2() == 0. as Float {
+        // degenerate polygon, nothing to cover
+        return ret;
+    }
+    let (s, t) = normal::get_basis_from(plane_norm.normalize());
+    // projected onto the plane such that the polygon winds counterclockwise
+    let projected: Vec<Point2f> = vertices.iter().map(|p| {
+        let v = p.to_vec();
+        Point2f::new(v.dot(s), v.dot(t))
+    }).collect();
+    let mut extent = 0. as Float;
+    for p in &projected {
+        extent = extent.max((*p - projected[0]).magnitude2());
+    }
+    let epsilon = extent * 1e-10 as Float;
+
+    let mut remaining: Vec<usize> = (0..n).collect();
+    let mut i = 0;
+    let mut misses = 0;
+    while remaining.len() > 3 {
+        let m = remaining.len();
+        let (ia, ib, ic) = (remaining[(i + m - 1) % m], remaining[i % m], remaining[(i + 1) % m]);
+        let (a, b, c) = (projected[ia], projected[ib], projected[ic]);
+        let cross = cross2(b - a, c - b);
+        if cross.abs() <= epsilon {
+            // collinear, or a spike folding back onto itself
+            remaining.remove(i % m);
+            misses = 0;
+            continue;
+        }
+        let is_ear = cross > 0. as Float && remaining.iter().all(|&j| {
+            j == ia || j == ib || j == ic
+            || projected[j] == a || projected[j] == b || projected[j] == c
+            || !in_triangle(projected[j], a, b, c)
+        });
+        if is_ear || misses >= m {
+            // no ear can be found for non-simple polygons,
+            // clip anyway to guarantee progress
+            ret.push([ia, ib, ic]);
+            remaining.remove(i % m);
+            misses = 0;
+        } else {
+            i += 1;
+            misses += 1;
+        }
+        i %= remaining.len();
+    }
+    if remaining.len() == 3 {
+        let (a, b, c) = (projected[remaining[0]], projected[remaining[1]], projected[remaining[2]]);
+        if cross2(b - a, c - b).abs() > epsilon {
+            ret.push([remaining[0], remaining[1], remaining[2]]);
+        }
+    }
+    ret
+}
+
+#[inline]
+fn cross2(a: Vector2f, b: Vector2f) -> Float {
+    a.x * b.y - a.y * b.x
+}
+
+// test if `p` lies inside or on the boundary of the
+// counterclockwise triangle `abc`
+#[inline]
+fn in_triangle(p: Point2f, a: Point2f, b: Point2f, c: Point2f) -> bool {
+    cross2(b - a, p - a) >= 0. as Float
+    && cross2(c - b, p - b) >= 0. as Float
+    && cross2(a - c, p - c) >= 0. as Float
+}
@@ -9,8 +9,10 @@
 //! Fundamental definition preludes
 
 pub use super::foundamental::*;
-pub use super::ray::{Ray, RawRay, RayDifferential};
+pub use super::ray::{Ray, RawRay, RayDifferential, RayKind, VisibilityFlags};
+pub use super::ray::{VISIBLE_CAMERA, VISIBLE_SHADOW, VISIBLE_DIFFUSE_INDIRECT, VISIBLE_SPECULAR_INDIRECT};
 pub use super::transform::TransformExt;
 pub use super::bbox::{BBox2, BBox3, BBox2f, BBox3f};
 pub use super::interaction::{DuvInfo, DxyInfo, InteractInfo, SurfaceInteraction};
+pub use super::frame::Frame;
 pub use super::float;
@@ -29,6 +29,13 @@ pub trait Ray {
     /// Set the max extend of the ray
     fn set_max_extend(&mut self, tmax: Float);
 
+    /// Returns the min extend of the ray, in `self.direction().length()`.
+    /// Nothing closer than that is hit
+    fn min_extend(&self) -> Float;
+
+    /// Set the min extend of the ray
+    fn set_min_extend(&mut self, tmin: Float);
+
     /// Returns where the ray heads to.
     /// The length of the returned vector is the unit of the ray
     fn direction(&self) -> Vector3f;
@@ -58,14 +65,79 @@ pub trait Ray {
     }
 }
 
-/// A semi-infinite line specified by its `origin` and `dir`ection.
+bitflags! {
+    /// Kinds of rays a primitive can be hit by
+    pub flags VisibilityFlags: u32 {
+        const VISIBLE_CAMERA = 0x1,
+        const VISIBLE_SHADOW = 0x2,
+        const VISIBLE_DIFFUSE_INDIRECT = 0x4,
+        const VISIBLE_SPECULAR_INDIRECT = 0x8,
+    }
+}
+
+impl Default for VisibilityFlags {
+    /// visible to all rays
+    #[inline]
+    fn default() -> VisibilityFlags {
+        VisibilityFlags::all()
+    }
+}
+
+/// What a ray is traced for, deciding the primitives it can hit
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RayKind {
+    /// hits every primitive, for rays not traced on behalf of a renderer
+    Any,
+    /// primary rays leaving the camera
+    Camera,
+    /// visibility tests between two points
+    Shadow,
+    /// rays continuing a path after a non-specular bounce
+    DiffuseIndirect,
+    /// rays continuing a path after a specular bounce
+    SpecularIndirect,
+}
+
+impl Default for RayKind {
+    #[inline]
+    fn default() -> RayKind {
+        RayKind::Any
+    }
+}
+
+impl RayKind {
+    /// the kind of a ray continuing a path after a bounce,
+    /// specular or not
+    #[inline]
+    pub fn indirect(specular: bool) -> RayKind {
+        if specular { RayKind::SpecularIndirect } else { RayKind::DiffuseIndirect }
+    }
+
+    /// test if a primitive with visibility `flags` can be hit
+    #[inline]
+    pub fn sees(self, flags: VisibilityFlags) -> bool {
+        match self {
+            RayKind::Any => true,
+            RayKind::Camera => flags.contains(VISIBLE_CAMERA),
+            RayKind::Shadow => flags.contains(VISIBLE_SHADOW),
+            RayKind::DiffuseIndirect => flags.contains(VISIBLE_DIFFUSE_INDIRECT),
+            RayKind::SpecularIndirect => flags.contains(VISIBLE_SPECULAR_INDIRECT),
+        }
+    }
+}
+
+/// A semi-infinite line specified by its `origin` and `dir`ection,
+/// spanning from `tmin`, by default 0, to `tmax`.
 #[derive(PartialEq, Copy, Clone, Debug)]
 #[must_use]
 pub struct RawRay {
     origin: Point3f,
     dir: Vector3f,
     tmax: Float,
+    tmin: Float,
     stc: ShearingTransformCache,
+    kind: RayKind,
+    time: Float,
 }
 
 impl RawRay {
@@ -76,7 +148,10 @@ impl RawRay {
             origin: origin,
             dir: dir,
             tmax: tmax,
+            tmin: 0. as Float,
             stc: unsafe {mem::uninitialized()},
+            kind: RayKind::Any,
+            time: 0. as Float,
         };
         let stc = ShearingTransformCache::from_ray(&ray);
         ray.stc = stc;
@@ -97,6 +172,51 @@ impl RawRay {
         RawRay::new(origin, dir_unormed/tmax, tmax)
     }
 
+    /// what the ray is traced for
+    #[inline]
+    pub fn kind(&self) -> RayKind {
+        self.kind
+    }
+
+    /// set what the ray is traced for
+    #[inline]
+    pub fn set_kind(&mut self, kind: RayKind) {
+        self.kind = kind;
+    }
+
+    /// the ray, traced for `kind`
+    #[inline]
+    pub fn with_kind(mut self, kind: RayKind) -> RawRay {
+        self.kind = kind;
+        self
+    }
+
+    /// time the ray is traced at, within the camera's shutter interval
+    #[inline]
+    pub fn time(&self) -> Float {
+        self.time
+    }
+
+    /// set the time the ray is traced at
+    #[inline]
+    pub fn set_time(&mut self, time: Float) {
+        self.time = time;
+    }
+
+    /// the ray, traced at `time`
+    #[inline]
+    pub fn with_time(mut self, time: Float) -> RawRay {
+        self.time = time;
+        self
+    }
+
+    /// the ray, starting from `tmin`
+    #[inline]
+    pub fn with_min_extend(mut self, tmin: Float) -> RawRay {
+        self.tmin = tmin;
+        self
+    }
+
     #[inline]
     fn reset_shearing_transform(&mut self) {
         let stc = ShearingTransformCache::from_ray(self);
@@ -134,8 +254,18 @@ impl Ray for RawRay {
 
     #[inline]
     fn set_max_extend(&mut self, tmax: Float) {
+        // the shearing transform only depends on origin and direction
         self.tmax = tmax;
-        self.reset_shearing_transform();
+    }
+
+    #[inline]
+    fn min_extend(&self) -> Float {
+        self.tmin
+    }
+
+    #[inline]
+    fn set_min_extend(&mut self, tmin: Float) {
+        self.tmin = tmin;
     }
 
     #[inline]
@@ -149,6 +279,8 @@ impl Ray for RawRay {
         self.reset_shearing_transform();
     }
 
+    /// The direction is transformed along without normalization,
+    /// so the extends in its length stay the same
     // FIXME: Deal with rounding error
     #[inline]
     fn apply_transform(&self, t: &Matrix4f) -> RawRay
@@ -157,7 +289,7 @@ impl Ray for RawRay {
             t.transform_point(self.origin),
             t.transform_vector(self.dir),
             self.tmax,
-        )
+        ).with_kind(self.kind).with_time(self.time).with_min_extend(self.tmin)
     }
 
     #[inline]
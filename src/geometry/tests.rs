@@ -104,4 +104,360 @@ mod bbox {
         assert_eq!(bboxiter.next(), Some(Point2::new(1, 1)));
         assert_eq!(bboxiter.next(), None);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_bbox3_rotated() {
+        let bbox = BBox3::new(Point3f::new(0. as Float, 0., 0.), Point3f::new(1. as Float, 1., 0.1));
+        let m = Matrix4f::from_angle_x(Deg(60. as Float));
+        let transformed = bbox.apply_transform(&m);
+        for i in 0..8 {
+            let corner = Point3f::new(
+                (i & 1) as Float, ((i >> 1) & 1) as Float, ((i >> 2) & 1) as Float * 0.1
+            );
+            let p = m.transform_point(corner);
+            assert!(transformed.pmin.x <= p.x + 1e-6 && transformed.pmax.x >= p.x - 1e-6);
+            assert!(transformed.pmin.y <= p.y + 1e-6 && transformed.pmax.y >= p.y - 1e-6);
+            assert!(transformed.pmin.z <= p.z + 1e-6 && transformed.pmax.z >= p.z - 1e-6);
+        }
+    }
+}
+#[cfg(test)]
+mod frame {
+    use geometry::prelude::*;
+
+    #[test]
+    fn test_frame_roundtrip() {
+        let n = Vector3f::new(0.3 as Float, -0.5, 0.8).normalize();
+        let dpdu = Vector3f::new(1. as Float, 0.2, 0.7);
+        let frame = Frame::new(n, dpdu);
+        let v = Vector3f::new(-0.4 as Float, 2.0, 0.1);
+        assert_relative_eq!(frame.to_world(frame.to_local(v)), v, epsilon = 1e-5);
+        assert_relative_eq!(frame.to_local(frame.to_world(v)), v, epsilon = 1e-5);
+        assert_relative_eq!(frame.to_local(n), Vector3f::new(0. as Float, 0., 1.), epsilon = 1e-5);
+    }
+
+    #[test]
+    fn test_frame_orthogonalize() {
+        let n = Vector3f::new(0. as Float, 0., 1.);
+        // skewed tangent, should follow its projection onto the tangent plane
+        let frame = Frame::new(n, Vector3f::new(1. as Float, 1., 3.));
+        assert_relative_eq!(frame.s, Vector3f::new(1. as Float, 1., 0.).normalize(), epsilon = 1e-5);
+        assert_relative_eq!(frame.s.dot(frame.n), 0. as Float, epsilon = 1e-5);
+        assert_relative_eq!(frame.t.dot(frame.n), 0. as Float, epsilon = 1e-5);
+        assert_relative_eq!(frame.s.dot(frame.t), 0. as Float, epsilon = 1e-5);
+        assert_relative_eq!(frame.s.cross(frame.t), frame.n, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn test_frame_degenerate_tangent() {
+        let n = Vector3f::new(0. as Float, 1., 0.);
+        let frame = Frame::new(n, n * 2. as Float);
+        assert_relative_eq!(frame.s.magnitude(), 1. as Float, epsilon = 1e-5);
+        assert_relative_eq!(frame.s.dot(frame.n), 0. as Float, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn test_basis_near_poles() {
+        let dirs = [
+            Vector3f::new(0. as Float, 0., 1.),
+            Vector3f::new(0. as Float, 0., -1.),
+            Vector3f::new(1e-4 as Float, 0., 1.).normalize(),
+            Vector3f::new(0. as Float, -1e-4, -1.).normalize(),
+        ];
+        for &dir in &dirs {
+            let (u, v) = normal::get_basis_from(dir);
+            assert_relative_eq!(u.magnitude(), 1. as Float, epsilon = 1e-5);
+            assert_relative_eq!(v.magnitude(), 1. as Float, epsilon = 1e-5);
+            assert_relative_eq!(u.dot(dir), 0. as Float, epsilon = 1e-5);
+            assert_relative_eq!(v.dot(dir), 0. as Float, epsilon = 1e-5);
+            assert_relative_eq!(u.dot(v), 0. as Float, epsilon = 1e-5);
+        }
+    }
+}
+#[cfg(test)]
+mod polygon {
+    use geometry::prelude::*;
+    use geometry::polygon::*;
+
+    fn area(vertices: &[Point3f], triangles: &[[usize; 3]]) -> Float {
+        triangles.iter().map(|t| {
+            (vertices[t[1]] - vertices[t[0]]).cross(vertices[t[2]] - vertices[t[0]]).magnitude() * 0.5 as Float
+        }).sum()
+    }
+
+    #[test]
+    fn test_concave() {
+        // an L-shape tilted out of the xy-plane
+        let l = [
+            (0., 0.), (2., 0.), (2., 1.), (1., 1.), (1., 2.), (0., 2.)
+        ];
+        let vertices: Vec<Point3f> = l.iter().map(|&(x, y)| {
+            Point3f::new(x as Float, y as Float, (x + y) as Float * 0.5)
+        }).collect();
+        let triangles = triangulate(&vertices);
+        assert_eq!(triangles.len(), 4);
+        let scale = (1.5 as Float).sqrt();
+        assert_relative_eq!(area(&vertices, &triangles), 3. as Float * scale, epsilon = 1e-4);
+        // no triangle covers the missing square
+        for t in &triangles {
+            let c = (vertices[t[0]].to_vec() + vertices[t[1]].to_vec() + vertices[t[2]].to_vec()) / 3. as Float;
+            assert!(c.x < 1. as Float || c.y < 1. as Float);
+            // winding kept
+            let n = (vertices[t[1]] - vertices[t[0]]).cross(vertices[t[2]] - vertices[t[0]]);
+            assert!(n.dot(newell_normal(&vertices)) > 0. as Float);
+        }
+        assert_relative_eq!(non_planarity(&vertices), 0. as Float, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn test_collinear() {
+        let vertices = [
+            Point3f::new(0. as Float, 0., 0.),
+            Point3f::new(1. as Float, 0., 0.),
+            Point3f::new(2. as Float, 0., 0.),
+            Point3f::new(2. as Float, 2., 0.),
+            Point3f::new(0. as Float, 2., 0.),
+            Point3f::new(0. as Float, 1., 0.),
+        ];
+        let triangles = triangulate(&vertices);
+        assert_relative_eq!(area(&vertices, &triangles), 4. as Float, epsilon = 1e-4);
+        for t in &triangles {
+            let n = (vertices[t[1]] - vertices[t[0]]).cross(vertices[t[2]] - vertices[t[0]]);
+            assert!(n.magnitude() > 1e-4 as Float);
+        }
+        let bent = [
+            Point3f::new(0. as Float, 0., 0.),
+            Point3f::new(1. as Float, 0., 0.),
+            Point3f::new(1. as Float, 1., 0.5),
+            Point3f::new(0. as Float, 1., 0.),
+        ];
+        assert!(non_planarity(&bent) > 0.1 as Float);
+    }
+}
+
+#[cfg(test)]
+mod nan_safety {
+    use geometry::prelude::*;
+    use std::cmp::Ordering;
+
+    #[test]
+    fn test_safe_div() {
+        assert_eq!(float::safe_div(1. as Float, 4. as Float), 0.25 as Float);
+        assert_eq!(float::safe_div(1. as Float, 0. as Float), 0. as Float);
+        assert_eq!(float::safe_div(0. as Float, 0. as Float), 0. as Float);
+        assert_eq!(float::safe_div(1. as Float, 1e-45 as Float), 0. as Float);
+        assert_eq!(float::safe_div(float::nan(), 1. as Float), 0. as Float);
+    }
+
+    #[test]
+    fn test_total_cmp() {
+        let mut v = vec![2. as Float, float::nan(), -1. as Float, float::infinity(), 0. as Float];
+        v.sort_by(|a, b| float::total_cmp(*a, *b));
+        assert_eq!(&v[..4], &[-1. as Float, 0. as Float, 2. as Float, float::infinity()]);
+        assert!(v[4].is_nan());
+        assert_eq!(float::total_cmp(float::nan(), float::nan()), Ordering::Equal);
+    }
+}
+
+#[cfg(test)]
+mod tolerances {
+    use geometry::prelude::*;
+
+    #[test]
+    fn test_eb_term() {
+        assert_eq!(float::eb_term(0. as Float), 0. as Float);
+        // about `n` half-ulps of one, bounding `n` rounded operations
+        for n in 1..16 {
+            let n = n as Float;
+            let term = float::eb_term(n);
+            assert!(term >= n * float::machine_epsilon());
+            assert_relative_eq!(term, n * float::machine_epsilon(), max_relative = 1e-5);
+            assert!(float::eb_term(n + 1. as Float) > term);
+        }
+        // a sum of three terms stays within its bound
+        let (a, b, c) = (0.1 as Float, 0.7 as Float, 1e4 as Float);
+        let exact = a as f64 + b as f64 + c as f64;
+        let err = ((a + b + c) as f64 - exact).abs();
+        assert!(err <= (float::eb_term(2. as Float) * (a.abs() + b.abs() + c.abs())) as f64);
+    }
+
+    #[test]
+    fn test_next_up_down() {
+        let tiny = Float::from_bits(1);
+        assert!(tiny > 0. as Float);
+        // crossing zero
+        assert_eq!(float::next_up(0. as Float), tiny);
+        assert_eq!(float::next_up(-0. as Float), tiny);
+        assert_eq!(float::next_down(0. as Float), -tiny);
+        assert_eq!(float::next_down(-0. as Float), -tiny);
+        assert_eq!(float::next_up(-tiny), 0. as Float);
+        assert_eq!(float::next_down(tiny), 0. as Float);
+        // subnormals step by the smallest one
+        assert_eq!(float::next_up(tiny), tiny * 2. as Float);
+        assert_eq!(float::next_down(-tiny), -tiny * 2. as Float);
+        let min_normal = <Float as ::num_traits::Float>::min_positive_value();
+        assert!(float::next_down(min_normal) < min_normal);
+        assert!(float::next_down(min_normal) > 0. as Float);
+        // infinities
+        assert_eq!(float::next_up(float::infinity()), float::infinity());
+        assert_eq!(float::next_down(float::neg_infinity()), float::neg_infinity());
+        assert_eq!(float::next_up(float::neg_infinity()), -::std::f32::MAX as Float);
+        assert_eq!(float::next_down(float::infinity()), ::std::f32::MAX as Float);
+        assert_eq!(float::next_up(::std::f32::MAX as Float), float::infinity());
+        assert!(float::next_up(float::nan()).is_nan());
+        // and ordinary values move by one ulp
+        for &v in &[1. as Float, -1., 3.5, -1e-20, 1e20] {
+            assert!(float::next_up(v) > v);
+            assert!(float::next_down(v) < v);
+            assert_eq!(float::next_down(float::next_up(v)), v);
+        }
+    }
+
+    #[test]
+    fn test_coincide() {
+        // far from the origin, the same point computed along two paths
+        let p = Point3f::new(1e6 as Float, -3e6, 2.5e6);
+        let offset = Vector3f::new(0.37 as Float, -0.11, 0.05);
+        let q = (p + offset) + (-offset);
+        assert!(float::coincide(p, q));
+        let r = Point3f::new(float::next_up(p.x), p.y, float::next_down(p.z));
+        assert!(float::coincide(p, r));
+        assert!(!float::coincide(p, p + Vector3f::new(1. as Float, 0., 0.)));
+        // near it, nearby points are distinct
+        let a = Point3f::new(1e-6 as Float, 0., 0.);
+        let b = Point3f::new(2e-6 as Float, 0., 0.);
+        assert!(!float::coincide(a, b));
+        assert!(float::coincide(a, a));
+        let origin = Point3f::new(0. as Float, 0., 0.);
+        assert!(!float::coincide(origin, Point3f::new(0. as Float, 1e-30, 0.)));
+    }
+
+    #[test]
+    fn test_solve_quadratic() {
+        // roots far apart, where the textbook formula cancels
+        let (t0, t1) = float::solve_quadratic(1., -(1e8 + 1e-8), 1.).unwrap();
+        assert_relative_eq!(t0, 1e-8, max_relative = 1e-12);
+        assert_relative_eq!(t1, 1e8, max_relative = 1e-12);
+        let (t0, t1) = float::solve_quadratic(-2., 2., 4.).unwrap();
+        assert_eq!((t0, t1), (-1., 2.));
+        assert_eq!(float::solve_quadratic(1., 0., 0.), Some((0., 0.)));
+        assert_eq!(float::solve_quadratic(1., 0., 1.), None);
+        assert_eq!(float::solve_quadratic(0., 2., -1.), Some((0.5, 0.5)));
+        assert_eq!(float::solve_quadratic(0., 0., 1.), None);
+    }
+
+    #[test]
+    fn test_scene_epsilon() {
+        let unit = BBox3f::new(Point3f::new(0. as Float, 0., 0.), Point3f::new(1. as Float, 1., 1.));
+        let large = BBox3f::new(Point3f::new(0. as Float, 0., 0.), Point3f::new(1e3 as Float, 1e3, 1e3));
+        let e = float::consts::scene_epsilon(&unit);
+        assert!(e > 0. as Float);
+        assert_relative_eq!(float::consts::scene_epsilon(&large), e * 1e3 as Float, max_relative = 1e-4);
+        let empty = BBox3f::new(
+            Point3f::new(float::infinity(), float::infinity(), float::infinity()),
+            Point3f::new(float::neg_infinity(), float::neg_infinity(), float::neg_infinity())
+        );
+        assert_eq!(float::consts::scene_epsilon(&empty), 0. as Float);
+    }
+}
+
+#[cfg(test)]
+mod transform_norm {
+    use geometry::prelude::*;
+
+    // non-uniformly scaled, then rotated and moved
+    fn transform() -> Matrix4f {
+        Matrix4f::from_translation(Vector3f::new(0.5 as Float, -1., 2.))
+            * Matrix4f::from_angle_y(Rad(0.7 as Float))
+            * Matrix4f::from_nonuniform_scale(2. as Float, 1., 1.)
+    }
+
+    fn sphere(theta: Float, phi: Float) -> Point3f {
+        let (st, ct) = theta.sin_cos();
+        let (sp, cp) = phi.sin_cos();
+        Point3f::new(st * cp, st * sp, ct)
+    }
+
+    #[test]
+    fn test_sphere_normals() {
+        let t = transform();
+        let h = 1e-3 as Float;
+        for i in 1..8 {
+            for j in 0..8 {
+                let (theta, phi) = (i as Float * 0.39 as Float, j as Float * 0.78 as Float);
+                let p = |theta, phi| t.transform_point(sphere(theta, phi));
+                let dtheta = (p(theta + h, phi) - p(theta - h, phi)) / (2. as Float * h);
+                let dphi = (p(theta, phi + h) - p(theta, phi - h)) / (2. as Float * h);
+                let norm = sphere(theta, phi).to_vec();
+                let transformed = t.transform_norm(norm);
+                assert_relative_eq!(transformed.magnitude(), 1. as Float, epsilon = 1e-5);
+                assert!(transformed.dot(dtheta.normalize()).abs() < 1e-3, "{} {}", theta, phi);
+                assert!(transformed.dot(dphi.normalize()).abs() < 1e-3, "{} {}", theta, phi);
+                // still facing outwards
+                assert!(transformed.dot(p(theta, phi) - t.transform_point(Point3f::new(0. as Float, 0., 0.))) > 0. as Float);
+
+                // areas of the patches spanned by the derivatives
+                let local = (sphere(theta + h, phi) - sphere(theta - h, phi))
+                    .cross(sphere(theta, phi + h) - sphere(theta, phi - h)).magnitude();
+                let parent = (dtheta.cross(dphi) * (4. as Float * h * h)).magnitude();
+                assert_relative_eq!(t.area_scale(norm), parent / local, max_relative = 1e-2);
+
+                // the shading frame stays orthonormal
+                let si = SurfaceInteraction::new(
+                    sphere(theta, phi), norm, norm, Point2f::new(phi, theta),
+                    DuvInfo{
+                        dpdu: Vector3f::new(-phi.sin(), phi.cos(), 0. as Float) * theta.sin(),
+                        dpdv: Vector3f::new(theta.cos() * phi.cos(), theta.cos() * phi.sin(), -theta.sin()),
+                        dndu: Vector3f::zero(),
+                        dndv: Vector3f::zero(),
+                    }
+                ).apply_transform(&t);
+                assert_relative_eq!(si.shading_norm.magnitude(), 1. as Float, epsilon = 1e-5);
+                assert!(si.shading_norm.dot(si.shading_duv.dpdu.normalize()).abs() < 1e-4);
+                assert!(si.shading_norm.dot(si.shading_duv.dpdv.normalize()).abs() < 1e-4);
+                assert_eq!(si.duv.dndu, Vector3f::zero());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod ray_extends {
+    use geometry::prelude::*;
+
+    #[test]
+    fn test_transform_round_trip() {
+        let ray = RawRay::new(
+            Point3f::new(1. as Float, -2., 0.5), Vector3f::new(0.3 as Float, 0.4, -0.2), 7. as Float
+        ).with_min_extend(1.5 as Float);
+        let m = Matrix4f::from_translation(Vector3f::new(3. as Float, 1., -4.))
+            * Matrix4f::from(Matrix3f::from_angle_x(Rad(0.9 as Float)))
+            * Matrix4f::from_nonuniform_scale(2. as Float, 0.5, 3.);
+        let transformed = ray.apply_transform(&m);
+        // both ends map to the ends of the transformed ray
+        for &t in &[ray.min_extend(), ray.max_extend()] {
+            let expected = m.transform_point(ray.evaluate(t));
+            let p = transformed.evaluate(t);
+            for i in 0..3 {
+                assert_relative_eq!(p[i], expected[i], max_relative = 1e-5, epsilon = 1e-5);
+            }
+        }
+        let back = transformed.apply_transform(&m.invert().unwrap());
+        assert_eq!(back.min_extend(), ray.min_extend());
+        assert_eq!(back.max_extend(), ray.max_extend());
+    }
+
+    #[test]
+    fn test_bbox_min_extend() {
+        let bbox = BBox3f::new(Point3f::new(-1. as Float, -1., 2.), Point3f::new(1. as Float, 1., 3.));
+        let ray = RawRay::from_od(Point3f::new(0. as Float, 0., 0.), Vector3f::new(0. as Float, 0., 1.));
+        let (t0, _) = bbox.intersect_ray(&ray).unwrap();
+        assert_eq!(t0, 2. as Float);
+        let (t0, _) = bbox.intersect_ray(&ray.with_min_extend(2.5 as Float)).unwrap();
+        assert_eq!(t0, 2.5 as Float);
+        let beyond = ray.with_min_extend(4. as Float);
+        assert!(bbox.intersect_ray(&beyond).is_none());
+        assert!(bbox.intersect_ray_cached(&BBox3f::construct_ray_cache(&beyond)).is_none());
+        assert!(bbox.intersect_ray_cached(&BBox3f::construct_ray_cache(&ray)).is_some());
+    }
+}
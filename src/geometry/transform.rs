@@ -49,12 +49,50 @@ pub trait TransformExt: Transform3<Float> + Copy {
         si.apply_transform(self)
     }
 
+    /// the matrix transforming normals, namely the inverse transpose
+    #[inline]
+    fn normal_matrix(&self) -> Matrix4f {
+        let m = <Self as Into<Matrix4<_>>>::into(*self);
+        m.invert().expect("Invalid inversion").transpose()
+    }
+
+    /// transform a normal, renormalized
     #[inline]
     fn transform_norm(&self, norm: Vector3f) -> Vector3f
     {
+        self.normal_matrix().transform_vector(norm).normalize()
+    }
+
+    /// transform a derivative of a unit normal, not renormalized, so
+    /// that vanishing derivatives stay zero
+    #[inline]
+    fn transform_norm_derivative(&self, dn: Vector3f) -> Vector3f {
+        self.normal_matrix().transform_vector(dn)
+    }
+
+    /// ratio of the area of a surface element with unit normal `norm`
+    /// after the transform to that before
+    #[inline]
+    fn area_scale(&self, norm: Vector3f) -> Float {
+        let m = <Self as Into<Matrix4<_>>>::into(*self);
+        let det = m.x.truncate().cross(m.y.truncate()).dot(m.z.truncate());
+        det.abs() * self.normal_matrix().transform_vector(norm).magnitude()
+    }
+
+    /// ratio of the solid angle of a small cone about unit direction
+    /// `dir` after the transform to that before
+    #[inline]
+    fn solid_angle_scale(&self, dir: Vector3f) -> Float {
         let m = <Self as Into<Matrix4<_>>>::into(*self);
-        let inverse_transpose = m.invert().expect("Invalid inversion").transpose();
-        inverse_transpose.transform_vector(norm).normalize()
+        let det = m.x.truncate().cross(m.y.truncate()).dot(m.z.truncate());
+        det.abs() / self.transform_vector(dir).magnitude().powi(3)
+    }
+
+    /// distance after the transform between two parallel surfaces
+    /// a unit apart along their unit normal `norm` before
+    #[inline]
+    fn normal_stretch(&self, norm: Vector3f) -> Float {
+        1. as Float / self.normal_matrix().transform_vector(norm).magnitude()
     }
 }
 
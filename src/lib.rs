@@ -87,3 +87,5 @@ pub mod texturing;
 pub mod lighting;
 pub mod renderer;
 pub mod prelude;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
@@ -78,6 +78,7 @@ impl Light for DistantLight {
             pfrom: pfrom,
             pto: pto,
             pdf: pdf,
+            normal: Vector3f::zero(),
         }
     }
 
@@ -99,11 +100,15 @@ impl Light for DistantLight {
     }
 
     #[inline]
-    fn pdf_path(&self, _pos: Point3f, _dir: Vector3f, _normal: Vector3f) -> (Float, Float) {
-        (
-            1. as Float / (self.world_radius * self.world_radius * float::pi()), 
+    fn pdf_path(&self, _pos: Point3f, dir: Vector3f, _normal: Vector3f) -> (Float, Float) {
+        // the delta in direction is taken as 1 along `self.dir`,
+        // as `generate_path` does
+        let pdfdir = if dir.normalize().dot(self.dir) > 1. as Float - 1e-4 as Float {
+            1. as Float
+        } else {
             0. as Float
-        )
+        };
+        (1. as Float / (self.world_radius * self.world_radius * float::pi()), pdfdir)
     }
 
     #[inline]
@@ -10,7 +10,8 @@
 
 use geometry::prelude::*;
 use spectrum::*;
-use component::Composable;
+use component::{Composable, Primitive};
+use sample;
 use renderer::scene::Scene;
 pub use filming::SampleInfo;
 
@@ -53,12 +54,22 @@ pub trait Light: Sync+ Send {
     /// Generate a photon path from the light source based on the sample info
     fn generate_path(&self, samples: SampleInfo) -> PathInfo;
 
-    /// Given position and direction of a photon path, and the light's `normal`
-    /// return its pdfs as `(pdfpos, pdfdir)`
+    /// Given position and normalized direction of a photon path, and the
+    /// light's `normal` there, return its pdfs as `(pdfpos, pdfdir)`,
+    /// the ones `generate_path` reports for the same path.
+    /// `pdfpos` is wrt area on the light, `pdfdir` wrt solid angle
+    /// at `pos`, integrating to 1 over the sphere of directions.
+    /// Lights with delta positions take `pdfpos` as 1, and lights with
+    /// delta directions take `pdfdir` as 1 along them, as their
+    /// `generate_path` does.
     fn pdf_path(&self, pos: Point3f, dir: Vector3f, normal: Vector3f) -> (Float, Float);
 
     /// Given a position and lighting ray `wi` of an interaction,
-    /// return the pdf of it
+    /// return the pdf of it wrt solid angle at `pos`, consistent with
+    /// the `pdf` of `evaluate_sampled`. Area lights should convert
+    /// their area densities with `sample::area_to_solid_angle`.
+    /// Unlike `pdf_path`, this is the density of reaching the light
+    /// from a receiver, not of leaving it.
     #[inline]
     fn pdf(&self, _pos: Point3f, _wi: Vector3f) -> Float {
         0. as Float
@@ -67,6 +78,25 @@ pub trait Light: Sync+ Send {
     /// returns an estimation of total power of this light
     fn power(&self) -> RGBSpectrumf;
 
+    /// test if hitting `primitive` amounts to hitting this light,
+    /// for lights made up of several primitives.
+    ///
+    /// Default implementation returns `false`.
+    #[inline]
+    fn emits_from(&self, _primitive: &Primitive) -> bool {
+        false
+    }
+
+    /// returns an upper bound on the luminance of incoming radiance
+    /// this light could deliver to any point inside `bbox`,
+    /// or `None` if no such bound is known.
+    ///
+    /// Default implementation returns `None`.
+    #[inline]
+    fn max_luminance_in(&self, _bbox: &BBox3f) -> Option<Float> {
+        None
+    }
+
     /// preporcess with scene components, if necessary.
     /// renderers should respect this requirement.
     ///
@@ -98,17 +128,87 @@ impl LightFlag {
     }
 }
 
+/// Which side(s) of its surface an area light emits from,
+/// relative to the surface normal
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum EmissionSide {
+    /// emits along the normal
+    Front,
+    /// emits against the normal
+    Back,
+    /// emits from both sides
+    Both,
+}
+
+impl Default for EmissionSide {
+    #[inline]
+    fn default() -> EmissionSide {
+        EmissionSide::Front
+    }
+}
+
+impl EmissionSide {
+    /// test if light leaving a surface with normal `norm` along `dir` is emitted
+    #[inline]
+    pub fn emits(self, norm: Vector3f, dir: Vector3f) -> bool {
+        let cos = norm.dot(dir);
+        match self {
+            EmissionSide::Front => cos > 0. as Float,
+            EmissionSide::Back => cos < 0. as Float,
+            EmissionSide::Both => cos != 0. as Float,
+        }
+    }
+
+    /// number of emitting sides
+    #[inline]
+    pub fn sides(self) -> Float {
+        match self {
+            EmissionSide::Both => 2. as Float,
+            _ => 1. as Float,
+        }
+    }
+
+    /// cosine-sample an emitting direction off a surface with normal `norm`,
+    /// returns `(dir, pdf)`
+    pub fn sample_cosw(self, norm: Vector3f, u: Point2f) -> (Vector3f, Float) {
+        let (u, back) = match self {
+            EmissionSide::Front => (u, false),
+            EmissionSide::Back => (u, true),
+            EmissionSide::Both => if u.x < 0.5 as Float {
+                (Point2f::new(u.x * 2. as Float, u.y), false)
+            } else {
+                (Point2f::new((u.x - 0.5 as Float) * 2. as Float, u.y), true)
+            },
+        };
+        let (dir, pdf) = sample::sample_cosw_hemisphere_about(if back { -norm } else { norm }, u);
+        (dir, pdf / self.sides())
+    }
+
+    /// pdf of `dir` being sampled by `self.sample_cosw(norm, _)`
+    #[inline]
+    pub fn pdf_cosw(self, norm: Vector3f, dir: Vector3f) -> Float {
+        if self.emits(norm, dir) {
+            sample::pdf_cosw_hemisphere(norm.dot(dir).abs()) / self.sides()
+        } else {
+            0. as Float
+        }
+    }
+}
+
 /// Results of a light's sampling evaluation
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub struct LightSample {
     /// outgoing radiance
     pub radiance: RGBSpectrumf,
-    /// pdf for this sample
+    /// pdf for this sample, wrt solid angle at `pto`
     pub pdf: Float,
     /// outgoing point, parent frame
     pub pfrom: Point3f,
     /// receiving point, parent frame
     pub pto: Point3f,
+    /// geometric normal of the light's surface, or the camera's lens,
+    /// at `pfrom`, parent frame. Zero for delta distributions
+    pub normal: Vector3f,
 }
 
 #[must_use]
@@ -123,13 +223,28 @@ impl LightSample {
     /// in `Composable`, assuming they are in the same world frame
     #[inline]
     pub fn occluded<C: Composable + ?Sized>(&self, components: &C) -> bool {
-        // TODO: check floating point error
-        let epsilon = Point3f::default_epsilon()*2.0;
-        let dir = self.pto - self.pfrom;
+        components.can_intersect(&self.shadow_ray())
+    }
+
+    /// the ray `occluded` tests, for testing many samples at once
+    /// with `Composable::occluded_batch`
+    #[inline]
+    pub fn shadow_ray(&self) -> RawRay {
+        self.shadow_ray_from(self.pto)
+    }
+
+    /// the shadow ray towards the light from `pto` in place of `self.pto`,
+    /// as from `SurfaceInteraction::shadow_origin`
+    #[inline]
+    pub fn shadow_ray_from(&self, pto: Point3f) -> RawRay {
+        // TODO: bound the offsets by the intersections' floating point error,
+        // a fixed fraction of the segment covers intersection routines
+        // computing hits relative to far-away origins
+        let epsilon = float::consts::RAY_EPSILON_SCALE;
+        let dir = pto - self.pfrom;
         let pfrom = self.pfrom + dir*epsilon;
-        let pto = self.pto + (-dir*epsilon);
-        let ray = RawRay::spawn(pfrom, pto);
-        components.can_intersect(&ray)
+        let pto = pto + (-dir*epsilon);
+        RawRay::spawn(pfrom, pto).with_kind(RayKind::Shadow)
     }
 
     #[inline]
@@ -141,6 +256,11 @@ impl LightSample {
             pdf: self.pdf,
             pfrom: t.transform_point(self.pfrom),
             pto: t.transform_point(self.pto),
+            normal: if self.normal == Vector3f::zero() {
+                self.normal
+            } else {
+                t.transform_norm(self.normal)
+            },
         }
     }
 
@@ -182,3 +302,6 @@ impl PathInfo {
 pub mod pointlights;
 pub mod distantlight;
 pub mod prelude;
+
+#[cfg(test)]
+mod tests;
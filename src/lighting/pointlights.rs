@@ -56,6 +56,7 @@ impl Light for PointLight {
             pdf: 1.0 as Float,
             pto: pto,
             pfrom: pfrom,
+            normal: Vector3f::zero(),
         }
     }
 
@@ -68,19 +69,26 @@ impl Light for PointLight {
             ray: ray,
             normal: dir,
             pdfpos: 1. as Float,
-            pdfdir: sample::pdf_uniform_sphere(),
+            pdfdir: sample::sample_uniform_sphere_pdf_wrt_solid_angle(),
             radiance: self.intensity,
         }
     }
 
     #[inline]
     fn pdf_path(&self, _pos: Point3f, _dir: Vector3f, _normal: Vector3f) -> (Float, Float) {
-        (0. as Float, sample::pdf_uniform_sphere())
+        (1. as Float, sample::sample_uniform_sphere_pdf_wrt_solid_angle())
     }
 
     fn power(&self) -> RGBSpectrumf {
         self.intensity * (float::pi() * 4.0 as Float)
     }
+
+    #[inline]
+    fn max_luminance_in(&self, bbox: &BBox3f) -> Option<Float> {
+        let d2 = bbox.distance2(self.posw);
+        if d2 == 0. as Float { return None; }
+        Some(self.intensity.to_xyz().y / d2)
+    }
 }
 
 /// Spot light emit light in a cone of directions
@@ -143,6 +151,16 @@ impl SpotLight {
         self.posw = pos;
     }
 
+    /// move the light by `offset`, keeping its orientation
+    #[inline]
+    pub fn translate(&mut self, offset: Vector3f) {
+        self.posw += offset;
+        // `parent_local` is `rotation * translation(posw)`
+        let w = self.parent_local.transform_vector(self.posw - Point3f::new(0.0 as Float, 0.0 as Float, 0.0 as Float));
+        self.parent_local.w = w.extend(1.0 as Float);
+        self.local_parent = self.parent_local.invert().expect("invalid inversion");
+    }
+
     // Compute the falloff, given a normalized direction in parent frame
     #[inline]
     fn falloff(&self, dir: Vector3f) -> Float {
@@ -189,12 +207,14 @@ impl Light for SpotLight {
             pdf: 1.0 as Float,
             pto: pto,
             pfrom: pfrom,
+            normal: Vector3f::zero(),
         }
     }
 
     #[inline]
     fn generate_path(&self, samples: SampleInfo) -> PathInfo {
-        let dir = sample::sample_uniform_cone(samples.pfilm, self.cost);
+        // the cone is about the local `z`
+        let dir = self.local_parent.transform_vector(sample::sample_uniform_cone(samples.pfilm, self.cost));
         let ray = RawRay::from_od(self.posw, dir);
 
         PathInfo{
@@ -209,13 +229,13 @@ impl Light for SpotLight {
 
     #[inline]
     fn pdf_path(&self, _pos: Point3f, dir: Vector3f, _normal: Vector3f) -> (Float, Float) {
-        let costheta = normal::cos_theta(dir);
+        let costheta = normal::cos_theta(self.parent_local.transform_vector(dir.normalize()));
         let pdfdir = if costheta >= self.cost {
             sample::pdf_uniform_cone(self.cost)
         } else {
             0. as Float
         };
-        (0. as Float, pdfdir)
+        (1. as Float, pdfdir)
     }
 
     #[inline]
@@ -224,6 +244,24 @@ impl Light for SpotLight {
             1.0 as Float - 0.5 as Float * (self.cosf - self.cost)
         )
     }
+
+    fn max_luminance_in(&self, bbox: &BBox3f) -> Option<Float> {
+        let d2 = bbox.distance2(self.posw);
+        if d2 == 0. as Float { return None; }
+        // conservative cone test against the bounding sphere of `bbox`
+        let (center, radius) = bbox.bsphere();
+        let tocenter = center - self.posw;
+        let dist = tocenter.magnitude();
+        if dist > radius {
+            let axis = self.local_parent.transform_vector(Vector3f::new(0. as Float, 0. as Float, 1. as Float)).normalize();
+            let theta = (tocenter.dot(axis) / dist).max(-1. as Float).min(1. as Float).acos();
+            let spread = (radius / dist).asin();
+            if theta - spread > self.cost.acos() {
+                return Some(0. as Float);
+            }
+        }
+        Some(self.intensity.to_xyz().y / d2)
+    }
 }
 
 // TODO: /// Projection light
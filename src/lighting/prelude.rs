@@ -6,6 +6,6 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-pub use super::{Light, LIGHT_INFINITE, LIGHT_AREA, LIGHT_DDIR, LIGHT_DPOS};
+pub use super::{Light, EmissionSide, LIGHT_INFINITE, LIGHT_AREA, LIGHT_DDIR, LIGHT_DPOS};
 pub use super::distantlight::DistantLight;
 pub use super::pointlights::{PointLight, SpotLight};
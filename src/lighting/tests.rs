@@ -0,0 +1,269 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+
+// tests
+
+#[cfg(test)]
+mod bound {
+    use lighting::prelude::*;
+    use geometry::prelude::*;
+    use spectrum::*;
+
+    fn luminance(s: RGBSpectrumf) -> Float {
+        s.to_xyz().y
+    }
+
+    #[test]
+    fn test_pointlight_bound() {
+        let light = PointLight::new(Point3f::new(0. as Float, 0., 3.), RGBSpectrumf::new(2. as Float, 1., 0.5));
+        let bbox = BBox3f::new(Point3f::new(-1. as Float, -1., -1.), Point3f::new(1. as Float, 1., 1.));
+        let bound = light.max_luminance_in(&bbox).unwrap();
+        for &p in &[bbox.pmin, bbox.pmax, Point3f::new(0. as Float, 0., 1.), Point3f::new(0.5 as Float, -0.3, 0.2)] {
+            let ls = light.evaluate_sampled(p, Point2f::new(0.5 as Float, 0.5));
+            assert!(luminance(ls.radiance) <= bound * (1. as Float + 1e-4));
+        }
+        let inside = BBox3f::new(Point3f::new(-1. as Float, -1., -1.), Point3f::new(1. as Float, 1., 4.));
+        assert!(light.max_luminance_in(&inside).is_none());
+    }
+
+    #[test]
+    fn test_spotlight_bound() {
+        let light = SpotLight::new(
+            Point3f::new(0. as Float, 0., 0.), Vector3f::new(0. as Float, 0., 1.),
+            RGBSpectrumf::new(1. as Float, 1., 1.), 0.5 as Float, 0.3 as Float
+        );
+        let front = BBox3f::new(Point3f::new(-0.5 as Float, -0.5, 4.), Point3f::new(0.5 as Float, 0.5, 5.));
+        let ls = light.evaluate_sampled(Point3f::new(0. as Float, 0., 4.), Point2f::new(0.5 as Float, 0.5));
+        assert!(luminance(ls.radiance) <= light.max_luminance_in(&front).unwrap() * (1. as Float + 1e-4));
+        let behind = BBox3f::new(Point3f::new(-0.5 as Float, -0.5, -5.), Point3f::new(0.5 as Float, 0.5, -4.));
+        assert_eq!(light.max_luminance_in(&behind), Some(0. as Float));
+    }
+}
+
+#[cfg(test)]
+mod sample_normal {
+    use lighting::prelude::*;
+    use lighting::LightSample;
+    use geometry::prelude::*;
+    use spectrum::*;
+    use shape::prelude::*;
+    use texturing::prelude::*;
+    use component::shape::ShapedPrimitive;
+    use component::transformed::TransformedComposable;
+    use test_utils;
+    use std::sync::Arc;
+
+    fn transform() -> Matrix4f {
+        Matrix4f::from_translation(Vector3f::new(1. as Float, -2., 3.))
+            * Matrix4f::from_angle_y(Deg(30. as Float))
+            * Matrix4f::from_nonuniform_scale(2. as Float, 1., 0.5)
+    }
+
+    fn transformed<T>(inner: T) -> TransformedComposable<T> {
+        let m = transform();
+        TransformedComposable::new(inner, Arc::new(m), Arc::new(m.invert().unwrap()))
+    }
+
+    // `check` the normals of samples of `light` from a few points around
+    fn for_samples<F: FnMut(&LightSample)>(light: &Light, mut check: F) {
+        let origins = [
+            Point3f::new(5. as Float, 0., 0.), Point3f::new(-3. as Float, 4., 8.), Point3f::new(0. as Float, -6., -2.),
+        ];
+        for &pos in &origins {
+            for i in 0..16 {
+                let u = Point2f::new((i % 4) as Float / 4. as Float + 0.1, (i / 4) as Float / 4. as Float + 0.1);
+                let ls = light.evaluate_sampled(pos, u);
+                assert_relative_eq!(ls.normal.magnitude(), 1. as Float, epsilon = 1e-4);
+                check(&ls);
+            }
+        }
+    }
+
+    #[test]
+    fn test_transformed_quad() {
+        let mesh = test_utils::quad([
+            Point3f::new(-1. as Float, -1., -1.), Point3f::new(1. as Float, -1., 1.),
+            Point3f::new(1. as Float, 1., 1.), Point3f::new(-1. as Float, 1., -1.),
+        ], test_utils::matte(0.5, 0.5, 0.5), Some(RGBSpectrumf::new(1. as Float, 1., 1.)));
+        let light: Arc<Light> = Arc::new(MeshLight::new(mesh));
+        let light = transformed(light);
+        // the plane's normal under the inverse transpose
+        let expected = transform().transform_norm(Vector3f::new(-1. as Float, 0., 1.));
+        for_samples(&light, |ls| {
+            assert_relative_eq!(ls.normal.dot(expected).abs(), 1. as Float, epsilon = 1e-4);
+        });
+    }
+
+    #[test]
+    fn test_transformed_sphere() {
+        let sphere = ShapedPrimitive::new(
+            Sphere::full(1. as Float), test_utils::matte(0.5, 0.5, 0.5),
+            Some(Arc::new(ConstantTexture{value: RGBSpectrumf::new(1. as Float, 1., 1.)}))
+        );
+        let light = transformed(sphere);
+        let inv = transform().invert().unwrap();
+        for_samples(&light, |ls| {
+            // outward at the sampled point of the ellipsoid
+            let local = inv.transform_point(ls.pfrom).to_vec();
+            let expected = transform().transform_norm(local);
+            assert_relative_eq!(ls.normal.dot(expected), 1. as Float, epsilon = 1e-4);
+        });
+    }
+}
+
+#[cfg(test)]
+mod path_pdf {
+    extern crate rand;
+    use self::rand::{Rng, SeedableRng, StdRng};
+    use lighting::prelude::*;
+    use lighting::SampleInfo;
+    use geometry::prelude::*;
+    use spectrum::*;
+    use shape::prelude::*;
+    use texturing::prelude::*;
+    use component::shape::ShapedPrimitive;
+    use component::transformed::TransformedComposable;
+    use sample;
+    use test_utils;
+    use std::sync::Arc;
+
+    fn rng() -> StdRng {
+        StdRng::from_seed(&[976usize][..])
+    }
+
+    // densities of paths generated by `light` are the ones it reports,
+    // and the paths carry its radiance
+    fn check_agreement(light: &Light) {
+        let mut rng = rng();
+        let (mut checked, mut lit) = (0, 0);
+        for _ in 0..1024 {
+            let path = light.generate_path(SampleInfo{
+                pfilm: Point2f::new(rng.gen(), rng.gen()),
+                plens: Point2f::new(rng.gen(), rng.gen()),
+                time: 0. as Float,
+            });
+            if path.pdfpos == 0. as Float || path.pdfdir == 0. as Float { continue; }
+            let dir = path.ray.direction().normalize();
+            let (pdfpos, pdfdir) = light.pdf_path(path.ray.origin(), dir, path.normal);
+            assert_relative_eq!(pdfpos, path.pdfpos, max_relative = 1e-3);
+            assert_relative_eq!(pdfdir, path.pdfdir, max_relative = 1e-3);
+            checked += 1;
+            if !path.radiance.is_black() { lit += 1; }
+        }
+        assert!(checked > 512 && lit > checked / 2, "{} of {} paths lit", lit, checked);
+    }
+
+    // integral of the directional density of `light` over the sphere,
+    // at `pos` with `normal`
+    fn integrate_pdfdir(light: &Light, pos: Point3f, normal: Vector3f) -> Float {
+        let mut rng = rng();
+        let n = 100000;
+        let mut sum = 0. as Float;
+        for _ in 0..n {
+            let dir = sample::sample_uniform_sphere(Point2f::new(rng.gen(), rng.gen()));
+            sum += light.pdf_path(pos, dir, normal).1 / sample::sample_uniform_sphere_pdf_wrt_solid_angle();
+        }
+        sum / n as Float
+    }
+
+    // `check_agreement`, then integrate the directional densities at the
+    // origins of a few paths
+    fn check(light: &Light) {
+        check_agreement(light);
+        let mut rng = rng();
+        for _ in 0..3 {
+            let path = light.generate_path(SampleInfo{
+                pfilm: Point2f::new(rng.gen(), rng.gen()),
+                plens: Point2f::new(rng.gen(), rng.gen()),
+                time: 0. as Float,
+            });
+            let integral = integrate_pdfdir(light, path.ray.origin(), path.normal);
+            assert_relative_eq!(integral, 1. as Float, epsilon = 0.02);
+        }
+    }
+
+    fn emissive_quad(side: EmissionSide) -> Arc<Light> {
+        let mesh = test_utils::quad([
+            Point3f::new(-1. as Float, -1., -1.), Point3f::new(1. as Float, -1., 1.),
+            Point3f::new(1. as Float, 1., 1.), Point3f::new(-1. as Float, 1., -1.),
+        ], test_utils::matte(0.5, 0.5, 0.5), Some(RGBSpectrumf::new(1. as Float, 1., 1.)));
+        let mut mesh = Arc::try_unwrap(mesh).ok().unwrap();
+        mesh.set_emission_side(side);
+        Arc::new(MeshLight::new(Arc::new(mesh)))
+    }
+
+    // a rotation, translation and uniform scaling, preserving solid angles
+    fn transformed<T>(inner: T) -> TransformedComposable<T> {
+        let m = Matrix4f::from_translation(Vector3f::new(1. as Float, -2., 3.))
+            * Matrix4f::from_angle_x(Deg(40. as Float))
+            * Matrix4f::from_scale(2. as Float);
+        TransformedComposable::new(inner, Arc::new(m), Arc::new(m.invert().unwrap()))
+    }
+
+    #[test]
+    fn test_point() {
+        let light: Arc<Light> = Arc::new(PointLight::new(Point3f::new(1. as Float, 2., 3.), RGBSpectrumf::new(1. as Float, 1., 1.)));
+        check(&*light);
+        let light = transformed(light);
+        check(&light);
+        // delta positions aren't scaled along
+        let path = light.generate_path(SampleInfo{
+            pfilm: Point2f::new(0.3 as Float, 0.6), plens: Point2f::new(0.5 as Float, 0.5), time: 0. as Float,
+        });
+        assert_eq!(path.pdfpos, 1. as Float);
+    }
+
+    #[test]
+    fn test_spot() {
+        let light = SpotLight::new(
+            Point3f::new(1. as Float, 2., 3.), Vector3f::new(1. as Float, -1., 0.5),
+            RGBSpectrumf::new(1. as Float, 1., 1.), 0.6 as Float, 0.3 as Float
+        );
+        check(&light);
+    }
+
+    #[test]
+    fn test_distant() {
+        let mut light = DistantLight::new(RGBSpectrumf::new(1. as Float, 1., 1.), Vector3f::new(1. as Float, -2., 0.5));
+        let sphere = ShapedPrimitive::new(Sphere::full(3. as Float), test_utils::matte(0.5, 0.5, 0.5), None);
+        light.set_world_bounds(&sphere);
+        // a delta in direction, integrating to zero against solid angle
+        check_agreement(&light);
+    }
+
+    #[test]
+    fn test_mesh() {
+        for &side in &[EmissionSide::Front, EmissionSide::Back, EmissionSide::Both] {
+            let light = emissive_quad(side);
+            check(&*light);
+            check(&transformed(light));
+        }
+    }
+
+    #[test]
+    fn test_triangle() {
+        let mesh = test_utils::quad([
+            Point3f::new(-1. as Float, -1., -1.), Point3f::new(1. as Float, -1., 1.),
+            Point3f::new(1. as Float, 1., 1.), Point3f::new(-1. as Float, 1., -1.),
+        ], test_utils::matte(0.5, 0.5, 0.5), Some(RGBSpectrumf::new(1. as Float, 1., 1.)));
+        for triangle in TriangleMesh::instances(&mesh) {
+            check(&triangle);
+        }
+    }
+
+    #[test]
+    fn test_sphere() {
+        let sphere = ShapedPrimitive::new(
+            Sphere::full(1. as Float), test_utils::matte(0.5, 0.5, 0.5),
+            Some(Arc::new(ConstantTexture{value: RGBSpectrumf::new(1. as Float, 1., 1.)}))
+        );
+        check(&sphere);
+        check(&transformed(sphere));
+    }
+}
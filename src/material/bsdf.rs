@@ -14,19 +14,20 @@ use bxdf::*;
 use geometry::prelude::*;
 use spectrum::{RGBSpectrumf, Spectrum};
 use std::cmp;
-use aren_alloc::Pointer;
+use std::mem;
+use aren_alloc::{Allocator, Pointer};
+use sample;
+
+/// maximum number of samples `Bsdf::rho` takes
+pub const MAX_RHO_SAMPLES: usize = 64;
 
 /// A bsdf
 pub struct Bsdf<'a> {
     pub eta: Float,
-    /// shading normal
-    ns: Vector3f,
+    /// shading frame
+    frame: Frame,
     /// geometry normal
     ng: Vector3f,
-    /// shading tangent
-    ts: Vector3f,
-    /// shading bitangent
-    bs: Vector3f,
     sink: BsdfSink<'a>,
 }
 
@@ -34,13 +35,33 @@ impl<'a> Bsdf<'a> {
     /// construction
     #[inline]
     pub fn new(si: &SurfaceInteraction, eta: Float) -> Bsdf<'a> {
-        let ts = si.shading_duv.dpdu.normalize();
-        let ns = si.shading_norm;
-        let bs = ns.cross(ts).normalize();
-        let ng = si.basic.norm;
+        let frame = Frame::new(si.shading_norm, si.shading_duv.dpdu);
+        // surfaces shadowed as the smooth surface their shading normals
+        // describe tell reflection from transmission by it as well
+        let ng = if si.shadow_pos.is_some() { si.shading_norm } else { si.basic.norm };
         Bsdf{
-            eta: eta, ns: ns, ng: ng, ts: ts, bs: bs, sink: Default::default(),
+            eta: eta, frame: frame, ng: ng, sink: Default::default(),
+        }
+    }
+
+    /// construction for opaque materials, whose surfaces have no inside.
+    /// On back face hits both normals are flipped to face `wo`, so that
+    /// its reflection lobes are evaluated in the upper hemisphere.
+    /// Transmissive materials should stick with `new`.
+    #[inline]
+    pub fn new_opaque(si: &SurfaceInteraction, eta: Float) -> Bsdf<'a> {
+        let mut ret = Bsdf::new(si, eta);
+        if si.is_backface() {
+            ret.frame = Frame::new(-si.shading_norm, si.shading_duv.dpdu);
+            ret.ng = -ret.ng;
         }
+        ret
+    }
+
+    /// the shading frame
+    #[inline]
+    pub fn frame(&self) -> &Frame {
+        &self.frame
     }
 
     /// adding an bxdf
@@ -49,6 +70,22 @@ impl<'a> Bsdf<'a> {
         self.sink.add(bxdf);
     }
 
+    /// make every lobe no sharper than a microfacet lobe of roughness
+    /// `min_alpha`, replacing perfect specular lobes with narrow glossy ones.
+    /// Used to regularize paths following a non-specular bounce.
+    pub fn regularize(&mut self, min_alpha: Float, alloc: &'a Allocator) {
+        let mut old = mem::replace(&mut self.sink, Default::default());
+        let n = old.n;
+        for slot in old.bxdfs[..n].iter_mut() {
+            if let Some(mut bxdf) = slot.take() {
+                let sink = &mut self.sink;
+                if bxdf.regularize(min_alpha, alloc, &mut |lobe| sink.add(lobe)) {
+                    sink.add(bxdf);
+                }
+            }
+        }
+    }
+
     /// returns how many bxdfs have `kind`
     #[inline]
     pub fn have_n(&self, kind: BxdfType) -> usize {
@@ -65,17 +102,13 @@ impl<'a> Bsdf<'a> {
     /// defined by the tangent space
     #[inline]
     pub fn parent_to_local(&self, v: Vector3f) -> Vector3f {
-        Vector3f::new(v.dot(self.ts), v.dot(self.bs), v.dot(self.ns))
+        self.frame.to_local(v)
     }
 
     /// transforms an vector from local tangent frame into parent frame
     #[inline]
     pub fn local_to_parent(&self, v: Vector3f) -> Vector3f {
-        Vector3f::new(
-            v.dot(Vector3f::new(self.ts.x, self.bs.x, self.ns.x)),
-            v.dot(Vector3f::new(self.ts.y, self.bs.y, self.ns.y)),
-            v.dot(Vector3f::new(self.ts.z, self.bs.z, self.ns.z))
-        )
+        self.frame.to_world(v)
     }
 
     /// evalute this bsdf. vectors given in parent frame
@@ -97,49 +130,51 @@ impl<'a> Bsdf<'a> {
         (ret, rettype)
     }
 
-    pub fn evaluate_sampled(&self, wow: Vector3f, u: Point2f, types: BxdfType) -> (RGBSpectrumf, Vector3f, Float, BxdfType) {
+    /// sample an incoming direction for `wow` from lobes of `types`,
+    /// vectors given in parent frame
+    pub fn evaluate_sampled(&self, wow: Vector3f, u: Point2f, types: BxdfType) -> BsdfSample {
         let match_count = self.have_n(types);
-        let mut ret = (
-            RGBSpectrumf::black(),
-            Vector3f::new(0.0 as Float, 1.0 as Float, 0.0 as Float),
-            0.0 as Float,
-            BxdfType::empty(),
-        );
+        let mut ret = BsdfSample::black();
         if match_count == 0 { return ret; }
         
         let wo = self.parent_to_local(wow).normalize();
         let idx = cmp::min((u.x * match_count as Float).floor() as usize, match_count-1);
         let mut i = 0;
         let mut is_specular = false;
+        let u = remap_lobe_sample(u, match_count, idx);
         for bxdf in self.sink.iter() {
+            if !bxdf.is(types) { continue; }
             if i == idx {
                 is_specular = bxdf.is(BXDF_SPECULAR);
                 // sample the target now
-                let (f, wi, pdf, t) = bxdf.evaluate_sampled(wo, u);
-                if pdf == 0.0 as Float { return ret; }
-                ret = (f, wi, pdf, t & types);
+                let sample = bxdf.evaluate_sampled(wo, u);
+                if sample.pdf == 0.0 as Float { return ret; }
+                ret = sample;
+                ret.kind = ret.kind & types;
             }
-            if bxdf.is(types) { i += 1; }
+            i += 1;
         }
-        let wi = ret.1;
-        ret.1 = self.local_to_parent(wi);
-        if ret.1.x.is_nan() || ret.1.y.is_nan() || ret.1.z.is_nan() {
-            warn!("Invalid wiw {:?}, wi {:?}, wow {:?}, wo {:?} bxdft {:?}", ret.1, wi, wow, wo, ret.3);
+        let wi = ret.wi;
+        ret.wi = self.local_to_parent(wi);
+        if ret.wi.x.is_nan() || ret.wi.y.is_nan() || ret.wi.z.is_nan() {
+            warn!("Invalid wiw {:?}, wi {:?}, wow {:?}, wo {:?} bxdft {:?}", ret.wi, wi, wow, wo, ret.kind);
         }
+        debug_assert!(ret.pdf.is_finite() && ret.pdf >= 0. as Float, "invalid pdf {} sampled", ret.pdf);
         if match_count == 1 || is_specular { return ret; }
-        ret.0 = RGBSpectrumf::black();
-        let is_reflection = wow.dot(self.ng) * ret.1.dot(self.ng) > 0.0 as Float;
+        ret.f = RGBSpectrumf::black();
+        let is_reflection = wow.dot(self.ng) * ret.wi.dot(self.ng) > 0.0 as Float;
         let mut pdfsum = 0.0 as Float;
         for bxdf in self.sink.iter() {
-            if bxdf.is(ret.3) && (
+            if bxdf.is(ret.kind) && (
             (is_reflection && bxdf.is(BXDF_REFLECTION))
              || (!is_reflection && bxdf.is(BXDF_TRANSMISSION))
             ) {
-                ret.0 += bxdf.evaluate(wo, wi);
+                ret.f += bxdf.evaluate(wo, wi);
                 pdfsum += bxdf.pdf(wo, wi).max(0. as Float);
             }
         }
-        ret.2 = pdfsum / match_count as Float;
+        ret.pdf = pdfsum / match_count as Float;
+        debug_assert!(ret.pdf.is_finite() && ret.pdf >= 0. as Float, "invalid pdf {} sampled", ret.pdf);
         ret
     }
 
@@ -162,31 +197,31 @@ impl<'a> Bsdf<'a> {
         (ret, rettype)
     }
 
-    pub fn evaluate_importance_sampled(&self, wow: Vector3f, u: Point2f, types: BxdfType)-> (RGBSpectrumf, Vector3f, Float, BxdfType) {
+    /// sample an incoming direction for `wow` from lobes of `types`,
+    /// with importance being traced, vectors given in parent frame
+    pub fn evaluate_importance_sampled(&self, wow: Vector3f, u: Point2f, types: BxdfType) -> BsdfSample {
         let match_count = self.have_n(types);
-        let mut ret = (
-            RGBSpectrumf::black(),
-            Vector3f::new(0.0 as Float, 1.0 as Float, 0.0 as Float),
-            0.0 as Float,
-            BxdfType::empty(),
-        );
+        let mut ret = BsdfSample::black();
         if match_count == 0 { return ret; }
         
         let wo = self.parent_to_local(wow);
         let idx = cmp::min((u.x * match_count as Float).floor() as usize, match_count-1);
         let mut i = 0;
+        let u = remap_lobe_sample(u, match_count, idx);
         for bxdf in self.sink.iter() {
+            if !bxdf.is(types) { continue; }
             if i == idx {
                 // sample the target now
-                let (f, wi, pdf, t) = bxdf.evaluate_importance_sampled(wo, u);
-                if pdf == 0.0 as Float { return ret; }
-                ret = (f, wi, pdf, t & types);
+                let sample = bxdf.evaluate_importance_sampled(wo, u);
+                if sample.pdf == 0.0 as Float { return ret; }
+                ret = sample;
+                ret.kind = ret.kind & types;
             }
-            if bxdf.is(types) { i += 1; }
+            i += 1;
         }
-        let wi = ret.1;
-        ret.1 = self.local_to_parent(wi);
-        
+        let wi = ret.wi;
+        ret.wi = self.local_to_parent(wi);
+        debug_assert!(ret.pdf.is_finite() && ret.pdf >= 0. as Float, "invalid pdf {} sampled", ret.pdf);
         if match_count == 1 { return ret; }
 
         let mut pdfsum = 0.0 as Float;
@@ -198,7 +233,8 @@ impl<'a> Bsdf<'a> {
         if match_count > 0 {
             pdfsum /= match_count as Float;
         }
-        ret.2 = pdfsum;
+        ret.pdf = pdfsum;
+        debug_assert!(ret.pdf.is_finite() && ret.pdf >= 0. as Float, "invalid pdf {} sampled", ret.pdf);
         ret
     }
 
@@ -214,11 +250,13 @@ impl<'a> Bsdf<'a> {
                 pdfsum += bxdf.pdf(wo, wi).max(0. as Float);
             }
         }
-        if match_count == 0 {
+        let pdf = if match_count == 0 {
             pdfsum
         } else {
             pdfsum / match_count as Float
-        }
+        };
+        debug_assert!(pdf.is_finite() && pdf >= 0. as Float, "invalid pdf {}", pdf);
+        pdf
     }
 
     pub fn rho_hd(&self, wow: Vector3f, samples: &[Point2f]) -> RGBSpectrumf {
@@ -230,6 +268,19 @@ impl<'a> Bsdf<'a> {
         ret
     }
 
+    /// albedo towards `wow`, summing those of the lobes as estimated by
+    /// `rho_hd` over the first `n_samples` Hammersley points, at most
+    /// `MAX_RHO_SAMPLES`. Deterministic, and exact for lambertian and
+    /// perfect specular lobes whatever the number of samples.
+    pub fn rho(&self, wow: Vector3f, n_samples: usize) -> RGBSpectrumf {
+        let n = n_samples.max(1).min(MAX_RHO_SAMPLES);
+        let mut samples = [Point2f::new(0. as Float, 0. as Float); MAX_RHO_SAMPLES];
+        for (i, sample) in samples[..n].iter_mut().enumerate() {
+            *sample = sample::hammersley(i, n);
+        }
+        self.rho_hd(wow, &samples[..n])
+    }
+
     pub fn rho_hh(&self, samples0: &[Point2f], samples1: &[Point2f]) -> RGBSpectrumf {
         let mut ret = RGBSpectrumf::black();
         for bxdf in self.sink.iter() {
@@ -295,3 +346,10 @@ impl<'a, 'b: 'a> Iterator for BsdfSinkIter<'a, 'b> {
         }
     }
 }
+
+// remap `u.x` used for choosing lobe `idx` out of `n` back to $[0, 1)$
+#[inline]
+fn remap_lobe_sample(u: Point2f, n: usize, idx: usize) -> Point2f {
+    let x = u.x * n as Float - idx as Float;
+    Point2f::new(x.max(0. as Float).min(float::one_minus_epsilon()), u.y)
+}
@@ -14,7 +14,12 @@ use super::*;
 use bxdf::prelude::*;
 use bxdf::microfacet::roughness_to_alpha;
 
-/// A glass material
+/// A glass material.
+///
+/// Light travelling through its interior is attenuated following the
+/// Beer-Lambert law by `sigma_a`. Nested dielectrics are not accounted for:
+/// a path entering another absorbing object while inside this one
+/// would be attenuated by the inner object alone.
 #[derive(Clone)]
 pub struct GlassMaterial {
     pub diffuse: Arc<Texture<Texel=RGBSpectrumf>>,
@@ -22,6 +27,8 @@ pub struct GlassMaterial {
     pub roughness: Arc<Texture<Texel=Float>>,
     pub eta: Float,
     pub bump: Option<Arc<Texture<Texel=Float>>>,
+    /// absorption coefficient of the interior, per unit length
+    pub sigma_a: RGBSpectrumf,
 }
 
 impl GlassMaterial {
@@ -33,7 +40,8 @@ impl GlassMaterial {
         bump: Option<Arc<Texture<Texel=Float>>>
     ) -> GlassMaterial {
         GlassMaterial{
-            diffuse, specular, roughness, eta, bump
+            diffuse, specular, roughness, eta, bump,
+            sigma_a: RGBSpectrumf::black(),
         }
     }
 }
@@ -78,4 +86,13 @@ impl Material for GlassMaterial {
         }
         ret
     }
+
+    #[inline]
+    fn absorption(&self) -> Option<RGBSpectrumf> {
+        if self.sigma_a.is_black() {
+            None
+        } else {
+            Some(self.sigma_a)
+        }
+    }
 }
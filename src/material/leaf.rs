@@ -0,0 +1,81 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A leaf material, for thin double-sided foliage
+
+use std::sync::Arc;
+use spectrum::RGBSpectrumf;
+use super::*;
+use bxdf::prelude::*;
+
+/// fraction of light passing through leaves by default
+pub const DEFAULT_LEAF_TRANSMISSION: Float = 0.3 as Float;
+
+/// A leaf material, for foliage modeled as single-sided cards.
+///
+/// Each side reflects diffusely with its own color, while a fraction
+/// `transmission` of the light passes through to the other side,
+/// leaving with a cosine lobe, tinted by the average of both colors.
+#[derive(Clone)]
+pub struct LeafMaterial {
+    /// color seen on the side the shading normal points to
+    pub front: Arc<Texture<Texel=RGBSpectrumf>>,
+    /// color seen on the other side
+    pub back: Arc<Texture<Texel=RGBSpectrumf>>,
+    /// fraction of light passing through, in `[0, 1]`
+    pub transmission: Float,
+    pub bump: Option<Arc<Texture<Texel=Float>>>,
+}
+
+impl LeafMaterial {
+    /// construction
+    #[inline]
+    pub fn new(
+        front: Arc<Texture<Texel=RGBSpectrumf>>,
+        back: Arc<Texture<Texel=RGBSpectrumf>>,
+        transmission: Float,
+        bump: Option<Arc<Texture<Texel=Float>>>
+    ) -> LeafMaterial {
+        LeafMaterial{
+            front: front, back: back,
+            transmission: transmission.max(0. as Float).min(1. as Float),
+            bump: bump,
+        }
+    }
+}
+
+impl Material for LeafMaterial {
+    fn compute_scattering<'a>(
+        &self,
+        si: &mut SurfaceInteraction,
+        dxy: &DxyInfo,
+        alloc: &'a Allocator
+    ) -> bsdf::Bsdf<'a> {
+        if let Some(ref bump) = self.bump {
+            add_bumping(si, dxy, &**bump);
+        }
+        let front = self.front.evaluate(si, dxy);
+        let back = self.back.evaluate(si, dxy);
+        let reflected = 1. as Float - self.transmission;
+        let mut ret = bsdf::Bsdf::new(si, 1.0 as Float);
+        if !(front.is_black() && back.is_black()) {
+            if reflected > 0. as Float {
+                ret.add(alloc.alloc(TwoSidedBxdf::with_back(
+                    LambertianRBxdf::new(front * reflected),
+                    LambertianRBxdf::new(back * reflected)
+                )));
+            }
+            if self.transmission > 0. as Float {
+                ret.add(alloc.alloc(LambertianTBxdf::new(
+                    (front + back) * (0.5 as Float * self.transmission)
+                )));
+            }
+        }
+        ret
+    }
+}
@@ -52,7 +52,7 @@ impl Material for MatteMaterial {
             0.0 as Float,
             90.0 as Float
         );
-        let mut ret = bsdf::Bsdf::new(si, 1.0 as Float);
+        let mut ret = bsdf::Bsdf::new_opaque(si, 1.0 as Float);
         if !r.is_black() {
             if sig == 0.0 as Float {
                 ret.add(alloc.alloc(LambertianRBxdf::new(r)));
@@ -0,0 +1,53 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Mirror material
+use std::sync::Arc;
+use spectrum::prelude::*;
+use super::*;
+use bxdf::specular::SpecularRBxdf;
+use bxdf::fresnel::Noop;
+
+/// A perfectly specular mirror
+#[derive(Clone)]
+pub struct MirrorMaterial {
+    pub kr: Arc<Texture<Texel=RGBSpectrumf>>,
+    pub bump: Option<Arc<Texture<Texel=Float>>>,
+}
+
+impl MirrorMaterial {
+    /// construction
+    #[inline]
+    pub fn new(
+        kr: Arc<Texture<Texel=RGBSpectrumf>>,
+        bump: Option<Arc<Texture<Texel=Float>>>
+    ) -> MirrorMaterial {
+        MirrorMaterial{
+            kr: kr, bump: bump,
+        }
+    }
+}
+
+impl Material for MirrorMaterial {
+    fn compute_scattering<'a>(
+        &self,
+        si: &mut SurfaceInteraction,
+        dxy: &DxyInfo,
+        alloc: &'a Allocator
+    ) -> bsdf::Bsdf<'a> {
+        if let Some(ref bump) = self.bump {
+            add_bumping(si, dxy, &**bump);
+        }
+        let r = self.kr.evaluate(si, dxy);
+        let mut ret = bsdf::Bsdf::new_opaque(si, 1.0 as Float);
+        if !r.is_black() {
+            ret.add(alloc.alloc(SpecularRBxdf::new(r, Noop)));
+        }
+        ret
+    }
+}
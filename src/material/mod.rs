@@ -12,6 +12,7 @@ use geometry::prelude::*;
 use texturing::*;
 use aren_alloc::Allocator;
 use std::sync::Arc;
+use spectrum::RGBSpectrumf;
 
 /// The material interface
 pub trait Material: Sync + Send {
@@ -22,6 +23,24 @@ pub trait Material: Sync + Send {
         dxy: &DxyInfo,
         alloc: &'a Allocator
     ) -> bsdf::Bsdf<'a>;
+
+    /// absorption coefficient of the material's interior, per unit
+    /// length, for materials enclosing an absorbing volume.
+    ///
+    /// Default implementation returns `None`.
+    #[inline]
+    fn absorption(&self) -> Option<RGBSpectrumf> {
+        None
+    }
+
+    /// emission of surfaces the material is applied to, used by
+    /// loaders when no emission is given otherwise.
+    ///
+    /// Default implementation returns `None`.
+    #[inline]
+    fn emission(&self) -> Option<Arc<Texture<Texel=RGBSpectrumf>>> {
+        None
+    }
 }
 
 impl<T: Material + ?Sized> Material for Arc<T> {
@@ -36,9 +55,20 @@ impl<T: Material + ?Sized> Material for Arc<T> {
             &*self, si, dxy, alloc
         )
     }
+
+    #[inline]
+    fn absorption(&self) -> Option<RGBSpectrumf> {
+        <T as Material>::absorption(&*self)
+    }
+
+    #[inline]
+    fn emission(&self) -> Option<Arc<Texture<Texel=RGBSpectrumf>>> {
+        <T as Material>::emission(&*self)
+    }
 }
 
-// utility to bump a map
+// utility to bump a map. Displacements are in the units of the frame
+// the surface is modeled in, scaled by `si.bump_scale` into its current one
 fn add_bumping<T: Texture<Texel=Float> + ?Sized>(
     si: &mut SurfaceInteraction, dxy: &DxyInfo, bump: &T
 ) {
@@ -53,7 +83,7 @@ fn add_bumping<T: Texture<Texel=Float> + ?Sized>(
         du
     };
 
-    let displacement_u = bump.evaluate(&sie, dxy);
+    let displacement_u = bump.evaluate(&sie, dxy) * si.bump_scale;
 
     let dv = {
         // shifting in v
@@ -65,15 +95,18 @@ fn add_bumping<T: Texture<Texel=Float> + ?Sized>(
         dv
     };
 
-    let displacement_v = bump.evaluate(&sie, dxy);
+    let displacement_v = bump.evaluate(&sie, dxy) * si.bump_scale;
+
+    let displacement = bump.evaluate(si, dxy) * si.bump_scale;
 
-    let displacement = bump.evaluate(si, dxy);
+    // bumps finer than the footprint would only alias, fade them out
+    let flattening = bump_flattening(bump.footprint_level(si, dxy));
 
     let dpdu = si.shading_duv.dpdu + // original
-        (displacement_u - displacement) / du * si.shading_norm + // ddu
+        (displacement_u - displacement) / du * flattening * si.shading_norm + // ddu
         displacement * si.shading_duv.dndu; // d
     let dpdv = si.shading_duv.dpdv + // original
-        (displacement_v - displacement) / dv * si.shading_norm + // ddu
+        (displacement_v - displacement) / dv * flattening * si.shading_norm + // ddu
         displacement * si.shading_duv.dndv; // d
 
     let duvinfo = DuvInfo{
@@ -85,9 +118,60 @@ fn add_bumping<T: Texture<Texel=Float> + ?Sized>(
     si.set_shading(duvinfo, false);
 }
 
+// weight of the bump slopes given the footprint `level` of the bump
+// texture, fading from `1` at footprints two texels wide to `0` at
+// eight texels wide
+#[inline]
+fn bump_flattening(level: Float) -> Float {
+    float::clamp((3. as Float - level) * 0.5 as Float, 0. as Float, 1. as Float)
+}
+
+// utility to perturb the shading normal by a tangent space normal map
+fn add_normal_mapping<T: Texture<Texel=RGBSpectrumf> + ?Sized>(
+    si: &mut SurfaceInteraction, dxy: &DxyInfo, normal_map: &T
+) {
+    let texel = normal_map.evaluate(si, dxy);
+    let local = Vector3f::new(
+        texel.r() * 2. as Float - 1. as Float,
+        texel.g() * 2. as Float - 1. as Float,
+        texel.b() * 2. as Float - 1. as Float
+    );
+    if !(local.magnitude2() > 0. as Float) { return; }
+    let frame = Frame::new(si.shading_norm, si.shading_duv.dpdu);
+    let n = frame.to_world(local).normalize();
+    // keep the tangents' lengths, such that `dpdu` cross `dpdv` is along `n`
+    let dpdu = si.shading_duv.dpdu - n * n.dot(si.shading_duv.dpdu);
+    if !(dpdu.magnitude2() > 0. as Float) { return; }
+    let dpdv = n.cross(dpdu).normalize() * si.shading_duv.dpdv.magnitude();
+    let duvinfo = DuvInfo{
+        dpdu: dpdu,
+        dpdv: dpdv,
+        dndu: si.shading_duv.dndu,
+        dndv: si.shading_duv.dndv,
+    };
+    si.set_shading(duvinfo, false);
+}
+
+// utility to rotate the shading tangents by `angle` about the shading normal
+fn rotate_tangents(si: &mut SurfaceInteraction, angle: Float) {
+    let n = si.shading_norm;
+    let (sin, cos) = angle.sin_cos();
+    // rodrigues' rotation, `dpdu` and `dpdv` keep their lengths
+    let rotate = |v: Vector3f| v * cos + n.cross(v) * sin + n * (n.dot(v) * (1. as Float - cos));
+    si.shading_duv.dpdu = rotate(si.shading_duv.dpdu);
+    si.shading_duv.dpdv = rotate(si.shading_duv.dpdv);
+}
+
 pub mod bsdf;
 pub mod matte;
 pub mod plastic;
 pub mod glass;
+pub mod thin_glass;
 pub mod translucent;
+pub mod leaf;
+pub mod mirror;
+pub mod pbr;
 pub mod prelude;
+
+#[cfg(test)]
+mod tests;
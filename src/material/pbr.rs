@@ -0,0 +1,106 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A metallic-roughness material, as found in PBR asset packs
+
+use std::sync::Arc;
+use spectrum::{RGBSpectrumf, Spectrum};
+use super::*;
+use bxdf::prelude::*;
+use texturing::textures::ConstantTexture;
+
+/// smallest microfacet alpha the remapped roughness is clamped to
+const MIN_ALPHA: Float = 1e-3;
+
+/// A material of the metallic workflow, blending a dielectric with
+/// a specular coating over a diffuse base by `1 - metallic`, and a
+/// conductor tinted by `base_color` by `metallic`
+#[derive(Clone)]
+pub struct PbrMaterial {
+    /// diffuse color of dielectrics, and reflectance at normal
+    /// incidence of conductors
+    pub base_color: Arc<Texture<Texel=RGBSpectrumf>>,
+    /// in `[0, 1]`
+    pub metallic: Arc<Texture<Texel=Float>>,
+    /// perceived roughness in `[0, 1]`, squared into the alpha
+    /// of a Trowbridge distribution
+    pub roughness: Arc<Texture<Texel=Float>>,
+    /// tangent space normal map, with `[0, 1]` mapped to `[-1, 1]`
+    pub normal: Option<Arc<Texture<Texel=RGBSpectrumf>>>,
+    /// emission of surfaces the material is applied to
+    pub emissive: Option<Arc<Texture<Texel=RGBSpectrumf>>>,
+    /// index of refraction of the dielectric, defaults to 1.5,
+    /// for a reflectance of 0.04 at normal incidence
+    pub eta: Float,
+}
+
+impl PbrMaterial {
+    pub fn new(
+        base_color: Arc<Texture<Texel=RGBSpectrumf>>,
+        metallic: Arc<Texture<Texel=Float>>,
+        roughness: Arc<Texture<Texel=Float>>
+    ) -> PbrMaterial {
+        PbrMaterial{
+            base_color, metallic, roughness,
+            normal: None,
+            emissive: None,
+            eta: 1.5 as Float,
+        }
+    }
+}
+
+impl Default for PbrMaterial {
+    /// a white, fully metallic and fully rough material, as glTF defaults to
+    fn default() -> PbrMaterial {
+        PbrMaterial::new(
+            Arc::new(ConstantTexture{value: RGBSpectrumf::grey_scale(1. as Float)}),
+            Arc::new(ConstantTexture{value: 1. as Float}),
+            Arc::new(ConstantTexture{value: 1. as Float})
+        )
+    }
+}
+
+impl Material for PbrMaterial {
+    fn compute_scattering<'a>(
+        &self,
+        si: &mut SurfaceInteraction,
+        dxy: &DxyInfo,
+        alloc: &'a Allocator
+    ) -> bsdf::Bsdf<'a> {
+        if let Some(ref normal) = self.normal {
+            add_normal_mapping(si, dxy, &**normal);
+        }
+        let base_color = self.base_color.evaluate(si, dxy);
+        let metallic = float::clamp(self.metallic.evaluate(si, dxy), 0. as Float, 1. as Float);
+        let roughness = float::clamp(self.roughness.evaluate(si, dxy), 0. as Float, 1. as Float);
+        let alpha = (roughness * roughness).max(MIN_ALPHA);
+        let distribution = Trowbridge{ax: alpha, ay: alpha};
+        let mut ret = bsdf::Bsdf::new_opaque(si, 1.0 as Float);
+        let dielectric = 1. as Float - metallic;
+        if dielectric > 0. as Float {
+            let coating = Dielectric::new(1. as Float, self.eta);
+            ret.add(alloc.alloc(TorranceSparrowRBxdf::new(
+                RGBSpectrumf::grey_scale(dielectric), distribution, coating
+            )));
+            if !base_color.is_black() {
+                ret.add(alloc.alloc(CoatedLambertianRBxdf::new(base_color * dielectric, coating)));
+            }
+        }
+        if metallic > 0. as Float && !base_color.is_black() {
+            ret.add(alloc.alloc(TorranceSparrowRBxdf::new(
+                RGBSpectrumf::grey_scale(metallic), distribution, Schlick{r0: base_color}
+            )));
+        }
+        ret
+    }
+
+    #[inline]
+    fn emission(&self) -> Option<Arc<Texture<Texel=RGBSpectrumf>>> {
+        self.emissive.clone()
+    }
+}
@@ -21,6 +21,14 @@ pub struct PlasticMaterial {
     pub specular: Arc<Texture<Texel=RGBSpectrumf>>,
     pub roughness: Arc<Texture<Texel=Float>>,
     pub bump: Option<Arc<Texture<Texel=Float>>>,
+    /// index of refraction of the coating, defaults to 1.5
+    pub eta: Float,
+    /// roughness along the rotated `dpdv` if anisotropic,
+    /// with `roughness` along the rotated `dpdu`
+    pub roughness_v: Option<Arc<Texture<Texel=Float>>>,
+    /// rotation of the tangent frame about the shading normal,
+    /// with `[0, 1]` mapped to `[0, pi]`
+    pub anisotropy_rotation: Option<Arc<Texture<Texel=Float>>>,
 }
 
 impl PlasticMaterial {
@@ -31,7 +39,10 @@ impl PlasticMaterial {
         bump: Option<Arc<Texture<Texel=Float>>>
     ) -> PlasticMaterial {
         PlasticMaterial{
-            diffuse, specular, roughness, bump
+            diffuse, specular, roughness, bump,
+            eta: 1.5 as Float,
+            roughness_v: None,
+            anisotropy_rotation: None,
         }
     }
 }
@@ -46,19 +57,33 @@ impl Material for PlasticMaterial {
         if let Some(ref bump) = self.bump {
             add_bumping(si, dxy, &**bump);
         }
+        if let Some(ref rotation) = self.anisotropy_rotation {
+            let angle = rotation.evaluate(si, dxy) * float::pi();
+            rotate_tangents(si, angle);
+        }
         let diffuse = self.diffuse.evaluate(si, dxy);
         let specular = self.specular.evaluate(si, dxy);
         let roughness = self.roughness.evaluate(si, dxy);
         let alpha = roughness_to_alpha(roughness);
-        let mut ret = bsdf::Bsdf::new(si, 1.0 as Float);
-        ret.add(alloc.alloc(
-            AshikhminShirleyBxdf::new(
-                diffuse, specular,
-                Beckmann{
-                    ax: alpha, ay: alpha
-                }
-            )
-        ));
+        let alpha_v = self.roughness_v.as_ref().map_or(alpha, |r| {
+            roughness_to_alpha(r.evaluate(si, dxy))
+        });
+        let mut ret = bsdf::Bsdf::new_opaque(si, 1.0 as Float);
+        let coating = Dielectric::new(1. as Float, self.eta);
+        if !specular.is_black() {
+            ret.add(alloc.alloc(
+                TorranceSparrowRBxdf::new(
+                    specular,
+                    Beckmann{
+                        ax: alpha, ay: alpha_v
+                    },
+                    coating
+                )
+            ));
+        }
+        if !diffuse.is_black() {
+            ret.add(alloc.alloc(CoatedLambertianRBxdf::new(diffuse, coating)));
+        }
         ret
     }
 }
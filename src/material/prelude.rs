@@ -11,4 +11,8 @@ pub use super::bsdf::Bsdf;
 pub use super::matte::MatteMaterial;
 pub use super::plastic::PlasticMaterial;
 pub use super::glass::GlassMaterial;
+pub use super::thin_glass::ThinGlassMaterial;
 pub use super::translucent::TranslucentMaterial;
+pub use super::leaf::LeafMaterial;
+pub use super::mirror::MirrorMaterial;
+pub use super::pbr::PbrMaterial;
@@ -0,0 +1,741 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod test_plastic {
+    use super::super::*;
+    use super::super::prelude::*;
+    use shape::prelude::*;
+    use spectrum::Spectrum;
+    use texturing::textures::ConstantTexture;
+
+    fn plastic(roughness: Float, eta: Float) -> PlasticMaterial {
+        let white = Arc::new(ConstantTexture{value: RGBSpectrumf::grey_scale(1. as Float)});
+        let mut plastic = PlasticMaterial::new(
+            white.clone(), white, Arc::new(ConstantTexture{value: roughness}), None
+        );
+        plastic.eta = eta;
+        plastic
+    }
+
+    // albedo of `material` seen from an angle with cosine `sqrt(1-offset^2)`
+    // to the normal, with the sphere's interaction as the surface
+    pub fn albedo<M: Material>(material: &M, offset: Float) -> Float {
+        let sphere = Sphere::new(1. as Float, -1. as Float, 1. as Float, float::pi() * 2. as Float);
+        let dir = Vector3f::new(1. as Float, 0. as Float, 0. as Float);
+        let ray = RawRay::from_od(Point3f::new(-5. as Float, offset, 0. as Float), dir);
+        let (_, mut si) = sphere.intersect_ray(&ray).unwrap();
+        let alloc = Allocator::new();
+        let bsdf = material.compute_scattering(&mut si, &DxyInfo::default(), &alloc);
+        const N: usize = 64;
+        let mut samples = Vec::with_capacity(N * N);
+        for i in 0..N {
+            for j in 0..N {
+                samples.push(Point2f::new(
+                    (i as Float + 0.5 as Float) / N as Float,
+                    (j as Float + 0.5 as Float) / N as Float
+                ));
+            }
+        }
+        bsdf.rho_hd(-dir, &samples).to_xyz().y
+    }
+
+    #[test]
+    fn test_furnace() {
+        for &roughness in &[0.001, 0.01, 0.05, 0.1, 0.3, 0.6, 1.0] {
+            let plastic = plastic(roughness as Float, 1.5 as Float);
+            for &offset in &[0., 0.5, 0.9, 0.99, 0.999] {
+                let albedo = albedo(&plastic, offset as Float);
+                assert!(
+                    albedo <= 1.01 as Float,
+                    "albedo {} at roughness {} and offset {}", albedo, roughness, offset
+                );
+            }
+        }
+        // little energy is lost by a smooth, white plastic at normal incidence
+        assert!(albedo(&plastic(0.001 as Float, 1.5 as Float), 0. as Float) > 0.95 as Float);
+    }
+
+    #[test]
+    fn test_eta() {
+        // a denser coating reflects more specularly, leaving less to the diffuse
+        let black = Arc::new(ConstantTexture{value: RGBSpectrumf::black()});
+        let mut low = plastic(0.1 as Float, 1.3 as Float);
+        low.diffuse = black.clone();
+        let mut high = plastic(0.1 as Float, 2. as Float);
+        high.diffuse = black;
+        assert!(albedo(&low, 0. as Float) < albedo(&high, 0. as Float));
+    }
+}
+
+#[cfg(test)]
+mod test_anisotropy {
+    use super::super::*;
+    use super::super::prelude::*;
+    use spectrum::Spectrum;
+    use bxdf::prelude::*;
+    use texturing::textures::ConstantTexture;
+
+    // direction of circles around the center of the uv square,
+    // as a rotation of `dpdu` with `[0, 1]` mapped to `[0, pi]`
+    struct Circular;
+
+    impl Texture for Circular {
+        type Texel = Float;
+
+        fn evaluate(&self, si: &SurfaceInteraction, _dxy: &DxyInfo) -> Float {
+            let phi = (si.uv.y - 0.5 as Float).atan2(si.uv.x - 0.5 as Float);
+            ((phi + float::frac_pi_2()) / float::pi()).rem_euclid(1. as Float)
+        }
+
+        fn mean(&self) -> Float {
+            0.5 as Float
+        }
+    }
+
+    // smooth along the brushing, rough across it
+    fn brushed(rotation: Option<Arc<Texture<Texel=Float>>>) -> PlasticMaterial {
+        let mut plastic = PlasticMaterial::new(
+            Arc::new(ConstantTexture{value: RGBSpectrumf::black()}),
+            Arc::new(ConstantTexture{value: RGBSpectrumf::grey_scale(1. as Float)}),
+            Arc::new(ConstantTexture{value: 0.01 as Float}),
+            None
+        );
+        plastic.roughness_v = Some(Arc::new(ConstantTexture{value: 0.5 as Float}));
+        plastic.anisotropy_rotation = rotation;
+        plastic
+    }
+
+    // reflectance towards the normal of light tilted by 20 degrees
+    // towards the radial and the tangential direction, at `phi` on a
+    // disk spanning the uv square in the xy plane
+    fn lobe<M: Material>(material: &M, phi: Float) -> (Float, Float) {
+        let (sin, cos) = phi.sin_cos();
+        let radial = Vector3f::new(cos, sin, 0. as Float);
+        let tangential = Vector3f::new(-sin, cos, 0. as Float);
+        let pos = Point3f::from_vec(radial * 0.5 as Float);
+        let mut si = SurfaceInteraction::new(
+            pos, Vector3f::zero(), Vector3f::new(0. as Float, 0., 1.),
+            Point2f::new(pos.x + 0.5 as Float, pos.y + 0.5 as Float),
+            DuvInfo{
+                dpdu: Vector3f::new(1. as Float, 0., 0.),
+                dpdv: Vector3f::new(0. as Float, 1., 0.),
+                dndu: Vector3f::zero(),
+                dndv: Vector3f::zero(),
+            }
+        );
+        let alloc = Allocator::new();
+        let bsdf = material.compute_scattering(&mut si, &DxyInfo::default(), &alloc);
+        let wo = Vector3f::new(0. as Float, 0., 1.);
+        let (sin, cos) = (20. as Float).to_radians().sin_cos();
+        let f = |dir: Vector3f| bsdf.evaluate(wo, dir * sin + wo * cos, BXDF_ALL).0.to_xyz().y;
+        (f(radial), f(tangential))
+    }
+
+    #[test]
+    fn test_circular_brushing() {
+        let circular = brushed(Some(Arc::new(Circular)));
+        let unrotated = brushed(None);
+        for i in 0..4 {
+            let phi = float::frac_pi_2() * i as Float;
+            // highlights spread across the circles, radially, at every compass point
+            let (radial, tangential) = lobe(&circular, phi);
+            assert!(radial > tangential * 4. as Float, "{}: {} vs {}", i, radial, tangential);
+            // without rotation, they spread along `dpdv` everywhere
+            let (radial, tangential) = lobe(&unrotated, phi);
+            if i % 2 == 0 {
+                assert!(tangential > radial * 4. as Float, "{}: {} vs {}", i, radial, tangential);
+            } else {
+                assert!(radial > tangential * 4. as Float, "{}: {} vs {}", i, radial, tangential);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_brushed_sphere {
+    use super::super::*;
+    use super::super::prelude::*;
+    use prelude::*;
+    use bxdf::prelude::*;
+    use component::ComponentPointer;
+    use lighting::pointlights::PointLight;
+    use sample::strata::StdStrataSampler;
+    use test_utils;
+    use filming::film::Image;
+    use rand::{SeedableRng, StdRng};
+
+    // a conductor with microfacets spread by `ax` along `dpdu`
+    // and `ay` across it
+    struct BrushedMetal {
+        ax: Float,
+        ay: Float,
+    }
+
+    impl Material for BrushedMetal {
+        fn compute_scattering<'a>(
+            &self,
+            si: &mut SurfaceInteraction,
+            _dxy: &DxyInfo,
+            alloc: &'a Allocator
+        ) -> Bsdf<'a> {
+            let mut ret = Bsdf::new_opaque(si, 1. as Float);
+            ret.add(alloc.alloc(TorranceSparrowRBxdf::new(
+                RGBSpectrumf::grey_scale(1. as Float),
+                Trowbridge{ax: self.ax, ay: self.ay},
+                Conductor::new(
+                    RGBSpectrumf::grey_scale(1. as Float),
+                    RGBSpectrumf::grey_scale(1.5 as Float),
+                    RGBSpectrumf::grey_scale(7.6 as Float)
+                )
+            )));
+            ret
+        }
+    }
+
+    const RESOLUTION: usize = 64;
+
+    // a unit sphere with its poles turned up and down, so that `dpdu`
+    // runs horizontally along its latitudes, seen from the front and
+    // lit by a point light by the eye
+    fn render(ax: Float, ay: Float) -> Image {
+        let sphere = ShapedPrimitive::new(
+            Sphere::new(1. as Float, -1. as Float, 1. as Float, float::pi() * 2. as Float),
+            Arc::new(BrushedMetal{ax, ay}), None
+        );
+        let rotation = Matrix4f::from_angle_x(Rad(-float::frac_pi_2()));
+        let sphere: Arc<Composable> = Arc::new(TransformedComposable::new(
+            sphere, Arc::new(rotation), Arc::new(rotation.invert().unwrap())
+        ));
+        let components: Vec<ComponentPointer> = vec![sphere.into()];
+        let eye = Point3f::new(0. as Float, 0., -4.);
+        let lights: Vec<Arc<Light>> = vec![Arc::new(PointLight::new(eye, RGBSpectrumf::grey_scale(16. as Float)))];
+        let scene = Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH)));
+        let sampler = StdStrataSampler::new(2, 2, 8, StdRng::from_seed(&[7usize][..]));
+        let camera = test_utils::camera(RESOLUTION, eye, Point3f::new(0. as Float, 0., 0.), 0.6 as Float);
+        WhittedRenderer::new(sampler, camera, "", 0. as Float).render_image(&scene)
+    }
+
+    // luminance-weighted variances of the pixels along `x` and `y`,
+    // over those brighter than a tenth of the brightest
+    fn spread(image: &Image) -> (Float, Float) {
+        let n = RESOLUTION as u32;
+        let mut peak = 0. as Float;
+        for y in 0..n {
+            for x in 0..n {
+                peak = peak.max(image[(x, y)].to_xyz().y);
+            }
+        }
+        let (mut w, mut mx, mut my, mut mxx, mut myy) = (0. as Float, 0. as Float, 0. as Float, 0. as Float, 0. as Float);
+        for y in 0..n {
+            for x in 0..n {
+                let l = image[(x, y)].to_xyz().y;
+                if l < 0.1 as Float * peak { continue; }
+                let (fx, fy) = (x as Float, y as Float);
+                w += l;
+                mx += l * fx;
+                my += l * fy;
+                mxx += l * fx * fx;
+                myy += l * fy * fy;
+            }
+        }
+        let (mx, my) = (mx / w, my / w);
+        (mxx / w - mx * mx, myy / w - my * my)
+    }
+
+    #[test]
+    fn test_highlight_follows_tangent() {
+        // rough along the latitudes, the highlight stretches sideways
+        let (x, y) = spread(&render(0.4 as Float, 0.05 as Float));
+        assert!(x > 4. as Float * y, "spread {} sideways, {} upwards", x, y);
+        // and upwards when rough across them
+        let (x, y) = spread(&render(0.05 as Float, 0.4 as Float));
+        assert!(y > 4. as Float * x, "spread {} sideways, {} upwards", x, y);
+    }
+}
+
+#[cfg(test)]
+mod test_pbr {
+    use super::super::*;
+    use super::super::prelude::*;
+    use super::test_plastic::albedo;
+    use spectrum::Spectrum;
+    use texturing::textures::ConstantTexture;
+    use prelude::*;
+    use component::ComponentPointer;
+    use test_utils;
+    use filming::film::Image;
+    use rand::{SeedableRng, StdRng};
+    use image;
+    use std::env;
+    use std::path::Path;
+
+    fn pbr(base_color: RGBSpectrumf, metallic: Float, roughness: Float) -> PbrMaterial {
+        PbrMaterial::new(
+            Arc::new(ConstantTexture{value: base_color}),
+            Arc::new(ConstantTexture{value: metallic}),
+            Arc::new(ConstantTexture{value: roughness})
+        )
+    }
+
+    #[test]
+    fn test_furnace() {
+        let white = RGBSpectrumf::grey_scale(1. as Float);
+        for &metallic in &[0., 0.25, 0.5, 0.75, 1.] {
+            for &roughness in &[0., 0.1, 0.3, 0.6, 1.] {
+                let material = pbr(white, metallic as Float, roughness as Float);
+                for &offset in &[0., 0.5, 0.9, 0.99] {
+                    let albedo = albedo(&material, offset as Float);
+                    assert!(
+                        albedo <= 1.01 as Float, "albedo {} at metallic {}, roughness {} and offset {}",
+                        albedo, metallic, roughness, offset
+                    );
+                }
+            }
+        }
+        // a smooth white metal reflects about everything at normal incidence
+        assert!(albedo(&pbr(white, 1. as Float, 0. as Float), 0. as Float) > 0.95 as Float);
+    }
+
+    #[test]
+    fn test_tinted_metal() {
+        // metals reflect their base color, dielectrics a white highlight
+        let red = RGBSpectrumf::new(1. as Float, 0. as Float, 0. as Float);
+        let alloc = Allocator::new();
+        let sphere = Sphere::new(1. as Float, -1. as Float, 1. as Float, float::pi() * 2. as Float);
+        let dir = Vector3f::new(1. as Float, 0. as Float, 0. as Float);
+        let ray = RawRay::from_od(Point3f::new(-5. as Float, 0., 0.), dir);
+        let (_, si) = sphere.intersect_ray(&ray).unwrap();
+        let highlight = |metallic: Float| {
+            let mut si = si.clone();
+            let bsdf = pbr(red, metallic, 0.2 as Float).compute_scattering(&mut si, &DxyInfo::default(), &alloc);
+            bsdf.evaluate(-dir, -dir, BXDF_ALL).0
+        };
+        let metal = highlight(1. as Float);
+        assert!(metal.r() > 0. as Float && metal.g() == 0. as Float && metal.b() == 0. as Float);
+        let dielectric = highlight(0. as Float);
+        assert!(dielectric.g() > 0. as Float && dielectric.r() > dielectric.g());
+    }
+
+    #[test]
+    fn test_normal_map() {
+        let sphere = Sphere::new(1. as Float, -1. as Float, 1. as Float, float::pi() * 2. as Float);
+        let dir = Vector3f::new(1. as Float, 0. as Float, 0. as Float);
+        let ray = RawRay::from_od(Point3f::new(-5. as Float, 0., 0.), dir);
+        let (_, si) = sphere.intersect_ray(&ray).unwrap();
+        let alloc = Allocator::new();
+        let shading_norm = |texel: RGBSpectrumf| {
+            let mut material = PbrMaterial::default();
+            material.normal = Some(Arc::new(ConstantTexture{value: texel}));
+            let mut si = si.clone();
+            material.compute_scattering(&mut si, &DxyInfo::default(), &alloc);
+            (si.shading_norm, si.shading_duv.dpdu.normalize())
+        };
+        // a flat map leaves the normal as is
+        let (n, _) = shading_norm(RGBSpectrumf::new(0.5 as Float, 0.5 as Float, 1. as Float));
+        assert_relative_eq!(n, si.shading_norm, epsilon = 1e-4);
+        // tilted halfway towards `dpdu`
+        let (n, dpdu) = shading_norm(RGBSpectrumf::new(1. as Float, 0.5 as Float, 1. as Float));
+        let tangent = si.shading_duv.dpdu.normalize();
+        assert_relative_eq!(n.dot(tangent), (0.5 as Float).sqrt(), epsilon = 1e-4);
+        assert_relative_eq!(n.dot(si.shading_norm), (0.5 as Float).sqrt(), epsilon = 1e-4);
+        assert_relative_eq!(n.dot(dpdu), 0. as Float, epsilon = 1e-4);
+    }
+
+    // a ladder of spheres, metallic increasing downwards
+    // and roughness rightwards, lit by a quad over the camera
+    fn render_ladder() -> Image {
+        const N: usize = 4;
+        let mut components: Vec<ComponentPointer> = Vec::new();
+        let base_color = RGBSpectrumf::new(0.95 as Float, 0.64 as Float, 0.54 as Float);
+        for i in 0..N {
+            for j in 0..N {
+                let metallic = i as Float / (N - 1) as Float;
+                let roughness = 0.05 as Float + 0.95 as Float * j as Float / (N - 1) as Float;
+                let sphere = ShapedPrimitive::new(
+                    Sphere::new(0.4 as Float, -0.4 as Float, 0.4 as Float, float::pi() * 2. as Float),
+                    Arc::new(pbr(base_color, metallic, roughness)), None
+                );
+                let offset = Vector3f::new(
+                    j as Float - 1.5 as Float, 1.5 as Float - i as Float, 0. as Float
+                );
+                let translation = Matrix4f::from_translation(offset);
+                let sphere: Arc<Composable> = Arc::new(TransformedComposable::new(
+                    sphere, Arc::new(translation), Arc::new(translation.invert().unwrap())
+                ));
+                components.push(sphere.into());
+            }
+        }
+        let p = |x: Float, y: Float, z: Float| Point3f::new(x, y, z);
+        let lamp = test_utils::quad(
+            [p(-3., 3., -5.), p(3., 3., -5.), p(3., 3., -1.), p(-3., 3., -1.)],
+            test_utils::matte(0. as Float, 0. as Float, 0. as Float),
+            Some(RGBSpectrumf::grey_scale(6. as Float))
+        );
+        for triangle in TriangleMesh::instances(&lamp) {
+            components.push(triangle.into());
+        }
+        let lights: Vec<Arc<Light>> = vec![Arc::new(MeshLight::new(lamp))];
+        let scene = Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH)));
+        let sampler = StdStrataSampler::new(4, 4, 8, StdRng::from_seed(&[7usize][..]));
+        let camera = test_utils::camera(
+            96, p(0., 0., -6.), p(0., 0., 0.), 0.75 as Float
+        );
+        WhittedRenderer::new(sampler, camera, "", 0. as Float).render_image(&scene)
+    }
+
+    #[test]
+    fn test_ladder() {
+        // the checked-in reference is rewritten if `ARENDUR_UPDATE_REFERENCES` is set
+        let reference = Path::new("tests/references/pbr_ladder.png");
+        let rendered = env::temp_dir().join("arendur_test_pbr_ladder.png");
+        render_ladder().save(&rendered).unwrap();
+        if env::var_os("ARENDUR_UPDATE_REFERENCES").is_some() {
+            ::std::fs::copy(&rendered, reference).unwrap();
+        }
+        let rendered = image::open(&rendered).unwrap().to_rgb();
+        let reference = image::open(reference).unwrap().to_rgb();
+        assert_eq!(rendered.dimensions(), reference.dimensions());
+        let (mut sum, mut outliers) = (0u64, 0);
+        for (a, b) in rendered.pixels().zip(reference.pixels()) {
+            for c in 0..3 {
+                let d = (a.data[c] as i32 - b.data[c] as i32).abs();
+                sum += d as u64;
+                if d > 8 { outliers += 1; }
+            }
+        }
+        let count = (rendered.width() * rendered.height() * 3) as u64;
+        assert!(sum < count, "mean difference {}", sum as f64 / count as f64);
+        assert!(outliers * 100 < count, "{} outliers", outliers);
+    }
+}
+
+#[cfg(test)]
+mod test_rho {
+    use super::super::*;
+    use super::super::prelude::*;
+    use shape::prelude::*;
+    use spectrum::Spectrum;
+    use texturing::textures::ConstantTexture;
+
+    // albedo of `material` towards a ray hitting a sphere head-on
+    fn rho<M: Material>(material: &M) -> RGBSpectrumf {
+        let sphere = Sphere::new(1. as Float, -1. as Float, 1. as Float, float::pi() * 2. as Float);
+        let dir = Vector3f::new(1. as Float, 0. as Float, 0. as Float);
+        let ray = RawRay::from_od(Point3f::new(-5. as Float, 0.2, 0.), dir);
+        let (_, mut si) = sphere.intersect_ray(&ray).unwrap();
+        let alloc = Allocator::new();
+        let bsdf = material.compute_scattering(&mut si, &DxyInfo::default(), &alloc);
+        bsdf.rho(-dir, 16)
+    }
+
+    fn constant(value: RGBSpectrumf) -> Arc<Texture<Texel=RGBSpectrumf>> {
+        Arc::new(ConstantTexture{value: value})
+    }
+
+    #[test]
+    fn test_lambertian() {
+        let matte = MatteMaterial::new(
+            constant(RGBSpectrumf::grey_scale(0.5 as Float)), Arc::new(ConstantTexture{value: 0. as Float}), None
+        );
+        assert_relative_eq!(rho(&matte).to_xyz().y, 0.5 as Float, epsilon = 1e-2);
+    }
+
+    #[test]
+    fn test_mirror() {
+        let reflectance = RGBSpectrumf::new(0.9 as Float, 0.6, 0.3);
+        let albedo = rho(&MirrorMaterial::new(constant(reflectance), None));
+        assert_relative_eq!(albedo.r(), reflectance.r(), epsilon = 1e-3);
+        assert_relative_eq!(albedo.g(), reflectance.g(), epsilon = 1e-3);
+        assert_relative_eq!(albedo.b(), reflectance.b(), epsilon = 1e-3);
+    }
+
+    #[test]
+    fn test_glass() {
+        let white = constant(RGBSpectrumf::grey_scale(1. as Float));
+        let glass = GlassMaterial::new(
+            white.clone(), white, Arc::new(ConstantTexture{value: 0. as Float}), 1.5 as Float, None
+        );
+        let albedo = rho(&glass).to_xyz().y;
+        assert!(albedo > 0. as Float && albedo < 1. as Float, "albedo {}", albedo);
+        // and it's deterministic
+        assert_eq!(rho(&glass).to_xyz().y, albedo);
+    }
+}
+
+#[cfg(test)]
+mod test_leaf {
+    use super::super::*;
+    use super::super::prelude::*;
+    use shape::prelude::*;
+    use spectrum::Spectrum;
+    use bxdf::prelude::*;
+    use texturing::textures::ConstantTexture;
+
+    fn constant(value: RGBSpectrumf) -> Arc<Texture<Texel=RGBSpectrumf>> {
+        Arc::new(ConstantTexture{value: value})
+    }
+
+    // bsdf of `material` at the same point of a sphere, seen from
+    // outside if `front`, and from inside otherwise. Returns it along
+    // with the direction towards the viewer
+    fn scatter<'a, M: Material>(material: &M, front: bool, alloc: &'a Allocator) -> (Bsdf<'a>, Vector3f) {
+        let sphere = Sphere::new(1. as Float, -1. as Float, 1. as Float, float::pi() * 2. as Float);
+        let (origin, dir) = if front {
+            (Point3f::new(-5. as Float, 0., 0.), Vector3f::new(1. as Float, 0., 0.))
+        } else {
+            (Point3f::new(0. as Float, 0., 0.), Vector3f::new(-1. as Float, 0., 0.))
+        };
+        let (_, mut si) = sphere.intersect_ray(&RawRay::from_od(origin, dir)).unwrap();
+        (material.compute_scattering(&mut si, &DxyInfo::default(), alloc), -dir)
+    }
+
+    #[test]
+    fn test_backlit() {
+        let green = RGBSpectrumf::new(0.2 as Float, 0.8, 0.1);
+        let leaf = LeafMaterial::new(constant(green), constant(green), 0.4 as Float, None);
+        let alloc = Allocator::new();
+        let (bsdf, wo) = scatter(&leaf, true, &alloc);
+        // light arriving from behind the surface reaches the viewer in front
+        let (f, kind) = bsdf.evaluate(wo, -wo, BXDF_ALL);
+        assert!(kind.contains(BXDF_TRANSMISSION));
+        let expected = green * (0.4 as Float * float::frac_1_pi());
+        assert_relative_eq!(f.r(), expected.r(), epsilon = 1e-4);
+        assert_relative_eq!(f.g(), expected.g(), epsilon = 1e-4);
+        assert_relative_eq!(f.b(), expected.b(), epsilon = 1e-4);
+    }
+
+    #[test]
+    fn test_energy() {
+        let white = constant(RGBSpectrumf::grey_scale(1. as Float));
+        let black = constant(RGBSpectrumf::black());
+        for &transmission in &[0., 0.3, 0.7, 1.] {
+            for &(ref front, ref back) in &[(&white, &white), (&white, &black), (&black, &white)] {
+                let leaf = LeafMaterial::new(
+                    (*front).clone(), (*back).clone(), transmission as Float, None
+                );
+                for &side in &[true, false] {
+                    let alloc = Allocator::new();
+                    let (bsdf, wo) = scatter(&leaf, side, &alloc);
+                    let albedo = bsdf.rho(wo, 16);
+                    assert!(
+                        albedo.to_xyz().y <= 1.001 as Float,
+                        "albedo {:?} at transmission {}", albedo, transmission
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_symmetry() {
+        let color = constant(RGBSpectrumf::new(0.5 as Float, 0.7, 0.3));
+        let leaf = LeafMaterial::new(color.clone(), color, 0.2 as Float, None);
+        let alloc = Allocator::new();
+        let (front, wof) = scatter(&leaf, true, &alloc);
+        let (back, wob) = scatter(&leaf, false, &alloc);
+        let front_rho = front.rho(wof, 16);
+        let back_rho = back.rho(wob, 16);
+        assert_relative_eq!(front_rho.r(), back_rho.r(), epsilon = 1e-4);
+        assert_relative_eq!(front_rho.g(), back_rho.g(), epsilon = 1e-4);
+        assert_relative_eq!(front_rho.b(), back_rho.b(), epsilon = 1e-4);
+        // mirrored directions reflect the same
+        let wi = Vector3f::new(-1. as Float, 0.5, 0.3).normalize();
+        let mirrored = Vector3f::new(-wi.x, wi.y, wi.z);
+        let (ff, _) = front.evaluate(wof, wi, BXDF_REFLECTION | BXDF_DIFFUSE);
+        let (fb, _) = back.evaluate(wob, mirrored, BXDF_REFLECTION | BXDF_DIFFUSE);
+        assert!(!ff.is_black());
+        assert_relative_eq!(ff.g(), fb.g(), epsilon = 1e-4);
+        // and sampled lobes stay on the side of the viewer
+        let s = back.evaluate_sampled(wob, Point2f::new(0.1 as Float, 0.6), BXDF_REFLECTION);
+        assert!(s.pdf > 0. as Float && s.wi.dot(wob) > 0. as Float);
+    }
+}
+
+#[cfg(test)]
+mod test_bump_prefilter {
+    use super::super::*;
+    use super::super::prelude::*;
+    use prelude::*;
+    use component::ComponentPointer;
+    use lighting::pointlights::PointLight;
+    use texturing::textures::ConstantTexture;
+    use texturing::textures::image::{LumaImageTexture, ImageInfo, ImageWrapMode};
+    use texturing::mappings::UVMapping;
+    use test_utils;
+    use filming::film::Image;
+    use rand::{Rng, SeedableRng, StdRng};
+    use std::collections::HashMap;
+    use image;
+    use tobj;
+
+    // `inner` with its footprint hidden, as bump maps were filtered before
+    struct Unfiltered(Arc<Texture<Texel=Float>>);
+
+    impl Texture for Unfiltered {
+        type Texel = Float;
+
+        fn evaluate(&self, si: &SurfaceInteraction, dxy: &DxyInfo) -> Float {
+            self.0.evaluate(si, dxy)
+        }
+
+        fn mean(&self) -> Float {
+            self.0.mean()
+        }
+    }
+
+    // bumps of random heights, one per texel, repeated over the plane
+    fn noise() -> Arc<Texture<Texel=Float>> {
+        let mut rng = StdRng::from_seed(&[3usize][..]);
+        let heights: Vec<u8> = (0..64 * 64).map(|_| rng.gen()).collect();
+        let buffer = image::GrayImage::from_raw(64, 64, heights).unwrap();
+        let info = ImageInfo{
+            name: String::new(),
+            trilinear: false,
+            max_aniso: 16. as Float,
+            wrapping: ImageWrapMode::Repeat,
+            gamma: false,
+            scale: 0.05 as Float,
+        };
+        let mapping = UVMapping{
+            scaling: Vector2f::new(64. as Float, 64. as Float),
+            shifting: Vector2f::zero(),
+        };
+        Arc::new(LumaImageTexture::from_buffer(
+            "arendur_test_bump_noise", buffer, info, mapping, &mut HashMap::new()
+        ).unwrap())
+    }
+
+    // a bumpy plane filling the view from `distance` away, under a light
+    // off to the side, rendered at `resolution` with the eye shifted by
+    // `shift` along `x`
+    fn render(bump: Arc<Texture<Texel=Float>>, distance: Float, resolution: usize, shift: Float) -> Image {
+        let model = tobj::Model::new(
+            tobj::Mesh::new(
+                vec![-8., -8., 0., 8., -8., 0., 8., 8., 0., -8., 8., 0.],
+                Vec::new(),
+                vec![0., 0., 1., 0., 1., 1., 0., 1.],
+                vec![0, 1, 2, 0, 2, 3],
+                None
+            ),
+            "plane".to_owned()
+        );
+        let material = Arc::new(MatteMaterial::new(
+            Arc::new(ConstantTexture{value: RGBSpectrumf::grey_scale(0.8 as Float)}),
+            Arc::new(ConstantTexture{value: 0. as Float}),
+            Some(bump)
+        ));
+        let mesh = Arc::new(TriangleMesh::from_model(model, material, None));
+        let components: Vec<ComponentPointer> = TriangleMesh::instances(&mesh)
+            .map(|t| t.into()).collect();
+        let lights: Vec<Arc<Light>> = vec![Arc::new(PointLight::new(
+            Point3f::new(3. as Float, 2. as Float, -2. as Float),
+            RGBSpectrumf::grey_scale(20. as Float)
+        ))];
+        let scene = Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH)));
+        let sampler = StdStrataSampler::new(2, 2, 4, StdRng::from_seed(&[7usize][..]));
+        let fov = 2. as Float * (1. as Float / distance).atan();
+        let camera = test_utils::camera(
+            resolution, Point3f::new(shift, 0. as Float, -distance),
+            Point3f::new(shift, 0. as Float, 0. as Float), fov
+        );
+        PTRenderer::new(sampler, camera, "", 1, true).render_image(&scene)
+    }
+
+    #[test]
+    fn test_resolution_invariant() {
+        let bump = noise();
+        let unfiltered: Arc<Texture<Texel=Float>> = Arc::new(Unfiltered(bump.clone()));
+        let low = test_utils::mean(&render(bump.clone(), 6. as Float, 8, 0. as Float)).to_xyz().y;
+        let high = test_utils::mean(&render(bump.clone(), 6. as Float, 16, 0. as Float)).to_xyz().y;
+        let low_unfiltered = test_utils::mean(&render(unfiltered.clone(), 6. as Float, 8, 0. as Float)).to_xyz().y;
+        let high_unfiltered = test_utils::mean(&render(unfiltered, 6. as Float, 16, 0. as Float)).to_xyz().y;
+        assert_relative_eq!(low, high, max_relative = 0.02 as Float);
+        assert!((low - high).abs() < (low_unfiltered - high_unfiltered).abs());
+    }
+
+    #[test]
+    fn test_less_flicker() {
+        let bump = noise();
+        let unfiltered: Arc<Texture<Texel=Float>> = Arc::new(Unfiltered(bump.clone()));
+        let shift = 0.013 as Float;
+        let flicker = |bump: &Arc<Texture<Texel=Float>>| test_utils::relative_mse(
+            &render(bump.clone(), 6. as Float, 16, 0. as Float),
+            &render(bump.clone(), 6. as Float, 16, shift)
+        );
+        let filtered = flicker(&bump);
+        let before = flicker(&unfiltered);
+        assert!(filtered < before * 0.5 as Float, "flicker {} against {}", filtered, before);
+    }
+}
+
+#[cfg(test)]
+mod test_bump_scale {
+    use super::super::*;
+    use super::super::prelude::*;
+    use component::prelude::*;
+    use shape::prelude::*;
+    use texturing::textures::ConstantTexture;
+
+    // ripples of `amplitude` along u
+    struct Ripple {
+        amplitude: Float,
+    }
+
+    impl Texture for Ripple {
+        type Texel = Float;
+
+        fn evaluate(&self, si: &SurfaceInteraction, _dxy: &DxyInfo) -> Float {
+            self.amplitude * (si.uv.x * float::pi() * 32. as Float).sin()
+        }
+
+        fn mean(&self) -> Float {
+            0. as Float
+        }
+    }
+
+    // angle between the bumped shading normal and the geometric one
+    // of a bumpy unit sphere instanced by `m`, hit by a ray along `dir`
+    fn perturbation(m: Matrix4f, dir: Vector3f) -> Float {
+        let material = Arc::new(MatteMaterial::new(
+            Arc::new(ConstantTexture{value: RGBSpectrumf::new(0.5 as Float, 0.5, 0.5)}),
+            Arc::new(ConstantTexture{value: 0. as Float}),
+            Some(Arc::new(Ripple{amplitude: 0.01 as Float}))
+        ));
+        let sphere = ShapedPrimitive::new(Sphere::full(1. as Float), material.clone(), None);
+        let sphere = TransformedComposable::new(sphere, Arc::new(m), Arc::new(m.invert().unwrap()));
+        let p = (Vector3f::new(0.3 as Float, 0.2, 0.) - dir).normalize();
+        let target = m.transform_point(Point3f::from_vec(p));
+        let mut ray = RawRay::from_od(target + dir * -100. as Float, dir);
+        let mut si = sphere.intersect_ray(&mut ray).unwrap();
+        let alloc = Allocator::new();
+        material.compute_scattering(&mut si, &DxyInfo::default(), &alloc);
+        si.shading_norm.dot(si.basic.norm).min(1. as Float).acos()
+    }
+
+    #[test]
+    fn test_scale_invariant() {
+        let dir = Vector3f::new(-1. as Float, 0., -1.).normalize();
+        let unit = perturbation(Matrix4f::identity(), dir);
+        assert!(unit > 0.05 as Float, "perturbation {}", unit);
+        let scaled = perturbation(Matrix4f::from_scale(5. as Float), dir);
+        assert_relative_eq!(unit, scaled, max_relative = 1e-3);
+        let moved = perturbation(Matrix4f::from_translation(Vector3f::new(2. as Float, -3., 1.)) * Matrix4f::from_scale(5. as Float), dir);
+        assert_relative_eq!(unit, moved, max_relative = 1e-3);
+    }
+
+    #[test]
+    fn test_anisotropic_scale() {
+        // stretching along the normal deepens bumps as it does the sphere
+        let dir = Vector3f::new(0. as Float, 0., -1.);
+        let unit = perturbation(Matrix4f::identity(), dir);
+        let along = perturbation(Matrix4f::from_nonuniform_scale(1. as Float, 1., 3.), dir);
+        let across = perturbation(Matrix4f::from_nonuniform_scale(3. as Float, 3., 1.), dir);
+        assert!(along > unit && across < unit, "{} {} {}", unit, along, across);
+    }
+}
@@ -0,0 +1,64 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Thin glass material
+
+use std::sync::Arc;
+use spectrum::prelude::*;
+use super::*;
+use bxdf::prelude::*;
+
+/// A single sheet of glass, for windows modelled without thickness.
+///
+/// Light passes straight through instead of being refracted,
+/// as it would be after crossing both sides of a thin slab.
+#[derive(Clone)]
+pub struct ThinGlassMaterial {
+    pub reflectance: Arc<Texture<Texel=RGBSpectrumf>>,
+    /// tint of the transmitted light
+    pub transmittance: Arc<Texture<Texel=RGBSpectrumf>>,
+    pub eta: Float,
+    pub bump: Option<Arc<Texture<Texel=Float>>>,
+}
+
+impl ThinGlassMaterial {
+    /// construction
+    #[inline]
+    pub fn new(
+        reflectance: Arc<Texture<Texel=RGBSpectrumf>>,
+        transmittance: Arc<Texture<Texel=RGBSpectrumf>>,
+        eta: Float,
+        bump: Option<Arc<Texture<Texel=Float>>>
+    ) -> ThinGlassMaterial {
+        ThinGlassMaterial{
+            reflectance, transmittance, eta, bump,
+        }
+    }
+}
+
+impl Material for ThinGlassMaterial {
+    fn compute_scattering<'a>(
+        &self,
+        si: &mut SurfaceInteraction,
+        dxy: &DxyInfo,
+        alloc: &'a Allocator
+    ) -> bsdf::Bsdf<'a> {
+        if let Some(ref bump) = self.bump {
+            add_bumping(si, dxy, &**bump);
+        }
+        let reflectance = self.reflectance.evaluate(si, dxy);
+        let transmittance = self.transmittance.evaluate(si, dxy);
+        let mut ret = bsdf::Bsdf::new(si, 1.0 as Float);
+        if !reflectance.is_black() || !transmittance.is_black() {
+            ret.add(alloc.alloc(ThinDielectricBxdf::new(
+                reflectance, transmittance, self.eta
+            )));
+        }
+        ret
+    }
+}
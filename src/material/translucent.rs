@@ -14,7 +14,7 @@ use super::*;
 use bxdf::prelude::*;
 use bxdf::microfacet::roughness_to_alpha;
 
-/// A plastic material
+/// A translucent material
 #[derive(Clone)]
 pub struct TranslucentMaterial {
     pub diffuse: Arc<Texture<Texel=RGBSpectrumf>>,
@@ -22,6 +22,8 @@ pub struct TranslucentMaterial {
     pub roughness: Arc<Texture<Texel=Float>>,
     pub dissolve: Float,
     pub bump: Option<Arc<Texture<Texel=Float>>>,
+    /// index of refraction of the glossy coating, defaults to 1.5
+    pub eta: Float,
 }
 
 impl TranslucentMaterial {
@@ -33,7 +35,8 @@ impl TranslucentMaterial {
         bump: Option<Arc<Texture<Texel=Float>>>
     ) -> TranslucentMaterial {
         TranslucentMaterial{
-            diffuse, specular, roughness, dissolve, bump
+            diffuse, specular, roughness, dissolve, bump,
+            eta: 1.5 as Float,
         }
     }
 }
@@ -54,14 +57,23 @@ impl Material for TranslucentMaterial {
         let alpha = roughness_to_alpha(roughness);
         let mut ret = bsdf::Bsdf::new(si, 1.0 as Float);
         if !relative_eq!(self.dissolve, 0. as Float) {
-            ret.add(alloc.alloc(
-                AshikhminShirleyBxdf::new(
-                    diffuse*self.dissolve, specular*self.dissolve,
-                    Trowbridge{
-                        ax: alpha, ay: alpha
-                    }
-                )
-            ));
+            let coating = Dielectric::new(1. as Float, self.eta);
+            if !specular.is_black() {
+                ret.add(alloc.alloc(
+                    TorranceSparrowRBxdf::new(
+                        specular*self.dissolve,
+                        Trowbridge{
+                            ax: alpha, ay: alpha
+                        },
+                        coating
+                    )
+                ));
+            }
+            if !diffuse.is_black() {
+                ret.add(alloc.alloc(
+                    CoatedLambertianRBxdf::new(diffuse*self.dissolve, coating)
+                ));
+            }
         }
         if !diffuse.is_black() {
             ret.add(alloc.alloc(
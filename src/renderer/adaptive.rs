@@ -0,0 +1,145 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Adaptive sampling, giving more samples to the pixels whose
+//! estimates are noisy, or which straddle geometric edges.
+//!
+//! Edges between surfaces of similar brightness hardly show in the
+//! variance of the luminance, but do in the spread of the first hits
+//! of camera rays through the pixel, which is considered as well.
+
+use geometry::prelude::*;
+use filming::film::{BoundedSink2D, Image, SampleStats, viridis};
+use spectrum::{RGBSpectrumf, Spectrum};
+use super::pathfilter::FirstHit;
+
+/// Parameters of adaptive sampling
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AdaptiveSampling {
+    /// rounds of the sampler's samples per pixel taken at most on
+    /// top of the first one
+    pub max_rounds: usize,
+    /// error of a pixel beyond which it's given another round
+    pub threshold: Float,
+    /// weight of the relative variance of the pixel's mean luminance
+    pub luminance_weight: Float,
+    /// weight of the spread of the pixel's first hits,
+    /// as given by `GeometryStats::spread`
+    pub geometry_weight: Float,
+}
+
+impl AdaptiveSampling {
+    /// test if the parameters make sense, namely a positive threshold
+    /// and non-negative weights
+    pub fn is_valid(&self) -> bool {
+        self.threshold > 0. as Float
+            && self.luminance_weight >= 0. as Float
+            && self.geometry_weight >= 0. as Float
+    }
+
+    /// error of a pixel given its samples' statistics, as the larger
+    /// of both weighted criteria. Zero for pixels of less than two samples
+    #[inline]
+    pub fn error(&self, luminance: &SampleStats, geometry: &GeometryStats) -> Float {
+        let luminance = luminance.relative_variance().unwrap_or(0. as Float);
+        (self.luminance_weight * luminance).max(self.geometry_weight * geometry.spread())
+    }
+}
+
+impl Default for AdaptiveSampling {
+    #[inline]
+    fn default() -> AdaptiveSampling {
+        AdaptiveSampling{
+            max_rounds: 3,
+            threshold: 0.01 as Float,
+            luminance_weight: 1. as Float,
+            geometry_weight: 1. as Float,
+        }
+    }
+}
+
+/// Statistics of the first hits of the camera samples within a pixel
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GeometryStats {
+    /// number of samples
+    pub count: u32,
+    /// number of samples hitting anything
+    pub hits: u32,
+    /// sum of the normals at the hits
+    pub normal: Vector3f,
+    /// mean distance of the hits from the camera
+    pub depth: Float,
+    /// sum of squared deviations of the distances from their mean
+    pub m2: Float,
+}
+
+impl Default for GeometryStats {
+    #[inline]
+    fn default() -> GeometryStats {
+        GeometryStats{
+            count: 0,
+            hits: 0,
+            normal: Vector3f::zero(),
+            depth: 0. as Float,
+            m2: 0. as Float,
+        }
+    }
+}
+
+impl GeometryStats {
+    /// add a sample first hitting `hit`, if any
+    #[inline]
+    pub fn add(&mut self, hit: Option<&FirstHit>) {
+        self.count += 1;
+        if let Some(hit) = hit {
+            self.hits += 1;
+            self.normal += hit.normal;
+            let delta = hit.depth - self.depth;
+            self.depth += delta / self.hits as Float;
+            self.m2 += delta * (hit.depth - self.depth);
+        }
+    }
+
+    /// spread of the first hits in $[0, 1]$, as the largest of the
+    /// mixing of hits and misses $4p(1-p)$, one minus the length of the
+    /// mean normal, and the relative standard deviation of the
+    /// distances, clamped to one
+    pub fn spread(&self) -> Float {
+        if self.count == 0 { return 0. as Float; }
+        let p = self.hits as Float / self.count as Float;
+        let coverage = 4. as Float * p * (1. as Float - p);
+        if self.hits < 2 { return coverage; }
+        let n = self.hits as Float;
+        let normal = 1. as Float - self.normal.magnitude() / n;
+        let depth = if self.depth > 0. as Float {
+            ((self.m2 / n).sqrt() / self.depth).min(1. as Float)
+        } else {
+            0. as Float
+        };
+        coverage.max(normal).max(depth)
+    }
+}
+
+/// false-colored map of the samples taken in each pixel, from the
+/// fewest to the most
+pub fn sample_count_map(counts: &BoundedSink2D<u32>) -> Image {
+    let bounding = counts.bounding();
+    let (mut min, mut max) = (u32::max_value(), 0);
+    for p in bounding {
+        let count = *counts.get_pixel(p);
+        min = min.min(count);
+        max = max.max(count);
+    }
+    let range = (max - min).max(1) as Float;
+    let mut image = Image::new(RGBSpectrumf::black(), bounding.pmax.cast());
+    for p in bounding {
+        let t = (*counts.get_pixel(p) - min) as Float / range;
+        image[p.cast()] = viridis(t);
+    }
+    image
+}
@@ -0,0 +1,180 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Rendering frame sequences of a camera moving through a static scene.
+//!
+//! The scene, along with its acceleration structures, is built once and
+//! shared by all frames; only the camera and the renderer change.
+
+use geometry::prelude::*;
+use filming::prelude::*;
+use filming::film::Image;
+use super::Renderer;
+use super::scene::Scene;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A turntable, circling the camera around `target`
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Orbit {
+    /// point looked at and circled around
+    pub target: Point3f,
+    /// distance of the camera from `target`
+    pub radius: Float,
+    /// angle of the camera above the horizontal plane, in radians
+    #[serde(default)]
+    pub elevation: Float,
+    /// angle around the `y` axis of the first frame, in radians,
+    /// with `0` on the `+z` side of `target`
+    #[serde(default)]
+    pub start_angle: Float,
+    /// angle around the `y` axis of the last frame, in radians
+    pub end_angle: Float,
+    /// number of frames, evenly spread from `start_angle` to `end_angle`
+    pub frames: usize,
+}
+
+impl Orbit {
+    /// position of the camera at `frame`
+    pub fn eye(&self, frame: usize) -> Point3f {
+        let t = if self.frames > 1 {
+            frame as Float / (self.frames - 1) as Float
+        } else {
+            0. as Float
+        };
+        let angle = self.start_angle + (self.end_angle - self.start_angle) * t;
+        let (sine, cose) = self.elevation.sin_cos();
+        let (sina, cosa) = angle.sin_cos();
+        self.target + Vector3f::new(cose * sina, sine, cose * cosa) * self.radius
+    }
+}
+
+/// How the camera moves across the frames
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CameraPath {
+    /// circling around a target
+    Orbit(Orbit),
+    /// a parent to view-space transform per frame
+    Transforms(Vec<Matrix4f>),
+}
+
+impl CameraPath {
+    /// number of frames
+    #[inline]
+    pub fn frames(&self) -> usize {
+        match *self {
+            CameraPath::Orbit(ref orbit) => orbit.frames,
+            CameraPath::Transforms(ref transforms) => transforms.len(),
+        }
+    }
+
+    /// test if the path makes sense, namely with at least one frame,
+    /// a positive orbit radius and invertible transforms
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            CameraPath::Orbit(ref orbit) => {
+                orbit.frames > 0 && orbit.radius > 0. as Float && orbit.radius.is_finite()
+                    && orbit.elevation.is_finite()
+                    && orbit.start_angle.is_finite() && orbit.end_angle.is_finite()
+            },
+            CameraPath::Transforms(ref transforms) => {
+                !transforms.is_empty() && transforms.iter().all(|t| t.inverse_transform().is_some())
+            },
+        }
+    }
+
+    /// move `camera` to its pose at `frame`
+    pub fn apply(&self, frame: usize, camera: &mut PerspecCam) {
+        match *self {
+            CameraPath::Orbit(ref orbit) => camera.look_from(
+                orbit.eye(frame), orbit.target, Vector3f::new(0. as Float, 1. as Float, 0. as Float)
+            ),
+            CameraPath::Transforms(ref transforms) => camera.set_transform(transforms[frame]),
+        }
+    }
+}
+
+/// Drives a renderer through the frames of a `CameraPath`, saving
+/// them as `frame_0000.png`, `frame_0001.png`, ... into a directory
+#[derive(Clone)]
+pub struct AnimationDriver {
+    camera: PerspecCam,
+    path: CameraPath,
+    directory: PathBuf,
+}
+
+impl AnimationDriver {
+    /// construction. `camera` provides everything but the pose,
+    /// including the film each frame starts from
+    pub fn new<P: AsRef<Path> + ?Sized>(camera: PerspecCam, path: CameraPath, directory: &P) -> AnimationDriver {
+        AnimationDriver{
+            camera: camera,
+            path: path,
+            directory: directory.as_ref().to_owned(),
+        }
+    }
+
+    /// number of frames
+    #[inline]
+    pub fn frames(&self) -> usize {
+        self.path.frames()
+    }
+
+    /// the camera posed for `frame`
+    pub fn camera(&self, frame: usize) -> PerspecCam {
+        let mut camera = self.camera.clone();
+        self.path.apply(frame, &mut camera);
+        camera
+    }
+
+    /// where `frame` is saved
+    #[inline]
+    pub fn frame_path(&self, frame: usize) -> PathBuf {
+        self.directory.join(format!("frame_{:04}.png", frame))
+    }
+
+    /// render `frame` of `scene` with the renderer built by `factory`.
+    ///
+    /// `factory` is given the posed camera, the path of the frame and
+    /// the image of the previous frame if any, which renderers may
+    /// take as a hint, e.g. for denoising
+    pub fn render_frame<R, F>(
+        &self, scene: &Scene, frame: usize, previous: Option<&Image>, factory: &mut F
+    ) -> Image
+        where R: Renderer,
+              F: FnMut(Arc<Camera>, &Path, Option<&Image>) -> R
+    {
+        let camera: Arc<Camera> = Arc::new(self.camera(frame));
+        let path = self.frame_path(frame);
+        factory(camera, &path, previous).render_image(scene)
+    }
+
+    /// render and save all frames of `scene` in order, with renderers
+    /// built by `factory`. Frames failing to be saved are reported and
+    /// skipped over, returning the number of frames saved
+    pub fn render<R, F>(&self, scene: &Scene, mut factory: F) -> usize
+        where R: Renderer,
+              F: FnMut(Arc<Camera>, &Path, Option<&Image>) -> R
+    {
+        let mut previous = None;
+        let mut saved = 0;
+        for frame in 0..self.frames() {
+            let image = self.render_frame(scene, frame, previous.as_ref(), &mut factory);
+            let path = self.frame_path(frame);
+            match image.save(&path) {
+                Ok(_) => {
+                    info!("Frame {} saved at {:?}", frame, path);
+                    saved += 1;
+                },
+                Err(e) => warn!("Saving frame {} at {:?} failed: {}", frame, path, e),
+            }
+            previous = Some(image);
+        }
+        saved
+    }
+}
@@ -0,0 +1,192 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Defines the ambient occlusion renderer, for a quick look at the
+//! geometry of a scene or as a feature for denoisers
+
+use sample::{self, Sampler};
+use filming::Camera;
+use super::Renderer;
+use std::sync::Arc;
+use super::scene::Scene;
+use filming::film::{FilmTile, FilmPreview, Image};
+use spectrum::{RGBSpectrumf, Spectrum};
+use rayon::prelude::*;
+use geometry::prelude::*;
+use std::path::{PathBuf, Path};
+use std::time::Duration;
+
+/// How much a hit occludes, depending on its distance
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OcclusionFalloff {
+    /// every hit within the maximum distance occludes fully
+    Constant,
+    /// hits occlude less the farther they are, linearly
+    Linear,
+    /// as `Linear`, but easing in and out with a smoothstep
+    Smooth,
+}
+
+impl OcclusionFalloff {
+    /// occlusion by a hit at `x` times the maximum distance away,
+    /// `x` in $[0, 1]$
+    #[inline]
+    pub fn occlusion(&self, x: Float) -> Float {
+        let x = x.max(0. as Float).min(1. as Float);
+        match *self {
+            OcclusionFalloff::Constant => 1. as Float,
+            OcclusionFalloff::Linear => 1. as Float - x,
+            OcclusionFalloff::Smooth => 1. as Float - x * x * (3. as Float - 2. as Float * x),
+        }
+    }
+}
+
+impl Default for OcclusionFalloff {
+    #[inline]
+    fn default() -> OcclusionFalloff {
+        OcclusionFalloff::Constant
+    }
+}
+
+/// Parameters of the ambient occlusion
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AmbientOcclusion {
+    /// hits farther away than this don't occlude
+    pub max_distance: Float,
+    /// cosine-weighted directions tested per shading point
+    pub samples: usize,
+    #[serde(default)]
+    pub falloff: OcclusionFalloff,
+}
+
+impl AmbientOcclusion {
+    /// test if the parameters make sense, namely a positive maximum
+    /// distance and samples
+    pub fn is_valid(&self) -> bool {
+        self.max_distance > 0. as Float && self.samples > 0
+    }
+}
+
+impl Default for AmbientOcclusion {
+    #[inline]
+    fn default() -> AmbientOcclusion {
+        AmbientOcclusion{
+            max_distance: 1. as Float,
+            samples: 16,
+            falloff: OcclusionFalloff::Constant,
+        }
+    }
+}
+
+/// ambient occlusion renderer, writing the unoccluded fraction of the
+/// hemisphere above each camera ray hit as a grayscale image. Pixels
+/// seeing nothing are left unoccluded, with the background transparent.
+pub struct AORenderer<S> {
+    sampler: S,
+    camera: Arc<Camera>,
+    path: PathBuf,
+    params: AmbientOcclusion,
+    preview_interval: Option<Duration>,
+}
+
+impl<S: Sampler> AORenderer<S> {
+    /// construction
+    pub fn new<P: AsRef<Path> + ?Sized>(sampler: S, camera: Arc<Camera>, path: &P, params: AmbientOcclusion) -> AORenderer<S> {
+        assert!(params.is_valid(), "invalid ambient occlusion {:?}", params);
+        AORenderer{
+            sampler: sampler,
+            camera: camera,
+            path: path.as_ref().to_path_buf(),
+            params: params,
+            preview_interval: None,
+        }
+    }
+
+    /// set whether `render` writes completed tiles into the output as
+    /// it goes, saving it at most once per `interval`. `None` disables
+    /// the preview.
+    #[inline]
+    pub fn set_preview_interval(&mut self, interval: Option<Duration>) {
+        self.preview_interval = interval;
+    }
+
+    // render `scene`, writing completed tiles into `preview` if presented
+    fn render_tiles(&self, scene: &Scene, preview: Option<&FilmPreview>) -> Image {
+        let film = self.camera.get_film();
+        let mut tiles: Vec<FilmTile<RGBSpectrumf>> = film.spawn_tiles(film.tile_size());
+        tiles.par_iter_mut().for_each(|tile| {
+            let tile_bound = tile.bounding();
+            let mut sampler = self.sampler.fork(super::tile_seed(tile_bound));
+            let mut us = vec![Point2f::new(0. as Float, 0. as Float); self.params.samples];
+            for p in tile_bound {
+                let p: Point2<u32> = p.cast();
+                sampler.start_pixel(p);
+                loop {
+                    let mut camera_sample_info = sampler.get_camera_sample(p, self.camera.shutter());
+                    let pfilm = camera_sample_info.pfilm;
+                    camera_sample_info.pfilm = tile.jitter(pfilm);
+                    let mut ray = self.camera.generate_path(camera_sample_info);
+                    let (ao, alpha) = match scene.intersect_clipped(&mut ray, self.camera.clip_planes()) {
+                        Some(si) => {
+                            sampler.request_2d(&mut us);
+                            (ambient_occlusion(&si, scene, &us, &self.params), 1. as Float)
+                        },
+                        None => (1. as Float, 0. as Float),
+                    };
+                    tile.add_sample_with_alpha(pfilm, &RGBSpectrumf::grey_scale(ao), alpha);
+                    if !sampler.next_sample() { break; }
+                }
+            }
+            if let Some(preview) = preview {
+                preview.add_tile(tile);
+            }
+        });
+        film.collect_into(tiles)
+    }
+}
+
+/// unoccluded fraction of the hemisphere above `si`, on the side it's
+/// seen from, estimated by a cosine-weighted direction for each of `us`
+pub fn ambient_occlusion(si: &SurfaceInteraction, scene: &Scene, us: &[Point2f], params: &AmbientOcclusion) -> Float {
+    let norm = if si.shading_norm.dot(si.basic.wo) < 0. as Float {
+        -si.shading_norm
+    } else {
+        si.shading_norm
+    };
+    let mut rays: Vec<RawRay> = us.iter().map(|&u| {
+        let (wi, _) = sample::sample_cosw_hemisphere_about(norm, u);
+        let mut ray = RawRay::from_od(si.basic.offset_towards(wi), wi).with_kind(RayKind::Shadow);
+        ray.set_max_extend(params.max_distance);
+        ray
+    }).collect();
+    let occlusion = if params.falloff == OcclusionFalloff::Constant {
+        let mut occluded = vec![false; rays.len()];
+        scene.aggregate.occluded_batch(&mut rays, &mut occluded);
+        occluded.into_iter().filter(|&o| o).count() as Float
+    } else {
+        // directions are normalized, so that distances are the hits' `t`
+        rays.iter().filter_map(|ray| scene.aggregate.intersect_t(ray)).map(|t| {
+            params.falloff.occlusion(t / params.max_distance)
+        }).sum()
+    };
+    1. as Float - occlusion / us.len() as Float
+}
+
+impl<S: Sampler> Renderer for AORenderer<S> {
+    fn render_image(&mut self, scene: &Scene) -> Image {
+        self.render_tiles(scene, None)
+    }
+
+    fn render(&mut self, scene: &Scene) {
+        let preview = self.preview_interval.map(|interval| {
+            self.camera.get_film().spawn_preview(&self.path, interval)
+        });
+        let render_result = self.render_tiles(scene, preview.as_ref());
+        render_result.save(&self.path).expect("saving failure");
+    }
+}
@@ -0,0 +1,382 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Baking lighting into the texture layout of a mesh.
+//!
+//! The footprint of each triangle in texture space is conservatively
+//! rasterized onto the texels of the output, and covered texels are
+//! shaded at the point of the triangle they map to, with the path
+//! tracer's estimators; no camera is involved. Texels around the charts
+//! are then filled by dilation, so that filtering the baked texture
+//! doesn't bleed the background into the seams.
+
+use geometry::prelude::*;
+use sample::prelude::*;
+use bxdf::prelude::*;
+use bxdf::lambertian::LambertianRBxdf;
+use material::bsdf::Bsdf;
+use shape::triangle::{TriangleMesh, TriangleInstance};
+use component::Primitive;
+use filming::film::{BoundedSink2D, Image};
+use spectrum::{RGBSpectrumf, Spectrum};
+use super::Renderer;
+use super::scene::Scene;
+use super::pt;
+use rayon::prelude::*;
+use aren_alloc::Allocator;
+use std::sync::Arc;
+use std::path::{PathBuf, Path};
+use std::fmt;
+
+/// What is baked into each texel
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BakeMode {
+    /// irradiance arriving at the front of the surface, as lightmaps hold
+    Irradiance,
+    /// radiance leaving the surface along its normal, shaded
+    /// with the mesh's material
+    Radiance,
+}
+
+impl Default for BakeMode {
+    #[inline]
+    fn default() -> BakeMode {
+        BakeMode::Irradiance
+    }
+}
+
+/// Reasons for a mesh not to be baked
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BakeError {
+    /// the mesh has no texture coordinates
+    MissingUVs,
+    /// the texture footprints of some triangles overlap, with the
+    /// centers of `texels` texels covered more than once
+    OverlappingUVs{
+        texels: usize,
+    },
+}
+
+impl fmt::Display for BakeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BakeError::MissingUVs => f.write_str("the mesh has no texture coordinates"),
+            BakeError::OverlappingUVs{texels} => write!(
+                f, "texture coordinates of the mesh overlap over {} texels", texels
+            ),
+        }
+    }
+}
+
+/// texels beyond the charts filled by dilation by default
+pub const DEFAULT_DILATION: usize = 4;
+
+/// A renderer baking the lighting of a mesh into its texture layout,
+/// the texel `(x, y)` of a `width` by `height` output covering the
+/// texture coordinates `[x/width, (x+1)/width) * [y/height, (y+1)/height)`
+pub struct BakeRenderer<S> {
+    sampler: S,
+    mesh: Arc<TriangleMesh>,
+    filename: PathBuf,
+    max_depth: usize,
+    min_depth: usize,
+    // smallest probability of paths surviving russian roulette
+    rr_threshold: Float,
+    multithreaded: bool,
+    mode: BakeMode,
+    dilation: usize,
+    // triangle shading each texel, if any
+    owners: BoundedSink2D<Option<u32>>,
+}
+
+impl<S: Sampler> BakeRenderer<S> {
+    /// construction, baking `mesh` into a texture of `resolution`.
+    /// Fails for meshes whose texture coordinates are missing or overlap
+    pub fn new<P: AsRef<Path> + ?Sized>(
+        sampler: S, mesh: Arc<TriangleMesh>, resolution: Point2<usize>,
+        filename: &P, max_depth: usize, multithreaded: bool
+    ) -> Result<BakeRenderer<S>, BakeError> {
+        if !mesh.has_uvs() { return Err(BakeError::MissingUVs); }
+        let owners = rasterize(&mesh, resolution)?;
+        Ok(BakeRenderer{
+            sampler: sampler,
+            mesh: mesh,
+            filename: filename.as_ref().to_path_buf(),
+            max_depth: max_depth,
+            min_depth: max_depth / 2,
+            rr_threshold: 0.05 as Float,
+            multithreaded: multithreaded,
+            mode: BakeMode::default(),
+            dilation: DEFAULT_DILATION,
+            owners: owners,
+        })
+    }
+
+    /// set what is baked
+    #[inline]
+    pub fn set_mode(&mut self, mode: BakeMode) {
+        self.mode = mode;
+    }
+
+    /// set how many texels beyond the charts are filled
+    #[inline]
+    pub fn set_dilation(&mut self, dilation: usize) {
+        self.dilation = dilation;
+    }
+
+    /// resolution of the output
+    #[inline]
+    pub fn resolution(&self) -> Point2<usize> {
+        self.owners.bounding().pmax.cast()
+    }
+
+    /// number of texels covered by the charts
+    pub fn covered_texels(&self) -> usize {
+        self.owners.bounding().into_iter().filter(|&p| self.owners.get_pixel(p).is_some()).count()
+    }
+
+    // mean estimate over the samples of texel `p`, covered by `triangle`
+    fn bake_texel(
+        &self, p: Point2<isize>, triangle: &TriangleInstance,
+        scene: &Scene, sampler: &mut S, alloc: &Allocator
+    ) -> RGBSpectrumf {
+        let resolution: Point2<Float> = self.resolution().cast();
+        let pu: Point2<u32> = p.cast();
+        sampler.start_pixel(pu);
+        let mut sum = RGBSpectrumf::black();
+        let mut count = 0;
+        loop {
+            let pfilm = sampler.get_camera_sample(pu, None).pfilm;
+            let uv = Point2f::new(pfilm.x / resolution.x, pfilm.y / resolution.y);
+            let term = self.estimate(triangle, uv, resolution, scene, sampler, alloc);
+            if term.valid() {
+                sum += term;
+            } else {
+                warn!("invalid estimate {:?} baked at texel {:?}", term, p);
+            }
+            count += 1;
+            if !sampler.next_sample() { break; }
+        }
+        sum / count as Float
+    }
+
+    // estimate at the point of `triangle` closest to `uv` in texture space
+    fn estimate(
+        &self, triangle: &TriangleInstance, uv: Point2f, resolution: Point2<Float>,
+        scene: &Scene, sampler: &mut S, alloc: &Allocator
+    ) -> RGBSpectrumf {
+        let b = clamped_barycentric(triangle.uvs(), uv);
+        let mut si = triangle.surface_at(b, triangle.face_normal());
+        let wo = si.basic.wo;
+        // the footprint of a texel
+        let dxy = DxyInfo{
+            dpdx: si.duv.dpdu / resolution.x,
+            dpdy: si.duv.dpdv / resolution.y,
+            dudx: 1. as Float / resolution.x,
+            dudy: 0. as Float,
+            dvdx: 0. as Float,
+            dvdy: 1. as Float / resolution.y,
+        };
+        let (mut ret, bsdf) = match self.mode {
+            BakeMode::Irradiance => {
+                let mut bsdf = Bsdf::new_opaque(&si, 1. as Float);
+                bsdf.add(alloc.alloc(LambertianRBxdf::new(
+                    RGBSpectrumf::new(1. as Float, 1. as Float, 1. as Float)
+                )));
+                (RGBSpectrumf::black(), bsdf)
+            },
+            BakeMode::Radiance => {
+                let le = si.le(wo);
+                let bsdf = triangle.get_material().compute_scattering(&mut si, &dxy, alloc);
+                (le, bsdf)
+            },
+        };
+        if bsdf.have_n(BXDF_DIFFUSE | BXDF_GLOSSY) > 0 {
+            ret += scene.uniform_sample_one_light(&si, sampler, &bsdf);
+        }
+        if self.max_depth > 1 {
+            let sample = bsdf.evaluate_sampled(wo, sampler.next_2d(), BXDF_ALL);
+            if !sample.f.is_black() && sample.pdf > 0. as Float {
+                let beta = sample.f * float::safe_div(sample.wi.dot(si.shading_norm).abs(), sample.pdf);
+                let mut ray = si.spawn_ray_differential(sample.wi, Some(&dxy));
+                ray.ray.set_kind(RayKind::indirect(sample.is_specular()));
+                ret += beta * pt::continue_path(
+                    ray, scene, sampler, alloc, 1, sample.is_specular(),
+                    self.max_depth, self.min_depth, self.rr_threshold
+                );
+            }
+        }
+        match self.mode {
+            // the white lambertian reflects the irradiance over pi
+            BakeMode::Irradiance => ret * float::pi(),
+            BakeMode::Radiance => ret,
+        }
+    }
+
+    // bake every covered texel, leaving the others black
+    fn bake(&self, scene: &Scene) -> BoundedSink2D<Option<RGBSpectrumf>> {
+        let bounding = self.owners.bounding();
+        let rows: Vec<isize> = (bounding.pmin.y..bounding.pmax.y).collect();
+        let bake_row = |&y: &isize| {
+            let row = BBox2::new(Point2::new(bounding.pmin.x, y), Point2::new(bounding.pmax.x, y + 1));
+            super::with_thread_allocator(|allocator| {
+                let mut sampler = self.sampler.fork(super::tile_seed(row));
+                row.into_iter().map(|p| {
+                    self.owners.get_pixel(p).map(|i| {
+                        let triangle = TriangleMesh::instance(&self.mesh, i as usize).expect("triangle out of range");
+                        self.bake_texel(p, &triangle, scene, &mut sampler, allocator)
+                    })
+                }).collect::<Vec<_>>()
+            })
+        };
+        let texels: Vec<Vec<Option<RGBSpectrumf>>> = if self.multithreaded {
+            rows.par_iter().map(&bake_row).collect()
+        } else {
+            rows.iter().map(&bake_row).collect()
+        };
+        let mut ret = BoundedSink2D::with_value(None, bounding);
+        for (&y, row) in rows.iter().zip(texels) {
+            for (x, texel) in (bounding.pmin.x..bounding.pmax.x).zip(row) {
+                *ret.get_pixel_mut(Point2::new(x, y)) = texel;
+            }
+        }
+        ret
+    }
+}
+
+impl<S: Sampler> Renderer for BakeRenderer<S> {
+    fn render_image(&mut self, scene: &Scene) -> Image {
+        let mut texels = self.bake(scene);
+        dilate(&mut texels, self.dilation);
+        let bounding = texels.bounding();
+        let mut image = Image::new(RGBSpectrumf::black(), bounding.pmax.cast());
+        for p in bounding {
+            if let Some(texel) = *texels.get_pixel(p) {
+                image[p.cast()] = texel;
+            }
+        }
+        image
+    }
+
+    fn render(&mut self, scene: &Scene) {
+        let render_result = self.render_image(scene);
+        render_result.save(&self.filename).expect("saving failure");
+    }
+}
+
+/// barycentric coordinates of `uv` relative to the texture coordinates
+/// `uvs` of a triangle, clamped onto the triangle
+pub fn clamped_barycentric(uvs: (Point2f, Point2f, Point2f), uv: Point2f) -> Vector3f {
+    let (e1, e2, ep) = (uvs.1 - uvs.0, uvs.2 - uvs.0, uv - uvs.0);
+    let det = e1.x * e2.y - e1.y * e2.x;
+    if det == 0. as Float {
+        let third = 1. as Float / 3. as Float;
+        return Vector3f::new(third, third, third);
+    }
+    let b1 = (ep.x * e2.y - ep.y * e2.x) / det;
+    let b2 = (e1.x * ep.y - e1.y * ep.x) / det;
+    let b = Vector3f::new(
+        (1. as Float - b1 - b2).max(0. as Float), b1.max(0. as Float), b2.max(0. as Float)
+    );
+    b / (b.x + b.y + b.z)
+}
+
+// texels owned by each triangle of `mesh`, conservatively rasterized.
+// Texels whose centers lie within a triangle go to that triangle,
+// others to any triangle overlapping them
+fn rasterize(mesh: &Arc<TriangleMesh>, resolution: Point2<usize>) -> Result<BoundedSink2D<Option<u32>>, BakeError> {
+    let bounding = BBox2::new(Point2::new(0, 0), resolution.cast());
+    // owner of each texel, and whether its center lies within the owner
+    let mut owners: BoundedSink2D<Option<(u32, bool)>> = BoundedSink2D::with_value(None, bounding);
+    let mut overlaps = 0;
+    let scale = Vector2f::new(resolution.x as Float, resolution.y as Float);
+    for triangle in TriangleMesh::instances(mesh) {
+        let index = triangle.index() as u32;
+        let (a, b, c) = triangle.uvs();
+        let (a, b, c) = (
+            Point2f::from_vec(a.to_vec().mul_element_wise(scale)),
+            Point2f::from_vec(b.to_vec().mul_element_wise(scale)),
+            Point2f::from_vec(c.to_vec().mul_element_wise(scale)),
+        );
+        let area = (b - a).perp_dot(c - a);
+        if area == 0. as Float { continue; }
+        // counter-clockwise edges, with the inside on their left
+        let edges = if area > 0. as Float { [(a, b), (b, c), (c, a)] } else { [(a, c), (c, b), (b, a)] };
+        let texels = BBox2::new(
+            Point2::new(a.x.min(b.x).min(c.x).floor() as isize, a.y.min(b.y).min(c.y).floor() as isize),
+            Point2::new(a.x.max(b.x).max(c.x).floor() as isize + 1, a.y.max(b.y).max(c.y).floor() as isize + 1)
+        );
+        for p in texels {
+            if !bounding.contain_lb(p) { continue; }
+            let center = Point2f::new(p.x as Float + 0.5 as Float, p.y as Float + 0.5 as Float);
+            let mut overlapping = true;
+            let mut inside = true;
+            let mut strictly_inside = true;
+            for &(from, to) in &edges {
+                let edge = to - from;
+                let distance = edge.perp_dot(center - from) / edge.magnitude();
+                // distance of the texel's corner farthest inside the edge
+                let reach = 0.5 as Float * (edge.x.abs() + edge.y.abs()) / edge.magnitude();
+                overlapping &= distance + reach > 0. as Float;
+                inside &= distance >= 0. as Float;
+                strictly_inside &= distance > OVERLAP_TOLERANCE;
+            }
+            if !overlapping { continue; }
+            let owner = owners.get_pixel_mut(p);
+            match *owner {
+                Some((_, true)) if inside => {
+                    if strictly_inside { overlaps += 1; }
+                },
+                Some((_, true)) => {},
+                Some((_, false)) if !inside => {},
+                _ => *owner = Some((index, inside)),
+            }
+        }
+    }
+    if overlaps > 0 {
+        return Err(BakeError::OverlappingUVs{texels: overlaps});
+    }
+    let mut ret = BoundedSink2D::with_value(None, bounding);
+    for p in bounding {
+        *ret.get_pixel_mut(p) = owners.get_pixel(p).map(|(i, _)| i);
+    }
+    Ok(ret)
+}
+
+// distance in texels texel centers are taken to be within two
+// triangles beyond, telling overlaps from shared edges
+const OVERLAP_TOLERANCE: Float = 1e-3 as Float;
+
+/// fill the texels around the filled ones of `texels`, `rounds` texels
+/// deep, each with the mean of its filled neighbors
+pub fn dilate(texels: &mut BoundedSink2D<Option<RGBSpectrumf>>, rounds: usize) {
+    let bounding = texels.bounding();
+    for _ in 0..rounds {
+        let mut filled = Vec::new();
+        for p in bounding {
+            if texels.get_pixel(p).is_some() { continue; }
+            let mut sum = RGBSpectrumf::black();
+            let mut count = 0;
+            for dy in -1..2 {
+                for dx in -1..2 {
+                    let q = Point2::new(p.x + dx, p.y + dy);
+                    if !bounding.contain_lb(q) { continue; }
+                    if let Some(texel) = *texels.get_pixel(q) {
+                        sum += texel;
+                        count += 1;
+                    }
+                }
+            }
+            if count > 0 { filled.push((p, sum / count as Float)); }
+        }
+        if filled.is_empty() { break; }
+        for (p, texel) in filled {
+            *texels.get_pixel_mut(p) = Some(texel);
+        }
+    }
+}
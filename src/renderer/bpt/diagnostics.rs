@@ -0,0 +1,151 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Per-pixel path-space statistics for debugging the bidirectional
+//! path tracer's strategies and their weights
+
+use geometry::prelude::*;
+use filming::film::{BoundedSink2D, Image, viridis};
+use spectrum::{RGBSpectrumf, Spectrum};
+use std::path::Path;
+use std::io::Result;
+
+/// Statistics over the strategies contributing to a pixel.
+///
+/// Contributions of light tracing strategies are attributed to
+/// the pixel their camera sample started from, rather than the
+/// pixel they are splatted onto.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PathStats {
+    /// total luminance contributed
+    pub contribution: Float,
+    /// luminance-weighted sum of path depths
+    pub depth: Float,
+    /// luminance contributed by strategies with `s` being 0, 1, and 2 or more
+    pub s_ranges: [Float; 3],
+    /// sum of mis weights of the contributing strategies
+    pub mis_weight: Float,
+    /// number of contributing strategies
+    pub strategies: u32,
+}
+
+impl PathStats {
+    /// record a contribution of `l` by strategy `(s, t)` weighted by
+    /// `mis_weight`, with `s + t >= 2`
+    #[inline]
+    pub fn record(&mut self, s: usize, t: usize, l: &RGBSpectrumf, mis_weight: Float) {
+        let y = l.to_xyz().y;
+        if !(y > 0. as Float) { return; }
+        self.contribution += y;
+        self.depth += y * (s + t - 2) as Float;
+        self.s_ranges[s.min(2)] += y;
+        self.mis_weight += mis_weight;
+        self.strategies += 1;
+    }
+
+    /// accumulate statistics from `other`
+    #[inline]
+    pub fn merge(&mut self, other: &PathStats) {
+        self.contribution += other.contribution;
+        self.depth += other.depth;
+        for (a, b) in self.s_ranges.iter_mut().zip(other.s_ranges.iter()) {
+            *a += *b;
+        }
+        self.mis_weight += other.mis_weight;
+        self.strategies += other.strategies;
+    }
+
+    /// contribution-weighted average path depth
+    #[inline]
+    pub fn average_depth(&self) -> Float {
+        float::safe_div(self.depth, self.contribution)
+    }
+
+    /// fraction of the contribution from strategies with `s`
+    /// in each of the ranges 0, 1, and 2 or more
+    #[inline]
+    pub fn s_fractions(&self) -> [Float; 3] {
+        [
+            float::safe_div(self.s_ranges[0], self.contribution),
+            float::safe_div(self.s_ranges[1], self.contribution),
+            float::safe_div(self.s_ranges[2], self.contribution),
+        ]
+    }
+
+    /// average mis weight of the contributing strategies
+    #[inline]
+    pub fn average_mis_weight(&self) -> Float {
+        float::safe_div(self.mis_weight, self.strategies as Float)
+    }
+}
+
+/// Path-space statistics over the film's crop window
+pub struct Diagnostics {
+    stats: BoundedSink2D<PathStats>,
+    max_depth: usize,
+}
+
+impl Diagnostics {
+    /// construction
+    #[inline]
+    pub fn new(stats: BoundedSink2D<PathStats>, max_depth: usize) -> Diagnostics {
+        Diagnostics{
+            stats: stats,
+            max_depth: max_depth,
+        }
+    }
+
+    /// statistics of pixel `p`
+    #[inline]
+    pub fn get(&self, p: Point2<isize>) -> &PathStats {
+        self.stats.get_pixel(p)
+    }
+
+    /// pixels covered, namely the film's crop window
+    #[inline]
+    pub fn bounding(&self) -> BBox2<isize> {
+        self.stats.bounding()
+    }
+
+    /// false-colored maps of the average depth over the maximum depth,
+    /// the contribution fractions of each range of `s`, and the average
+    /// mis weight, named accordingly. Pixels without any contribution
+    /// are left black.
+    pub fn heat_maps(&self) -> Vec<(&'static str, Image)> {
+        let max_depth = self.max_depth.max(1) as Float;
+        let scalars: [(&'static str, &Fn(&PathStats) -> Float); 5] = [
+            ("depth", &|s| s.average_depth() / max_depth),
+            ("s0", &|s| s.s_fractions()[0]),
+            ("s1", &|s| s.s_fractions()[1]),
+            ("s2", &|s| s.s_fractions()[2]),
+            ("mis", &|s| s.average_mis_weight()),
+        ];
+        let bounding = self.stats.bounding();
+        scalars.iter().map(|&(name, scalar)| {
+            let mut image = Image::new(RGBSpectrumf::black(), bounding.pmax.cast());
+            for p in bounding {
+                let stats = self.stats.get_pixel(p);
+                if stats.strategies > 0 {
+                    let p: Point2<u32> = p.cast();
+                    image[p] = viridis(scalar(stats));
+                }
+            }
+            (name, image)
+        }).collect()
+    }
+
+    /// save the heat maps next to `path`, as `<stem>_<name>.png`
+    pub fn save<P: AsRef<Path> + ?Sized>(&self, path: &P) -> Result<()> {
+        let path = path.as_ref();
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("bpt");
+        for (name, image) in self.heat_maps() {
+            image.save(&path.with_file_name(format!("{}_{}.png", stem, name)))?;
+        }
+        Ok(())
+    }
+}
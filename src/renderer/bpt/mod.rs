@@ -9,19 +9,27 @@
 //! A bidirectional path tracing renderer
 
 use bxdf::*;
-use sample::Sampler;
+use sample::{self, Sampler};
 use filming::Camera;
 use super::Renderer;
 use std::sync::Arc;
+use std::mem;
 use super::scene::Scene;
-use filming::film::FilmTile;
+use filming::film::{FilmTile, Image};
 use spectrum::{RGBSpectrumf, Spectrum};
 use rayon::prelude::*;
-use copy_arena::{Allocator, Arena};
+use aren_alloc::Allocator;
 use geometry::prelude::*;
 use std::path::{PathBuf, Path};
 use self::node::Node;
 use filming::SampleInfo;
+use lighting::LIGHT_DPOS;
+use filming::film::BoundedSink2D;
+use self::diagnostics::{Diagnostics, PathStats};
+
+/// splats a tile buffers before adding them to the film, bounding
+/// the memory taken regardless of the sample count
+const SPLAT_BATCH: usize = 4096;
 
 /// A bidirectional path tracing renderer
 pub struct BPTRenderer<S> {
@@ -29,6 +37,8 @@ pub struct BPTRenderer<S> {
     camera: Arc<Camera>,
     path: PathBuf,
     max_depth: usize,
+    regularization: Option<Float>,
+    diagnostics: bool,
 }
 
 impl<S: Sampler> BPTRenderer<S> {
@@ -40,185 +50,307 @@ impl<S: Sampler> BPTRenderer<S> {
             camera: camera,
             path: path.as_ref().to_path_buf(),
             max_depth: max_depth,
+            regularization: None,
+            diagnostics: false,
         }
     }
-}
 
-impl<S: Sampler> Renderer for BPTRenderer<S> {
-    fn render(&mut self, scene: &Scene) {
-        let mut tiles: Vec<FilmTile<RGBSpectrumf>> = self.camera.get_film().spawn_flat_tiles(16, 16);
-        // for tile in tiles.iter_mut() {
-        tiles.par_iter_mut().for_each(|tile| {
-            let mut arena = Arena::new();
-            let mut sampler = self.sampler.clone();
+    /// set the minimum microfacet roughness of surfaces met after
+    /// the first non-specular bounce of either subpath.
+    /// `None` disables regularization.
+    #[inline]
+    pub fn set_regularization(&mut self, min_alpha: Option<Float>) {
+        self.regularization = min_alpha;
+    }
+
+    /// whether per-pixel path-space statistics are gathered while
+    /// rendering, saved as heat maps next to the output by `render`
+    #[inline]
+    pub fn set_diagnostics(&mut self, diagnostics: bool) {
+        self.diagnostics = diagnostics;
+    }
+
+    /// render a scene into an in-memory image, along with its
+    /// path-space statistics if diagnostics are enabled
+    pub fn render_diagnosed(&mut self, scene: &Scene) -> (Image, Option<Diagnostics>) {
+        let (image, diagnostics, _) = self.render_until(scene, &|| false);
+        (image, diagnostics)
+    }
+
+    /// render a scene into an in-memory image, stopping once `cancelled`
+    /// returns true, as checked before each pixel. Returns the image along
+    /// with the samples completed in each pixel, zero in those left out.
+    ///
+    /// Light paths traced from completed pixels are splatted as in a full
+    /// render, such that completed pixels come out as they would have.
+    pub fn render_cancellable<F>(&mut self, scene: &Scene, cancelled: F) -> (Image, BoundedSink2D<u32>)
+        where F: Fn() -> bool + Sync
+    {
+        let (image, _, counts) = self.render_until(scene, &cancelled);
+        (image, counts)
+    }
+
+    fn render_until<F>(&self, scene: &Scene, cancelled: &F) -> (Image, Option<Diagnostics>, BoundedSink2D<u32>)
+        where F: Fn() -> bool + Sync
+    {
+        let film = self.camera.get_film();
+        // light subpaths reach the camera through the full scene
+        if !self.camera.clip_planes().is_empty() {
+            warn!("clipping planes are ignored by bidirectional path tracing");
+        }
+        let mut tiles: Vec<FilmTile<RGBSpectrumf>> = film.spawn_tiles(film.tile_size());
+        let splats = film.spawn_splats();
+        // fixed sampler dimension slots: film and lens samples followed
+        // by one per camera subpath node, the light subpath's origin
+        // samples followed by one per node, then one per strategy
+        let light_dimension = 2 + self.max_depth + 2;
+        let connect_dimension = light_dimension + 2 + self.max_depth + 1;
+        let diagnostics = self.diagnostics;
+        let tile_stats: Vec<Option<BoundedSink2D<PathStats>>> = tiles.par_iter_mut().map(|tile| super::with_thread_allocator(|allocator| {
             let tile_bound = tile.bounding();
-            for p in tile_bound {
-                let p: Point2<u32> = p.cast();
+            let mut sampler = self.sampler.fork(super::tile_seed(tile_bound));
+            let mut tile_splats = Vec::with_capacity(SPLAT_BATCH);
+            let mut stats = if diagnostics {
+                Some(BoundedSink2D::with_value(PathStats::default(), tile_bound))
+            } else {
+                None
+            };
+            for pidx in tile_bound {
+                if cancelled() { break; }
+                let p: Point2<u32> = pidx.cast();
                 sampler.start_pixel(p);
                 loop {
-                    let mut allocator = arena.allocator();
-                    let pfilm = sampler.next_2d() + p.to_vec().cast();
-                    // let pfilm = p.cast() + caminfo.pfilm;
-                    let mut cam_nodes = allocator.alloc_slice_default(self.max_depth + 2);
-                    let mut light_nodes = allocator.alloc_slice_default(self.max_depth + 1);
-                    let ncam = generate_camera_subpath(
-                        scene, &mut sampler, &mut allocator, &*self.camera, pfilm, cam_nodes
+                    let pfilm = sample::pixel_position(p, sampler.next_2d());
+                    let mut cam_nodes = Vec::with_capacity(self.max_depth + 2);
+                    let mut light_nodes = Vec::with_capacity(self.max_depth + 1);
+                    generate_camera_subpath(
+                        scene, &mut sampler, allocator, &*self.camera, tile.jitter(pfilm), self.max_depth + 2,
+                        self.regularization, &mut cam_nodes
                     );
-                    let nlight = generate_light_subpath(
-                        scene, &mut sampler, &mut allocator, light_nodes
+                    sampler.set_dimension(light_dimension);
+                    generate_light_subpath(
+                        scene, &mut sampler, allocator, self.max_depth + 1,
+                        self.regularization, &mut light_nodes
                     );
+                    let ncam = cam_nodes.len();
+                    let nlight = light_nodes.len();
                     let mut l = RGBSpectrumf::black();
-                    for t in 1..ncam {
-                        for s in 0..nlight {
+                    for t in 1..ncam+1 {
+                        for s in 0..nlight+1 {
                             let depth = t as isize + s as isize - 2isize;
                             if (s==1 && t==1) || depth < 0 || depth>self.max_depth as isize {
                                 continue;
                             }
+                            sampler.set_dimension(connect_dimension + t * (self.max_depth + 2) + s);
                             let mut pfilm_new = pfilm;
                             let mut mis_weight = 0. as Float;
                             let lpath = connect(
-                                scene, &mut cam_nodes[0..t], 
-                                &mut light_nodes[0..s], &*self.camera, 
+                                scene, &mut cam_nodes[0..t],
+                                &mut light_nodes[0..s], &*self.camera,
                                 &mut sampler, &mut pfilm_new, &mut mis_weight
                             );
-                            // TODO: visualize strategies
+                            if !lpath.valid() { continue; }
+                            if let Some(ref mut stats) = stats {
+                                stats.get_pixel_mut(pidx).record(s, t, &lpath, mis_weight);
+                            }
                             if t!=1 {l+=lpath;}
-                            else {tile.add_sample(pfilm_new, &lpath)};
+                            else if !lpath.is_black() {tile_splats.push((pfilm_new, lpath));}
                         }
                     }
-                    tile.add_sample(pfilm, &l);
+                    // the camera ray hit nothing if its subpath ends at the camera
+                    let alpha = if ncam > 1 { 1. as Float } else { 0. as Float };
+                    tile.add_sample_with_alpha(pfilm, &l, alpha);
+                    if tile_splats.len() >= SPLAT_BATCH {
+                        splats.add_splats(&tile_splats);
+                        tile_splats.clear();
+                    }
                     if !sampler.next_sample() { break; }
                 }
             }
-        });
-        // }
-        let render_result = self.camera.get_film().collect_into(tiles);
-        render_result.save(self.path.clone()).expect("saving failure");
+            splats.add_splats(&tile_splats);
+            stats
+        })).collect();
+        let diagnostics = if diagnostics && !tile_stats.is_empty() {
+            let tile_stats: Vec<_> = tile_stats.into_iter().filter_map(|stats| stats).collect();
+            let bounding = tile_stats.iter().skip(1).fold(
+                tile_stats[0].bounding(), |b, stats| b.union(&stats.bounding())
+            );
+            let mut merged = BoundedSink2D::with_value(PathStats::default(), bounding);
+            for stats in &tile_stats {
+                for p in stats.bounding() {
+                    merged.get_pixel_mut(p).merge(stats.get_pixel(p));
+                }
+            }
+            Some(Diagnostics::new(merged, self.max_depth))
+        } else {
+            None
+        };
+        let counts = film.sample_counts(&tiles);
+        let scale = film.splat_scale(&tiles);
+        (film.collect_with_splats(tiles, splats, scale), diagnostics, counts)
+    }
+}
+
+impl<S: Sampler> Renderer for BPTRenderer<S> {
+    #[inline]
+    fn render_image(&mut self, scene: &Scene) -> Image {
+        self.render_diagnosed(scene).0
+    }
+
+    fn render(&mut self, scene: &Scene) {
+        let (render_result, diagnostics) = self.render_diagnosed(scene);
+        render_result.save(&self.path).expect("saving failure");
+        if let Some(diagnostics) = diagnostics {
+            diagnostics.save(&self.path).expect("saving failure");
+        }
     }
 }
 
 fn generate_camera_subpath<'a, S: Sampler>(
-    scene: &'a Scene, sampler: &mut S, 
-    allocator: &mut Allocator<'a>,
-    camera: &'a Camera, pfilm: Point2f, path: &mut [Node<'a>]
-) -> usize {
-    if path.len() == 0 { return 0; }
+    scene: &'a Scene, sampler: &mut S,
+    allocator: &'a Allocator,
+    camera: &'a Camera, pfilm: Point2f,
+    max_nodes: usize, regularization: Option<Float>,
+    path: &mut Vec<Node<'a>>
+) {
+    if max_nodes == 0 { return; }
     let plens = sampler.next_2d();
+    let time = match camera.shutter() {
+        Some((open, close)) => open + (close - open) * sampler.next(),
+        None => 0. as Float,
+    };
     let sampleinfo = SampleInfo{
-        pfilm: pfilm, plens: plens,
+        pfilm: pfilm, plens: plens, time: time,
     };
     let mut ray_differential = camera.generate_path_differential(sampleinfo);
     ray_differential.scale_differentials(1.0 as Float / sampler.sample_per_pixel() as Float);
-    // TODO: double check ray direction
     let (pdfpos, pdfdir) = camera.pdf(
         ray_differential.ray.origin(), ray_differential.ray.direction()
     );
     let beta = RGBSpectrumf::new(1. as Float, 1. as Float, 1. as Float);
-    path[0] = Node::Camera{
+    path.push(Node::Camera{
         camera: camera,
         info: InteractInfo{
             pos: ray_differential.ray.origin(),
+            pos_err: Vector3f::zero(),
             wo: Vector3f::zero(),
             norm: Vector3f::zero(),
         },
         beta: beta,
         pdf: pdfpos,
-        pdf_reversed: 1. as Float,
-    };
-    random_walk(scene, ray_differential, sampler, allocator, beta, pdfdir, TransportMode::Radiance, path) + 1
+        pdf_reversed: 0. as Float,
+    });
+    random_walk(scene, ray_differential, sampler, allocator, beta, pdfdir, TransportMode::Radiance, max_nodes, regularization, path);
 }
 
 fn generate_light_subpath<'a, S: Sampler>(
-    scene: &'a Scene, sampler: &mut S, 
-    allocator: &mut Allocator<'a>, path: &mut [Node<'a>]
-) -> usize {
-    if path.len() == 0 { return 0; }
+    scene: &'a Scene, sampler: &mut S,
+    allocator: &'a Allocator, max_nodes: usize,
+    regularization: Option<Float>, path: &mut Vec<Node<'a>>
+) {
+    if max_nodes == 0 || scene.lights.is_empty() { return; }
     let (light_index, light_pdf, _) = scene.light_distribution.sample_discrete(sampler.next());
     let light = scene.get_light(light_index);
-    // TODO
     let pathinfo = light.generate_path(sampler.get_light_sample());
     if pathinfo.pdfpos == 0. as Float || pathinfo.pdfdir == 0. as Float || pathinfo.radiance.is_black() {
-        return 0;
+        return;
     }
-    path[0] = Node::Light{
+    // lights with delta positions have no surface to speak of
+    let norm = if light.flags().intersects(LIGHT_DPOS) {
+        Vector3f::zero()
+    } else {
+        pathinfo.normal
+    };
+    path.push(Node::Light{
         light: light,
         info: InteractInfo{
-            // TODO: double check
             pos: pathinfo.ray.origin(),
+            pos_err: Vector3f::zero(),
             wo: pathinfo.ray.direction(),
-            norm: Vector3f::zero(),
+            norm: norm,
         },
-        beta: pathinfo.radiance,
+        beta: pathinfo.radiance * float::safe_div(1. as Float, light_pdf * pathinfo.pdfpos),
         pdf: pathinfo.pdfpos * light_pdf,
-        pdf_reversed: 1. as Float,
-    };
-    let beta = pathinfo.radiance * pathinfo.ray.direction().dot(pathinfo.normal).abs() / (light_pdf * pathinfo.pdfpos * pathinfo.pdfdir);
+        pdf_reversed: 0. as Float,
+    });
+    let beta = pathinfo.radiance * float::safe_div(
+        pathinfo.ray.direction().dot(pathinfo.normal).abs(),
+        light_pdf * pathinfo.pdfpos * pathinfo.pdfdir
+    );
     // TODO: handle infinite lights
-    random_walk(scene, pathinfo.ray.into(), sampler, allocator, beta, pathinfo.pdfdir, TransportMode::Importance, path) + 1
+    let ray = pathinfo.ray.with_kind(RayKind::DiffuseIndirect);
+    random_walk(scene, ray.into(), sampler, allocator, beta, pathinfo.pdfdir, TransportMode::Importance, max_nodes, regularization, path);
 }
 
 fn random_walk<'a, S: Sampler>(
     scene: &'a Scene, mut ray_differential: RayDifferential,
-    sampler: &mut S, allocator: &mut Allocator<'a>,
+    sampler: &mut S, allocator: &'a Allocator,
     mut beta: RGBSpectrumf, mut pdf: Float, mode: TransportMode,
-    path: &mut [Node<'a>]
-) -> usize {
-    if path.len() == 1 { return 0; }
-    let mut pdfrev = 0. as Float;
-    let mut bounces = 1usize;
-    // let pathptr = path.as_mut_ptr();
-    loop {
-        // let (node, prev) = unsafe {
-        //     (pathptr.offset(bounces).as_mut().unwrap(),
-        //     pathptr.offset(bounces-1).as_mut().unwrap())
-        // };
+    max_nodes: usize, regularization: Option<Float>, path: &mut Vec<Node<'a>>
+) {
+    let mut rough_bounce = false;
+    while path.len() < max_nodes {
         // TODO: handle medium
-        if let Some(mut si) = scene.aggregate.intersect_ray(&mut ray_differential.ray) {
+        let mut si = if let Some(si) = scene.aggregate.intersect_ray(&mut ray_differential.ray) {
+            si
+        } else {
             // TODO: handle infinite lights
-            if let Some(primitive) = si.primitive_hit {
-                let dxy = si.compute_dxy(&ray_differential);
-                // FIXME: accounting for transport modes
-                let bsdf = primitive.get_material().compute_scattering(
-                    &mut si, &dxy, allocator
-                );
-                let bsdf = allocator.alloc(bsdf);
-                path[bounces] = Node::Surface{
-                    // TODO: check if this is valid
-                    // bsdf: unsafe {(&bsdf as *const _).as_ref().unwrap()},
-                    bsdf: bsdf,
-                    si: si,
-                    beta: beta,
-                    pdf: pdf,
-                    pdf_reversed: 1. as Float,
-                };
-                let pdf_converted = path[bounces-1].convert_density(&path[bounces], pdf);
-                *path[bounces].get_pdf_mut() = pdf_converted;
-                bounces += 1;
-                if bounces as usize >= path.len() { break; }
-                let wo = path[bounces].wo();
-                let (f, wi, pdffwd, _) = if mode == TransportMode::Radiance {
-                    bsdf.evaluate_sampled(wo, sampler.next_2d(), BXDF_ALL)
-                } else {
-                    bsdf.evaluate_importance_sampled(wo, sampler.next_2d(), BXDF_ALL)
-                };
-                if f.is_black() || pdffwd == 0. as Float { break; }
-                pdf = pdffwd;
-                beta *= f * wi.dot(si.shading_norm).abs() / pdf;
-                pdfrev = bsdf.pdf(wi, wo, BXDF_ALL);
-                // FIXME: delta
-                beta *= correct_shading_normal(&si, wo, wi, mode);
-                // FIXME: spawn ray differential
-                ray_differential = RawRay::from_od(si.basic.pos, wi).into();
-            } else {
-                break;
-            }
+            break;
+        };
+        let primitive = if let Some(primitive) = si.primitive_hit {
+            primitive
         } else {
             break;
+        };
+        let dxy = si.compute_dxy(&ray_differential);
+        let mut bsdf = primitive.get_material().compute_scattering(
+            &mut si, &dxy, allocator
+        );
+        if let Some(min_alpha) = regularization {
+            if rough_bounce { bsdf.regularize(min_alpha, allocator); }
         }
-        let pdf_converted = path[bounces-1].convert_density(&path[bounces-2], pdfrev);
-        *path[bounces-2].get_pdf_rev_mut() = pdf_converted;
+        let mut node = Node::Surface{
+            bsdf: bsdf,
+            si: si,
+            beta: beta,
+            pdf: 0. as Float,
+            pdf_reversed: 0. as Float,
+            delta: false,
+        };
+        *node.get_pdf_mut() = path.last().unwrap().convert_density(&node, pdf);
+        path.push(node);
+        if path.len() >= max_nodes { break; }
+
+        let bounces = path.len() - 1;
+        let wo = si.basic.wo;
+        let (f, wi, pdffwd, bt, pdfrev) = if let Node::Surface{ref bsdf, ..} = path[bounces] {
+            let sample = if mode == TransportMode::Radiance {
+                bsdf.evaluate_sampled(wo, sampler.next_2d(), BXDF_ALL)
+            } else {
+                bsdf.evaluate_importance_sampled(wo, sampler.next_2d(), BXDF_ALL)
+            };
+            (sample.f, sample.wi, sample.pdf, sample.kind, bsdf.pdf(sample.wi, wo, BXDF_ALL))
+        } else {
+            unreachable!();
+        };
+        if f.is_black() || pdffwd == 0. as Float { break; }
+        beta *= f * float::safe_div(wi.dot(si.shading_norm).abs(), pdffwd);
+        beta *= correct_shading_normal(&si, wo, wi, mode);
+        if !beta.valid() { break; }
+        let (pdffwd, pdfrev) = if bt.intersects(BXDF_SPECULAR) {
+            path[bounces].set_delta(true);
+            (0. as Float, 0. as Float)
+        } else {
+            rough_bounce = true;
+            (pdffwd, pdfrev)
+        };
+        pdf = pdffwd;
+        let pdf_converted = path[bounces].convert_density(&path[bounces-1], pdfrev);
+        *path[bounces-1].get_pdf_rev_mut() = pdf_converted;
+        // FIXME: spawn ray differential
+        ray_differential = si.spawn_ray_differential(wi, None);
+        ray_differential.ray.set_kind(RayKind::indirect(bt.intersects(BXDF_SPECULAR)));
     }
-    bounces as usize
 }
 
 #[inline]
@@ -226,33 +358,32 @@ fn correct_shading_normal(si: &SurfaceInteraction, wo: Vector3f, wi: Vector3f, m
     if mode == TransportMode::Importance {
         let num = (wo.dot(si.shading_norm) * wi.dot(si.basic.norm)).abs();
         let denom = (wo.dot(si.basic.norm) * wi.dot(si.shading_norm)).abs();
-        if denom == 0. as Float { 0. as Float }
-        else { num/denom }
+        float::safe_div(num, denom)
     } else { 1. as Float }
 }
 
-fn connect<S: Sampler>(
-    scene: &Scene, cam_nodes: &mut [Node],
-    light_nodes: &mut [Node], camera: &Camera,
-    sampler: &mut S, praster: &mut Point2f, 
+fn connect<'a, S: Sampler>(
+    scene: &'a Scene, cam_nodes: &mut [Node<'a>],
+    light_nodes: &mut [Node<'a>], camera: &'a Camera,
+    sampler: &mut S, praster: &mut Point2f,
     mis_weight: &mut Float
 ) -> RGBSpectrumf {
     let mut ret = RGBSpectrumf::black();
     let t = cam_nodes.len();
     let s = light_nodes.len();
-    if t > 1 
-        && s != 0 
+    if t > 1
+        && s != 0
         && cam_nodes.last().unwrap().is_light_node() {
         // invalid connection strategy
         return ret;
     }
 
-    let mut sampled;
+    let mut sampled = None;
     if s == 0 {
-        // no lights
-        let pt = cam_nodes.last().unwrap();
-        if let Some(light) = pt.as_light() {
-            // TODO: handle infinite light sources
+        // camera subpath hitting a light
+        let pt = &cam_nodes[t-1];
+        if pt.is_light() {
+            ret = pt.le(&cam_nodes[t-2]) * pt.get_beta();
         }
     } else if t == 1 {
         // sample a point on camera, connecting to light subpath
@@ -263,21 +394,29 @@ fn connect<S: Sampler>(
             );
             *praster = pr;
             if !importance_sample.no_effect() {
-                sampled = Node::Camera{
+                // sampled wrt solid angle at `qs`, like light samples
+                // at `pt` below
+                let (pdfpos, _) = camera.pdf(importance_sample.pfrom, -importance_sample.wi());
+                let node = Node::Camera{
                     camera: camera,
                     info: InteractInfo{
                         pos: importance_sample.pfrom,
+                        pos_err: Vector3f::zero(),
                         wo: Vector3f::zero(),
                         norm: Vector3f::zero(),
                     },
-                    beta: importance_sample.radiance/importance_sample.pdf,
-                    pdf: 1. as Float,
+                    beta: importance_sample.radiance * float::safe_div(1. as Float, importance_sample.pdf),
+                    pdf: pdfpos,
                     pdf_reversed: 0. as Float,
                 };
-                let l = qs.get_beta() * qs.evaluate(&sampled, TransportMode::Importance) * sampled.get_beta();
-                if qs.on_surface() && !l.is_black() && !importance_sample.occluded(&*scene.aggregate) {
-                    ret = l * importance_sample.wi().dot(qs.shading_norm()).abs();
+                let mut l = qs.get_beta() * qs.evaluate(&node, TransportMode::Importance) * node.get_beta();
+                if qs.on_surface() {
+                    l *= importance_sample.wi().dot(qs.shading_norm()).abs();
+                }
+                if !l.is_black() && !importance_sample.occluded(&*scene.aggregate) {
+                    ret = l;
                 }
+                sampled = Some(node);
             }
         }
     } else if s == 1 {
@@ -288,24 +427,28 @@ fn connect<S: Sampler>(
             let light = scene.get_light(lightidx);
             let lightsample = light.evaluate_sampled(pt.pos(), sampler.next_2d());
             if !lightsample.no_effect() {
-                sampled = Node::Light{
+                let mut node = Node::Light{
                     light: light,
                     info: InteractInfo{
-                        // TODO: double check
                         pos: lightsample.pfrom,
+                        pos_err: Vector3f::zero(),
                         wo: Vector3f::zero(),
-                        norm: Vector3f::zero(),
+                        norm: lightsample.normal,
                     },
-                    beta: lightsample.radiance / (lightsample.pdf * lightpdf),
+                    beta: lightsample.radiance * float::safe_div(1. as Float, lightsample.pdf * lightpdf),
                     pdf: 0. as Float,
                     pdf_reversed: 0. as Float,
                 };
-                let pdffwd = sampled.pdf_light_origin(scene, pt);
-                *sampled.get_pdf_mut() = pdffwd;
-                let l = pt.get_beta() * pt.evaluate(&sampled, TransportMode::Radiance) * sampled.get_beta();
-                if pt.on_surface() && !l.is_black() && !lightsample.occluded(&*scene.aggregate) {
-                    ret = l * lightsample.wi().dot(pt.shading_norm()).abs();
+                let pdffwd = node.pdf_light_origin(scene, pt);
+                *node.get_pdf_mut() = pdffwd;
+                let mut l = pt.get_beta() * pt.evaluate(&node, TransportMode::Radiance) * node.get_beta();
+                if pt.on_surface() {
+                    l *= lightsample.wi().dot(pt.shading_norm()).abs();
+                }
+                if !l.is_black() && !lightsample.occluded(&*scene.aggregate) {
+                    ret = l;
                 }
+                sampled = Some(node);
             }
         }
     } else {
@@ -322,65 +465,140 @@ fn connect<S: Sampler>(
     *mis_weight = if ret.is_black() {
         0. as Float
     } else {
-        cal_mis_weight(scene, cam_nodes, light_nodes)
+        cal_mis_weight(scene, cam_nodes, light_nodes, sampled)
     };
     ret * (*mis_weight)
 }
 
-fn g<S: Sampler>(scene: &Scene, sampler: &mut S, v0: &Node, v1: &Node) -> RGBSpectrumf {
-    let d = v0.pos() - v1.pos();
-    let mut g = 1. as Float / d.magnitude2();
-    let d = d * g.sqrt();
-    if v0.on_surface() { g *= v0.shading_norm().dot(d).abs(); }
-    if v1.on_surface() { g *= v1.shading_norm().dot(d).abs(); }
-    let ray = RawRay::from_od(v1.pos(), d);
-    let epsilon = Point3f::default_epsilon();
-    let epsilon = Vector3f::new(epsilon, epsilon, epsilon);
-    let pfrom = v0.pos() + epsilon;
-    let mut ray = RawRay::spawn(pfrom, v1.pos());
-    let unoccluded = if let Some(si) = scene.aggregate.intersect_ray(&mut ray) {
-        relative_eq!(si.basic.pos, v1.pos())
+fn g<S: Sampler>(scene: &Scene, _sampler: &mut S, v0: &Node, v1: &Node) -> RGBSpectrumf {
+    let g = geometry_term(v0.pos(), v0.shading_norm(), v1.pos(), v1.shading_norm());
+    if g == 0. as Float { return RGBSpectrumf::black(); }
+    let ray = v0.info().spawn_to(&v1.info());
+    if scene.aggregate.can_intersect(&ray) {
+        RGBSpectrumf::black()
     } else {
-        true
-    };
-    if unoccluded {
-        // TODO: double check
         RGBSpectrumf::new(g, g, g)
-    } else {
-        RGBSpectrumf::black()
     }
 }
 
-fn cal_mis_weight(
-    scene: &Scene, cam_nodes: &[Node],
-    light_nodes: &[Node]
+/// geometry term between `p0` and `p1`, with normals `n0` and `n1`.
+/// A zero normal marks a point off any surface, contributing no cosine.
+fn geometry_term(p0: Point3f, n0: Vector3f, p1: Point3f, n1: Vector3f) -> Float {
+    let d = p1 - p0;
+    let mut g = float::safe_div(1. as Float, d.magnitude2());
+    if g == 0. as Float { return 0. as Float; }
+    let d = d * g.sqrt();
+    if n0 != Vector3f::zero() { g *= n0.dot(d).abs(); }
+    if n1 != Vector3f::zero() { g *= n1.dot(d).abs(); }
+    g
+}
+
+/// compute the MIS weight of the path made up by `cam_nodes` and
+/// `light_nodes`, with the connecting endpoint replaced by `sampled`
+/// for single-endpoint strategies
+fn cal_mis_weight<'a>(
+    scene: &Scene, cam_nodes: &mut [Node<'a>],
+    light_nodes: &mut [Node<'a>], sampled: Option<Node<'a>>
 ) -> Float {
-    let t = cam_nodes.len() as usize;
-    let s = light_nodes.len() as usize;
+    let t = cam_nodes.len();
+    let s = light_nodes.len();
     if s + t == 2 {return 1. as Float; }
-    let mut sum_ri = 0. as Float;
-    let remap0 = |f| {
-        if f == 0. as Float {
+
+    // temporarily swap in the sampled endpoint
+    let mut sampled = sampled;
+    if let Some(ref mut node) = sampled {
+        if s == 1 {
+            mem::swap(&mut light_nodes[0], node);
+        } else if t == 1 {
+            mem::swap(&mut cam_nodes[0], node);
+        }
+    }
+
+    // update reversed pdfs of the endpoints and their predecessors,
+    // remembering the originals
+    let saved_delta = (
+        cam_nodes[t-1].is_delta(),
+        if s > 0 { light_nodes[s-1].is_delta() } else { false },
+    );
+    let saved_pdfs = (
+        cam_nodes[t-1].get_pdf_rev(),
+        if t > 1 { cam_nodes[t-2].get_pdf_rev() } else { 0. as Float },
+        if s > 0 { light_nodes[s-1].get_pdf_rev() } else { 0. as Float },
+        if s > 1 { light_nodes[s-2].get_pdf_rev() } else { 0. as Float },
+    );
+    {
+        let pt = &cam_nodes[t-1];
+        let pt_minus = if t > 1 { Some(&cam_nodes[t-2]) } else { None };
+        let qs = if s > 0 { Some(&light_nodes[s-1]) } else { None };
+        let qs_minus = if s > 1 { Some(&light_nodes[s-2]) } else { None };
+        let pt_rev = if let Some(qs) = qs {
+            qs.pdf(scene, qs_minus, pt)
+        } else {
+            pt_minus.map_or(0. as Float, |ptm| pt.pdf_light_origin(scene, ptm))
+        };
+        let pt_minus_rev = pt_minus.map(|ptm| if let Some(qs) = qs {
+            pt.pdf(scene, Some(qs), ptm)
+        } else {
+            pt.pdf_light(scene, ptm)
+        });
+        let qs_rev = qs.map(|qs| pt.pdf(scene, pt_minus, qs));
+        let qs_minus_rev = qs_minus.map(|qsm| qs.unwrap().pdf(scene, Some(pt), qsm));
+
+        *cam_nodes[t-1].get_pdf_rev_mut() = pt_rev;
+        if let Some(v) = pt_minus_rev { *cam_nodes[t-2].get_pdf_rev_mut() = v; }
+        if let Some(v) = qs_rev { *light_nodes[s-1].get_pdf_rev_mut() = v; }
+        if let Some(v) = qs_minus_rev { *light_nodes[s-2].get_pdf_rev_mut() = v; }
+    }
+    cam_nodes[t-1].set_delta(false);
+    if s > 0 { light_nodes[s-1].set_delta(false); }
+
+    // non-finite densities would poison every weight along the path
+    let remap0 = |f: Float| {
+        if f == 0. as Float || !f.is_finite() {
             1. as Float
         } else {
             f
         }
     };
+    let mut sum_ri = 0. as Float;
     let mut ri = 1. as Float;
-    for i in 1..t {
-        let pdfrev = cam_nodes[t-i-1].get_pdf_rev();
-        let pdffwd = cam_nodes[t-i-1].get_pdf();
-        ri *= remap0(pdfrev)/remap0(pdffwd);
-        sum_ri += ri;
+    for i in (1..t).rev() {
+        ri *= float::safe_div(remap0(cam_nodes[i].get_pdf_rev()), remap0(cam_nodes[i].get_pdf()));
+        if !cam_nodes[i].is_delta() && !cam_nodes[i-1].is_delta() {
+            sum_ri += ri;
+        }
     }
     ri = 1. as Float;
-    for i in 0..s {
-        let pdfrev = light_nodes[s-i-1].get_pdf_rev();
-        let pdffwd = light_nodes[s-i-1].get_pdf();
-        ri *= remap0(pdfrev)/remap0(pdffwd);
-        sum_ri += ri;
+    for i in (0..s).rev() {
+        ri *= float::safe_div(remap0(light_nodes[i].get_pdf_rev()), remap0(light_nodes[i].get_pdf()));
+        let delta_light = if i > 0 {
+            light_nodes[i-1].is_delta()
+        } else {
+            light_nodes[0].is_delta_light()
+        };
+        if !light_nodes[i].is_delta() && !delta_light {
+            sum_ri += ri;
+        }
+    }
+
+    // restore
+    cam_nodes[t-1].set_delta(saved_delta.0);
+    *cam_nodes[t-1].get_pdf_rev_mut() = saved_pdfs.0;
+    if t > 1 { *cam_nodes[t-2].get_pdf_rev_mut() = saved_pdfs.1; }
+    if s > 0 {
+        light_nodes[s-1].set_delta(saved_delta.1);
+        *light_nodes[s-1].get_pdf_rev_mut() = saved_pdfs.2;
+    }
+    if s > 1 { *light_nodes[s-2].get_pdf_rev_mut() = saved_pdfs.3; }
+    if let Some(ref mut node) = sampled {
+        if s == 1 {
+            mem::swap(&mut light_nodes[0], node);
+        } else if t == 1 {
+            mem::swap(&mut cam_nodes[0], node);
+        }
     }
-    1. as Float / (1. as Float + sum_ri)
+
+    float::safe_div(1. as Float, 1. as Float + sum_ri)
 }
 
 #[derive(Copy, Clone, PartialEq)]
@@ -390,3 +608,7 @@ enum TransportMode {
 }
 
 mod node;
+pub mod diagnostics;
+
+#[cfg(test)]
+mod tests;
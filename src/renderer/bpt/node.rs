@@ -16,12 +16,28 @@ use lighting::prelude::*;
 use material::prelude::*;
 use spectrum::{Spectrum, RGBSpectrumf};
 use bxdf::prelude::*;
-use renderer::scene::Scene;
-use std::ptr;
-use std::mem;
+use renderer::scene::{Scene, same_light};
 use super::TransportMode;
 
-#[derive(Copy, Clone)]
+/// A node on a light or camera subpath.
+///
+/// `pdf` is the area density of sampling this node from the
+/// preceding one along the subpath, `pdf_reversed` the area density
+/// of sampling it from the succeeding one. Directions are sampled wrt
+/// solid angle, with `convert_density` taking their densities into
+/// area densities at the nodes they hit.
+///
+/// Endpoints follow the same convention: the `pdf` of a camera node is
+/// the area density of its position on the lens, that of a light node
+/// the area density of its position on the light times the probability
+/// of choosing the light. Delta positions, like those of pinholes and
+/// point lights, count as a density of 1.
+///
+/// `beta` is the throughput of the subpath up to and including this
+/// node. For endpoints sampled to connect to the other subpath, that is
+/// their emission divided by the solid angle density of sampling them
+/// from the node they connect to, times the probability of choosing
+/// them, if any.
 pub enum Node<'a> {
     Camera{
         camera: &'a Camera,
@@ -38,11 +54,13 @@ pub enum Node<'a> {
         pdf_reversed: Float,
     },
     Surface{
-        bsdf: &'a Bsdf<'a>,
+        bsdf: Bsdf<'a>,
         si: SurfaceInteraction<'a>,
         beta: RGBSpectrumf,
         pdf: Float,
         pdf_reversed: Float,
+        /// if the node was scattered by a specular component
+        delta: bool,
     },
     Medium{
         info: InteractInfo,
@@ -93,12 +111,12 @@ impl<'a> Node<'a> {
         self.info().wo
     }
 
+    /// evaluate the scattering function at this node towards `next`
     #[inline]
     pub fn evaluate(&self, next: &Node, mode: TransportMode) -> RGBSpectrumf {
-
         let wi = (next.pos() - self.pos()).normalize();
         match *self {
-            Node::Surface{bsdf, ref si, ..} => {
+            Node::Surface{ref bsdf, ref si, ..} => {
                 if mode == TransportMode::Radiance {
                     bsdf.evaluate(si.basic.wo, wi, BXDF_ALL).0
                 } else {
@@ -109,11 +127,12 @@ impl<'a> Node<'a> {
         }
     }
 
+    /// test if a deterministic connection can be made to this node
     #[inline]
     pub fn is_connectible(&self) -> bool {
         match *self {
-            Node::Light{light, ..} => light.flags().intersects(LIGHT_DDIR),
-            Node::Surface{bsdf, ..} => bsdf.have_n(BXDF_DIFFUSE|BXDF_GLOSSY|BXDF_REFLECTION|BXDF_TRANSMISSION) > 0,
+            Node::Light{light, ..} => !light.flags().intersects(LIGHT_DDIR),
+            Node::Surface{ref bsdf, ..} => bsdf.have_n(BXDF_DIFFUSE|BXDF_GLOSSY) > 0,
             _ => true,
         }
     }
@@ -143,88 +162,110 @@ impl<'a> Node<'a> {
         }
     }
 
+    #[inline]
+    pub fn is_delta(&self) -> bool {
+        match *self {
+            Node::Surface{delta, ..} => delta,
+            _ => false,
+        }
+    }
+
+    #[inline]
+    pub fn set_delta(&mut self, value: bool) {
+        if let Node::Surface{ref mut delta, ..} = *self {
+            *delta = value;
+        }
+    }
+
+    /// emitted radiance from this node towards `prev`
+    #[inline]
+    pub fn le(&self, prev: &Node) -> RGBSpectrumf {
+        match *self {
+            Node::Surface{ref si, ..} => {
+                let w = (prev.pos() - self.pos()).normalize();
+                si.le(w)
+            },
+            _ => RGBSpectrumf::black(),
+        }
+    }
+
+    /// find the light in `scene` this node lies on, with its index
+    pub fn scene_light<'b>(&self, scene: &'b Scene) -> Option<(usize, &'b Light)> {
+        match *self {
+            Node::Light{light, ..} => {
+                scene.lights.iter().position(|l| same_light(l.as_ref(), light))
+                    .map(|i| (i, scene.get_light(i)))
+            },
+            Node::Surface{ref si, ..} => {
+                let primitive = si.primitive_hit?;
+                if !primitive.is_emissive() { return None; }
+                scene.lights.iter().position(|l| {
+                    same_light(l.as_ref(), primitive.as_light()) || l.emits_from(primitive)
+                }).map(|i| (i, scene.get_light(i)))
+            },
+            _ => None,
+        }
+    }
+
+    /// convert a solid angle `pdf` of sampling `next` from this node
+    /// into area density at `next`
     #[inline]
     pub fn convert_density(&self, next: &Node, mut pdf: Float) -> Float {
         // TODO: account for infinite area lights
         let wi = next.pos() - self.pos();
-        let invdist2 = 1. as Float / wi.magnitude2();
-        let norm = next.norm();
-        if norm != Vector3f::new(0. as Float, 0. as Float, 0. as Float) {
-            pdf *= norm.dot(wi*invdist2.sqrt()).abs();
+        let invdist2 = float::safe_div(1. as Float, wi.magnitude2());
+        if invdist2 == 0. as Float { return 0. as Float; }
+        if next.on_surface() {
+            pdf *= next.norm().dot(wi*invdist2.sqrt()).abs();
         }
-        pdf * invdist2
+        float::safe_div(pdf, wi.magnitude2())
     }
 
-    #[inline]
-    pub fn pdf(&self, prev: Option<&Node>, next: &Node) -> Float {
+    /// area density of sampling `next` from this node, given `prev`
+    pub fn pdf(&self, scene: &Scene, prev: Option<&Node>, next: &Node) -> Float {
+        if self.is_light_node() {
+            return self.pdf_light(scene, next);
+        }
         let wp = if let Some(prev) = prev {
             (prev.pos() - self.pos()).normalize()
         } else {
-            Vector3f::new(0. as Float, 0. as Float, 0. as Float)
+            Vector3f::zero()
         };
         let wn = (next.pos() - self.pos()).normalize();
         let pdf = match *self {
-            Node::Light{light, ref info, ..} => light.pdf(info.pos, wn, info.norm).1,
             Node::Camera{camera, ref info, ..} => camera.pdf(info.pos, wn).1,
-            Node::Surface{bsdf, ..} => bsdf.pdf(wp, wn, BXDF_ALL),
+            Node::Surface{ref bsdf, ..} => bsdf.pdf(wp, wn, BXDF_ALL),
             _ => unimplemented!(),
         };
         self.convert_density(next, pdf)
     }
 
-    pub fn pdf_light(&self, next: &Node) -> Float {
+    /// area density of a light path leaving this node to hit `next`
+    pub fn pdf_light(&self, scene: &Scene, next: &Node) -> Float {
         let wi = next.pos() - self.pos();
-        let invdist2 = 1. as Float / wi.magnitude2();
+        let invdist2 = float::safe_div(1. as Float, wi.magnitude2());
+        if invdist2 == 0. as Float { return 0. as Float; }
         let wn = wi*invdist2.sqrt();
-        let mut pdf = match *self {
-            Node::Light{light, ref info, ..} => light.pdf(info.pos, wn, info.norm).1,
-            Node::Surface{ref si, ..} => {
-                if let Some(light) = si.primitive_hit {
-                    light.pdf(si.basic.pos, wn, si.basic.norm).1
-                } else {
-                    0. as Float
-                }
-            },
-            _ => 0. as Float,
+        let mut pdf = if let Some((_, light)) = self.scene_light(scene) {
+            light.pdf_path(self.pos(), wn, self.norm()).1
+        } else {
+            0. as Float
         };
-        let nnorm = next.norm();
-        if nnorm != Vector3f::new(0. as Float, 0. as Float, 0. as Float) {
-            pdf *= nnorm.dot(wn).abs();
+        if next.on_surface() {
+            pdf *= next.norm().dot(wn).abs();
         }
         pdf * invdist2
     }
 
+    /// area density of this node being sampled as the origin of a
+    /// light path, with `next` as the succeeding node
     pub fn pdf_light_origin(&self, scene: &Scene, next: &Node) -> Float {
         let wi = (next.pos() - self.pos()).normalize();
-        match *self {
-            Node::Light{light, ref info, ..} => {
-                let pdf_pos = light.pdf(info.pos, wi, info.norm).0;
-                let mut pdf_choice = 0. as Float;
-                for (i, l) in scene.lights.iter().enumerate() {
-                    if ptr::eq(light, l.as_ref()) {
-                        pdf_choice = scene.light_distribution.discrete_pdf(i);
-                        break;
-                    }
-                }
-                pdf_pos * pdf_choice
-            }
-            Node::Surface{ref si, ..} => {
-                if let Some(light) = si.primitive_hit {
-                    let pdf_pos = light.pdf(si.basic.pos, wi, si.basic.norm).0;
-                    let mut pdf_choice = 0. as Float;
-                    // for (i, l) in scene.area_lights.iter().enumerate() {
-                    //     // TODO: double check if desirable
-                    //     if ptr::eq(light.as_light(), l.as_light()) {
-                    //         pdf_choice = scene.light_distribution.discrete_pdf(i+scene.lights.len());
-                    //         break;
-                    //     }
-                    // }
-                    pdf_pos * pdf_choice
-                } else {
-                    0. as Float
-                }
-            },
-            _ => 0. as Float,
+        if let Some((i, light)) = self.scene_light(scene) {
+            let pdf_pos = light.pdf_path(self.pos(), wi, self.norm()).0;
+            pdf_pos * scene.light_distribution.discrete_pdf(i)
+        } else {
+            0. as Float
         }
     }
 
@@ -297,9 +338,3 @@ impl<'a> Node<'a> {
         }
     }
 }
-
-impl<'a> Default for Node<'a> {
-    fn default() -> Self {unsafe {
-        mem::uninitialized()
-    }}
-}
\ No newline at end of file
@@ -0,0 +1,369 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod geometry_term {
+    use super::super::geometry_term;
+    use geometry::prelude::*;
+
+    #[test]
+    fn test_facing_planes() {
+        let g = geometry_term(
+            Point3f::new(0. as Float, 0. as Float, 0. as Float), Vector3f::new(0. as Float, 0. as Float, 1. as Float),
+            Point3f::new(0. as Float, 0. as Float, 2. as Float), Vector3f::new(0. as Float, 0. as Float, -1. as Float),
+        );
+        assert_relative_eq!(g, 0.25 as Float);
+    }
+
+    #[test]
+    fn test_oblique() {
+        // both cosines are $\frac{1}{\sqrt{2}}$ at a squared distance of 2
+        let g = geometry_term(
+            Point3f::new(0. as Float, 0. as Float, 0. as Float), Vector3f::new(0. as Float, 0. as Float, 1. as Float),
+            Point3f::new(1. as Float, 0. as Float, 1. as Float), Vector3f::new(-1. as Float, 0. as Float, 0. as Float),
+        );
+        assert_relative_eq!(g, 0.25 as Float);
+    }
+
+    #[test]
+    fn test_off_surface() {
+        let g = geometry_term(
+            Point3f::new(0. as Float, 0. as Float, 0. as Float), Vector3f::zero(),
+            Point3f::new(0. as Float, 0. as Float, 3. as Float), Vector3f::new(0. as Float, 0. as Float, -1. as Float),
+        );
+        assert_relative_eq!(g, 1. as Float / 9. as Float);
+    }
+
+    #[test]
+    fn test_degenerate() {
+        let n = Vector3f::new(0. as Float, 0. as Float, 1. as Float);
+        let p = Point3f::new(1. as Float, 2. as Float, 3. as Float);
+        assert_eq!(geometry_term(p, n, p, n), 0. as Float);
+        let grazing = geometry_term(p, n, p + Vector3f::new(1. as Float, 0. as Float, 0. as Float), n);
+        assert_eq!(grazing, 0. as Float);
+    }
+}
+
+#[cfg(test)]
+mod strategies {
+    use super::super::*;
+    use component::ComponentPointer;
+    use component::bvh::{BVH, BVHStrategy};
+    use lighting::Light;
+    use lighting::pointlights::PointLight;
+    use sample::strata::StdStrataSampler;
+    use rand::{StdRng, SeedableRng};
+    use shape::triangle::TriangleMesh;
+    use test_utils;
+
+    const RESOLUTION: usize = 16;
+
+    fn scene() -> (Scene, Arc<Camera>) {
+        let p = |x: Float, y: Float| Point3f::new(x, y, 0. as Float);
+        let quad = test_utils::quad(
+            [p(-1., -1.), p(1., -1.), p(1., 1.), p(-1., 1.)],
+            test_utils::matte(0.5 as Float, 0.5 as Float, 0.5 as Float),
+            None
+        );
+        let components: Vec<ComponentPointer> = TriangleMesh::instances(&quad)
+            .map(|t| t.into()).collect();
+        let lights: Vec<Arc<Light>> = vec![Arc::new(PointLight::new(
+            Point3f::new(0.3 as Float, 0.2 as Float, -1.5 as Float),
+            RGBSpectrumf::new(1. as Float, 1. as Float, 1. as Float)
+        ))];
+        let camera = test_utils::camera(
+            RESOLUTION, Point3f::new(0. as Float, 0. as Float, -3. as Float),
+            Point3f::new(0. as Float, 0. as Float, 0. as Float), float::frac_pi_3()
+        );
+        (Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))), camera)
+    }
+
+    // image of the single-bounce contributions of strategy `t`,
+    // unweighted unless `weighted`, with light subpaths splatted as
+    // `BPTRenderer` does, along with the sums of the squared
+    // contributions of the green channel. Pixels take `n`x`n`
+    // stratified samples seeded by `seed`
+    fn strategy_image(
+        scene: &Scene, camera: &Arc<Camera>, t: usize, n: u32, seed: usize, weighted: bool
+    ) -> (Vec<RGBSpectrumf>, Vec<Float>) {
+        let s = 3 - t;
+        let allocator = Allocator::new();
+        let mut sampler = StdStrataSampler::new(n, n, 8, StdRng::from_seed(&[seed][..]));
+        let spp = sampler.sample_per_pixel();
+        let mut image = vec![RGBSpectrumf::black(); RESOLUTION * RESOLUTION];
+        let mut squared = vec![0. as Float; RESOLUTION * RESOLUTION];
+        for y in 0..RESOLUTION {
+            for x in 0..RESOLUTION {
+                let p = Point2::new(x as u32, y as u32);
+                sampler.start_pixel(p);
+                loop {
+                    let pfilm = sampler.next_2d() + p.cast().to_vec();
+                    let mut cam_nodes = Vec::new();
+                    let mut light_nodes = Vec::new();
+                    generate_camera_subpath(scene, &mut sampler, &allocator, &**camera, pfilm, t, None, &mut cam_nodes);
+                    generate_light_subpath(scene, &mut sampler, &allocator, s, None, &mut light_nodes);
+                    if cam_nodes.len() == t && light_nodes.len() == s {
+                        let mut praster = pfilm;
+                        let mut mis_weight = 0. as Float;
+                        let l = connect(
+                            scene, &mut cam_nodes, &mut light_nodes, &**camera,
+                            &mut sampler, &mut praster, &mut mis_weight
+                        );
+                        if mis_weight > 0. as Float {
+                            let l = if weighted { l } else { l / mis_weight } / spp as Float;
+                            let idx = if t == 1 {
+                                praster.y as usize * RESOLUTION + praster.x as usize
+                            } else {
+                                y * RESOLUTION + x
+                            };
+                            image[idx] += l;
+                            squared[idx] += l.g() * l.g();
+                        }
+                    }
+                    if !sampler.next_sample() { break; }
+                }
+            }
+        }
+        (image, squared)
+    }
+
+    // reflected radiance of each pixel, integrated over a grid of
+    // film positions: $\frac{\rho}{\pi} I \frac{\cos\theta}{d^2}$ where
+    // camera rays hit the quad
+    fn analytic_image(camera: &Arc<Camera>) -> Vec<Float> {
+        let light = Point3f::new(0.3 as Float, 0.2 as Float, -1.5 as Float);
+        let n = 8;
+        let mut image = vec![0. as Float; RESOLUTION * RESOLUTION];
+        for y in 0..RESOLUTION {
+            for x in 0..RESOLUTION {
+                let mut sum = 0. as Float;
+                for j in 0..n {
+                    for i in 0..n {
+                        let pfilm = Point2f::new(
+                            x as Float + (i as Float + 0.5 as Float) / n as Float,
+                            y as Float + (j as Float + 0.5 as Float) / n as Float
+                        );
+                        let ray = camera.generate_path(SampleInfo{
+                            pfilm: pfilm, plens: Point2f::new(0.5 as Float, 0.5 as Float), time: 0. as Float,
+                        });
+                        let t = -ray.origin().z / ray.direction().z;
+                        let p = ray.evaluate(t);
+                        if t > 0. as Float && p.x.abs() <= 1. as Float && p.y.abs() <= 1. as Float {
+                            let d = light - p;
+                            let d2 = d.magnitude2();
+                            sum += 0.5 as Float * float::frac_1_pi() * d.z.abs() / (d2 * d2.sqrt());
+                        }
+                    }
+                }
+                image[y * RESOLUTION + x] = sum / (n * n) as Float;
+            }
+        }
+        image
+    }
+
+    // compare `image` against `analytic` over the whole image and per
+    // 4x4 blocks, within 4 standard deviations bounded by `squared` as
+    // in `test_light_tracing_agrees`, and 1% for the analytic quadrature
+    fn assert_matches(image: &(Vec<RGBSpectrumf>, Vec<Float>), analytic: &[Float], name: &str) {
+        let (ref image, ref squared) = *image;
+        assert!(analytic.iter().sum::<Float>() > 0. as Float);
+        let matches = |pixels: &[usize]| {
+            let a = pixels.iter().map(|&i| image[i].g()).sum::<Float>();
+            let b = pixels.iter().map(|&i| analytic[i]).sum::<Float>();
+            let var = pixels.iter().map(|&i| squared[i]).sum::<Float>();
+            assert!(
+                (a - b).abs() <= 4. as Float * var.sqrt() + 0.01 as Float * b,
+                "{} differs at pixels {:?}: {} against {}", name, pixels, a, b
+            );
+        };
+        matches(&(0..RESOLUTION * RESOLUTION).collect::<Vec<_>>());
+        for by in 0..RESOLUTION/4 {
+            for bx in 0..RESOLUTION/4 {
+                let block: Vec<_> = (by*4..by*4+4).flat_map(|y| {
+                    (bx*4..bx*4+4).map(move |x| y * RESOLUTION + x)
+                }).collect();
+                matches(&block);
+            }
+        }
+    }
+
+    #[test]
+    fn test_strategies_unbiased() {
+        let (scene, camera) = scene();
+        let analytic = analytic_image(&camera);
+        assert_matches(&strategy_image(&scene, &camera, 2, 16, 3, false), &analytic, "(s, t) = (1, 2)");
+        assert_matches(&strategy_image(&scene, &camera, 1, 16, 7, false), &analytic, "(s, t) = (2, 1)");
+        // the point light can't be hit
+        assert!(strategy_image(&scene, &camera, 3, 2, 1, false).0.iter().all(|l| l.is_black()));
+        // weighted, the strategies sum up to the same answer
+        let (traced, traced2) = strategy_image(&scene, &camera, 2, 16, 4, true);
+        let (splatted, splatted2) = strategy_image(&scene, &camera, 1, 16, 5, true);
+        let combined = (
+            traced.iter().zip(&splatted).map(|(&a, &b)| a + b).collect(),
+            traced2.iter().zip(&splatted2).map(|(&a, &b)| a + b).collect(),
+        );
+        assert_matches(&combined, &analytic, "combined");
+    }
+
+    #[test]
+    fn test_light_tracing_agrees() {
+        let (scene, camera) = scene();
+        let (traced, traced2) = strategy_image(&scene, &camera, 2, 16, 1, false);
+        let (splatted, splatted2) = strategy_image(&scene, &camera, 1, 16, 2, false);
+        // both are sums of independent contributions, whose variance is
+        // bounded by the sum of their squares. Stratification only
+        // lowers it, so that 4 standard deviations is a loose tolerance
+        let agree = |pixels: &[usize]| {
+            let sum = |image: &[RGBSpectrumf]| pixels.iter().map(|&i| image[i].g()).sum::<Float>();
+            let var = |squared: &[Float]| pixels.iter().map(|&i| squared[i]).sum::<Float>();
+            let tolerance = 4. as Float * (var(&traced2) + var(&splatted2)).sqrt();
+            assert!(sum(&traced) > 0. as Float);
+            assert!((sum(&splatted) - sum(&traced)).abs() <= tolerance,
+                "{} vs {}, tolerance {}", sum(&splatted), sum(&traced), tolerance);
+        };
+        agree(&(0..RESOLUTION * RESOLUTION).collect::<Vec<_>>());
+        // per 4x4 blocks
+        for by in 0..RESOLUTION/4 {
+            for bx in 0..RESOLUTION/4 {
+                let block: Vec<_> = (by*4..by*4+4).flat_map(|y| {
+                    (bx*4..bx*4+4).map(move |x| y * RESOLUTION + x)
+                }).collect();
+                agree(&block);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod degenerate {
+    use super::super::*;
+    use sample;
+
+    #[test]
+    fn test_zero_length_connection() {
+        let p = Point3f::new(1. as Float, 2. as Float, 3. as Float);
+        let n = Vector3f::new(0. as Float, 0. as Float, 1. as Float);
+        assert_eq!(geometry_term(p, n, p, n), 0. as Float);
+        // the closest distinct point along the normal
+        let q = Point3f::new(p.x, p.y, float::next_up(p.z));
+        assert!(q != p);
+        assert!(geometry_term(p, n, q, n).is_finite());
+    }
+
+    #[test]
+    fn test_heuristics_non_finite() {
+        for &(f, g) in &[
+            (float::infinity(), 1. as Float), (1. as Float, float::nan()),
+            (float::nan(), float::nan()), (0. as Float, 0. as Float),
+        ] {
+            assert_eq!(sample::power_heuristic(1, f, 1, g), 0. as Float);
+            assert_eq!(sample::balance_heuristic(1, f, 1, g), 0. as Float);
+        }
+        assert_relative_eq!(sample::power_heuristic(1, 3. as Float, 1, 1. as Float), 0.9 as Float);
+    }
+}
+
+#[cfg(test)]
+mod diagnostics {
+    use super::super::*;
+    use sample::strata::StdStrataSampler;
+    use filming::film::viridis;
+    use test_utils;
+    use rand::StdRng;
+
+    #[test]
+    fn test_beauty_unchanged() {
+        let scene = test_utils::cornell_box(16);
+        let sampler = StdStrataSampler::new(2, 2, 8, StdRng::new().unwrap());
+        let mut plain = BPTRenderer::new(sampler.clone(), scene.camera.clone(), "", 4);
+        let mut diagnosed = BPTRenderer::new(sampler, scene.camera.clone(), "", 4);
+        diagnosed.set_diagnostics(true);
+        let (reference, none) = plain.render_diagnosed(&scene.scene);
+        let (image, diagnostics) = diagnosed.render_diagnosed(&scene.scene);
+        assert!(none.is_none());
+        let diagnostics = diagnostics.unwrap();
+
+        // splats sum up the same whatever order they come in
+        let dim = image.dimension();
+        for y in 0..dim.y {
+            for x in 0..dim.x {
+                assert_eq!(reference[(x, y)], image[(x, y)]);
+            }
+        }
+
+        let mut lit = 0;
+        for p in diagnostics.bounding() {
+            let stats = diagnostics.get(p);
+            if stats.strategies == 0 { continue; }
+            lit += 1;
+            let f = stats.s_fractions();
+            assert_relative_eq!(f[0] + f[1] + f[2], 1. as Float, epsilon = 1e-4 as Float);
+            let depth = stats.average_depth();
+            assert!(depth >= 0. as Float && depth <= 4. as Float);
+            let mis = stats.average_mis_weight();
+            assert!(mis >= 0. as Float && mis <= 1. as Float + 1e-4 as Float);
+        }
+        assert!(lit > 0);
+        assert_eq!(diagnostics.heat_maps().len(), 5);
+    }
+
+    #[test]
+    fn test_viridis() {
+        assert_eq!(viridis(-1. as Float), viridis(0. as Float));
+        assert_eq!(viridis(2. as Float), viridis(1. as Float));
+        assert_eq!(viridis(float::nan()), viridis(0. as Float));
+        // luminance increases monotonically along the map
+        let mut last = 0. as Float;
+        for i in 0..33 {
+            let y = viridis(i as Float / 32. as Float).to_xyz().y;
+            assert!(y > last);
+            last = y;
+        }
+    }
+}
+
+#[cfg(test)]
+mod cancellation {
+    use super::super::*;
+    use sample::strata::StdStrataSampler;
+    use test_utils;
+    use rand::{StdRng, SeedableRng};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // mean luminance over pixels with all their samples completed
+    fn completed_mean(image: &Image, counts: &BoundedSink2D<u32>, spp: u32) -> (Float, usize) {
+        let mut sum = 0. as Float;
+        let mut n = 0;
+        for p in counts.bounding() {
+            if *counts.get_pixel(p) != spp { continue; }
+            sum += image[(p.x as u32, p.y as u32)].to_xyz().y;
+            n += 1;
+        }
+        (sum / n as Float, n)
+    }
+
+    #[test]
+    fn test_cancelled_splats_normalized() {
+        // caustics reach the camera through light paths only
+        let scene = test_utils::glass_sphere_caustic(16);
+        let spp = 64;
+        let sampler = StdStrataSampler::new(8, 8, 8, StdRng::from_seed(&[3usize][..]));
+        let mut renderer = BPTRenderer::new(sampler, scene.camera.clone(), "", 5);
+        let (reference, counts) = renderer.render_cancellable(&scene.scene, || false);
+        assert!(counts.bounding().into_iter().all(|p| *counts.get_pixel(p) == spp));
+
+        let pixels = AtomicUsize::new(0);
+        let (cancelled, counts) = renderer.render_cancellable(&scene.scene, || {
+            pixels.fetch_add(1, Ordering::SeqCst) >= 16 * 16 / 2
+        });
+        let (mean, n) = completed_mean(&cancelled, &counts, spp);
+        assert!(n > 0 && n < 16 * 16);
+        let (expected, _) = completed_mean(&reference, &counts, spp);
+        assert_relative_eq!(mean, expected, max_relative = 0.07 as Float);
+    }
+}
@@ -0,0 +1,392 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A validating builder for the renderers
+
+use sample::Sampler;
+use filming::Camera;
+use filming::bloom::Bloom;
+use geometry::prelude::*;
+use super::whitted::WhittedRenderer;
+use super::pt::{PTRenderer, LobeDepths, PathChannel};
+use super::pathfilter::PathFilter;
+use super::adaptive::AdaptiveSampling;
+use super::prepass::Prepass;
+use super::bpt::BPTRenderer;
+use super::ao::{AORenderer, AmbientOcclusion};
+use std::sync::Arc;
+use std::path::{PathBuf, Path};
+use std::fmt;
+use std::time::Duration;
+
+/// file extensions the rendered image can be saved as
+const OUTPUT_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "ppm", "ico"];
+
+/// Reasons for a renderer configuration to be rejected
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigError {
+    /// paths are not allowed to scatter at all
+    ZeroDepth,
+    /// the camera's film has no pixel
+    ZeroResolution,
+    /// the sampler takes no sample per pixel
+    ZeroSamples,
+    /// no light sample is taken per shading point
+    ZeroLightSamples,
+    /// the output can't be saved as an image of its extension
+    UnsupportedOutput(PathBuf),
+    /// the regularization roughness is not positive
+    InvalidRegularization(Float),
+    /// the path filter's thresholds make no sense
+    InvalidPathFilter(PathFilter),
+    /// the bloom has negative parameters
+    InvalidBloom(Bloom),
+    /// the adaptive sampling's threshold or weights make no sense
+    InvalidAdaptive(AdaptiveSampling),
+    /// the prepass has a zero stride or rounds, or too few samples
+    InvalidPrepass(Prepass),
+    /// the ambient occlusion has no positive distance or samples
+    InvalidAmbientOcclusion(AmbientOcclusion),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::ZeroDepth => f.write_str("maximum depth should be at least 1"),
+            ConfigError::ZeroResolution => f.write_str("film resolution should be positive"),
+            ConfigError::ZeroSamples => f.write_str("samples per pixel should be positive"),
+            ConfigError::ZeroLightSamples => f.write_str("light samples per shading point should be positive"),
+            ConfigError::UnsupportedOutput(ref path) => write!(
+                f, "can't save to {:?}, supported extensions are {:?}", path, OUTPUT_EXTENSIONS
+            ),
+            ConfigError::InvalidRegularization(alpha) => write!(
+                f, "regularization roughness should be positive, got {}", alpha
+            ),
+            ConfigError::InvalidPathFilter(ref filter) => write!(
+                f, "path filter should have a positive position threshold and a normal threshold within [-1, 1], got {:?}", filter
+            ),
+            ConfigError::InvalidBloom(ref bloom) => write!(
+                f, "bloom should have non-negative threshold, radius and intensity, got {:?}", bloom
+            ),
+            ConfigError::InvalidAdaptive(ref adaptive) => write!(
+                f, "adaptive sampling should have a positive threshold and non-negative weights, got {:?}", adaptive
+            ),
+            ConfigError::InvalidPrepass(ref prepass) => write!(
+                f, "prepass should have a positive stride and rounds, and at least 2 samples, got {:?}", prepass
+            ),
+            ConfigError::InvalidAmbientOcclusion(ref ao) => write!(
+                f, "ambient occlusion should have a positive maximum distance and samples, got {:?}", ao
+            ),
+        }
+    }
+}
+
+/// Builds renderers out of named parameters, validating them
+/// before any rendering happens.
+///
+/// Parameters not applicable to the renderer being built are ignored.
+#[derive(Clone)]
+pub struct RendererBuilder<S> {
+    sampler: S,
+    camera: Arc<Camera>,
+    output: PathBuf,
+    max_depth: usize,
+    multithreaded: bool,
+    lobe_depths: Option<LobeDepths>,
+    regularization: Option<Float>,
+    light_threshold: Float,
+    light_samples: usize,
+    sample_all_lights: bool,
+    light_groups: bool,
+    id_pass: bool,
+    albedo_pass: bool,
+    path_filter: Option<PathFilter>,
+    preview_interval: Option<Duration>,
+    sample_dump: Option<PathBuf>,
+    bloom: Option<Bloom>,
+    path_channels: Vec<PathChannel>,
+    adaptive: Option<AdaptiveSampling>,
+    prepass: Option<Prepass>,
+    ambient_occlusion: AmbientOcclusion,
+}
+
+impl<S: Sampler> RendererBuilder<S> {
+    /// a builder writing to `render.png`, with maximum depth 5,
+    /// multithreading on and no regularization or light culling
+    pub fn new(sampler: S, camera: Arc<Camera>) -> RendererBuilder<S> {
+        RendererBuilder{
+            sampler: sampler,
+            camera: camera,
+            output: PathBuf::from("render.png"),
+            max_depth: 5,
+            multithreaded: true,
+            lobe_depths: None,
+            regularization: None,
+            light_threshold: 0. as Float,
+            light_samples: 1,
+            sample_all_lights: true,
+            light_groups: false,
+            id_pass: false,
+            albedo_pass: false,
+            path_filter: None,
+            preview_interval: None,
+            sample_dump: None,
+            bloom: None,
+            path_channels: Vec::new(),
+            adaptive: None,
+            prepass: None,
+            ambient_occlusion: AmbientOcclusion::default(),
+        }
+    }
+
+    /// file the rendered image is saved to
+    pub fn output<P: AsRef<Path> + ?Sized>(mut self, path: &P) -> Self {
+        self.output = path.as_ref().to_path_buf();
+        self
+    }
+
+    /// maximum number of scattering events along a path
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// whether tiles are rendered in parallel, path tracing only
+    pub fn multithreaded(mut self, multithreaded: bool) -> Self {
+        self.multithreaded = multithreaded;
+        self
+    }
+
+    /// per-lobe maximum depths, path tracing only
+    pub fn lobe_depths(mut self, lobe_depths: LobeDepths) -> Self {
+        self.lobe_depths = Some(lobe_depths);
+        self
+    }
+
+    /// minimum roughness after the first non-specular bounce,
+    /// `None` to disable
+    pub fn regularization(mut self, min_alpha: Option<Float>) -> Self {
+        self.regularization = min_alpha;
+        self
+    }
+
+    /// light culling threshold, whitted only
+    pub fn light_threshold(mut self, light_threshold: Float) -> Self {
+        self.light_threshold = light_threshold;
+        self
+    }
+
+    /// light samples per shading point, whitted only
+    pub fn light_samples(mut self, n: usize) -> Self {
+        self.light_samples = n;
+        self
+    }
+
+    /// whether light samples are taken from every light rather than
+    /// one picked per sample, whitted only
+    pub fn sample_all_lights(mut self, sample_all_lights: bool) -> Self {
+        self.sample_all_lights = sample_all_lights;
+        self
+    }
+
+    /// whether light groups are saved separately, path tracing only
+    pub fn light_groups(mut self, light_groups: bool) -> Self {
+        self.light_groups = light_groups;
+        self
+    }
+
+    /// whether the primitive id pass is saved, path tracing only
+    pub fn id_pass(mut self, id_pass: bool) -> Self {
+        self.id_pass = id_pass;
+        self
+    }
+
+    /// whether the albedo pass is saved, path tracing only
+    pub fn albedo_pass(mut self, albedo_pass: bool) -> Self {
+        self.albedo_pass = albedo_pass;
+        self
+    }
+
+    /// filtering of indirect radiance over neighboring pixels, path
+    /// tracing only. `None` disables the filtering.
+    pub fn path_filter(mut self, filter: Option<PathFilter>) -> Self {
+        self.path_filter = filter;
+        self
+    }
+
+    /// how often completed tiles are saved into the output while
+    /// rendering, whitted, ambient occlusion and path tracing only.
+    /// `None` disables the preview.
+    pub fn preview_interval(mut self, interval: Option<Duration>) -> Self {
+        self.preview_interval = interval;
+        self
+    }
+
+    /// file the raw samples of the render are streamed into,
+    /// path tracing only. `None` disables the dump.
+    pub fn sample_dump(mut self, path: Option<PathBuf>) -> Self {
+        self.sample_dump = path;
+        self
+    }
+
+    /// glow added around bright pixels of the render, path tracing
+    /// only. `None` disables the bloom.
+    pub fn bloom(mut self, bloom: Option<Bloom>) -> Self {
+        self.bloom = bloom;
+        self
+    }
+
+    /// path channels saved separately, path tracing only
+    pub fn path_channels(mut self, channels: Vec<PathChannel>) -> Self {
+        self.path_channels = channels;
+        self
+    }
+
+    /// extra samples for noisy pixels and geometric edges, path
+    /// tracing only. `None` disables adaptive sampling.
+    pub fn adaptive(mut self, adaptive: Option<AdaptiveSampling>) -> Self {
+        self.adaptive = adaptive;
+        self
+    }
+
+    /// prepass ordering tiles by their estimated cost, path tracing
+    /// only. `None` disables the prepass.
+    pub fn prepass(mut self, prepass: Option<Prepass>) -> Self {
+        self.prepass = prepass;
+        self
+    }
+
+    /// maximum distance, samples and falloff of the ambient
+    /// occlusion, ambient occlusion only
+    pub fn ambient_occlusion(mut self, ao: AmbientOcclusion) -> Self {
+        self.ambient_occlusion = ao;
+        self
+    }
+
+    /// build a `WhittedRenderer`
+    pub fn build_whitted(self) -> Result<WhittedRenderer<S>, ConfigError> {
+        self.validate_common()?;
+        if self.light_samples == 0 {
+            return Err(ConfigError::ZeroLightSamples);
+        }
+        let mut renderer = WhittedRenderer::new(self.sampler, self.camera, &self.output, self.light_threshold);
+        renderer.set_preview_interval(self.preview_interval);
+        renderer.set_light_samples(self.light_samples);
+        renderer.set_sample_all_lights(self.sample_all_lights);
+        Ok(renderer)
+    }
+
+    /// build a `PTRenderer`
+    pub fn build_pt(self) -> Result<PTRenderer<S>, ConfigError> {
+        self.validate_tracing()?;
+        let mut renderer = PTRenderer::new(
+            self.sampler, self.camera, &self.output, self.max_depth, self.multithreaded
+        );
+        if let Some(depths) = self.lobe_depths {
+            renderer.set_lobe_depths(depths);
+        }
+        renderer.set_regularization(self.regularization);
+        renderer.set_light_groups(self.light_groups);
+        renderer.set_id_pass(self.id_pass);
+        renderer.set_albedo_pass(self.albedo_pass);
+        renderer.set_path_filter(self.path_filter);
+        renderer.set_preview_interval(self.preview_interval);
+        renderer.set_sample_dump(self.sample_dump);
+        renderer.set_bloom(self.bloom);
+        renderer.set_path_channels(self.path_channels);
+        renderer.set_adaptive(self.adaptive);
+        renderer.set_prepass(self.prepass);
+        Ok(renderer)
+    }
+
+    /// build a `BPTRenderer`
+    pub fn build_bpt(self) -> Result<BPTRenderer<S>, ConfigError> {
+        self.validate_tracing()?;
+        let mut renderer = BPTRenderer::new(self.sampler, self.camera, &self.output, self.max_depth);
+        renderer.set_regularization(self.regularization);
+        Ok(renderer)
+    }
+
+    /// build an `AORenderer`
+    pub fn build_ao(self) -> Result<AORenderer<S>, ConfigError> {
+        self.validate_common()?;
+        if !self.ambient_occlusion.is_valid() {
+            return Err(ConfigError::InvalidAmbientOcclusion(self.ambient_occlusion));
+        }
+        let mut renderer = AORenderer::new(self.sampler, self.camera, &self.output, self.ambient_occlusion);
+        renderer.set_preview_interval(self.preview_interval);
+        Ok(renderer)
+    }
+
+    /// every reason for a `PTRenderer` or a `BPTRenderer` of this
+    /// configuration to be rejected, rather than only the first
+    pub fn tracing_errors(&self) -> Vec<ConfigError> {
+        self.errors(true)
+    }
+
+    fn errors(&self, tracing: bool) -> Vec<ConfigError> {
+        let mut ret = Vec::new();
+        let resolution = self.camera.get_film().resolution();
+        if resolution.x == 0 || resolution.y == 0 {
+            ret.push(ConfigError::ZeroResolution);
+        }
+        if self.sampler.sample_per_pixel() == 0 {
+            ret.push(ConfigError::ZeroSamples);
+        }
+        let supported = self.output.extension()
+            .and_then(|ext| ext.to_str())
+            .map_or(false, |ext| OUTPUT_EXTENSIONS.contains(&&*ext.to_lowercase()));
+        if !supported {
+            ret.push(ConfigError::UnsupportedOutput(self.output.clone()));
+        }
+        if !tracing { return ret; }
+        if self.max_depth == 0 {
+            ret.push(ConfigError::ZeroDepth);
+        }
+        if let Some(alpha) = self.regularization {
+            if !(alpha > 0. as Float) {
+                ret.push(ConfigError::InvalidRegularization(alpha));
+            }
+        }
+        if let Some(filter) = self.path_filter {
+            if !filter.is_valid() {
+                ret.push(ConfigError::InvalidPathFilter(filter));
+            }
+        }
+        if let Some(bloom) = self.bloom {
+            if !bloom.is_valid() {
+                ret.push(ConfigError::InvalidBloom(bloom));
+            }
+        }
+        if let Some(adaptive) = self.adaptive {
+            if !adaptive.is_valid() {
+                ret.push(ConfigError::InvalidAdaptive(adaptive));
+            }
+        }
+        if let Some(prepass) = self.prepass {
+            if !prepass.is_valid() {
+                ret.push(ConfigError::InvalidPrepass(prepass));
+            }
+        }
+        ret
+    }
+
+    fn validate_common(&self) -> Result<(), ConfigError> {
+        match self.errors(false).into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn validate_tracing(&self) -> Result<(), ConfigError> {
+        match self.errors(true).into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
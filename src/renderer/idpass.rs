@@ -0,0 +1,134 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Per-pixel primitive ids, for building object masks in compositing
+
+use geometry::prelude::*;
+use filming::film::{BoundedSink2D, Image};
+use spectrum::{RGBSpectrumf, Spectrum};
+use std::path::Path;
+use std::fs::File;
+use std::io::{Result, Write, BufWriter};
+
+/// The primitive seen through a pixel, decided by a majority vote
+/// among the pixel's samples
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct IdVote {
+    /// id of the primitive, `0` for the background
+    pub id: u32,
+    /// number of samples seeing the primitive
+    pub count: u32,
+}
+
+impl IdVote {
+    /// the vote among `ids` of a pixel's samples, with ties
+    /// going to the smallest id. `ids` gets sorted.
+    pub fn majority(ids: &mut [u32]) -> IdVote {
+        ids.sort();
+        let mut ret = IdVote::default();
+        let mut i = 0;
+        while i < ids.len() {
+            let id = ids[i];
+            let count = ids[i..].iter().take_while(|&&x| x == id).count();
+            if count as u32 > ret.count {
+                ret = IdVote{ id: id, count: count as u32 };
+            }
+            i += count;
+        }
+        ret
+    }
+}
+
+/// Primitive ids over the film's crop window
+pub struct IdPass {
+    votes: BoundedSink2D<IdVote>,
+}
+
+impl IdPass {
+    /// construction
+    #[inline]
+    pub fn new(votes: BoundedSink2D<IdVote>) -> IdPass {
+        IdPass{
+            votes: votes,
+        }
+    }
+
+    /// id of the primitive seen through pixel `p`
+    #[inline]
+    pub fn get(&self, p: Point2<isize>) -> u32 {
+        self.votes.get_pixel(p).id
+    }
+
+    /// pixels covered, namely the film's crop window
+    #[inline]
+    pub fn bounding(&self) -> BBox2<isize> {
+        self.votes.bounding()
+    }
+
+    /// the ids colorized by hashing, leaving the background black
+    pub fn to_image(&self) -> Image {
+        let bounding = self.votes.bounding();
+        let mut image = Image::new(RGBSpectrumf::black(), bounding.pmax.cast());
+        for p in bounding {
+            let id = self.get(p);
+            if id != 0 {
+                let p: Point2<u32> = p.cast();
+                image[p] = id_color(id);
+            }
+        }
+        image
+    }
+
+    /// write the exact ids into `w`, as the width and the height
+    /// followed by the ids in rows, all as little-endian `u32`s.
+    /// Pixels outside the crop window are `0`.
+    pub fn write_raw<W: Write>(&self, w: &mut W) -> Result<()> {
+        let bounding = self.votes.bounding();
+        let (width, height) = (bounding.pmax.x.max(0) as u32, bounding.pmax.y.max(0) as u32);
+        write_u32(w, width)?;
+        write_u32(w, height)?;
+        for y in 0..height {
+            for x in 0..width {
+                let p = Point2::new(x as isize, y as isize);
+                let id = if bounding.contain_lb(p) { self.get(p) } else { 0 };
+                write_u32(w, id)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// save the colorized ids next to `path` as `<stem>_id.png`,
+    /// along with the exact ids as `<stem>_id.u32`
+    pub fn save<P: AsRef<Path> + ?Sized>(&self, path: &P) -> Result<()> {
+        let path = path.as_ref();
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("render");
+        self.to_image().save(&path.with_file_name(format!("{}_id.png", stem)))?;
+        let mut file = BufWriter::new(File::create(path.with_file_name(format!("{}_id.u32", stem)))?);
+        self.write_raw(&mut file)?;
+        file.flush()
+    }
+}
+
+#[inline]
+fn write_u32<W: Write>(w: &mut W, v: u32) -> Result<()> {
+    w.write_all(&[v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8])
+}
+
+/// a color for `id`, scattered by hashing so that nearby ids are told
+/// apart easily. Channels stay above `0.2` so that no id but `0` is black.
+pub fn id_color(id: u32) -> RGBSpectrumf {
+    // finalizer of murmur3
+    let mut h = id;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^= h >> 16;
+    let channel = |shift: u32| 0.2 as Float + 0.8 as Float * ((h >> shift) & 0xff) as Float / 255. as Float;
+    RGBSpectrumf::new(channel(0), channel(8), channel(16))
+}
@@ -9,21 +9,78 @@
 //! Defines `Renderer` which can render a scene
 
 use self::scene::Scene;
+use filming::film::Image;
+use aren_alloc::Allocator;
+use geometry::prelude::*;
 
 /// A renderer
 pub trait Renderer {
-    /// render a scene
+    /// render a scene into an in-memory image.
+    ///
+    /// Renderers implemented outside of this crate need to provide it
+    /// alongside `render`, which usually just saves its result.
+    fn render_image(&mut self, scene: &Scene) -> Image;
+
+    /// render a scene, writing the result to the renderer's output
     fn render(&mut self, scene: &Scene);
 }
 
+impl<R: Renderer + ?Sized> Renderer for Box<R> {
+    #[inline]
+    fn render_image(&mut self, scene: &Scene) -> Image {
+        (**self).render_image(scene)
+    }
+
+    #[inline]
+    fn render(&mut self, scene: &Scene) {
+        (**self).render(scene)
+    }
+}
+
+thread_local!(static ALLOCATOR: Allocator = Allocator::new());
+
+/// run `f` with the calling thread's allocator, kept across the tiles
+/// rendered on that thread so that its pools are reused rather than
+/// rebuilt per tile. Allocations can't outlive `f`, and are expected to
+/// be dropped, thus recycled, by the end of each sample.
+fn with_thread_allocator<R, F: FnOnce(&Allocator) -> R>(f: F) -> R {
+    ALLOCATOR.with(f)
+}
+
+/// seed of the sampler forked for the tile covering `bbox`
+#[inline]
+fn tile_seed(bbox: BBox2<isize>) -> u64 {
+    (bbox.pmin.x as u32 as u64) | ((bbox.pmin.y as u32 as u64) << 32)
+}
+
 pub mod scene;
 pub mod whitted;
-// pub mod bpt;
+pub mod bpt;
 pub mod pt;
+pub mod builder;
+pub mod idpass;
+pub mod pathfilter;
+pub mod adaptive;
+pub mod prepass;
+pub mod bake;
+pub mod scene_builder;
+pub mod animation;
+pub mod ao;
 pub mod prelude {
     pub use super::Renderer;
-    pub use super::scene::Scene;
+    pub use super::scene::{Scene, SceneSummary, MAX_LIGHT_GROUPS};
     pub use super::whitted::WhittedRenderer;
-    // pub use super::bpt::BPTRenderer;
-    pub use super::pt::PTRenderer;
+    pub use super::bpt::BPTRenderer;
+    pub use super::pt::{PTRenderer, LobeDepths, PathChannel};
+    pub use super::pathfilter::PathFilter;
+    pub use super::adaptive::AdaptiveSampling;
+    pub use super::prepass::Prepass;
+    pub use super::bake::{BakeRenderer, BakeMode, BakeError};
+    pub use super::builder::{RendererBuilder, ConfigError};
+    pub use super::scene_builder::{SceneBuilder, SceneBuildError, Recentering};
+    pub use super::animation::{AnimationDriver, CameraPath, Orbit};
+    pub use super::ao::{AORenderer, AmbientOcclusion, OcclusionFalloff};
 }
+
+#[cfg(test)]
+mod tests;
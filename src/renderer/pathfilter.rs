@@ -0,0 +1,236 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Path-space filtering of indirect illumination, trading bias for
+//! less noise in previews of low sample counts.
+//!
+//! The indirect radiance of each pixel is averaged with that of the
+//! neighboring pixels whose camera rays first hit nearby points of
+//! similar orientation. Direct lighting is left untouched.
+
+use geometry::prelude::*;
+use filming::film::{BoundedSink2D, Image};
+use spectrum::{RGBSpectrumf, Spectrum};
+use rayon::prelude::*;
+
+/// Parameters of path-space filtering
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PathFilter {
+    /// half width of the neighborhood gathered from, in pixels
+    pub radius: usize,
+    /// largest distance between the first hits of neighbors, relative
+    /// to the distance of the pixel's first hit from the camera
+    pub position_threshold: Float,
+    /// smallest cosine between the normals at the first hits of neighbors
+    pub normal_threshold: Float,
+}
+
+impl PathFilter {
+    /// test if the thresholds make sense, namely a positive
+    /// `position_threshold` and a `normal_threshold` within `[-1, 1]`
+    pub fn is_valid(&self) -> bool {
+        self.position_threshold > 0. as Float
+            && self.normal_threshold >= -1. as Float
+            && self.normal_threshold <= 1. as Float
+    }
+
+    // test if the neighbor first hitting `b` is gathered from by the
+    // pixel first hitting `a`
+    #[inline]
+    fn accepts(&self, a: &FirstHit, b: &FirstHit) -> bool {
+        (b.position - a.position).magnitude() <= self.position_threshold * a.depth
+            && a.normal.dot(b.normal) >= self.normal_threshold
+    }
+}
+
+impl Default for PathFilter {
+    #[inline]
+    fn default() -> PathFilter {
+        PathFilter{
+            radius: 4,
+            position_threshold: 0.1 as Float,
+            normal_threshold: 0.9 as Float,
+        }
+    }
+}
+
+/// Where a camera ray first hits the scene
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FirstHit {
+    pub position: Point3f,
+    /// shading normal, facing the camera
+    pub normal: Vector3f,
+    /// distance from the camera
+    pub depth: Float,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct HitSum {
+    position: Vector3f,
+    normal: Vector3f,
+    depth: Float,
+    count: usize,
+}
+
+/// First hits of the camera rays through each pixel, averaged
+/// over the pixel's samples hitting anything
+pub struct HitBuffer {
+    sums: BoundedSink2D<HitSum>,
+}
+
+impl HitBuffer {
+    /// a buffer of the pixels within `bounding`, with nothing hit
+    pub fn new(bounding: BBox2<isize>) -> HitBuffer {
+        HitBuffer{
+            sums: BoundedSink2D::with_value(HitSum{
+                position: Vector3f::zero(),
+                normal: Vector3f::zero(),
+                depth: 0. as Float,
+                count: 0,
+            }, bounding),
+        }
+    }
+
+    /// record a sample of pixel `p` first hitting `hit`
+    #[inline]
+    pub fn add(&mut self, p: Point2<isize>, hit: &FirstHit) {
+        let sum = self.sums.get_pixel_mut(p);
+        sum.position += hit.position.to_vec();
+        sum.normal += hit.normal;
+        sum.depth += hit.depth;
+        sum.count += 1;
+    }
+
+    /// copy in the pixels of `other` within the bounds
+    pub fn merge(&mut self, other: &HitBuffer) {
+        if let Some(overlap) = self.sums.bounding().intersect(&other.sums.bounding()) {
+            for p in overlap {
+                *self.sums.get_pixel_mut(p) = *other.sums.get_pixel(p);
+            }
+        }
+    }
+
+    /// the averaged first hit of pixel `p`, `None` if none of its
+    /// samples hit anything or it lies out of bounds
+    pub fn get(&self, p: Point2<isize>) -> Option<FirstHit> {
+        if !self.sums.bounding().contain_lb(p) { return None; }
+        let sum = self.sums.get_pixel(p);
+        if sum.count == 0 { return None; }
+        let inv = 1. as Float / sum.count as Float;
+        let normal = if sum.normal.magnitude2() > 0. as Float {
+            sum.normal.normalize()
+        } else {
+            Vector3f::zero()
+        };
+        Some(FirstHit{
+            position: Point3f::from_vec(sum.position * inv),
+            normal: normal,
+            depth: sum.depth * inv,
+        })
+    }
+
+    /// pixels covered
+    #[inline]
+    pub fn bounding(&self) -> BBox2<isize> {
+        self.sums.bounding()
+    }
+}
+
+/// The beauty image split into the radiance arriving from the first
+/// hits of camera paths directly, and the rest of it, along with the
+/// first hits
+pub struct PathBuffers {
+    /// emitted by the first hits, or scattered there from lights
+    pub direct: Image,
+    /// brought by the rest of the paths
+    pub indirect: Image,
+    pub hits: HitBuffer,
+}
+
+impl PathBuffers {
+    /// the beauty image, as rendered
+    pub fn unfiltered(self) -> Image {
+        let indirect = self.indirect;
+        compose(self.direct, |p| indirect[p])
+    }
+
+    /// the beauty image with the indirect radiance filtered by `filter`
+    pub fn filtered(self, filter: &PathFilter) -> Image {
+        let indirect = filter_indirect(&self.indirect, &self.hits, filter);
+        compose(self.direct, |p| indirect[p])
+    }
+}
+
+// add `indirect` onto each pixel of `direct`
+fn compose<F>(mut direct: Image, indirect: F) -> Image
+    where F: Fn(Point2<u32>) -> RGBSpectrumf
+{
+    let dim = direct.dimension();
+    for y in 0..dim.y {
+        for x in 0..dim.x {
+            let p = Point2::new(x, y);
+            direct[p] += indirect(p);
+        }
+    }
+    direct
+}
+
+/// filter `indirect` over the pixels of `hits` by a horizontal, then
+/// a vertical gaussian of `filter.radius`, gathering only from
+/// neighbors `filter` accepts. Pixels hitting nothing are left as is.
+pub fn filter_indirect(indirect: &Image, hits: &HitBuffer, filter: &PathFilter) -> Image {
+    let horizontal = filter_pass(indirect, hits, filter, Vector2::new(1, 0));
+    filter_pass(&horizontal, hits, filter, Vector2::new(0, 1))
+}
+
+// one pass of `filter_indirect` along `step`, in parallel over rows
+fn filter_pass(src: &Image, hits: &HitBuffer, filter: &PathFilter, step: Vector2<isize>) -> Image {
+    let bounding = hits.bounding();
+    let radius = filter.radius as isize;
+    let sigma = (filter.radius as Float * 0.5 as Float).max(0.5 as Float);
+    let weights: Vec<Float> = (0..radius + 1).map(|i| {
+        (-((i * i) as Float) / (2. as Float * sigma * sigma)).exp()
+    }).collect();
+    let rows: Vec<Vec<RGBSpectrumf>> = (bounding.pmin.y..bounding.pmax.y).into_par_iter().map(|y| {
+        (bounding.pmin.x..bounding.pmax.x).map(|x| {
+            let p = Point2::new(x, y);
+            let center = match hits.get(p) {
+                Some(hit) => hit,
+                None => return src[p.cast::<u32>()],
+            };
+            let mut sum = RGBSpectrumf::black();
+            let mut weight_sum = 0. as Float;
+            for i in -radius..radius + 1 {
+                let q = p + step * i;
+                match hits.get(q) {
+                    Some(ref hit) if filter.accepts(&center, hit) => {
+                        let w = weights[i.abs() as usize];
+                        sum += src[q.cast::<u32>()] * w;
+                        weight_sum += w;
+                    },
+                    _ => (),
+                }
+            }
+            // the center is accepted unless its normals cancel out
+            if weight_sum > 0. as Float { sum / weight_sum } else { src[p.cast::<u32>()] }
+        }).collect()
+    }).collect();
+    let dim = src.dimension();
+    let mut ret = Image::new(RGBSpectrumf::black(), dim);
+    for y in 0..dim.y {
+        for x in 0..dim.x {
+            ret[(x, y)] = src[(x, y)];
+        }
+    }
+    for (row, y) in rows.into_iter().zip(bounding.pmin.y..bounding.pmax.y) {
+        for (s, x) in row.into_iter().zip(bounding.pmin.x..bounding.pmax.x) {
+            ret[(x as u32, y as u32)] = s;
+        }
+    }
+    ret
+}
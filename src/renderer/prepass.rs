@@ -0,0 +1,107 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A quick prepass over a sparse grid of pixels, estimating how costly
+//! and how noisy each tile is going to be.
+//!
+//! Tiles are then rendered from the costliest to the cheapest, so that
+//! the slowest ones don't start last and keep a single thread busy
+//! while the others idle. Optionally, tiles are also given rounds of
+//! samples in proportion to their noise.
+
+use geometry::prelude::*;
+
+/// Parameters of the prepass
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Prepass {
+    /// one pixel out of each `stride` by `stride` block is sampled
+    pub stride: usize,
+    /// samples taken through each sampled pixel, at most the
+    /// sampler's samples per pixel
+    pub samples: usize,
+    /// whether tiles are given rounds of the sampler's samples per
+    /// pixel in proportion to their estimated noise
+    #[serde(default)]
+    pub allocate: bool,
+    /// mean rounds given to a tile when allocating
+    pub rounds: usize,
+}
+
+impl Prepass {
+    /// test if the parameters make sense, namely a positive stride and
+    /// rounds, and enough samples to estimate a variance from
+    pub fn is_valid(&self) -> bool {
+        self.stride > 0 && self.samples > 1 && self.rounds > 0
+    }
+}
+
+impl Default for Prepass {
+    #[inline]
+    fn default() -> Prepass {
+        Prepass{
+            stride: 8,
+            samples: 4,
+            allocate: false,
+            rounds: 2,
+        }
+    }
+}
+
+/// What the prepass found out about a tile
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TileEstimate {
+    /// pixels covered by the tile
+    pub bounding: BBox2<isize>,
+    /// rays traced per sample, shadow rays included. Unlike timings,
+    /// it stays the same from run to run
+    pub cost: Float,
+    /// mean relative variance of the luminance of a single sample,
+    /// over the sampled pixels
+    pub variance: Float,
+}
+
+/// pixels of `bounding` sampled by a prepass of `stride`, at the centers
+/// of the blocks of the film's grid, or the center of `bounding` if none
+pub fn sampled_pixels(bounding: BBox2<isize>, stride: usize) -> Vec<Point2<isize>> {
+    let stride = stride as isize;
+    let offset = stride / 2;
+    let ret: Vec<_> = bounding.into_iter().filter(|p| {
+        (p.x - offset) % stride == 0 && (p.y - offset) % stride == 0
+    }).collect();
+    if ret.is_empty() {
+        vec![Point2::new(
+            (bounding.pmin.x + bounding.pmax.x) / 2, (bounding.pmin.y + bounding.pmax.y) / 2
+        )]
+    } else {
+        ret
+    }
+}
+
+/// indices into `estimates` from the costliest tile to the cheapest,
+/// keeping the original order among tiles of the same cost
+pub fn priority(estimates: &[TileEstimate]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..estimates.len()).collect();
+    order.sort_by(|&a, &b| {
+        estimates[b].cost.partial_cmp(&estimates[a].cost).unwrap_or(::std::cmp::Ordering::Equal)
+    });
+    order
+}
+
+/// rounds given to each of `estimates`, in proportion to its variance.
+/// Each tile is given at least one, the total being `rounds` per tile
+/// but for the tiles rounded up to one
+pub fn allocate_rounds(estimates: &[TileEstimate], rounds: usize) -> Vec<usize> {
+    let total: Float = estimates.iter().map(|e| e.variance).sum();
+    if !(total > 0. as Float) || !total.is_finite() {
+        return vec![rounds; estimates.len()];
+    }
+    let budget = (rounds * estimates.len()) as Float;
+    estimates.iter().map(|e| {
+        ((budget * e.variance / total).round() as usize).max(1)
+    }).collect()
+}
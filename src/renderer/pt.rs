@@ -10,16 +10,22 @@
 
 use bxdf::prelude::*;
 use sample::prelude::*;
+use sample;
 use filming::prelude::*;
-use filming::film::FilmTile;
+use filming::film::{FilmTile, FilmPreview, Image, BoundedSink2D};
 use super::Renderer;
-use std::sync::Arc;
+use super::idpass::{IdPass, IdVote};
+use super::pathfilter::{PathFilter, PathBuffers, FirstHit, HitBuffer};
+use super::adaptive::{AdaptiveSampling, GeometryStats, sample_count_map};
+use super::prepass::{self, Prepass, TileEstimate};
+use std::sync::{Arc, Mutex};
 use super::scene::Scene;
 use spectrum::{RGBSpectrumf, Spectrum};
 use rayon::prelude::*;
 use aren_alloc::Allocator;
 use geometry::prelude::*;
 use std::path::{PathBuf, Path};
+use std::time::{Duration, Instant};
 profile_use!();
 
 /// A path tracing renderer
@@ -28,9 +34,138 @@ pub struct PTRenderer<S> {
     camera: Arc<Camera>,
     filename: PathBuf,
     max_depth: usize,
+    depths: LobeDepths,
     multithreaded: bool,
+    // smallest probability of paths surviving russian roulette
     rr_threshold: Float,
     min_depth: usize,
+    regularization: Option<Float>,
+    light_groups: bool,
+    id_pass: bool,
+    albedo_pass: bool,
+    path_filter: Option<PathFilter>,
+    preview_interval: Option<Duration>,
+    sample_dump: Option<PathBuf>,
+    bloom: Option<Bloom>,
+    channels: Vec<PathChannel>,
+    adaptive: Option<AdaptiveSampling>,
+    prepass: Option<Prepass>,
+}
+
+/// Maximum number of scattering events allowed per lobe type
+/// along a path, on top of the total maximum depth
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LobeDepths {
+    pub diffuse: usize,
+    pub glossy: usize,
+    pub specular: usize,
+}
+
+impl LobeDepths {
+    /// allows `depth` scattering events for every lobe type
+    #[inline]
+    pub fn uniform(depth: usize) -> LobeDepths {
+        LobeDepths{
+            diffuse: depth, glossy: depth, specular: depth,
+        }
+    }
+
+    // test if `counts` has reached the limit of any lobe type
+    #[inline]
+    fn exceeded_by(&self, counts: &LobeDepths) -> bool {
+        counts.diffuse >= self.diffuse
+        || counts.glossy >= self.glossy
+        || counts.specular >= self.specular
+    }
+
+    // count a scattering event of type `bt`
+    #[inline]
+    fn count(&mut self, bt: BxdfType) {
+        if bt.intersects(BXDF_SPECULAR) {
+            self.specular += 1;
+        } else if bt.intersects(BXDF_GLOSSY) {
+            self.glossy += 1;
+        } else if bt.intersects(BXDF_DIFFUSE) {
+            self.diffuse += 1;
+        }
+    }
+}
+
+/// Components of the beauty image, telling paths apart by the lobes
+/// sampled along them before reaching the light. The radiance of
+/// all the channels sums up to the beauty.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PathChannel {
+    /// light emitted by the first surface hit, or reaching it directly
+    Direct,
+    /// light bounced further, its first non-specular bounce diffuse
+    DiffuseIndirect,
+    /// light bounced further, its first non-specular bounce glossy,
+    /// or bounced off specular surfaces only
+    GlossyIndirect,
+    /// light bounced off a specular surface after a diffuse one,
+    /// namely focused onto diffuse surfaces by specular ones
+    Caustic,
+}
+
+/// number of `PathChannel`s
+pub const PATH_CHANNELS: usize = 4;
+
+impl PathChannel {
+    /// every channel
+    #[inline]
+    pub fn all() -> [PathChannel; PATH_CHANNELS] {
+        [PathChannel::Direct, PathChannel::DiffuseIndirect, PathChannel::GlossyIndirect, PathChannel::Caustic]
+    }
+
+    /// name of the channel, as in file names
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        match *self {
+            PathChannel::Direct => "direct",
+            PathChannel::DiffuseIndirect => "diffuse_indirect",
+            PathChannel::GlossyIndirect => "glossy_indirect",
+            PathChannel::Caustic => "caustic",
+        }
+    }
+}
+
+// the channel of a path, by the lobes sampled along it so far
+#[derive(Copy, Clone, Default)]
+struct PathClass {
+    bounced: bool,
+    // whether the first non-specular bounce is glossy, if any
+    first_glossy: Option<bool>,
+    diffuse: bool,
+    caustic: bool,
+}
+
+impl PathClass {
+    // count a scattering event of type `bt`, classified as `LobeDepths::count` does
+    #[inline]
+    fn scatter(&mut self, bt: BxdfType) {
+        self.bounced = true;
+        if bt.intersects(BXDF_SPECULAR) {
+            self.caustic |= self.diffuse;
+        } else {
+            let glossy = bt.intersects(BXDF_GLOSSY);
+            if self.first_glossy.is_none() { self.first_glossy = Some(glossy); }
+            self.diffuse |= !glossy && bt.intersects(BXDF_DIFFUSE);
+        }
+    }
+
+    #[inline]
+    fn channel(&self) -> PathChannel {
+        if !self.bounced {
+            PathChannel::Direct
+        } else if self.caustic {
+            PathChannel::Caustic
+        } else if self.first_glossy == Some(false) {
+            PathChannel::DiffuseIndirect
+        } else {
+            PathChannel::GlossyIndirect
+        }
+    }
 }
 
 impl<S: Sampler> PTRenderer<S> {
@@ -43,64 +178,791 @@ impl<S: Sampler> PTRenderer<S> {
             camera: camera,
             filename: filename.as_ref().to_path_buf(),
             max_depth: max_depth,
+            depths: LobeDepths::uniform(max_depth),
             multithreaded: multithreaded,
             rr_threshold: 0.05 as Float,
             min_depth: max_depth/2,
+            regularization: None,
+            light_groups: false,
+            id_pass: false,
+            albedo_pass: false,
+            path_filter: None,
+            preview_interval: None,
+            sample_dump: None,
+            bloom: None,
+            channels: Vec::new(),
+            adaptive: None,
+            prepass: None,
         }
     }
+
+    /// set the maximum scattering events per lobe type.
+    /// `max_depth` remains as a hard cap on the total.
+    #[inline]
+    pub fn set_lobe_depths(&mut self, depths: LobeDepths) {
+        self.depths = depths;
+    }
+
+    /// set the minimum microfacet roughness of surfaces met after
+    /// the first non-specular bounce of a path, trading bias for
+    /// less noisy caustics. `None` disables regularization.
+    #[inline]
+    pub fn set_regularization(&mut self, min_alpha: Option<Float>) {
+        self.regularization = min_alpha;
+    }
+
+    /// set whether `render` also saves the contribution of each
+    /// light group in use, as `<stem>_group<i>.<ext>` next to the output
+    #[inline]
+    pub fn set_light_groups(&mut self, enabled: bool) {
+        self.light_groups = enabled;
+    }
+
+    /// set whether `render` also saves the primitive id pass,
+    /// as `<stem>_id.png` and `<stem>_id.u32` next to the output
+    #[inline]
+    pub fn set_id_pass(&mut self, enabled: bool) {
+        self.id_pass = enabled;
+    }
+
+    /// set whether `render` also saves the albedo pass, the albedo of
+    /// the surfaces seen through each pixel, as `<stem>_albedo.<ext>`
+    /// next to the output, for denoisers
+    #[inline]
+    pub fn set_albedo_pass(&mut self, enabled: bool) {
+        self.albedo_pass = enabled;
+    }
+
+    /// set whether the indirect radiance is filtered over neighboring
+    /// pixels of similar first hits by `filter`, trading bias for less
+    /// noise. Light groups and previews are left out of filtered
+    /// renders. `None` disables the filtering.
+    #[inline]
+    pub fn set_path_filter(&mut self, filter: Option<PathFilter>) {
+        self.path_filter = filter;
+    }
+
+    /// set whether `render` writes completed tiles into the output as
+    /// it goes, saving it at most once per `interval`. `None` disables
+    /// the preview.
+    #[inline]
+    pub fn set_preview_interval(&mut self, interval: Option<Duration>) {
+        self.preview_interval = interval;
+    }
+
+    /// set the file `render` streams the raw samples of the beauty
+    /// image into, tile by tile, for them to be `refilter`ed later.
+    /// Ignored along with a path filter. `None` disables the dump.
+    #[inline]
+    pub fn set_sample_dump(&mut self, path: Option<PathBuf>) {
+        self.sample_dump = path;
+    }
+
+    /// set the bloom applied to rendered images, light groups and
+    /// other passes left alone. `None` disables the bloom.
+    #[inline]
+    pub fn set_bloom(&mut self, bloom: Option<Bloom>) {
+        self.bloom = bloom;
+    }
+
+    /// set the path channels `render` also saves, each as
+    /// `<stem>_<name>.<ext>` next to the output. Only the channels
+    /// given are kept in memory while rendering. Like light groups,
+    /// channels are left out of filtered renders and bloom.
+    #[inline]
+    pub fn set_path_channels(&mut self, channels: Vec<PathChannel>) {
+        self.channels = channels;
+    }
+
+    /// set whether pixels of noisy estimates or straddling geometric
+    /// edges are given more samples, in rounds of the sampler's samples
+    /// per pixel. `render` also saves a map of the samples taken as
+    /// `<stem>_samples.<ext>`. Ignored along with a path filter.
+    /// `None` disables adaptive sampling.
+    #[inline]
+    pub fn set_adaptive(&mut self, adaptive: Option<AdaptiveSampling>) {
+        self.adaptive = adaptive;
+    }
+
+    /// set whether a quick prepass estimates the cost and noise of each
+    /// tile, such that the costliest tiles are rendered first, and,
+    /// if `prepass.allocate`, tiles are given rounds of the sampler's
+    /// samples per pixel in proportion to their noise. Ignored along
+    /// with a path filter. `None` disables the prepass.
+    #[inline]
+    pub fn set_prepass(&mut self, prepass: Option<Prepass>) {
+        self.prepass = prepass;
+    }
+
+    /// run the prepass over `scene`, estimating each tile in the order
+    /// of `Film::spawn_tiles`. The default prepass is run if none is set
+    pub fn tile_estimates(&self, scene: &Scene) -> Vec<TileEstimate> {
+        let prepass = self.prepass.unwrap_or_default();
+        let film = self.camera.get_film();
+        let mut tiles: Vec<FilmTile<RGBSpectrumf>> = film.spawn_tiles(film.tile_size());
+        let estimate = |tile: &mut FilmTile<RGBSpectrumf>| {
+            super::with_thread_allocator(|allocator| {
+                let tile_bound = tile.bounding();
+                // not to repeat the samples of the render itself
+                let mut sampler = self.sampler.fork(sample::mix(!super::tile_seed(tile_bound)));
+                let mut contributions = Vec::new();
+                let pixels = prepass::sampled_pixels(tile_bound, prepass.stride);
+                let mut samples = 0;
+                let mut rays = 0;
+                for &pi in &pixels {
+                    let p: Point2<u32> = pi.cast();
+                    sampler.start_pixel(p);
+                    for _ in 0..prepass.samples {
+                        rays += self.add_pixel_sample(
+                            scene, &mut sampler, p, allocator, tile, &mut [], &mut contributions, &[]
+                        ).rays;
+                        samples += 1;
+                        if !sampler.next_sample() { break; }
+                    }
+                }
+                let variance: Float = pixels.iter().map(|&pi| {
+                    let stats = tile.stats(pi);
+                    stats.relative_variance().unwrap_or(0. as Float) * stats.count as Float
+                }).sum();
+                TileEstimate{
+                    bounding: tile_bound,
+                    cost: rays as Float / samples as Float,
+                    variance: variance / pixels.len() as Float,
+                }
+            })
+        };
+        if self.multithreaded {
+            tiles.par_iter_mut().map(|tile| estimate(tile)).collect()
+        } else {
+            tiles.iter_mut().map(|tile| estimate(tile)).collect()
+        }
+    }
+
+    /// the tiles of the film in the order they are rendered in, from
+    /// the costliest to the cheapest as estimated by the prepass
+    pub fn tile_order(&self, scene: &Scene) -> Vec<BBox2<isize>> {
+        let estimates = self.tile_estimates(scene);
+        prepass::priority(&estimates).into_iter().map(|i| estimates[i].bounding).collect()
+    }
+
+    /// render the beauty image, along with the samples taken in each
+    /// pixel of the crop window
+    pub fn render_sample_counts(&self, scene: &Scene) -> (Image, BoundedSink2D<u32>) {
+        let (image, _, counts) = self.render_passes(scene, 0, &[], None, None);
+        (image, counts)
+    }
+
+    /// find the primitive seen through each pixel, as the one hit by
+    /// the most camera rays among the pixel's samples
+    pub fn render_ids(&self, scene: &Scene) -> IdPass {
+        let film = self.camera.get_film();
+        let tiles = film.spawn_tiles::<RGBSpectrumf>(film.tile_size());
+        let vote_tile = |tile: &FilmTile<RGBSpectrumf>| {
+            let tile_bound = tile.bounding();
+            let mut sampler = self.sampler.fork(super::tile_seed(tile_bound));
+            let mut votes = BoundedSink2D::with_value(IdVote::default(), tile_bound);
+            let mut ids = Vec::with_capacity(sampler.sample_per_pixel());
+            for p in tile_bound {
+                let pu: Point2<u32> = p.cast();
+                sampler.start_pixel(pu);
+                ids.clear();
+                loop {
+                    let mut camera_sample_info = sampler.get_camera_sample(pu, self.camera.shutter());
+                    camera_sample_info.pfilm = tile.jitter(camera_sample_info.pfilm);
+                    let mut ray = self.camera.generate_path(camera_sample_info);
+                    ids.push(scene.intersect_clipped(&mut ray, self.camera.clip_planes())
+                        .and_then(|si| si.primitive_hit)
+                        .map_or(0, |primitive| scene.primitive_id(primitive)));
+                    if !sampler.next_sample() { break; }
+                }
+                *votes.get_pixel_mut(p) = IdVote::majority(&mut ids);
+            }
+            votes
+        };
+        let tile_votes: Vec<_> = if self.multithreaded {
+            tiles.par_iter().map(|tile| vote_tile(tile)).collect()
+        } else {
+            tiles.iter().map(|tile| vote_tile(tile)).collect()
+        };
+        let mut merged = BoundedSink2D::with_value(IdVote::default(), film.crop_window_raster());
+        for votes in &tile_votes {
+            for p in votes.bounding() {
+                *merged.get_pixel_mut(p) = *votes.get_pixel(p);
+            }
+        }
+        IdPass::new(merged)
+    }
+
+    /// render the albedo of the surfaces first hit by camera rays, as
+    /// `Bsdf::rho` estimates it, filtered as the beauty image is.
+    /// Rays leaving the scene count as black.
+    pub fn render_albedo(&self, scene: &Scene) -> Image {
+        let film = self.camera.get_film();
+        let mut tiles = film.spawn_tiles::<RGBSpectrumf>(film.tile_size());
+        let render_tile = |tile: &mut FilmTile<RGBSpectrumf>| {
+            super::with_thread_allocator(|allocator| {
+                let tile_bound = tile.bounding();
+                let mut sampler = self.sampler.fork(super::tile_seed(tile_bound));
+                for p in tile_bound {
+                    let p: Point2<u32> = p.cast();
+                    sampler.start_pixel(p);
+                    loop {
+                        let mut camera_sample_info = sampler.get_camera_sample(p, self.camera.shutter());
+                        let pfilm = camera_sample_info.pfilm;
+                        camera_sample_info.pfilm = tile.jitter(pfilm);
+                        let mut ray = self.camera.generate_path_differential(camera_sample_info);
+                        ray.scale_differentials(1.0 as Float / sampler.sample_per_pixel() as Float);
+                        let albedo = first_hit_albedo(&mut ray, scene, self.camera.clip_planes(), allocator);
+                        tile.add_sample(pfilm, &albedo);
+                        if !sampler.next_sample() { break; }
+                    }
+                }
+            })
+        };
+        if self.multithreaded {
+            tiles.par_iter_mut().for_each(|tile| render_tile(tile));
+        } else {
+            for tile in &mut tiles { render_tile(tile); }
+        }
+        film.collect_into(tiles)
+    }
+
+    /// render the beauty image split into its direct and indirect
+    /// radiance, along with the first hits of camera rays, as path-space
+    /// filtering works on
+    pub fn render_path_buffers(&self, scene: &Scene) -> PathBuffers {
+        let film = self.camera.get_film();
+        let tile_size = film.tile_size();
+        let mut tiles: Vec<(FilmTile<RGBSpectrumf>, FilmTile<RGBSpectrumf>, HitBuffer)> = {
+            film.spawn_tiles(tile_size).into_iter().zip(film.spawn_tiles(tile_size)).map(|(direct, indirect)| {
+                let hits = HitBuffer::new(direct.bounding());
+                (direct, indirect, hits)
+            }).collect()
+        };
+        let render_tile = |&mut (ref mut direct, ref mut indirect, ref mut hits): &mut (FilmTile<_>, FilmTile<_>, HitBuffer)| {
+            super::with_thread_allocator(|allocator| {
+                let tile_bound = direct.bounding();
+                let mut sampler = self.sampler.fork(super::tile_seed(tile_bound));
+                for p in tile_bound {
+                    let pu: Point2<u32> = p.cast();
+                    sampler.start_pixel(pu);
+                    loop {
+                        let mut camera_sample_info = sampler.get_camera_sample(pu, self.camera.shutter());
+                        let pfilm = camera_sample_info.pfilm;
+                        camera_sample_info.pfilm = direct.jitter(pfilm);
+                        let mut ray_differential = self.camera.generate_path_differential(camera_sample_info);
+                        ray_differential.scale_differentials(1.0 as Float / sampler.sample_per_pixel() as Float);
+                        let mut split = PathSplit{ direct: RGBSpectrumf::black(), hit: None, rays: 0 };
+                        let (total, alpha) = calculate_lighting(
+                            ray_differential, scene, self.camera.clip_planes(), &mut sampler,
+                            allocator, 0, false, self.max_depth, self.depths,
+                            self.min_depth, self.rr_threshold, self.regularization,
+                            None, Some(&mut split), None
+                        );
+                        let (d, i) = if total.valid() && split.direct.valid() {
+                            (split.direct, total - split.direct)
+                        } else {
+                            (RGBSpectrumf::black(), RGBSpectrumf::black())
+                        };
+                        direct.add_sample_with_alpha(pfilm, &d, alpha);
+                        indirect.add_sample_with_alpha(pfilm, &i, alpha);
+                        if let Some(ref hit) = split.hit { hits.add(p, hit); }
+                        if !sampler.next_sample() { break; }
+                    }
+                }
+            })
+        };
+        if self.multithreaded {
+            tiles.par_iter_mut().for_each(|tile| render_tile(tile));
+        } else {
+            for tile in &mut tiles { render_tile(tile); }
+        }
+        let mut hits = HitBuffer::new(film.crop_window_raster());
+        let mut direct_tiles = Vec::with_capacity(tiles.len());
+        let mut indirect_tiles = Vec::with_capacity(tiles.len());
+        for (direct, indirect, tile_hits) in tiles {
+            hits.merge(&tile_hits);
+            direct_tiles.push(direct);
+            indirect_tiles.push(indirect);
+        }
+        PathBuffers{
+            direct: film.collect_into(direct_tiles),
+            indirect: film.collect_into(indirect_tiles),
+            hits: hits,
+        }
+    }
+
+    /// render the beauty image, along with the contributions of each
+    /// light group of `scene` in use, which sum up to the beauty.
+    ///
+    /// Contributions of sampled lights are attributed to their groups,
+    /// as are emitters hit by camera rays or after specular bounces.
+    pub fn render_light_groups(&mut self, scene: &Scene) -> (Image, Vec<Image>) {
+        let (image, groups, _) = self.render_passes(scene, scene.light_group_count(), &[], None, None);
+        (image, groups)
+    }
+
+    /// render the beauty image, along with the radiance of each of
+    /// `channels`. The images of all the channels sum up to the beauty.
+    pub fn render_path_channels(&self, scene: &Scene, channels: &[PathChannel]) -> (Image, Vec<Image>) {
+        let (image, channels, _) = self.render_passes(scene, 0, channels, None, None);
+        (image, channels)
+    }
+
+    /// render `scene` through each of `cameras` in turn, saving the
+    /// results to the corresponding `outputs`. The scene and its
+    /// acceleration structure are shared by all renders.
+    pub fn render_multi<P: AsRef<Path>>(
+        &mut self, scene: &Scene, cameras: &[Arc<Camera>], outputs: &[P]
+    ) {
+        assert_eq!(cameras.len(), outputs.len(), "cameras and outputs mismatch");
+        let camera = self.camera.clone();
+        let filename = self.filename.clone();
+        for (camera, output) in cameras.iter().zip(outputs) {
+            self.camera = camera.clone();
+            self.filename = output.as_ref().to_path_buf();
+            self.render(scene);
+        }
+        self.camera = camera;
+        self.filename = filename;
+    }
+
+    // render the beauty image along with `groups` light group images
+    // followed by the images of `channels`, writing completed beauty
+    // tiles into `preview` and their raw samples into `dump` if presented.
+    // Also returns the samples taken in each pixel
+    fn render_passes(
+        &self, scene: &Scene, groups: usize, channels: &[PathChannel],
+        preview: Option<&FilmPreview>, dump: Option<&SampleDumpWriter>
+    ) -> (Image, Vec<Image>, BoundedSink2D<u32>) {
+        profile_start!("pt rendering");
+        info!("Path tracing rendering process started");
+        let film = self.camera.get_film();
+        let aovs = groups + channels.len();
+        let mut tiles: Vec<(FilmTile<RGBSpectrumf>, Vec<FilmTile<RGBSpectrumf>>)> = {
+            let tile_size = film.tile_size();
+            let mut group_tiles: Vec<_> = (0..aovs).map(|_| {
+                film.spawn_tiles(tile_size).into_iter()
+            }).collect();
+            film.spawn_tiles(tile_size).into_iter().map(|mut tile: FilmTile<_>| {
+                if dump.is_some() { tile.set_raw_samples(true); }
+                (tile, group_tiles.iter_mut().map(|g| g.next().unwrap()).collect())
+            }).collect()
+        };
+        // rounds of samples per pixel given to each tile
+        let mut rounds = vec![1; tiles.len()];
+        let mut order = None;
+        if let Some(prepass) = self.prepass {
+            let estimates = self.tile_estimates(scene);
+            if prepass.allocate {
+                rounds = prepass::allocate_rounds(&estimates, prepass.rounds);
+            }
+            let priority = prepass::priority(&estimates);
+            let mut slots: Vec<_> = tiles.into_iter().zip(rounds).map(Some).collect();
+            let (t, r) = priority.iter().map(|&i| {
+                slots[i].take().expect("tile ordered twice")
+            }).unzip();
+            tiles = t;
+            rounds = r;
+            order = Some(priority);
+        }
+        let render_tile = |&mut (ref mut tile, ref mut group_tiles): &mut (FilmTile<_>, Vec<FilmTile<_>>), rounds: usize| {
+            super::with_thread_allocator(|allocator| {
+                let tile_bound = tile.bounding();
+                let seed = super::tile_seed(tile_bound);
+                let mut sampler = self.sampler.fork(seed);
+                let mut contributions = vec![RGBSpectrumf::black(); aovs];
+                for pi in tile_bound {
+                    let p: Point2<u32> = pi.cast();
+                    let mut geometry = GeometryStats::default();
+                    self.sample_pixel(
+                        scene, &mut sampler, p, allocator, tile, group_tiles, &mut contributions, channels,
+                        self.adaptive.as_ref().map(|_| &mut geometry)
+                    );
+                    for round in 1..rounds {
+                        let mut extra = self.sampler.fork(sample::mix(seed ^ sample::mix(round as u64)));
+                        self.sample_pixel(
+                            scene, &mut extra, p, allocator, tile, group_tiles, &mut contributions, channels,
+                            self.adaptive.as_ref().map(|_| &mut geometry)
+                        );
+                    }
+                    if let Some(ref adaptive) = self.adaptive {
+                        for round in rounds..rounds + adaptive.max_rounds {
+                            if adaptive.error(tile.stats(pi), &geometry) <= adaptive.threshold { break; }
+                            let mut extra = self.sampler.fork(sample::mix(seed ^ sample::mix(round as u64)));
+                            self.sample_pixel(
+                                scene, &mut extra, p, allocator, tile, group_tiles, &mut contributions, channels,
+                                Some(&mut geometry)
+                            );
+                        }
+                    }
+                }
+                if let Some(preview) = preview {
+                    preview.add_tile(tile);
+                }
+                if let Some(dump) = dump {
+                    if let Err(e) = dump.write_tile(tile) {
+                        warn!("Dumping samples of tile {:?} failed: {}", tile_bound, e);
+                    }
+                }
+            })
+        };
+        if self.multithreaded && self.prepass.is_some() {
+            // tiles are taken from a shared queue, such that they start in order
+            let queue = Mutex::new(tiles.iter_mut().zip(rounds.iter()));
+            (0..rayon::current_num_threads()).into_par_iter().for_each(|_| loop {
+                let next = queue.lock().expect("poisoned tile queue").next();
+                match next {
+                    Some((tile, &rounds)) => render_tile(tile, rounds),
+                    None => break,
+                }
+            });
+        } else if self.multithreaded {
+            tiles.par_iter_mut().zip(rounds.par_iter()).for_each(|(tile, &rounds)| render_tile(tile, rounds));
+        } else {
+            for (tile, &rounds) in tiles.iter_mut().zip(rounds.iter()) { render_tile(tile, rounds); }
+        }
+        // back to the film's order, so that the result doesn't depend on timings
+        if let Some(order) = order {
+            let mut slots: Vec<_> = (0..tiles.len()).map(|_| None).collect();
+            for (i, tile) in order.into_iter().zip(tiles) {
+                slots[i] = Some(tile);
+            }
+            tiles = slots.into_iter().map(|tile| tile.expect("tile lost")).collect();
+        }
+        let mut group_tiles: Vec<Vec<_>> = (0..aovs).map(|_| Vec::with_capacity(tiles.len())).collect();
+        let mut beauty_tiles = Vec::with_capacity(tiles.len());
+        for (tile, gs) in tiles {
+            beauty_tiles.push(tile);
+            for (g, tile) in group_tiles.iter_mut().zip(gs) {
+                g.push(tile);
+            }
+        }
+        // collect the beauty last, leaving its variance on the film
+        let group_results = group_tiles.into_iter().map(|g| film.collect_into(g)).collect();
+        let counts = film.sample_counts(&beauty_tiles);
+        let render_result = film.collect_into(beauty_tiles);
+        profile_end!("pt rendering");
+        info!("Path tracing rendering process ended");
+        (render_result, group_results, counts)
+    }
+
+    // take all the samples of `sampler` through pixel `p` as
+    // `add_pixel_sample` does, recording their first hits into
+    // `geometry` if presented
+    fn sample_pixel(
+        &self, scene: &Scene, sampler: &mut S, p: Point2<u32>, allocator: &Allocator,
+        tile: &mut FilmTile<RGBSpectrumf>, group_tiles: &mut [FilmTile<RGBSpectrumf>],
+        contributions: &mut Vec<RGBSpectrumf>, channels: &[PathChannel],
+        mut geometry: Option<&mut GeometryStats>
+    ) {
+        sampler.start_pixel(p);
+        loop {
+            let split = self.add_pixel_sample(
+                scene, sampler, p, allocator, tile, group_tiles, contributions, channels
+            );
+            if let Some(ref mut geometry) = geometry {
+                geometry.add(split.hit.as_ref());
+            }
+            if !sampler.next_sample() { break; }
+        }
+    }
+
+    // trace a camera sample through pixel `p`, adding it into `tile`,
+    // and the contribution of each light group followed by that of
+    // each of `channels` into `group_tiles`. Returns the first hit
+    // of the camera ray and the rays traced
+    fn add_pixel_sample(
+        &self, scene: &Scene, sampler: &mut S, p: Point2<u32>, allocator: &Allocator,
+        tile: &mut FilmTile<RGBSpectrumf>, group_tiles: &mut [FilmTile<RGBSpectrumf>],
+        contributions: &mut Vec<RGBSpectrumf>, channels: &[PathChannel]
+    ) -> PathSplit {
+        let mut camera_sample_info = sampler.get_camera_sample(p, self.camera.shutter());
+        let pfilm = camera_sample_info.pfilm;
+        camera_sample_info.pfilm = tile.jitter(pfilm);
+        let mut ray_differential = self.camera.generate_path_differential(camera_sample_info);
+        ray_differential.scale_differentials(1.0 as Float / sampler.sample_per_pixel() as Float);
+        for c in contributions.iter_mut() { *c = RGBSpectrumf::black(); }
+        let groups = contributions.len() - channels.len();
+        let mut channel_radiance = [RGBSpectrumf::black(); PATH_CHANNELS];
+        let mut split = PathSplit{ direct: RGBSpectrumf::black(), hit: None, rays: 0 };
+        profile_start!("pt light calculation");
+        let (total_randiance, alpha) = calculate_lighting(
+            ray_differential, scene, self.camera.clip_planes(), sampler,
+            allocator, 0, false, self.max_depth, self.depths,
+            self.min_depth, self.rr_threshold, self.regularization,
+            if groups > 0 { Some(&mut *contributions) } else { None },
+            Some(&mut split),
+            if channels.is_empty() { None } else { Some(&mut channel_radiance) }
+        );
+        profile_end!("pt light calculation");
+        for (c, channel) in contributions[groups..].iter_mut().zip(channels) {
+            *c = channel_radiance[*channel as usize];
+        }
+
+        profile_start!("pt add sample");
+        let valid = total_randiance.valid();
+        if valid {
+            tile.add_sample_with_alpha(pfilm, &total_randiance, alpha);
+        } else {
+            tile.add_sample_with_alpha(pfilm, &RGBSpectrumf::black(), alpha);
+        }
+        for (group_tile, c) in group_tiles.iter_mut().zip(contributions.iter()) {
+            let c = if valid { *c } else { RGBSpectrumf::black() };
+            group_tile.add_sample_with_alpha(pfilm, &c, alpha);
+        }
+        profile_end!("pt add sample");
+        split
+    }
+
+    /// render `scene` in passes of one sample per pixel, until `done`
+    /// returns true given the number of passes taken and the film's
+    /// `mean_relative_variance` after them. Returns the image along
+    /// with that variance.
+    ///
+    /// Pass `i` takes sample `i % sample_per_pixel` of a sampler forked
+    /// anew for it, so that each stratum of a stratified sampler is
+    /// visited once every `sample_per_pixel` passes.
+    pub fn render_progressive<F>(&self, scene: &Scene, mut done: F) -> (Image, Option<Float>)
+        where F: FnMut(usize, Option<Float>) -> bool
+    {
+        let film = self.camera.get_film();
+        let spp = self.sampler.sample_per_pixel().max(1);
+        let mut tiles: Vec<FilmTile<RGBSpectrumf>> = film.spawn_tiles(film.tile_size());
+        let mut passes = 0;
+        loop {
+            let render_tile = |tile: &mut FilmTile<RGBSpectrumf>| {
+                super::with_thread_allocator(|allocator| {
+                    let tile_bound = tile.bounding();
+                    let seed = super::tile_seed(tile_bound) ^ sample::mix(passes as u64);
+                    let mut sampler = self.sampler.fork(sample::mix(seed));
+                    let mut contributions = Vec::new();
+                    for p in tile_bound {
+                        let p: Point2<u32> = p.cast();
+                        sampler.start_pixel(p);
+                        for _ in 0..passes % spp { sampler.next_sample(); }
+                        self.add_pixel_sample(scene, &mut sampler, p, allocator, tile, &mut [], &mut contributions, &[]);
+                    }
+                })
+            };
+            if self.multithreaded {
+                tiles.par_iter_mut().for_each(|tile| render_tile(tile));
+            } else {
+                for tile in &mut tiles { render_tile(tile); }
+            }
+            passes += 1;
+            let image = film.collect_tiles(&tiles);
+            let variance = film.mean_relative_variance();
+            if done(passes, variance) {
+                return (image, variance);
+            }
+        }
+    }
+
+    /// render `scene` progressively as `render_progressive` does, until
+    /// the film's mean relative variance is at most `target_rel_variance`,
+    /// or `max_seconds` have passed, saving the result. The estimate is
+    /// logged after each pass. At least two passes are taken, as the
+    /// variance of a pixel can't be estimated out of less samples.
+    pub fn render_until(
+        &mut self, scene: &Scene, target_rel_variance: Float, max_seconds: Float
+    ) -> Convergence {
+        let start = Instant::now();
+        let mut convergence = Convergence{
+            passes: 0,
+            relative_variance: None,
+            seconds: 0. as Float,
+        };
+        let (image, _) = self.render_progressive(scene, |passes, variance| {
+            let elapsed = start.elapsed();
+            let seconds = elapsed.as_secs() as Float + elapsed.subsec_nanos() as Float * 1e-9 as Float;
+            match variance {
+                Some(v) => info!("pass {}: mean relative variance {:.3e} after {:.1}s", passes, v, seconds),
+                None => info!("pass {}: variance not estimated yet after {:.1}s", passes, seconds),
+            }
+            convergence = Convergence{
+                passes: passes,
+                relative_variance: variance,
+                seconds: seconds,
+            };
+            variance.map_or(false, |v| v <= target_rel_variance) || seconds >= max_seconds
+        });
+        if let Ok(_) = image.save(&self.filename) {
+            info!("Path tracing result saved at {:?}", self.filename);
+        } else {
+            warn!("Path tracing result saving at {:?} failed", self.filename);
+        }
+        convergence
+    }
+}
+
+/// How far `PTRenderer::render_until` went
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Convergence {
+    /// passes taken, namely samples per pixel
+    pub passes: usize,
+    /// mean relative variance reached, `None` if not estimated
+    pub relative_variance: Option<Float>,
+    /// seconds taken for rendering
+    pub seconds: Float,
+}
+
+
+/// sampler dimensions reserved for the film and lens samples
+const CAMERA_DIMENSIONS: usize = 2;
+
+/// sampler dimensions reserved for each bounce, holding the light
+/// selection, light, scattering and roulette samples
+const BOUNCE_DIMENSIONS: usize = 3;
+
+/// samples `Bsdf::rho` takes for albedos of the roulette and the albedo pass
+const ALBEDO_SAMPLES: usize = 16;
+
+// albedo of the surface `ray` first hits past `clip`, black if none
+fn first_hit_albedo(ray: &mut RayDifferential, scene: &Scene, clip: &[ClipPlane], alloc: &Allocator) -> RGBSpectrumf {
+    if let Some(mut si) = scene.intersect_clipped(&mut ray.ray, clip) {
+        if let Some(primitive) = si.primitive_hit {
+            let dxy = si.compute_dxy(ray);
+            let bsdf = primitive.get_material().compute_scattering(&mut si, &dxy, alloc);
+            return bsdf.rho(-ray.ray.direction(), ALBEDO_SAMPLES);
+        }
+    }
+    RGBSpectrumf::black()
 }
 
+// the first hit of a camera path, and the radiance arriving from there
+// directly, as path-space filtering tells apart, along with the rays
+// traced for the path, shadow rays included, as the prepass counts
+struct PathSplit {
+    direct: RGBSpectrumf,
+    hit: Option<FirstHit>,
+    rays: usize,
+}
 
-// helper function for whitted rendering's light computation
+// helper function for path tracing's light computation, returning the
+// radiance premultiplied by the coverage, and the coverage itself.
+// Camera rays skip over hits clipped away by `clip`. `ray` continues
+// a path past `depth` scattering events, the last of them `specular`
+// or not; camera paths start at zero
 fn calculate_lighting<S: Sampler>(
     mut ray: RayDifferential, 
     scene: &Scene, 
+    clip: &[ClipPlane],
     sampler: &mut S, 
     alloc: &Allocator,
     depth: usize,
+    specular: bool,
     max_depth: usize,
+    depths: LobeDepths,
     min_depth: usize,
-    rr_threshold: Float
-) -> RGBSpectrumf {
+    rr_threshold: Float,
+    regularization: Option<Float>,
+    mut groups: Option<&mut Vec<RGBSpectrumf>>,
+    mut split: Option<&mut PathSplit>,
+    mut channels: Option<&mut [RGBSpectrumf; PATH_CHANNELS]>
+) -> (RGBSpectrumf, Float) {
     let mut ret = RGBSpectrumf::black();
-    if depth > max_depth { return ret; }
+    if depth > 0 && depth >= max_depth { return (ret, 1. as Float); }
     let mut beta = RGBSpectrumf::new(1. as Float, 1. as Float, 1. as Float);
-    let mut specular_bounce = false;
-    let mut bounces = 0;
+    // probability the path survives roulette after the current bounce
+    let mut survival = 1. as Float;
+    let mut specular_bounce = specular;
+    let mut rough_bounce = depth > 0 && !specular;
+    let mut bounces = depth;
+    let mut counts = LobeDepths::uniform(0);
+    let mut class = PathClass::default();
+    // absorption of the volume the current segment lies in, toggled
+    // on each transmission through an absorbing material's boundary
+    let mut interior: Option<RGBSpectrumf> = None;
     loop {
-        if let Some(mut si) = scene.aggregate.intersect_ray(&mut ray.ray) {
+        sampler.set_dimension(CAMERA_DIMENSIONS + bounces * BOUNCE_DIMENSIONS);
+        if let Some(ref mut split) = split { split.rays += 1; }
+        if let Some(mut si) = scene.intersect_clipped(&mut ray.ray, clip) {
+            if let Some(sigma_a) = interior {
+                let dist = (si.basic.pos - ray.ray.origin()).magnitude();
+                beta *= (sigma_a * -dist).exp();
+            }
             if bounces == 0 || specular_bounce {
                 let term = si.le(-ray.ray.direction());
                 if !term.valid() {
                     warn!("invalid le {:?} from {:p}, vray: {:p}", term, &si, &ray);
                 }
                 ret += beta * term;
+                if let Some(ref mut groups) = groups {
+                    if let Some(primitive) = si.primitive_hit {
+                        if !term.is_black() { groups[scene.light_group_of(primitive)] += beta * term; }
+                    }
+                }
+                if let Some(ref mut channels) = channels {
+                    channels[class.channel() as usize] += beta * term;
+                }
+                if bounces == 0 {
+                    if let Some(ref mut split) = split {
+                        let wo = -ray.ray.direction();
+                        let normal = if si.shading_norm.dot(wo) < 0. as Float { -si.shading_norm } else { si.shading_norm };
+                        split.direct += term;
+                        split.hit = Some(FirstHit{
+                            position: si.basic.pos,
+                            normal: normal,
+                            depth: (si.basic.pos - ray.ray.origin()).magnitude(),
+                        });
+                    }
+                }
             }
             if let Some(primitive) = si.primitive_hit {
+                if bounces == 0 && primitive.is_shadow_catcher() {
+                    return shadow_catcher_coverage(&mut si, &ray, scene, sampler, alloc);
+                }
                 let dxy = si.compute_dxy(&ray);
-                let bsdf = primitive.get_material().compute_scattering(
+                let mut bsdf = primitive.get_material().compute_scattering(
                     &mut si, &dxy, alloc
                 );
+                if let Some(min_alpha) = regularization {
+                    if rough_bounce { bsdf.regularize(min_alpha, alloc); }
+                }
                 // sample illumination, skip perfect specular
-                let mut tags = BXDF_ALL;
-                tags.remove(BXDF_SPECULAR);
-                if bsdf.have_n(tags) > 0 {
+                if bsdf.have_n(BXDF_DIFFUSE | BXDF_GLOSSY) > 0 {
                     // let term = scene.uniform_sample_all_lights(&si, sampler, &bsdf);
-                    let term = scene.uniform_sample_one_light(&si, sampler, &bsdf);
+                    let (term, group) = scene.uniform_sample_one_light_grouped(&si, sampler, &bsdf);
                     ret += beta * term;
+                    if let Some(ref mut groups) = groups { groups[group] += beta * term; }
+                    if let Some(ref mut channels) = channels {
+                        channels[class.channel() as usize] += beta * term;
+                    }
+                    if let Some(ref mut split) = split {
+                        if bounces == 0 { split.direct += term; }
+                        split.rays += 1;
+                    }
                 }
                 // sample bsdf to get new path direction
                 let wo = -(ray.ray.direction());
-                let (f, wi, pdf, bt) = bsdf.evaluate_sampled(wo, sampler.next_2d(), BXDF_ALL);
-                specular_bounce = bt.intersects(BXDF_SPECULAR);
-                if f.is_black() || pdf == 0. as Float { break; }
-                beta *= f * (wi.dot(si.shading_norm).abs() / pdf);
+                if bounces + 1 >= min_depth {
+                    // paths carry on as much as the surface reflects
+                    let albedo = bsdf.rho(wo, ALBEDO_SAMPLES).to_xyz().y;
+                    survival = float::clamp(albedo, rr_threshold, 1. as Float);
+                }
+                let sample = bsdf.evaluate_sampled(wo, sampler.next_2d(), BXDF_ALL);
+                specular_bounce = sample.is_specular();
+                rough_bounce |= !specular_bounce;
+                if sample.f.is_black() || sample.pdf == 0. as Float { break; }
+                counts.count(sample.kind);
+                class.scatter(sample.kind);
+                if sample.is_transmission() {
+                    interior = if interior.is_some() {
+                        None
+                    } else {
+                        primitive.get_material().absorption()
+                    };
+                }
+                let wi = sample.wi;
+                beta *= sample.f * float::safe_div(wi.dot(si.shading_norm).abs(), sample.pdf);
                 if !beta.valid() {
-                    warn!("invalid beta {:?} encountered from {:?} dot {:?} with pdf {}, breaking current bouncing", beta, wi, si.shading_norm, pdf);
+                    warn!("invalid beta {:?} encountered from {:?} dot {:?} with pdf {}, breaking current bouncing", beta, wi, si.shading_norm, sample.pdf);
                     break;
                 }
                 debug_assert!(beta.inner.y >= 0. as Float);
                 ray = si.spawn_ray_differential(wi, Some(&dxy));
+                ray.ray.set_kind(RayKind::indirect(specular_bounce));
 
             } else {
                 // TODO: handle media boundary
@@ -108,71 +970,147 @@ fn calculate_lighting<S: Sampler>(
             }
         } else {
             // TODO: infinite area lighting
+            if bounces == 0 { return (ret, 0. as Float); }
             break;
         }
 
         bounces += 1;
-        if bounces >= max_depth { break; }
+        if bounces >= max_depth || depths.exceeded_by(&counts) { break; }
 
-        // possibly terminates the path with russian roulette threshold
-        if beta.to_xyz().y < rr_threshold && bounces >= min_depth {
-            let q = rr_threshold.max(0.05 as Float);
-            if sampler.next() < q { break; }
-            beta /= 1.0 as Float - q;
+        // possibly terminates the path with russian roulette
+        if bounces >= min_depth && survival < 1. as Float {
+            if sampler.next() >= survival { break; }
+            beta /= survival;
         }
     }
-    ret
+    (ret, 1. as Float)
+}
+
+/// radiance arriving along `ray`, which continues a path past `depth`
+/// scattering events, the last of them `specular` or not, as estimated
+/// by the path tracer with russian roulette past `min_depth`. Emission
+/// first hit is only counted after specular events, light sampling at
+/// the previous one accounting for it otherwise
+pub fn continue_path<S: Sampler>(
+    ray: RayDifferential,
+    scene: &Scene,
+    sampler: &mut S,
+    alloc: &Allocator,
+    depth: usize,
+    specular: bool,
+    max_depth: usize,
+    min_depth: usize,
+    rr_threshold: Float
+) -> RGBSpectrumf {
+    calculate_lighting(
+        ray, scene, &[], sampler, alloc, depth, specular, max_depth,
+        LobeDepths::uniform(max_depth), min_depth, rr_threshold, None, None, None, None
+    ).0
+}
+
+// a shadow catcher seen by a camera ray is transparent black, as opaque
+// as the fraction of its direct illumination blocked by the scene
+fn shadow_catcher_coverage<S: Sampler>(
+    si: &mut SurfaceInteraction,
+    ray: &RayDifferential,
+    scene: &Scene,
+    sampler: &mut S,
+    alloc: &Allocator
+) -> (RGBSpectrumf, Float) {
+    let primitive = si.primitive_hit.expect("shadow catcher not hit");
+    let dxy = si.compute_dxy(ray);
+    let bsdf = primitive.get_material().compute_scattering(si, &dxy, alloc);
+    let (shadowed, unshadowed) = scene.sample_one_light_shadowing(si, sampler, &bsdf);
+    let unshadowed = unshadowed.to_xyz().y;
+    let alpha = if unshadowed > 0. as Float {
+        1. as Float - float::clamp(shadowed.to_xyz().y / unshadowed, 0. as Float, 1. as Float)
+    } else {
+        0. as Float
+    };
+    (RGBSpectrumf::black(), alpha)
 }
 
 impl<S: Sampler> Renderer for PTRenderer<S> {
-    fn render(&mut self, scene: &Scene) {
-        profile_start!("pt rendering");
-        info!("Path tracing rendering process started");
-        let mut tiles: Vec<FilmTile<RGBSpectrumf>> = self.camera.get_film().spawn_tiles(16, 16);
-        let render_tile = |tile: &mut FilmTile<_>| {
-            let mut sampler = self.sampler.clone();
-            let tile_bound = tile.bounding();
-            let allocator = Allocator::new();
-            for p in tile_bound {
-                let p: Point2<u32> = p.cast();
-                sampler.start_pixel(p);
-                loop {
-                    let camera_sample_info = sampler.get_camera_sample(p);
-                    let mut ray_differential = self.camera.generate_path_differential(camera_sample_info);
-                    ray_differential.scale_differentials(1.0 as Float / sampler.sample_per_pixel() as Float);
-                    profile_start!("pt light calculation");
-                    let total_randiance = calculate_lighting(
-                        ray_differential, scene, &mut sampler, 
-                        &allocator, 0, self.max_depth,
-                        self.min_depth, self.rr_threshold
-                    );
-                    profile_end!("pt light calculation");
+    fn render_image(&mut self, scene: &Scene) -> Image {
+        let mut image = match self.path_filter {
+            Some(ref filter) => self.render_path_buffers(scene).filtered(filter),
+            None => self.render_passes(scene, 0, &[], None, None).0,
+        };
+        if let Some(bloom) = self.bloom {
+            bloom.apply(&mut image);
+        }
+        image
+    }
 
-                    profile_start!("pt add sample");
-                    if total_randiance.valid() {
-                        tile.add_sample(camera_sample_info.pfilm, &total_randiance);
-                    } else {
-                        tile.add_sample(camera_sample_info.pfilm, &RGBSpectrumf::black());
+    fn render(&mut self, scene: &Scene) {
+        let groups = if self.light_groups { scene.light_group_count() } else { 0 };
+        let stem = self.filename.file_stem().and_then(|s| s.to_str()).unwrap_or("pt").to_owned();
+        let ext = self.filename.extension().and_then(|s| s.to_str()).unwrap_or("png").to_owned();
+        if self.albedo_pass {
+            // ahead of the beauty, which leaves its variance on the film
+            let path = self.filename.with_file_name(format!("{}_albedo.{}", stem, ext));
+            if let Err(e) = self.render_albedo(scene).save(&path) {
+                warn!("Saving the albedo pass at {:?} failed: {}", path, e);
+            }
+        }
+        let (render_result, group_results) = match self.path_filter {
+            Some(ref filter) => (self.render_path_buffers(scene).filtered(filter), Vec::new()),
+            None => {
+                let preview = self.preview_interval.map(|interval| {
+                    self.camera.get_film().spawn_preview(&self.filename, interval)
+                });
+                let dump = self.sample_dump.as_ref().and_then(|path| {
+                    match SampleDumpWriter::create(path, &self.camera.get_film()) {
+                        Ok(dump) => Some(dump),
+                        Err(e) => {
+                            warn!("Creating the sample dump at {:?} failed: {}", path, e);
+                            None
+                        },
+                    }
+                });
+                let (image, groups, counts) = self.render_passes(scene, groups, &self.channels, preview.as_ref(), dump.as_ref());
+                if let Some(dump) = dump {
+                    match dump.finish() {
+                        Ok(_) => info!("Raw samples dumped at {:?}", self.sample_dump),
+                        Err(e) => warn!("Dumping samples at {:?} failed: {}", self.sample_dump, e),
                     }
-                    profile_end!("pt add sample");
-                    if !sampler.next_sample() { break; }
                 }
-            }
-            // println!("tile {:?} done!", tile_bound);
+                if self.adaptive.is_some() {
+                    let path = self.filename.with_file_name(format!("{}_samples.{}", stem, ext));
+                    if let Err(e) = sample_count_map(&counts).save(&path) {
+                        warn!("Saving the sample count map at {:?} failed: {}", path, e);
+                    }
+                }
+                (image, groups)
+            },
         };
-        if self.multithreaded {
-            tiles.par_iter_mut().for_each(|tile| render_tile(tile));
-        } else {
-            for tile in &mut tiles { render_tile(tile); }
+        let mut render_result = render_result;
+        if let Some(bloom) = self.bloom {
+            bloom.apply(&mut render_result);
         }
-        let render_result = self.camera.get_film().collect_into(tiles);
-        profile_end!("pt rendering");
-        info!("Path tracing rendering process ended");
         if let Ok(_) = render_result.save(&self.filename) {
             info!("Path tracing result saved at {:?}", self.filename);
         } else {
             warn!("Path tracing result saving at {:?} failed", self.filename);
         }
+        let mut group_results = group_results.into_iter();
+        for (i, image) in group_results.by_ref().take(groups).enumerate() {
+            let path = self.filename.with_file_name(format!("{}_group{}.{}", stem, i, ext));
+            if let Err(e) = image.save(&path) {
+                warn!("Saving light group {} at {:?} failed: {}", i, path, e);
+            }
+        }
+        for (channel, image) in self.channels.iter().zip(group_results) {
+            let path = self.filename.with_file_name(format!("{}_{}.{}", stem, channel.name(), ext));
+            if let Err(e) = image.save(&path) {
+                warn!("Saving the {} channel at {:?} failed: {}", channel.name(), path, e);
+            }
+        }
+        if self.id_pass {
+            if let Err(e) = self.render_ids(scene).save(&self.filename) {
+                warn!("Saving the id pass next to {:?} failed: {}", self.filename, e);
+            }
+        }
         profile_dump!("pt rendering results.html");
     }
 }
\ No newline at end of file
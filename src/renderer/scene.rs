@@ -8,16 +8,40 @@
 
 //! A scene in the world.
 
-use component::Composable;
-use lighting::Light;
+use component::{Composable, Primitive};
+use lighting::{Light, LightSample, LightFlag, LIGHT_AREA, LIGHT_INFINITE, LIGHT_DDIR, LIGHT_DPOS};
+use std::fmt;
 use std::sync::Arc;
+use std::collections::HashMap;
 use sample::prelude::*;
 use sample;
 use spectrum::{Spectrum, RGBSpectrumf};
 use material::bsdf::Bsdf;
 use bxdf::prelude::*;
 use geometry::prelude::*;
-use std::ptr;
+use filming::clip::{self, ClipPlane};
+
+/// Maximum number of light groups. Rendering light groups separately
+/// takes an additional film-sized buffer for each group in use.
+pub const MAX_LIGHT_GROUPS: usize = 8;
+
+// a light sample for direct lighting, awaiting its occlusion test
+struct DirectSample {
+    ls: LightSample,
+    // bsdf value times the cosine term, black if not worth shadowing
+    f: RGBSpectrumf,
+    spdf: Float,
+}
+
+impl DirectSample {
+    #[inline]
+    fn needs_shadow_ray(&self) -> bool {
+        !self.f.is_black()
+    }
+}
+
+/// clipped hits a camera ray skips at most before it's taken as a miss
+const MAX_CLIPPED_HITS: usize = 64;
 
 /// A scene in the world
 pub struct Scene {
@@ -25,6 +49,10 @@ pub struct Scene {
     // pub area_lights: Vec<Arc<Composable>>,
     pub light_distribution: Distribution1D,
     pub aggregate: Arc<Composable>,
+    /// light group of each light, all `0` by default
+    pub light_groups: Vec<u8>,
+    /// id of each primitive of the aggregate, keyed by `primitive_key`
+    primitive_ids: HashMap<usize, u32>,
 }
 
 impl Scene {
@@ -42,37 +70,134 @@ impl Scene {
         //     func.push(component.as_light().power().to_xyz().y);
         // }
         let light_distribution = Distribution1D::new(func);
+        let light_groups = vec![0; lights.len()];
+        let mut primitive_ids = HashMap::new();
+        aggregate.visit_primitives(&mut |primitive| {
+            let next = primitive_ids.len() as u32 + 1;
+            primitive_ids.entry(primitive_key(primitive)).or_insert(next);
+        });
         Scene{
             lights: lights,
             // area_lights: area_lights,
             light_distribution: light_distribution,
             aggregate: aggregate,
+            light_groups: light_groups,
+            primitive_ids: primitive_ids,
         }
     }
 
+    /// first hit of `ray` with the aggregate, skipping hits clipped away
+    /// by any of `planes` if `ray` is a camera ray, and plainly the first
+    /// hit otherwise. As with cutouts, the ray is respawned past each
+    /// clipped hit, keeping its origin so its differentials still hold
+    pub fn intersect_clipped(&self, ray: &mut RawRay, planes: &[ClipPlane]) -> Option<SurfaceInteraction> {
+        if planes.is_empty() || ray.kind() != RayKind::Camera {
+            return self.aggregate.intersect_ray(ray);
+        }
+        let tmax = ray.max_extend();
+        for _ in 0..MAX_CLIPPED_HITS {
+            let si = self.aggregate.intersect_ray(ray)?;
+            if !clip::clipped(planes, si.basic.pos) {
+                return Some(si);
+            }
+            let thit = ray.max_extend();
+            ray.set_min_extend(float::next_up(thit));
+            ray.set_max_extend(tmax);
+        }
+        None
+    }
+
+    /// id of `primitive`, assigned in the order primitives of the aggregate
+    /// are visited, starting from `1`. Triangles share the id of their mesh.
+    /// `0` if `primitive` is not part of the aggregate.
+    #[inline]
+    pub fn primitive_id(&self, primitive: &Primitive) -> u32 {
+        self.primitive_ids.get(&primitive_key(primitive)).cloned().unwrap_or(0)
+    }
+
+    /// number of distinct primitive ids in use
+    #[inline]
+    pub fn primitive_id_count(&self) -> usize {
+        self.primitive_ids.len()
+    }
+
     #[inline]
     pub fn get_light(&self, idx: usize) -> &Light {
         self.lights[idx].as_ref()
     }
 
+    /// assign the `idx`th light to `group`, which should be
+    /// less than `MAX_LIGHT_GROUPS`
+    #[inline]
+    pub fn set_light_group(&mut self, idx: usize, group: u8) {
+        assert!((group as usize) < MAX_LIGHT_GROUPS, "light group {} out of range", group);
+        self.light_groups[idx] = group;
+    }
+
+    /// number of light groups in use
+    #[inline]
+    pub fn light_group_count(&self) -> usize {
+        self.light_groups.iter().map(|&g| g as usize + 1).max().unwrap_or(1)
+    }
+
+    /// light group of the light `primitive` is part of, or `0`
+    /// if it is not part of any light of the scene
+    pub fn light_group_of(&self, primitive: &Primitive) -> usize {
+        self.lights.iter().position(|light| {
+            same_light(light.as_ref(), primitive.as_light()) || light.emits_from(primitive)
+        }).map_or(0, |i| self.light_groups[i] as usize)
+    }
+
     pub fn uniform_sample_one_light<S: Sampler>(
         &self, si: &SurfaceInteraction, sampler: &mut S, bsdf: &Bsdf
     ) -> RGBSpectrumf {
+        self.uniform_sample_one_light_grouped(si, sampler, bsdf).0
+    }
+
+    /// as `uniform_sample_one_light`, also returning
+    /// the light group of the sampled light
+    pub fn uniform_sample_one_light_grouped<S: Sampler>(
+        &self, si: &SurfaceInteraction, sampler: &mut S, bsdf: &Bsdf
+    ) -> (RGBSpectrumf, usize) {
         trace!("Sampling one light at {:?}", si);
-        let (light, lightpdf) = self.sample_one_light(sampler.next());
+        let (idx, lightpdf, _) = self.light_distribution.sample_discrete(sampler.next());
+        let light = self.get_light(idx);
         let ulight = sampler.next_2d();
         let uscattering = sampler.next_2d();
-        self.evaluate_direct(light, ulight, uscattering, si, bsdf)/lightpdf
+        (
+            self.evaluate_direct(light, ulight, uscattering, si, bsdf)/lightpdf,
+            self.light_groups[idx] as usize
+        )
     }
 
     pub fn uniform_sample_all_lights<S: Sampler>(
         &self, si: &SurfaceInteraction, sampler: &mut S, bsdf: &Bsdf
     ) -> RGBSpectrumf {
-        let mut ret = RGBSpectrumf::black();
+        // sample every light before testing their shadow rays in one batch
+        let mut samples = Vec::with_capacity(self.lights.len());
+        let mut rays = Vec::with_capacity(self.lights.len());
         for light in self.lights.iter() {
             let ulight = sampler.next_2d();
             let uscattering = sampler.next_2d();
-            let term = self.evaluate_direct(light.as_ref(), ulight, uscattering, si, bsdf);
+            let sample = self.sample_direct(light.as_ref(), ulight, si, bsdf);
+            if let Some(ref sample) = sample {
+                if sample.needs_shadow_ray() {
+                    rays.push(sample.ls.shadow_ray_from(si.shadow_origin(sample.ls.wi())));
+                }
+            }
+            samples.push((sample, uscattering));
+        }
+        let mut occluded = vec![false; rays.len()];
+        self.aggregate.occluded_batch(&mut rays, &mut occluded);
+        let mut occluded = occluded.into_iter();
+
+        let mut ret = RGBSpectrumf::black();
+        for (light, (sample, uscattering)) in self.lights.iter().zip(samples) {
+            let sample = sample.map(|sample| {
+                let occluded = sample.needs_shadow_ray() && occluded.next().unwrap();
+                (sample, occluded)
+            });
+            let term = self.combine_direct(light.as_ref(), sample, uscattering, si, bsdf);
             if term.valid() {
                 ret += term;
             }
@@ -80,6 +205,29 @@ impl Scene {
         ret
     }
 
+    /// sample one light's direct illumination at `si` by light sampling
+    /// alone, returning it with and without occlusion by the scene
+    pub fn sample_one_light_shadowing<S: Sampler>(
+        &self, si: &SurfaceInteraction, sampler: &mut S, bsdf: &Bsdf
+    ) -> (RGBSpectrumf, RGBSpectrumf) {
+        let (light, lightpdf) = self.sample_one_light(sampler.next());
+        let ls = light.evaluate_sampled(si.basic.pos, sampler.next_2d());
+        if ls.no_effect() {
+            return (RGBSpectrumf::black(), RGBSpectrumf::black());
+        }
+        let wi = ls.wi();
+        let f = bsdf.evaluate(si.basic.wo, wi, BXDF_ALL).0 * wi.dot(si.shading_norm).abs();
+        let unshadowed = ls.radiance * f * float::safe_div(1. as Float, ls.pdf * lightpdf);
+        if !unshadowed.valid() || unshadowed.is_black() {
+            return (RGBSpectrumf::black(), RGBSpectrumf::black());
+        }
+        if self.aggregate.can_intersect(&ls.shadow_ray_from(si.shadow_origin(wi))) {
+            (RGBSpectrumf::black(), unshadowed)
+        } else {
+            (unshadowed, unshadowed)
+        }
+    }
+
     fn evaluate_direct(&self,
         light: &Light, ulight: Point2f, uscattering: Point2f,
         si: &SurfaceInteraction, bsdf: &Bsdf
@@ -88,25 +236,48 @@ impl Scene {
             "evaluating light {:p}, si {:p}, bsdf {:p}， ulight: {:?}, uscatter: {:?}", 
             light, si, bsdf, ulight, uscattering
         );
-        let mut ret = RGBSpectrumf::black();
+        let sample = self.sample_direct(light, ulight, si, bsdf).map(|sample| {
+            let occluded = sample.needs_shadow_ray() && self.aggregate.can_intersect(
+                &sample.ls.shadow_ray_from(si.shadow_origin(sample.ls.wi()))
+            );
+            (sample, occluded)
+        });
+        self.combine_direct(light, sample, uscattering, si, bsdf)
+    }
+
+    // the light sampling half of `evaluate_direct`, up to the occlusion test
+    fn sample_direct(&self,
+        light: &Light, ulight: Point2f, si: &SurfaceInteraction, bsdf: &Bsdf
+    ) -> Option<DirectSample> {
         let ls = light.evaluate_sampled(si.basic.pos, ulight);
         trace!("sampled ls: {:?}", ls);
+        if ls.no_effect() { return None; }
         let wi = ls.wi();
-        if !ls.no_effect() {
-            let mut f = bsdf.evaluate(
-                si.basic.wo, wi, BXDF_ALL
-            ).0 * wi.dot(si.shading_norm).abs();
-            let spdf = bsdf.pdf(si.basic.wo, wi, BXDF_ALL);
-            trace!("with bsdf {:?}, spdf {:?}", f, spdf);
-            if spdf == 0. as Float {
-                f = RGBSpectrumf::black();
-            }
-            if !f.is_black() && ls.occluded(&*self.aggregate) {
+        let mut f = bsdf.evaluate(
+            si.basic.wo, wi, BXDF_ALL
+        ).0 * wi.dot(si.shading_norm).abs();
+        let spdf = bsdf.pdf(si.basic.wo, wi, BXDF_ALL);
+        trace!("with bsdf {:?}, spdf {:?}", f, spdf);
+        if spdf == 0. as Float {
+            f = RGBSpectrumf::black();
+        }
+        Some(DirectSample{ls: ls, f: f, spdf: spdf})
+    }
+
+    // the rest of `evaluate_direct`, given the light sample
+    // and whether it is occluded
+    fn combine_direct(&self,
+        light: &Light, sample: Option<(DirectSample, bool)>, uscattering: Point2f,
+        si: &SurfaceInteraction, bsdf: &Bsdf
+    ) -> RGBSpectrumf {
+        let mut ret = RGBSpectrumf::black();
+        if let Some((DirectSample{ls, mut f, spdf}, occluded)) = sample {
+            if occluded {
                 f = RGBSpectrumf::black();
                 trace!("occluded");
             }
             if light.is_delta() {
-                let addition = ls.radiance * f / ls.pdf;
+                let addition = ls.radiance * f * float::safe_div(1. as Float, ls.pdf);
                 trace!(
                     "dlight, adding {:?}", addition
                 );
@@ -116,7 +287,7 @@ impl Scene {
                 ret += addition;
             } else {
                 let weight = sample::power_heuristic(1, ls.pdf, 1, spdf);
-                let addition = ls.radiance * f * weight / ls.pdf;
+                let addition = ls.radiance * f * float::safe_div(weight, ls.pdf);
                 trace!("ndlight, MISw {}, adding {:?}", weight, addition);
                 if !addition.valid() {
                     warn!("invalid adding {:?} from light sampling", addition);
@@ -127,7 +298,7 @@ impl Scene {
         
         // sample BSDF with multiple importance sampling
         if !light.is_delta() {
-            let (mut f, wi, pdf, bt) = bsdf.evaluate_sampled(
+            let BsdfSample{mut f, wi, pdf, kind: bt, ..} = bsdf.evaluate_sampled(
                 si.basic.wo, uscattering, BXDF_ALL
             );
             f *= wi.dot(si.shading_norm).abs();
@@ -144,17 +315,18 @@ impl Scene {
                 }
                 trace!("MISw {}", weight);
                 let mut ray = si.spawn_ray_differential(wi, None);
+                ray.ray.set_kind(RayKind::indirect(bt.intersects(BXDF_SPECULAR)));
                 let mut li = RGBSpectrumf::black();
                 if let Some(lsi) = self.aggregate.intersect_ray(&mut ray.ray) {
                     if let Some(primitive) = lsi.primitive_hit {
-                        if ptr::eq(light, primitive.as_light()) {
+                        if same_light(light, primitive.as_light()) || light.emits_from(primitive) {
                             li = lsi.le(-wi);
                             trace!("li {:?}", li);
                         }
                     }
                 }
                 if !li.is_black() {
-                    let addition = f * li * weight / pdf;
+                    let addition = f * li * float::safe_div(weight, pdf);
                     if !addition.valid() {
                         warn!("invalid adding {:?} from bsdf sampling", addition);
                     }
@@ -171,4 +343,82 @@ impl Scene {
         let (idx, pdf, _) = self.light_distribution.sample_discrete(u);
         (self.get_light(idx), pdf)
     }
-}
\ No newline at end of file
+
+    /// resident statistics of the scene, for sanity checking before rendering
+    pub fn summary(&self) -> SceneSummary {
+        SceneSummary{
+            primitives: self.aggregate.component_count(),
+            bounds: self.aggregate.bbox_parent(),
+            lights: self.lights.iter().map(|l| (light_kind(l.flags()), l.power())).collect(),
+            memory: self.aggregate.memory_estimate(),
+        }
+    }
+}
+
+/// Resident statistics of a scene
+#[derive(Clone, Debug)]
+pub struct SceneSummary {
+    /// number of primitives in the aggregate
+    pub primitives: usize,
+    /// world bounds of the aggregate
+    pub bounds: BBox3f,
+    /// kind and power of each light
+    pub lights: Vec<(&'static str, RGBSpectrumf)>,
+    /// approximate memory taken by the aggregate, in bytes
+    pub memory: usize,
+}
+
+impl fmt::Display for SceneSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "primitives: {}", self.primitives)?;
+        writeln!(
+            f, "bounds: ({}, {}, {}) to ({}, {}, {})",
+            self.bounds.pmin.x, self.bounds.pmin.y, self.bounds.pmin.z,
+            self.bounds.pmax.x, self.bounds.pmax.y, self.bounds.pmax.z
+        )?;
+        writeln!(f, "lights: {}", self.lights.len())?;
+        for &(kind, power) in &self.lights {
+            writeln!(
+                f, "  {}, power ({:.3}, {:.3}, {:.3}) W", kind, power.r(), power.g(), power.b()
+            )?;
+        }
+        let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+        let mut memory = self.memory as f64;
+        let mut unit = 0;
+        while memory >= 1024. && unit + 1 < units.len() {
+            memory /= 1024.;
+            unit += 1;
+        }
+        write!(f, "memory: {:.1} {}", memory, units[unit])
+    }
+}
+
+fn light_kind(flags: LightFlag) -> &'static str {
+    if flags.contains(LIGHT_AREA) {
+        "area"
+    } else if flags.contains(LIGHT_INFINITE) {
+        "infinite"
+    } else if flags.contains(LIGHT_DDIR) {
+        "directional"
+    } else if flags.contains(LIGHT_DPOS) {
+        "point"
+    } else {
+        "unknown"
+    }
+}
+
+/// identity of `primitive`, shared by the triangles of a mesh
+#[inline]
+fn primitive_key(primitive: &Primitive) -> usize {
+    if let Some(mesh) = primitive.parent_mesh() {
+        mesh as *const _ as usize
+    } else {
+        primitive as *const Primitive as *const u8 as usize
+    }
+}
+
+/// test if two lights are the same object, ignoring vtables
+#[inline]
+pub fn same_light(a: &Light, b: &Light) -> bool {
+    a as *const Light as *const u8 == b as *const Light as *const u8
+}
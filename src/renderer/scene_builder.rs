@@ -0,0 +1,433 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A builder for scenes constructed in code, along with helpers
+//! for common materials.
+//!
+//! See `examples/whitted.rs` for a scene built this way.
+
+use prelude::*;
+use component::{self, ComponentPointer};
+use filming::film::Film;
+use sample::filters::MitchellFilter;
+use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::fmt;
+use tobj;
+
+/// a lambertian material of constant reflectance `color`, with
+/// Oren-Nayar roughness `sigma` in degrees
+pub fn matte(color: RGBSpectrumf, sigma: Float) -> Arc<Material> {
+    Arc::new(MatteMaterial::new(
+        Arc::new(ConstantTexture{value: color}),
+        Arc::new(ConstantTexture{value: sigma}),
+        None
+    ))
+}
+
+/// a glossy coated material of constant `diffuse` and `specular`
+/// reflectance and `roughness`
+pub fn plastic(diffuse: RGBSpectrumf, specular: RGBSpectrumf, roughness: Float) -> Arc<Material> {
+    Arc::new(PlasticMaterial::new(
+        Arc::new(ConstantTexture{value: diffuse}),
+        Arc::new(ConstantTexture{value: specular}),
+        Arc::new(ConstantTexture{value: roughness}),
+        None
+    ))
+}
+
+/// a clear, perfectly smooth glass of index of refraction `eta`
+pub fn glass(eta: Float) -> Arc<Material> {
+    Arc::new(GlassMaterial::new(
+        Arc::new(ConstantTexture{value: RGBSpectrumf::grey_scale(1. as Float)}),
+        Arc::new(ConstantTexture{value: RGBSpectrumf::grey_scale(1. as Float)}),
+        Arc::new(ConstantTexture{value: 0. as Float}),
+        eta, None
+    ))
+}
+
+/// a perfect mirror of constant reflectance `color`
+pub fn mirror(color: RGBSpectrumf) -> Arc<Material> {
+    Arc::new(MirrorMaterial::new(Arc::new(ConstantTexture{value: color}), None))
+}
+
+/// Reasons for a `SceneBuilder` to fail building
+#[derive(Debug)]
+pub enum SceneBuildError {
+    /// no camera is set
+    NoCamera,
+    /// no object is added
+    NoObjects,
+    /// the object of the index has a non-positive radius
+    InvalidRadius(usize, Float),
+    /// the object of the index is placed by a non-invertible transform
+    SingularTransform(usize),
+    /// the mesh of the index can't be loaded from the file
+    MeshLoad(usize, PathBuf, component::ObjError),
+    /// the scene is to be camera relative, but the camera is set
+    /// by `set_camera` and can't be moved
+    FixedCamera,
+}
+
+impl fmt::Display for SceneBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SceneBuildError::NoCamera => f.write_str("no camera is set"),
+            SceneBuildError::NoObjects => f.write_str("the scene has no object"),
+            SceneBuildError::InvalidRadius(i, radius) => write!(
+                f, "radius of object {} should be positive, got {}", i, radius
+            ),
+            SceneBuildError::SingularTransform(i) => write!(
+                f, "transform of object {} is not invertible", i
+            ),
+            SceneBuildError::MeshLoad(i, ref path, ref e) => write!(
+                f, "loading object {} from {:?} failed: {}", i, path, e
+            ),
+            SceneBuildError::FixedCamera => f.write_str(
+                "a camera set by `set_camera` can't be moved to the origin"
+            ),
+        }
+    }
+}
+
+enum ObjectShape {
+    Sphere(Float),
+    Mesh(PathBuf),
+}
+
+struct ObjectDesc {
+    shape: ObjectShape,
+    transform: Matrix4f,
+    material: Option<Arc<Material>>,
+    emission: Option<RGBSpectrumf>,
+    emission_side: EmissionSide,
+}
+
+enum Item {
+    Object(ObjectDesc),
+    PointLight(Point3f, RGBSpectrumf),
+    Light(Arc<Light>),
+}
+
+enum CameraDesc {
+    LookAt{eye: Point3f, to: Point3f, fov: Float, resolution: (usize, usize)},
+    Fixed(Arc<Camera>),
+}
+
+/// Translation moving a scene so that its camera sits at the origin,
+/// for precision with large coordinates.
+///
+/// The translation is folded into the transforms of the scene rather
+/// than wrapped around them, so that intersection and shading happen
+/// near the origin.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Recentering {
+    offset: Vector3f,
+}
+
+impl Recentering {
+    /// subtracting `offset` from positions
+    pub fn new(offset: Vector3f) -> Recentering {
+        Recentering{offset}
+    }
+
+    /// moving the camera placed by `view_parent` to the origin
+    pub fn around_camera(view_parent: Matrix4f) -> Recentering {
+        Recentering::new(view_parent.w.truncate())
+    }
+
+    /// the offset subtracted from positions
+    #[inline]
+    pub fn offset(&self) -> Vector3f {
+        self.offset
+    }
+
+    /// `p` moved
+    #[inline]
+    pub fn point(&self, p: Point3f) -> Point3f {
+        p + -self.offset
+    }
+
+    /// `m` followed by the translation, computed exactly when the
+    /// translated coordinates are representable
+    pub fn transform(&self, mut m: Matrix4f) -> Matrix4f {
+        for c in 0..4 {
+            let w = m[c].w;
+            m[c].x -= self.offset.x * w;
+            m[c].y -= self.offset.y * w;
+            m[c].z -= self.offset.z * w;
+        }
+        m
+    }
+
+    /// `m` applied to what is moved already
+    #[inline]
+    pub fn conjugate(&self, m: Matrix4f) -> Matrix4f {
+        self.transform(m) * Matrix4f::from_translation(self.offset)
+    }
+
+    /// parent to view transform of the camera placed by `view_parent`, moved
+    pub fn camera(&self, view_parent: Matrix4f) -> Matrix4f {
+        self.transform(view_parent).inverse_transform().expect("matrix inversion failure")
+    }
+}
+
+/// Builds a `Scene` along with the camera viewing it, taking care of
+/// transform inversion, reference counting, the aggregate and lights.
+///
+/// Lights are registered in the order they are added, emissive
+/// objects included.
+pub struct SceneBuilder {
+    items: Vec<Item>,
+    camera: Option<CameraDesc>,
+    strategy: BVHStrategy,
+    camera_relative: bool,
+}
+
+/// An object added to a `SceneBuilder`, further specified by chaining
+pub struct ObjectBuilder<'a> {
+    desc: &'a mut ObjectDesc,
+}
+
+impl<'a> ObjectBuilder<'a> {
+    /// move the object to `(x, y, z)`, after any transform so far
+    pub fn at(self, x: Float, y: Float, z: Float) -> Self {
+        self.transform(Matrix4f::from_translation(Vector3f::new(x, y, z)))
+    }
+
+    /// apply `transform` to the object, after any transform so far
+    pub fn transform(self, transform: Matrix4f) -> Self {
+        self.desc.transform = transform * self.desc.transform;
+        self
+    }
+
+    /// material of the object. Meshes keep the materials of their
+    /// `.mtl` if none is given. Defaults to a grey `matte` otherwise.
+    pub fn material(self, material: Arc<Material>) -> Self {
+        self.desc.material = Some(material);
+        self
+    }
+
+    /// make the object emit `radiance`, registering it as a light
+    pub fn emissive(self, radiance: RGBSpectrumf) -> Self {
+        self.desc.emission = Some(radiance);
+        self
+    }
+
+    /// which side(s) of the object emit light
+    pub fn emission_side(self, side: EmissionSide) -> Self {
+        self.desc.emission_side = side;
+        self
+    }
+}
+
+impl SceneBuilder {
+    /// an empty builder, building a `BVH` by surface area heuristics
+    pub fn new() -> SceneBuilder {
+        SceneBuilder{
+            items: Vec::new(),
+            camera: None,
+            strategy: BVHStrategy::SAH,
+            camera_relative: false,
+        }
+    }
+
+    fn object(&mut self, shape: ObjectShape) -> ObjectBuilder {
+        self.items.push(Item::Object(ObjectDesc{
+            shape: shape,
+            transform: Matrix4f::identity(),
+            material: None,
+            emission: None,
+            emission_side: EmissionSide::default(),
+        }));
+        match self.items.last_mut() {
+            Some(&mut Item::Object(ref mut desc)) => ObjectBuilder{desc: desc},
+            _ => unreachable!(),
+        }
+    }
+
+    /// add a full sphere of `radius` centered at the origin
+    pub fn sphere(&mut self, radius: Float) -> ObjectBuilder {
+        self.object(ObjectShape::Sphere(radius))
+    }
+
+    /// add the meshes of the `.obj` file at `path`, along with the
+    /// lights their `.mtl` make up
+    pub fn mesh<P: AsRef<Path> + ?Sized>(&mut self, path: &P) -> ObjectBuilder {
+        self.object(ObjectShape::Mesh(path.as_ref().to_path_buf()))
+    }
+
+    /// add a point light at `pos`
+    pub fn point_light(&mut self, pos: Point3f, intensity: RGBSpectrumf) -> &mut Self {
+        self.items.push(Item::PointLight(pos, intensity));
+        self
+    }
+
+    /// add any other light
+    pub fn light(&mut self, light: Arc<Light>) -> &mut Self {
+        self.items.push(Item::Light(light));
+        self
+    }
+
+    /// a perspective camera at `eye` looking at `to` with `y` up, with
+    /// a vertical field of view of `fov` in radians and a
+    /// `resolution` of `(width, height)` pixels
+    pub fn camera(&mut self, eye: Point3f, to: Point3f, fov: Float, resolution: (usize, usize)) -> &mut Self {
+        self.camera = Some(CameraDesc::LookAt{eye, to, fov, resolution});
+        self
+    }
+
+    /// use `camera` as is
+    pub fn set_camera(&mut self, camera: Arc<Camera>) -> &mut Self {
+        self.camera = Some(CameraDesc::Fixed(camera));
+        self
+    }
+
+    /// move the whole scene so the camera sits at the origin, by a
+    /// `Recentering`, for precision with large coordinates. Lights
+    /// added by `light` are wrapped in the translation instead.
+    ///
+    /// The camera must be set by `camera`.
+    pub fn camera_relative(&mut self, camera_relative: bool) -> &mut Self {
+        self.camera_relative = camera_relative;
+        self
+    }
+
+    /// strategy the `BVH` over the objects is built by
+    pub fn bvh_strategy(&mut self, strategy: BVHStrategy) -> &mut Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// build the scene along with its camera
+    pub fn build(self) -> Result<(Scene, Arc<Camera>), SceneBuildError> {
+        let (camera, recentering) = match (self.camera, self.camera_relative) {
+            (None, _) => return Err(SceneBuildError::NoCamera),
+            (Some(CameraDesc::Fixed(_)), true) => return Err(SceneBuildError::FixedCamera),
+            (Some(CameraDesc::Fixed(camera)), false) => (camera, None),
+            (Some(CameraDesc::LookAt{eye, to, fov, resolution}), false) => {
+                (Arc::new(look_at(eye, to, fov, resolution)) as Arc<Camera>, None)
+            },
+            // moved by its position as given, which the view transform
+            // only approximates far from the origin
+            (Some(CameraDesc::LookAt{eye, to, fov, resolution}), true) => {
+                let recentering = Recentering::new(eye - Point3f::new(0. as Float, 0., 0.));
+                let camera = look_at(recentering.point(eye), recentering.point(to), fov, resolution);
+                (Arc::new(camera) as Arc<Camera>, Some(recentering))
+            },
+        };
+        let mut components: Vec<ComponentPointer> = Vec::new();
+        let mut lights = Vec::new();
+        let mut index = 0;
+        for item in self.items {
+            let mut desc = match item {
+                Item::PointLight(pos, intensity) => {
+                    let pos = recentering.map_or(pos, |r| r.point(pos));
+                    lights.push(Arc::new(PointLight::new(pos, intensity)) as Arc<Light>);
+                    continue;
+                },
+                Item::Light(light) => {
+                    lights.push(match recentering {
+                        Some(r) => Arc::new(TransformedComposable::new(
+                            light,
+                            Arc::new(Matrix4f::from_translation(-r.offset())),
+                            Arc::new(Matrix4f::from_translation(r.offset()))
+                        )) as Arc<Light>,
+                        None => light,
+                    });
+                    continue;
+                },
+                Item::Object(desc) => desc,
+            };
+            if let Some(r) = recentering {
+                desc.transform = r.transform(desc.transform);
+            }
+            let inv = desc.transform.invert().ok_or(SceneBuildError::SingularTransform(index))?;
+            let emission = desc.emission.map(|e| {
+                Arc::new(ConstantTexture{value: e}) as Arc<Texture<Texel=RGBSpectrumf>>
+            });
+            match desc.shape {
+                ObjectShape::Sphere(radius) => {
+                    if !(radius > 0. as Float) {
+                        return Err(SceneBuildError::InvalidRadius(index, radius));
+                    }
+                    let material = desc.material.unwrap_or_else(default_material);
+                    let mut sp = ShapedPrimitive::new(Sphere::full(radius), material, emission);
+                    sp.emission_side = desc.emission_side;
+                    let sp = Arc::new(TransformedComposable::new(
+                        sp, Arc::new(desc.transform), Arc::new(inv)
+                    ));
+                    if sp.is_emissive() { lights.push(sp.clone() as Arc<Light>); }
+                    components.push((sp as Arc<Composable>).into());
+                },
+                ObjectShape::Mesh(ref path) => {
+                    let map_err = |e| SceneBuildError::MeshLoad(index, path.clone(), e);
+                    if desc.material.is_none() && emission.is_none() {
+                        let options = component::ObjOptions{
+                            emission_side: desc.emission_side,
+                            ..component::ObjOptions::default()
+                        };
+                        let (ptrs, meshlights) = component::load_obj_with_options(path, desc.transform, options)
+                            .map_err(map_err)?;
+                        components.extend(ptrs);
+                        lights.extend(meshlights.into_iter().map(|l| l as Arc<Light>));
+                    } else {
+                        // the `.mtl` is left out for the given material
+                        let (models, _) = tobj::load_obj(path).map_err(|e| map_err(e.into()))?;
+                        let material = desc.material.unwrap_or_else(default_material);
+                        for model in models {
+                            let mut mesh = TriangleMesh::from_model_transformed(
+                                model, desc.transform, material.clone(), emission.clone()
+                            );
+                            mesh.set_emission_side(desc.emission_side);
+                            let mesh = Arc::new(mesh);
+                            components.extend(TriangleMesh::instances(&mesh).into_iter().map(|t| t.into()));
+                            if mesh.is_emissive() {
+                                lights.push(Arc::new(MeshLight::new(mesh)));
+                            }
+                        }
+                    }
+                },
+            }
+            index += 1;
+        }
+        if components.is_empty() { return Err(SceneBuildError::NoObjects); }
+        let aggregate = Arc::new(BVH::new(&components, self.strategy));
+        Ok((Scene::new(lights, aggregate), camera))
+    }
+}
+
+impl Default for SceneBuilder {
+    #[inline]
+    fn default() -> SceneBuilder {
+        SceneBuilder::new()
+    }
+}
+
+// the camera of `SceneBuilder::camera`
+fn look_at(eye: Point3f, to: Point3f, fov: Float, resolution: (usize, usize)) -> PerspecCam {
+    let aspect = resolution.0 as Float / resolution.1.max(1) as Float;
+    let film = Film::new(
+        Point2::new(resolution.0, resolution.1),
+        BBox2f::new(Point2f::new(0. as Float, 0. as Float), Point2f::new(1. as Float, 1. as Float)),
+        Arc::new(MitchellFilter::new(
+            Vector2f::new(2. as Float, 2. as Float), 1. as Float / 3. as Float, 1. as Float / 3. as Float
+        ))
+    );
+    let mut camera = PerspecCam::new(
+        Matrix4f::identity(),
+        BBox2f::new(Point2f::new(-aspect, -1. as Float), Point2f::new(aspect, 1. as Float)),
+        0.01 as Float, 1000. as Float, fov, None, film
+    );
+    camera.look_from(eye, to, Vector3f::new(0. as Float, 1. as Float, 0. as Float));
+    camera
+}
+
+fn default_material() -> Arc<Material> {
+    matte(RGBSpectrumf::grey_scale(0.5 as Float), 0. as Float)
+}
@@ -0,0 +1,2376 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod test_builder {
+    use test_utils::*;
+    use prelude::*;
+    use sample::naive::Naive;
+
+    fn builder(resolution: usize, spp: usize) -> RendererBuilder<Naive> {
+        let camera = camera(
+            resolution,
+            Point3f::new(0. as Float, 2.5 as Float, -4. as Float),
+            Point3f::new(0. as Float, 2.5 as Float, 0. as Float),
+            float::frac_pi_2()
+        );
+        RendererBuilder::new(Naive::new(spp), camera)
+    }
+
+    #[test]
+    fn test_validation() {
+        assert_eq!(builder(8, 1).max_depth(0).build_pt().err(), Some(ConfigError::ZeroDepth));
+        assert_eq!(builder(8, 1).max_depth(0).build_bpt().err(), Some(ConfigError::ZeroDepth));
+        assert_eq!(builder(0, 1).build_pt().err(), Some(ConfigError::ZeroResolution));
+        assert_eq!(builder(0, 1).build_whitted().err(), Some(ConfigError::ZeroResolution));
+        assert_eq!(builder(8, 0).build_bpt().err(), Some(ConfigError::ZeroSamples));
+        for output in &["", "out", "out.exr", "out.png.txt"] {
+            let err = builder(8, 1).output(output).build_whitted().err();
+            match err {
+                Some(ConfigError::UnsupportedOutput(_)) => {},
+                _ => panic!("{:?} accepted", output),
+            }
+        }
+        assert!(builder(8, 1).output("OUT.PNG").build_whitted().is_ok());
+        let ao = AmbientOcclusion{max_distance: 0. as Float, samples: 4, falloff: OcclusionFalloff::Linear};
+        assert_eq!(
+            builder(8, 1).ambient_occlusion(ao).build_ao().err(),
+            Some(ConfigError::InvalidAmbientOcclusion(ao))
+        );
+        assert!(builder(8, 1).build_ao().is_ok());
+        assert_eq!(
+            builder(8, 1).regularization(Some(0. as Float)).build_pt().err(),
+            Some(ConfigError::InvalidRegularization(0. as Float))
+        );
+        let bloom = Bloom{threshold: 1. as Float, radius: -1. as Float, intensity: 0.1 as Float};
+        assert_eq!(builder(8, 1).bloom(Some(bloom)).build_pt().err(), Some(ConfigError::InvalidBloom(bloom)));
+        assert_eq!(
+            builder(8, 1).output("out.tga").build_pt().err().map(|e| format!("{}", e).contains("out.tga")),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn test_all_errors() {
+        let errors = builder(0, 0).output("out.exr").max_depth(0).tracing_errors();
+        assert_eq!(errors.len(), 4);
+        assert_eq!(errors[0], ConfigError::ZeroResolution);
+        assert_eq!(errors[3], ConfigError::ZeroDepth);
+        assert!(builder(8, 1).tracing_errors().is_empty());
+    }
+
+    #[test]
+    fn test_defaults_render() {
+        let scene = cornell_box(8);
+        let builder = RendererBuilder::new(Naive::new(4), scene.camera.clone());
+        let images = vec![
+            builder.clone().build_whitted().unwrap().render_image(&scene.scene),
+            builder.clone().build_pt().unwrap().render_image(&scene.scene),
+            builder.build_bpt().unwrap().render_image(&scene.scene),
+        ];
+        for image in &images {
+            assert_eq!(image.dimension(), Point2::new(8, 8));
+            let m = mean(image);
+            assert!(m.valid() && !m.is_black());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_thread_allocator {
+    use super::super::with_thread_allocator;
+    use aren_alloc::Allocator;
+
+    #[test]
+    fn test_reused() {
+        let first = with_thread_allocator(|allocator| allocator as *const Allocator);
+        let second = with_thread_allocator(|allocator| {
+            // pools are recycled as allocations are dropped
+            let _ = allocator.alloc(0u64);
+            allocator as *const Allocator
+        });
+        assert_eq!(first, second);
+    }
+}
+
+#[cfg(test)]
+mod test_shadow_catcher {
+    use test_utils::*;
+    use prelude::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_shadow_catcher() {
+        let resolution = 32;
+        let mut scene = shadow_catcher(resolution);
+        Arc::get_mut(&mut scene.camera).unwrap().get_film_mut().set_alpha(true);
+        let image = render(&scene, RendererKind::PT(5), 16);
+        assert!(image.has_alpha());
+        let n = resolution as u32;
+        let (mut umbra, mut penumbra) = (0, 0);
+        for y in 0..n {
+            for x in 0..n {
+                let p = Point2::new(x, y);
+                let alpha = image.alpha(p);
+                assert!(alpha >= 0. as Float && alpha <= 1. as Float);
+                if image[p].is_black() {
+                    if alpha > 0.9 as Float { umbra += 1; }
+                    else if alpha > 0.1 as Float { penumbra += 1; }
+                }
+            }
+        }
+        // nothing is seen along the top row
+        for x in 0..n {
+            assert_eq!(image.alpha(Point2::new(x, 0)), 0. as Float);
+            assert!(image[Point2::new(x, 0)].is_black());
+        }
+        // the sphere is opaque
+        let center = Point2::new(n / 2, n / 2);
+        assert_eq!(image.alpha(center), 1. as Float);
+        assert!(!image[center].is_black());
+        // the ground far from the sphere is transparent
+        for &x in &[0, n - 1] {
+            let corner = Point2::new(x, n - 1);
+            assert!(image.alpha(corner) < 0.05 as Float, "alpha {}", image.alpha(corner));
+            assert!(image[corner].is_black());
+        }
+        // while it darkens underneath the sphere, softly
+        assert!(umbra > 0 && penumbra > 0, "umbra {}, penumbra {}", umbra, penumbra);
+    }
+}
+
+#[cfg(test)]
+mod test_determinism {
+    use test_utils::*;
+    use prelude::*;
+    use filming::film::{Film, Image};
+    use sample::filters::LanczosSincFilter;
+    use rand::{SeedableRng, StdRng};
+    use rayon::{Configuration, ThreadPool};
+    use std::sync::Arc;
+    use std::env;
+    use std::fs;
+
+    // a camera over the cornell box whose filter spans neighboring
+    // tiles, split into many small tiles
+    fn camera(resolution: usize) -> Arc<Camera> {
+        let mut film = Film::new(
+            Point2::new(resolution, resolution),
+            BBox2f::new(Point2f::new(0. as Float, 0. as Float), Point2f::new(1. as Float, 1. as Float)),
+            Arc::new(LanczosSincFilter::new(Vector2f::new(2. as Float, 2. as Float), 3. as Float))
+        );
+        film.set_tile_size(Some(4));
+        let mut camera = PerspecCam::new(
+            Matrix4f::identity(),
+            BBox2f::new(Point2f::new(-1. as Float, -1. as Float), Point2f::new(1. as Float, 1. as Float)),
+            0.01 as Float, 1000. as Float, float::frac_pi_2(), None, film
+        );
+        camera.look_from(
+            Point3f::new(0. as Float, 0. as Float, -3.4 as Float),
+            Point3f::new(0. as Float, 0. as Float, 0. as Float),
+            Vector3f::new(0. as Float, 1. as Float, 0. as Float)
+        );
+        Arc::new(camera)
+    }
+
+    // render with `threads` rayon threads, returning the saved png
+    fn render_png<F: Fn(StdStrataSampler, Arc<Camera>) -> Image + Sync>(
+        render: &F, threads: usize, name: &str
+    ) -> Vec<u8> {
+        let sampler = StdStrataSampler::new(2, 2, 8, StdRng::from_seed(&[7, 11, 13][..]));
+        let pool = ThreadPool::new(Configuration::new().num_threads(threads)).unwrap();
+        let image = pool.install(|| render(sampler, camera(16)));
+        let path = env::temp_dir().join(format!("arendur_test_determinism_{}_{}.png", name, threads));
+        image.save(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        bytes
+    }
+
+    fn assert_reproducible<F: Fn(StdStrataSampler, Arc<Camera>) -> Image + Sync>(render: F, name: &str) {
+        let reference = render_png(&render, 1, name);
+        for &threads in &[4, 16] {
+            assert!(reference == render_png(&render, threads, name), "{} differs with {} threads", name, threads);
+        }
+    }
+
+    #[test]
+    fn test_pt_reproducible() {
+        let scene = cornell_box(16);
+        assert_reproducible(|sampler, camera| {
+            PTRenderer::new(sampler, camera, "", 4, true).render_image(&scene.scene)
+        }, "pt");
+    }
+
+    #[test]
+    fn test_bpt_reproducible() {
+        let scene = cornell_box(16);
+        assert_reproducible(|sampler, camera| {
+            BPTRenderer::new(sampler, camera, "", 4).render_image(&scene.scene)
+        }, "bpt");
+    }
+}
+
+#[cfg(test)]
+mod test_summary {
+    use test_utils::*;
+    use prelude::*;
+    use component::ComponentPointer;
+    use component::naive::Naive;
+    use component::transformed::TransformedComposable;
+    use std::sync::Arc;
+
+    fn sphere(center: Vector3f, radius: Float) -> Arc<Composable> {
+        let shaped = ShapedPrimitive::new(
+            Sphere::new(radius, -radius, radius, float::pi() * 2. as Float),
+            matte(0.5 as Float, 0.5, 0.5),
+            None
+        );
+        let local_parent = Matrix4f::from_translation(center);
+        Arc::new(TransformedComposable::new(
+            Arc::new(shaped), Arc::new(local_parent), Arc::new(local_parent.invert().unwrap())
+        ))
+    }
+
+    #[test]
+    fn test_counts() {
+        let spheres = vec![
+            sphere(Vector3f::new(-3. as Float, 0., 0.), 1. as Float),
+            sphere(Vector3f::new(0. as Float, 2., 0.), 0.5 as Float),
+            sphere(Vector3f::new(0. as Float, 0., 5.), 2. as Float),
+        ];
+        let floor = quad([
+            Point3f::new(-4. as Float, -1., -4.), Point3f::new(4. as Float, -1., -4.),
+            Point3f::new(4. as Float, -1., 4.), Point3f::new(-4. as Float, -1., 4.),
+        ], matte(0.5 as Float, 0.5, 0.5), None);
+        let light = quad([
+            Point3f::new(-1. as Float, 6., -1.), Point3f::new(1. as Float, 6., -1.),
+            Point3f::new(1. as Float, 6., 1.), Point3f::new(-1. as Float, 6., 1.),
+        ], matte(0. as Float, 0., 0.), Some(RGBSpectrumf::new(1. as Float, 1., 1.)));
+
+        let mut components: Vec<ComponentPointer> = TriangleMesh::instances(&floor)
+            .chain(TriangleMesh::instances(&light)).map(|t| t.into()).collect();
+        components.push(spheres[0].clone().into());
+        // nested aggregates are counted through
+        let nested: Arc<Composable> = Arc::new(Naive::new(vec![spheres[1].clone(), spheres[2].clone()]));
+        components.push(nested.into());
+        let lights: Vec<Arc<Light>> = vec![Arc::new(MeshLight::new(light.clone()))];
+        let scene = Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH)));
+
+        let summary = scene.summary();
+        assert_eq!(summary.primitives, 3 + 4);
+        let mut bounds = floor.bounding().union(&light.bounding());
+        for s in &spheres {
+            bounds = bounds.union(&s.bbox_parent());
+        }
+        assert_eq!(summary.bounds, bounds);
+        assert_eq!(summary.bounds.pmin, Point3f::new(-4. as Float, -2., -4.));
+        assert_eq!(summary.bounds.pmax, Point3f::new(4. as Float, 6., 7.));
+        assert_eq!(summary.lights.len(), 1);
+        assert_eq!(summary.lights[0].0, "area");
+        assert!(summary.lights[0].1.r() > 0. as Float);
+        assert!(summary.memory > 0);
+        assert!(format!("{}", summary).contains("primitives: 7"));
+    }
+}
+
+#[cfg(test)]
+mod test_visibility {
+    use test_utils::*;
+    use prelude::*;
+    use component::ComponentPointer;
+    use component::transformed::TransformedComposable;
+    use filming::SampleInfo;
+    use filming::film::Image;
+    use std::sync::Arc;
+
+    const RESOLUTION: usize = 64;
+
+    fn translated<S: Shape + 'static>(
+        primitive: ShapedPrimitive<S, Arc<Material>>, at: Vector3f
+    ) -> Arc<TransformedComposable<ShapedPrimitive<S, Arc<Material>>>> {
+        let translation = Matrix4f::from_translation(at);
+        Arc::new(TransformedComposable::new(
+            primitive, Arc::new(translation), Arc::new(translation.invert().unwrap())
+        ))
+    }
+
+    fn sphere(radius: Float) -> Sphere {
+        Sphere::new(radius, -radius, radius, float::pi() * 2. as Float)
+    }
+
+    // a small lamp off to the side of a sphere resting on the ground,
+    // casting the sphere's shadow away from it. The lamp is a point
+    // light, or a spherical emitter with visibility `emitter` if presented.
+    fn scene(emitter: Option<VisibilityFlags>, blocker: Option<VisibilityFlags>) -> TestScene {
+        let lamp = Vector3f::new(1.5 as Float, 1.5, -1.);
+        let ground = quad([
+            Point3f::new(-20. as Float, 0., -20.), Point3f::new(20. as Float, 0., -20.),
+            Point3f::new(20. as Float, 0., 20.), Point3f::new(-20. as Float, 0., 20.),
+        ], matte(0.8 as Float, 0.8, 0.8), None);
+        let mut components: Vec<ComponentPointer> = TriangleMesh::instances(&ground).map(|t| t.into()).collect();
+        let lights: Vec<Arc<Light>> = if let Some(visibility) = emitter {
+            let mut emitter = ShapedPrimitive::new(
+                sphere(0.2 as Float), matte(0. as Float, 0., 0.),
+                Some(Arc::new(ConstantTexture{value: RGBSpectrumf::new(30. as Float, 30., 30.)}))
+            );
+            emitter.visibility = visibility;
+            let emitter = translated(emitter, lamp);
+            components.push((emitter.clone() as Arc<Composable>).into());
+            vec![emitter]
+        } else {
+            vec![Arc::new(PointLight::new(Point3f::from_vec(lamp), RGBSpectrumf::new(4. as Float, 4., 4.)))]
+        };
+        if let Some(visibility) = blocker {
+            let mut blocker = ShapedPrimitive::new(sphere(0.5 as Float), matte(0.8 as Float, 0.8, 0.8), None);
+            blocker.visibility = visibility;
+            let blocker: Arc<Composable> = translated(blocker, Vector3f::new(0. as Float, 0.5, 0.));
+            components.push(blocker.into());
+        }
+        TestScene{
+            scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+            camera: camera(
+                RESOLUTION,
+                Point3f::new(0. as Float, 3., -6.),
+                Point3f::new(0. as Float, 0.5, 0.),
+                float::frac_pi_2() * 0.6 as Float
+            ),
+        }
+    }
+
+    // where the camera ray through the center of pixel `p` hits
+    fn hit(scene: &TestScene, p: Point2<u32>) -> Option<Point3f> {
+        let mut ray = scene.camera.generate_path(SampleInfo{
+            pfilm: Point2f::new(p.x as Float + 0.5 as Float, p.y as Float + 0.5 as Float),
+            plens: Point2f::new(0.5 as Float, 0.5 as Float),
+            time: 0. as Float,
+        });
+        scene.scene.aggregate.intersect_ray(&mut ray).map(|si| si.basic.pos)
+    }
+
+    fn pixels() -> Vec<Point2<u32>> {
+        let n = RESOLUTION as u32;
+        (0..n*n).map(|i| Point2::new(i % n, i / n)).collect()
+    }
+
+    // pixels whose neighbourhood sees only hits satisfying `f`
+    fn interior_pixels<F: Fn(Point3f) -> bool>(scene: &TestScene, f: F) -> Vec<Point2<u32>> {
+        let n = RESOLUTION as u32;
+        let inside = |x: u32, y: u32| hit(scene, Point2::new(x, y)).map_or(false, |p| f(p));
+        pixels().into_iter().filter(|p| {
+            p.x > 0 && p.y > 0 && p.x + 1 < n && p.y + 1 < n
+            && (0..9).all(|i| inside(p.x + i % 3 - 1, p.y + i / 3 - 1))
+        }).collect()
+    }
+
+    // pixels seeing only the ground, away from any edges
+    fn ground_pixels(scene: &TestScene) -> Vec<Point2<u32>> {
+        interior_pixels(scene, |p| p.y < 1e-3 as Float)
+    }
+
+    fn luminance(image: &Image, p: Point2<u32>) -> Float {
+        image[p].to_xyz().y
+    }
+
+    #[test]
+    fn test_camera_invisible_light() {
+        let visible = scene(Some(VisibilityFlags::all()), None);
+        let invisible = scene(Some(VisibilityFlags::all() - VISIBLE_CAMERA), None);
+        let lamp = pixels().into_iter().find(|&p| {
+            hit(&visible, p).map_or(false, |h| h.y > 1. as Float)
+        }).expect("lamp out of view");
+        let a = render(&visible, RendererKind::Whitted, 16);
+        let b = render(&invisible, RendererKind::Whitted, 16);
+        // seen through, while the ground around is lit all the same
+        assert!(luminance(&a, lamp) > 10. as Float);
+        let behind = luminance(&b, lamp);
+        assert!(behind > 0. as Float && behind < 1. as Float, "{}", behind);
+        let (mut sa, mut sb) = (0. as Float, 0. as Float);
+        for p in ground_pixels(&visible) {
+            sa += luminance(&a, p);
+            sb += luminance(&b, p);
+        }
+        assert!(sa > 0. as Float);
+        assert_relative_eq!(sa, sb, max_relative = 0.05 as Float);
+    }
+
+    #[test]
+    fn test_shadow_invisible_blocker() {
+        let shadowing = scene(None, Some(VisibilityFlags::all()));
+        let unshadowing = scene(None, Some(VisibilityFlags::all() - VISIBLE_SHADOW));
+        let shadowed = render(&shadowing, RendererKind::Whitted, 4);
+        let unshadowed = render(&unshadowing, RendererKind::Whitted, 4);
+        let reference = render(&scene(None, None), RendererKind::Whitted, 4);
+
+        // the blocker is still seen, lit as it was where facing the lamp
+        let blocker = interior_pixels(&unshadowing, |h| {
+            let n = h - Point3f::new(0. as Float, 0.5, 0.);
+            let l = (Point3f::new(1.5 as Float, 1.5, -1.) - h).normalize();
+            h.y > 0.1 as Float && n.magnitude() < 0.51 as Float && n.normalize().dot(l) > 0.3 as Float
+        });
+        assert!(!blocker.is_empty());
+        let (mut sa, mut sb) = (0. as Float, 0. as Float);
+        for p in blocker {
+            sa += luminance(&unshadowed, p);
+            sb += luminance(&shadowed, p);
+        }
+        assert!(sa > 0. as Float);
+        assert_relative_eq!(sa, sb, max_relative = 0.05 as Float);
+
+        // but the ground is lit as if it were not there
+        let (mut umbra, mut missing) = (0, 0);
+        let (mut sa, mut sb) = (0. as Float, 0. as Float);
+        for p in ground_pixels(&shadowing) {
+            let lit = luminance(&reference, p);
+            if luminance(&shadowed, p) < 0.2 as Float * lit { umbra += 1; }
+            if luminance(&unshadowed, p) < 0.2 as Float * lit { missing += 1; }
+            sa += luminance(&unshadowed, p);
+            sb += lit;
+        }
+        assert!(umbra > 0);
+        assert_eq!(missing, 0);
+        assert_relative_eq!(sa, sb, max_relative = 0.05 as Float);
+    }
+}
+
+#[cfg(test)]
+mod test_light_groups {
+    use test_utils::*;
+    use prelude::*;
+    use sample::naive::Naive;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_groups_sum_to_beauty() {
+        let mut box_scene = cornell_box(16);
+        let mut lights = box_scene.scene.lights.clone();
+        lights.push(Arc::new(PointLight::new(
+            Point3f::new(0.5 as Float, -0.5, -0.5), RGBSpectrumf::new(1. as Float, 0.5, 0.2)
+        )));
+        box_scene.scene = Scene::new(lights, box_scene.scene.aggregate.clone());
+        box_scene.scene.set_light_group(1, 2);
+        assert_eq!(box_scene.scene.light_group_count(), 3);
+
+        let mut renderer = PTRenderer::new(Naive::new(4), box_scene.camera.clone(), "", 4, true);
+        let (beauty, groups) = renderer.render_light_groups(&box_scene.scene);
+        assert_eq!(groups.len(), 3);
+        // the area light, nothing, and the point light
+        assert!(mean(&groups[0]).to_xyz().y > 0. as Float);
+        assert_eq!(mean(&groups[1]), RGBSpectrumf::black());
+        assert!(mean(&groups[2]).to_xyz().y > 0. as Float);
+        let dim = beauty.dimension();
+        for y in 0..dim.y {
+            for x in 0..dim.x {
+                let sum = groups[0][(x, y)] + groups[1][(x, y)] + groups[2][(x, y)];
+                assert_relative_eq!(sum.inner, beauty[(x, y)].inner, epsilon = 1e-4, max_relative = 1e-3);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_path_channels {
+    use test_utils::*;
+    use prelude::*;
+    use sample::naive::Naive;
+
+    #[test]
+    fn test_channels_sum_to_beauty() {
+        let scene = glass_sphere_caustic(16);
+        let renderer = PTRenderer::new(Naive::new(4), scene.camera.clone(), "", 5, true);
+        let all = PathChannel::all();
+        let (beauty, channels) = renderer.render_path_channels(&scene.scene, &all);
+        assert_eq!(channels.len(), all.len());
+        for (channel, image) in all.iter().zip(&channels) {
+            if *channel != PathChannel::GlossyIndirect {
+                assert!(mean(image).to_xyz().y > 0. as Float, "{:?} is black", channel);
+            }
+        }
+        let dim = beauty.dimension();
+        for y in 0..dim.y {
+            for x in 0..dim.x {
+                let sum = channels.iter().fold(RGBSpectrumf::black(), |sum, c| sum + c[(x, y)]);
+                assert_relative_eq!(sum.inner, beauty[(x, y)].inner, epsilon = 1e-4, max_relative = 1e-3);
+            }
+        }
+        // channels rendered alone are the same
+        let (_, caustic) = renderer.render_path_channels(&scene.scene, &[PathChannel::Caustic]);
+        assert_eq!(caustic.len(), 1);
+        for y in 0..dim.y {
+            for x in 0..dim.x {
+                assert_relative_eq!(caustic[0][(x, y)].inner, channels[3][(x, y)].inner, epsilon = 1e-5);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_stereo {
+    use test_utils::*;
+    use prelude::*;
+    use component::ComponentPointer;
+    use filming::film::Image;
+    use std::sync::Arc;
+    use std::fs;
+    use sample::naive::Naive;
+
+    const RESOLUTION: usize = 64;
+    const INTEROCULAR: Float = 0.5;
+    const DEPTH: Float = 5.;
+
+    // an emissive sphere at the origin, `DEPTH` in front of the camera
+    fn scene() -> (Scene, PerspecCam) {
+        let sphere = Arc::new(ShapedPrimitive::new(
+            Sphere::new(0.5 as Float, -0.5, 0.5, float::pi() * 2. as Float),
+            matte(0. as Float, 0., 0.),
+            Some(Arc::new(ConstantTexture{value: RGBSpectrumf::new(1. as Float, 1., 1.)}))
+        ));
+        let components: Vec<ComponentPointer> = vec![(sphere.clone() as Arc<Composable>).into()];
+        let lights: Vec<Arc<Light>> = vec![sphere];
+        let camera = perspec_cam(
+            RESOLUTION,
+            Point3f::new(0. as Float, 0., -DEPTH),
+            Point3f::new(0. as Float, 0., 0.),
+            float::frac_pi_4()
+        );
+        (Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))), camera)
+    }
+
+    // luminance-weighted centroid of the columns `x0..x1`,
+    // relative to the center of those columns
+    fn centroid(image: &Image, x0: u32, x1: u32) -> Point2f {
+        let mut sum = 0. as Float;
+        let mut p = Point2f::new(0. as Float, 0.);
+        for y in 0..image.dimension().y {
+            for x in x0..x1 {
+                let l = image[(x, y)].to_xyz().y;
+                sum += l;
+                p.x += l * (x - x0) as Float;
+                p.y += l * y as Float;
+            }
+        }
+        let center = (x1 - x0) as Float * 0.5 as Float - 0.5 as Float;
+        Point2f::new(p.x / sum - center, p.y / sum)
+    }
+
+    #[test]
+    fn test_stereo_disparity() {
+        let (scene, camera) = scene();
+        let stereo = StereoCam::new(camera, INTEROCULAR);
+        assert_eq!(stereo.get_film().resolution(), Point2::new(RESOLUTION * 2, RESOLUTION));
+        let image = render(&TestScene{
+            scene: scene,
+            camera: Arc::new(stereo),
+        }, RendererKind::Whitted, 16);
+        assert_eq!(image.dimension(), Point2::new(RESOLUTION as u32 * 2, RESOLUTION as u32));
+        let w = RESOLUTION as u32;
+        let left = centroid(&image, 0, w);
+        let right = centroid(&image, w, w * 2);
+        // both eyes see the sphere at the same height, shifted
+        // symmetrically towards each other
+        assert_relative_eq!(left.y, right.y, epsilon = 0.1 as Float);
+        assert_relative_eq!(left.x, -right.x, epsilon = 0.2 as Float);
+        let inv_tan = 1. as Float / (float::frac_pi_4() * 0.5 as Float).tan();
+        let disparity = INTEROCULAR / DEPTH * inv_tan * RESOLUTION as Float * 0.5 as Float;
+        assert!(left.x > 0. as Float);
+        assert_relative_eq!(left.x - right.x, disparity, epsilon = 0.3 as Float);
+    }
+
+    #[test]
+    fn test_render_multi() {
+        let (scene, camera) = scene();
+        let mut small = camera.clone();
+        small.set_resolution(Point2::new(8, 8));
+        let cameras: Vec<Arc<Camera>> = vec![
+            Arc::new(small.clone()), Arc::new(StereoCam::new(small, INTEROCULAR))
+        ];
+        let dir = ::std::env::temp_dir();
+        let outputs = vec![dir.join("arendur_multi_mono.png"), dir.join("arendur_multi_stereo.png")];
+        let mut renderer = PTRenderer::new(Naive::new(1), Arc::new(camera), "", 1, false);
+        renderer.render_multi(&scene, &cameras, &outputs);
+        for output in &outputs {
+            assert!(output.exists(), "{:?} not saved", output);
+            fs::remove_file(output).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_id_pass {
+    use test_utils::*;
+    use prelude::*;
+    use component::ComponentPointer;
+    use component::transformed::TransformedComposable;
+    use sample::naive::Naive;
+    use std::sync::Arc;
+    use std::collections::HashSet;
+
+    const RESOLUTION: usize = 32;
+
+    fn sphere_at(x: Float) -> Arc<Composable> {
+        let translation = Matrix4f::from_translation(Vector3f::new(x, 0., 0.));
+        Arc::new(TransformedComposable::new(
+            ShapedPrimitive::new(
+                Sphere::new(0.5 as Float, -0.5, 0.5, float::pi() * 2. as Float),
+                matte(0.8 as Float, 0.8, 0.8), None
+            ),
+            Arc::new(translation), Arc::new(translation.invert().unwrap())
+        ))
+    }
+
+    #[test]
+    fn test_two_spheres() {
+        let components: Vec<ComponentPointer> = vec![sphere_at(-0.7).into(), sphere_at(0.7).into()];
+        let scene = Scene::new(Vec::new(), Arc::new(BVH::new(&components, BVHStrategy::SAH)));
+        let camera = camera(
+            RESOLUTION, Point3f::new(0. as Float, 0., -4.), Point3f::new(0. as Float, 0., 0.),
+            float::frac_pi_4()
+        );
+        let renderer = PTRenderer::new(Naive::new(4), camera, "", 1, true);
+        let ids = renderer.render_ids(&scene);
+        let distinct: HashSet<u32> = ids.bounding().into_iter().map(|p| ids.get(p)).collect();
+        assert!(distinct.contains(&0));
+        assert_eq!(distinct.len(), 3, "ids found: {:?}", distinct);
+        for p in ids.bounding() {
+            let x = p.x as usize;
+            if p.y == 0 || x == 0 || x == RESOLUTION - 1 {
+                assert_eq!(ids.get(p), 0, "background at {:?} got an id", p);
+            }
+        }
+        // each sphere covers one half of the image
+        let mid = RESOLUTION as isize / 2;
+        let left = ids.get(Point2::new(mid - 6, mid));
+        let right = ids.get(Point2::new(mid + 6, mid));
+        assert!(left != 0 && right != 0 && left != right);
+    }
+
+    #[test]
+    fn test_mesh_shares_id() {
+        let mesh = quad([
+            Point3f::new(-1. as Float, -1., 0.), Point3f::new(1. as Float, -1., 0.),
+            Point3f::new(1. as Float, 1., 0.), Point3f::new(-1. as Float, 1., 0.),
+        ], matte(0.8 as Float, 0.8, 0.8), None);
+        let triangles: Vec<_> = TriangleMesh::instances(&mesh).collect();
+        let mut components: Vec<ComponentPointer> = triangles.iter().map(|t| t.clone().into()).collect();
+        components.push(sphere_at(3.).into());
+        let scene = Scene::new(Vec::new(), Arc::new(BVH::new(&components, BVHStrategy::SAH)));
+        assert_eq!(scene.primitive_id_count(), 2);
+        let id = scene.primitive_id(&triangles[0]);
+        assert!(id != 0);
+        assert_eq!(scene.primitive_id(&triangles[1]), id);
+    }
+
+    #[test]
+    fn test_albedo_pass() {
+        let components: Vec<ComponentPointer> = vec![sphere_at(-0.7).into(), sphere_at(0.7).into()];
+        let scene = Scene::new(Vec::new(), Arc::new(BVH::new(&components, BVHStrategy::SAH)));
+        let camera = camera(
+            RESOLUTION, Point3f::new(0. as Float, 0., -4.), Point3f::new(0. as Float, 0., 0.),
+            float::frac_pi_4()
+        );
+        let renderer = PTRenderer::new(Naive::new(4), camera, "", 1, true);
+        let albedo = renderer.render_albedo(&scene);
+        let mid = RESOLUTION as u32 / 2;
+        // the matte spheres, lit or not, and the background
+        for &x in &[mid - 6, mid + 6] {
+            let pixel = albedo[(x, mid)];
+            assert_relative_eq!(pixel.r(), 0.8 as Float, epsilon = 1e-2);
+        }
+        assert_eq!(albedo[(0, 0)].r(), 0. as Float);
+    }
+}
+
+#[cfg(test)]
+mod test_dome {
+    use test_utils::*;
+    use prelude::*;
+    use component::ComponentPointer;
+    use std::sync::Arc;
+    use std::collections::HashMap;
+    use std::env;
+    use std::fs;
+    use image;
+
+    const RESOLUTION: usize = 32;
+
+    #[test]
+    fn test_dome_lighting() {
+        // an equirectangular map black but for one texel
+        // just above the horizon along +x
+        let name = env::temp_dir().join("arendur_test_dome.png").into_os_string().into_string().unwrap();
+        image::RgbImage::from_fn(8, 4, |x, y| {
+            image::Rgb{data: if x == 0 && y == 1 { [255, 255, 255] } else { [0, 0, 0] }}
+        }).save(&name).unwrap();
+        let sky = RGBImageTexture::new_as_arc(
+            ImageInfo{
+                name: name.clone(),
+                trilinear: false,
+                max_aniso: DEFAULT_MAX_ANISO,
+                wrapping: ImageWrapMode::Repeat,
+                gamma: false,
+                scale: 1. as Float,
+            },
+            UVMapping{
+                scaling: Vector2f::new(1. as Float, 1.),
+                shifting: Vector2f::zero(),
+            },
+            &mut HashMap::new()
+        ).unwrap();
+        let _ = fs::remove_file(&name);
+        let dome = Arc::new(ShapedPrimitive::new(
+            Dome::new(20. as Float, false), matte(0. as Float, 0., 0.), Some(sky)
+        ));
+        let ball: Arc<Composable> = Arc::new(ShapedPrimitive::new(
+            Sphere::full(1. as Float), matte(0.8 as Float, 0.8, 0.8), None
+        ));
+        let components: Vec<ComponentPointer> = vec![(dome.clone() as Arc<Composable>).into(), ball.into()];
+        let lights: Vec<Arc<Light>> = vec![dome];
+        // looking up the z axis, with +x to the right
+        let scene = TestScene{
+            scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+            camera: camera(
+                RESOLUTION, Point3f::new(0. as Float, 0., -4.), Point3f::new(0. as Float, 0., 0.),
+                float::frac_pi_3()
+            ),
+        };
+        let image = render(&scene, RendererKind::PT(1), 64);
+        let (mut left, mut right) = (0. as Float, 0. as Float);
+        let half = RESOLUTION as u32 / 2;
+        for y in 0..RESOLUTION as u32 {
+            for x in 0..half {
+                left += image[(x, y)].to_xyz().y;
+                right += image[(x + half, y)].to_xyz().y;
+            }
+        }
+        assert!(right > 0. as Float);
+        assert!(right > left * 10. as Float, "lit from +x {}, from -x {}", right, left);
+    }
+}
+
+#[cfg(test)]
+mod test_interior {
+    use test_utils::*;
+    use prelude::*;
+    use component::ComponentPointer;
+    use std::sync::Arc;
+
+    // inside a sphere of `radius` made of `material`, lit by a point light at its center
+    fn room(radius: Float, material: Arc<Material>) -> TestScene {
+        let room: Arc<Composable> = Arc::new(ShapedPrimitive::new(
+            Sphere::full(radius), material, None
+        ));
+        let lights: Vec<Arc<Light>> = vec![Arc::new(PointLight::new(
+            Point3f::new(0. as Float, 0., 0.), RGBSpectrumf::grey_scale(25. as Float)
+        ))];
+        let components: Vec<ComponentPointer> = vec![room.into()];
+        let eye = Point3f::new(0.3 as Float, -0.2, 0.1);
+        TestScene{
+            scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+            camera: camera(8, eye, Point3f::new(0. as Float, 0., 1.), float::frac_pi_4()),
+        }
+    }
+
+    #[test]
+    fn test_inside_diffuse_sphere() {
+        let sphere = Sphere::full(5. as Float);
+        let ray = RawRay::from_od(Point3f::new(0. as Float, 0., 0.), Vector3f::new(0. as Float, 0., 1.));
+        assert!(sphere.intersect_ray(&ray).unwrap().1.is_backface());
+
+        let plastic: Arc<Material> = Arc::new(PlasticMaterial::new(
+            Arc::new(ConstantTexture{value: RGBSpectrumf::grey_scale(0.5 as Float)}),
+            Arc::new(ConstantTexture{value: RGBSpectrumf::grey_scale(0.5 as Float)}),
+            Arc::new(ConstantTexture{value: 0.3 as Float}),
+            None
+        ));
+        for &radius in &[2.5 as Float, 5.] {
+            // the wall is evenly lit, as if seen from outside
+            let expected = 0.5 as Float * 25. as Float / (float::pi() * radius * radius);
+            for &kind in &[RendererKind::Whitted, RendererKind::PT(1)] {
+                let image = render(&room(radius, matte(0.5 as Float, 0.5, 0.5)), kind, 4);
+                let dim = image.dimension();
+                for y in 0..dim.y {
+                    for x in 0..dim.x {
+                        assert_relative_eq!(image[Point2::new(x, y)].r(), expected, max_relative = 1e-3);
+                    }
+                }
+                // glossy walls are lit from inside too
+                assert!(mean(&render(&room(radius, plastic.clone()), kind, 4)).r() > 0.5 as Float * expected);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_decorrelation {
+    use prelude::*;
+    use sample::prelude::StdStrataSampler;
+    use rand::{SeedableRng, StdRng};
+
+    // samples drawn for pixel `p` by a clone of `sampler`, as
+    // every tile starts from a clone of the same sampler
+    fn draw(sampler: &StdStrataSampler, p: Point2<u32>) -> Vec<Float> {
+        let mut sampler = sampler.clone();
+        sampler.start_pixel(p);
+        let mut ret = Vec::new();
+        loop {
+            ret.push(sampler.next());
+            let u = sampler.next_2d();
+            ret.push(u.x);
+            ret.push(u.y);
+            let mut buf = [0. as Float; 2];
+            sampler.request(&mut buf);
+            ret.extend_from_slice(&buf);
+            if !sampler.next_sample() { break; }
+        }
+        ret
+    }
+
+    #[test]
+    fn test_pixels_decorrelated() {
+        let mut sampler = StdStrataSampler::new(2, 2, 4, StdRng::from_seed(&[7usize][..]));
+        let (a, b) = (Point2::new(3, 5), Point2::new(11, 5));
+        sampler.set_decorrelate(false);
+        assert_eq!(draw(&sampler, a), draw(&sampler, b));
+        sampler.set_decorrelate(true);
+        let (sa, sb) = (draw(&sampler, a), draw(&sampler, b));
+        assert_eq!(sa, draw(&sampler, a));
+        assert!(sa.iter().zip(sb.iter()).filter(|&(x, y)| x == y).count() < sa.len() / 4);
+        for &u in sa.iter().chain(sb.iter()) {
+            assert!(u >= 0. as Float && u < 1. as Float);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_thin_glass {
+    use test_utils::*;
+    use prelude::*;
+    use filming::film::Image;
+
+    /// luminance of each column, averaged over rows
+    fn column_means(image: &Image) -> Vec<Float> {
+        let dim = image.dimension();
+        (0..dim.x).map(|x| {
+            (0..dim.y).map(|y| image[(x, y)].to_xyz().y).sum::<Float>() / dim.y as Float
+        }).collect()
+    }
+
+    fn darkest(columns: &[Float]) -> usize {
+        (0..columns.len()).min_by(|&a, &b| float::total_cmp(columns[a], columns[b])).unwrap()
+    }
+
+    #[test]
+    fn test_no_offset() {
+        let reference = column_means(&render(&thin_window(32, 1.5 as Float, false), RendererKind::PT(4), 4));
+        let through = column_means(&render(&thin_window(32, 1.5 as Float, true), RendererKind::PT(4), 4));
+        let bar = darkest(&reference);
+        assert_eq!(bar, darkest(&through));
+        assert!(through[bar] < 0.1 as Float);
+        // elsewhere dimmed by the slab reflectance at about 45 degrees, around 0.096
+        let others: Vec<Float> = (0..32).filter(|&x| (x as isize - bar as isize).abs() > 2)
+            .map(|x| through[x]).collect();
+        let y = others.iter().sum::<Float>() / others.len() as Float;
+        assert!(y > 0.85 as Float && y < 0.95 as Float, "{}", y);
+    }
+}
+
+#[cfg(test)]
+mod test_preview {
+    use test_utils::*;
+    use prelude::*;
+    use component::ComponentPointer;
+    use sample::naive::Naive;
+    use aren_alloc::Allocator;
+    use image;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+    use std::thread;
+    use std::env;
+    use std::fs;
+
+    /// a matte material taking its time
+    struct Slow(Arc<Material>);
+
+    impl Material for Slow {
+        fn compute_scattering<'a>(
+            &self, si: &mut SurfaceInteraction, dxy: &DxyInfo, alloc: &'a Allocator
+        ) -> Bsdf<'a> {
+            thread::sleep(Duration::from_millis(1));
+            self.0.compute_scattering(si, dxy, alloc)
+        }
+    }
+
+    // a lit quad filling the view
+    fn scene() -> TestScene {
+        let p = |x: Float, y: Float, z: Float| Point3f::new(x, y, z);
+        let lamp = quad(
+            [p(-8., -8., 3.), p(8., -8., 3.), p(8., 8., 3.), p(-8., 8., 3.)],
+            Arc::new(Slow(matte(0.5 as Float, 0.5, 0.5))),
+            Some(RGBSpectrumf::new(0.5 as Float, 0.5, 0.5))
+        );
+        let components: Vec<ComponentPointer> = TriangleMesh::instances(&lamp)
+            .into_iter().map(|t| t.into()).collect();
+        let lights: Vec<Arc<Light>> = vec![Arc::new(MeshLight::new(lamp))];
+        let mut camera = perspec_cam(16, p(0., 0., -2.), p(0., 0., 0.), float::frac_pi_4());
+        camera.get_film_mut().set_tile_size(Some(4));
+        TestScene{
+            scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+            camera: Arc::new(camera),
+        }
+    }
+
+    fn lit_pixels(bytes: &[u8]) -> usize {
+        bytes.chunks(3).filter(|c| c.iter().any(|&b| b > 0)).count()
+    }
+
+    #[test]
+    fn test_preview_grows() {
+        let scene = scene();
+        let path = env::temp_dir().join("arendur_test_preview.png");
+        let _ = fs::remove_file(&path);
+        let done = Arc::new(AtomicBool::new(false));
+        let watcher = {
+            let done = done.clone();
+            let path = path.clone();
+            thread::spawn(move || {
+                let mut counts = Vec::new();
+                while !done.load(Ordering::SeqCst) {
+                    if let Ok(preview) = image::open(&path) {
+                        counts.push(lit_pixels(&preview.raw_pixels()));
+                    }
+                    thread::sleep(Duration::from_millis(2));
+                }
+                counts
+            })
+        };
+        let mut renderer = PTRenderer::new(Naive::new(1), scene.camera.clone(), &path, 2, false);
+        renderer.set_preview_interval(Some(Duration::from_millis(0)));
+        renderer.render(&scene.scene);
+        done.store(true, Ordering::SeqCst);
+        let counts = watcher.join().unwrap();
+        let saved = image::open(&path).unwrap().raw_pixels();
+        fs::remove_file(&path).unwrap();
+
+        assert!(counts.iter().any(|&c| c > 0 && c < 16 * 16), "{:?}", counts);
+        assert!(counts.windows(2).all(|w| w[0] <= w[1]), "{:?}", counts);
+        renderer.set_preview_interval(None);
+        assert_eq!(saved, renderer.render_image(&scene.scene).to_ldr().0);
+    }
+}
+
+#[cfg(test)]
+mod test_tile_seeding {
+    use test_utils::*;
+    use prelude::*;
+    use component::ComponentPointer;
+    use filming::film::Image;
+    use rand::{SeedableRng, StdRng};
+    use std::sync::Arc;
+
+    /// a stratified sampler handing out plain clones to tiles
+    #[derive(Clone)]
+    struct Cloned(StdStrataSampler);
+
+    impl Sampler for Cloned {
+        fn start_pixel(&mut self, p: Point2<u32>) { self.0.start_pixel(p) }
+        fn next(&mut self) -> Float { self.0.next() }
+        fn next_2d(&mut self) -> Point2f { self.0.next_2d() }
+        fn sample_per_pixel(&self) -> usize { self.0.sample_per_pixel() }
+        fn set_sample_per_pixel(&mut self, spp: usize) { self.0.set_sample_per_pixel(spp) }
+        fn next_sample(&mut self) -> bool { self.0.next_sample() }
+        fn set_sample_index(&mut self, idx: usize) -> bool { self.0.set_sample_index(idx) }
+        fn set_dimension(&mut self, d: usize) { self.0.set_dimension(d) }
+    }
+
+    // a plane lit by a quad light, viewed from above through tiles of 16 pixels
+    fn scene() -> TestScene {
+        let p = |x: Float, y: Float, z: Float| Point3f::new(x, y, z);
+        let meshes = vec![
+            quad([p(-8., 0., -8.), p(8., 0., -8.), p(8., 0., 8.), p(-8., 0., 8.)], matte(0.8 as Float, 0.8, 0.8), None),
+            quad(
+                [p(-2., 4., -2.), p(2., 4., -2.), p(2., 4., 2.), p(-2., 4., 2.)],
+                matte(0. as Float, 0., 0.), Some(RGBSpectrumf::new(1. as Float, 1., 1.))
+            ),
+        ];
+        let mut components: Vec<ComponentPointer> = Vec::new();
+        let mut lights: Vec<Arc<Light>> = Vec::new();
+        for mesh in &meshes {
+            if mesh.is_emissive() {
+                lights.push(Arc::new(MeshLight::new(mesh.clone())));
+            }
+            for triangle in TriangleMesh::instances(mesh) {
+                components.push(triangle.into());
+            }
+        }
+        let mut camera = perspec_cam(64, p(0., 3., 0.01), p(0., 0., 0.), float::frac_pi_4());
+        camera.get_film_mut().set_tile_size(Some(16));
+        TestScene{
+            scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+            camera: Arc::new(camera),
+        }
+    }
+
+    /// correlation of the high-passed image with itself shifted by `lag` pixels along x
+    fn autocorrelation(image: &Image, lag: u32) -> Float {
+        let dim = image.dimension();
+        let v = |x: u32, y: u32| image[(x, y)].to_xyz().y;
+        let d = |x: u32, y: u32| {
+            v(x, y) - 0.25 as Float * (v(x - 1, y) + v(x + 1, y) + v(x, y - 1) + v(x, y + 1))
+        };
+        let (mut ab, mut aa, mut bb) = (0. as Float, 0. as Float, 0. as Float);
+        for y in 1..dim.y - 1 {
+            for x in 1..dim.x - 1 - lag {
+                let (a, b) = (d(x, y), d(x + lag, y));
+                ab += a * b;
+                aa += a * a;
+                bb += b * b;
+            }
+        }
+        ab / (aa * bb).sqrt()
+    }
+
+    fn sampler() -> StdStrataSampler {
+        let mut sampler = StdStrataSampler::new(1, 1, 8, StdRng::from_seed(&[7usize][..]));
+        sampler.set_decorrelate(false);
+        sampler
+    }
+
+    #[test]
+    fn test_no_tile_correlation() {
+        let scene = scene();
+        let cloned = PTRenderer::new(Cloned(sampler()), scene.camera.clone(), "", 1, true)
+            .render_image(&scene.scene);
+        assert!(autocorrelation(&cloned, 16) > 0.5 as Float, "{}", autocorrelation(&cloned, 16));
+        let forked = PTRenderer::new(sampler(), scene.camera.clone(), "", 1, true)
+            .render_image(&scene.scene);
+        for &lag in &[7, 16, 32] {
+            let c = autocorrelation(&forked, lag);
+            assert!(c.abs() < 0.1 as Float, "{} at lag {}", c, lag);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_progressive {
+    use test_utils::*;
+    use prelude::*;
+    use component::ComponentPointer;
+    use sample::naive::Naive;
+    use std::sync::Arc;
+    use std::env;
+    use std::fs;
+
+    // a quad of constant emission filling the view
+    fn flat() -> TestScene {
+        let p = |x: Float, y: Float, z: Float| Point3f::new(x, y, z);
+        let lamp = quad(
+            [p(-8., -8., 3.), p(8., -8., 3.), p(8., 8., 3.), p(-8., 8., 3.)],
+            matte(0. as Float, 0., 0.), Some(RGBSpectrumf::new(0.5 as Float, 0.5, 0.5))
+        );
+        let components: Vec<ComponentPointer> = TriangleMesh::instances(&lamp)
+            .into_iter().map(|t| t.into()).collect();
+        let lights: Vec<Arc<Light>> = vec![Arc::new(MeshLight::new(lamp))];
+        TestScene{
+            scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+            camera: Arc::new(perspec_cam(16, p(0., 0., -2.), p(0., 0., 0.), float::frac_pi_4())),
+        }
+    }
+
+    #[test]
+    fn test_flat_converges() {
+        let scene = flat();
+        let path = env::temp_dir().join("arendur_test_progressive.png");
+        let mut renderer = PTRenderer::new(Naive::new(4), scene.camera.clone(), &path, 2, true);
+        let convergence = renderer.render_until(&scene.scene, 1e-6 as Float, 60. as Float);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(convergence.passes, 2);
+        assert!(convergence.relative_variance.unwrap() < 1e-6 as Float, "{:?}", convergence);
+    }
+
+    // a plane lit by a quad light, viewed from above
+    fn lit_plane() -> TestScene {
+        let p = |x: Float, y: Float, z: Float| Point3f::new(x, y, z);
+        let meshes = vec![
+            quad([p(-8., 0., -8.), p(8., 0., -8.), p(8., 0., 8.), p(-8., 0., 8.)], matte(0.8 as Float, 0.8, 0.8), None),
+            quad(
+                [p(-2., 4., -2.), p(2., 4., -2.), p(2., 4., 2.), p(-2., 4., 2.)],
+                matte(0. as Float, 0., 0.), Some(RGBSpectrumf::new(1. as Float, 1., 1.))
+            ),
+        ];
+        let mut components: Vec<ComponentPointer> = Vec::new();
+        let mut lights: Vec<Arc<Light>> = Vec::new();
+        for mesh in &meshes {
+            if mesh.is_emissive() {
+                lights.push(Arc::new(MeshLight::new(mesh.clone())));
+            }
+            for triangle in TriangleMesh::instances(mesh) {
+                components.push(triangle.into());
+            }
+        }
+        TestScene{
+            scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+            camera: Arc::new(perspec_cam(16, p(0., 3., 0.01), p(0., 0., 0.), float::frac_pi_4())),
+        }
+    }
+
+    #[test]
+    fn test_variance_falls_with_passes() {
+        let scene = lit_plane();
+        let renderer = PTRenderer::new(Naive::new(4), scene.camera.clone(), "", 1, true);
+        let mut variances = Vec::new();
+        let (_, last) = renderer.render_progressive(&scene.scene, |passes, variance| {
+            if passes == 1 { assert!(variance.is_none()); }
+            variances.push(variance);
+            passes == 32
+        });
+        assert_eq!(last, variances[31]);
+        // quadrupling the samples quarters the variance
+        let ratio = variances[7].unwrap() / variances[31].unwrap();
+        assert!(ratio > 2.5 as Float && ratio < 6. as Float, "{:?}", variances);
+    }
+}
+
+#[cfg(test)]
+mod test_scene_builder {
+    use prelude::*;
+    use renderer::scene_builder::*;
+    use test_utils;
+    use component::{self, ComponentPointer};
+    use filming::film::Image;
+    use rand::{SeedableRng, StdRng};
+    use std::sync::Arc;
+    use std::env;
+    use std::fs;
+
+    fn render(scene: &Scene, camera: Arc<Camera>) -> Image {
+        let sampler = StdStrataSampler::new(2, 2, 4, StdRng::from_seed(&[7usize][..]));
+        WhittedRenderer::new(sampler, camera, "", 0. as Float).render_image(scene)
+    }
+
+    #[test]
+    fn test_same_as_by_hand() {
+        let floor = env::temp_dir().join("arendur_test_scene_builder.obj");
+        fs::write(&floor, "v -4 0 -4\nv 4 0 -4\nv 4 0 4\nv -4 0 4\nf 1 2 3 4\n").unwrap();
+        let lift = Matrix4f::from_translation(Vector3f::new(0. as Float, -1., 5.));
+        let blue = matte(RGBSpectrumf::new(0.2 as Float, 0.3, 0.8), 0. as Float);
+        let lamp = RGBSpectrumf::new(4. as Float, 3., 2.);
+        let bulb = RGBSpectrumf::new(20. as Float, 20., 20.);
+
+        let mut builder = SceneBuilder::new();
+        builder.sphere(1. as Float).at(0., 0., 5.).material(blue.clone());
+        builder.point_light(Point3f::new(-2. as Float, 3., 2.), bulb);
+        builder.sphere(0.3 as Float).at(1.5, 1.5, 4.).emissive(lamp);
+        builder.mesh(&floor).transform(lift);
+        builder.camera(Point3f::new(0. as Float, 1., 0.), Point3f::new(0. as Float, 0., 5.), 1. as Float, (24, 16));
+        let (scene, camera) = builder.build().unwrap();
+
+        let ball = ShapedPrimitive::new(Sphere::full(1. as Float), blue, None);
+        let to_ball = Matrix4f::from_translation(Vector3f::new(0. as Float, 0., 5.));
+        let ball = Arc::new(TransformedComposable::new(
+            ball, Arc::new(to_ball), Arc::new(to_ball.invert().unwrap())
+        ));
+        let glow = ShapedPrimitive::new(
+            Sphere::full(0.3 as Float),
+            matte(RGBSpectrumf::grey_scale(0.5 as Float), 0. as Float),
+            Some(Arc::new(ConstantTexture{value: lamp}))
+        );
+        let to_glow = Matrix4f::from_translation(Vector3f::new(1.5 as Float, 1.5, 4.));
+        let glow = Arc::new(TransformedComposable::new(
+            glow, Arc::new(to_glow), Arc::new(to_glow.invert().unwrap())
+        ));
+        let mut components: Vec<ComponentPointer> = vec![
+            (ball as Arc<Composable>).into(), (glow.clone() as Arc<Composable>).into()
+        ];
+        let (meshes, meshlights) = component::load_obj_with_lights(&floor, lift).unwrap();
+        let _ = fs::remove_file(&floor);
+        assert!(meshlights.is_empty());
+        components.extend(meshes);
+        let lights: Vec<Arc<Light>> = vec![Arc::new(PointLight::new(Point3f::new(-2. as Float, 3., 2.), bulb)), glow];
+        let by_hand = Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH)));
+
+        assert_eq!(scene.lights.len(), 2);
+        let (a, b) = (render(&scene, camera.clone()), render(&by_hand, camera));
+        let mut lit = 0;
+        for y in 0..16 {
+            for x in 0..24 {
+                assert_eq!(a[(x, y)], b[(x, y)], "({}, {})", x, y);
+                if a[(x, y)].r() > 0. as Float { lit += 1; }
+            }
+        }
+        assert!(lit > 200);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut builder = SceneBuilder::new();
+        builder.sphere(1. as Float);
+        match builder.build() {
+            Err(SceneBuildError::NoCamera) => (),
+            _ => panic!("built without a camera"),
+        }
+        let mut builder = SceneBuilder::new();
+        builder.camera(Point3f::origin(), Point3f::new(0. as Float, 0., 1.), 1. as Float, (4, 4));
+        builder.sphere(1. as Float);
+        builder.sphere(-1. as Float);
+        match builder.build() {
+            Err(SceneBuildError::InvalidRadius(1, _)) => (),
+            _ => panic!("built with a negative radius"),
+        }
+        let mut builder = SceneBuilder::new();
+        builder.camera(Point3f::origin(), Point3f::new(0. as Float, 0., 1.), 1. as Float, (4, 4));
+        builder.sphere(1. as Float).transform(Matrix4f::from_scale(0. as Float));
+        match builder.build() {
+            Err(SceneBuildError::SingularTransform(0)) => (),
+            _ => panic!("built with a singular transform"),
+        }
+        let mut builder = SceneBuilder::new();
+        builder.camera(Point3f::origin(), Point3f::new(0. as Float, 0., 1.), 1. as Float, (4, 4));
+        builder.mesh("no/such/file.obj");
+        match builder.build() {
+            Err(SceneBuildError::MeshLoad(0, _, _)) => (),
+            _ => panic!("built with a missing mesh"),
+        }
+        let mut builder = SceneBuilder::new();
+        builder.set_camera(test_utils::camera(
+            4, Point3f::origin(), Point3f::new(0. as Float, 0., 1.), 1. as Float
+        ));
+        builder.sphere(1. as Float);
+        builder.camera_relative(true);
+        match builder.build() {
+            Err(SceneBuildError::FixedCamera) => (),
+            _ => panic!("moved a fixed camera"),
+        }
+    }
+
+    // a ball on a ground sphere lit by a point light, all moved by
+    // `origin` along each axis
+    fn offset_scene(origin: Float, camera_relative: bool) -> (Scene, Arc<Camera>) {
+        let p = |x: Float, y: Float, z: Float| Point3f::new(origin + x, origin + y, origin + z);
+        let grey = matte(RGBSpectrumf::grey_scale(0.5 as Float), 0. as Float);
+        let mut builder = SceneBuilder::new();
+        builder.sphere(1. as Float).at(origin + 0.25, origin, origin + 5.).material(grey.clone());
+        builder.sphere(64. as Float).at(origin, origin - 65., origin + 5.).material(grey);
+        builder.point_light(p(-2., 3., 2.), RGBSpectrumf::grey_scale(20. as Float));
+        builder.camera(p(0., 1., 0.), p(0., 0., 5.), 1. as Float, (24, 16));
+        builder.camera_relative(camera_relative);
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn test_camera_relative() {
+        let image = |origin: Float, camera_relative: bool| {
+            let (scene, camera) = offset_scene(origin, camera_relative);
+            render(&scene, camera)
+        };
+        let reference = image(0. as Float, true);
+        let (relative, absolute) = (image(1e6 as Float, true), image(1e6 as Float, false));
+        let mut lit = 0;
+        let mut differs = false;
+        for y in 0..16 {
+            for x in 0..24 {
+                assert_eq!(relative[(x, y)], reference[(x, y)], "({}, {})", x, y);
+                if reference[(x, y)].r() > 0. as Float { lit += 1; }
+                differs |= absolute[(x, y)] != reference[(x, y)];
+            }
+        }
+        assert!(lit > 100);
+        assert!(differs);
+    }
+}
+
+#[cfg(test)]
+mod test_path_filter {
+    use test_utils::*;
+    use prelude::*;
+    use renderer::pathfilter::{PathFilter, PathBuffers, filter_indirect};
+    use filming::film::Image;
+    use rand::{SeedableRng, StdRng};
+
+    // at 4spp
+    fn buffers(scene: &TestScene) -> PathBuffers {
+        let sampler = StdStrataSampler::new(2, 2, 32, StdRng::from_seed(&[7usize][..]));
+        PTRenderer::new(sampler, scene.camera.clone(), "", 5, true)
+            .render_path_buffers(&scene.scene)
+    }
+
+    fn sum(a: &Image, b: &Image) -> Image {
+        let dim = a.dimension();
+        let mut ret = Image::new(RGBSpectrumf::black(), dim);
+        for y in 0..dim.y {
+            for x in 0..dim.x {
+                ret[(x, y)] = a[(x, y)] + b[(x, y)];
+            }
+        }
+        ret
+    }
+
+    #[test]
+    fn test_less_noise() {
+        let scene = cornell_box(32);
+        let reference = render(&scene, RendererKind::PT(5), 1024);
+        let buffers = buffers(&scene);
+        let noisy = sum(&buffers.direct, &buffers.indirect);
+        let filtered = buffers.filtered(&PathFilter::default());
+        let (noisy, filtered) = (relative_mse(&noisy, &reference), relative_mse(&filtered, &reference));
+        assert!(filtered < noisy, "{} vs {}", filtered, noisy);
+    }
+
+    #[test]
+    fn test_edges_kept() {
+        let scene = cornell_box(32);
+        let buffers = buffers(&scene);
+        // indirect radiance coloring the normals, alike over each wall
+        let dim = buffers.indirect.dimension();
+        let mut coded = Image::new(RGBSpectrumf::black(), dim);
+        let mut pure = Vec::new();
+        for y in 0..dim.y {
+            for x in 0..dim.x {
+                if let Some(hit) = buffers.hits.get(Point2::new(x as isize, y as isize)) {
+                    let n = hit.normal;
+                    coded[(x, y)] = RGBSpectrumf::new(n.x + 1. as Float, n.y + 1. as Float, n.z + 1. as Float) * 0.5 as Float;
+                    if n.x.abs().max(n.y.abs()).max(n.z.abs()) > 0.9999 as Float { pure.push((x, y)); }
+                }
+            }
+        }
+        // pixels along the edges between the walls see both
+        assert!(pure.len() + 16 < (dim.x * dim.y) as usize);
+        let deviation = |filter: &PathFilter| {
+            let filtered = filter_indirect(&coded, &buffers.hits, filter);
+            pure.iter().map(|&(x, y)| {
+                let d = filtered[(x, y)] - coded[(x, y)];
+                d.r().abs().max(d.g().abs()).max(d.b().abs())
+            }).fold(0. as Float, |a, b| a.max(b))
+        };
+        let kept = deviation(&PathFilter::default());
+        let blurred = deviation(&PathFilter{
+            position_threshold: 1e3 as Float,
+            normal_threshold: -1. as Float,
+            ..PathFilter::default()
+        });
+        // only pixels seeing mostly the same wall might be gathered
+        // from across an edge
+        assert!(kept < 0.05 as Float, "{}", kept);
+        assert!(blurred > 0.2 as Float, "{}", blurred);
+    }
+}
+
+#[cfg(test)]
+mod test_alpha_background {
+    use prelude::*;
+    use renderer::scene_builder::SceneBuilder;
+    use test_utils::{perspec_cam, quad, matte};
+    use component::ComponentPointer;
+    use filming::SampleInfo;
+    use filming::film::Image;
+    use rand::{SeedableRng, StdRng};
+    use std::sync::Arc;
+
+    #[derive(Copy, Clone, Debug)]
+    enum Kind {
+        Whitted,
+        PT,
+        BPT,
+    }
+
+    // at `strata` squared samples per pixel
+    fn render(scene: &Scene, camera: Arc<Camera>, kind: Kind, strata: u32) -> Image {
+        let sampler = StdStrataSampler::new(strata, strata, 4, StdRng::from_seed(&[7usize][..]));
+        match kind {
+            Kind::Whitted => WhittedRenderer::new(sampler, camera, "", 0. as Float).render_image(scene),
+            Kind::PT => PTRenderer::new(sampler, camera, "", 3, true).render_image(scene),
+            Kind::BPT => BPTRenderer::new(sampler, camera, "", 3).render_image(scene),
+        }
+    }
+
+    #[test]
+    fn test_silhouette() {
+        let n = 32;
+        let mut builder = SceneBuilder::new();
+        builder.sphere(1. as Float);
+        builder.point_light(Point3f::new(0. as Float, 0., -5.), RGBSpectrumf::grey_scale(25. as Float));
+        builder.camera(
+            Point3f::new(0. as Float, 0., -5.), Point3f::new(0. as Float, 0., 0.),
+            float::frac_pi_4(), (n, n)
+        );
+        let (scene, mut camera) = builder.build().unwrap();
+        Arc::get_mut(&mut camera).unwrap().get_film_mut().set_alpha(true);
+        // whether the ray through the center of each pixel hits the sphere
+        let hit = |x: isize, y: isize| {
+            let mut ray = camera.generate_path(SampleInfo{
+                pfilm: Point2f::new(x as Float + 0.5 as Float, y as Float + 0.5 as Float),
+                plens: Point2f::new(0.5 as Float, 0.5 as Float),
+                time: 0. as Float,
+            });
+            scene.aggregate.intersect_ray(&mut ray).is_some()
+        };
+        // samples reach pixels within the filter radius of 2, so those
+        // whose neighbors within 3 all hit, or all miss, are settled
+        let n = n as isize;
+        let neighbors = |x: isize, y: isize| {
+            let mut hits = 0;
+            for j in y - 3..y + 4 {
+                for i in x - 3..x + 4 {
+                    if hit(i, j) { hits += 1; }
+                }
+            }
+            hits
+        };
+        let settled: Vec<usize> = (0..n * n).map(|i| neighbors(i % n, i / n)).collect();
+        for &kind in &[Kind::Whitted, Kind::PT, Kind::BPT] {
+            let image = render(&scene, camera.clone(), kind, 2);
+            assert!(image.has_alpha());
+            let mut band = 0;
+            for y in 0..n {
+                for x in 0..n {
+                    let p = Point2::new(x as u32, y as u32);
+                    let alpha = image.alpha(p);
+                    match settled[(y * n + x) as usize] {
+                        49 => assert!((alpha - 1. as Float).abs() < 1e-4 as Float, "{:?} at {:?}: {}", kind, p, alpha),
+                        0 => {
+                            assert_eq!(alpha, 0. as Float, "{:?} at {:?}", kind, p);
+                            assert!(image[p].is_black());
+                        },
+                        _ => if alpha > 0.01 as Float && alpha < 0.99 as Float { band += 1; },
+                    }
+                }
+            }
+            assert!(band > 0, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn test_composite() {
+        let n = 32;
+        let p = |x: Float, y: Float, z: Float| Point3f::new(x, y, z);
+        let sphere: Arc<Composable> = Arc::new(ShapedPrimitive::new(
+            Sphere::full(1. as Float), matte(0.8 as Float, 0.8 as Float, 0.8 as Float), None
+        ));
+        // a checkerboard of squares edged away from the silhouette,
+        // emitting without lighting anything
+        let mut checker: Vec<ComponentPointer> = Vec::new();
+        for j in 0..3 {
+            for i in 0..3 {
+                let (x, y) = (-6. as Float + 4. as Float * i as Float, -6. as Float + 4. as Float * j as Float);
+                let emission = if (i + j) % 2 == 0 {
+                    RGBSpectrumf::new(0.8 as Float, 0.4, 0.1)
+                } else {
+                    RGBSpectrumf::new(0.1 as Float, 0.2, 0.6)
+                };
+                let square = quad(
+                    [p(x, y, 3.), p(x + 4., y, 3.), p(x + 4., y + 4., 3.), p(x, y + 4., 3.)],
+                    matte(0. as Float, 0. as Float, 0. as Float), Some(emission)
+                );
+                checker.extend(TriangleMesh::instances(&square).into_iter().map(|t| t.into()));
+            }
+        }
+        let scene = |components: Vec<ComponentPointer>| {
+            let light: Arc<Light> = Arc::new(PointLight::new(p(0., 0., -5.), RGBSpectrumf::grey_scale(25. as Float)));
+            Scene::new(vec![light], Arc::new(BVH::new(&components, BVHStrategy::SAH)))
+        };
+        let camera = |alpha: bool| {
+            let mut camera = perspec_cam(n, p(0., 0., -5.), p(0., 0., 0.), float::frac_pi_4());
+            camera.get_film_mut().set_alpha(alpha);
+            Arc::new(camera) as Arc<Camera>
+        };
+        let mut together = checker.clone();
+        together.push(sphere.clone().into());
+        let foreground = render(&scene(vec![sphere.into()]), camera(true), Kind::Whitted, 8);
+        let background = render(&scene(checker), camera(false), Kind::Whitted, 8);
+        let expected = render(&scene(together), camera(false), Kind::Whitted, 8);
+
+        let dim = foreground.dimension();
+        let (mut blended, mut sum) = (0, 0. as Float);
+        for y in 0..dim.y {
+            for x in 0..dim.x {
+                let alpha = foreground.alpha(Point2::new(x, y));
+                if alpha > 0. as Float && alpha < 1. as Float { blended += 1; }
+                // `foreground` is premultiplied
+                let composite = foreground[(x, y)] + background[(x, y)] * (1. as Float - alpha);
+                let e = expected[(x, y)];
+                let d = composite - e;
+                let d = d.r().abs().max(d.g().abs()).max(d.b().abs());
+                assert!(d < 0.1 as Float, "{:?} vs {:?} at ({}, {})", composite, e, x, y);
+                sum += d;
+            }
+        }
+        assert!(blended > 0);
+        // differing only by the coverage noise of the silhouette
+        let mean = sum / (dim.x * dim.y) as Float;
+        assert!(mean < 4e-3 as Float, "{}", mean);
+    }
+}
+
+#[cfg(test)]
+mod test_animation {
+    use test_utils::*;
+    use prelude::*;
+    use component::ComponentPointer;
+    use filming::film::Image;
+    use std::sync::Arc;
+    use rand::{SeedableRng, StdRng};
+
+    const RESOLUTION: usize = 32;
+
+    // an emissive sphere off the orbited origin, towards `+x+z`
+    fn scene() -> Scene {
+        let sphere = ShapedPrimitive::new(
+            Sphere::new(0.3 as Float, -0.3, 0.3, float::pi() * 2. as Float),
+            matte(0. as Float, 0., 0.),
+            Some(Arc::new(ConstantTexture{value: RGBSpectrumf::new(1. as Float, 1., 1.)}))
+        );
+        let offset = Vector3f::new(1. as Float, 0., 1.).normalize();
+        let translation = Matrix4f::from_translation(offset);
+        let sphere = Arc::new(TransformedComposable::new(
+            sphere, Arc::new(translation), Arc::new(translation.invert().unwrap())
+        ));
+        let components: Vec<ComponentPointer> = vec![(sphere as Arc<Composable>).into()];
+        Scene::new(Vec::new(), Arc::new(BVH::new(&components, BVHStrategy::SAH)))
+    }
+
+    fn driver(path: CameraPath) -> AnimationDriver {
+        let camera = perspec_cam(
+            RESOLUTION, Point3f::new(0. as Float, 0., 5.), Point3f::new(0. as Float, 0., 0.), float::frac_pi_4()
+        );
+        AnimationDriver::new(camera, path, "")
+    }
+
+    // seeded, such that identical frames render identically
+    fn whitted(camera: Arc<Camera>, _: &::std::path::Path, _: Option<&Image>) -> WhittedRenderer<StdStrataSampler> {
+        let sampler = StdStrataSampler::new(2, 2, 8, StdRng::from_seed(&[7, 11, 13][..]));
+        WhittedRenderer::new(sampler, camera, "", 0. as Float)
+    }
+
+    // luminance-weighted mean column
+    fn centroid(image: &Image) -> Float {
+        let (mut sum, mut x_sum) = (0. as Float, 0. as Float);
+        let dim = image.dimension();
+        for y in 0..dim.y {
+            for x in 0..dim.x {
+                let l = image[(x, y)].to_xyz().y;
+                sum += l;
+                x_sum += l * x as Float;
+            }
+        }
+        assert!(sum > 0. as Float);
+        x_sum / sum
+    }
+
+    #[test]
+    fn test_static_frames_identical() {
+        let scene = scene();
+        let view = driver(CameraPath::Orbit(Orbit{
+            target: Point3f::new(0. as Float, 0., 0.),
+            radius: 5. as Float, elevation: 0.3 as Float,
+            start_angle: 0.5 as Float, end_angle: 0.5 as Float, frames: 2,
+        }));
+        let mut factory = whitted;
+        let first = view.render_frame(&scene, 0, None, &mut factory);
+        let second = view.render_frame(&scene, 1, Some(&first), &mut factory);
+        let dim = first.dimension();
+        for y in 0..dim.y {
+            for x in 0..dim.x {
+                assert_eq!(first[(x, y)], second[(x, y)]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_orbit_moves_monotonically() {
+        let scene = scene();
+        let view = driver(CameraPath::Orbit(Orbit{
+            target: Point3f::new(0. as Float, 0., 0.),
+            radius: 5. as Float, elevation: 0.3 as Float,
+            start_angle: 0. as Float, end_angle: float::frac_pi_2(), frames: 5,
+        }));
+        let mut factory = whitted;
+        let centroids: Vec<_> = (0..view.frames()).map(|frame| {
+            centroid(&view.render_frame(&scene, frame, None, &mut factory))
+        }).collect();
+        let center = (RESOLUTION as Float - 1. as Float) * 0.5 as Float;
+        // the sphere crosses the middle of the frame halfway through
+        assert!((centroids[2] - center).abs() < 0.5 as Float, "{:?}", centroids);
+        let sign = (centroids[4] - centroids[0]).signum();
+        assert!((centroids[4] - centroids[0]).abs() > 4. as Float, "{:?}", centroids);
+        for w in centroids.windows(2) {
+            assert!((w[1] - w[0]) * sign > 0. as Float, "{:?}", centroids);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_adaptive {
+    use test_utils::*;
+    use prelude::*;
+    use component::ComponentPointer;
+    use sample::strata::StdStrataSampler;
+    use rand::{SeedableRng, StdRng};
+    use std::sync::Arc;
+
+    // a quad in front of a wall, both emitting the same radiance, with
+    // the quad's edges crossing the middles of rows and columns 4 and 11
+    fn overlapping() -> TestScene {
+        let p = |x: Float, y: Float, z: Float| Point3f::new(x, y, z);
+        // width of a pixel at the depth of the quad
+        let pixel = 4. as Float * (float::pi() / 8. as Float).tan() / 8. as Float;
+        let e = 3.5 as Float * pixel;
+        let emission = Some(RGBSpectrumf::new(0.5 as Float, 0.5, 0.5));
+        let meshes = vec![
+            quad([p(-e, -e, 0.), p(e, -e, 0.), p(e, e, 0.), p(-e, e, 0.)], matte(0. as Float, 0., 0.), emission),
+            quad([p(-20., -20., 4.), p(20., -20., 4.), p(20., 20., 4.), p(-20., 20., 4.)], matte(0. as Float, 0., 0.), emission),
+        ];
+        let mut components: Vec<ComponentPointer> = Vec::new();
+        let mut lights: Vec<Arc<Light>> = Vec::new();
+        for mesh in &meshes {
+            lights.push(Arc::new(MeshLight::new(mesh.clone())));
+            for triangle in TriangleMesh::instances(mesh) {
+                components.push(triangle.into());
+            }
+        }
+        TestScene{
+            scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+            camera: Arc::new(perspec_cam(16, p(0., 0., -4.), p(0., 0., 0.), float::frac_pi_4())),
+        }
+    }
+
+    #[test]
+    fn test_edges_sampled_more() {
+        let scene = overlapping();
+        let sampler = StdStrataSampler::new(4, 4, 8, StdRng::from_seed(&[7, 11, 13][..]));
+        let mut renderer = PTRenderer::new(sampler, scene.camera.clone(), "", 1, true);
+        renderer.set_adaptive(Some(AdaptiveSampling{
+            max_rounds: 2,
+            threshold: 0.02 as Float,
+            luminance_weight: 1. as Float,
+            geometry_weight: 1. as Float,
+        }));
+        let (image, counts) = renderer.render_sample_counts(&scene.scene);
+        // nothing to see in the image
+        let mean = mean(&image);
+        assert_relative_eq!(mean.r(), 0.5 as Float, epsilon = 1e-4);
+        for p in counts.bounding() {
+            let (x, y) = (p.x, p.y);
+            let edge = ((x == 4 || x == 11) && y >= 4 && y <= 11)
+                || ((y == 4 || y == 11) && x >= 4 && x <= 11);
+            let count = *counts.get_pixel(p);
+            if edge {
+                assert_eq!(count, 48, "edge pixel {:?}", p);
+            } else {
+                assert_eq!(count, 16, "flat pixel {:?}", p);
+            }
+        }
+
+        // without the geometric criterion, nothing stands out
+        renderer.set_adaptive(Some(AdaptiveSampling{
+            max_rounds: 2,
+            threshold: 0.02 as Float,
+            luminance_weight: 1. as Float,
+            geometry_weight: 0. as Float,
+        }));
+        let (_, counts) = renderer.render_sample_counts(&scene.scene);
+        assert!(counts.bounding().into_iter().all(|p| *counts.get_pixel(p) == 16));
+    }
+}
+
+#[cfg(test)]
+mod test_prepass {
+    use test_utils::*;
+    use prelude::*;
+    use component::ComponentPointer;
+    use sample::strata::StdStrataSampler;
+    use rand::{SeedableRng, StdRng};
+    use rayon::{Configuration, ThreadPool};
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    // a cluster of glass spheres, seen through the quadrant of the film
+    // around the raster position returned, in front of a lit wall
+    fn cluster(resolution: usize) -> (TestScene, Point2f) {
+        let p = |x: Float, y: Float, z: Float| Point3f::new(x, y, z);
+        let wall = quad(
+            [p(-20., -20., 4.), p(20., -20., 4.), p(20., 20., 4.), p(-20., 20., 4.)],
+            matte(0.5 as Float, 0.5, 0.5), Some(RGBSpectrumf::new(0.5 as Float, 0.5, 0.5))
+        );
+        let mut components: Vec<ComponentPointer> = Vec::new();
+        for triangle in TriangleMesh::instances(&wall) {
+            components.push(triangle.into());
+        }
+        let center = p(-0.8, 0.8, 0.);
+        for i in 0..4 {
+            for j in 0..4 {
+                for k in 0..2 {
+                    let glass = GlassMaterial::new(
+                        Arc::new(ConstantTexture{value: RGBSpectrumf::new(1. as Float, 1. as Float, 1. as Float)}),
+                        Arc::new(ConstantTexture{value: RGBSpectrumf::new(1. as Float, 1. as Float, 1. as Float)}),
+                        Arc::new(ConstantTexture{value: 0. as Float}),
+                        1.5 as Float,
+                        None
+                    );
+                    let sphere = ShapedPrimitive::new(
+                        Sphere::new(0.12 as Float, -0.12 as Float, 0.12 as Float, float::pi() * 2. as Float),
+                        glass, None
+                    );
+                    let offset = Vector3f::new(i as Float - 1.5 as Float, j as Float - 1.5 as Float, k as Float) * 0.2 as Float;
+                    let translation = Matrix4f::from_translation(center - Point3f::new(0. as Float, 0., 0.) + offset);
+                    let sphere: Arc<Composable> = Arc::new(TransformedComposable::new(
+                        sphere, Arc::new(translation), Arc::new(translation.invert().unwrap())
+                    ));
+                    components.push(sphere.into());
+                }
+            }
+        }
+        let lights: Vec<Arc<Light>> = vec![Arc::new(MeshLight::new(wall))];
+        let eye = p(0., 0., -4.);
+        let mut camera = perspec_cam(resolution, eye, p(0., 0., 0.), float::frac_pi_4());
+        camera.get_film_mut().set_tile_size(Some(resolution as isize / 4));
+        let (_, raster) = camera.evaluate_importance(eye, center - eye).unwrap();
+        (TestScene{
+            scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+            camera: Arc::new(camera),
+        }, raster)
+    }
+
+    fn renderer(scene: &TestScene) -> PTRenderer<StdStrataSampler> {
+        let sampler = StdStrataSampler::new(4, 4, 8, StdRng::from_seed(&[7, 11, 13][..]));
+        PTRenderer::new(sampler, scene.camera.clone(), "", 16, true)
+    }
+
+    #[test]
+    fn test_complex_quadrant_first() {
+        let (scene, raster) = cluster(64);
+        let mut renderer = renderer(&scene);
+        renderer.set_prepass(Some(Prepass{ stride: 4, samples: 16, allocate: false, rounds: 1 }));
+        let order = renderer.tile_order(&scene.scene);
+        assert_eq!(order.len(), 16);
+        let half = 32 as Float;
+        let in_quadrant = |bbox: &BBox2<isize>| {
+            (bbox.pmin.x as Float >= half) == (raster.x >= half)
+                && (bbox.pmin.y as Float >= half) == (raster.y >= half)
+        };
+        for bbox in &order[..4] {
+            assert!(in_quadrant(bbox), "{:?} started before the cluster, in {:?}", bbox, order);
+        }
+    }
+
+    #[test]
+    fn test_allocation_follows_noise() {
+        let (scene, raster) = cluster(32);
+        let mut renderer = renderer(&scene);
+        renderer.set_prepass(Some(Prepass{ stride: 4, samples: 16, allocate: true, rounds: 2 }));
+        let (image, counts) = renderer.render_sample_counts(&scene.scene);
+        let quadrant = |p: Point2<isize>| {
+            (p.x as Float >= 16.) == (raster.x >= 16. as Float)
+                && (p.y as Float >= 16.) == (raster.y >= 16. as Float)
+        };
+        let (mut noisy, mut flat) = (0, 0);
+        for p in counts.bounding() {
+            if quadrant(p) {
+                noisy = noisy.max(*counts.get_pixel(p));
+            } else {
+                flat = flat.max(*counts.get_pixel(p));
+            }
+        }
+        assert!(noisy > 32 && flat == 16, "{} samples around the cluster, {} elsewhere", noisy, flat);
+        // the wall alone is unaffected
+        let reference = renderer_image(&scene);
+        assert_relative_eq!(image[Point2::new(30, 30)].r(), reference[Point2::new(30, 30)].r(), epsilon = 1e-4);
+    }
+
+    fn renderer_image(scene: &TestScene) -> ::filming::film::Image {
+        renderer(scene).render_image(&scene.scene)
+    }
+
+    // run with `cargo test --release -- --ignored bench_prepass_ordering --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_prepass_ordering() {
+        let (scene, _) = cluster(128);
+        let pool = ThreadPool::new(Configuration::new().num_threads(8)).unwrap();
+        let time = |prepass: Option<Prepass>| {
+            let mut renderer = renderer(&scene);
+            renderer.set_prepass(prepass);
+            let start = Instant::now();
+            pool.install(|| renderer.render_image(&scene.scene));
+            let elapsed = start.elapsed();
+            elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9
+        };
+        let naive = time(None);
+        let ordered = time(Some(Prepass::default()));
+        println!("{:.4}s in the film's order, {:.4}s costliest first", naive, ordered);
+        assert!(ordered < naive * 1.1);
+    }
+}
+
+#[cfg(test)]
+mod test_clip {
+    use test_utils::*;
+    use prelude::*;
+    use component::ComponentPointer;
+    use sample::strata::StdStrataSampler;
+    use rand::{SeedableRng, StdRng};
+    use filming::film::Image;
+    use std::sync::Arc;
+
+    const RESOLUTION: usize = 32;
+
+    // an open-bottomed box over $[-1, 1] \times [0, 2] \times [-1, 1]$
+    // resting on a floor, lit from the side by a point light, such that
+    // it casts a shadow towards `-x`
+    fn boxed() -> Scene {
+        let p = |x: Float, y: Float, z: Float| Point3f::new(x, y, z);
+        let white = || matte(0.8 as Float, 0.8, 0.8);
+        let meshes = vec![
+            quad([p(-8., 0., -8.), p(8., 0., -8.), p(8., 0., 8.), p(-8., 0., 8.)], white(), None),
+            quad([p(-1., 2., -1.), p(1., 2., -1.), p(1., 2., 1.), p(-1., 2., 1.)], white(), None),
+            quad([p(-1., 0., -1.), p(1., 0., -1.), p(1., 2., -1.), p(-1., 2., -1.)], white(), None),
+            quad([p(-1., 0., 1.), p(1., 0., 1.), p(1., 2., 1.), p(-1., 2., 1.)], white(), None),
+            quad([p(-1., 0., -1.), p(-1., 0., 1.), p(-1., 2., 1.), p(-1., 2., -1.)], white(), None),
+            quad([p(1., 0., -1.), p(1., 0., 1.), p(1., 2., 1.), p(1., 2., -1.)], white(), None),
+        ];
+        let mut components: Vec<ComponentPointer> = Vec::new();
+        for mesh in &meshes {
+            for triangle in TriangleMesh::instances(mesh) {
+                components.push(triangle.into());
+            }
+        }
+        let lights: Vec<Arc<Light>> = vec![Arc::new(PointLight::new(
+            p(4., 8., 0.), RGBSpectrumf::new(60. as Float, 60., 60.)
+        ))];
+        Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH)))
+    }
+
+    // looking down at the box from above its front
+    fn camera(clip_planes: Vec<ClipPlane>) -> Arc<Camera> {
+        let mut camera = perspec_cam(
+            RESOLUTION, Point3f::new(0. as Float, 8., -3.), Point3f::new(0. as Float, 0., 0.), float::frac_pi_4()
+        );
+        camera.set_clip_planes(clip_planes);
+        Arc::new(camera)
+    }
+
+    // cutting the top of the box off above $y = 1.2$
+    fn cut() -> Vec<ClipPlane> {
+        vec![ClipPlane::through(Point3f::new(0. as Float, 1.2, 0.), Vector3f::new(0. as Float, 1., 0.))]
+    }
+
+    fn renderer(camera: Arc<Camera>) -> PTRenderer<StdStrataSampler> {
+        let sampler = StdStrataSampler::new(2, 2, 8, StdRng::from_seed(&[7, 11, 13][..]));
+        PTRenderer::new(sampler, camera, "", 3, false)
+    }
+
+    // direct lighting only, the shadow coming out black
+    fn direct(camera: Arc<Camera>) -> PTRenderer<StdStrataSampler> {
+        let sampler = StdStrataSampler::new(2, 2, 8, StdRng::from_seed(&[7, 11, 13][..]));
+        PTRenderer::new(sampler, camera, "", 1, false)
+    }
+
+    // id of what's first hit from `origin` along `dir`
+    fn id_along(scene: &Scene, origin: Point3f, dir: Vector3f) -> u32 {
+        let mut ray = RawRay::from_od(origin, dir);
+        scene.primitive_id(scene.aggregate.intersect_ray(&mut ray).unwrap().primitive_hit.unwrap())
+    }
+
+    #[test]
+    fn test_interior_shown() {
+        let scene = boxed();
+        let top = id_along(&scene, Point3f::new(0. as Float, 5., 0.), Vector3f::new(0. as Float, -1., 0.));
+        let floor = id_along(&scene, Point3f::new(0. as Float, -1., 0.), Vector3f::new(0. as Float, 1., 0.));
+        let center = Point2::new(RESOLUTION as isize / 2, RESOLUTION as isize / 2);
+        assert_eq!(renderer(camera(Vec::new())).render_ids(&scene).get(center), top);
+        assert_eq!(renderer(camera(cut())).render_ids(&scene).get(center), floor);
+        // the floor within the box is only lit indirectly
+        let pixel = |image: &Image| image[(center.x as u32, center.y as u32)].to_xyz().y;
+        let full = renderer(camera(Vec::new())).render_image(&scene);
+        let clipped = renderer(camera(cut())).render_image(&scene);
+        assert!(pixel(&clipped) < 0.5 as Float * pixel(&full), "{} against {}", pixel(&clipped), pixel(&full));
+    }
+
+    #[test]
+    fn test_shadow_kept() {
+        let scene = boxed();
+        let floor = id_along(&scene, Point3f::new(0. as Float, -1., 0.), Vector3f::new(0. as Float, 1., 0.));
+        let full_ids = renderer(camera(Vec::new())).render_ids(&scene);
+        let clipped_ids = renderer(camera(cut())).render_ids(&scene);
+        let full = direct(camera(Vec::new())).render_image(&scene);
+        let clipped = direct(camera(cut())).render_image(&scene);
+        let bounding = full_ids.bounding();
+        // pixels seeing the floor outside the box in both, away from edges
+        let open = |p: Point2<isize>| {
+            (-1..2).all(|dy| (-1..2).all(|dx| {
+                let q = Point2::new(p.x + dx, p.y + dy);
+                !bounding.contain_lb(q)
+                    || (full_ids.get(q) == floor && clipped_ids.get(q) == floor)
+            }))
+        };
+        // samples are jittered differently once the paths of earlier
+        // pixels differ, but shadowed pixels stay exactly black
+        let (mut compared, mut shadowed) = (0, 0);
+        let lit = full.dimension();
+        let lit = full[(lit.x - 1, lit.y / 2)].to_xyz().y;
+        for p in bounding {
+            if !open(p) { continue; }
+            let (x, y) = (p.x as u32, p.y as u32);
+            let (a, b) = (full[(x, y)].to_xyz().y, clipped[(x, y)].to_xyz().y);
+            assert!((a - b).abs() < 0.05 as Float * lit, "{} against {} at {:?}", b, a, p);
+            assert_eq!(a == 0. as Float, b == 0. as Float, "at {:?}", p);
+            compared += 1;
+            if a == 0. as Float { shadowed += 1; }
+        }
+        assert!(compared > 100, "{} compared", compared);
+        assert!(shadowed > 10, "{} shadowed", shadowed);
+    }
+
+    #[test]
+    fn test_unclipped_identical() {
+        let scene = boxed();
+        let full = renderer(camera(Vec::new())).render_image(&scene);
+        let far = vec![ClipPlane::through(Point3f::new(0. as Float, 100., 0.), Vector3f::new(0. as Float, 1., 0.))];
+        for planes in vec![Vec::new(), far] {
+            let image = renderer(camera(planes)).render_image(&scene);
+            let dim = full.dimension();
+            for y in 0..dim.y {
+                for x in 0..dim.x {
+                    assert_eq!(full[(x, y)], image[(x, y)]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_bake {
+    use test_utils::*;
+    use prelude::*;
+    use component::ComponentPointer;
+    use sample::strata::StdStrataSampler;
+    use filming::film::Image;
+    use rand::{SeedableRng, StdRng};
+    use std::sync::Arc;
+    use tobj;
+
+    const RESOLUTION: usize = 16;
+
+    // a quad spanning $[-1, 1]^2$ at $y = 0$ facing `+y`, whose texture
+    // coordinates span `uvs` along `x` and `z`
+    fn floor(uvs: [Float; 2]) -> Arc<TriangleMesh> {
+        let corners = [(-1., -1.), (-1., 1.), (1., 1.), (1., -1.)];
+        let mut positions = Vec::new();
+        let mut texcoords = Vec::new();
+        for &(x, z) in &corners {
+            positions.extend_from_slice(&[x as f32, 0., z as f32]);
+            let t = |c: f32| uvs[0] as f32 + (c + 1.) * 0.5 * (uvs[1] - uvs[0]) as f32;
+            texcoords.extend_from_slice(&[t(x as f32), t(z as f32)]);
+        }
+        let model = tobj::Model::new(
+            tobj::Mesh::new(positions, Vec::new(), texcoords, vec![0, 1, 2, 0, 2, 3], None),
+            "floor".to_owned()
+        );
+        Arc::new(TriangleMesh::from_model(model, matte(0.5 as Float, 0.5, 0.5), None))
+    }
+
+    // `mesh` within a black sphere of radius 10 uniformly emitting
+    // `radiance` inwards, thus irradiated by $\pi$ times `radiance`
+    fn furnace(mesh: &Arc<TriangleMesh>, radiance: Float) -> Scene {
+        let mut outer = ShapedPrimitive::new(
+            Sphere::new(10. as Float, -10. as Float, 10. as Float, float::pi() * 2. as Float),
+            matte(0. as Float, 0., 0.),
+            Some(Arc::new(ConstantTexture{value: RGBSpectrumf::new(radiance, radiance, radiance)}))
+        );
+        outer.emission_side = EmissionSide::Back;
+        let outer = Arc::new(outer);
+        let mut components: Vec<ComponentPointer> = vec![(outer.clone() as Arc<Composable>).into()];
+        for triangle in TriangleMesh::instances(mesh) {
+            components.push(triangle.into());
+        }
+        let lights: Vec<Arc<Light>> = vec![outer];
+        Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH)))
+    }
+
+    fn baker(mesh: &Arc<TriangleMesh>, dilation: usize) -> BakeRenderer<StdStrataSampler> {
+        let sampler = StdStrataSampler::new(8, 8, 8, StdRng::from_seed(&[7, 11, 13][..]));
+        let mut renderer = BakeRenderer::new(
+            sampler, mesh.clone(), Point2::new(RESOLUTION, RESOLUTION), "", 4, true
+        ).unwrap();
+        renderer.set_dilation(dilation);
+        renderer
+    }
+
+    // texels of `image` that aren't black
+    fn filled(image: &Image) -> Vec<(u32, u32)> {
+        let mut ret = Vec::new();
+        for y in 0..RESOLUTION as u32 {
+            for x in 0..RESOLUTION as u32 {
+                if !image[(x, y)].is_black() { ret.push((x, y)); }
+            }
+        }
+        ret
+    }
+
+    #[test]
+    fn test_uniform_irradiance() {
+        let mesh = floor([0.25, 0.75]);
+        let scene = furnace(&mesh, 0.5 as Float);
+        let mut renderer = baker(&mesh, 0);
+        let image = renderer.render_image(&scene);
+        let expected = 0.5 as Float * float::pi();
+        let texels = filled(&image);
+        assert_eq!(texels.len(), renderer.covered_texels());
+        // the chart spans texels 4 to 11 along both axes
+        assert_eq!(texels.len(), 64);
+        let mut sum = 0. as Float;
+        for &(x, y) in &texels {
+            assert!(x >= 4 && x < 12 && y >= 4 && y < 12, "({}, {}) outside the chart", x, y);
+            let e = image[(x, y)].to_xyz().y;
+            assert_relative_eq!(e, expected, max_relative = 0.1 as Float);
+            sum += e;
+        }
+        assert_relative_eq!(sum / texels.len() as Float, expected, max_relative = 0.02 as Float);
+    }
+
+    #[test]
+    fn test_dilation() {
+        let mesh = floor([0.25, 0.75]);
+        let scene = furnace(&mesh, 0.5 as Float);
+        let image = baker(&mesh, 2).render_image(&scene);
+        let expected = 0.5 as Float * float::pi();
+        for y in 0..RESOLUTION as u32 {
+            for x in 0..RESOLUTION as u32 {
+                // texels from the chart in the chessboard metric
+                let distance = |c: u32| if c < 4 { 4 - c } else if c >= 12 { c - 11 } else { 0 };
+                let e = image[(x, y)].to_xyz().y;
+                if distance(x).max(distance(y)) <= 2 {
+                    assert_relative_eq!(e, expected, max_relative = 0.1 as Float);
+                } else {
+                    assert_eq!(e, 0. as Float, "({}, {}) filled", x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_layouts() {
+        let sampler = || StdStrataSampler::new(1, 1, 8, StdRng::from_seed(&[7, 11, 13][..]));
+        let resolution = Point2::new(RESOLUTION, RESOLUTION);
+        let p = |x: Float, z: Float| Point3f::new(x, 0., z);
+        let bare = quad([p(-1., -1.), p(-1., 1.), p(1., 1.), p(1., -1.)], matte(0.5 as Float, 0.5, 0.5), None);
+        assert_eq!(
+            BakeRenderer::new(sampler(), bare, resolution, "", 1, false).err(),
+            Some(BakeError::MissingUVs)
+        );
+        // both triangles mapped onto the same half of the layout
+        let model = tobj::Model::new(
+            tobj::Mesh::new(
+                vec![-1., 0., -1., -1., 0., 1., 1., 0., 1., 1., 0., -1.],
+                Vec::new(),
+                vec![0., 0., 0., 1., 1., 1., 0., 0.],
+                vec![0, 1, 2, 3, 1, 2], None
+            ),
+            "folded".to_owned()
+        );
+        let folded = Arc::new(TriangleMesh::from_model(model, matte(0.5 as Float, 0.5, 0.5), None));
+        match BakeRenderer::new(sampler(), folded, resolution, "", 1, false) {
+            Err(BakeError::OverlappingUVs{texels}) => assert!(texels > 50, "{} texels", texels),
+            _ => panic!("overlap undetected"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_whitted_light_samples {
+    use test_utils::*;
+    use prelude::*;
+    use component::ComponentPointer;
+    use sample::strata::StdStrataSampler;
+    use rand::{SeedableRng, StdRng};
+    use filming::film::Image;
+    use std::sync::Arc;
+
+    const RESOLUTION: usize = 64;
+
+    // a floor lit by quad lights at `lights` overhead, shadowed over
+    // `x < 0` by a blocker halfway up, hidden from the camera as the lights
+    fn penumbra(lights: &[Float]) -> TestScene {
+        let p = |x: Float, y: Float, z: Float| Point3f::new(x, y, z);
+        let hidden = VisibilityFlags::all() - VISIBLE_CAMERA;
+        let mut meshes = vec![
+            quad([p(-8., 0., -8.), p(8., 0., -8.), p(8., 0., 8.), p(-8., 0., 8.)], matte(0.8 as Float, 0.8, 0.8), None),
+            quad([p(-8., 1., -8.), p(0., 1., -8.), p(0., 1., 8.), p(-8., 1., 8.)], matte(0.8 as Float, 0.8, 0.8), None),
+        ];
+        for &x in lights {
+            meshes.push(quad(
+                [p(x - 0.5, 2., -0.5), p(x + 0.5, 2., -0.5), p(x + 0.5, 2., 0.5), p(x - 0.5, 2., 0.5)],
+                matte(0. as Float, 0., 0.), Some(RGBSpectrumf::new(4. as Float, 4., 4.))
+            ));
+        }
+        let mut components: Vec<ComponentPointer> = Vec::new();
+        let mut scene_lights: Vec<Arc<Light>> = Vec::new();
+        for (i, mut mesh) in meshes.into_iter().enumerate() {
+            if i > 0 {
+                Arc::get_mut(&mut mesh).unwrap().set_visibility(hidden);
+            }
+            if mesh.is_emissive() {
+                scene_lights.push(Arc::new(MeshLight::new(mesh.clone())));
+            }
+            components.extend(TriangleMesh::instances(&mesh).map(ComponentPointer::from));
+        }
+        TestScene{
+            scene: Scene::new(scene_lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+            camera: camera(RESOLUTION, p(0., 5., -2.), p(0., 0., 0.), float::frac_pi_4()),
+        }
+    }
+
+    fn render(scene: &TestScene, nsamples: usize, all_lights: bool) -> Image {
+        let sampler = StdStrataSampler::new(1, 1, 8, StdRng::from_seed(&[7, 11, 13][..]));
+        let mut renderer = WhittedRenderer::new(sampler, scene.camera.clone(), "", 0. as Float);
+        renderer.set_light_samples(nsamples);
+        renderer.set_sample_all_lights(all_lights);
+        renderer.render_image(&scene.scene)
+    }
+
+    // mean luminance of each column over the middle half of the rows
+    fn profile(image: &Image) -> Vec<Float> {
+        let n = RESOLUTION as u32;
+        (0..n).map(|x| {
+            (n / 4..3 * n / 4).map(|y| image[(x, y)].to_xyz().y).sum::<Float>() / (n / 2) as Float
+        }).collect()
+    }
+
+    #[test]
+    fn test_smooth_penumbra() {
+        let profile = profile(&render(&penumbra(&[0. as Float]), 16, true));
+        // walk from the umbra towards the brightest column
+        let peak = (0..profile.len()).max_by(|&a, &b| profile[a].partial_cmp(&profile[b]).unwrap()).unwrap();
+        let ramp: Vec<Float> = if profile[0] < profile[profile.len() - 1] {
+            profile[..peak + 1].to_vec()
+        } else {
+            profile[peak..].iter().rev().cloned().collect()
+        };
+        let top = profile[peak];
+        assert!(ramp[0] < 0.01 as Float * top, "no umbra in {:?}", profile);
+        // enough columns across the penumbra to tell bands from a ramp
+        let penumbra = ramp.iter().filter(|&&v| v > 0.05 as Float * top && v < 0.95 as Float * top).count();
+        assert!(penumbra >= 8, "penumbra of {} columns in {:?}", penumbra, profile);
+        for w in ramp.windows(2) {
+            assert!(w[1] >= w[0] - 0.02 as Float * top, "banding in {:?}", profile);
+        }
+    }
+
+    #[test]
+    fn test_one_light_per_sample() {
+        let scene = penumbra(&[-1. as Float, 1.]);
+        let all = mean(&render(&scene, 16, true)).to_xyz().y;
+        let one = mean(&render(&scene, 16, false)).to_xyz().y;
+        assert!((all - one).abs() < 0.03 as Float * all, "{} against {}", one, all);
+    }
+}
+
+#[cfg(test)]
+mod test_ambient_occlusion {
+    use test_utils::*;
+    use prelude::*;
+    use component::ComponentPointer;
+    use filming::film::Image;
+    use sample::prelude::StdStrataSampler;
+    use rand::{SeedableRng, StdRng};
+    use std::sync::Arc;
+
+    const FALLOFFS: [OcclusionFalloff; 3] = [
+        OcclusionFalloff::Constant, OcclusionFalloff::Linear, OcclusionFalloff::Smooth
+    ];
+
+    fn scene(quads: Vec<Arc<TriangleMesh>>, camera: Arc<Camera>) -> TestScene {
+        let components: Vec<ComponentPointer> = quads.iter()
+            .flat_map(|mesh| TriangleMesh::instances(mesh))
+            .map(ComponentPointer::Triangle).collect();
+        TestScene{
+            scene: Scene::new(Vec::new(), Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+            camera: camera,
+        }
+    }
+
+    // the same samples for every render
+    fn render_ao(scene: &TestScene, params: AmbientOcclusion) -> Image {
+        let sampler = StdStrataSampler::new(2, 2, 8, StdRng::from_seed(&[978usize][..]));
+        AORenderer::new(sampler, scene.camera.clone(), "", params).render_image(&scene.scene)
+    }
+
+    fn pixels(image: &Image) -> Vec<Float> {
+        let dim = image.dimension();
+        let mut ret = Vec::new();
+        for y in 0..dim.y {
+            for x in 0..dim.x {
+                ret.push(image[Point2::new(x, y)].r());
+            }
+        }
+        ret
+    }
+
+    #[test]
+    fn test_open_plane() {
+        let p = |x: Float, y: Float| Point3f::new(x, y, 0. as Float);
+        let r = 1000. as Float;
+        let plane = scene(
+            vec![quad([p(-r, -r), p(r, -r), p(r, r), p(-r, r)], matte(0.5, 0.5, 0.5), None)],
+            camera(8, Point3f::new(0. as Float, 0., 2.), p(0., 0.), float::frac_pi_4())
+        );
+        for &falloff in &FALLOFFS {
+            let params = AmbientOcclusion{max_distance: r, samples: 16, falloff: falloff};
+            let image = render(&plane, RendererKind::AO(params), 4);
+            for v in pixels(&image) {
+                assert_eq!(v, 1. as Float, "{:?}", falloff);
+            }
+        }
+    }
+
+    #[test]
+    fn test_box_interior() {
+        let p = |x: Float, y: Float, z: Float| Point3f::new(x, y, z);
+        let white = matte(0.5, 0.5, 0.5);
+        let walls = vec![
+            quad([p(-1., -1., -1.), p(1., -1., -1.), p(1., -1., 1.), p(-1., -1., 1.)], white.clone(), None),
+            quad([p(-1., 1., -1.), p(-1., 1., 1.), p(1., 1., 1.), p(1., 1., -1.)], white.clone(), None),
+            quad([p(-1., -1., 1.), p(1., -1., 1.), p(1., 1., 1.), p(-1., 1., 1.)], white.clone(), None),
+            quad([p(-1., -1., -1.), p(-1., 1., -1.), p(1., 1., -1.), p(1., -1., -1.)], white.clone(), None),
+            quad([p(-1., -1., -1.), p(-1., -1., 1.), p(-1., 1., 1.), p(-1., 1., -1.)], white.clone(), None),
+            quad([p(1., -1., -1.), p(1., 1., -1.), p(1., 1., 1.), p(1., -1., 1.)], white, None),
+        ];
+        let room = scene(walls, camera(8, p(0., 0., 0.), p(0., 0., 1.), float::frac_pi_4()));
+        for &falloff in &[OcclusionFalloff::Constant, OcclusionFalloff::Linear] {
+            // hits within the box count for little against a linear
+            // falloff over a short distance, so it's given a far longer one
+            let max_distance = if falloff == OcclusionFalloff::Constant { 4. as Float } else { 1000. as Float };
+            let params = AmbientOcclusion{max_distance: max_distance, samples: 16, falloff: falloff};
+            let image = render(&room, RendererKind::AO(params), 4);
+            let center = image[Point2::new(4, 4)].r();
+            assert!(center < 0.01 as Float, "{:?} {}", falloff, center);
+            assert!(mean(&image).r() < 0.01 as Float, "{:?}", falloff);
+        }
+    }
+
+    #[test]
+    fn test_halving_distance() {
+        let cornell = cornell_box(8);
+        for &falloff in &FALLOFFS {
+            let mut previous: Option<Vec<Float>> = None;
+            for &max_distance in &[2. as Float, 1., 0.5, 0.25] {
+                let params = AmbientOcclusion{max_distance: max_distance, samples: 8, falloff: falloff};
+                let current = pixels(&render_ao(&cornell, params));
+                if let Some(previous) = previous {
+                    for (a, b) in previous.iter().zip(&current) {
+                        assert!(*b >= *a - 1e-5 as Float, "{:?} at {}: {} to {}", falloff, max_distance, a, b);
+                    }
+                    let sum = |v: &[Float]| v.iter().fold(0. as Float, |s, &x| s + x);
+                    assert!(sum(&current) > sum(&previous), "{:?} at {}", falloff, max_distance);
+                }
+                previous = Some(current);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_light_cache {
+    use test_utils::*;
+    use prelude::*;
+    use component::ComponentPointer;
+    use sample::strata::StdStrataSampler;
+    use rand::{SeedableRng, StdRng};
+    use filming::film::Image;
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    const REFLECTANCE: Float = 0.8 as Float;
+
+    // a floor under a `n`x`n` grid of point lights 10 apart, hanging
+    // one above it, seen at an angle such that each tile covers the
+    // regions around a few lights
+    fn lamps(resolution: usize, n: usize) -> TestScene {
+        let p = |x: Float, y: Float, z: Float| Point3f::new(x, y, z);
+        let half = 5. as Float * n as Float;
+        let floor = quad(
+            [p(-half, 0., -half), p(half, 0., -half), p(half, 0., half), p(-half, 0., half)],
+            matte(REFLECTANCE, REFLECTANCE, REFLECTANCE), None
+        );
+        let components: Vec<ComponentPointer> = TriangleMesh::instances(&floor).map(ComponentPointer::from).collect();
+        let mut lights: Vec<Arc<Light>> = Vec::with_capacity(n * n);
+        for i in 0..n {
+            for j in 0..n {
+                let x = 10. as Float * i as Float + 5. as Float - half;
+                let z = 10. as Float * j as Float + 5. as Float - half;
+                lights.push(Arc::new(PointLight::new(p(x, 1., z), RGBSpectrumf::grey_scale(10. as Float))));
+            }
+        }
+        let mut camera = perspec_cam(resolution, p(0., 1.2 * half, -1.2 * half), p(0., 0., 0.), float::frac_pi_2());
+        camera.get_film_mut().set_tile_size(Some(resolution as isize / 8));
+        TestScene{
+            scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+            camera: Arc::new(camera),
+        }
+    }
+
+    /// a single sample through each pixel's center, drawing the same
+    /// samples however many were drawn before
+    #[derive(Clone)]
+    struct Centered;
+
+    impl Sampler for Centered {
+        fn start_pixel(&mut self, _p: Point2<u32>) { }
+        fn next(&mut self) -> Float { 0.5 as Float }
+        fn sample_per_pixel(&self) -> usize { 1 }
+        fn set_sample_per_pixel(&mut self, _spp: usize) { }
+        fn next_sample(&mut self) -> bool { false }
+        fn set_sample_index(&mut self, idx: usize) -> bool { idx == 0 }
+    }
+
+    fn render<S: Sampler>(scene: &TestScene, sampler: S, threshold: Float) -> Image {
+        WhittedRenderer::new(sampler, scene.camera.clone(), "", threshold).render_image(&scene.scene)
+    }
+
+    #[test]
+    fn test_culled_agrees() {
+        let scene = lamps(64, 8);
+        let threshold = 0.01 as Float;
+        let unculled = render(&scene, Centered, 0. as Float);
+        let culled = render(&scene, Centered, threshold);
+        // with the same samples and point lights, culling only drops
+        // contributions. Each culled light delivers less than `threshold`
+        // to the lambertian floor, adding at most `REFLECTANCE/pi*threshold`
+        // to a pixel.
+        let bound = scene.scene.lights.len() as Float * REFLECTANCE * float::frac_1_pi() * threshold;
+        let mut dropped = 0. as Float;
+        for y in 0..64 {
+            for x in 0..64 {
+                let (a, b) = (unculled[(x, y)].to_xyz().y, culled[(x, y)].to_xyz().y);
+                assert!(b <= a + 1e-4 as Float && a - b <= bound, "{} culled to {} at {:?}", a, b, (x, y));
+                dropped += a - b;
+            }
+        }
+        let total = mean(&unculled).to_xyz().y * (64 * 64) as Float;
+        assert!(dropped > 0. as Float, "nothing culled");
+        // in practice, the far lights culled add up to well under a percent
+        assert!(dropped < 0.01 as Float * total, "{} out of {} dropped", dropped, total);
+    }
+
+    // run with `cargo test --release -- --ignored bench_light_cache --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_light_cache() {
+        let scene = lamps(256, 8);
+        let time = |threshold: Float| {
+            let start = Instant::now();
+            let sampler = StdStrataSampler::new(2, 2, 8, StdRng::from_seed(&[7, 11, 13][..]));
+            render(&scene, sampler, threshold);
+            let elapsed = start.elapsed();
+            elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9
+        };
+        let unculled = time(0. as Float);
+        let culled = time(0.01 as Float);
+        println!("64 lights: {:.4}s unculled, {:.4}s culled", unculled, culled);
+        assert!(culled < unculled);
+    }
+}
@@ -10,64 +10,251 @@
 
 use bxdf::*;
 use sample::Sampler;
-use filming::Camera;
+use filming::{Camera, SampleInfo};
+use filming::clip::ClipPlane;
 use super::Renderer;
 use std::sync::Arc;
 use super::scene::Scene;
-use filming::film::FilmTile;
+use filming::film::{FilmTile, FilmPreview, Image};
 use spectrum::{RGBSpectrumf, Spectrum};
 use rayon::prelude::*;
 use aren_alloc::Allocator;
 use geometry::prelude::*;
 use std::path::{PathBuf, Path};
+use std::time::Duration;
 
 /// whitted renderer
 pub struct WhittedRenderer<S> {
     sampler: S,
     camera: Arc<Camera>,
     path: PathBuf,
+    light_threshold: Float,
+    preview_interval: Option<Duration>,
+    light_samples: usize,
+    sample_all_lights: bool,
 }
 
 impl<S: Sampler> WhittedRenderer<S> {
-    pub fn new<P: AsRef<Path> + ?Sized>(sampler: S, camera: Arc<Camera>, path: &P) -> WhittedRenderer<S> {
+    /// construction.
+    ///
+    /// Lights whose incoming luminance is bounded below `light_threshold`
+    /// over a tile's visible region would be skipped when shading that
+    /// tile. Zero disables culling.
+    pub fn new<P: AsRef<Path> + ?Sized>(sampler: S, camera: Arc<Camera>, path: &P, light_threshold: Float) -> WhittedRenderer<S> {
         WhittedRenderer{
             sampler: sampler,
             camera: camera,
             path: path.as_ref().to_path_buf(),
+            light_threshold: light_threshold,
+            preview_interval: None,
+            light_samples: 1,
+            sample_all_lights: true,
         }
     }
+
+    /// set the number of light samples taken per shading point, drawn
+    /// as one stratified array per light. Arrays are scrambled per pixel
+    /// by the sampler, so that penumbras don't band. Defaults to 1.
+    #[inline]
+    pub fn set_light_samples(&mut self, n: usize) {
+        assert!(n > 0, "light sample count must be positive");
+        self.light_samples = n;
+    }
+
+    /// set whether each light sample is taken from every light, which
+    /// is the default, or from a single light picked uniformly, which
+    /// is cheaper for many lights but noisier
+    #[inline]
+    pub fn set_sample_all_lights(&mut self, sample_all_lights: bool) {
+        self.sample_all_lights = sample_all_lights;
+    }
+
+    /// set whether `render` writes completed tiles into the output as
+    /// it goes, saving it at most once per `interval`. `None` disables
+    /// the preview.
+    #[inline]
+    pub fn set_preview_interval(&mut self, interval: Option<Duration>) {
+        self.preview_interval = interval;
+    }
+
+    // render `scene`, writing completed tiles into `preview` if presented
+    fn render_tiles(&self, scene: &Scene, preview: Option<&FilmPreview>) -> Image {
+        let film = self.camera.get_film();
+        let mut tiles: Vec<FilmTile<RGBSpectrumf>> = film.spawn_tiles(film.tile_size());
+        
+        // let mut rc = 0;
+        // let mut tc = 0;
+        tiles.par_iter_mut().for_each(|tile| super::with_thread_allocator(|allocator| {
+        // for tile in &mut tiles {
+            let tile_bound = tile.bounding();
+            let mut sampler = self.sampler.fork(super::tile_seed(tile_bound));
+            let cache = if self.light_threshold > 0. as Float {
+                LightCache::new(tile_bound, &*self.camera, scene, self.light_threshold)
+            } else {
+                None
+            };
+            for p in tile_bound {
+                let p: Point2<u32> = p.cast();
+                sampler.start_pixel(p);
+                loop {
+                    let mut camera_sample_info = sampler.get_camera_sample(p, self.camera.shutter());
+                    let pfilm = camera_sample_info.pfilm;
+                    camera_sample_info.pfilm = tile.jitter(pfilm);
+                    let mut ray_differential = self.camera.generate_path_differential(camera_sample_info);
+                    ray_differential.scale_differentials(1.0 as Float / sampler.sample_per_pixel() as Float);
+                    let (total_randiance, alpha) = calculate_lighting(ray_differential, scene, self.camera.clip_planes(), &mut sampler, allocator, cache.as_ref(), self.light_samples, self.sample_all_lights, 0);
+                    // if total_randiance != RGBSpectrumf::black() { rc += 1; }
+                    // tc += 1;
+                    tile.add_sample_with_alpha(pfilm, &total_randiance, alpha);
+                    
+                    if !sampler.next_sample() { break; }
+                }
+            }
+            if let Some(preview) = preview {
+                preview.add_tile(tile);
+            }
+        }));
+        // }
+        film.collect_into(tiles)
+    }
+}
+
+/// Lights possibly contributing to points inside `bbox`
+struct LightCache {
+    bbox: BBox3f,
+    lights: Vec<usize>,
 }
 
-// helper function for whitted rendering's light computation
+impl LightCache {
+    /// build the cache for `tile_bound` by a pre-pass of primary hits
+    /// through pixel centers. Returns `None` if nothing is hit.
+    fn new(
+        tile_bound: BBox2<isize>,
+        camera: &Camera,
+        scene: &Scene,
+        threshold: Float
+    ) -> Option<LightCache> {
+        let mut bbox: Option<BBox3f> = None;
+        for p in tile_bound {
+            let p: Point2f = p.cast();
+            let sample_info = SampleInfo{
+                pfilm: p + Vector2f::new(0.5 as Float, 0.5 as Float),
+                plens: Point2f::new(0.5 as Float, 0.5 as Float),
+                time: 0. as Float,
+            };
+            let mut ray = camera.generate_path(sample_info);
+            if let Some(si) = scene.intersect_clipped(&mut ray, camera.clip_planes()) {
+                let pos = si.basic.pos;
+                bbox = Some(match bbox {
+                    Some(bbox) => bbox.extend(pos),
+                    None => BBox3f::new(pos, pos),
+                });
+            }
+        }
+        let bbox = bbox?;
+        // pad to cover hits from jittered samples near the pre-pass hits
+        let bbox = bbox.expand_by(
+            0.01 as Float * bbox.diagonal().magnitude()
+            + float::consts::scene_epsilon(&scene.aggregate.bbox_parent())
+        );
+        let lights = scene.lights.iter().enumerate().filter(|&(_, light)| {
+            match light.max_luminance_in(&bbox) {
+                Some(bound) => bound >= threshold,
+                None => true,
+            }
+        }).map(|(i, _)| i).collect();
+        Some(LightCache{
+            bbox: bbox, lights: lights,
+        })
+    }
+}
+
+// helper function for whitted rendering's light computation,
+// camera rays skipping over hits clipped away by `clip`. Takes
+// `nsamples` light samples from every light if `all_lights`,
+// or from one light picked for each otherwise
 fn calculate_lighting<S: Sampler>(
     mut ray: RayDifferential, 
     scene: &Scene, 
+    clip: &[ClipPlane],
     sampler: &mut S, 
     alloc: &Allocator, 
+    cache: Option<&LightCache>,
+    nsamples: usize,
+    all_lights: bool,
     depth: usize
-) -> RGBSpectrumf {
+) -> (RGBSpectrumf, Float) {
     let mut ret = RGBSpectrumf::black();
-    if depth > 5 { return ret; }
-    if let Some(mut surinter) = scene.aggregate.intersect_ray(&mut ray.ray) {
+    if depth > 5 { return (ret, 1. as Float); }
+    if let Some(mut surinter) = scene.intersect_clipped(&mut ray.ray, clip) {
         let pos = surinter.basic.pos;
         let norm = surinter.shading_norm;
         let wo = surinter.basic.wo;
         let dxy = surinter.compute_dxy(&ray);
         if let Some(primitive) = surinter.primitive_hit {
             if primitive.is_emissive() {
-                ret += primitive.evaluate_ray(&ray);
-                // let rad = primitive.evaluate_ray(&ray);
-                // print!("emission found: {:?}", rad);
+                ret += surinter.le(-ray.ray.direction());
             }
             let bsdf = primitive.get_material().compute_scattering(&mut surinter, &dxy, alloc);
-            for light in &scene.lights {
-                let lightsample = light.evaluate_sampled(pos, sampler.next_2d());
+            // fall back to all lights for points outside the cached region
+            let culled = cache.and_then(|cache| if cache.bbox.contain(pos) {
+                Some(&cache.lights)
+            } else {
+                None
+            });
+            let nlights = culled.map_or(scene.lights.len(), |lights| lights.len());
+            // pairs of the lights sampled and the samples taken, along
+            // with the weight of each sample
+            let (picks, weight) = if nlights == 0 {
+                (Vec::new(), 0. as Float)
+            } else if nsamples == 1 {
+                // a single sample, stratified over the pixel's samples instead
+                let picks = if all_lights {
+                    (0..nlights).map(|i| (i, sampler.next_2d())).collect()
+                } else {
+                    let i = ((sampler.next() * nlights as Float) as usize).min(nlights - 1);
+                    vec![(i, sampler.next_2d())]
+                };
+                (picks, if all_lights { 1. as Float } else { nlights as Float })
+            } else {
+                let mut us = vec![Point2f::new(0. as Float, 0. as Float); nsamples];
+                if all_lights {
+                    let mut picks = Vec::with_capacity(nlights * nsamples);
+                    for i in 0..nlights {
+                        sampler.request_2d(&mut us);
+                        picks.extend(us.iter().map(|&u| (i, u)));
+                    }
+                    (picks, 1. as Float / nsamples as Float)
+                } else {
+                    let mut choices = vec![0. as Float; nsamples];
+                    sampler.request(&mut choices);
+                    sampler.request_2d(&mut us);
+                    let picks = choices.iter().zip(&us).map(|(&c, &u)| {
+                        (((c * nlights as Float) as usize).min(nlights - 1), u)
+                    }).collect();
+                    (picks, nlights as Float / nsamples as Float)
+                }
+            };
+            // sample the lights before testing their shadow rays in one batch
+            let mut samples = Vec::with_capacity(picks.len());
+            let mut rays = Vec::with_capacity(picks.len());
+            for (i, u) in picks {
+                let light = scene.get_light(culled.map_or(i, |lights| lights[i]));
+                let lightsample = light.evaluate_sampled(pos, u);
                 if lightsample.no_effect() { continue; }
                 let wi = lightsample.wi();
                 let (bsdfv, _) = bsdf.evaluate(wo, wi, BXDF_ALL);
-                if bsdfv != RGBSpectrumf::black() && !lightsample.occluded(&*scene.aggregate) {
-                    let coontribution = bsdfv * lightsample.radiance * wi.dot(norm) / lightsample.pdf;
-                    ret += coontribution;
+                if bsdfv != RGBSpectrumf::black() {
+                    rays.push(lightsample.shadow_ray_from(surinter.shadow_origin(wi)));
+                    samples.push((lightsample, wi, bsdfv));
+                }
+            }
+            let mut occluded = vec![false; rays.len()];
+            scene.aggregate.occluded_batch(&mut rays, &mut occluded);
+            for ((lightsample, wi, bsdfv), occluded) in samples.into_iter().zip(occluded) {
+                if !occluded {
+                    let coontribution = bsdfv * lightsample.radiance * float::safe_div(wi.dot(norm).abs(), lightsample.pdf);
+                    ret += coontribution * weight;
                     // TODO: specular reflect, specular transmit
                 }
             }
@@ -76,40 +263,22 @@ fn calculate_lighting<S: Sampler>(
         for light in &scene.lights {
             ret += light.evaluate_ray(&ray);
         }
+        // nothing is hit, leaving the background transparent
+        return (ret, 0. as Float);
     }
-    ret
+    (ret, 1. as Float)
 }
 
 impl<S: Sampler> Renderer for WhittedRenderer<S> {
+    fn render_image(&mut self, scene: &Scene) -> Image {
+        self.render_tiles(scene, None)
+    }
+
     fn render(&mut self, scene: &Scene) {
-        let mut tiles: Vec<FilmTile<RGBSpectrumf>> = self.camera.get_film().spawn_tiles(16, 16);
-        
-        // let mut rc = 0;
-        // let mut tc = 0;
-        tiles.par_iter_mut().for_each(|tile| {
-        // for tile in &mut tiles {
-            // let mut arena = Arena::new();
-            let allocator = Allocator::new();
-            let mut sampler = self.sampler.clone();
-            let tile_bound = tile.bounding();
-            for p in tile_bound {
-                let p: Point2<u32> = p.cast();
-                sampler.start_pixel(p);
-                loop {
-                    let camera_sample_info = sampler.get_camera_sample(p);
-                    let mut ray_differential = self.camera.generate_path_differential(camera_sample_info);
-                    ray_differential.scale_differentials(1.0 as Float / sampler.sample_per_pixel() as Float);
-                    let total_randiance = calculate_lighting(ray_differential, scene, &mut sampler, &allocator, 0);
-                    // if total_randiance != RGBSpectrumf::black() { rc += 1; }
-                    // tc += 1;
-                    tile.add_sample(camera_sample_info.pfilm, &total_randiance);
-                    
-                    if !sampler.next_sample() { break; }
-                }
-            }
+        let preview = self.preview_interval.map(|interval| {
+            self.camera.get_film().spawn_preview(&self.path, interval)
         });
-        // }
-        let render_result = self.camera.get_film().collect_into(tiles);
+        let render_result = self.render_tiles(scene, preview.as_ref());
         render_result.save(&self.path).expect("saving failure");
     }
 }
\ No newline at end of file
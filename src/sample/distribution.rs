@@ -10,7 +10,6 @@
 
 use super::*;
 use std::iter::FromIterator;
-use std::cmp::Ordering;
 
 /// A 1d distribution
 #[derive(Serialize, Deserialize, Debug)]
@@ -84,8 +83,9 @@ impl Distribution1D {
         if ceil - floor > 0. as Float {
             du /= ceil - floor;
         }
+        // density wrt the continuous domain $[0, 1)$
         let pdf = if self.func_integral > 0. as Float {unsafe {
-            *self.func.get_unchecked(offset) / self.func_integral
+            *self.func.get_unchecked(offset) * self.len() as Float / self.func_integral
         }} else {
             0. as Float
         };
@@ -119,17 +119,18 @@ impl Distribution1D {
 
     #[inline]
     pub fn discrete_pdf(&self, index: usize) -> Float {
-        self.func[index] / (self.func_integral * self.len() as Float)
+        if self.func_integral > 0. as Float {
+            self.func[index] / self.func_integral
+        } else {
+            0. as Float
+        }
     }
 
     #[inline]
     fn search_offset(&self, mut u: Float) -> usize {
         if u == 0. as Float { u += float::epsilon(); }
-        self.cdf.binary_search_by(|v| {
-            if *v < u { Ordering::Less }
-            else if *v == u { Ordering::Equal }
-            else { Ordering::Greater }
-        }).unwrap_or_else(|v| v) - 1
+        self.cdf.binary_search_by(|v| float::total_cmp(*v, u))
+            .unwrap_or_else(|v| v).max(1).min(self.func.len()) - 1
 
         // let ret = self.cdf.binary_search_by(|v| {
         //     if *v < u { Ordering::Less }
@@ -208,6 +209,10 @@ impl Distribution2D {
         } else {
             iv as usize
         };
-        self.pcv[iv].func[iu] / self.pmarginal.func_integral
+        if self.pmarginal.func_integral > 0. as Float {
+            self.pcv[iv].func[iu] * (n * m) as Float / self.pmarginal.func_integral
+        } else {
+            0. as Float
+        }
     }
 }
\ No newline at end of file
@@ -57,6 +57,14 @@ impl Filter for BoxFilter {
     unsafe fn evaluate_unsafe(&self, _p: Point2f) -> Float {
         1.0 as Float
     }
+
+    #[inline]
+    fn sample(&self, u: Point2f) -> (Vector2f, Float) {
+        (Vector2f::new(
+            (2. as Float * u.x - 1. as Float) * self.radius.x,
+            (2. as Float * u.y - 1. as Float) * self.radius.y
+        ), 1. as Float)
+    }
 }
 
 /// A triangle filter!
@@ -84,6 +92,26 @@ impl Filter for TriangleFilter {
     unsafe fn evaluate_unsafe(&self, p: Point2f) -> Float {
         (self.radius.x - p.x.abs()) * (self.radius.y - p.y.abs())
     }
+
+    #[inline]
+    fn sample(&self, u: Point2f) -> (Vector2f, Float) {
+        (Vector2f::new(
+            TriangleFilter::sample_tent(u.x, self.radius.x),
+            TriangleFilter::sample_tent(u.y, self.radius.y)
+        ), 1. as Float)
+    }
+}
+
+impl TriangleFilter {
+    // inverse of the cumulative distribution of a tent over `[-r, r]`
+    #[inline]
+    fn sample_tent(u: Float, r: Float) -> Float {
+        if u < 0.5 as Float {
+            r * ((2. as Float * u).sqrt() - 1. as Float)
+        } else {
+            r * (1. as Float - (2. as Float - 2. as Float * u).sqrt())
+        }
+    }
 }
 
 /// A Gausssian filter!
@@ -106,8 +134,8 @@ impl GaussianFilter {
         assert!(radius.y > 0.0 as Float);
         let neg_alpha = -alpha;
         let exp = Vector2f::new(
-            neg_alpha * radius.x * radius.x,
-            neg_alpha * radius.y * radius.y
+            (neg_alpha * radius.x * radius.x).exp(),
+            (neg_alpha * radius.y * radius.y).exp()
         );
         GaussianFilter{
             radius: radius,
@@ -127,6 +155,112 @@ impl Filter for GaussianFilter {
         let gy = (self.neg_alpha * p.y * p.y).exp() - self.exp.y;
         gx * gy
     }
+
+    #[inline]
+    fn sample(&self, u: Point2f) -> (Vector2f, Float) {
+        (Vector2f::new(
+            self.sample_1d(u.x, self.radius.x, self.exp.x),
+            self.sample_1d(u.y, self.radius.y, self.exp.y)
+        ), 1. as Float)
+    }
+}
+
+impl GaussianFilter {
+    // inverse of the cumulative distribution of the 1D filter over
+    // `[-r, r]`, whose floor is `c`, found by safeguarded newton steps
+    fn sample_1d(&self, u: Float, r: Float, c: Float) -> Float {
+        let alpha = -self.neg_alpha;
+        let sqrt_alpha = alpha.sqrt();
+        let k = float::pi().sqrt() * 0.5 as Float / sqrt_alpha;
+        let cdf = |x: Float| k * (erf(sqrt_alpha * x) + erf(sqrt_alpha * r)) - c * (x + r);
+        let target = u * cdf(r);
+        let (mut lo, mut hi) = (-r, r);
+        let mut x = (2. as Float * u - 1. as Float) * r;
+        for _ in 0..32 {
+            let f = cdf(x) - target;
+            if f.abs() <= 1e-7 as Float * k { break; }
+            if f < 0. as Float { lo = x; } else { hi = x; }
+            let d = (self.neg_alpha * x * x).exp() - c;
+            let next = x - f / d;
+            // bisect where newton leaves the bracket
+            x = if d > 0. as Float && next > lo && next < hi { next } else { 0.5 as Float * (lo + hi) };
+            if hi - lo < 1e-6 as Float * r { break; }
+        }
+        x
+    }
+}
+
+/// error function, within $1.5\times 10^{-7}$ as per
+/// Abramowitz and Stegun, 7.1.26
+fn erf(x: Float) -> Float {
+    let t = 1. as Float / (1. as Float + 0.3275911 as Float * x.abs());
+    let poly = t * (0.254829592 as Float + t * (-0.284496736 as Float + t * (
+        1.421413741 as Float + t * (-1.453152027 as Float + t * 1.061405429 as Float)
+    )));
+    let y = 1. as Float - poly * (-x * x).exp();
+    if x < 0. as Float { -y } else { y }
+}
+
+/// number of bins filter profiles are tabulated into for sampling
+const PROFILE_BINS: usize = 32;
+
+/// Absolute values of a symmetric 1D filter over $[0, radius]$,
+/// tabulated for sampling filters with no closed-form inverse
+#[derive(Copy, Clone, Serialize, Deserialize, Debug)]
+struct Profile {
+    radius: Float,
+    // absolute values at the center of each bin
+    func: [Float; PROFILE_BINS],
+    // cumulative distribution at the end of each bin
+    cdf: [Float; PROFILE_BINS],
+}
+
+impl Profile {
+    fn new<F: Fn(Float) -> Float>(radius: Float, f: F) -> Profile {
+        let mut func = [0. as Float; PROFILE_BINS];
+        let mut cdf = [0. as Float; PROFILE_BINS];
+        let mut sum = 0. as Float;
+        for i in 0..PROFILE_BINS {
+            func[i] = f(radius * (i as Float + 0.5 as Float) / PROFILE_BINS as Float).abs();
+            sum += func[i];
+            cdf[i] = sum;
+        }
+        if sum > 0. as Float {
+            for c in &mut cdf { *c /= sum; }
+        } else {
+            // an all-zero filter, sampled uniformly
+            for i in 0..PROFILE_BINS {
+                func[i] = 1. as Float;
+                cdf[i] = (i + 1) as Float / PROFILE_BINS as Float;
+            }
+        }
+        Profile{ radius: radius, func: func, cdf: cdf }
+    }
+
+    // an offset in `[-radius, radius]` sampled by `u`, piecewise
+    // constantly along the tabulation, along with its tabulated value
+    fn sample(&self, u: Float) -> (Float, Float) {
+        // the first half of `u` for the negative side
+        let (sign, u) = if u < 0.5 as Float {
+            (-1. as Float, 2. as Float * u)
+        } else {
+            (1. as Float, 2. as Float * u - 1. as Float)
+        };
+        let i = self.cdf.iter().position(|&c| c > u).unwrap_or(PROFILE_BINS - 1);
+        let c0 = if i == 0 { 0. as Float } else { self.cdf[i - 1] };
+        let t = ((u - c0) / (self.cdf[i] - c0)).max(0. as Float).min(1. as Float);
+        (sign * self.radius * (i as Float + t) / PROFILE_BINS as Float, self.func[i])
+    }
+}
+
+// sample a separable filter `f` by tabulations of its profiles, weighting
+// by its value over the tabulated one, about its sign
+#[inline]
+fn sample_profiles<F: Filter + ?Sized>(f: &F, profiles: &[Profile; 2], u: Point2f) -> (Vector2f, Float) {
+    let (x, fx) = profiles[0].sample(u.x);
+    let (y, fy) = profiles[1].sample(u.y);
+    let value = unsafe { f.evaluate_unsafe(Point2f::new(x, y)) };
+    (Vector2f::new(x, y), value / (fx * fy))
 }
 
 /// Mitchell filter as per Mitchell-Netravali [1988]
@@ -136,6 +270,7 @@ pub struct MitchellFilter {
     inv_radius: Vector2f,
     b: Float,
     c: Float,
+    profiles: [Profile; 2],
 }
 
 impl MitchellFilter {
@@ -146,11 +281,15 @@ impl MitchellFilter {
         assert!(radius.x > 0.0 as Float);
         assert!(radius.y > 0.0 as Float);
         let inv_radius = 1.0 as Float / radius;
+        let profile = |r: Float| Profile::new(r, |x| {
+            MitchellFilter::mitchell_1d((2. as Float * x / r).min(2. as Float), b, c)
+        });
         MitchellFilter {
             radius: radius,
             inv_radius: inv_radius,
             b: b,
             c: c,
+            profiles: [profile(radius.x), profile(radius.y)],
         }
     }
 
@@ -184,6 +323,12 @@ impl Filter for MitchellFilter {
         MitchellFilter::mitchell_1d(mp.x.abs(), self.b, self.c)
         * MitchellFilter::mitchell_1d(mp.y.abs(), self.b, self.c)
     }
+
+    /// tabulated, weights being close to the signs of the filter
+    #[inline]
+    fn sample(&self, u: Point2f) -> (Vector2f, Float) {
+        sample_profiles(self, &self.profiles, u)
+    }
 }
 
 /// A windowed sinc filter as per [Lanczos](https://en.wikipedia.org/wiki/Lanczos_resampling).
@@ -193,6 +338,7 @@ impl Filter for MitchellFilter {
 pub struct LanczosSincFilter {
     radius: Vector2f,
     inv_tau: Float,
+    profiles: [Profile; 2],
 }
 
 impl LanczosSincFilter {
@@ -202,24 +348,27 @@ impl LanczosSincFilter {
         assert!(radius.x > 0.0 as Float);
         assert!(radius.y > 0.0 as Float);
         assert!(tau > 0.0 as Float);
+        let inv_tau = 1.0 as Float / tau;
+        let profile = |r: Float| Profile::new(r, |x| LanczosSincFilter::lanczos_sinc(x, inv_tau));
         LanczosSincFilter{
             radius: radius,
-            inv_tau: 1.0 as Float / tau,
+            inv_tau: inv_tau,
+            profiles: [profile(radius.x), profile(radius.y)],
         }
     }
 
     /// evaluate lanczos sinc filter given by
     /// $f(x) = sinc(x/tau) * sinc(x)$
-    /// x should be greater than zero
     #[inline]
     fn lanczos_sinc(x: Float, inv_tau: Float) -> Float {
-        LanczosSincFilter::sinc((x*inv_tau))
+        LanczosSincFilter::sinc(x*inv_tau)
         * LanczosSincFilter::sinc(x)
     }
 
-    /// x should be greater than zero
+    /// even in `x`
     #[inline]
     fn sinc(x: Float) -> Float {
+        let x = x.abs();
         if x < 1.0e-5 as Float {
             1.0 as Float
         } else {
@@ -238,6 +387,12 @@ impl Filter for LanczosSincFilter {
         LanczosSincFilter::lanczos_sinc(p.x, self.inv_tau)
         * LanczosSincFilter::lanczos_sinc(p.y, self.inv_tau)
     }
+
+    /// tabulated, weights being close to the signs of the filter
+    #[inline]
+    fn sample(&self, u: Point2f) -> (Vector2f, Float) {
+        sample_profiles(self, &self.profiles, u)
+    }
 }
 
 
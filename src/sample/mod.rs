@@ -21,9 +21,25 @@ use filming;
 /// such that implementations might provide better-quality.
 pub trait Sampler: Clone + Sync + Send
 {
-    /// Start sampling a new pixel
+    /// Start sampling a new pixel.
+    ///
+    /// Samples drawn afterwards should be determined by the state the
+    /// sampler was forked with and the pixels started since, such that
+    /// a tile rendered by a fork comes out the same whichever thread
+    /// renders it.
     fn start_pixel(&mut self, p: Point2<u32>);
 
+    /// a sampler of the same configuration, drawing samples determined
+    /// by `seed` and the state of `self`. Renderers fork one per tile,
+    /// seeded by the tile's position, so that tiles don't repeat each
+    /// other's noise.
+    ///
+    /// Default implementation clones `self`, ignoring `seed`.
+    #[inline]
+    fn fork(&self, _seed: u64) -> Self {
+        self.clone()
+    }
+
     /// get next 1-dimensional sample
     fn next(&mut self) -> Float;
 
@@ -33,12 +49,23 @@ pub trait Sampler: Clone + Sync + Send
         Point2f::new(self.next(), self.next())
     }
 
-    /// convinient method to sample a camera
+    /// convinient method to sample a camera. An extra 1D sample
+    /// is mapped into the `shutter` interval if presented, otherwise
+    /// the sample is taken at time zero
     #[inline]
-    fn get_camera_sample(&mut self, idx: Point2<u32>) -> filming::SampleInfo {
+    fn get_camera_sample(
+        &mut self, idx: Point2<u32>, shutter: Option<(Float, Float)>
+    ) -> filming::SampleInfo {
+        let pfilm = pixel_position(idx, self.next_2d());
+        let plens = self.next_2d();
+        let time = match shutter {
+            Some((open, close)) => open + (close - open) * self.next(),
+            None => 0. as Float,
+        };
         filming::SampleInfo{
-            pfilm: self.next_2d() + idx.cast().to_vec(),
-            plens: self.next_2d(),
+            pfilm: pfilm,
+            plens: plens,
+            time: time,
         }
     }
 
@@ -48,6 +75,7 @@ pub trait Sampler: Clone + Sync + Send
         filming::SampleInfo{
             pfilm: self.next_2d(),
             plens: self.next_2d(),
+            time: 0. as Float,
         }
     }
 
@@ -84,6 +112,15 @@ pub trait Sampler: Clone + Sync + Send
     /// maximum sample count per pixel
     fn sample_per_pixel(&self) -> usize;
 
+    /// set the maximum sample count per pixel to `spp`,
+    /// resetting the sampling process.
+    ///
+    /// Default implementation panics, for samplers whose
+    /// sample count is fixed on construction.
+    fn set_sample_per_pixel(&mut self, _spp: usize) {
+        unimplemented!("this sampler doesn't support changing its sample count");
+    }
+
     /// Try to advance to the next sample
     /// `true` if the sampling process can continue
     /// `false` when overflowing `sample_per_pixel`, eg
@@ -91,6 +128,37 @@ pub trait Sampler: Clone + Sync + Send
 
     /// try to set current sample to a particular index
     fn set_sample_index(&mut self, idx: usize) -> bool;
+
+    /// set the dimension index the next 1d and 2d samples are drawn from.
+    /// Dimensions are restarted from `0` by `start_pixel`, `next_sample`
+    /// and `set_sample_index`, so that renderers can reserve fixed
+    /// dimension slots for fixed-purpose samples.
+    ///
+    /// Default implementation is noop.
+    #[inline]
+    fn set_dimension(&mut self, _d: usize) { }
+}
+
+/// film position of a sample `u` in $[0,1)^2$ within pixel `idx`,
+/// kept inside the pixel as the sum can round up to the next one
+#[inline]
+pub fn pixel_position(idx: Point2<u32>, u: Point2f) -> Point2f {
+    let p: Point2f = idx.cast();
+    Point2f::new(
+        (p.x + u.x).min(float::next_down(p.x + 1. as Float)),
+        (p.y + u.y).min(float::next_down(p.y + 1. as Float))
+    )
+}
+
+/// murmur3's 64-bit finalizer, scrambling the bits of `h`
+#[inline]
+pub fn mix(mut h: u64) -> u64 {
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51afd7ed558ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ceb9fe1a85ec53);
+    h ^= h >> 33;
+    h
 }
 
 /// The filter interface.
@@ -124,6 +192,23 @@ pub trait Filter: Send + Sync {
             0.0 as Float
         }
     }
+
+    /// Sample an offset within the support from a uniform `u` in
+    /// $[0, 1)^2$, distributed as close to the filter's absolute value
+    /// as the filter can afford, along with the sample's weight relative
+    /// to other samples. Weights are the signs of the filter for filters
+    /// sampled exactly.
+    ///
+    /// Defaults to uniform offsets weighted by the filter's value.
+    #[inline]
+    fn sample(&self, u: Point2f) -> (Vector2f, Float) {
+        let r = self.radius();
+        let offset = Vector2f::new(
+            (2. as Float * u.x - 1. as Float) * r.x,
+            (2. as Float * u.y - 1. as Float) * r.y
+        );
+        (offset, unsafe { self.evaluate_unsafe(Point2f::from_vec(offset)) })
+    }
 }
 
 /// transform an uniformly sampled `u` in $[0,1)^2$
@@ -131,7 +216,7 @@ pub trait Filter: Send + Sync {
 #[inline]
 pub fn sample_uniform_hemisphere(u: Point2f) -> Vector3f {
     let costheta = u.x;
-    let sintheta = (1.0 as Float - costheta).max(0.0 as Float).sqrt();
+    let sintheta = (1.0 as Float - costheta * costheta).max(0.0 as Float).sqrt();
     let phi = 2.0 as Float * float::pi() * u.y;
     Vector3f::new(sintheta*phi.cos(), sintheta*phi.sin(), costheta)
 }
@@ -147,12 +232,12 @@ pub fn pdf_uniform_hemisphere() -> Float {
 #[inline]
 pub fn sample_uniform_sphere(u: Point2f) -> Vector3f {
     let costheta = 1.0 as Float - 2.0 as Float * u.x;
-    let sintheta = (1.0 as Float - costheta).max(0.0 as Float).sqrt();
+    let sintheta = (1.0 as Float - costheta * costheta).max(0.0 as Float).sqrt();
     let phi = 2.0 as Float * float::pi() * u.y;
     Vector3f::new(sintheta*phi.cos(), sintheta*phi.sin(), costheta)
 }
 
-/// pdf of uniform samples on a hemisphere
+/// pdf of uniform samples on a sphere
 #[inline]
 pub fn pdf_uniform_sphere() -> Float {
     0.25 as Float * float::frac_1_pi()
@@ -212,6 +297,45 @@ pub fn pdf_cosw_hemisphere(cos_theta: Float) -> Float {
     cos_theta * float::frac_1_pi()
 }
 
+/// transform an uniformly sampled `u` in $[0,1)^2$ into cosine-theta
+/// weighted samples on the hemisphere about the normalized `n`,
+/// returning the direction and its pdf wrt solid angle
+#[inline]
+pub fn sample_cosw_hemisphere_about(n: Vector3f, u: Point2f) -> (Vector3f, Float) {
+    let d = sample_cosw_hemisphere(u);
+    let (s, t) = normal::orthonormal_basis(n);
+    (d.x * s + d.y * t + d.z * n, pdf_cosw_hemisphere(d.z))
+}
+
+/// pdf of `w` being sampled by `sample_cosw_hemisphere_about(n, _)`
+#[inline]
+pub fn pdf_cosw_hemisphere_about(n: Vector3f, w: Vector3f) -> Float {
+    pdf_cosw_hemisphere(n.dot(w).max(0. as Float))
+}
+
+/// transform an uniformly sampled `u` in $[0,1)^2$ into uniform
+/// samples on the hemisphere about the normalized `n`, returning
+/// the direction and its pdf wrt solid angle
+#[inline]
+pub fn sample_uniform_hemisphere_about(n: Vector3f, u: Point2f) -> (Vector3f, Float) {
+    let d = sample_uniform_hemisphere(u);
+    let (s, t) = normal::orthonormal_basis(n);
+    (d.x * s + d.y * t + d.z * n, pdf_uniform_hemisphere())
+}
+
+/// pdf of `w` being sampled by `sample_uniform_hemisphere_about(n, _)`
+#[inline]
+pub fn pdf_uniform_hemisphere_about(n: Vector3f, w: Vector3f) -> Float {
+    if n.dot(w) > 0. as Float { pdf_uniform_hemisphere() } else { 0. as Float }
+}
+
+/// pdf wrt solid angle of the directions given by `sample_uniform_sphere`,
+/// whichever way the sphere is oriented
+#[inline]
+pub fn sample_uniform_sphere_pdf_wrt_solid_angle() -> Float {
+    pdf_uniform_sphere()
+}
+
 /// transform an uniformly sampled `u` in $[0,1)^2$
 /// into uniform samples on a cone
 #[inline]
@@ -228,6 +352,23 @@ pub fn pdf_uniform_cone(cos_max: Float) -> Float {
     1.0 as Float / ((1.0 as Float - cos_max) * 2.0 as Float * float::pi())
 }
 
+/// convert a `pdf` wrt area at a point into a pdf wrt solid angle
+/// seen from a reference at squared distance `dist2`, with `costheta`
+/// being the cosine between the point's normal and the direction
+/// to the reference. Degenerate configurations yield zero.
+#[inline]
+pub fn area_to_solid_angle(pdf: Float, dist2: Float, costheta: Float) -> Float {
+    let costheta = costheta.abs();
+    if costheta == 0. as Float { return 0. as Float; }
+    float::safe_div(pdf * dist2, costheta)
+}
+
+/// inverse of `area_to_solid_angle`
+#[inline]
+pub fn solid_angle_to_area(pdf: Float, dist2: Float, costheta: Float) -> Float {
+    float::safe_div(pdf * costheta.abs(), dist2)
+}
+
 /// transform an uniformly sampled `u` in $[0,1)^2$
 /// into uniform samples on a triangle's barycentric coordinates
 #[inline]
@@ -238,19 +379,32 @@ pub fn sample_uniform_triangle(u: Point2f) -> Vector3f {
     Vector3f::new(x, y, 1.0 as Float - x - y)
 }
 
+/// the `i`th of `n` points of the Hammersley set in $[0,1)^2$, whose
+/// `x` are stratified and `y` the base 2 radical inverses of `i`
+#[inline]
+pub fn hammersley(i: usize, n: usize) -> Point2f {
+    let y = (i as u32).reverse_bits() as f64 / (1u64 << 32) as f64;
+    Point2f::new(
+        (i as Float + 0.5 as Float) / n as Float,
+        (y as Float).min(float::one_minus_epsilon())
+    )
+}
+
 /// power heuristic as per $\beta = 2$
 #[inline]
 pub fn power_heuristic(nf: usize, pdff: Float, ng: usize, pdfg: Float) -> Float {
+    if !pdff.is_finite() || !pdfg.is_finite() { return 0. as Float; }
     let f = nf as Float * pdff;
     let g = ng as Float * pdfg;
-    (f*f)/(f*f+g*g)
+    float::safe_div(f*f, f*f+g*g)
 }
 
 #[inline]   
 pub fn balance_heuristic(nf: usize, pdff: Float, ng: usize, pdfg: Float) -> Float {
+    if !pdff.is_finite() || !pdfg.is_finite() { return 0. as Float; }
     let f = nf as Float * pdff;
     let g = ng as Float * pdfg;
-    f/(f+g)
+    float::safe_div(f, f+g)
 }
 
 pub mod naive;
@@ -259,3 +413,6 @@ pub mod filters;
 pub mod distribution;
 pub mod prelude;
 mod sink;
+
+#[cfg(test)]
+mod tests;
@@ -10,9 +10,9 @@
 #![allow(unused_variables)]
 
 extern crate rand;
-use self::rand::Rng;
+use self::rand::{Rng, SeedableRng};
 use geometry::prelude::*;
-use super::Sampler;
+use super::{Sampler, mix};
 
 // Copyright 2017 Dasein Phaos aka. Luxko
 //
@@ -36,6 +36,15 @@ impl Naive {
             rng: rand::StdRng::new().unwrap(), nsample, isample: 0,
         }
     }
+
+    /// construction, drawing samples from `rng` such that
+    /// renders can be reproduced
+    #[inline]
+    pub fn with_rng(nsample: usize, rng: rand::StdRng) -> Naive {
+        Naive {
+            rng, nsample, isample: 0,
+        }
+    }
 }
 
 impl Clone for Naive {
@@ -61,6 +70,17 @@ impl Sampler for Naive {
         self.isample = 0;
     }
 
+    /// a sampler whose rng is seeded from `seed` and a draw from `self`'s
+    fn fork(&self, seed: u64) -> Self {
+        let base: u64 = self.rng.clone().gen();
+        let key = mix(base ^ mix(seed));
+        Naive {
+            rng: rand::StdRng::from_seed(&[key as usize, mix(key) as usize][..]),
+            nsample: self.nsample,
+            isample: 0,
+        }
+    }
+
     fn next(&mut self) -> Float {
         self.rng.gen_range(0.0 as Float, 1.0 as Float)
     }
@@ -69,12 +89,18 @@ impl Sampler for Naive {
         self.nsample
     }
 
+    fn set_sample_per_pixel(&mut self, spp: usize) {
+        assert!(spp > 0, "sample count must be positive");
+        self.nsample = spp;
+        self.isample = 0;
+    }
+
     fn next_sample(&mut self) -> bool {
-        if self.isample < self.nsample {
+        if self.isample + 1 >= self.nsample {
+            false
+        } else {
             self.isample += 1;
             true
-        } else {
-            false
         }
     }
 
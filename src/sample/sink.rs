@@ -64,28 +64,36 @@ impl<T: Copy> Sink<T> {
         }
     }
 
-    /// advance to next sample
+    /// advance to next sample, restarting from the first dimension
     #[inline]
     pub fn next_sample(&mut self) -> bool {
         if self.isample + 1 >= self.nsample {
             false
         } else {
             self.isample += 1;
+            self.idim = 0;
             true
         }
     }
 
-    /// set sample index
+    /// set sample index, restarting from the first dimension
     #[inline]
     pub fn set_sample_index(&mut self, idx: usize) -> bool {
         if idx >= self.nsample {
             false
         } else {
             self.isample = idx;
+            self.idim = 0;
             true
         }
     }
 
+    /// set the dimension the next value is taken from
+    #[inline]
+    pub fn set_dim(&mut self, idim: usize) {
+        self.idim = idim;
+    }
+
     /// get total dimension
     #[inline]
     pub fn ndim(&self) -> usize {
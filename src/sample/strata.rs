@@ -9,8 +9,8 @@
 //! Defines a stratified sampler
 extern crate rand;
 use super::sink::{Sinkf, Sink2f};
-use super::Sampler;
-use self::rand::Rng;
+use super::{Sampler, mix};
+use self::rand::{Rng, SeedableRng};
 use geometry::*;
 use std;
 use serde;
@@ -20,7 +20,17 @@ use serde::de::{Deserializer, MapAccess, SeqAccess, Visitor};
 
 pub type StdStrataSampler = StrataSampler<rand::StdRng>;
 
-/// Represents a stratified sampler
+/// Represents a stratified sampler.
+///
+/// Stratified arrays are pre-generated for the first `ndim` 1d and
+/// 2d dimensions of each pixel, dimensions beyond fall back to `rng`.
+///
+/// If decorrelating, which is the default, every dimension of every
+/// pixel is shifted by its own offset modulo 1 (Cranley-Patterson
+/// rotation), with offsets hashed from the pixel and the dimension.
+/// Otherwise clones sharing the state of `rng` generate the same
+/// samples, so tiles rendered by clones repeat each other's noise.
+/// Forks reseed `rng` instead, and don't.
 #[derive(Debug)]
 pub struct StrataSampler<T> {
     sinkf: Sinkf,
@@ -28,6 +38,9 @@ pub struct StrataSampler<T> {
     sampledx: u32,
     sampledy: u32,
     rng: T,
+    decorrelate: bool,
+    pixel: Point2<u32>,
+    irequest: u64,
 }
 
 impl<T: Rng> StrataSampler<T> {
@@ -42,9 +55,43 @@ impl<T: Rng> StrataSampler<T> {
             sampledx: sampledx,
             sampledy: sampledy,
             rng: rng,
+            decorrelate: true,
+            pixel: Point2::new(0, 0),
+            irequest: 0,
         }
     }
 
+    /// whether samples are decorrelated across pixels
+    #[inline]
+    pub fn decorrelate(&self) -> bool {
+        self.decorrelate
+    }
+
+    /// set whether samples are decorrelated across pixels, turning it
+    /// off reproduces the output of versions without decorrelation
+    #[inline]
+    pub fn set_decorrelate(&mut self, decorrelate: bool) {
+        self.decorrelate = decorrelate;
+    }
+
+    /// offset of the current pixel in dimension `dim` of `stream`
+    #[inline]
+    fn offset(&self, stream: u64, dim: u64) -> Float {
+        let key = (self.pixel.x as u64) | ((self.pixel.y as u64) << 32);
+        let h = mix(mix(key) ^ (stream << 56 | dim));
+        // 24 bits fit exactly into the mantissa
+        (h >> 40) as Float / (1u64 << 24) as Float
+    }
+
+    /// rotate `u` by `offset` modulo 1
+    #[inline]
+    fn rotate(&self, u: Float, offset: Float) -> Float {
+        if !self.decorrelate { return u; }
+        let ret = u + offset;
+        let ret = if ret >= 1. as Float { ret - 1. as Float } else { ret };
+        ret.min(float::one_minus_epsilon())
+    }
+
     /// generate a series of stratified samples in 1d
     fn generate_strata(&mut self, over: &mut [Float]) {
         let n = over.len();
@@ -80,12 +127,19 @@ impl<T: Rng> StrataSampler<T> {
     }
 }
 
+// streams decorrelated separately
+const STREAM_1D: u64 = 0;
+const STREAM_2D_X: u64 = 1;
+const STREAM_2D_Y: u64 = 2;
+const STREAM_REQUEST: u64 = 3;
+
 impl Serialize for StrataSampler<rand::StdRng> {
     fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
-        let mut state = s.serialize_struct("StrataSampler", 3)?;
+        let mut state = s.serialize_struct("StrataSampler", 4)?;
         state.serialize_field("sampledx", &self.sampledx)?;
         state.serialize_field("sampledy", &self.sampledy)?;
         state.serialize_field("ndim", &self.sinkf.ndim())?;
+        state.serialize_field("decorrelate", &self.decorrelate)?;
         state.end()
     }
 }
@@ -96,7 +150,7 @@ impl<'de> Deserialize<'de> for StrataSampler<rand::StdRng> {
     {
         #[derive(Deserialize)]
         #[serde(field_identifier, rename_all = "lowercase")]
-        enum Field { Sampledx, Sampledy, Ndim }
+        enum Field { Sampledx, Sampledy, Ndim, Decorrelate }
 
         struct SamplerVisitor;
         impl<'de> Visitor<'de> for SamplerVisitor {
@@ -114,7 +168,10 @@ impl<'de> Deserialize<'de> for StrataSampler<rand::StdRng> {
                     .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
                 let ndim = seq.next_element()?
                     .ok_or_else(|| serde::de::Error::invalid_length(2, &self))?;
-                Ok(StrataSampler::new(sampledx, sampledy, ndim, rand::StdRng::new().unwrap()))
+                let decorrelate = seq.next_element()?.unwrap_or(true);
+                let mut ret = StrataSampler::new(sampledx, sampledy, ndim, rand::StdRng::new().unwrap());
+                ret.set_decorrelate(decorrelate);
+                Ok(ret)
             }
 
             fn visit_map<V>(self, mut map: V) -> Result<Self::Value, V::Error>
@@ -123,6 +180,7 @@ impl<'de> Deserialize<'de> for StrataSampler<rand::StdRng> {
                 let mut sampledx = None;
                 let mut sampledy = None;
                 let mut ndim = None;
+                let mut decorrelate = None;
                 while let Some(key) = map.next_key()? {
                     match key {
                         Field::Sampledx => {
@@ -143,6 +201,12 @@ impl<'de> Deserialize<'de> for StrataSampler<rand::StdRng> {
                             }
                             ndim = Some(map.next_value()?);
                         }
+                        Field::Decorrelate => {
+                            if decorrelate.is_some() {
+                                return Err(serde::de::Error::duplicate_field("decorrelate"));
+                            }
+                            decorrelate = Some(map.next_value()?);
+                        }
                     }
                 }
                 let sampledx = sampledx.ok_or_else(|| 
@@ -155,16 +219,22 @@ impl<'de> Deserialize<'de> for StrataSampler<rand::StdRng> {
                     serde::de::Error::missing_field("ndim")
                 )?;
 
-                Ok(StrataSampler::new(sampledx, sampledy, ndim, rand::StdRng::new().unwrap()))
+                let mut ret = StrataSampler::new(sampledx, sampledy, ndim, rand::StdRng::new().unwrap());
+                ret.set_decorrelate(decorrelate.unwrap_or(true));
+                Ok(ret)
             }
         }
-        const FIELDS: &[&str] = &["sampledx", "sampledy", "ndim"];
+        const FIELDS: &[&str] = &["sampledx", "sampledy", "ndim", "decorrelate"];
         deserializer.deserialize_struct("StrataSampler", FIELDS, SamplerVisitor)
     }
 }
 
-impl<T: Rng + Clone + Sync + Send> Sampler for StrataSampler<T> {
-    fn start_pixel(&mut self, _p: Point2<u32>) {
+impl<T> Sampler for StrataSampler<T>
+    where T: Rng + for<'a> SeedableRng<&'a [usize]> + Clone + Sync + Send
+{
+    fn start_pixel(&mut self, p: Point2<u32>) {
+        self.pixel = p;
+        self.irequest = 0;
         let nsample = self.sinkf.nsample();
         let ndim = self.sinkf.ndim();
         {
@@ -193,19 +263,34 @@ impl<T: Rng + Clone + Sync + Send> Sampler for StrataSampler<T> {
         self.sink2f.reset();
     }
 
+    /// a clone whose `rng` is reseeded from `seed` and a draw from `rng`
+    fn fork(&self, seed: u64) -> Self {
+        let base: u64 = self.rng.clone().gen();
+        let key = mix(base ^ mix(seed));
+        let mut ret = self.clone();
+        ret.rng = T::from_seed(&[key as usize, mix(key) as usize][..]);
+        ret
+    }
+
     #[inline]
     fn next(&mut self) -> Float {
+        let dim = self.sinkf.idim() as u64;
         let next = self.sinkf.next_dim();
-        next.unwrap_or(self.rng.gen_range(0.0 as Float, 1.0 as Float))
+        let u = next.unwrap_or(self.rng.gen_range(0.0 as Float, 1.0 as Float));
+        let offset = self.offset(STREAM_1D, dim);
+        self.rotate(u, offset)
     }
 
     #[inline]
     fn next_2d(&mut self) -> Point2f {
+        let dim = self.sink2f.idim() as u64;
         let next = self.sink2f.next_dim();
-        next.unwrap_or(Point2f::new(
+        let u = next.unwrap_or(Point2f::new(
             self.rng.gen_range(0.0 as Float, 1.0 as Float),
             self.rng.gen_range(0.0 as Float, 1.0 as Float)
-        ))
+        ));
+        let (ox, oy) = (self.offset(STREAM_2D_X, dim), self.offset(STREAM_2D_Y, dim));
+        Point2f::new(self.rotate(u.x, ox), self.rotate(u.y, oy))
     }
 
     #[inline]
@@ -213,19 +298,44 @@ impl<T: Rng + Clone + Sync + Send> Sampler for StrataSampler<T> {
         self.sinkf.nsample()
     }
 
+    fn set_sample_per_pixel(&mut self, spp: usize) {
+        assert!(spp > 0, "sample count must be positive");
+        // the most square factorization of `spp` into strata
+        let mut sampledx = (spp as f64).sqrt() as usize;
+        while spp % sampledx != 0 { sampledx -= 1; }
+        let ndim = self.sinkf.ndim();
+        self.sampledx = sampledx as u32;
+        self.sampledy = (spp / sampledx) as u32;
+        self.sinkf = Sinkf::new(ndim, spp);
+        self.sink2f = Sink2f::new(ndim, spp);
+    }
+
     #[inline]
     fn next_sample(&mut self) -> bool {
+        self.irequest = 0;
         self.sinkf.next_sample() && self.sink2f.next_sample()
     }
 
     #[inline]
     fn set_sample_index(&mut self, idx: usize) -> bool {
+        self.irequest = 0;
         self.sinkf.set_sample_index(idx) && self.sink2f.set_sample_index(idx)
     }
 
+    #[inline]
+    fn set_dimension(&mut self, d: usize) {
+        self.sinkf.set_dim(d);
+        self.sink2f.set_dim(d);
+    }
+
     #[inline]
     fn request(&mut self, buf: &mut [Float]) {
         self.generate_strata(buf);
+        let offset = self.offset(STREAM_REQUEST, self.irequest);
+        self.irequest += 1;
+        for u in buf.iter_mut() {
+            *u = self.rotate(*u, offset);
+        }
     }
 
     #[inline]
@@ -243,12 +353,22 @@ impl<T: Rng + Clone + Sync + Send> Sampler for StrataSampler<T> {
         for i in 0..tmp.len() {unsafe {
             buf.get_unchecked_mut(i).y = *tmp.get_unchecked(i);
         }}
+        let (ox, oy) = (
+            self.offset(STREAM_REQUEST, self.irequest), self.offset(STREAM_REQUEST, self.irequest + 1)
+        );
+        self.irequest += 2;
+        for p in buf.iter_mut() {
+            p.x = self.rotate(p.x, ox);
+            p.y = self.rotate(p.y, oy);
+        }
     }
 }
 
 impl<T: Rng + Clone> Clone for StrataSampler<T> {
     #[inline]
     fn clone(&self) -> Self {
-        StrataSampler::new(self.sampledx, self.sampledy, self.sinkf.ndim() as u32, self.rng.clone())
+        let mut ret = StrataSampler::new(self.sampledx, self.sampledy, self.sinkf.ndim() as u32, self.rng.clone());
+        ret.decorrelate = self.decorrelate;
+        ret
     }
 }
\ No newline at end of file
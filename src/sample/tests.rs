@@ -0,0 +1,174 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// tests
+
+#[cfg(test)]
+mod test_hemisphere {
+    use sample::*;
+    use rand::{Rng, SeedableRng, StdRng};
+
+    fn normals(rng: &mut StdRng) -> Vec<Vector3f> {
+        let mut ret = vec![
+            Vector3f::new(0. as Float, 0., 1.),
+            Vector3f::new(0. as Float, 0., -1.),
+            Vector3f::new(1e-7 as Float, 0., -1.).normalize(),
+            Vector3f::new(0. as Float, 1e-4, 1.).normalize(),
+            Vector3f::new(1. as Float, 0., 0.),
+        ];
+        for _ in 0..8 {
+            ret.push(sample_uniform_sphere(Point2f::new(rng.gen(), rng.gen())));
+        }
+        ret
+    }
+
+    #[test]
+    fn test_orthonormal_basis() {
+        let mut rng = StdRng::from_seed(&[7usize][..]);
+        for n in normals(&mut rng) {
+            let (s, t) = normal::orthonormal_basis(n);
+            for v in &[s, t] {
+                assert!(v.x.is_finite() && v.y.is_finite() && v.z.is_finite(), "{:?} from {:?}", v, n);
+                assert_relative_eq!(v.magnitude(), 1. as Float, epsilon = 1e-5);
+            }
+            assert_relative_eq!(s.dot(t), 0. as Float, epsilon = 1e-5);
+            assert_relative_eq!(s.dot(n), 0. as Float, epsilon = 1e-5);
+            assert_relative_eq!(t.dot(n), 0. as Float, epsilon = 1e-5);
+            assert_relative_eq!(s.cross(t), n, epsilon = 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_about_normal() {
+        let mut rng = StdRng::from_seed(&[7usize][..]);
+        let n = 200000;
+        for normal in normals(&mut rng) {
+            // integrating the pdf against uniform sphere samples gives 1
+            let (mut cosw, mut uniform) = (0. as Float, 0. as Float);
+            for _ in 0..n {
+                let w = sample_uniform_sphere(Point2f::new(rng.gen(), rng.gen()));
+                let pdf = sample_uniform_sphere_pdf_wrt_solid_angle();
+                cosw += pdf_cosw_hemisphere_about(normal, w) / pdf;
+                uniform += pdf_uniform_hemisphere_about(normal, w) / pdf;
+            }
+            assert_relative_eq!(cosw / n as Float, 1. as Float, epsilon = 0.02);
+            assert_relative_eq!(uniform / n as Float, 1. as Float, epsilon = 0.02);
+            for _ in 0..64 {
+                let u = Point2f::new(rng.gen(), rng.gen());
+                let (w, pdf) = sample_cosw_hemisphere_about(normal, u);
+                assert!(w.dot(normal) > 0. as Float, "{:?} about {:?}", w, normal);
+                assert_relative_eq!(pdf, pdf_cosw_hemisphere_about(normal, w), epsilon = 1e-4);
+                let (w, pdf) = sample_uniform_hemisphere_about(normal, u);
+                assert!(w.dot(normal) > 0. as Float, "{:?} about {:?}", w, normal);
+                assert_relative_eq!(pdf, pdf_uniform_hemisphere_about(normal, w));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_filter_sampling {
+    use sample::*;
+    use sample::filters::*;
+
+    // `g` averaged over the filter by its samples, and by quadrature
+    fn moments(filter: &Filter, g: &Fn(Vector2f) -> Float) -> (Float, Float) {
+        let r = filter.radius();
+        let grid = |n: usize, f: &mut FnMut(Point2f)| for i in 0..n {
+            for j in 0..n {
+                f(Point2f::new((i as Float + 0.5 as Float) / n as Float, (j as Float + 0.5 as Float) / n as Float));
+            }
+        };
+        let (mut sampled, mut sampled_weight) = (0. as Float, 0. as Float);
+        grid(1024, &mut |u| {
+            let (offset, weight) = filter.sample(u);
+            assert!(offset.x.abs() <= r.x && offset.y.abs() <= r.y, "{:?} out of support", offset);
+            sampled += weight * g(offset);
+            sampled_weight += weight;
+        });
+        let (mut integral, mut integral_weight) = (0. as Float, 0. as Float);
+        grid(256, &mut |u| {
+            let p = Vector2f::new((2. as Float * u.x - 1. as Float) * r.x, (2. as Float * u.y - 1. as Float) * r.y);
+            let f = filter.evaluate(Point2f::from_vec(p));
+            integral += f * g(p);
+            integral_weight += f;
+        });
+        (sampled / sampled_weight, integral / integral_weight)
+    }
+
+    #[test]
+    fn test_moments() {
+        let r = Vector2f::new(2. as Float, 1.5 as Float);
+        let filters: Vec<(&str, Box<Filter>)> = vec![
+            ("box", Box::new(BoxFilter::new(r))),
+            ("triangle", Box::new(TriangleFilter::new(r))),
+            ("gaussian", Box::new(GaussianFilter::new(2. as Float, r))),
+            ("mitchell", Box::new(MitchellFilter::new(r, 1. as Float / 3. as Float, 1. as Float / 3. as Float))),
+            ("lanczos", Box::new(LanczosSincFilter::new(r, 3. as Float))),
+            ("precomputed", Box::new(PrecomputedFilter::new(&TriangleFilter::new(r)))),
+        ];
+        let g = |d: Vector2f| d.x * d.x + 0.5 as Float * d.y * d.y + 0.25 as Float * d.x;
+        for &(name, ref filter) in &filters {
+            let (sampled, integral) = moments(&**filter, &g);
+            assert!((sampled - integral).abs() < 0.01 as Float * integral.abs().max(1. as Float), "{}: {} vs {}", name, sampled, integral);
+        }
+        // sampled exactly, every weight is one
+        for &(name, ref filter) in &filters[..3] {
+            for &u in &[0.01 as Float, 0.3, 0.5, 0.77, 0.999] {
+                assert_eq!(filter.sample(Point2f::new(u, 1. as Float - u)).1, 1. as Float, "{}", name);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_pixel_position {
+    use sample::*;
+
+    #[test]
+    fn test_stays_in_pixel() {
+        let u = Point2f::new(float::one_minus_epsilon(), float::one_minus_epsilon());
+        for &i in &[0u32, 1, 15, 255, 1023, 4095] {
+            let p = pixel_position(Point2::new(i, i + 1), u);
+            assert_eq!(p.x.floor() as u32, i);
+            assert_eq!(p.y.floor() as u32, i + 1);
+        }
+        let p = pixel_position(Point2::new(2, 3), Point2f::new(0.25 as Float, 0.5 as Float));
+        assert_eq!(p, Point2f::new(2.25 as Float, 3.5 as Float));
+    }
+}
+
+#[cfg(test)]
+mod test_sample_count {
+    use sample::*;
+    use sample::naive::Naive;
+    use sample::strata::StdStrataSampler;
+    use rand::{SeedableRng, StdRng};
+
+    // samples drawn for a pixel after setting the count to `spp`
+    fn count<S: Sampler>(mut sampler: S, spp: usize) -> usize {
+        sampler.set_sample_per_pixel(spp);
+        assert_eq!(sampler.sample_per_pixel(), spp);
+        sampler.start_pixel(Point2::new(0u32, 0u32));
+        let mut n = 1;
+        while sampler.next_sample() { n += 1; }
+        n
+    }
+
+    #[test]
+    fn test_resets() {
+        assert_eq!(count(Naive::with_rng(4, StdRng::from_seed(&[22usize][..])), 6), 6);
+        assert_eq!(count(StdStrataSampler::new(2, 2, 4, StdRng::from_seed(&[23usize][..])), 6), 6);
+    }
+
+    #[test]
+    #[should_panic(expected = "sample count must be positive")]
+    fn test_naive_zero() {
+        Naive::new(4).set_sample_per_pixel(0);
+    }
+}
@@ -0,0 +1,137 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Defines a dome, proxy geometry for lighting a scene from afar
+
+use geometry::prelude::*;
+use super::{Shape, ShapeSample};
+use super::sphere::Sphere;
+
+/// A sphere centered at the origin, or its upper half above the
+/// xy-plane, with normals pointing inwards. Emissive, it lights
+/// whatever it encloses like an environment map does.
+///
+/// Surface coordinates follow equirectangular images: `u` goes
+/// with the azimuth `phi` around the z axis from the x axis, and
+/// `v` with the polar angle `theta` from the z axis, spanning the
+/// image from the zenith down to the nadir, or to the horizon for
+/// hemispheres.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Dome {
+    /// the radius of the dome
+    pub radius: Float,
+    /// if only the upper half is presented
+    #[serde(default)]
+    pub hemisphere: bool,
+}
+
+impl Dome {
+    /// construction
+    #[inline]
+    pub fn new(radius: Float, hemisphere: bool) -> Dome {
+        assert!(radius > 0. as Float, "Dome radius should be positive");
+        Dome{
+            radius: radius,
+            hemisphere: hemisphere,
+        }
+    }
+
+    /// the lowest `z` covered
+    #[inline]
+    fn zmin(&self) -> Float {
+        if self.hemisphere { 0. as Float } else { -self.radius }
+    }
+
+    /// the polar angle `v` reaches `1` at
+    #[inline]
+    fn thetamax(&self) -> Float {
+        if self.hemisphere { float::frac_pi_2() } else { float::pi() }
+    }
+
+    /// surface coordinates and their derivatives at `p` on the dome
+    fn surface_at(&self, p: Point3f) -> (Point2f, DuvInfo) {
+        // keep the derivatives from vanishing at the poles
+        let p = if p.x == 0. as Float && p.y == 0. as Float {
+            Point3f::new(1e-5 as Float * self.radius, 0. as Float, p.z)
+        } else {
+            p
+        };
+        let mut phi = p.y.atan2(p.x);
+        if phi < 0. as Float { phi += 2. as Float * float::pi(); }
+        let theta = float::clamp(p.z / self.radius, -1. as Float, 1. as Float).acos();
+        let phimax = 2. as Float * float::pi();
+        let thetamax = self.thetamax();
+        let rxy = (p.x * p.x + p.y * p.y).sqrt().max(1e-5 as Float * self.radius);
+        let (cos_phi, sin_phi) = (p.x / rxy, p.y / rxy);
+        let dpdu = Vector3f::new(-phimax * p.y, phimax * p.x, 0. as Float);
+        let dpdv = thetamax * Vector3f::new(p.z * cos_phi, p.z * sin_phi, -self.radius * theta.sin());
+        // `dpdu` and `dpdv` go along increasing azimuth and polar angles,
+        // their cross product points inwards. So does the normal,
+        // $-p/r$, whose derivatives follow
+        let inv_radius = 1. as Float / self.radius;
+        (
+            Point2f::new(phi / phimax, theta / thetamax),
+            DuvInfo{
+                dpdu: dpdu,
+                dpdv: dpdv,
+                dndu: -dpdu * inv_radius,
+                dndv: -dpdv * inv_radius,
+            }
+        )
+    }
+}
+
+impl Shape for Dome {
+    #[inline]
+    fn bbox_local(&self) -> BBox3f {
+        BBox3f::new(
+            Point3f::new(-self.radius, -self.radius, self.zmin()),
+            Point3f::new(self.radius, self.radius, self.radius)
+        )
+    }
+
+    fn intersect_ray(&self, ray: &RawRay) -> Option<(Float, SurfaceInteraction)> {
+        let direction = ray.direction();
+        let (t0, t1) = Sphere::roots(self.radius, ray)?;
+        let (tmin, tmax) = (ray.min_extend(), ray.max_extend());
+        for &t in &[t0, t1] {
+            if !(t > tmin) || t > tmax { continue; }
+            // refine the hit point onto the sphere
+            let p = ray.evaluate(t).to_vec();
+            let p = Point3f::from_vec(p * (self.radius / p.magnitude()));
+            if p.z < self.zmin() { continue; }
+            let (uv, duv) = self.surface_at(p);
+            return Some((t, SurfaceInteraction::new(
+                p,
+                // error bound of the reprojected hit point
+                Vector3f::new(p.x.abs(), p.y.abs(), p.z.abs()) * float::eb_term(5. as Float),
+                -direction, uv, duv
+            )));
+        }
+        None
+    }
+
+    #[inline]
+    fn surface_area(&self) -> Float {
+        2. as Float * float::pi() * self.radius * (self.radius - self.zmin())
+    }
+
+    fn sample(&self, sample: Point2f) -> ShapeSample {
+        // uniform in `z` is uniform wrt area on a sphere
+        let phi = sample.x * 2. as Float * float::pi();
+        let z = self.zmin() + sample.y * (self.radius - self.zmin());
+        let rxy = (self.radius * self.radius - z * z).max(0. as Float).sqrt();
+        let pos = Point3f::new(rxy * phi.cos(), rxy * phi.sin(), z);
+        ShapeSample{
+            pos: pos,
+            norm: -pos.to_vec() / self.radius,
+            uv: self.surface_at(pos).0,
+            pdf: 1. as Float / self.surface_area(),
+        }
+    }
+}
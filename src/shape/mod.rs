@@ -10,6 +10,7 @@
 //! which resides in certain coordinate frames.
 
 use geometry::prelude::*;
+use sample::area_to_solid_angle;
 
 /// A shape
 pub trait Shape: Sync + Send
@@ -39,8 +40,8 @@ pub trait Shape: Sync + Send
     /// Return an estimation of the surface area of the shape, in local space
     fn surface_area(&self) -> Float;
 
-    /// Sample the shape, return a point and normal of the sampled point
-    fn sample(&self, sample: Point2f) -> (Point3f, Vector3f, Float);
+    /// Sample a point on the shape, with `pdf` wrt area
+    fn sample(&self, sample: Point2f) -> ShapeSample;
 
     /// pdf of a sampled interaction on the surface, defaults to `1/area`
     #[inline]
@@ -48,34 +49,88 @@ pub trait Shape: Sync + Send
         1. as Float / self.surface_area()
     }
 
-    /// Sample the shape wrt some reference point
-    fn sample_wrt(&self, pref: Point3f, sample: Point2f) -> (Point3f, Vector3f, Float) {
-        let (lp, lnorm, mut lpdf) = self.sample(sample);
-        let wi = lp - pref;
-        let distance2 = wi.magnitude2();
-        if relative_eq!(distance2, 0. as Float) {
-            lpdf = 0. as Float;
-        } else {
-            let wi = wi/distance2.sqrt();
-            lpdf *= distance2 / lnorm.dot(wi).abs();
-            if lpdf.is_infinite() { lpdf = 0. as Float; }
-        }
-        (lp, lnorm, lpdf)
+    /// Sample the shape wrt some reference point, with `pdf` wrt
+    /// solid angle at `pref`.
+    ///
+    /// Default implementation samples by area, see `sample_area_wrt`.
+    fn sample_wrt(&self, pref: Point3f, sample: Point2f) -> ShapeSample {
+        sample_area_wrt(self, pref, sample)
     }
 
-    /// Pdf wrt some reference point and an associated incoming ray
+    /// Pdf wrt solid angle at `pos_ref` of sampling the incoming
+    /// direction `wi` by `self.sample_wrt`.
+    ///
+    /// Default implementation matches the default `sample_wrt`,
+    /// see `pdf_area_wrt`.
     fn pdf_wrt(&self, pos_ref: Point3f, wi: Vector3f) -> Float {
-        let ray = RawRay::from_od(pos_ref, wi);
-        if let Some((_t, si)) = self.intersect_ray(&ray) {
-            (si.basic.pos - pos_ref).magnitude2() /
-            (wi.dot(si.basic.norm).abs()*self.surface_area())
-        } else {
-            0. as Float
-        }
+        pdf_area_wrt(self, pos_ref, wi)
+    }
+}
+
+/// Sample `shape` by area, with `pdf` converted to solid angle at `pref`
+pub fn sample_area_wrt<S: Shape + ?Sized>(shape: &S, pref: Point3f, sample: Point2f) -> ShapeSample {
+    let mut ret = shape.sample(sample);
+    let wi = ret.pos - pref;
+    let distance2 = wi.magnitude2();
+    if float::coincide(ret.pos, pref) {
+        ret.pdf = 0. as Float;
+    } else {
+        let wi = wi/distance2.sqrt();
+        ret.pdf = area_to_solid_angle(ret.pdf, distance2, ret.norm.dot(wi));
+    }
+    ret
+}
+
+/// Pdf of `sample_area_wrt` sampling `wi` from `pos_ref`, wrt solid
+/// angle. `shape.pdf` is converted at the first hit along `wi`, so
+/// the two agree as long as no other point of `shape` lies along `wi`.
+pub fn pdf_area_wrt<S: Shape + ?Sized>(shape: &S, pos_ref: Point3f, wi: Vector3f) -> Float {
+    let ray = RawRay::from_od(pos_ref, wi);
+    if let Some((_t, si)) = shape.intersect_ray(&ray) {
+        let wi = wi.normalize();
+        area_to_solid_angle(
+            shape.pdf(si.basic.pos, si.basic.norm),
+            (si.basic.pos - pos_ref).magnitude2(),
+            wi.dot(si.basic.norm)
+        )
+    } else {
+        0. as Float
+    }
+}
+
+/// A point sampled on the surface of a shape
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ShapeSample {
+    /// the sampled position
+    pub pos: Point3f,
+    /// surface normal at `pos`
+    pub norm: Vector3f,
+    /// surface coordinates at `pos`
+    pub uv: Point2f,
+    /// density of the sample
+    pub pdf: Float,
+}
+
+impl ShapeSample {
+    /// an interaction at the sampled point seen from `wo`,
+    /// enough for evaluating textures
+    pub fn interaction<'a>(&self, wo: Vector3f) -> SurfaceInteraction<'a> {
+        let (dpdu, dpdv) = normal::get_basis_from(self.norm);
+        SurfaceInteraction::new(
+            self.pos, Vector3f::zero(), wo, self.uv,
+            DuvInfo{
+                dpdu: dpdu,
+                dpdv: dpdv,
+                dndu: Vector3f::zero(),
+                dndv: Vector3f::zero(),
+            }
+        )
     }
 }
 
 pub mod sphere;
+pub mod dome;
+pub mod sdf;
 pub mod triangle;
 pub mod prelude;
 #[cfg(test)]
@@ -6,6 +6,8 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-pub use super::Shape;
+pub use super::{Shape, ShapeSample};
 pub use super::sphere::Sphere;
-pub use super::triangle::{TriangleInstance, TriangleMesh};
+pub use super::dome::Dome;
+pub use super::sdf::{SdfShape, SdfNode};
+pub use super::triangle::{TriangleInstance, TriangleMesh, MeshLight, ShadingMode};
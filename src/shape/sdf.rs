@@ -0,0 +1,290 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Defines shapes given by signed distance fields, intersected by
+//! sphere tracing
+
+use geometry::prelude::*;
+use super::{Shape, ShapeSample};
+use sample::sample_uniform_sphere;
+use std::fmt;
+
+/// default maximum number of sphere tracing steps along a ray
+pub const DEFAULT_MAX_STEPS: usize = 256;
+/// distance to the surface below which a ray hits it, for shapes
+/// with unbounded or degenerate bounds
+pub const DEFAULT_EPSILON: Float = 1e-4;
+
+/// A signed distance function, negative inside
+pub type DistanceFn = Box<Fn(Point3f) -> Float + Send + Sync>;
+
+/// A shape whose surface is the zero set of a signed distance field,
+/// bounded by a box.
+///
+/// The field is expected not to overestimate the distance to the
+/// surface, or rays might march past it.
+///
+/// Surface coordinates are planar projections of the position along
+/// the axis the normal is closest to, as in triplanar mapping.
+///
+/// `surface_area` and `sample` are crude approximations, such that
+/// these shapes are not meant as area lights.
+pub struct SdfShape {
+    distance: DistanceFn,
+    bbox: BBox3f,
+    /// maximum number of steps along a ray
+    pub max_steps: usize,
+    /// distance to the surface below which a ray hits it
+    pub epsilon: Float,
+}
+
+impl SdfShape {
+    /// construction, with the surface of `distance` lying within `bbox`.
+    /// `epsilon` defaults to `float::consts::scene_epsilon` of the bounds
+    pub fn new(distance: DistanceFn, bbox: BBox3f) -> SdfShape {
+        let epsilon = float::consts::scene_epsilon(&bbox);
+        let epsilon = if epsilon > 0. as Float { epsilon } else { DEFAULT_EPSILON };
+        SdfShape{
+            distance: distance,
+            bbox: bbox,
+            max_steps: DEFAULT_MAX_STEPS,
+            epsilon: epsilon,
+        }
+    }
+
+    /// construction out of a tree of nodes
+    #[inline]
+    pub fn from_node(node: &SdfNode) -> SdfShape {
+        SdfShape::new(node.compile(), node.bbox())
+    }
+
+    /// signed distance to the surface from `p`
+    #[inline]
+    pub fn distance(&self, p: Point3f) -> Float {
+        (self.distance)(p)
+    }
+
+    /// normalized gradient of the field at `p`, by central differences
+    pub fn normal(&self, p: Point3f) -> Vector3f {
+        let h = self.epsilon;
+        let d = |v: Vector3f| self.distance(p + v) - self.distance(p + (-v));
+        let n = Vector3f::new(
+            d(Vector3f::new(h, 0. as Float, 0. as Float)),
+            d(Vector3f::new(0. as Float, h, 0. as Float)),
+            d(Vector3f::new(0. as Float, 0. as Float, h))
+        );
+        if n.magnitude2() > 0. as Float { n.normalize() } else { Vector3f::new(0. as Float, 0. as Float, 1. as Float) }
+    }
+
+    /// march along `ray`, returning the `t` of the hit if any, or
+    /// `Err` with whether the steps ran out before leaving the bounds
+    fn march(&self, ray: &RawRay) -> Result<Float, bool> {
+        let (t0, t1) = self.bbox.intersect_ray(ray).ok_or(false)?;
+        let inv_length = 1. as Float / ray.direction().magnitude();
+        let mut t = t0;
+        for _ in 0..self.max_steps {
+            let d = self.distance(ray.evaluate(t));
+            if d.abs() < self.epsilon { return Ok(t); }
+            // inside or out, nothing lies closer than `|d|`
+            t += d.abs() * inv_length;
+            if t > t1 { return Err(false); }
+        }
+        Err(true)
+    }
+
+    /// the interaction at `p` on the surface, seen from `wo`
+    fn interaction_at<'a>(&self, p: Point3f, wo: Vector3f) -> SurfaceInteraction<'a> {
+        let n = self.normal(p);
+        let (uv, dpdu, dpdv) = triplanar(p, n);
+        SurfaceInteraction::new(
+            p,
+            // the hit lies within `epsilon` of the surface
+            Vector3f::new(self.epsilon, self.epsilon, self.epsilon) * 4. as Float,
+            wo, uv,
+            DuvInfo{
+                dpdu: dpdu,
+                dpdv: dpdv,
+                dndu: Vector3f::zero(),
+                dndv: Vector3f::zero(),
+            }
+        )
+    }
+}
+
+/// surface coordinates at `p` with normal `n`, projected along the
+/// axis closest to `n`, along with their derivatives on the tangent
+/// plane, such that `dpdu` cross `dpdv` goes along `n`
+fn triplanar(p: Point3f, n: Vector3f) -> (Point2f, Vector3f, Vector3f) {
+    let axis = if n.x.abs() > n.y.abs() && n.x.abs() > n.z.abs() { 0 }
+        else if n.y.abs() > n.z.abs() { 1 } else { 2 };
+    let mut eu = Vector3f::zero();
+    let mut ev = Vector3f::zero();
+    eu[(axis + 1) % 3] = 1. as Float;
+    ev[(axis + 2) % 3] = 1. as Float;
+    if n[axis] < 0. as Float { eu = -eu; }
+    // tangents moving one of `u` and `v` while keeping the other
+    let dpdu = ev.cross(n) / eu.dot(ev.cross(n));
+    let dpdv = n.cross(eu) / ev.dot(n.cross(eu));
+    (Point2f::new(p.to_vec().dot(eu), p.to_vec().dot(ev)), dpdu, dpdv)
+}
+
+impl Shape for SdfShape {
+    #[inline]
+    fn bbox_local(&self) -> BBox3f {
+        self.bbox
+    }
+
+    fn intersect_ray(&self, ray: &RawRay) -> Option<(Float, SurfaceInteraction)> {
+        let t = self.march(ray).ok()?;
+        Some((t, self.interaction_at(ray.evaluate(t), -ray.direction())))
+    }
+
+    /// rays running out of steps within the bounds are
+    /// taken as blocked
+    #[inline]
+    fn can_intersect(&self, ray: &RawRay) -> bool {
+        match self.march(ray) {
+            Ok(_) | Err(true) => true,
+            Err(false) => false,
+        }
+    }
+
+    /// the area of the bounds, an upper bound for convex shapes
+    #[inline]
+    fn surface_area(&self) -> Float {
+        self.bbox.surface_area()
+    }
+
+    /// a point hit by a ray towards the center of the bounds from a
+    /// uniformly sampled direction, not distributed uniformly by area
+    fn sample(&self, sample: Point2f) -> ShapeSample {
+        let center = self.bbox.pmin + self.bbox.diagonal() * 0.5 as Float;
+        let dir = sample_uniform_sphere(sample);
+        let radius = self.bbox.diagonal().magnitude();
+        let ray = RawRay::from_od(center + dir * radius, -dir);
+        let pos = match self.march(&ray) {
+            Ok(t) => ray.evaluate(t),
+            Err(_) => center,
+        };
+        let norm = self.normal(pos);
+        ShapeSample{
+            pos: pos,
+            norm: norm,
+            uv: triplanar(pos, norm).0,
+            pdf: 1. as Float / self.surface_area(),
+        }
+    }
+}
+
+impl fmt::Debug for SdfShape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SdfShape")
+            .field("bbox", &self.bbox)
+            .field("max_steps", &self.max_steps)
+            .field("epsilon", &self.epsilon)
+            .finish()
+    }
+}
+
+/// Nodes of signed distance fields, compiled into a `DistanceFn`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SdfNode {
+    /// centered at the origin
+    Sphere{
+        radius: Float,
+    },
+    /// axis-aligned, centered at the origin
+    Box{
+        half_extents: Vector3f,
+    },
+    /// around the z axis, centered at the origin
+    Torus{
+        /// from the center to the center of the tube
+        major_radius: Float,
+        /// of the tube
+        minor_radius: Float,
+    },
+    /// union of `a` and `b`, blended over distances within `k`
+    SmoothUnion{
+        a: Box<SdfNode>,
+        b: Box<SdfNode>,
+        k: Float,
+    },
+    /// `node` placed into its parent frame by `transform`. Distances
+    /// are scaled back by the largest scaling along the axes, which
+    /// keeps them from overestimating without shearing.
+    Transform{
+        transform: Matrix4f,
+        node: Box<SdfNode>,
+    },
+}
+
+impl SdfNode {
+    /// the signed distance function of the tree
+    pub fn compile(&self) -> DistanceFn {
+        match *self {
+            SdfNode::Sphere{radius} => Box::new(move |p: Point3f| {
+                p.to_vec().magnitude() - radius
+            }),
+            SdfNode::Box{half_extents} => Box::new(move |p: Point3f| {
+                let q = Vector3f::new(
+                    p.x.abs() - half_extents.x, p.y.abs() - half_extents.y, p.z.abs() - half_extents.z
+                );
+                let outside = Vector3f::new(
+                    q.x.max(0. as Float), q.y.max(0. as Float), q.z.max(0. as Float)
+                ).magnitude();
+                outside + q.x.max(q.y).max(q.z).min(0. as Float)
+            }),
+            SdfNode::Torus{major_radius, minor_radius} => Box::new(move |p: Point3f| {
+                let ring = (p.x * p.x + p.y * p.y).sqrt() - major_radius;
+                (ring * ring + p.z * p.z).sqrt() - minor_radius
+            }),
+            SdfNode::SmoothUnion{ref a, ref b, k} => {
+                let (a, b) = (a.compile(), b.compile());
+                Box::new(move |p: Point3f| smooth_min(a(p), b(p), k))
+            },
+            SdfNode::Transform{ref transform, ref node} => {
+                let inner = node.compile();
+                let inverse = transform.invert().expect("singular sdf transform");
+                let scale = (0..3).map(|i| inverse[i].truncate().magnitude())
+                    .fold(0. as Float, |a, b| a.max(b));
+                Box::new(move |p: Point3f| inner(inverse.transform_point(p)) / scale)
+            },
+        }
+    }
+
+    /// bounds of the surface
+    pub fn bbox(&self) -> BBox3f {
+        match *self {
+            SdfNode::Sphere{radius} => {
+                let r = Vector3f::new(radius, radius, radius);
+                BBox3f::new(Point3f::from_vec(-r), Point3f::from_vec(r))
+            },
+            SdfNode::Box{half_extents} => BBox3f::new(
+                Point3f::from_vec(-half_extents), Point3f::from_vec(half_extents)
+            ),
+            SdfNode::Torus{major_radius, minor_radius} => {
+                let r = major_radius + minor_radius;
+                let r = Vector3f::new(r, r, minor_radius);
+                BBox3f::new(Point3f::from_vec(-r), Point3f::from_vec(r))
+            },
+            // the blend reaches out by `k/4` at most
+            SdfNode::SmoothUnion{ref a, ref b, k} => a.bbox().union(&b.bbox())
+                .expand_by(0.25 as Float * k.max(0. as Float)),
+            SdfNode::Transform{ref transform, ref node} => node.bbox().apply_transform(transform),
+        }
+    }
+}
+
+/// polynomial smooth minimum of `a` and `b` over distances within `k`
+#[inline]
+fn smooth_min(a: Float, b: Float, k: Float) -> Float {
+    if !(k > 0. as Float) { return a.min(b); }
+    let h = (k - (a - b).abs()).max(0. as Float) / k;
+    a.min(b) - h * h * k * 0.25 as Float
+}
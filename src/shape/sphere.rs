@@ -7,13 +7,17 @@
 // except according to those terms.
 
 use geometry::prelude::*;
-use super::Shape;
+use super::{Shape, ShapeSample, sample_area_wrt, pdf_area_wrt};
+use sample::{sample_uniform_cone, pdf_uniform_cone};
 use std;
 use serde;
 use serde::{Serialize, Deserialize};
 use serde::ser::{Serializer, SerializeStruct};
 use serde::de::{Deserializer, MapAccess, SeqAccess, Visitor};
 
+/// `sin(theta)` below which hits are treated as lying on a pole
+pub const POLE_SIN_THETA: Float = 1e-3;
+
 /// A (possibly-partial) sphere, as a geometry definition
 #[derive(Copy, Clone, PartialEq)]
 pub struct Sphere {
@@ -161,6 +165,34 @@ impl Sphere {
         Sphere::new(radius, -radius, radius, float::pi() * (2.0 as Float))
     }
 
+    /// test if the sphere is not clipped at all
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.zmin <= -self.radius && self.zmax >= self.radius
+        && self.phimax >= float::pi() * (2.0 as Float)
+    }
+
+    // surface coordinates of `p` on the sphere, as `self.intersect_ray` does
+    #[inline]
+    fn uv_at(&self, p: Point3f) -> Point2f {
+        let mut phi = p.y.atan2(p.x);
+        if phi < (0.0 as Float) { phi += (2.0 as Float) * float::pi(); }
+        let theta = float::clamp(p.z / self.radius, -1. as Float, 1. as Float).acos();
+        Point2f::new(
+            phi / self.phimax, (theta - self.thetamin) / (self.thetamax - self.thetamin)
+        )
+    }
+
+    // cosine of the half-angle of the cone subtended by a full sphere
+    // seen from `pref`, or `None` if `pref` is inside the sphere
+    #[inline]
+    fn cone_wrt(&self, pref: Point3f) -> Option<Float> {
+        let dc2 = pref.to_vec().magnitude2();
+        let r2 = self.radius * self.radius;
+        if !self.is_full() || dc2 <= r2 * (1.0001 as Float) { return None; }
+        Some((1. as Float - r2 / dc2).max(0. as Float).sqrt())
+    }
+
     /// returns the local space bounding box
     #[inline]
     pub fn bounding(&self) -> BBox3f {
@@ -189,29 +221,42 @@ impl Sphere {
     //     }
     // }
 
+    /// parameters `(t0, t1)`, `t0 <= t1`, at which the line along `ray`
+    /// meets the full sphere of `radius`, regardless of the ray's extent.
+    ///
+    /// Solved in double precision by `float::solve_quadratic`, each root
+    /// then refined by a newton step on $|p|^2 - r^2$, such that rays
+    /// grazing the silhouette hit or miss consistently.
+    pub fn roots(radius: Float, ray: &RawRay) -> Option<(Float, Float)> {
+        let o = ray.origin();
+        let d = ray.direction();
+        let (ox, oy, oz) = (o.x as f64, o.y as f64, o.z as f64);
+        let (dx, dy, dz) = (d.x as f64, d.y as f64, d.z as f64);
+        let r2 = radius as f64 * radius as f64;
+        let a = dx * dx + dy * dy + dz * dz;
+        let b = 2. * (dx * ox + dy * oy + dz * oz);
+        let c = ox * ox + oy * oy + oz * oz - r2;
+        let (t0, t1) = float::solve_quadratic(a, b, c)?;
+        let refine = |t: f64| {
+            let (px, py, pz) = (ox + t * dx, oy + t * dy, oz + t * dz);
+            let f = px * px + py * py + pz * pz - r2;
+            let df = 2. * (dx * px + dy * py + dz * pz);
+            if df == 0. { return t; }
+            let refined = t - f / df;
+            let (px, py, pz) = (ox + refined * dx, oy + refined * dy, oz + refined * dz);
+            // steps off tangency might overshoot
+            if (px * px + py * py + pz * pz - r2).abs() < f.abs() { refined } else { t }
+        };
+        Some((refine(t0) as Float, refine(t1) as Float))
+    }
+
     /// test intersection against the full sphere
     pub fn intersect_ray_full(radius: Float, ray: &RawRay) -> Option<Float>
     {
-        let origin = ray.origin().to_vec();
-        let direction = ray.direction();
-        let a = direction.magnitude2();
-        let b = (direction.mul_element_wise(origin) * (2.0 as Float)).sum();
-        let c = origin.magnitude2() - radius * radius;
-
-        let delta = b* b - (4.0 as Float) * a * c;
-        if delta < (0.0 as Float) { return None; }
-        let invert_2a = (1.0 as Float) / ((2.0 as Float) * a);
-        let d1 = delta.sqrt() * invert_2a;
-        let d0 = -b * invert_2a;
-
-        let(t0, t1) = if invert_2a > 0.0 as Float {
-            (d0-d1, d0+d1)
-        } else {
-            (d0+d1, d0-d1)
-        };
-        let tmax = ray.max_extend();
-        if t0 > tmax || t1 < (0.0 as Float) { return None; }
-        if t0 > (0.0 as Float) {
+        let (t0, t1) = Sphere::roots(radius, ray)?;
+        let (tmin, tmax) = (ray.min_extend(), ray.max_extend());
+        if t0 > tmax || t1 <= tmin { return None; }
+        if t0 > tmin {
             Some(t0)
         } else if t1 > tmax {
             None
@@ -233,9 +278,6 @@ impl Shape for Sphere {
             let mut p = ray.evaluate(t).to_vec();
             // refine sphere intersection
             p = p* self.radius / p.magnitude();
-            if p.x == 0.0 as Float && p.y == 0.0 as Float {
-                p.x = 1e-5 as Float * self.radius;
-            }
             let p = Point3f::from_vec(p);
 
             let mut phi = p.y.atan2(p.x);
@@ -250,36 +292,52 @@ impl Shape for Sphere {
                 let thetamin = self.thetamin;
                 let thetadelta = thetamax - thetamin;
                 let u = phi / phimax;
-                let theta = (p.z / self.radius).acos();
+                let theta = float::clamp(p.z / self.radius, -1. as Float, 1. as Float).acos();
                 let v = (theta - thetamin) / thetadelta;
                 let inv_z_radius = (1.0 as Float) / (p.x * p.x + p.y * p.y).sqrt();
                 let cos_phi = p.x * inv_z_radius;
                 let sin_phi = p.y * inv_z_radius;
-                let dpdu = Vector3f::new(-phimax * p.y, phimax * p.x, 0.0 as Float);
-                let dpdv = thetadelta * Vector3f::new(p.z * cos_phi, p.z * sin_phi, -self.radius * theta.sin());
-                let (dndu, dndv) = {
-                    let dppduu = - phimax * phimax * Vector3f::new(p.x, p.y, 0.0 as Float);
-                    let dppduv = thetadelta * p.z * phimax * Vector3f::new(-sin_phi, cos_phi, 0.0 as Float);
-                    let dppdvv = -thetadelta * thetadelta * Vector3f::new(p.x, p.y, p.z);
-
-                    let e = dpdu.dot(dpdu);
-                    let f = dpdu.dot(dpdv);
-                    let g = dpdv.dot(dpdv);
-                    let n = dpdu.cross(dpdv).normalize();
-                    let ee = n.dot(dppduu);
-                    let ff = n.dot(dppduv);
-                    let gg = n.dot(dppdvv);
-                    let inv = (1.0 as Float) / (e * g - f * f);
-                    (
-                        (ff*f - ee*g) * inv * dpdu + (ee*f - ff*e) * inv * dpdv,
-                        (gg*f - ff*g) * inv * dpdu + (ff*f - gg*e) * inv * dpdv
-                    )
+                let sin_theta = theta.sin();
+                let (dpdu, dpdv, dndu, dndv) = if sin_theta < POLE_SIN_THETA {
+                    // `dpdu` vanishes at the poles, along with the
+                    // curvature computed from it. Use an orthonormal
+                    // frame around the normal instead, scaled as `dpdu`
+                    // at the edge of the pole cap, for which the
+                    // normal varies as the position does over the radius
+                    let sign = -(phimax * thetadelta).signum();
+                    let frame = Frame::new(p.to_vec() * sign, Vector3f::new(-p.y, p.x, 0.0 as Float));
+                    let dpdu = frame.s * (phimax * self.radius * POLE_SIN_THETA);
+                    let dpdv = frame.t * (thetadelta.abs() * self.radius);
+                    let inv_radius = sign / self.radius;
+                    (dpdu, dpdv, dpdu * inv_radius, dpdv * inv_radius)
+                } else {
+                    let dpdu = Vector3f::new(-phimax * p.y, phimax * p.x, 0.0 as Float);
+                    let dpdv = thetadelta * Vector3f::new(p.z * cos_phi, p.z * sin_phi, -self.radius * sin_theta);
+                    let (dndu, dndv) = {
+                        let dppduu = - phimax * phimax * Vector3f::new(p.x, p.y, 0.0 as Float);
+                        let dppduv = thetadelta * p.z * phimax * Vector3f::new(-sin_phi, cos_phi, 0.0 as Float);
+                        let dppdvv = -thetadelta * thetadelta * Vector3f::new(p.x, p.y, p.z);
+
+                        let e = dpdu.dot(dpdu);
+                        let f = dpdu.dot(dpdv);
+                        let g = dpdv.dot(dpdv);
+                        let n = dpdu.cross(dpdv).normalize();
+                        let ee = n.dot(dppduu);
+                        let ff = n.dot(dppduv);
+                        let gg = n.dot(dppdvv);
+                        let inv = (1.0 as Float) / (e * g - f * f);
+                        (
+                            (ff*f - ee*g) * inv * dpdu + (ee*f - ff*e) * inv * dpdv,
+                            (gg*f - ff*g) * inv * dpdu + (ff*f - gg*e) * inv * dpdv
+                        )
+                    };
+                    (dpdu, dpdv, dndu, dndv)
                 };
                 Some((
                     t, SurfaceInteraction::new(
                         p, 
-                        // FIXME: wrong
-                        Vector3f::zero(),
+                        // error bound of the reprojected hit point
+                        Vector3f::new(p.x.abs(), p.y.abs(), p.z.abs()) * float::eb_term(5. as Float),
                         -ray.direction(), Point2f::new(u, v),
                         DuvInfo{
                             dpdu: dpdu,
@@ -301,18 +359,69 @@ impl Shape for Sphere {
         self.phimax * self.radius * (self.zmax - self.zmin)
     }
 
-    fn sample(&self, sample: Point2f) -> (Point3f, Vector3f, Float) {
+    fn sample(&self, sample: Point2f) -> ShapeSample {
         // sample.x scaled to [0, phimax]
         let phi = sample.x * self.phimax;
-        // sample.y scaled to [thetamin, thetamax]
-        let theta = sample.y * (self.thetamax - self.thetamin) + self.thetamin;
-        let dir = Sphericalf::new(theta, phi).to_vec();
+        // sample.y scaled to [zmin, zmax], which is uniform wrt area
+        let z = sample.y * (self.zmax - self.zmin) + self.zmin;
+        let rxy = (self.radius * self.radius - z * z).max(0. as Float).sqrt();
+        let dir = Vector3f::new(rxy * phi.cos(), rxy * phi.sin(), z) / self.radius;
         let pos = Point3f::from_vec(dir*self.radius);
-        (pos, dir, 1. as Float / self.surface_area())
+        // same parameterization as `self.intersect_ray`
+        let theta = dir.z.max(-1. as Float).min(1. as Float).acos();
+        let uv = Point2f::new(
+            sample.x, (theta - self.thetamin) / (self.thetamax - self.thetamin)
+        );
+        ShapeSample{
+            pos: pos,
+            norm: dir,
+            uv: uv,
+            pdf: 1. as Float / self.surface_area(),
+        }
 
         // use sample::sample_uniform_sphere;
         // let dir = sample_uniform_sphere(sample);
         // let pos = Point3f::from_vec(dir*self.radius);
         // (pos, dir, 1. as Float / self.surface_area())
     }
+
+    /// For full spheres seen from outside, only the visible cap is
+    /// sampled, uniformly wrt solid angle over the subtended cone.
+    fn sample_wrt(&self, pref: Point3f, sample: Point2f) -> ShapeSample {
+        let cos_max = match self.cone_wrt(pref) {
+            Some(cos_max) => cos_max,
+            None => return sample_area_wrt(self, pref, sample),
+        };
+        let dc = pref.to_vec().magnitude();
+        let wc = -pref.to_vec() / dc;
+        let (wcx, wcy) = normal::get_basis_from(wc);
+        let d = sample_uniform_cone(sample, cos_max);
+        let costheta = d.z;
+        let d = wcx * d.x + wcy * d.y + wc * d.z;
+        // nearest hit of the sampled direction
+        let sin2theta = (1. as Float - costheta * costheta).max(0. as Float);
+        let t = dc * costheta - (self.radius * self.radius - dc * dc * sin2theta).max(0. as Float).sqrt();
+        let norm = (pref + d * t).to_vec().normalize();
+        let pos = Point3f::from_vec(norm * self.radius);
+        ShapeSample{
+            pos: pos,
+            norm: norm,
+            uv: self.uv_at(pos),
+            pdf: pdf_uniform_cone(cos_max),
+        }
+    }
+
+    fn pdf_wrt(&self, pos_ref: Point3f, wi: Vector3f) -> Float {
+        match self.cone_wrt(pos_ref) {
+            Some(cos_max) => {
+                let ray = RawRay::from_od(pos_ref, wi);
+                if self.can_intersect(&ray) {
+                    pdf_uniform_cone(cos_max)
+                } else {
+                    0. as Float
+                }
+            },
+            None => pdf_area_wrt(self, pos_ref, wi),
+        }
+    }
 }
@@ -77,4 +77,1011 @@ mod test_sphere {
             assert!(unhit != ROUNDS);
         }
     }
+
+    // whether `ray` hits a full sphere of `radius` within its extent, in
+    // double precision with the discriminant taken as the distance of
+    // the line to the center, or `None` if too close to tell
+    fn reference_hit(radius: Float, ray: &RawRay) -> Option<bool> {
+        let o = ray.origin();
+        let d = ray.direction();
+        let (o, d) = ([o.x as f64, o.y as f64, o.z as f64], [d.x as f64, d.y as f64, d.z as f64]);
+        let a = d[0] * d[0] + d[1] * d[1] + d[2] * d[2];
+        let tc = -(o[0] * d[0] + o[1] * d[1] + o[2] * d[2]) / a;
+        let closest = [o[0] + tc * d[0], o[1] + tc * d[1], o[2] + tc * d[2]];
+        let h2 = closest[0] * closest[0] + closest[1] * closest[1] + closest[2] * closest[2];
+        let r2 = radius as f64 * radius as f64;
+        if (h2 - r2).abs() <= 1e-9 * r2 { return None; }
+        if h2 > r2 { return Some(false); }
+        let half = ((r2 - h2) / a).sqrt();
+        let tmax = ray.max_extend() as f64;
+        Some((tc - half > 0. && tc - half <= tmax) || (tc + half > 0. && tc + half <= tmax))
+    }
+
+    #[test]
+    fn test_near_tangent() {
+        let mut checked = 0;
+        for &radius in &[1e-2, 1., 1e2, 1e4] {
+            for &distance in &[2., 10., 1e3, 1e4] {
+                for k in -40..41 {
+                    // rays from afar towards points right around the limb
+                    let radius = radius as Float;
+                    let origin = Point3f::new(-distance as Float * radius, 0.3 as Float * radius, 0.1 as Float * radius);
+                    let offset = 1. as Float + k as Float * 1e-5 as Float;
+                    let target = Point3f::new(0. as Float, radius * offset, 0. as Float);
+                    let ray = RawRay::from_od(origin, target - origin);
+                    let sphere = Sphere::full(radius);
+                    if let Some(expected) = reference_hit(radius, &ray) {
+                        assert_eq!(
+                            sphere.can_intersect(&ray), expected,
+                            "radius {}, distance {}, offset {}", radius, distance, offset
+                        );
+                        checked += 1;
+                    }
+                }
+            }
+        }
+        assert!(checked > 1000);
+    }
+
+    #[test]
+    fn test_silhouette_speckles() {
+        const RESOLUTION: usize = 128;
+        // a sphere far from the camera, filling most of the view
+        let radius = 50. as Float;
+        let camera = ::test_utils::camera(
+            RESOLUTION, Point3f::new(0. as Float, 0., -5e3), Point3f::new(0. as Float, 0., 0.), 0.024 as Float
+        );
+        let sphere = Sphere::full(radius);
+        let mut hits = vec![false; RESOLUTION * RESOLUTION];
+        let mut mismatches = 0;
+        for y in 0..RESOLUTION {
+            for x in 0..RESOLUTION {
+                let ray = camera.generate_path(::lighting::SampleInfo{
+                    pfilm: Point2f::new(x as Float + 0.5 as Float, y as Float + 0.5 as Float),
+                    plens: Point2f::new(0.5 as Float, 0.5 as Float),
+                    time: 0. as Float,
+                });
+                let hit = sphere.can_intersect(&ray);
+                if let Some(expected) = reference_hit(radius, &ray) {
+                    if hit != expected { mismatches += 1; }
+                }
+                hits[y * RESOLUTION + x] = hit;
+            }
+        }
+        assert_eq!(mismatches, 0);
+        // no pixel differs from all of its neighbors
+        let mut speckles = 0;
+        let mut covered = 0;
+        for y in 1..RESOLUTION - 1 {
+            for x in 1..RESOLUTION - 1 {
+                let h = hits[y * RESOLUTION + x];
+                if h { covered += 1; }
+                let neighbors = [
+                    hits[y * RESOLUTION + x - 1], hits[y * RESOLUTION + x + 1],
+                    hits[(y - 1) * RESOLUTION + x], hits[(y + 1) * RESOLUTION + x],
+                ];
+                if neighbors.iter().all(|&n| n != h) { speckles += 1; }
+            }
+        }
+        assert!(covered > RESOLUTION * RESOLUTION / 4);
+        assert_eq!(speckles, 0);
+    }
+}
+
+#[cfg(test)]
+mod test_meshlight {
+    use super::*;
+    use super::triangle::*;
+    use lighting::{Light, EmissionSide, SampleInfo};
+    use material::prelude::*;
+    use texturing::prelude::*;
+    use spectrum::*;
+    use std::sync::Arc;
+    use std::time::Instant;
+    use tobj;
+
+    // a `n`x`n` grid of quads on `z=0`, facing `+z`
+    fn grid(n: usize, side: EmissionSide) -> Arc<TriangleMesh> {
+        let mut positions = Vec::with_capacity((n+1)*(n+1)*3);
+        for j in 0..n+1 {
+            for i in 0..n+1 {
+                positions.push(i as f32 / n as f32);
+                positions.push(j as f32 / n as f32);
+                positions.push(0.);
+            }
+        }
+        let mut indices = Vec::with_capacity(n*n*6);
+        for j in 0..n {
+            for i in 0..n {
+                let v = (j*(n+1) + i) as u32;
+                let w = v + n as u32 + 1;
+                indices.extend_from_slice(&[v, v+1, w, v+1, w+1, w]);
+            }
+        }
+        let model = tobj::Model::new(
+            tobj::Mesh::new(positions, Vec::new(), Vec::new(), indices, None),
+            "lamp".to_owned()
+        );
+        let material = Arc::new(MatteMaterial::new(
+            Arc::new(ConstantTexture{value: RGBSpectrumf::new(0.5 as Float, 0.5, 0.5)}),
+            Arc::new(ConstantTexture{value: 0. as Float}),
+            None
+        ));
+        let mut mesh = TriangleMesh::from_model(
+            model, material,
+            Some(Arc::new(ConstantTexture{value: RGBSpectrumf::new(1. as Float, 1., 1.)}))
+        );
+        mesh.set_emission_side(side);
+        Arc::new(mesh)
+    }
+
+    #[test]
+    fn test_meshlight_sampling() {
+        // 10k triangles
+        let mesh = grid(71, EmissionSide::Front);
+        let start = Instant::now();
+        let light = MeshLight::new(mesh);
+        let elapsed = start.elapsed();
+        assert!(elapsed.as_secs() < 2);
+        assert_relative_eq!(light.surface_area(), 1. as Float, epsilon = 1e-3);
+
+        let pos = Point3f::new(0.5 as Float, 0.5, 1.);
+        let mut rng = StdRng::new().unwrap();
+        let n = 4096;
+        let mut left = 0;
+        for _ in 0..n {
+            let u = Point2f::new(rng.gen(), rng.gen());
+            let ls = light.evaluate_sampled(pos, u);
+            assert!(ls.pdf > 0. as Float);
+            assert!(!ls.no_effect());
+            assert_relative_eq!(ls.pdf, light.pdf(pos, ls.wi()), max_relative = 1e-2);
+            if ls.pfrom.x < 0.5 as Float { left += 1; }
+        }
+        let ratio = left as Float / n as Float;
+        assert!((ratio - 0.5 as Float).abs() < 0.05 as Float);
+    }
+
+    #[test]
+    fn test_triangle_pdfs() {
+        let mesh = grid(1, EmissionSide::Front);
+        let pos = Point3f::new(0.3 as Float, 0.2, 2.);
+        let mut rng = StdRng::from_seed(&[18usize][..]);
+        for triangle in TriangleMesh::instances(&mesh) {
+            // origins of photon paths are densities wrt area
+            let path = triangle.generate_path(SampleInfo{
+                pfilm: Point2f::new(rng.gen(), rng.gen()),
+                plens: Point2f::new(rng.gen(), rng.gen()),
+                time: 0. as Float,
+            });
+            let (pdfpos, _) = triangle.pdf_path(path.ray.origin(), path.ray.direction(), path.normal);
+            assert_relative_eq!(pdfpos, path.pdfpos, max_relative = 1e-5);
+            assert_relative_eq!(pdfpos, 2. as Float, max_relative = 1e-5);
+            // incoming directions are densities wrt solid angle
+            let ls = triangle.evaluate_sampled(pos, Point2f::new(rng.gen(), rng.gen()));
+            assert_relative_eq!(ls.pdf, Light::pdf(&triangle, pos, ls.wi()), max_relative = 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_emission_side() {
+        let above = Point3f::new(0.5 as Float, 0.5, 1.);
+        let below = Point3f::new(0.5 as Float, 0.5, -1.);
+        let u = Point2f::new(0.3 as Float, 0.6);
+        let lit = |light: &MeshLight, pos: Point3f| !light.evaluate_sampled(pos, u).no_effect();
+
+        let front = MeshLight::new(grid(1, EmissionSide::Front));
+        assert!(lit(&front, above));
+        assert!(!lit(&front, below));
+
+        let back = MeshLight::new(grid(1, EmissionSide::Back));
+        assert!(!lit(&back, above));
+        assert!(lit(&back, below));
+        let path = back.generate_path(SampleInfo{pfilm: u, plens: Point2f::new(0.2 as Float, 0.7), time: 0. as Float});
+        assert!(path.ray.direction().z < 0. as Float);
+
+        let both = MeshLight::new(grid(1, EmissionSide::Both));
+        assert!(lit(&both, above));
+        assert!(lit(&both, below));
+        assert_relative_eq!(both.power().inner, front.power().inner * 2. as Float);
+        assert_relative_eq!(back.power().inner, front.power().inner);
+    }
+
+    #[test]
+    fn test_evaluate_path() {
+        let up = Vector3f::new(0.2 as Float, -0.1, 1.).normalize();
+        let front = MeshLight::new(grid(4, EmissionSide::Front));
+        let back = MeshLight::new(grid(4, EmissionSide::Back));
+        let mut rng = StdRng::from_seed(&[5usize][..]);
+        for _ in 0..64 {
+            let pos = Point3f::new(rng.gen(), rng.gen(), 0. as Float);
+            assert_relative_eq!(front.evaluate_path(pos, up).inner, Vector3f::new(1. as Float, 1., 1.));
+            assert!(front.evaluate_path(pos, -up).is_black());
+            assert!(back.evaluate_path(pos, up).is_black());
+            assert_relative_eq!(back.evaluate_path(pos, -up).inner, Vector3f::new(1. as Float, 1., 1.));
+        }
+        // shared edges and corners
+        for &(x, y) in &[(0.25 as Float, 0.5 as Float), (0.5, 0.5), (0., 0.), (1., 1.)] {
+            assert!(!front.evaluate_path(Point3f::new(x, y, 0. as Float), up).is_black());
+        }
+        // off the mesh
+        for &pos in &[Point3f::new(0.5 as Float, 0.5, 0.1), Point3f::new(1.5 as Float, 0.5, 0.), Point3f::new(-0.1 as Float, 0.5, 0.)] {
+            assert!(front.evaluate_path(pos, up).is_black());
+        }
+
+        // the profile is looked up where `pos` lies, on either triangle
+        let window = MeshLight::new(window_quad());
+        let at = |x: Float, y: Float| window.evaluate_path(Point3f::new(x, y, 0. as Float), up);
+        assert_relative_eq!(at(0.65 as Float, 0.25).inner, Vector3f::new(100. as Float, 100., 100.));
+        assert!(at(0.55 as Float, 0.25).is_black());
+        assert!(at(0.3 as Float, 0.9).is_black());
+        assert!(at(0.9 as Float, 0.8).is_black());
+    }
+
+    // a unit quad on `z=0` facing `+z`, with `uv` equal to `xy`, dark
+    // but for a small bright window
+    fn window_quad() -> Arc<TriangleMesh> {
+        struct Window;
+        impl Texture for Window {
+            type Texel = RGBSpectrumf;
+            fn evaluate(&self, si: &SurfaceInteraction, _dxy: &DxyInfo) -> RGBSpectrumf {
+                let inside = |v: Float, lo: Float| v >= lo && v < lo + 0.1 as Float;
+                if inside(si.uv.x, 0.6 as Float) && inside(si.uv.y, 0.2 as Float) {
+                    RGBSpectrumf::grey_scale(100. as Float)
+                } else {
+                    RGBSpectrumf::black()
+                }
+            }
+            fn mean(&self) -> RGBSpectrumf {
+                RGBSpectrumf::grey_scale(1. as Float)
+            }
+        }
+        let model = tobj::Model::new(
+            tobj::Mesh::new(
+                vec![0., 0., 0., 1., 0., 0., 0., 1., 0., 1., 1., 0.],
+                Vec::new(),
+                vec![0., 0., 1., 0., 0., 1., 1., 1.],
+                vec![0, 1, 2, 1, 3, 2],
+                None
+            ),
+            "window".to_owned()
+        );
+        let material = Arc::new(MatteMaterial::new(
+            Arc::new(ConstantTexture{value: RGBSpectrumf::grey_scale(0.5 as Float)}),
+            Arc::new(ConstantTexture{value: 0. as Float}),
+            None
+        ));
+        Arc::new(TriangleMesh::from_model(model, material, Some(Arc::new(Window))))
+    }
+
+    #[test]
+    fn test_profile_weighted() {
+        let mesh = window_quad();
+        let uniform = MeshLight::new(mesh.clone());
+        let weighted = MeshLight::profile_weighted(mesh, DEFAULT_PROFILE_RESOLUTION);
+        assert!(!uniform.is_profile_weighted());
+        assert!(weighted.is_profile_weighted());
+
+        let pos = Point3f::new(0.4 as Float, 0.5, 1.);
+        let mut rng = StdRng::from_seed(&[7usize][..]);
+        // irradiance at `pos` facing the quad, by 16 samples of `light`
+        let mut estimate = |light: &MeshLight| {
+            let mut sum = 0. as Float;
+            for _ in 0..16 {
+                let ls = light.evaluate_sampled(pos, Point2f::new(rng.gen(), rng.gen()));
+                if ls.pdf > 0. as Float {
+                    sum += ls.radiance.to_xyz().y * ls.wi().z.abs() / ls.pdf;
+                }
+            }
+            sum / 16. as Float
+        };
+        let n = 256;
+        let mut stats = Vec::new();
+        for light in &[&uniform, &weighted] {
+            let estimates: Vec<_> = (0..n).map(|_| estimate(light)).collect();
+            let mean = estimates.iter().sum::<Float>() / n as Float;
+            let variance = estimates.iter().map(|e| (e - mean) * (e - mean)).sum::<Float>() / (n - 1) as Float;
+            stats.push((mean, variance));
+        }
+        let (uniform, weighted) = (stats[0], stats[1]);
+        assert!(uniform.1 > weighted.1 * 10. as Float, "{:?} vs {:?}", weighted, uniform);
+        // against the irradiance integrated over the window
+        let mut reference = 0. as Float;
+        for i in 0..100 {
+            for j in 0..100 {
+                let p = Point3f::new(
+                    0.6 as Float + (i as Float + 0.5) * 1e-3, 0.2 as Float + (j as Float + 0.5) * 1e-3, 0.
+                );
+                let r2 = (pos - p).magnitude2();
+                reference += 100. as Float * pos.z * pos.z / (r2 * r2) * 1e-6;
+            }
+        }
+        assert_relative_eq!(weighted.0, reference, max_relative = 0.05);
+        assert_relative_eq!(uniform.0, reference, max_relative = 0.5);
+
+        // densities match those of the samples
+        let light = MeshLight::profile_weighted(window_quad(), DEFAULT_PROFILE_RESOLUTION);
+        for _ in 0..256 {
+            let ls = light.evaluate_sampled(pos, Point2f::new(rng.gen(), rng.gen()));
+            assert_relative_eq!(ls.pdf, light.pdf(pos, ls.wi()), max_relative = 1e-2);
+            let path = light.generate_path(SampleInfo{
+                pfilm: Point2f::new(rng.gen(), rng.gen()),
+                plens: Point2f::new(rng.gen(), rng.gen()),
+                time: 0. as Float,
+            });
+            let (pdfpos, _) = light.pdf_path(path.ray.origin(), path.ray.direction(), path.normal);
+            assert_relative_eq!(pdfpos, path.pdfpos, max_relative = 1e-2);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_generate_normals {
+    use super::*;
+    use super::triangle::*;
+    use test_utils;
+    use std::sync::Arc;
+    use tobj;
+
+    fn mesh(positions: Vec<f32>, indices: Vec<u32>) -> Arc<TriangleMesh> {
+        let model = tobj::Model::new(
+            tobj::Mesh::new(positions, Vec::new(), Vec::new(), indices, None),
+            "mesh".to_owned()
+        );
+        let mut mesh = TriangleMesh::from_model(model, test_utils::matte(0.5 as Float, 0.5, 0.5), None);
+        mesh.generate_normals(30. as Float);
+        Arc::new(mesh)
+    }
+
+    // shading normal at where a ray from `origin` towards the center hits
+    fn shading_norm(mesh: &Arc<TriangleMesh>, origin: Point3f) -> Option<(Point3f, Vector3f)> {
+        let ray = RawRay::from_od(origin, Point3f::new(0. as Float, 0., 0.) - origin);
+        TriangleMesh::instances(mesh).filter_map(|t| {
+            t.intersect_ray(&ray).map(|(t, si)| (t, si.basic.pos, si.shading_norm))
+        }).min_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+            .map(|(_, p, n)| (p, n))
+    }
+
+    #[test]
+    fn test_cube_stays_faceted() {
+        let mut positions = Vec::new();
+        for i in 0..8 {
+            positions.push(if i & 1 == 0 { -1. } else { 1. });
+            positions.push(if i & 2 == 0 { -1. } else { 1. });
+            positions.push(if i & 4 == 0 { -1. } else { 1. });
+        }
+        let quads = [
+            [0, 2, 3, 1], [4, 5, 7, 6], [0, 1, 5, 4],
+            [2, 6, 7, 3], [0, 4, 6, 2], [1, 3, 7, 5],
+        ];
+        let mut indices = Vec::new();
+        for q in &quads {
+            indices.extend_from_slice(&[q[0], q[1], q[2], q[0], q[2], q[3]]);
+        }
+        let cube = mesh(positions, indices);
+        // corners are split into one vertex per face
+        assert_eq!(cube.vertex_count(), 24);
+        let mut rng = StdRng::new().unwrap();
+        for _ in 0..256 {
+            let origin = Point3f::new(
+                rng.gen_range(-5. as Float, 5.), rng.gen_range(-5. as Float, 5.), rng.gen_range(-5. as Float, 5.)
+            );
+            if origin.x.abs().max(origin.y.abs()).max(origin.z.abs()) < 1.5 as Float { continue; }
+            let (p, n) = shading_norm(&cube, origin).unwrap();
+            // the face hit is given by the dominant axis
+            let axis = if p.x.abs() >= p.y.abs() && p.x.abs() >= p.z.abs() {
+                Vector3f::new(1. as Float, 0., 0.)
+            } else if p.y.abs() >= p.z.abs() {
+                Vector3f::new(0. as Float, 1., 0.)
+            } else {
+                Vector3f::new(0. as Float, 0., 1.)
+            };
+            assert_relative_eq!(n.dot(axis).abs(), 1. as Float, epsilon = 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_sphere_becomes_smooth() {
+        let (nphi, ntheta) = (48, 24);
+        let mut positions = Vec::new();
+        for j in 0..ntheta+1 {
+            let theta = float::pi() * j as Float / ntheta as Float;
+            // keep the poles exact so that they get welded
+            let sin_theta = if j == 0 || j == ntheta { 0. as Float } else { theta.sin() };
+            for i in 0..nphi {
+                let phi = float::pi() * 2. as Float * i as Float / nphi as Float;
+                positions.push((sin_theta * phi.cos()) as f32);
+                positions.push((sin_theta * phi.sin()) as f32);
+                positions.push(theta.cos() as f32);
+            }
+        }
+        let mut indices = Vec::new();
+        for j in 0..ntheta {
+            for i in 0..nphi {
+                let v = (j * nphi + i) as u32;
+                let w = (j * nphi + (i + 1) % nphi) as u32;
+                let (v1, w1) = (v + nphi as u32, w + nphi as u32);
+                indices.extend_from_slice(&[v, v1, w1, v, w1, w]);
+            }
+        }
+        let sphere = mesh(positions, indices);
+        let mut rng = StdRng::new().unwrap();
+        let mut max_deviation = 0. as Float;
+        for _ in 0..512 {
+            let u = Point2f::new(rng.gen(), rng.gen());
+            let origin = Point3f::from_vec(sample::sample_uniform_sphere(u) * 4. as Float);
+            let (p, n) = shading_norm(&sphere, origin).unwrap();
+            let analytic = p.to_vec().normalize();
+            max_deviation = max_deviation.max(n.dot(analytic).abs().min(1. as Float).acos());
+        }
+        // a facet normal deviates by up to half a segment, about 3.75 degrees
+        assert!(max_deviation < (2. as Float).to_radians(), "deviation: {}", max_deviation.to_degrees());
+    }
+}
+
+#[cfg(test)]
+mod test_shading_mode {
+    use super::*;
+    use super::triangle::*;
+    use test_utils;
+    use std::sync::Arc;
+    use tobj;
+
+    // a coarse unit uv sphere carrying its analytic vertex normals
+    fn sphere(mode: ShadingMode) -> Arc<TriangleMesh> {
+        let (nphi, ntheta) = (16, 8);
+        let mut positions = Vec::new();
+        for j in 0..ntheta+1 {
+            let theta = float::pi() * j as Float / ntheta as Float;
+            for i in 0..nphi {
+                let phi = float::pi() * 2. as Float * i as Float / nphi as Float;
+                positions.push((theta.sin() * phi.cos()) as f32);
+                positions.push((theta.sin() * phi.sin()) as f32);
+                positions.push(theta.cos() as f32);
+            }
+        }
+        let mut indices = Vec::new();
+        for j in 0..ntheta {
+            for i in 0..nphi {
+                let v = (j * nphi + i) as u32;
+                let w = (j * nphi + (i + 1) % nphi) as u32;
+                let (v1, w1) = (v + nphi as u32, w + nphi as u32);
+                indices.extend_from_slice(&[v, v1, w1, v, w1, w]);
+            }
+        }
+        let model = tobj::Model::new(
+            tobj::Mesh::new(positions.clone(), positions, Vec::new(), indices, None),
+            "sphere".to_owned()
+        );
+        let mut mesh = TriangleMesh::from_model(model, test_utils::matte(0.5 as Float, 0.5, 0.5), None);
+        mesh.set_shading_mode(mode);
+        Arc::new(mesh)
+    }
+
+    // shading normals seen through a 32x32 grid of rays, `None` where missed
+    fn normals(mode: ShadingMode) -> Vec<Option<Vector3f>> {
+        let mesh = sphere(mode);
+        let mut ret = Vec::new();
+        for y in 0..32 {
+            for x in 0..32 {
+                let target = Point3f::new(
+                    (x as Float + 0.5 as Float) / 16. as Float - 1. as Float,
+                    (y as Float + 0.5 as Float) / 16. as Float - 1. as Float,
+                    0. as Float
+                );
+                let origin = Point3f::new(0. as Float, 0., -4.);
+                let ray = RawRay::from_od(origin, target - origin);
+                ret.push(TriangleMesh::instances(&mesh).filter_map(|t| {
+                    t.intersect_ray(&ray).map(|(t, si)| (t, si.shading_norm))
+                }).min_by(|a, b| a.0.partial_cmp(&b.0).unwrap()).map(|(_, n)| n));
+            }
+        }
+        ret
+    }
+
+    #[test]
+    fn test_modes() {
+        let smooth = normals(ShadingMode::Smooth);
+        let faceted = normals(ShadingMode::Faceted);
+        assert!(smooth.iter().any(|n| n.is_some()));
+        let mut max_deviation = 0. as Float;
+        for (s, f) in smooth.iter().zip(faceted.iter()) {
+            match (*s, *f) {
+                (Some(s), Some(f)) => max_deviation = max_deviation.max(
+                    float::clamp(s.dot(f), -1. as Float, 1. as Float).acos()
+                ),
+                (None, None) => (),
+                _ => panic!("shading changed coverage"),
+            }
+        }
+        // facets span 22.5 degrees, normals differ by up to about half that
+        assert!(max_deviation > (5. as Float).to_radians(), "deviation: {}", max_deviation.to_degrees());
+        assert_eq!(normals(ShadingMode::AutoByAngle(0. as Float)), faceted);
+        assert_eq!(normals(ShadingMode::AutoByAngle(180. as Float)), smooth);
+        // in between, the smooth normal is kept only where close enough
+        let auto = normals(ShadingMode::AutoByAngle(5. as Float));
+        assert!(auto != smooth && auto != faceted);
+    }
+}
+
+#[cfg(test)]
+mod test_dome {
+    use super::*;
+    use super::dome::Dome;
+
+    #[test]
+    fn test_dome_inside() {
+        let dome = Dome::new(2. as Float, false);
+        let hemisphere = Dome::new(2. as Float, true);
+        // seen from the center, normals point back inwards and
+        // the horizon along +x is the left edge of the middle row
+        let ray = RawRay::from_od(Point3f::new(0. as Float, 0., 0.), Vector3f::new(1. as Float, 0., 0.));
+        let (t, si) = dome.intersect_ray(&ray).unwrap();
+        assert_relative_eq!(t, 2. as Float, epsilon = 1e-4);
+        assert_relative_eq!(si.basic.norm, Vector3f::new(-1. as Float, 0., 0.), epsilon = 1e-4);
+        assert_relative_eq!(si.uv, Point2f::new(0. as Float, 0.5), epsilon = 1e-4);
+        let (_, si) = hemisphere.intersect_ray(&ray).unwrap();
+        assert_relative_eq!(si.uv, Point2f::new(0. as Float, 1.), epsilon = 1e-4);
+        let up = RawRay::from_od(Point3f::new(0. as Float, 0., 0.), Vector3f::new(0. as Float, 0., 1.));
+        let (_, si) = hemisphere.intersect_ray(&up).unwrap();
+        assert_relative_eq!(si.uv.y, 0. as Float, epsilon = 1e-4);
+        assert!(si.basic.norm.z < -0.99 as Float);
+
+        // from below, rays pass the missing half to hit the upper one
+        let ray = RawRay::from_od(Point3f::new(0. as Float, 0., -5.), Vector3f::new(0. as Float, 0., 1.));
+        let (t, _) = hemisphere.intersect_ray(&ray).unwrap();
+        assert_relative_eq!(t, 7. as Float, epsilon = 1e-3);
+        let down = RawRay::from_od(Point3f::new(0.5 as Float, 0., 0.5), Vector3f::new(0. as Float, 0., -1.));
+        assert!(hemisphere.intersect_ray(&down).is_none());
+
+        // samples and their pdfs agree with `pdf_wrt` from inside
+        let mut rng = StdRng::from_seed(&[19usize][..]);
+        // on the base plane, the hemisphere covers exactly half of the directions
+        let pref = Point3f::new(0.3 as Float, -0.2, 0.);
+        for shape in &[dome, hemisphere] {
+            let mut sum = 0. as Float;
+            let n = 10000;
+            for _ in 0..n {
+                let ss = shape.sample_wrt(pref, Point2f::new(rng.gen(), rng.gen()));
+                assert!(ss.norm.dot(ss.pos.to_vec()) < 0. as Float);
+                let wi = (ss.pos - pref).normalize();
+                assert_relative_eq!(shape.pdf_wrt(pref, wi), ss.pdf, max_relative = 1e-2);
+                sum += 1. as Float / ss.pdf;
+            }
+            // the solid angle subtended from inside
+            let expected = if shape.hemisphere { 2. as Float * float::pi() } else { 4. as Float * float::pi() };
+            assert_relative_eq!(sum / n as Float, expected, max_relative = 0.1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_backface_culling {
+    use super::*;
+    use super::triangle::*;
+    use component::Composable;
+    use test_utils;
+    use std::sync::Arc;
+    use tobj;
+
+    // a closed, outward-winding cube spanning [-1, 1]
+    fn cube(cull: bool) -> Arc<TriangleMesh> {
+        let mut positions = Vec::new();
+        for i in 0..8 {
+            positions.push(if i & 1 == 0 { -1. } else { 1. });
+            positions.push(if i & 2 == 0 { -1. } else { 1. });
+            positions.push(if i & 4 == 0 { -1. } else { 1. });
+        }
+        let quads = [
+            [0, 2, 3, 1], [4, 5, 7, 6], [0, 1, 5, 4],
+            [2, 6, 7, 3], [0, 4, 6, 2], [1, 3, 7, 5],
+        ];
+        let mut indices = Vec::new();
+        for q in &quads {
+            indices.extend_from_slice(&[q[0], q[1], q[2], q[0], q[2], q[3]]);
+        }
+        let model = tobj::Model::new(
+            tobj::Mesh::new(positions, Vec::new(), Vec::new(), indices, None),
+            "cube".to_owned()
+        );
+        let mut mesh = TriangleMesh::from_model(model, test_utils::matte(0.5 as Float, 0.5, 0.5), None);
+        mesh.set_cull_backfaces(cull);
+        Arc::new(mesh)
+    }
+
+    // nearest hit of `ray` over the mesh, along with the count of
+    // triangles culled before testing
+    fn nearest(mesh: &Arc<TriangleMesh>, ray: &RawRay) -> (Option<(Float, Point3f)>, usize) {
+        let mut ray = *ray;
+        let mut hit = None;
+        let mut culled = 0;
+        for t in TriangleMesh::instances(mesh) {
+            if t.culls(ray.kind()) && t.is_backface(&ray) { culled += 1; }
+            if let Some(si) = Composable::intersect_ray(&t, &mut ray) {
+                hit = Some((ray.max_extend(), si.basic.pos));
+            }
+        }
+        (hit, culled)
+    }
+
+    #[test]
+    fn test_cube_from_outside() {
+        let (culled, unculled) = (cube(true), cube(false));
+        let mut rng = StdRng::from_seed(&[20usize][..]);
+        let mut skipped = 0;
+        let mut tested = 0;
+        for _ in 0..256 {
+            let origin = Point3f::from_vec(
+                sample::sample_uniform_sphere(Point2f::new(rng.gen(), rng.gen())) * 4. as Float
+            );
+            let target = Point3f::new(
+                rng.gen_range(-0.9 as Float, 0.9), rng.gen_range(-0.9 as Float, 0.9), rng.gen_range(-0.9 as Float, 0.9)
+            );
+            for &kind in &[RayKind::Camera, RayKind::DiffuseIndirect, RayKind::SpecularIndirect] {
+                let ray = RawRay::from_od(origin, target - origin).with_kind(kind);
+                let (a, n) = nearest(&culled, &ray);
+                let (b, m) = nearest(&unculled, &ray);
+                // same nearest hits, hence the same image
+                assert_eq!(a, b);
+                assert!(a.is_some());
+                assert_eq!(m, 0);
+                skipped += n;
+                tested += 12;
+            }
+        }
+        // opposite faces of the cube face opposite ways, so exactly
+        // half of the triangle tests are skipped
+        assert_eq!(skipped * 2, tested);
+    }
+
+    #[test]
+    fn test_cube_from_inside() {
+        let mesh = cube(true);
+        let origin = Point3f::new(0.2 as Float, -0.3, 0.1);
+        let dir = Vector3f::new(0.3 as Float, 1., -0.2);
+        // back faces are all there is to see from inside
+        let camera = RawRay::from_od(origin, dir).with_kind(RayKind::Camera);
+        assert!(nearest(&mesh, &camera).0.is_none());
+        // while shadow rays are never culled
+        for &kind in &[RayKind::Shadow, RayKind::Any] {
+            let ray = RawRay::from_od(origin, dir).with_kind(kind);
+            assert!(nearest(&mesh, &ray).0.is_some());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_sdf {
+    use super::*;
+    use super::sphere::Sphere;
+    use super::sdf::*;
+    use prelude::*;
+    use spectrum::*;
+    use component::ComponentPointer;
+    use filming::film::Image;
+    use test_utils;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_against_sphere() {
+        let sdf = SdfShape::from_node(&SdfNode::Sphere{radius: 1. as Float});
+        let sphere = Sphere::full(1. as Float);
+        let mut hits = 0;
+        for i in 0..32 {
+            for j in 0..32 {
+                let x = -1.2 as Float + 2.4 as Float * i as Float / 31. as Float;
+                let y = -1.2 as Float + 2.4 as Float * j as Float / 31. as Float;
+                let origin = Point3f::new(x, y, -5. as Float);
+                for dir in &[Vector3f::new(0. as Float, 0., 1.), Vector3f::new(0.1 as Float, -0.05, 1.)] {
+                    // grazing rays are left out, where marching slows down
+                    let closest = origin.to_vec().cross(*dir).magnitude() / dir.magnitude();
+                    if (closest - 1. as Float).abs() < 0.02 as Float { continue; }
+                    let ray = RawRay::from_od(origin, *dir);
+                    let expected = sphere.intersect_ray(&ray);
+                    let marched = sdf.intersect_ray(&ray);
+                    assert_eq!(expected.is_some(), marched.is_some(), "({}, {})", x, y);
+                    assert_eq!(sdf.can_intersect(&ray), marched.is_some());
+                    if let (Some((t0, si0)), Some((t1, si1))) = (expected, marched) {
+                        hits += 1;
+                        assert_relative_eq!(t0, t1, epsilon = 1e-3);
+                        assert_relative_eq!(si0.basic.norm, si1.basic.norm, epsilon = 1e-2);
+                        assert!(si1.basic.norm.dot(si1.basic.wo) > 0. as Float);
+                    }
+                }
+            }
+        }
+        assert!(hits > 800);
+    }
+
+    fn render_blob(max_steps: usize, epsilon: Float) -> Image {
+        let a = SdfNode::Transform{
+            transform: Matrix4f::from_translation(Vector3f::new(-0.5 as Float, 0., 0.)),
+            node: Box::new(SdfNode::Sphere{radius: 0.7 as Float}),
+        };
+        let b = SdfNode::Transform{
+            transform: Matrix4f::from_translation(Vector3f::new(0.5 as Float, 0., 0.)),
+            node: Box::new(SdfNode::Sphere{radius: 0.6 as Float}),
+        };
+        let mut sdf = SdfShape::from_node(&SdfNode::SmoothUnion{
+            a: Box::new(a), b: Box::new(b), k: 0.4 as Float,
+        });
+        sdf.max_steps = max_steps;
+        sdf.epsilon = epsilon;
+        let blob = ShapedPrimitive::new(sdf, test_utils::matte(0.8 as Float, 0.6, 0.4), None);
+        let components: Vec<ComponentPointer> = vec![(Arc::new(blob) as Arc<Composable>).into()];
+        let eye = Point3f::new(0.5 as Float, -1., 3.);
+        let lights: Vec<Arc<Light>> = vec![
+            Arc::new(PointLight::new(eye, RGBSpectrumf::new(9. as Float, 9. as Float, 9. as Float)))
+        ];
+        let scene = Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH)));
+        let sampler = StdStrataSampler::new(1, 1, 4, StdRng::from_seed(&[7usize][..]));
+        let camera = test_utils::camera(48, eye, Point3f::new(0. as Float, 0., 0.), 0.7 as Float);
+        WhittedRenderer::new(sampler, camera, "", 0. as Float).render_image(&scene)
+    }
+
+    #[test]
+    fn test_smooth_union() {
+        let image = render_blob(DEFAULT_MAX_STEPS, DEFAULT_EPSILON);
+        let reference = render_blob(4096, 1e-6 as Float);
+        // silhouettes are left out, where grazing rays either run out of
+        // steps or come within `epsilon` of the surface
+        let lit = |image: &Image, x: u32, y: u32| image[(x, y)].r() > 0.1 as Float;
+        let (mut max, mut sum, mut count) = (0. as Float, 0. as Float, 0);
+        for y in 1..47 {
+            for x in 1..47 {
+                let inside = (y-1..y+2).all(|j| (x-1..x+2).all(|i| lit(&reference, i, j)));
+                if !inside { continue; }
+                // no holes within the blob
+                assert!(image[(x, y)].r() > 0. as Float, "hole at ({}, {})", x, y);
+                let (a, b) = (image[(x, y)], reference[(x, y)]);
+                for &(a, b) in &[(a.r(), b.r()), (a.g(), b.g()), (a.b(), b.b())] {
+                    max = max.max((a - b).abs());
+                    sum += (a - b).abs();
+                    count += 1;
+                }
+            }
+        }
+        assert!(count > 2000);
+        assert!(max < 0.02 as Float, "{}", max);
+        assert!(sum / (count as Float) < 0.002 as Float, "{}", sum / count as Float);
+    }
+}
+
+#[cfg(test)]
+mod test_shadow_terminator {
+    use super::*;
+    use super::triangle::*;
+    use prelude::*;
+    use spectrum::*;
+    use component::ComponentPointer;
+    use filming::film::Image;
+    use test_utils;
+    use std::sync::Arc;
+    use tobj;
+
+    const EYE: [Float; 3] = [0., 0., -4.];
+    const LIGHT: [Float; 3] = [20., 10., -15.];
+
+    // a coarse unit sphere with exact vertex normals
+    fn sphere(offset_shadows: bool) -> Arc<TriangleMesh> {
+        let (nseg, nring) = (12, 8);
+        let mut positions = Vec::new();
+        for j in 0..nring+1 {
+            let theta = float::pi() * j as Float / nring as Float;
+            for i in 0..nseg+1 {
+                let phi = 2. as Float * float::pi() * i as Float / nseg as Float;
+                positions.push((theta.sin() * phi.cos()) as f32);
+                positions.push(theta.cos() as f32);
+                positions.push((theta.sin() * phi.sin()) as f32);
+            }
+        }
+        let mut indices = Vec::new();
+        for j in 0..nring {
+            for i in 0..nseg {
+                let a = (j * (nseg + 1) + i) as u32;
+                let b = a + nseg as u32 + 1;
+                indices.extend_from_slice(&[a, a + 1, b, a + 1, b + 1, b]);
+            }
+        }
+        let model = tobj::Model::new(
+            tobj::Mesh::new(positions.clone(), positions, Vec::new(), indices, None),
+            "sphere".to_owned()
+        );
+        let mut mesh = TriangleMesh::from_model(model, test_utils::matte(0.8 as Float, 0.8, 0.8), None);
+        mesh.set_offset_shadows(offset_shadows);
+        Arc::new(mesh)
+    }
+
+    fn render(offset_shadows: bool) -> (Image, Arc<Camera>) {
+        let mesh = sphere(offset_shadows);
+        let components: Vec<ComponentPointer> = TriangleMesh::instances(&mesh)
+            .into_iter().map(|t| t.into()).collect();
+        let lights: Vec<Arc<Light>> = vec![Arc::new(PointLight::new(
+            Point3f::new(LIGHT[0], LIGHT[1], LIGHT[2]), RGBSpectrumf::new(6000. as Float, 6000., 6000.)
+        ))];
+        let scene = Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH)));
+        let sampler = StdStrataSampler::new(1, 1, 4, StdRng::from_seed(&[7usize][..]));
+        let camera = test_utils::camera(
+            128, Point3f::new(EYE[0], EYE[1], EYE[2]), Point3f::new(0. as Float, 0., 0.), 0.6 as Float
+        );
+        let image = WhittedRenderer::new(sampler, camera.clone(), "", 0. as Float).render_image(&scene);
+        (image, camera)
+    }
+
+    // luminance along the points of the sphere whose normals are
+    // at `cos` to the light, that is just inside the lit side
+    fn profile(image: &Image, camera: &Arc<Camera>, cos: Float) -> Vec<Float> {
+        let l = Vector3f::new(LIGHT[0], LIGHT[1], LIGHT[2]).normalize();
+        let u = l.cross(Vector3f::new(0. as Float, 1., 0.)).normalize();
+        let v = l.cross(u);
+        let eye = Point3f::new(EYE[0], EYE[1], EYE[2]);
+        let sin = (1. as Float - cos * cos).sqrt();
+        let mut ret = Vec::new();
+        for k in 0..720 {
+            let theta = 2. as Float * float::pi() * k as Float / 720. as Float;
+            let n = l * cos + (u * theta.cos() + v * theta.sin()) * sin;
+            let p = Point3f::from_vec(n);
+            // away from the silhouette
+            if n.dot((eye - p).normalize()) < 0.3 as Float { continue; }
+            let raster = camera.evaluate_importance_sampled(p, Point2f::new(0.5 as Float, 0.5)).1;
+            let pixel = image[(raster.x as u32, raster.y as u32)];
+            ret.push(pixel.to_xyz().y);
+        }
+        ret
+    }
+
+    #[test]
+    fn test_offset_shadows() {
+        let (before, camera) = render(false);
+        let (after, _) = render(true);
+        let dark = |p: &[Float]| p.iter().filter(|&&v| v <= 0. as Float).count();
+        for &cos in &[0.15 as Float, 0.2] {
+            let before = profile(&before, &camera, cos);
+            let after = profile(&after, &camera, cos);
+            assert!(before.len() > 200);
+            // facets turned away from the light leave the lit side banded
+            assert!(dark(&before) > 20, "{} dark of {}", dark(&before), before.len());
+            assert_eq!(dark(&after), 0);
+        }
+        // well within the lit side nothing changes
+        let before = profile(&before, &camera, 0.5 as Float);
+        let after = profile(&after, &camera, 0.5 as Float);
+        assert_eq!(before, after);
+    }
+}
+
+#[cfg(test)]
+mod test_obj_streaming {
+    use super::*;
+    use super::triangle::*;
+    use test_utils;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::env;
+    use std::fmt::Write;
+    use std::fs;
+    use std::sync::Arc;
+    use tobj;
+
+    // bytes live and at peak, allocated by the current thread
+    thread_local!(static COUNTS: Cell<(isize, isize)> = const { Cell::new((0, 0)) });
+
+    struct CountingAllocator;
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = COUNTS.try_with(|c| {
+                let (live, peak) = c.get();
+                let live = live + layout.size() as isize;
+                c.set((live, peak.max(live)));
+            });
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            let _ = COUNTS.try_with(|c| {
+                let (live, peak) = c.get();
+                c.set((live - layout.size() as isize, peak));
+            });
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    // `f`'s result, bytes it leaves allocated, and bytes it
+    // allocated beyond those at peak
+    fn measure<T, F: FnOnce() -> T>(f: F) -> (T, isize, isize) {
+        let start = COUNTS.with(|c| {
+            let (live, _) = c.get();
+            c.set((live, live));
+            live
+        });
+        let ret = f();
+        let (live, peak) = COUNTS.with(|c| c.get());
+        (ret, live - start, peak - live)
+    }
+
+    fn transform() -> Matrix4f {
+        Matrix4f::from_translation(Vector3f::new(1. as Float, -2., 3.))
+            * Matrix4f::from_angle_y(Deg(30. as Float))
+            * Matrix4f::from_nonuniform_scale(2. as Float, 1., 0.5)
+    }
+
+    fn by_tobj(path: &::std::path::Path) -> TriangleMesh {
+        let (mut models, _) = tobj::load_obj(path).unwrap();
+        assert_eq!(models.len(), 1);
+        TriangleMesh::from_model_transformed(
+            models.pop().unwrap(), transform(), test_utils::matte(0.5 as Float, 0.5, 0.5), None
+        )
+    }
+
+    fn streamed(path: &::std::path::Path) -> TriangleMesh {
+        TriangleMesh::load_obj_streaming(
+            path, transform(), test_utils::matte(0.5 as Float, 0.5, 0.5), None
+        ).unwrap()
+    }
+
+    #[test]
+    fn test_same_as_tobj() {
+        // vertices used out of order, one unused, quads, and
+        // relative indices
+        let path = env::temp_dir().join("arendur_test_obj_streaming.obj");
+        fs::write(&path, "o fixture\n\
+            v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 5 5 5\nv 0 0 1\n\
+            vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nvt 0.5 0.5\nvt 0.2 0.8\n\
+            vn 0 0 1\nvn 0 0.6 0.8\nvn 0.6 0 0.8\nvn 0 0 1\nvn 1 0 0\nvn 0 1 0\n\
+            f 3/3/3 4/4/4 1/1/1 2/2/2\n\
+            f -1/-1/-1 -6/-6/-6 -5/-5/-5\n\
+            f 2/2/2 6/6/6 3/3/3\n").unwrap();
+        let expected = Arc::new(by_tobj(&path));
+        let mesh = Arc::new(streamed(&path));
+        assert_eq!(mesh.triangle_count(), 4);
+        assert_eq!(mesh.vertex_count(), 5);
+        assert_eq!(mesh.content_hash(), expected.content_hash());
+        assert_eq!(mesh.bounding(), expected.bounding());
+        assert_eq!(mesh.name, expected.name);
+        assert!(mesh.has_normals());
+        for (a, b) in TriangleMesh::instances(&mesh).zip(TriangleMesh::instances(&expected)) {
+            assert_eq!(a.uvs(), b.uvs());
+            // shading normals at the centroid
+            let c = a.x() + ((a.y() - a.x()) + (a.z() - a.x())) / 3. as Float;
+            let ray = RawRay::from_od(c + a.face_normal(), -a.face_normal());
+            let (_, sa) = a.intersect_ray(&ray).unwrap();
+            let (_, sb) = b.intersect_ray(&ray).unwrap();
+            assert_eq!(sa.shading_norm, sb.shading_norm);
+        }
+        // attributes indexed apart from positions are left to `tobj`
+        fs::write(&path, "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nf 1/2 2/3 3/1\n").unwrap();
+        assert!(TriangleMesh::load_obj_streaming(
+            &path, transform(), test_utils::matte(0.5 as Float, 0.5, 0.5), None
+        ).is_err());
+    }
+
+    #[test]
+    fn test_transient_memory() {
+        let n = 200;
+        let mut source = String::new();
+        for y in 0..n + 1 {
+            for x in 0..n + 1 {
+                writeln!(source, "v {} {} {}", x, y, ((x * y) % 7) as f32 * 0.1).unwrap();
+            }
+        }
+        for y in 0..n {
+            for x in 0..n {
+                let i = y * (n + 1) + x + 1;
+                writeln!(source, "f {} {} {} {}", i, i + 1, i + n + 2, i + n + 1).unwrap();
+            }
+        }
+        let path = env::temp_dir().join("arendur_test_obj_streaming_large.obj");
+        fs::write(&path, source).unwrap();
+
+        let (mesh, size, transient) = measure(|| streamed(&path));
+        assert_eq!(mesh.triangle_count(), 2 * n * n);
+        assert!(size as usize >= mesh.memory_estimate() - ::std::mem::size_of::<TriangleMesh>());
+        assert!((transient as Float) < 1.2 as Float * size as Float, "{} transient for {}", transient, size);
+        let (expected, size, tobj_transient) = measure(|| by_tobj(&path));
+        assert_eq!(mesh.content_hash(), expected.content_hash());
+        assert!(tobj_transient > transient, "{} by tobj, {} streamed, for {}", tobj_transient, transient, size);
+    }
 }
@@ -8,20 +8,49 @@
 
 //! Defines triangle mesh and triangle instance
 use geometry::prelude::*;
-use super::Shape;
+use super::{Shape, ShapeSample};
 use std::ops;
+use std::ptr;
+use std::mem;
 use sample::*;
+use sample::distribution::{Distribution1D, Distribution2D};
 use std::sync::Arc;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::str::SplitWhitespace;
 use tobj;
-use lighting::{Light, LightFlag, LightSample, LIGHT_AREA, SampleInfo, PathInfo};
+use lighting::{Light, LightFlag, LightSample, LIGHT_AREA, SampleInfo, PathInfo, EmissionSide};
 use component::prelude::*;
+use component::ComponentPointer;
+use component::cost::CostModel;
+use component::progress;
 use material::Material;
 use texturing::prelude::*;
 use spectrum::prelude::*;
-use sample;
 
 pub type Model = tobj::Model;
 
+/// How a mesh chooses its shading normals
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum ShadingMode {
+    /// interpolate vertex normals if any
+    Smooth,
+    /// always shade with the geometric normal
+    Faceted,
+    /// interpolate vertex normals, falling back to the geometric
+    /// normal where they deviate from it by at least this angle in degrees
+    AutoByAngle(Float),
+}
+
+impl Default for ShadingMode {
+    #[inline]
+    fn default() -> ShadingMode {
+        ShadingMode::Smooth
+    }
+}
+
 /// A triangle mesh
 pub struct TriangleMesh {
     vertices: Vec<Point3f>,
@@ -32,6 +61,11 @@ pub struct TriangleMesh {
     bbox: BBox3f,
     material: Arc<Material>,
     lighting_profile: Option<Arc<Texture<Texel=RGBSpectrumf>>>,
+    emission_side: EmissionSide,
+    shading: ShadingMode,
+    visibility: VisibilityFlags,
+    cull_backfaces: bool,
+    offset_shadows: bool,
     pub name: String,
 }
 
@@ -48,11 +82,226 @@ impl TriangleMesh {
         self.vertices.len()
     }
 
+    /// approximate memory taken by the mesh's geometry in bytes
+    pub fn memory_estimate(&self) -> usize {
+        mem::size_of::<TriangleMesh>()
+            + self.vertices.capacity() * mem::size_of::<Point3f>()
+            + self.indices.capacity() * mem::size_of::<usize>()
+            + self.tangents.as_ref().map_or(0, |v| v.capacity() * mem::size_of::<Vector3f>())
+            + self.normals.as_ref().map_or(0, |v| v.capacity() * mem::size_of::<Vector3f>())
+            + self.uvs.as_ref().map_or(0, |v| v.capacity() * mem::size_of::<Point2f>())
+            + self.name.capacity()
+    }
+
+    /// hash over vertex positions and indices, stable across runs
+    /// and platforms, for detecting geometry changes
+    pub fn content_hash(&self) -> u64 {
+        // FNV-1a
+        let mut hash = 0xcbf29ce484222325u64;
+        {
+            let mut feed = |word: u64| {
+                for i in 0..8 {
+                    hash ^= (word >> (8 * i)) & 0xff;
+                    hash = hash.wrapping_mul(0x100000001b3);
+                }
+            };
+            feed(self.vertices.len() as u64);
+            for v in &self.vertices {
+                feed(v.x.to_bits() as u64);
+                feed(v.y.to_bits() as u64);
+                feed(v.z.to_bits() as u64);
+            }
+            feed(self.indices.len() as u64);
+            for &i in &self.indices {
+                feed(i as u64);
+            }
+        }
+        hash
+    }
+
     /// bounding box, in local frame
     pub fn bounding(&self) -> BBox3f {
         self.bbox
     }
 
+    /// test if the mesh emits light
+    #[inline]
+    pub fn is_emissive(&self) -> bool {
+        self.lighting_profile.is_some()
+    }
+
+    /// which side(s) the mesh emits light from
+    #[inline]
+    pub fn emission_side(&self) -> EmissionSide {
+        self.emission_side
+    }
+
+    /// set which side(s) the mesh emits light from
+    #[inline]
+    pub fn set_emission_side(&mut self, side: EmissionSide) {
+        self.emission_side = side;
+    }
+
+    /// how shading normals are chosen
+    #[inline]
+    pub fn shading_mode(&self) -> ShadingMode {
+        self.shading
+    }
+
+    /// set how shading normals are chosen
+    #[inline]
+    pub fn set_shading_mode(&mut self, shading: ShadingMode) {
+        self.shading = shading;
+    }
+
+    /// kinds of rays the mesh can be hit by
+    #[inline]
+    pub fn visibility(&self) -> VisibilityFlags {
+        self.visibility
+    }
+
+    /// set kinds of rays the mesh can be hit by
+    #[inline]
+    pub fn set_visibility(&mut self, visibility: VisibilityFlags) {
+        self.visibility = visibility;
+    }
+
+    /// test if camera and indirect rays skip the mesh's back faces
+    #[inline]
+    pub fn cull_backfaces(&self) -> bool {
+        self.cull_backfaces
+    }
+
+    /// set if camera and indirect rays skip the mesh's back faces,
+    /// namely faces whose vertices wind clockwise as seen by the ray.
+    ///
+    /// Only meant for closed opaque meshes. Shadow rays, and rays
+    /// against an emissive mesh, are never culled.
+    #[inline]
+    pub fn set_cull_backfaces(&mut self, cull_backfaces: bool) {
+        self.cull_backfaces = cull_backfaces;
+    }
+
+    /// test if shadow rays leave from points shifted off the surface
+    #[inline]
+    pub fn offset_shadows(&self) -> bool {
+        self.offset_shadows
+    }
+
+    /// set if shadow rays leave from points shifted off the flat
+    /// triangles onto the smooth surface the vertex normals describe,
+    /// with bsdfs telling reflection from transmission by the shading
+    /// normal rather than the triangle's.
+    ///
+    /// This hides the stepped shadow terminators of coarse meshes with
+    /// interpolated normals, where points facing the light by their
+    /// shading normals are shadowed by their own or neighbouring
+    /// triangles. Meshes without vertex normals or with faceted
+    /// shading are not affected.
+    #[inline]
+    pub fn set_offset_shadows(&mut self, offset_shadows: bool) {
+        self.offset_shadows = offset_shadows;
+    }
+
+    /// test if the mesh comes with vertex normals
+    #[inline]
+    pub fn has_normals(&self) -> bool {
+        self.normals.is_some()
+    }
+
+    /// test if the mesh comes with texture coordinates
+    #[inline]
+    pub fn has_uvs(&self) -> bool {
+        self.uvs.is_some()
+    }
+
+    /// generate area-weighted vertex normals, replacing existing ones.
+    /// Vertices are split where adjacent faces meet at an angle above
+    /// `angle_threshold` degrees, so that hard edges stay hard.
+    pub fn generate_normals(&mut self, angle_threshold: Float) {
+        let cos_threshold = angle_threshold.to_radians().cos();
+        let ntri = self.triangle_count();
+        let face_normals: Vec<Vector3f> = (0..ntri).map(|f| {
+            let p0 = self.vertices[self.indices[3*f]];
+            let p1 = self.vertices[self.indices[3*f+1]];
+            let p2 = self.vertices[self.indices[3*f+2]];
+            (p1 - p0).cross(p2 - p0) * 0.5 as Float
+        }).collect();
+        // faces around each position, vertices split by uvs are welded here
+        let key = |p: Point3f| {
+            ((p.x + 0. as Float).to_bits(), (p.y + 0. as Float).to_bits(), (p.z + 0. as Float).to_bits())
+        };
+        let mut adjacency = HashMap::new();
+        for (corner, &vidx) in self.indices.iter().enumerate() {
+            adjacency.entry(key(self.vertices[vidx])).or_insert_with(Vec::new).push(corner / 3);
+        }
+
+        let mut vertices = Vec::with_capacity(self.vertices.len());
+        let mut normals = Vec::with_capacity(self.vertices.len());
+        let mut uvs = self.uvs.as_ref().map(|uvs| Vec::with_capacity(uvs.len()));
+        let mut tangents = self.tangents.as_ref().map(|ts| Vec::with_capacity(ts.len()));
+        let mut split = HashMap::new();
+        for corner in 0..self.indices.len() {
+            let vidx = self.indices[corner];
+            let nf = face_normals[corner / 3];
+            let faces = &adjacency[&key(self.vertices[vidx])];
+            let mut n = Vector3f::zero();
+            for &g in faces {
+                let ng = face_normals[g];
+                if ng.magnitude2() == 0. as Float { continue; }
+                if nf.magnitude2() == 0. as Float
+                    || nf.normalize().dot(ng.normalize()) >= cos_threshold {
+                    n += ng;
+                }
+            }
+            let n = if n.magnitude2() > 0. as Float {
+                n.normalize()
+            } else {
+                Vector3f::new(0. as Float, 0. as Float, 1. as Float)
+            };
+            let (ouvs, otangents, overtices) = (&self.uvs, &self.tangents, &self.vertices);
+            self.indices[corner] = *split.entry(
+                (vidx, n.x.to_bits(), n.y.to_bits(), n.z.to_bits())
+            ).or_insert_with(|| {
+                vertices.push(overtices[vidx]);
+                normals.push(n);
+                if let (Some(uvs), &Some(ref ouvs)) = (uvs.as_mut(), ouvs) {
+                    uvs.push(ouvs[vidx]);
+                }
+                if let (Some(tangents), &Some(ref otangents)) = (tangents.as_mut(), otangents) {
+                    tangents.push(otangents[vidx]);
+                }
+                vertices.len() - 1
+            });
+        }
+        self.vertices = vertices;
+        self.normals = Some(normals);
+        self.uvs = uvs;
+        self.tangents = tangents;
+    }
+
+    /// iterate through triangles of a shared mesh
+    #[inline]
+    pub fn instances(mesh: &Arc<TriangleMesh>) -> TriangleInstance {
+        TriangleInstance{
+            mesh: Arc::clone(mesh),
+            idx: 0,
+        }
+    }
+
+    /// the `idx`th triangle of a shared mesh, if any
+    #[inline]
+    pub fn instance(mesh: &Arc<TriangleMesh>, idx: usize) -> Option<TriangleInstance> {
+        if idx < mesh.triangle_count() {
+            Some(TriangleInstance{
+                mesh: Arc::clone(mesh),
+                idx: 3 * idx,
+            })
+        } else {
+            None
+        }
+    }
+
     // /// load meshes from an `.obj` file
     // #[inline]
     // pub fn load_from_file<P>(file_name: &P) -> Result<Vec<TriangleMesh>, tobj::LoadError>
@@ -113,7 +362,12 @@ impl TriangleMesh {
         let name = model.name;
         TriangleMesh{
             vertices, indices, tangents, normals, 
-            uvs, bbox, name, material, lighting_profile
+            uvs, bbox, name, material, lighting_profile,
+            emission_side: Default::default(),
+            shading: Default::default(),
+            visibility: Default::default(),
+            cull_backfaces: false,
+            offset_shadows: false,
         }
     }
 
@@ -141,7 +395,8 @@ impl TriangleMesh {
             i as usize
         ).collect();
         let normals = if model.mesh.normals.len() > 0 {
-            Some(map_f32s_to_vec(&model.mesh.normals, |n| transform.transform_norm(n)))
+            let normal_matrix = transform.normal_matrix();
+            Some(map_f32s_to_vec(&model.mesh.normals, |n| normal_matrix.transform_vector(n).normalize()))
         } else {
             None
         };
@@ -155,9 +410,245 @@ impl TriangleMesh {
         let name = model.name;
         TriangleMesh{
             vertices, indices, tangents, normals, 
-            uvs, bbox, name, material, lighting_profile
+            uvs, bbox, name, material, lighting_profile,
+            emission_side: Default::default(),
+            shading: Default::default(),
+            visibility: Default::default(),
+            cull_backfaces: false,
+            offset_shadows: false,
         }
     }
+
+    /// load all faces of the `.obj` file at `path` into a single mesh,
+    /// applying `transform` to it, without the transient copies of
+    /// `tobj` that take about thrice the memory of the final mesh.
+    ///
+    /// The file is read twice, counting elements to allocate the final
+    /// arrays exactly, then filling them, after which vertices are put
+    /// into the order faces first use them. Materials, objects and groups
+    /// are ignored. Faces must index texture coordinates and normals,
+    /// if any, as they index positions, or `FaceParseError` is returned,
+    /// such meshes being left to `tobj`. The result is the mesh
+    /// `from_model_transformed` builds out of `tobj`'s single model
+    /// of the file otherwise.
+    pub fn load_obj_streaming<P: AsRef<Path> + ?Sized>(
+        path: &P,
+        transform: Matrix4f,
+        material: Arc<Material>,
+        lighting_profile: Option<Arc<Texture<Texel=RGBSpectrumf>>>
+    ) -> Result<TriangleMesh, tobj::LoadError> {
+        TriangleMesh::load_obj_streaming_with_progress(
+            path, transform, material, lighting_profile, &mut progress::ignore_progress
+        )
+    }
+
+    /// `load_obj_streaming`, reporting to `progress` the bytes parsed
+    /// over both passes, totaling twice the size of the file
+    pub fn load_obj_streaming_with_progress<P: AsRef<Path> + ?Sized>(
+        path: &P,
+        transform: Matrix4f,
+        material: Arc<Material>,
+        lighting_profile: Option<Arc<Texture<Texel=RGBSpectrumf>>>,
+        progress: &mut FnMut(LoadProgress)
+    ) -> Result<TriangleMesh, tobj::LoadError> {
+        let path = path.as_ref();
+        let open = || File::open(path).map(BufReader::new).map_err(|_| tobj::LoadError::OpenFileFailed);
+        let mut line = String::new();
+        let size = path.metadata().map_err(|_| tobj::LoadError::OpenFileFailed)?.len() as usize;
+        let mut parsed = ParseProgress::new(2 * size, progress);
+
+        // counting pass
+        let (mut nv, mut nvt, mut nvn, mut ntri) = (0usize, 0usize, 0usize, 0usize);
+        let mut reader = open()?;
+        while parsed.read_line(&mut reader, &mut line)? {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("v") => nv += 1,
+                Some("vt") => nvt += 1,
+                Some("vn") => nvn += 1,
+                Some("f") => ntri += words.count().saturating_sub(2),
+                _ => (),
+            }
+        }
+
+        // filling pass, straight into the final arrays
+        let mut vertices = Vec::with_capacity(nv);
+        let mut uvs = Vec::with_capacity(nvt);
+        let mut normals = Vec::with_capacity(nvn);
+        let normal_matrix = transform.normal_matrix();
+        let mut indices = Vec::with_capacity(3 * ntri);
+        let (mut has_uvs, mut has_normals) = (nvt > 0, nvn > 0);
+        let mut name = None;
+        let mut reader = open()?;
+        while parsed.read_line(&mut reader, &mut line)? {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("v") => {
+                    let v = parse_obj_floats(words, 3).ok_or(tobj::LoadError::PositionParseError)?;
+                    vertices.push(transform.transform_point(Point3f::new(v[0], v[1], v[2])));
+                },
+                Some("vt") => {
+                    let v = parse_obj_floats(words, 2).ok_or(tobj::LoadError::TexcoordParseError)?;
+                    uvs.push(Point2f::new(v[0], v[1]));
+                },
+                Some("vn") => {
+                    let v = parse_obj_floats(words, 3).ok_or(tobj::LoadError::NormalParseError)?;
+                    normals.push(normal_matrix.transform_vector(Vector3f::new(v[0], v[1], v[2])).normalize());
+                },
+                Some("f") => {
+                    let (mut corners, mut first, mut last) = (0, 0, 0);
+                    for word in words {
+                        let mut parts = word.split('/');
+                        let v = parts.next().and_then(|i| parse_obj_index(i, vertices.len()))
+                            .ok_or(tobj::LoadError::FaceParseError)?;
+                        has_uvs &= indexed_alike(parts.next(), uvs.len(), v)?;
+                        has_normals &= indexed_alike(parts.next(), normals.len(), v)?;
+                        // triangulated as a fan, as `tobj` does
+                        if corners == 0 { first = v; }
+                        if corners >= 2 {
+                            indices.push(first);
+                            indices.push(last);
+                            indices.push(v);
+                        }
+                        last = v;
+                        corners += 1;
+                    }
+                },
+                Some("o") | Some("g") => if name.is_none() {
+                    name = Some(words.next().unwrap_or("").to_owned());
+                },
+                _ => (),
+            }
+        }
+        parsed.finish();
+        if indices.is_empty() { return Err(tobj::LoadError::GenericFailure); }
+        has_uvs = has_uvs && uvs.len() == vertices.len();
+        has_normals = has_normals && normals.len() == vertices.len();
+        if !has_uvs { uvs = Vec::new(); }
+        if !has_normals { normals = Vec::new(); }
+
+        // index pass, numbering vertices by first use, then moving
+        // each into its place, unused ones last
+        let mut order = vec![usize::max_value(); vertices.len()];
+        let mut used = 0;
+        for i in &mut indices {
+            if order[*i] == usize::max_value() {
+                order[*i] = used;
+                used += 1;
+            }
+            *i = order[*i];
+        }
+        let mut unused = used;
+        for o in &mut order {
+            if *o == usize::max_value() {
+                *o = unused;
+                unused += 1;
+            }
+        }
+        for i in 0..order.len() {
+            while order[i] != i {
+                let j = order[i];
+                vertices.swap(i, j);
+                if has_uvs { uvs.swap(i, j); }
+                if has_normals { normals.swap(i, j); }
+                order.swap(i, j);
+            }
+        }
+        let transient = order.capacity() * mem::size_of::<usize>() + line.capacity();
+        drop(order);
+        vertices.truncate(used);
+        uvs.truncate(used);
+        normals.truncate(used);
+
+        let bbox = vertices.iter().skip(1).fold(BBox3f::new(vertices[0], vertices[0]), |b, &p| b.extend(p));
+        let mesh = TriangleMesh{
+            vertices, indices, bbox, material, lighting_profile,
+            tangents: None,
+            normals: if has_normals { Some(normals) } else { None },
+            uvs: if has_uvs { Some(uvs) } else { None },
+            name: name.unwrap_or_else(|| "unnamed_object".to_owned()),
+            emission_side: Default::default(),
+            shading: Default::default(),
+            visibility: Default::default(),
+            cull_backfaces: false,
+            offset_shadows: false,
+        };
+        info!(
+            "streamed {} triangles from {}, taking {} bytes with at most {} more in transit",
+            mesh.triangle_count(), path.display(), mesh.memory_estimate(), transient
+        );
+        Ok(mesh)
+    }
+}
+
+// read the next line of an `.obj` into `line`, returning if any
+fn read_obj_line<R: BufRead>(reader: &mut R, line: &mut String) -> Result<bool, tobj::LoadError> {
+    line.clear();
+    reader.read_line(line).map(|n| n > 0).map_err(|_| tobj::LoadError::ReadError)
+}
+
+// bytes read out of `total`, reported every `PROGRESS_STRIDE` of them
+struct ParseProgress<'a> {
+    done: usize,
+    reported: usize,
+    total: usize,
+    progress: &'a mut FnMut(LoadProgress),
+}
+
+impl<'a> ParseProgress<'a> {
+    fn new(total: usize, progress: &'a mut FnMut(LoadProgress)) -> ParseProgress<'a> {
+        let mut ret = ParseProgress{ done: 0, reported: 0, total: total, progress: progress };
+        ret.report();
+        ret
+    }
+
+    fn read_line<R: BufRead>(&mut self, reader: &mut R, line: &mut String) -> Result<bool, tobj::LoadError> {
+        let ret = read_obj_line(reader, line)?;
+        // the file might have grown since its size was taken
+        self.done = (self.done + line.len()).min(self.total);
+        if self.done - self.reported >= progress::PROGRESS_STRIDE {
+            self.report();
+        }
+        Ok(ret)
+    }
+
+    fn finish(&mut self) {
+        self.done = self.total;
+        self.report();
+    }
+
+    fn report(&mut self) {
+        self.reported = self.done;
+        (self.progress)(LoadProgress::new(LoadPhase::Parsing, self.done, self.total, self.done as u64));
+    }
+}
+
+// the first `n` floats of `words`, parsed as `tobj` does
+fn parse_obj_floats(words: SplitWhitespace, n: usize) -> Option<[Float; 3]> {
+    let mut ret = [0. as Float; 3];
+    let mut count = 0;
+    for word in words.take(n) {
+        ret[count] = word.parse::<f32>().ok()? as Float;
+        count += 1;
+    }
+    if count == n { Some(ret) } else { None }
+}
+
+// if a face corner of position index `v` comes with the attribute
+// index `word` among `count` elements, which must equal `v`
+fn indexed_alike(word: Option<&str>, count: usize, v: usize) -> Result<bool, tobj::LoadError> {
+    match word {
+        None | Some("") => Ok(false),
+        Some(i) if parse_obj_index(i, count) == Some(v) => Ok(true),
+        _ => Err(tobj::LoadError::FaceParseError),
+    }
+}
+
+// a one-based or negative, relative index among `count` elements
+fn parse_obj_index(word: &str, count: usize) -> Option<usize> {
+    let i: isize = word.parse().ok()?;
+    let i = if i < 0 { i + count as isize } else { i - 1 };
+    if i >= 0 && (i as usize) < count { Some(i as usize) } else { None }
 }
 
 fn map_f32s_to_vec<F>(src: &[f32], mut f: F) -> Vec<Vector3f>
@@ -264,6 +755,18 @@ pub struct TriangleInstance {
 }
 
 impl TriangleInstance {
+    /// the mesh the triangle is part of
+    #[inline]
+    pub fn mesh(&self) -> &Arc<TriangleMesh> {
+        &self.mesh
+    }
+
+    /// index of the triangle in its mesh
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.idx / 3
+    }
+
     /// return points in local frame
     #[inline]
     pub fn x(&self) -> Point3f {
@@ -296,6 +799,103 @@ impl TriangleInstance {
         )}
     }
 
+    /// test if rays of `kind` skip the triangle's back face
+    #[inline]
+    pub fn culls(&self, kind: RayKind) -> bool {
+        self.mesh.cull_backfaces && !self.mesh.is_emissive() && match kind {
+            RayKind::Camera | RayKind::DiffuseIndirect | RayKind::SpecularIndirect => true,
+            RayKind::Any | RayKind::Shadow => false,
+        }
+    }
+
+    /// test if `ray` approaches the triangle from its back, namely
+    /// the side its vertices wind clockwise as seen from
+    #[inline]
+    pub fn is_backface(&self, ray: &RawRay) -> bool {
+        (self.y() - self.x()).cross(self.z() - self.x()).dot(ray.direction()) > 0. as Float
+    }
+
+    /// geometric normal, facing the same side as the shading normals if any
+    #[inline]
+    pub fn face_normal(&self) -> Vector3f {
+        let n = (self.y() - self.x()).cross(self.z() - self.x()).normalize();
+        if let Some(ref normals) = self.mesh.normals {
+            let ns = normals[self.vidx(0)] + normals[self.vidx(1)] + normals[self.vidx(2)];
+            if n.dot(ns) < 0. as Float { return -n; }
+        }
+        n
+    }
+
+    /// barycentric coordinates of `p` projected onto the triangle's plane,
+    /// ordered as the vertices
+    pub fn barycentric(&self, p: Point3f) -> Vector3f {
+        let (e1, e2, ep) = (self.y() - self.x(), self.z() - self.x(), p - self.x());
+        let (d11, d12, d22) = (e1.dot(e1), e1.dot(e2), e2.dot(e2));
+        let (dp1, dp2) = (ep.dot(e1), ep.dot(e2));
+        let denom = d11 * d22 - d12 * d12;
+        if denom == 0. as Float {
+            return Vector3f::new(1. as Float, 0. as Float, 0. as Float);
+        }
+        let b1 = (d22 * dp1 - d12 * dp2) / denom;
+        let b2 = (d11 * dp2 - d12 * dp1) / denom;
+        Vector3f::new(1. as Float - b1 - b2, b1, b2)
+    }
+
+    /// surface interaction at barycentric coordinates `b`, for
+    /// evaluating textures without re-intersecting the triangle
+    fn interaction_at(&self, b: Vector3f, wo: Vector3f) -> SurfaceInteraction {
+        let (p0, p1, p2) = (self.x().to_vec(), self.y().to_vec(), self.z().to_vec());
+        let uvs = self.uvs();
+        let pos = Point3f::from_vec(b.x * p0 + b.y * p1 + b.z * p2);
+        let uv = Point2f::from_vec(b.x * uvs.0.to_vec() + b.y * uvs.1.to_vec() + b.z * uvs.2.to_vec());
+        let (dpdu, dpdv) = TriangleInstance::computedpduv(p0, p1, p2, uvs);
+        SurfaceInteraction::new(
+            pos, Vector3f::zero(), wo, uv,
+            DuvInfo{
+                dpdu: dpdu,
+                dpdv: dpdv,
+                dndu: Vector3f::zero(),
+                dndv: Vector3f::zero(),
+            }
+        )
+    }
+
+    /// surface interaction at barycentric coordinates `b` seen from `wo`,
+    /// shaded and attributed to the triangle as if hit there by a ray
+    pub fn surface_at(&self, b: Vector3f, wo: Vector3f) -> SurfaceInteraction {
+        let (p0, p1, p2) = (self.x().to_vec(), self.y().to_vec(), self.z().to_vec());
+        let mut si = self.interaction_at(b, wo);
+        si.basic.pos_err = float::eb_term(7. as Float) * Vector3f::new(
+            (b.x*p0.x).abs() + (b.y*p1.x).abs() + (b.z*p2.x).abs(),
+            (b.x*p0.y).abs() + (b.y*p1.y).abs() + (b.z*p2.y).abs(),
+            (b.x*p0.z).abs() + (b.y*p1.z).abs() + (b.z*p2.z).abs()
+        );
+        let dpdu = si.duv.dpdu;
+        si.set_shading(self.compute_shading_at(b, dpdu), true);
+        if self.mesh.offset_shadows {
+            si.shadow_pos = self.shadow_position(b, si.basic.pos);
+        }
+        si.set_primitive(self);
+        si
+    }
+
+    /// `pos` at barycentric coordinates `b`, moved out of the tangent
+    /// planes of the vertex normals it lies below, blended by `b`, as in
+    /// "Hacking the Shadow Terminator" by Hanika
+    fn shadow_position(&self, b: Vector3f, pos: Point3f) -> Option<Point3f> {
+        let normals = match (self.mesh.shading, &self.mesh.normals) {
+            (ShadingMode::Faceted, _) | (_, &None) => return None,
+            (_, &Some(ref normals)) => normals,
+        };
+        let mut offset = Vector3f::zero();
+        for i in 0..3 {
+            let n = normals[self.vidx(i)].normalize();
+            let below = (pos - self[i]).dot(n).min(0. as Float);
+            offset -= n * (below * b[i]);
+        }
+        Some(pos + offset)
+    }
+
     /// return vertice indices in the parent mesh
     #[inline]
     pub fn vidx(&self, idx: usize) -> usize {
@@ -337,39 +937,45 @@ impl TriangleInstance {
         let p1 = self.y();
         let p2 = self.z();
 
-        let (shading_normal, dndu, dndv) = if let Some(ref normals) = self.mesh.normals {
-            let n0 = normals[self.vidx(0)];
-            let n1 = normals[self.vidx(1)];
-            let n2 = normals[self.vidx(2)];
-            let surface_normal = (b.x * n0 + b.y * n1 + b.z * n2).normalize();
-            let uvs = self.uvs();
-            let (dndu, dndv) = TriangleInstance::computedpduv(n0, n1, n2, uvs);
-            (surface_normal, dndu, dndv)
-        } else {(
+        let interpolated = match (self.mesh.shading, &self.mesh.normals) {
+            (ShadingMode::Faceted, _) | (_, &None) => None,
+            (mode, &Some(ref normals)) => {
+                let n0 = normals[self.vidx(0)];
+                let n1 = normals[self.vidx(1)];
+                let n2 = normals[self.vidx(2)];
+                let surface_normal = (b.x * n0 + b.y * n1 + b.z * n2).normalize();
+                let deviates = if let ShadingMode::AutoByAngle(angle) = mode {
+                    let cos = float::clamp(surface_normal.dot(self.face_normal()), -1. as Float, 1. as Float);
+                    cos.acos() >= angle.to_radians()
+                } else {
+                    false
+                };
+                if deviates {
+                    None
+                } else {
+                    let uvs = self.uvs();
+                    let (dndu, dndv) = TriangleInstance::computedpduv(n0, n1, n2, uvs);
+                    Some((surface_normal, dndu, dndv))
+                }
+            },
+        };
+        let (shading_normal, dndu, dndv) = interpolated.unwrap_or_else(|| (
             (p2 - p0).cross(p1 - p0).normalize(),
             Vector3f::zero(),
             Vector3f::zero(),
-        )};
+        ));
 
-        let mut shading_tangent = if let Some(ref tangents) = self.mesh.tangents {
-            (b.x * tangents[self.vidx(0)] + b.y * tangents[self.vidx(1)] + b.z * tangents[self.vidx(2)]).normalize()
+        let shading_tangent = if let Some(ref tangents) = self.mesh.tangents {
+            b.x * tangents[self.vidx(0)] + b.y * tangents[self.vidx(1)] + b.z * tangents[self.vidx(2)]
         } else {
-            dpdu.normalize()
+            dpdu
         };
 
-        let mut shading_bitangent = shading_tangent.cross(shading_normal);
-        if shading_bitangent.magnitude2() > (0.0 as Float) {
-            shading_bitangent = shading_bitangent.normalize();
-            shading_tangent = shading_bitangent.cross(shading_normal);
-        } else {
-            let tbt = normal::get_basis_from(shading_normal);
-            shading_tangent = tbt.0;
-            shading_bitangent = tbt.1;
-        };
+        let frame = Frame::new(shading_normal, shading_tangent);
 
         DuvInfo {
-            dpdu: shading_tangent,
-            dpdv: shading_bitangent,
+            dpdu: frame.s,
+            dpdv: frame.t,
             dndu: dndu,
             dndv: dndv,
         }
@@ -386,15 +992,16 @@ impl ops::Index<usize> for TriangleInstance {
     }
 }
 
-impl Shape for TriangleInstance {
-    #[inline]
-    fn bbox_local(&self) -> BBox3f {
-        let bbox = BBox3f::new(self.x(), self.y());
-        bbox.extend(self.z())
+impl TriangleInstance {
+    /// intersect `ray`, rejecting back face hits early if `cull`
+    fn intersect_culled(&self, ray: &RawRay, cull: bool) -> Option<(Float, SurfaceInteraction)> {
+        let (t, b0, b1, b2) = self.hit_culled(ray, cull)?;
+        Some((t, self.interaction_hit(ray, b0, b1, b2)))
     }
 
-    #[inline]
-    fn intersect_ray(&self, ray: &RawRay) -> Option<(Float, SurfaceInteraction)> {
+    /// distance and barycentrics of the hit of `ray`, as `intersect_culled`
+    /// finds, without computing its interaction
+    fn hit_culled(&self, ray: &RawRay, cull: bool) -> Option<(Float, Float, Float, Float)> {
         let p0 = self.x();
         let p1 = self.y();
         let p2 = self.z();
@@ -410,15 +1017,19 @@ impl Shape for TriangleInstance {
         }
         let det = e0 + e1 + e2;
         if det == ZERO { return None; }
+        // `det` is the winding normal dotted with the permuted direction
+        // over its z component, which `shear.z` is the reciprocal of
+        if cull && det * stc.shear.z > ZERO { return None; }
 
         p0t.z *= stc.shear.z;
         p1t.z *= stc.shear.z;
         p2t.z *= stc.shear.z;
 
         let tscaled = e0 * p0t.z + e1 * p1t.z + e2 * p2t.z;
-        if det < ZERO && (tscaled >= ZERO || tscaled < ray.max_extend() * det) {
+        let tmin = ray.min_extend() * det;
+        if det < ZERO && (tscaled >= tmin || tscaled < ray.max_extend() * det) {
             return None;
-        } else if det > ZERO && (tscaled <= ZERO || tscaled > ray.max_extend() * det) {
+        } else if det > ZERO && (tscaled <= tmin || tscaled > ray.max_extend() * det) {
             return None;
         }
 
@@ -449,11 +1060,15 @@ impl Shape for TriangleInstance {
         ) * inv_det.abs();
 
         if t <= delta_t { return None; }
+        Some((t, b0, b1, b2))
+    }
 
+    // the interaction at barycentrics `b0`, `b1` and `b2`, hit by `ray`
+    fn interaction_hit(&self, ray: &RawRay, b0: Float, b1: Float, b2: Float) -> SurfaceInteraction {
         let uvs = self.uvs();
-        let p0 = p0.to_vec();
-        let p1 = p1.to_vec();
-        let p2 = p2.to_vec();
+        let p0 = self.x().to_vec();
+        let p1 = self.y().to_vec();
+        let p2 = self.z().to_vec();
 
         let phit = Point3f::from_vec(b0 * p0 + b1 * p1 + b2 * p2);
         let perr = float::eb_term(7. as Float) * Vector3f::new(
@@ -480,18 +1095,34 @@ impl Shape for TriangleInstance {
         surface_interaction.set_shading(
             self.compute_shading_at(Vector3f::new(b0, b1, b2), dpdu), true
         );
-        Some((t, surface_interaction))
+        if self.mesh.offset_shadows {
+            surface_interaction.shadow_pos = self.shadow_position(Vector3f::new(b0, b1, b2), phit);
+        }
+        surface_interaction
+    }
+}
+
+impl Shape for TriangleInstance {
+    #[inline]
+    fn bbox_local(&self) -> BBox3f {
+        let bbox = BBox3f::new(self.x(), self.y());
+        bbox.extend(self.z())
+    }
+
+    #[inline]
+    fn intersect_ray(&self, ray: &RawRay) -> Option<(Float, SurfaceInteraction)> {
+        self.intersect_culled(ray, false)
     }
 
     #[inline]
     fn surface_area(&self) -> Float {
         let a = self.x() - self.z();
-        let b = self.x() - self.z();
+        let b = self.y() - self.z();
         (0.5 as Float) * (a.cross(b).magnitude())
     }
 
     #[inline]
-    fn sample(&self, sample: Point2f) -> (Point3f, Vector3f, Float) {
+    fn sample(&self, sample: Point2f) -> ShapeSample {
         let barycentrc = sample_uniform_triangle(sample);
         let p = barycentrc.x * self.x().to_vec() + barycentrc.y * self.y().to_vec() + (1. as Float - barycentrc.x - barycentrc.y) * self.z().to_vec();
         let p = Point3f::from_vec(p);
@@ -500,7 +1131,16 @@ impl Shape for TriangleInstance {
         } else {
             (self.y() - self.x()).cross(self.z() - self.x())
         };
-        (p, n.normalize(), 1. as Float / self.surface_area())
+        let uvs = self.uvs();
+        let uv = Point2f::from_vec(
+            barycentrc.x * uvs.0.to_vec() + barycentrc.y * uvs.1.to_vec() + barycentrc.z * uvs.2.to_vec()
+        );
+        ShapeSample{
+            pos: p,
+            norm: n.normalize(),
+            uv: uv,
+            pdf: 1. as Float / self.surface_area(),
+        }
     }
 }
 
@@ -512,7 +1152,8 @@ impl Composable for TriangleInstance {
 
     #[inline]
     fn intersect_ray(&self, ray: &mut RawRay) -> Option<SurfaceInteraction> {
-        let r = Shape::intersect_ray(self, ray);
+        if !ray.kind().sees(self.mesh.visibility) { return None; }
+        let r = self.intersect_culled(ray, self.culls(ray.kind()));
         if let Some((t, mut si)) = r {
             ray.set_max_extend(t);
             si.set_primitive(self);
@@ -522,9 +1163,16 @@ impl Composable for TriangleInstance {
         }
     }
 
+    #[inline]
+    fn intersect_t(&self, ray: &RawRay) -> Option<Float> {
+        if !ray.kind().sees(self.mesh.visibility) { return None; }
+        self.hit_culled(ray, self.culls(ray.kind())).map(|hit| hit.0)
+    }
+
     #[inline]
     fn can_intersect(&self, ray: &RawRay) -> bool {
-        Shape::can_intersect(self, ray)
+        ray.kind().sees(self.mesh.visibility)
+            && self.hit_culled(ray, self.culls(ray.kind())).is_some()
     }
 
     #[inline]
@@ -533,8 +1181,20 @@ impl Composable for TriangleInstance {
     }
 
     #[inline]
-    fn intersection_cost(&self) -> Float {
-        3.0 as Float
+    fn intersection_cost(&self, costs: &CostModel) -> Float {
+        costs.triangle
+    }
+
+    /// the instance itself, plus an even share of its mesh
+    #[inline]
+    fn memory_estimate(&self) -> usize {
+        mem::size_of::<TriangleInstance>()
+            + self.mesh.memory_estimate() / self.mesh.triangle_count().max(1)
+    }
+
+    #[inline]
+    fn visit_primitives(&self, f: &mut FnMut(&Primitive)) {
+        f(self)
     }
 }
 
@@ -552,12 +1212,15 @@ impl Light for TriangleInstance {
     #[inline]
     fn evaluate_path(&self, pos: Point3f, dir: Vector3f) -> RGBSpectrumf {
         if let Some(ref lp) = self.mesh.lighting_profile {
+            if !self.mesh.emission_side.emits(self.face_normal(), dir) {
+                return RGBSpectrumf::black();
+            }
             let p = pos + dir;
             // match `wi` against surface normal
             let ray = RawRay::from_od(p, -dir);
             if let Some((_t, si)) = Shape::intersect_ray(self, &ray) {
-                // retrive (u, v)
-                let dxy = DxyInfo::from_duv(&si.duv);
+                // point look-up, there is no footprint to filter over
+                let dxy = DxyInfo::default();
                 return lp.evaluate(&si, &dxy);
             }
         }
@@ -567,22 +1230,24 @@ impl Light for TriangleInstance {
     fn evaluate_sampled(
         &self, pos: Point3f, sample: Point2f
     ) -> LightSample {
-        let (l_pos, l_norm, l_pdf) = self.sample_wrt(pos, sample);
+        let ss = self.sample_wrt(pos, sample);
         let mut ret = LightSample{
             radiance: RGBSpectrumf::black(),
-            pdf: l_pdf,
-            pfrom: l_pos,
+            pdf: ss.pdf,
+            pfrom: ss.pos,
             pto: pos,
+            normal: self.face_normal(),
         };
         // match against surface normal
         if let Some(ref lp) = self.mesh.lighting_profile {
-            let ldir = pos - l_pos;
-            if ldir.dot(l_norm) > 0. as Float {
-                let ray = RawRay::from_od(pos, -ldir);
-                if let Some((_, si)) = Shape::intersect_ray(self, &ray) {
-                    let dxy = DxyInfo::from_duv(&si.duv);
-                    ret.radiance = lp.evaluate(&si, &dxy);
-                }
+            let ldir = pos - ss.pos;
+            if self.mesh.emission_side.emits(self.face_normal(), ldir) {
+                // same barycentrics as `self.sample`
+                let b = sample_uniform_triangle(sample);
+                let si = self.interaction_at(b, ldir.normalize());
+                // point look-up, there is no footprint to filter over
+                let dxy = DxyInfo::default();
+                ret.radiance = lp.evaluate(&si, &dxy);
             }
         }
         ret
@@ -590,24 +1255,23 @@ impl Light for TriangleInstance {
 
     #[inline]
     fn generate_path(&self, samples: SampleInfo) -> PathInfo {
-        let (pos, norm, pdfpos) = self.sample(samples.pfilm);
-        let (u, v) = normal::get_basis_from(norm);
-        let dir = sample::sample_cosw_hemisphere(samples.plens);
-        let dir = dir.x * u + dir.y * v + dir.z * norm;
+        let ss = self.sample(samples.pfilm);
+        let norm = self.face_normal();
+        let (dir, pdfdir) = self.mesh.emission_side.sample_cosw(norm, samples.plens);
         PathInfo{
-            ray: RawRay::from_od(pos, dir),
+            ray: RawRay::from_od(ss.pos, dir),
             normal: norm,
-            pdfpos: pdfpos,
-            pdfdir: sample::pdf_cosw_hemisphere(dir.z),
-            radiance: self.evaluate_path(pos, dir),
+            pdfpos: ss.pdf,
+            pdfdir: pdfdir,
+            radiance: self.evaluate_path(ss.pos, dir),
         }
     }
 
     #[inline]
     fn pdf_path(&self, pos: Point3f, dir: Vector3f, norm: Vector3f) -> (Float, Float) {
         (
-            Light::pdf(self, pos, norm),
-            sample::pdf_cosw_hemisphere(norm.dot(dir).abs())
+            Shape::pdf(self, pos, norm),
+            self.mesh.emission_side.pdf_cosw(norm, dir)
         )
     }
 
@@ -618,7 +1282,7 @@ impl Light for TriangleInstance {
     fn power(&self) -> RGBSpectrumf {
         if let Some(ref lp) = self.mesh.lighting_profile {
             debug_assert!(self.surface_area() >= 0. as Float);
-            lp.mean() * self.surface_area() * float::pi()
+            lp.mean() * self.surface_area() * float::pi() * self.mesh.emission_side.sides()
         } else {
             RGBSpectrumf::black()
         }
@@ -635,4 +1299,273 @@ impl Primitive for TriangleInstance {
     fn is_emissive(&self) -> bool {
         self.mesh.lighting_profile.is_some()
     }
+
+    #[inline]
+    fn parent_mesh(&self) -> Option<&TriangleMesh> {
+        Some(&*self.mesh)
+    }
+
+    #[inline]
+    fn triangle_index(&self) -> Option<usize> {
+        Some(self.index())
+    }
+}
+
+/// default resolution lighting profiles are rasterized at over
+/// each triangle, see `MeshLight::profile_weighted`
+pub const DEFAULT_PROFILE_RESOLUTION: usize = 16;
+
+/// coefficient of variation of a triangle's rasterized lighting
+/// profile above which points within it are sampled by the profile
+pub const PROFILE_VARIATION_THRESHOLD: Float = 0.25;
+
+// share of the mean luminance every triangle and raster cell is
+// weighted by at least, keeping the density positive wherever the
+// rasterization misses some emission
+const PROFILE_FLOOR: Float = 0.01;
+
+/// An emissive triangle mesh as a single light.
+///
+/// Triangles are picked according to their surface area, then
+/// sampled uniformly, unless the light is built by `profile_weighted`.
+pub struct MeshLight {
+    mesh: Arc<TriangleMesh>,
+    triangles: Vec<TriangleInstance>,
+    distribution: Distribution1D,
+    area: Float,
+    bvh: BVH,
+    // distributions over the unit square `sample_uniform_triangle`
+    // maps onto each triangle, if sampled by the lighting profile
+    profile: Option<Vec<Option<Distribution2D>>>,
+}
+
+impl MeshLight {
+    /// construction, `mesh` should have a lighting profile
+    pub fn new(mesh: Arc<TriangleMesh>) -> MeshLight {
+        debug_assert!(mesh.is_emissive());
+        let triangles: Vec<_> = TriangleMesh::instances(&mesh).collect();
+        // the profile is shared across the mesh, so weighting by its
+        // mean radiance leaves the area distribution unchanged
+        let areas: Vec<_> = triangles.iter().map(|t| t.surface_area()).collect();
+        let area = areas.iter().sum();
+        let distribution = Distribution1D::new(areas);
+        let components: Vec<ComponentPointer> = triangles.iter().map(|t| t.clone().into()).collect();
+        let bvh = BVH::new(&components, BVHStrategy::SAH);
+        MeshLight{
+            mesh, triangles, distribution, area, bvh,
+            profile: None,
+        }
+    }
+
+    /// construction, with triangles picked by their emitted power,
+    /// estimated by evaluating the lighting profile over `resolution`
+    /// by `resolution` cells of each. Points within triangles whose
+    /// profile varies much are sampled by these estimates as well.
+    ///
+    /// This suits profiles that are mostly dark, like textures of a
+    /// few bright windows, where sampling by area wastes most samples.
+    pub fn profile_weighted(mesh: Arc<TriangleMesh>, resolution: usize) -> MeshLight {
+        let mut ret = MeshLight::new(mesh);
+        let lp = match ret.mesh.lighting_profile {
+            Some(ref lp) => lp.clone(),
+            None => return ret,
+        };
+        let resolution = resolution.max(2);
+        let dxy = DxyInfo::default();
+        let rasters: Vec<Vec<Float>> = ret.triangles.iter().map(|triangle| {
+            let norm = triangle.face_normal();
+            let luminance = |x: Float, y: Float| {
+                let u = Point2f::new(x / resolution as Float, y / resolution as Float);
+                let si = triangle.interaction_at(sample_uniform_triangle(u), norm);
+                lp.evaluate(&si, &dxy).to_xyz().y.max(0. as Float)
+            };
+            let corners: Vec<_> = (0..(resolution + 1) * (resolution + 1)).map(|i| {
+                luminance((i % (resolution + 1)) as Float, (i / (resolution + 1)) as Float)
+            }).collect();
+            // the largest of the corners and the center of each cell,
+            // such that cells partly covered by bright spots are not
+            // missed
+            (0..resolution * resolution).map(|i| {
+                let (x, y) = (i % resolution, i / resolution);
+                let corner = |dx: usize, dy: usize| corners[(y + dy) * (resolution + 1) + x + dx];
+                luminance(x as Float + 0.5 as Float, y as Float + 0.5 as Float)
+                    .max(corner(0, 0)).max(corner(1, 0)).max(corner(0, 1)).max(corner(1, 1))
+            }).collect()
+        }).collect();
+        let means: Vec<Float> = rasters.iter()
+            .map(|raster| raster.iter().sum::<Float>() / raster.len() as Float)
+            .collect();
+        let power: Float = ret.triangles.iter().zip(&means).map(|(t, m)| t.surface_area() * m).sum();
+        if !(power > 0. as Float) { return ret; }
+        let floor = PROFILE_FLOOR * power / ret.area;
+        ret.distribution = Distribution1D::new(
+            ret.triangles.iter().zip(&means).map(|(t, m)| t.surface_area() * (m + floor))
+        );
+        ret.profile = Some(rasters.into_iter().zip(means).map(|(raster, mean)| {
+            if !(mean > 0. as Float) { return None; }
+            let variance = raster.iter().map(|v| (v - mean) * (v - mean)).sum::<Float>() / raster.len() as Float;
+            if variance.sqrt() / mean > PROFILE_VARIATION_THRESHOLD {
+                let raster: Vec<_> = raster.iter().map(|v| v + PROFILE_FLOOR * mean).collect();
+                Some(Distribution2D::new(&raster, resolution))
+            } else {
+                None
+            }
+        }).collect());
+        ret
+    }
+
+    /// the underlying mesh
+    #[inline]
+    pub fn mesh(&self) -> &Arc<TriangleMesh> {
+        &self.mesh
+    }
+
+    /// total surface area
+    #[inline]
+    pub fn surface_area(&self) -> Float {
+        self.area
+    }
+
+    /// test if triangles are sampled by their lighting profile
+    #[inline]
+    pub fn is_profile_weighted(&self) -> bool {
+        self.profile.is_some()
+    }
+
+    // pick a triangle by `sample`, returns its index with its probability
+    // and the sample on the unit square over it, along with its density
+    fn pick(&self, sample: Point2f) -> (usize, Float, Point2f, Float) {
+        let (idx, pmf, du) = self.distribution.sample_discrete(sample.x);
+        let u = Point2f::new(du.min(float::one_minus_epsilon()), sample.y);
+        let (u, pdf) = match self.within(idx) {
+            Some(d) => d.sample_continuous(u),
+            None => (u, 1. as Float),
+        };
+        (idx, pmf, u, pdf)
+    }
+
+    #[inline]
+    fn within(&self, idx: usize) -> Option<&Distribution2D> {
+        self.profile.as_ref().and_then(|p| p[idx].as_ref())
+    }
+
+    // the triangle `pos` lies on, up to a tolerance relative to the
+    // mesh's extent
+    fn locate(&self, pos: Point3f) -> Option<&TriangleInstance> {
+        let epsilon = 1e-4 as Float * self.bvh.bbox_parent().diagonal().magnitude();
+        let mut found = None;
+        self.bvh.locate(pos, epsilon, |component| match *component {
+            ComponentPointer::Triangle(ref t) => {
+                let b = t.barycentric(pos);
+                let tolerance = epsilon / t.surface_area().sqrt();
+                let on = (pos - t.x()).dot(t.face_normal()).abs() <= epsilon
+                    && b.x >= -tolerance && b.y >= -tolerance && b.z >= -tolerance;
+                if on { found = Some(t.index()); }
+                on
+            },
+            ComponentPointer::Arc(_) => false,
+        });
+        found.map(|idx| &self.triangles[idx])
+    }
+
+    // density wrt area of sampling `si`, a hit on the mesh
+    fn pdf_area(&self, si: &SurfaceInteraction) -> Float {
+        if self.profile.is_none() { return 1. as Float / self.area; }
+        let idx = match si.primitive_hit.and_then(|p| p.triangle_index()) {
+            Some(idx) => idx,
+            None => return 0. as Float,
+        };
+        let triangle = &self.triangles[idx];
+        let density = match self.within(idx) {
+            Some(d) => {
+                // inverse of `sample_uniform_triangle`
+                let b = triangle.barycentric(si.basic.pos);
+                let r = float::clamp(1. as Float - b.x, 0. as Float, 1. as Float);
+                let v = if r > 0. as Float { b.y / r } else { 0. as Float };
+                d.pdf(Point2f::new(r * r, float::clamp(v, 0. as Float, float::one_minus_epsilon())))
+            },
+            None => 1. as Float,
+        };
+        self.distribution.discrete_pdf(idx) * density / triangle.surface_area()
+    }
+}
+
+impl Light for MeshLight {
+    #[inline]
+    fn flags(&self) -> LightFlag {
+        LIGHT_AREA
+    }
+
+    #[inline]
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    fn evaluate_path(&self, pos: Point3f, dir: Vector3f) -> RGBSpectrumf {
+        if let Some(ref lp) = self.mesh.lighting_profile {
+            if let Some(triangle) = self.locate(pos) {
+                if self.mesh.emission_side.emits(triangle.face_normal(), dir) {
+                    let si = triangle.interaction_at(triangle.barycentric(pos), dir);
+                    // point look-up, there is no footprint to filter over
+                    return lp.evaluate(&si, &DxyInfo::default());
+                }
+            }
+        }
+        RGBSpectrumf::black()
+    }
+
+    fn evaluate_sampled(&self, pos: Point3f, sample: Point2f) -> LightSample {
+        let (idx, pmf, u, pdf) = self.pick(sample);
+        let mut ret = self.triangles[idx].evaluate_sampled(pos, u);
+        ret.pdf *= pmf * pdf;
+        ret
+    }
+
+    fn generate_path(&self, samples: SampleInfo) -> PathInfo {
+        let (idx, pmf, u, pdf) = self.pick(samples.pfilm);
+        let mut ret = self.triangles[idx].generate_path(SampleInfo{
+            pfilm: u,
+            plens: samples.plens,
+            time: samples.time,
+        });
+        ret.pdfpos *= pmf * pdf;
+        ret
+    }
+
+    fn pdf_path(&self, pos: Point3f, dir: Vector3f, norm: Vector3f) -> (Float, Float) {
+        let pdfpos = if self.profile.is_some() {
+            // find the triangle `pos` lies on
+            let mut ray = RawRay::from_od(pos + norm, -norm);
+            self.bvh.intersect_ray(&mut ray).map_or(0. as Float, |si| self.pdf_area(&si))
+        } else {
+            1. as Float / self.area
+        };
+        (pdfpos, self.mesh.emission_side.pdf_cosw(norm, dir))
+    }
+
+    fn pdf(&self, pos: Point3f, wi: Vector3f) -> Float {
+        let mut ray = RawRay::from_od(pos, wi);
+        if let Some(si) = self.bvh.intersect_ray(&mut ray) {
+            area_to_solid_angle(
+                self.pdf_area(&si),
+                (si.basic.pos - pos).magnitude2(),
+                si.basic.norm.dot(wi.normalize())
+            )
+        } else {
+            0. as Float
+        }
+    }
+
+    fn power(&self) -> RGBSpectrumf {
+        if let Some(ref lp) = self.mesh.lighting_profile {
+            lp.mean() * self.area * float::pi() * self.mesh.emission_side.sides()
+        } else {
+            RGBSpectrumf::black()
+        }
+    }
+
+    #[inline]
+    fn emits_from(&self, primitive: &Primitive) -> bool {
+        primitive.parent_mesh().map_or(false, |mesh| ptr::eq(mesh, &*self.mesh))
+    }
 }
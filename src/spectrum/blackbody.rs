@@ -0,0 +1,74 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Blackbody emission and its conversion into XYZ
+
+use geometry::prelude::*;
+
+/// shortest wavelength integrated over, in nanometers
+pub const LAMBDA_MIN: Float = 360.;
+/// longest wavelength integrated over, in nanometers
+pub const LAMBDA_MAX: Float = 830.;
+
+/// spectral radiance emitted by a blackbody at `kelvin`
+/// for wavelength `lambda` in nanometers, in $W/(sr \cdot m^3)$
+pub fn planck(lambda: Float, kelvin: Float) -> Float {
+    if !(kelvin > 0. as Float) { return 0. as Float; }
+    const C: f64 = 299792458.;
+    const H: f64 = 6.62606957e-34;
+    const KB: f64 = 1.3806488e-23;
+    let l = lambda as f64 * 1e-9;
+    let ret = 2. * H * C * C / (l.powi(5) * ((H * C / (l * KB * kelvin as f64)).exp() - 1.));
+    ret as Float
+}
+
+/// wavelength of the peak of blackbody emission at `kelvin`,
+/// in nanometers, by Wien's displacement law
+#[inline]
+pub fn peak_wavelength(kelvin: Float) -> Float {
+    2.8977721e6 as Float / kelvin
+}
+
+/// CIE 1931 color matching functions at `lambda` in nanometers, by
+/// the multi-lobe fit of Wyman et al., "Simple Analytic Approximations
+/// to the CIE XYZ Color Matching Functions", 2013
+pub fn cie_xyz(lambda: Float) -> Vector3f {
+    let g = |mu: Float, s1: Float, s2: Float| {
+        let t = (lambda - mu) / if lambda < mu { s1 } else { s2 };
+        (-0.5 as Float * t * t).exp()
+    };
+    Vector3f::new(
+        1.056 as Float * g(599.8, 37.9, 31.0) + 0.362 as Float * g(442.0, 16.0, 26.7)
+            - 0.065 as Float * g(501.1, 20.4, 26.2),
+        0.821 as Float * g(568.8, 46.9, 40.5) + 0.286 as Float * g(530.9, 16.3, 31.1),
+        1.217 as Float * g(437.0, 11.8, 36.0) + 0.681 as Float * g(459.0, 26.0, 13.8)
+    )
+}
+
+/// XYZ of a spectrum sampled at each nanometer of the visible range,
+/// normalized such that a constant unit spectrum has unit luminance
+pub fn spectrum_to_xyz<F: Fn(Float) -> Float>(spectrum: F) -> Vector3f {
+    let mut xyz = Vector3f::zero();
+    let mut ysum = 0. as Float;
+    let n = (LAMBDA_MAX - LAMBDA_MIN) as usize;
+    for i in 0..n+1 {
+        let lambda = LAMBDA_MIN + i as Float;
+        let cie = cie_xyz(lambda);
+        xyz += cie * spectrum(lambda);
+        ysum += cie.y;
+    }
+    xyz / ysum
+}
+
+/// XYZ of blackbody emission at `kelvin`, scaled such that
+/// its spectral radiance peaks at 1
+pub fn blackbody_xyz(kelvin: Float) -> Vector3f {
+    if !(kelvin > 0. as Float) { return Vector3f::zero(); }
+    let peak = planck(peak_wavelength(kelvin), kelvin);
+    spectrum_to_xyz(|lambda| planck(lambda, kelvin) / peak)
+}
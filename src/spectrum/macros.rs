@@ -170,9 +170,10 @@ macro_rules! delegate_impl_to_norm {
             }
 
             #[inline]
-            fn from_norm(mut f: Float) -> Self {
-                f = float::clamp(f, 0.0 as Float, 1.0 as Float);
-                <Self as NumCast>::from(f * <Float as NumCast>::from(std::$Type::MAX).unwrap()).unwrap()
+            fn from_norm(f: Float) -> Self {
+                if !(f > 0.0 as Float) { return 0; }
+                if f >= 1.0 as Float { return std::$Type::MAX; }
+                <Self as NumCast>::from(f * <Float as NumCast>::from(std::$Type::MAX).unwrap()).unwrap_or(std::$Type::MAX)
             }
         }
     }
@@ -12,6 +12,7 @@ use std;
 use std::ops;
 use std::mem;
 use num_traits::cast::NumCast;
+use num_traits::Bounded;
 use image;
 
 pub type RGBSpectrumf = RGBSpectrum<Float>;
@@ -52,6 +53,7 @@ pub trait Spectrum
 
 /// An spectrum represented in SRGB
 #[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[repr(C)]
 pub struct RGBSpectrum<T: BaseNum> {
     pub inner: Vector3<T>,
 }
@@ -150,9 +152,10 @@ impl<T: BaseNum + image::Primitive> image::Pixel for RGBSpectrum<T> {
         (self.inner.x, self.inner.y, self.inner.z, <T as One>::one())
     }
 
+    /// the alpha channel `_d` is dropped, as for `image::Rgb`
     #[inline]
-    fn from_channels(a: T, b: T, c: T, d: T) -> Self {
-        RGBSpectrum::new(a*d, b*d, c*d)
+    fn from_channels(a: T, b: T, c: T, _d: T) -> Self {
+        RGBSpectrum::new(a, b, c)
     }
 
     #[inline]
@@ -261,9 +264,11 @@ impl<T: BaseNum + image::Primitive> image::Pixel for RGBSpectrum<T> {
         );
     }
 
+    /// each channel `x` becomes `max - x`, `max` being the largest
+    /// value of `T`, as for `image::Rgb`
     #[inline]
     fn invert(&mut self) {
-        self.apply(|x| <T as One>::one() / x);
+        self.apply(|x| <T as Bounded>::max_value() - x);
     }
 
     #[inline]
@@ -293,12 +298,33 @@ impl RGBSpectrumf {
         )
     }
 
+    /// emission of a blackbody at `kelvin`, with its spectral
+    /// radiance normalized to peak at `scale`
+    pub fn from_blackbody(kelvin: Float, scale: Float) -> RGBSpectrumf {
+        let rgb = RGBSpectrumf::from_xyz(blackbody::blackbody_xyz(kelvin)) * scale;
+        // out-of-gamut components
+        rgb.clamp(0. as Float, float::infinity())
+    }
+
+    /// color of a blackbody at `kelvin`, normalized to unit luminance
+    pub fn from_color_temperature(kelvin: Float) -> RGBSpectrumf {
+        let rgb = RGBSpectrumf::from_blackbody(kelvin, 1. as Float);
+        let y = rgb.to_xyz().y;
+        if y > 0. as Float { rgb / y } else { RGBSpectrumf::black() }
+    }
+
     /// sqrt
     #[inline]
     pub fn sqrt(self) -> RGBSpectrumf {
         RGBSpectrumf::new(self.inner.x.sqrt(), self.inner.y.sqrt(), self.inner.z.sqrt())   
     }
 
+    /// exp
+    #[inline]
+    pub fn exp(self) -> RGBSpectrumf {
+        RGBSpectrumf::new(self.inner.x.exp(), self.inner.y.exp(), self.inner.z.exp())
+    }
+
     #[inline]
     pub fn valid(&self) -> bool {
         !(self.r().is_nan() || self.g().is_nan() || self.b().is_nan())
@@ -350,6 +376,9 @@ impl Spectrum for RGBSpectrumf {
 
 #[macro_use]
 mod macros;
+pub mod blackbody;
+#[cfg(test)]
+mod tests;
 
 delegate_impl_op!(Add, add, add_element_wise for RGBSpectrumf);
 delegate_impl_op!(Sub, sub, sub_element_wise for RGBSpectrumf);
@@ -365,24 +394,42 @@ delegate_impl_op!(@assign DivAssign, div_assign, div_assign_element_wise for RGB
 delegate_impl_op!(@assign MulAssign<Float>, mul_assign, mul_assign for RGBSpectrumf);
 delegate_impl_op!(@assign DivAssign<Float>, div_assign, div_assign for RGBSpectrumf);
 
-pub trait ToNorm {
+/// Conversion between channel values and normalized floats
+pub trait ToNorm: Sized {
+    /// integers are mapped from their full range into [0, 1],
+    /// floats are kept as is, HDR values included
     fn to_norm(self) -> Float;
-    
+
+    /// as `to_norm`, for data that is required to be normalized,
+    /// asserting so in debug builds
+    #[inline]
+    fn to_norm_checked(self) -> Float {
+        let f = self.to_norm();
+        debug_assert!(f >= 0. as Float && f <= 1. as Float, "{} is not normalized", f);
+        f
+    }
+
+    /// inverse of `to_norm`. Integers saturate outside of [0, 1],
+    /// with NaN mapping to 0
     fn from_norm(f: Float) -> Self;
+
+    /// `from_norm` of `f` clamped into [0, 1], with NaN mapping to 0,
+    /// for writing low dynamic range data
+    #[inline]
+    fn from_norm_clamped(f: Float) -> Self {
+        let f = if f.is_nan() { 0. as Float } else { float::clamp(f, 0. as Float, 1. as Float) };
+        Self::from_norm(f)
+    }
 }
 
 impl ToNorm for Float {
     #[inline]
     fn to_norm(self) -> Float {
-        debug_assert!(self>=0. as Float);
-        debug_assert!(self<=1. as Float);
         self
     }
 
     #[inline]
     fn from_norm(f: Float) -> Self {
-        debug_assert!(f>=0. as Float);
-        debug_assert!(f<=1. as Float);
         f
     }
 }
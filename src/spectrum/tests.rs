@@ -0,0 +1,74 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// tests
+#[cfg(test)]
+mod test_blackbody {
+    use super::super::*;
+
+    #[test]
+    fn test_white_point() {
+        let rgb = RGBSpectrumf::from_color_temperature(6500. as Float);
+        assert_relative_eq!(rgb.to_xyz().y, 1. as Float, max_relative = 1e-4);
+        let mean = (rgb.r() + rgb.g() + rgb.b()) / 3. as Float;
+        for &c in &[rgb.r(), rgb.g(), rgb.b()] {
+            assert_relative_eq!(c, mean, max_relative = 0.05);
+        }
+    }
+
+    #[test]
+    fn test_red_shift() {
+        let rgb = RGBSpectrumf::from_color_temperature(2000. as Float);
+        assert!(rgb.r() > 2. as Float * rgb.g(), "{:?}", rgb);
+        assert!(rgb.g() > 2. as Float * rgb.b(), "{:?}", rgb);
+        // and hotter ones lean blue
+        let rgb = RGBSpectrumf::from_color_temperature(12000. as Float);
+        assert!(rgb.b() > rgb.r(), "{:?}", rgb);
+    }
+
+    #[test]
+    fn test_scale() {
+        let unit = RGBSpectrumf::from_blackbody(3200. as Float, 1. as Float);
+        let scaled = RGBSpectrumf::from_blackbody(3200. as Float, 10. as Float);
+        assert!(unit.to_xyz().y > 0. as Float);
+        assert_relative_eq!(scaled.r(), unit.r() * 10. as Float, max_relative = 1e-5);
+        assert_relative_eq!(scaled.g(), unit.g() * 10. as Float, max_relative = 1e-5);
+        assert_relative_eq!(scaled.b(), unit.b() * 10. as Float, max_relative = 1e-5);
+    }
+}
+
+#[cfg(test)]
+mod test_pixel {
+    use super::super::*;
+    use image::{self, Pixel};
+
+    #[test]
+    fn test_from_channels() {
+        let rgb = <RGBSpectrum<u8> as Pixel>::from_channels(10, 20, 30, 0);
+        assert_eq!(rgb, RGBSpectrum::new(10u8, 20, 30));
+        let rgb = <RGBSpectrumf as Pixel>::from_channels(0.2 as Float, 0.4, 0.8, 0.5);
+        assert_eq!(rgb, RGBSpectrumf::new(0.2 as Float, 0.4, 0.8));
+        // in line with the `image` crate's own pixels
+        let rgba = image::Rgba{data: [10u8, 20, 30, 40]};
+        assert_eq!(rgba.to_rgb(), image::Rgb{data: [10u8, 20, 30]});
+        assert_eq!(<image::Rgb<u8> as Pixel>::from_channels(10, 20, 30, 40).data, [10, 20, 30]);
+    }
+
+    #[test]
+    fn test_invert() {
+        let mut rgb = RGBSpectrum::new(0u8, 100, 255);
+        rgb.invert();
+        assert_eq!(rgb, RGBSpectrum::new(255u8, 155, 0));
+        let mut expected = image::Rgb{data: [0u8, 100, 255]};
+        expected.invert();
+        assert_eq!(rgb.channels(), &expected.data[..]);
+        let mut rgb = RGBSpectrumf::new(0. as Float, 0.5, 2.);
+        rgb.invert();
+        assert_eq!(rgb, RGBSpectrumf::new(Float::max_value(), Float::max_value() - 0.5, Float::max_value() - 2.));
+    }
+}
@@ -0,0 +1,524 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Utilities for validating renderers against each other and against
+//! analytic answers: canonical scenes, image comparison and harnesses.
+//!
+//! Enabled by the `test-utils` feature.
+
+use prelude::*;
+use component::ComponentPointer;
+use filming::film::{Film, Image};
+use sample::filters::BoxFilter;
+use sample::naive::Naive;
+use rand::{SeedableRng, StdRng};
+use std::sync::Arc;
+use tobj;
+
+/// A scene along with the camera viewing it
+pub struct TestScene {
+    pub scene: Scene,
+    pub camera: Arc<Camera>,
+}
+
+/// Renderers available to the harnesses
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RendererKind {
+    /// `WhittedRenderer`, direct lighting only
+    Whitted,
+    /// `PTRenderer` with the given maximum depth
+    PT(usize),
+    /// `PTRenderer` with the given maximum depth and per-lobe depths
+    PTLobes(usize, LobeDepths),
+    /// `BPTRenderer` with the given maximum depth
+    BPT(usize),
+    /// `AORenderer` with the given parameters
+    AO(AmbientOcclusion),
+}
+
+/// a `resolution`x`resolution` pinhole camera at `eye` looking at `to`,
+/// with a box filter so that pixels don't share samples.
+/// `fov` in radians
+pub fn camera(resolution: usize, eye: Point3f, to: Point3f, fov: Float) -> Arc<Camera> {
+    Arc::new(perspec_cam(resolution, eye, to, fov))
+}
+
+/// the `PerspecCam` behind `camera`
+pub fn perspec_cam(resolution: usize, eye: Point3f, to: Point3f, fov: Float) -> PerspecCam {
+    perspec_cam_near(resolution, eye, to, fov, 0.01 as Float)
+}
+
+/// `perspec_cam`, with its near plane at `znear`
+pub fn perspec_cam_near(resolution: usize, eye: Point3f, to: Point3f, fov: Float, znear: Float) -> PerspecCam {
+    let film = Film::new(
+        Point2::new(resolution, resolution),
+        BBox2f::new(Point2f::new(0. as Float, 0. as Float), Point2f::new(1. as Float, 1. as Float)),
+        Arc::new(BoxFilter::new(Vector2f::new(0.5 as Float, 0.5 as Float)))
+    );
+    let mut camera = PerspecCam::new(
+        Matrix4f::identity(),
+        BBox2f::new(Point2f::new(-1. as Float, -1. as Float), Point2f::new(1. as Float, 1. as Float)),
+        znear, 1000. as Float, fov, None, film
+    );
+    camera.look_from(eye, to, Vector3f::new(0. as Float, 1. as Float, 0. as Float));
+    camera
+}
+
+/// a lambertian material with constant reflectance
+pub fn matte(r: Float, g: Float, b: Float) -> Arc<Material> {
+    Arc::new(MatteMaterial::new(
+        Arc::new(ConstantTexture{value: RGBSpectrumf::new(r, g, b)}),
+        Arc::new(ConstantTexture{value: 0. as Float}),
+        None
+    ))
+}
+
+/// a single quad `p0p1p2p3` as a triangle mesh, emitting `emission`
+/// from both sides if presented
+pub fn quad(
+    p: [Point3f; 4], material: Arc<Material>, emission: Option<RGBSpectrumf>
+) -> Arc<TriangleMesh> {
+    let mut positions = Vec::with_capacity(12);
+    for v in &p {
+        positions.push(v.x as f32);
+        positions.push(v.y as f32);
+        positions.push(v.z as f32);
+    }
+    let model = tobj::Model::new(
+        tobj::Mesh::new(positions, Vec::new(), Vec::new(), vec![0, 1, 2, 0, 2, 3], None),
+        "quad".to_owned()
+    );
+    let lighting = emission.map(|e| {
+        let t: Arc<Texture<Texel=RGBSpectrumf>> = Arc::new(ConstantTexture{value: e});
+        t
+    });
+    let mut mesh = TriangleMesh::from_model(model, material, lighting);
+    mesh.set_emission_side(EmissionSide::Both);
+    Arc::new(mesh)
+}
+
+/// a unit-radius white lambertian sphere at origin, enclosed by
+/// a black sphere of radius 10 uniformly emitting `radiance` inwards.
+/// Every pixel should come out as `radiance` given enough depth.
+pub fn furnace(resolution: usize, radiance: Float) -> TestScene {
+    let inner = ShapedPrimitive::new(
+        Sphere::new(1. as Float, -1. as Float, 1. as Float, float::pi() * 2. as Float),
+        matte(1. as Float, 1. as Float, 1. as Float),
+        None
+    );
+    let mut outer = ShapedPrimitive::new(
+        Sphere::new(10. as Float, -10. as Float, 10. as Float, float::pi() * 2. as Float),
+        matte(0. as Float, 0. as Float, 0. as Float),
+        Some(Arc::new(ConstantTexture{value: RGBSpectrumf::new(radiance, radiance, radiance)}))
+    );
+    outer.emission_side = EmissionSide::Back;
+    let outer = Arc::new(outer);
+    let inner: Arc<Composable> = Arc::new(inner);
+    let components: Vec<ComponentPointer> = vec![inner.into(), (outer.clone() as Arc<Composable>).into()];
+    let lights: Vec<Arc<Light>> = vec![outer];
+    TestScene{
+        scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+        camera: camera(
+            resolution,
+            Point3f::new(0. as Float, 0. as Float, -4. as Float),
+            Point3f::new(0. as Float, 0. as Float, 0. as Float),
+            float::frac_pi_2()
+        ),
+    }
+}
+
+/// a cornell-like box spanning $[-1, 1]^3$ made up of quads, open
+/// towards `-z`, lit by a small quad light below its ceiling
+pub fn cornell_box(resolution: usize) -> TestScene {
+    let p = |x: Float, y: Float, z: Float| Point3f::new(x, y, z);
+    let white = matte(0.7 as Float, 0.7 as Float, 0.7 as Float);
+    let red = matte(0.7 as Float, 0.1 as Float, 0.1 as Float);
+    let green = matte(0.1 as Float, 0.7 as Float, 0.1 as Float);
+    let s = 0.25 as Float;
+    let h = 0.98 as Float;
+    let meshes = vec![
+        // floor, ceiling and back
+        quad([p(-1., -1., -1.), p(1., -1., -1.), p(1., -1., 1.), p(-1., -1., 1.)], white.clone(), None),
+        quad([p(-1., 1., -1.), p(-1., 1., 1.), p(1., 1., 1.), p(1., 1., -1.)], white.clone(), None),
+        quad([p(-1., -1., 1.), p(1., -1., 1.), p(1., 1., 1.), p(-1., 1., 1.)], white.clone(), None),
+        // left and right
+        quad([p(-1., -1., -1.), p(-1., -1., 1.), p(-1., 1., 1.), p(-1., 1., -1.)], red, None),
+        quad([p(1., -1., -1.), p(1., 1., -1.), p(1., 1., 1.), p(1., -1., 1.)], green, None),
+        // light
+        quad(
+            [p(-s, h, -s), p(s, h, -s), p(s, h, s), p(-s, h, s)],
+            matte(0. as Float, 0. as Float, 0. as Float),
+            Some(RGBSpectrumf::new(8. as Float, 8. as Float, 8. as Float))
+        ),
+    ];
+    let mut components: Vec<ComponentPointer> = Vec::new();
+    let mut lights: Vec<Arc<Light>> = Vec::new();
+    for mesh in &meshes {
+        if mesh.is_emissive() {
+            lights.push(Arc::new(MeshLight::new(mesh.clone())));
+        }
+        for triangle in TriangleMesh::instances(mesh) {
+            components.push(triangle.into());
+        }
+    }
+    TestScene{
+        scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+        camera: camera(
+            resolution,
+            p(0., 0., -3.4),
+            p(0., 0., 0.),
+            float::frac_pi_2() * 0.5 as Float
+        ),
+    }
+}
+
+/// a glass sphere above a white floor, lit by a small quad light
+/// overhead, focusing a caustic onto the floor
+pub fn glass_sphere_caustic(resolution: usize) -> TestScene {
+    let p = |x: Float, y: Float, z: Float| Point3f::new(x, y, z);
+    let s = 0.2 as Float;
+    let floor = quad(
+        [p(-4., 0., -4.), p(4., 0., -4.), p(4., 0., 4.), p(-4., 0., 4.)],
+        matte(0.8 as Float, 0.8 as Float, 0.8 as Float), None
+    );
+    let lamp = quad(
+        [p(-s, 4., -s), p(s, 4., -s), p(s, 4., s), p(-s, 4., s)],
+        matte(0. as Float, 0. as Float, 0. as Float),
+        Some(RGBSpectrumf::new(50. as Float, 50. as Float, 50. as Float))
+    );
+    let glass = GlassMaterial::new(
+        Arc::new(ConstantTexture{value: RGBSpectrumf::new(1. as Float, 1. as Float, 1. as Float)}),
+        Arc::new(ConstantTexture{value: RGBSpectrumf::new(1. as Float, 1. as Float, 1. as Float)}),
+        Arc::new(ConstantTexture{value: 0. as Float}),
+        1.5 as Float,
+        None
+    );
+    let sphere = ShapedPrimitive::new(
+        Sphere::new(1. as Float, -1. as Float, 1. as Float, float::pi() * 2. as Float),
+        glass, None
+    );
+    let translation = Matrix4f::from_translation(Vector3f::new(0. as Float, 1.5 as Float, 0. as Float));
+    let sphere = TransformedComposable::new(
+        sphere, Arc::new(translation), Arc::new(translation.invert().unwrap())
+    );
+    let mut components: Vec<ComponentPointer> = Vec::new();
+    for triangle in TriangleMesh::instances(&floor).chain(TriangleMesh::instances(&lamp)) {
+        components.push(triangle.into());
+    }
+    let sphere: Arc<Composable> = Arc::new(sphere);
+    components.push(sphere.into());
+    let lights: Vec<Arc<Light>> = vec![Arc::new(MeshLight::new(lamp))];
+    TestScene{
+        scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+        camera: camera(
+            resolution,
+            p(0., 3., -6.),
+            p(0., 0.5, 0.),
+            float::frac_pi_2() * 0.6 as Float
+        ),
+    }
+}
+
+/// a unit-radius white sphere resting on a shadow catcher ground at
+/// $y = 0$, lit by a small quad light overhead and viewed from above
+/// the horizon, such that the top of the view sees nothing
+pub fn shadow_catcher(resolution: usize) -> TestScene {
+    let p = |x: Float, y: Float, z: Float| Point3f::new(x, y, z);
+    let s = 0.5 as Float;
+    let lamp = quad(
+        [p(-s, 4., -s), p(s, 4., -s), p(s, 4., s), p(-s, 4., s)],
+        matte(0. as Float, 0. as Float, 0. as Float),
+        Some(RGBSpectrumf::new(20. as Float, 20. as Float, 20. as Float))
+    );
+    let mut ground = ShapedPrimitive::new(
+        Sphere::new(100. as Float, -100. as Float, 100. as Float, float::pi() * 2. as Float),
+        matte(0.8 as Float, 0.8 as Float, 0.8 as Float),
+        None
+    );
+    ground.shadow_catcher = true;
+    let translation = Matrix4f::from_translation(Vector3f::new(0. as Float, -100. as Float, 0. as Float));
+    let ground = TransformedComposable::new(
+        ground, Arc::new(translation), Arc::new(translation.invert().unwrap())
+    );
+    let sphere = ShapedPrimitive::new(
+        Sphere::new(1. as Float, -1. as Float, 1. as Float, float::pi() * 2. as Float),
+        matte(0.8 as Float, 0.8 as Float, 0.8 as Float),
+        None
+    );
+    let translation = Matrix4f::from_translation(Vector3f::new(0. as Float, 1. as Float, 0. as Float));
+    let sphere = TransformedComposable::new(
+        sphere, Arc::new(translation), Arc::new(translation.invert().unwrap())
+    );
+    let mut components: Vec<ComponentPointer> = Vec::new();
+    for triangle in TriangleMesh::instances(&lamp) {
+        components.push(triangle.into());
+    }
+    let ground: Arc<Composable> = Arc::new(ground);
+    let sphere: Arc<Composable> = Arc::new(sphere);
+    components.push(ground.into());
+    components.push(sphere.into());
+    let lights: Vec<Arc<Light>> = vec![Arc::new(MeshLight::new(lamp))];
+    TestScene{
+        scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+        camera: camera(
+            resolution,
+            p(0., 3., -6.),
+            p(0., 0.5, 0.),
+            float::frac_pi_2() * 0.6 as Float
+        ),
+    }
+}
+
+/// a white lambertian sphere between two facing mirrors at $z = \pm 1$,
+/// lit by a point light and viewed from near one of the mirrors,
+/// showing ever deeper reflections of the sphere
+pub fn mirror_corridor(resolution: usize) -> TestScene {
+    let p = |x: Float, y: Float, z: Float| Point3f::new(x, y, z);
+    let mirror: Arc<Material> = Arc::new(MirrorMaterial::new(
+        Arc::new(ConstantTexture{value: RGBSpectrumf::new(0.9 as Float, 0.9 as Float, 0.9 as Float)}),
+        None
+    ));
+    let meshes = vec![
+        quad([p(-2., -2., 1.), p(2., -2., 1.), p(2., 2., 1.), p(-2., 2., 1.)], mirror.clone(), None),
+        quad([p(-2., -2., -1.), p(-2., 2., -1.), p(2., 2., -1.), p(2., -2., -1.)], mirror, None),
+    ];
+    let sphere: Arc<Composable> = Arc::new(ShapedPrimitive::new(
+        Sphere::new(0.25 as Float, -0.25 as Float, 0.25 as Float, float::pi() * 2. as Float),
+        matte(1. as Float, 1. as Float, 1. as Float),
+        None
+    ));
+    let mut components: Vec<ComponentPointer> = vec![sphere.into()];
+    for mesh in &meshes {
+        for triangle in TriangleMesh::instances(mesh) {
+            components.push(triangle.into());
+        }
+    }
+    let lights: Vec<Arc<Light>> = vec![Arc::new(PointLight::new(
+        p(0., 0.8, 0.), RGBSpectrumf::new(1. as Float, 1. as Float, 1. as Float)
+    ))];
+    TestScene{
+        scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+        camera: camera(
+            resolution,
+            p(1.5, 0., -0.9),
+            p(0., 0., 2.),
+            float::frac_pi_2()
+        ),
+    }
+}
+
+/// a non-refracting clear glass absorbing `sigma_a` per unit length
+pub fn absorbing_glass(sigma_a: RGBSpectrumf) -> Arc<Material> {
+    let mut glass = GlassMaterial::new(
+        Arc::new(ConstantTexture{value: RGBSpectrumf::new(0. as Float, 0. as Float, 0. as Float)}),
+        Arc::new(ConstantTexture{value: RGBSpectrumf::new(1. as Float, 1. as Float, 1. as Float)}),
+        Arc::new(ConstantTexture{value: 0. as Float}),
+        1. as Float,
+        None
+    );
+    glass.sigma_a = sigma_a;
+    Arc::new(glass)
+}
+
+/// a unit-thickness slab of `absorbing_glass` between $z = 0$ and $z = 1$,
+/// in front of a white quad light with unit radiance, viewed head-on
+/// through a narrow field of view
+pub fn glass_slab(resolution: usize, sigma_a: RGBSpectrumf) -> TestScene {
+    let p = |x: Float, y: Float, z: Float| Point3f::new(x, y, z);
+    let glass = absorbing_glass(sigma_a);
+    let meshes = vec![
+        quad([p(-4., -4., 0.), p(4., -4., 0.), p(4., 4., 0.), p(-4., 4., 0.)], glass.clone(), None),
+        quad([p(-4., -4., 1.), p(-4., 4., 1.), p(4., 4., 1.), p(4., -4., 1.)], glass, None),
+        quad(
+            [p(-8., -8., 3.), p(8., -8., 3.), p(8., 8., 3.), p(-8., 8., 3.)],
+            matte(0. as Float, 0. as Float, 0. as Float),
+            Some(RGBSpectrumf::new(1. as Float, 1. as Float, 1. as Float))
+        ),
+    ];
+    let mut components: Vec<ComponentPointer> = Vec::new();
+    let mut lights: Vec<Arc<Light>> = Vec::new();
+    for mesh in &meshes {
+        if mesh.is_emissive() {
+            lights.push(Arc::new(MeshLight::new(mesh.clone())));
+        }
+        for triangle in TriangleMesh::instances(mesh) {
+            components.push(triangle.into());
+        }
+    }
+    TestScene{
+        scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+        camera: camera(resolution, p(0., 0., -2.), p(0., 0., 0.), 0.1 as Float),
+    }
+}
+
+/// a unit-radius sphere of `absorbing_glass` at origin, in front of
+/// a white quad light with unit radiance, filling the center of the view
+pub fn glass_ball(resolution: usize, sigma_a: RGBSpectrumf) -> TestScene {
+    let p = |x: Float, y: Float, z: Float| Point3f::new(x, y, z);
+    let lamp = quad(
+        [p(-8., -8., 3.), p(8., -8., 3.), p(8., 8., 3.), p(-8., 8., 3.)],
+        matte(0. as Float, 0. as Float, 0. as Float),
+        Some(RGBSpectrumf::new(1. as Float, 1. as Float, 1. as Float))
+    );
+    let sphere: Arc<Composable> = Arc::new(ShapedPrimitive::new(
+        Sphere::new(1. as Float, -1. as Float, 1. as Float, float::pi() * 2. as Float),
+        absorbing_glass(sigma_a),
+        None
+    ));
+    let mut components: Vec<ComponentPointer> = vec![sphere.into()];
+    for triangle in TriangleMesh::instances(&lamp) {
+        components.push(triangle.into());
+    }
+    let lights: Vec<Arc<Light>> = vec![Arc::new(MeshLight::new(lamp))];
+    TestScene{
+        scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+        camera: camera(resolution, p(0., 0., -4.), p(0., 0., 0.), float::frac_pi_4()),
+    }
+}
+
+/// a white quad light with unit radiance crossed by a black vertical
+/// bar, viewed head-on, with a sheet of `ThinGlassMaterial` of `eta`
+/// tilted by 45 degrees in between if `window` is set
+pub fn thin_window(resolution: usize, eta: Float, window: bool) -> TestScene {
+    let p = |x: Float, y: Float, z: Float| Point3f::new(x, y, z);
+    let mut meshes = vec![
+        quad(
+            [p(-8., -8., 3.), p(8., -8., 3.), p(8., 8., 3.), p(-8., 8., 3.)],
+            matte(0. as Float, 0. as Float, 0. as Float),
+            Some(RGBSpectrumf::new(1. as Float, 1. as Float, 1. as Float))
+        ),
+        quad(
+            [p(0.2, -8., 2.9), p(0.2, 8., 2.9), p(0.6, 8., 2.9), p(0.6, -8., 2.9)],
+            matte(0. as Float, 0. as Float, 0. as Float),
+            None
+        ),
+    ];
+    if window {
+        let glass: Arc<Material> = Arc::new(ThinGlassMaterial::new(
+            Arc::new(ConstantTexture{value: RGBSpectrumf::new(1. as Float, 1. as Float, 1. as Float)}),
+            Arc::new(ConstantTexture{value: RGBSpectrumf::new(1. as Float, 1. as Float, 1. as Float)}),
+            eta,
+            None
+        ));
+        meshes.push(quad(
+            [p(-4., -8., -4.), p(4., -8., 4.), p(4., 8., 4.), p(-4., 8., -4.)], glass, None
+        ));
+    }
+    let mut components: Vec<ComponentPointer> = Vec::new();
+    let mut lights: Vec<Arc<Light>> = Vec::new();
+    for mesh in &meshes {
+        if mesh.is_emissive() {
+            lights.push(Arc::new(MeshLight::new(mesh.clone())));
+        }
+        for triangle in TriangleMesh::instances(mesh) {
+            components.push(triangle.into());
+        }
+    }
+    TestScene{
+        scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+        camera: camera(resolution, p(0., 0., -4.), p(0., 0., 0.), float::frac_pi_4()),
+    }
+}
+
+/// render `scene` with `kind` at `spp` samples per pixel, from a fixed
+/// seed such that harnesses pass or fail the same every run
+pub fn render(scene: &TestScene, kind: RendererKind, spp: usize) -> Image {
+    let sampler = Naive::with_rng(spp, StdRng::from_seed(&[7usize][..]));
+    let camera = scene.camera.clone();
+    match kind {
+        RendererKind::Whitted => WhittedRenderer::new(
+            sampler, camera, "", 0. as Float
+        ).render_image(&scene.scene),
+        RendererKind::PT(max_depth) => PTRenderer::new(
+            sampler, camera, "", max_depth, true
+        ).render_image(&scene.scene),
+        RendererKind::PTLobes(max_depth, depths) => {
+            let mut renderer = PTRenderer::new(sampler, camera, "", max_depth, true);
+            renderer.set_lobe_depths(depths);
+            renderer.render_image(&scene.scene)
+        },
+        RendererKind::BPT(max_depth) => BPTRenderer::new(
+            sampler, camera, "", max_depth
+        ).render_image(&scene.scene),
+        RendererKind::AO(params) => AORenderer::new(
+            sampler, camera, "", params
+        ).render_image(&scene.scene),
+    }
+}
+
+/// mean of all pixels
+pub fn mean(image: &Image) -> RGBSpectrumf {
+    let dim = image.dimension();
+    let mut sum = RGBSpectrumf::black();
+    for y in 0..dim.y {
+        for x in 0..dim.x {
+            sum += image[(x, y)];
+        }
+    }
+    sum / (dim.x * dim.y) as Float
+}
+
+/// box-downsample `image` by an integral `factor`, dropping the remainders
+pub fn downsample(image: &Image, factor: u32) -> Image {
+    assert!(factor > 0);
+    let dim = image.dimension();
+    let newdim = Point2::new(dim.x / factor, dim.y / factor);
+    let mut ret = Image::new(RGBSpectrumf::black(), newdim);
+    let scale = 1. as Float / (factor * factor) as Float;
+    for y in 0..newdim.y * factor {
+        for x in 0..newdim.x * factor {
+            ret[(x / factor, y / factor)] += image[(x, y)] * scale;
+        }
+    }
+    ret
+}
+
+/// per-pixel relative mean squared error between `a` and `b`,
+/// averaged over pixels and channels. Symmetric in `a` and `b`.
+pub fn relative_mse(a: &Image, b: &Image) -> Float {
+    let dim = a.dimension();
+    assert_eq!(dim, b.dimension(), "comparing images of different dimensions");
+    let mut sum = 0. as Float;
+    for y in 0..dim.y {
+        for x in 0..dim.x {
+            let pa = a[(x, y)];
+            let pb = b[(x, y)];
+            for &(ca, cb) in &[(pa.r(), pb.r()), (pa.g(), pb.g()), (pa.b(), pb.b())] {
+                let m = 0.5 as Float * (ca + cb);
+                sum += (ca - cb) * (ca - cb) / (m * m + 1e-2 as Float);
+            }
+        }
+    }
+    sum / (dim.x * dim.y * 3) as Float
+}
+
+/// test if `a` and `b` agree within `tol`. Images are downsampled by `4`
+/// before taking their relative MSE, as noise is expected to be
+/// high-frequency while systematic errors usually are not.
+pub fn images_agree(a: &Image, b: &Image, tol: Float) -> bool {
+    relative_mse(&downsample(a, 4), &downsample(b, 4)) <= tol
+}
+
+/// render `scene` with all `renderers` at `spp`, asserting
+/// their results agree with the first one's within `tol`
+pub fn assert_renderers_agree(scene: &TestScene, renderers: &[RendererKind], spp: usize, tol: Float) {
+    assert!(renderers.len() > 1, "nothing to compare against");
+    let reference = render(scene, renderers[0], spp);
+    for &kind in &renderers[1..] {
+        let image = render(scene, kind, spp);
+        let error = relative_mse(&downsample(&image, 4), &downsample(&reference, 4));
+        assert!(
+            error <= tol,
+            "{:?} disagrees with {:?}: relative MSE {} exceeds {}, means {:?} vs {:?}",
+            kind, renderers[0], error, tol, mean(&image), mean(&reference)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,347 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#[cfg(test)]
+mod consistency {
+    use super::super::*;
+
+    #[test]
+    fn test_furnace() {
+        let radiance = 0.5 as Float;
+        let scene = furnace(16, radiance);
+        let image = render(&scene, RendererKind::PT(4), 64);
+        let m = mean(&image);
+        for &c in &[m.r(), m.g(), m.b()] {
+            assert_relative_eq!(c, radiance, max_relative = 0.01 as Float);
+        }
+    }
+
+    #[test]
+    fn test_whitted_pt_direct() {
+        let scene = cornell_box(32);
+        assert_renderers_agree(
+            &scene, &[RendererKind::PT(1), RendererKind::Whitted], 64, 0.05 as Float
+        );
+    }
+
+    #[test]
+    fn test_pt_bpt_two_bounces() {
+        let scene = cornell_box(32);
+        assert_renderers_agree(
+            &scene, &[RendererKind::PT(2), RendererKind::BPT(2)], 16, 0.02 as Float
+        );
+    }
+
+    #[test]
+    fn test_pt_bpt_three_bounces() {
+        let scene = cornell_box(32);
+        assert_renderers_agree(
+            &scene, &[RendererKind::PT(3), RendererKind::BPT(3)], 16, 0.02 as Float
+        );
+    }
+}
+
+#[cfg(test)]
+mod lobe_depths {
+    use super::super::*;
+    use component::ComponentPointer;
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    #[test]
+    fn test_specular_depth() {
+        let scene = mirror_corridor(64);
+        let lit = |image: &Image| {
+            let dim = image.dimension();
+            let mut count = 0;
+            for y in 0..dim.y {
+                for x in 0..dim.x {
+                    if image[(x, y)].g() > 1e-3 as Float { count += 1; }
+                }
+            }
+            count
+        };
+        // each level of specular depth should reveal another reflection
+        let mut last = 0;
+        for specular in 1..4 {
+            let depths = LobeDepths{diffuse: 1, glossy: 1, specular: specular};
+            let count = lit(&render(&scene, RendererKind::PTLobes(16, depths), 16));
+            assert!(
+                count > last,
+                "no deeper reflections at specular depth {}: {} pixels lit after {}", specular, count, last
+            );
+            last = count;
+        }
+    }
+
+    #[test]
+    fn test_uniform_depths() {
+        // per-lobe depths as deep as the total leave paths as the total
+        // alone cuts them, as done before lobes were told apart
+        let unlimited = LobeDepths::uniform(usize::max_value());
+        for scene in &[cornell_box(32), mirror_corridor(32)] {
+            let reference = render(scene, RendererKind::PTLobes(3, unlimited), 4);
+            let image = render(scene, RendererKind::PTLobes(3, LobeDepths::uniform(3)), 4);
+            let dim = image.dimension();
+            for y in 0..dim.y {
+                for x in 0..dim.x {
+                    assert_eq!(image[(x, y)], reference[(x, y)], "at ({}, {})", x, y);
+                }
+            }
+        }
+    }
+
+    // run with `cargo test --release -- --ignored bench_specular_depth --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_specular_depth() {
+        // two wide facing mirrors, seen nearly head-on such that camera
+        // paths bounce between them till their specular depth runs out
+        let p = |x: Float, y: Float, z: Float| Point3f::new(x, y, z);
+        let mirror: Arc<Material> = Arc::new(MirrorMaterial::new(
+            Arc::new(ConstantTexture{value: RGBSpectrumf::grey_scale(0.9 as Float)}),
+            None
+        ));
+        let meshes = vec![
+            quad([p(-100., -100., 1.), p(100., -100., 1.), p(100., 100., 1.), p(-100., 100., 1.)], mirror.clone(), None),
+            quad([p(-100., -100., -1.), p(-100., 100., -1.), p(100., 100., -1.), p(100., -100., -1.)], mirror, None),
+        ];
+        let mut components: Vec<ComponentPointer> = Vec::new();
+        for mesh in &meshes {
+            components.extend(TriangleMesh::instances(mesh).map(ComponentPointer::from));
+        }
+        let lights: Vec<Arc<Light>> = vec![Arc::new(PointLight::new(p(0., 0.5, 0.), RGBSpectrumf::grey_scale(1. as Float)))];
+        let scene = TestScene{
+            scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+            camera: camera(128, p(0., 0., -0.9), p(0.1, 0.05, 2.), 0.3 as Float),
+        };
+        let time = |specular: usize| {
+            let depths = LobeDepths{diffuse: 1, glossy: 1, specular: specular};
+            let start = Instant::now();
+            render(&scene, RendererKind::PTLobes(64, depths), 4);
+            let elapsed = start.elapsed();
+            elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9
+        };
+        let depths = [4, 8, 16, 32];
+        let times: Vec<f64> = depths.iter().map(|&d| time(d)).collect();
+        for (d, t) in depths.iter().zip(&times) {
+            println!("specular depth {}: {:.4}s", d, t);
+        }
+        // the time per extra bounce stays about the same as paths deepen
+        let early = (times[1] - times[0]) / 4.;
+        let late = (times[3] - times[2]) / 16.;
+        assert!(late > 0.5 * early && late < 2. * early, "{:.4}s per bounce from 4 to 8, {:.4}s from 16 to 32", early, late);
+    }
+}
+
+#[cfg(test)]
+mod absorption {
+    use super::super::*;
+
+    #[test]
+    fn test_slab_transmittance() {
+        let ln2 = (2. as Float).ln();
+        let scene = glass_slab(16, RGBSpectrumf::new(0. as Float, ln2, ln2));
+        let m = mean(&render(&scene, RendererKind::PT(4), 4));
+        assert_relative_eq!(m.r(), 1. as Float, max_relative = 0.01 as Float);
+        assert_relative_eq!(m.g(), 0.5 as Float, max_relative = 0.01 as Float);
+        assert_relative_eq!(m.b(), 0.5 as Float, max_relative = 0.01 as Float);
+    }
+
+    #[test]
+    fn test_depth_tinting() {
+        let scene = glass_ball(32, RGBSpectrumf::new(0. as Float, 0.5 as Float, 0.5 as Float));
+        let image = render(&scene, RendererKind::PT(4), 4);
+        let tint = |x: u32| image[(x, 16)].g() / image[(x, 16)].r();
+        // paths through the center of the ball are the longest
+        let (center, off_center, outside) = (tint(16), tint(21), tint(31));
+        assert!(center < off_center, "{} >= {}", center, off_center);
+        assert!(off_center < outside, "{} >= {}", off_center, outside);
+        assert_relative_eq!(center, (-1. as Float).exp(), max_relative = 0.05 as Float);
+        assert_relative_eq!(outside, 1. as Float, max_relative = 0.01 as Float);
+    }
+}
+
+#[cfg(test)]
+mod sampler_dimensions {
+    use super::super::*;
+    use sample::strata::StdStrataSampler;
+    use std::sync::Mutex;
+    use std::collections::HashMap;
+    use rand::StdRng;
+
+    // (pixel, 2d?, dimension) -> values drawn across the pixel's samples
+    type Records = HashMap<(Point2<u32>, bool, usize), Vec<Point2f>>;
+
+    // a sampler recording the values it hands out per dimension
+    #[derive(Clone)]
+    struct Recording {
+        inner: StdStrataSampler,
+        records: Arc<Mutex<Records>>,
+        pixel: Point2<u32>,
+        dim1: usize,
+        dim2: usize,
+    }
+
+    impl Recording {
+        fn restart(&mut self) {
+            self.dim1 = 0;
+            self.dim2 = 0;
+        }
+
+        fn record(&self, is_2d: bool, dim: usize, value: Point2f) {
+            let mut records = self.records.lock().unwrap();
+            records.entry((self.pixel, is_2d, dim)).or_insert_with(Vec::new).push(value);
+        }
+    }
+
+    impl Sampler for Recording {
+        fn start_pixel(&mut self, p: Point2<u32>) {
+            self.inner.start_pixel(p);
+            self.pixel = p;
+            self.restart();
+        }
+
+        fn next(&mut self) -> Float {
+            let ret = self.inner.next();
+            let dim = self.dim1;
+            self.record(false, dim, Point2f::new(ret, 0. as Float));
+            self.dim1 += 1;
+            ret
+        }
+
+        fn next_2d(&mut self) -> Point2f {
+            let ret = self.inner.next_2d();
+            let dim = self.dim2;
+            self.record(true, dim, ret);
+            self.dim2 += 1;
+            ret
+        }
+
+        fn sample_per_pixel(&self) -> usize {
+            self.inner.sample_per_pixel()
+        }
+
+        fn set_sample_per_pixel(&mut self, spp: usize) {
+            self.inner.set_sample_per_pixel(spp);
+        }
+
+        fn next_sample(&mut self) -> bool {
+            self.restart();
+            self.inner.next_sample()
+        }
+
+        fn set_sample_index(&mut self, idx: usize) -> bool {
+            self.restart();
+            self.inner.set_sample_index(idx)
+        }
+
+        fn set_dimension(&mut self, d: usize) {
+            self.inner.set_dimension(d);
+            self.dim1 = d;
+            self.dim2 = d;
+        }
+    }
+
+    // a closed white sphere glowing inwards, with the camera at its
+    // center, so that no path terminates before roulette kicks in
+    fn closed_sphere() -> TestScene {
+        let mut sphere = ShapedPrimitive::new(
+            Sphere::new(2. as Float, -2. as Float, 2. as Float, float::pi() * 2. as Float),
+            matte(0.8 as Float, 0.8 as Float, 0.8 as Float),
+            Some(Arc::new(ConstantTexture{value: RGBSpectrumf::new(0.5 as Float, 0.5 as Float, 0.5 as Float)}))
+        );
+        sphere.emission_side = EmissionSide::Back;
+        let sphere = Arc::new(sphere);
+        let components: Vec<ComponentPointer> = vec![(sphere.clone() as Arc<Composable>).into()];
+        let lights: Vec<Arc<Light>> = vec![sphere];
+        TestScene{
+            scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+            camera: camera(
+                16,
+                Point3f::new(0. as Float, 0. as Float, 0. as Float),
+                Point3f::new(0. as Float, 0. as Float, 1. as Float),
+                float::frac_pi_2()
+            ),
+        }
+    }
+
+    #[test]
+    fn test_pt_stratification() {
+        let n = 4;
+        let records = Arc::new(Mutex::new(HashMap::new()));
+        // rotation shifts the strata off the grid checked against
+        let mut inner = StdStrataSampler::new(n, n, 8, StdRng::new().unwrap());
+        inner.set_decorrelate(false);
+        let sampler = Recording{
+            inner: inner,
+            records: records.clone(),
+            pixel: Point2::new(0, 0),
+            dim1: 0,
+            dim2: 0,
+        };
+        let scene = closed_sphere();
+        PTRenderer::new(sampler, scene.camera.clone(), "", 6, true).render_image(&scene.scene);
+        let records = records.lock().unwrap();
+        let nsample = (n * n) as usize;
+        for y in 0..16 {
+            for x in 0..16 {
+                let pixel = Point2::new(x, y);
+                for dim in 0..8 {
+                    // the first three bounces are never terminated
+                    let values = &records[&(pixel, true, dim)];
+                    assert_eq!(values.len(), nsample, "2d dimension {} not drawn by every sample", dim);
+                    let mut cells: Vec<_> = values.iter().map(|v| {
+                        ((v.x * n as Float) as usize, (v.y * n as Float) as usize)
+                    }).collect();
+                    cells.sort();
+                    cells.dedup();
+                    assert_eq!(cells.len(), nsample, "2d dimension {} of {:?} not stratified", dim, pixel);
+                    if let Some(values) = records.get(&(pixel, false, dim)) {
+                        if values.len() < nsample { continue; }
+                        let mut cells: Vec<_> = values.iter().map(|v| (v.x * nsample as Float) as usize).collect();
+                        cells.sort();
+                        cells.dedup();
+                        assert_eq!(cells.len(), nsample, "1d dimension {} of {:?} not stratified", dim, pixel);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod regularization {
+    use super::super::*;
+    use sample::strata::StdStrataSampler;
+    use rand::StdRng;
+
+    fn identical(a: &Image, b: &Image) -> bool {
+        let dim = a.dimension();
+        dim == b.dimension() && (0..dim.y).all(|y| (0..dim.x).all(|x| a[(x, y)] == b[(x, y)]))
+    }
+
+    #[test]
+    fn test_none_is_unregularized() {
+        let scene = glass_sphere_caustic(16);
+        let sampler = StdStrataSampler::new(2, 2, 8, StdRng::new().unwrap());
+        let camera = scene.camera.clone();
+
+        let reference = PTRenderer::new(sampler.clone(), camera.clone(), "", 6, true).render_image(&scene.scene);
+        let mut renderer = PTRenderer::new(sampler.clone(), camera.clone(), "", 6, true);
+        renderer.set_regularization(None);
+        assert!(identical(&reference, &renderer.render_image(&scene.scene)));
+        renderer.set_regularization(Some(0.3 as Float));
+        assert!(!identical(&reference, &renderer.render_image(&scene.scene)));
+
+        let reference = BPTRenderer::new(sampler.clone(), camera.clone(), "", 6).render_image(&scene.scene);
+        let mut renderer = BPTRenderer::new(sampler, camera, "", 6);
+        renderer.set_regularization(None);
+        assert!(identical(&reference, &renderer.render_image(&scene.scene)));
+    }
+}
@@ -55,6 +55,14 @@ pub trait Texture: Send + Sync {
 
     /// Mean value of the texture
     fn mean(&self) -> Self::Texel;
+
+    /// Level of detail `evaluate` filters at given the footprint
+    /// in `dxy`, as the base-2 log of the footprint's width in texels
+    /// of the finest level. `0` for textures without texels
+    #[inline]
+    fn footprint_level(&self, _si: &SurfaceInteraction, _dxy: &DxyInfo) -> Float {
+        0. as Float
+    }
 }
 
 impl<'a, T: 'a> Texture for &'a T
@@ -71,6 +79,11 @@ impl<'a, T: 'a> Texture for &'a T
     fn mean(&self) -> Self::Texel {
         (*self).mean()
     }
+
+    #[inline]
+    fn footprint_level(&self, si: &SurfaceInteraction, dxy: &DxyInfo) -> Float {
+        (*self).footprint_level(si, dxy)
+    }
 }
 
 impl<T: Texture + ?Sized> Texture for Arc<T> {
@@ -85,8 +98,16 @@ impl<T: Texture + ?Sized> Texture for Arc<T> {
     fn mean(&self) -> Self::Texel {
         (**self).mean()
     }
+
+    #[inline]
+    fn footprint_level(&self, si: &SurfaceInteraction, dxy: &DxyInfo) -> Float {
+        (**self).footprint_level(si, dxy)
+    }
 }
 
 pub mod mappings;
 pub mod textures;
 pub mod prelude;
+
+#[cfg(test)]
+mod tests;
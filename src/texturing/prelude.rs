@@ -8,5 +8,7 @@
 
 pub use super::{TexInfo2D, TexInfo3D, Mapping2D, Mapping3D, Texture};
 pub use super::mappings::*;
-pub use super::textures::{ConstantTexture, ProductTexture, MixTexture};
-pub use super::textures::image::{ImageTexture, ImageInfo, ImageWrapMode, MipMap, RGBImageTexture, LumaImageTexture, RGBMipMapHashTable, LumaMipMapHashTable};
+pub use super::textures::{ConstantTexture, ProductTexture, MixTexture, ScaleTexture, UvTransformTexture, ClampTexel, ClampTexture};
+pub use super::textures::image::{ImageTexture, ImageInfo, ImageWrapMode, DEFAULT_MAX_ANISO, MipMap, RGBImageTexture, LumaImageTexture, RGBMipMapHashTable, LumaMipMapHashTable, TextureError};
+pub use super::textures::fallback::{FallbackMode, PlaceholderTexture, TextureFallback, PLACEHOLDER_CHECKS};
+pub use super::textures::curve::{SplineMode, Spline, CurveTexture, ColorRamp, RampTexture};
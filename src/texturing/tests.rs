@@ -0,0 +1,568 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// tests
+use super::*;
+
+#[cfg(test)]
+mod test_mipmap {
+    use super::*;
+    use super::prelude::*;
+    use spectrum::*;
+    use prelude::*;
+    use component::ComponentPointer;
+    use test_utils::{self, RendererKind, TestScene};
+    use filming::film::Image;
+    use rand::{SeedableRng, StdRng};
+    use std::collections::HashMap;
+    use std::env;
+    use std::fs;
+    use image;
+    use tobj;
+
+    fn info(name: &str, wrapping: ImageWrapMode) -> ImageInfo {
+        ImageInfo{
+            name: env::temp_dir().join(name).into_os_string().into_string().unwrap(),
+            trilinear: false,
+            max_aniso: 16. as Float,
+            wrapping: wrapping,
+            gamma: false,
+            scale: 1. as Float,
+        }
+    }
+
+    #[test]
+    fn test_npot_pyramid() {
+        let info = info("arendur_test_npot_pyramid.png", ImageWrapMode::Clamp);
+        let corners = [[255u8, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 0]];
+        image::RgbImage::from_fn(3, 5, |x, y| {
+            image::Rgb{data: match (x, y) {
+                (0, 0) => corners[0],
+                (2, 0) => corners[1],
+                (0, 4) => corners[2],
+                (2, 4) => corners[3],
+                _ => [128, 128, 128],
+            }}
+        }).save(&info.name).unwrap();
+        let mipmap = MipMap::<Float, RGBSpectrumf>::new(info.clone()).unwrap();
+        let _ = fs::remove_file(&info.name);
+
+        let dimensions: Vec<_> = (0..mipmap.levels()).map(|i| mipmap.level_dimensions(i)).collect();
+        assert_eq!(dimensions, vec![(3, 5), (2, 3), (1, 2), (1, 1)]);
+
+        let zero = Vector2f::zero();
+        let st = [(0., 0.), (1., 0.), (0., 1.), (1., 1.)];
+        for (c, &(s, t)) in corners.iter().zip(st.iter()) {
+            let texel = mipmap.look_up(Point2f::new(s as Float, t as Float), zero, zero);
+            let expected = RGBSpectrumf::new(
+                c[0] as Float / 255., c[1] as Float / 255., c[2] as Float / 255.
+            );
+            assert_relative_eq!(texel.r(), expected.r(), epsilon = 1e-4);
+            assert_relative_eq!(texel.g(), expected.g(), epsilon = 1e-4);
+            assert_relative_eq!(texel.b(), expected.b(), epsilon = 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_rgba_source() {
+        // the same colors with and without a meaningless alpha channel
+        let color = |x: u32, y: u32| [(x * 37 % 256) as u8, (y * 53 % 256) as u8, ((x * y) % 256) as u8];
+        let rgb_info = info("arendur_test_rgba_source_rgb.png", ImageWrapMode::Clamp);
+        let rgba_info = info("arendur_test_rgba_source_rgba.png", ImageWrapMode::Clamp);
+        image::RgbImage::from_fn(9, 6, |x, y| image::Rgb{data: color(x, y)}).save(&rgb_info.name).unwrap();
+        image::RgbaImage::from_fn(9, 6, |x, y| {
+            let c = color(x, y);
+            image::Rgba{data: [c[0], c[1], c[2], ((x + y) * 29 % 256) as u8]}
+        }).save(&rgba_info.name).unwrap();
+        let rgb = MipMap::<Float, RGBSpectrumf>::new(rgb_info.clone()).unwrap();
+        let rgba = MipMap::<Float, RGBSpectrumf>::new(rgba_info.clone()).unwrap();
+        let _ = fs::remove_file(&rgb_info.name);
+        let _ = fs::remove_file(&rgba_info.name);
+
+        let zero = Vector2f::zero();
+        for y in 0..6 {
+            for x in 0..9 {
+                let st = Point2f::new((x as Float + 0.5) / 9., (y as Float + 0.5) / 6.);
+                let c = color(x, y);
+                let expected = RGBSpectrumf::new(c[0].to_norm(), c[1].to_norm(), c[2].to_norm());
+                assert_eq!(rgba.look_up(st, zero, zero), expected, "({}, {})", x, y);
+                // coarser levels are filtered from the same colors
+                let dst = Vector2f::new(0.3 as Float, 0.1 as Float);
+                assert_eq!(rgba.look_up(st, dst, zero), rgb.look_up(st, dst, zero));
+            }
+        }
+    }
+
+    #[test]
+    fn test_aspect_kept() {
+        // a 2:1 texture with a square in the middle, on a 2:1 emissive quad
+        let info = info("arendur_test_aspect_kept.png", ImageWrapMode::Clamp);
+        image::RgbImage::from_fn(50, 25, |x, y| {
+            let inside = x >= 17 && x < 33 && y >= 5 && y < 21;
+            image::Rgb{data: if inside { [255, 255, 255] } else { [0, 0, 0] }}
+        }).save(&info.name).unwrap();
+        let texture = RGBImageTexture::new_as_arc(
+            info.clone(),
+            UVMapping{
+                scaling: Vector2f::new(1. as Float, 1. as Float),
+                shifting: Vector2f::zero(),
+            },
+            &mut HashMap::new()
+        ).unwrap();
+        let _ = fs::remove_file(&info.name);
+
+        let model = tobj::Model::new(
+            tobj::Mesh::new(
+                vec![-1., -0.5, 0., 1., -0.5, 0., 1., 0.5, 0., -1., 0.5, 0.],
+                Vec::new(),
+                vec![0., 0., 1., 0., 1., 1., 0., 1.],
+                vec![0, 1, 2, 0, 2, 3],
+                None
+            ),
+            "quad".to_owned()
+        );
+        let mut mesh = TriangleMesh::from_model(
+            model, test_utils::matte(0. as Float, 0. as Float, 0. as Float), Some(texture)
+        );
+        mesh.set_emission_side(EmissionSide::Both);
+        let mesh = Arc::new(mesh);
+        let components: Vec<ComponentPointer> = TriangleMesh::instances(&mesh)
+            .map(|t| t.into()).collect();
+        let lights: Vec<Arc<Light>> = vec![Arc::new(MeshLight::new(mesh))];
+        let scene = TestScene{
+            scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+            camera: test_utils::camera(
+                64, Point3f::new(0. as Float, 0., -3.), Point3f::new(0. as Float, 0., 0.), 0.8 as Float
+            ),
+        };
+        let image = test_utils::render(&scene, RendererKind::Whitted, 1);
+        let (mut columns, mut rows) = (0, 0);
+        for i in 0..64 {
+            if (0..64).any(|j| image[(i, j)].r() > 0.5 as Float) { columns += 1; }
+            if (0..64).any(|j| image[(j, i)].r() > 0.5 as Float) { rows += 1; }
+        }
+        assert!(columns > 8);
+        assert!((columns as isize - rows as isize).abs() <= 1, "{}x{}", columns, rows);
+    }
+
+    // standard deviations along `s` and `t` in texels of the response of
+    // a single bright texel to look-ups with footprint `dst0` and `dst1`
+    fn blur(mipmap: &MipMap<Float, RGBSpectrumf>, dst0: Vector2f, dst1: Vector2f) -> (Float, Float) {
+        let (mut sum, mut ss, mut tt) = (0. as Float, 0. as Float, 0. as Float);
+        let n = 256;
+        let center = 32.5 as Float / 64. as Float;
+        for i in 0..n {
+            for j in 0..n {
+                let s = (i as Float + 0.5 as Float) / n as Float;
+                let t = (j as Float + 0.5 as Float) / n as Float;
+                let r = mipmap.look_up(Point2f::new(s, t), dst0, dst1).r();
+                sum += r;
+                ss += r * (s - center) * (s - center);
+                tt += r * (t - center) * (t - center);
+            }
+        }
+        ((ss / sum).sqrt() * 64. as Float, (tt / sum).sqrt() * 64. as Float)
+    }
+
+    #[test]
+    fn test_anisotropy_clamp() {
+        let name = info("arendur_test_anisotropy_clamp.png", ImageWrapMode::Black).name;
+        image::RgbImage::from_fn(64, 64, |x, y| {
+            image::Rgb{data: if x == 32 && y == 32 { [255, 255, 255] } else { [0, 0, 0] }}
+        }).save(&name).unwrap();
+        // a footprint a hundred times longer along `s`, as seen on floors
+        // at grazing angles
+        let dst0 = Vector2f::new(0.1 as Float, 0.);
+        let dst1 = Vector2f::new(0. as Float, 0.001);
+        let mut last = 0. as Float;
+        for &max_aniso in &[0.5 as Float, 1., 2., 4., 8., 16.] {
+            let mut info = info("arendur_test_anisotropy_clamp.png", ImageWrapMode::Black);
+            info.max_aniso = max_aniso;
+            let mipmap = MipMap::<Float, RGBSpectrumf>::new(info).unwrap();
+            let (sigma_s, sigma_t) = blur(&mipmap, dst0, dst1);
+            let ratio = sigma_s / sigma_t;
+            // the major axis stays major, and the clamp is honored
+            assert!(ratio > 0.95 as Float, "{}: {}", max_aniso, ratio);
+            assert!(ratio <= max_aniso.max(1. as Float) * 1.05 as Float, "{}: {}", max_aniso, ratio);
+            // with more anisotropy allowed, the minor axis only gets sharper
+            assert!(ratio >= last * 0.95 as Float, "{}: {} after {}", max_aniso, ratio, last);
+            last = ratio;
+        }
+        let _ = fs::remove_file(&name);
+        assert!(last > 4. as Float);
+    }
+
+    // a unit sphere textured by a smooth function of the world position
+    // through its lat-long parameterization, placed by `rotation`, lit
+    // from and viewed at its north pole and the meridian of its seam
+    fn render_latlong(name: &str, rotation: Matrix4f) -> Image {
+        let info = info(name, ImageWrapMode::LatLong);
+        let (nx, ny) = (128, 64);
+        image::RgbImage::from_fn(nx, ny, |x, y| {
+            let phi = (x as Float + 0.5 as Float) / nx as Float * 2. as Float * float::pi();
+            let theta = float::pi() * (1. as Float - (y as Float + 0.5 as Float) / ny as Float);
+            let local = Vector3f::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos());
+            let p = rotation.transform_vector(local);
+            let channel = |c: Float| ((0.5 as Float + 0.4 as Float * (2. as Float * c).sin()) * 255. as Float) as u8;
+            image::Rgb{data: [channel(p.x), channel(p.y), channel(p.z)]}
+        }).save(&info.name).unwrap();
+        let texture = RGBImageTexture::new_as_arc(
+            info.clone(),
+            UVMapping{
+                scaling: Vector2f::new(1. as Float, 1. as Float),
+                shifting: Vector2f::zero(),
+            },
+            &mut HashMap::new()
+        ).unwrap();
+        let _ = fs::remove_file(&info.name);
+
+        let sphere = ShapedPrimitive::new(
+            Sphere::new(1. as Float, -1. as Float, 1. as Float, float::pi() * 2. as Float),
+            Arc::new(MatteMaterial::new(texture, Arc::new(ConstantTexture{value: 0. as Float}), None)),
+            None
+        );
+        let sphere: Arc<Composable> = Arc::new(TransformedComposable::new(
+            sphere, Arc::new(rotation), Arc::new(rotation.invert().unwrap())
+        ));
+        let components: Vec<ComponentPointer> = vec![sphere.into()];
+        let eye = Point3f::new(1.2 as Float, 0.4, 2.8);
+        let lights: Vec<Arc<Light>> = vec![
+            Arc::new(PointLight::new(eye, RGBSpectrumf::new(9. as Float, 9. as Float, 9. as Float)))
+        ];
+        let scene = Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH)));
+        // seeded, such that both renders share their camera samples
+        let sampler = StdStrataSampler::new(1, 1, 4, StdRng::from_seed(&[7usize][..]));
+        let camera = test_utils::camera(64, eye, Point3f::new(0. as Float, 0., 0.), 0.8 as Float);
+        WhittedRenderer::new(sampler, camera, "", 0. as Float).render_image(&scene)
+    }
+
+    // `texture` as the emission of a quad filling the view, rendered
+    // with a seeded sampler
+    fn render_emissive_quad(texture: Arc<Texture<Texel=RGBSpectrumf>>) -> Image {
+        let model = tobj::Model::new(
+            tobj::Mesh::new(
+                vec![-1., -1., 0., 1., -1., 0., 1., 1., 0., -1., 1., 0.],
+                Vec::new(),
+                vec![0., 0., 1., 0., 1., 1., 0., 1.],
+                vec![0, 1, 2, 0, 2, 3],
+                None
+            ),
+            "quad".to_owned()
+        );
+        let mut mesh = TriangleMesh::from_model(
+            model, test_utils::matte(0. as Float, 0. as Float, 0. as Float), Some(texture)
+        );
+        mesh.set_emission_side(EmissionSide::Both);
+        let mesh = Arc::new(mesh);
+        let components: Vec<ComponentPointer> = TriangleMesh::instances(&mesh)
+            .map(|t| t.into()).collect();
+        let lights: Vec<Arc<Light>> = vec![Arc::new(MeshLight::new(mesh))];
+        let scene = Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH)));
+        let sampler = StdStrataSampler::new(2, 2, 4, StdRng::from_seed(&[7usize][..]));
+        let camera = test_utils::camera(
+            32, Point3f::new(0. as Float, 0., -1.), Point3f::new(0. as Float, 0., 0.), float::frac_pi_2()
+        );
+        WhittedRenderer::new(sampler, camera, "", 0. as Float).render_image(&scene)
+    }
+
+    #[test]
+    fn test_from_buffer() {
+        let info = info("arendur_test_from_buffer.png", ImageWrapMode::Repeat);
+        let buffer = image::RgbImage::from_fn(13, 7, |x, y| {
+            image::Rgb{data: [(x * 19) as u8, (y * 36) as u8, ((x + y) % 2 * 255) as u8]}
+        });
+        buffer.save(&info.name).unwrap();
+        let mapping = UVMapping{
+            scaling: Vector2f::new(1. as Float, 1. as Float),
+            shifting: Vector2f::zero(),
+        };
+        let loaded = RGBImageTexture::new_as_arc(info.clone(), mapping, &mut HashMap::new()).unwrap();
+        let _ = fs::remove_file(&info.name);
+
+        let mut table = HashMap::new();
+        let from_buffer: Arc<Texture<Texel=RGBSpectrumf>> = Arc::new(RGBImageTexture::from_buffer(
+            "procedural", buffer, info.clone(), mapping, &mut table
+        ).unwrap());
+        assert!(table.keys().all(|key| key.name == "procedural"));
+        assert_eq!(loaded.mean(), from_buffer.mean());
+
+        let (a, b) = (render_emissive_quad(loaded), render_emissive_quad(from_buffer.clone()));
+        assert!(a[(16, 16)].r() > 0. as Float);
+        for y in 0..32 {
+            for x in 0..32 {
+                assert_eq!(a[(x, y)], b[(x, y)], "({}, {})", x, y);
+            }
+        }
+
+        // a mipmap alive under the id is shared
+        let black = image::RgbImage::new(2, 2);
+        let shared = RGBImageTexture::from_buffer("procedural", black, info, mapping, &mut table).unwrap();
+        assert_eq!(shared.mean(), from_buffer.mean());
+    }
+
+    #[test]
+    fn test_latlong_sphere() {
+        // the same sphere, with its poles and seam turned away from the
+        // view by the rotation its texture is generated with
+        let straight = render_latlong("arendur_test_latlong_straight.png", Matrix4f::identity());
+        let turned = render_latlong(
+            "arendur_test_latlong_turned.png", Matrix4f::from_angle_x(Rad(float::frac_pi_2()))
+        );
+        // silhouettes are left out, where the slightest difference
+        // in the hits turns a sample into a miss
+        let lit = |image: &Image, x: u32, y: u32| image[(x, y)].r() > 0. as Float;
+        let (mut max, mut sum, mut count) = (0. as Float, 0. as Float, 0);
+        for y in 1..63 {
+            for x in 1..63 {
+                let inside = (y-1..y+2).all(|j| (x-1..x+2).all(|i| lit(&straight, i, j) && lit(&turned, i, j)));
+                if !inside { continue; }
+                let (a, b) = (straight[(x, y)], turned[(x, y)]);
+                for &(a, b) in &[(a.r(), b.r()), (a.g(), b.g()), (a.b(), b.b())] {
+                    assert!(a.is_finite() && b.is_finite(), "({}, {})", x, y);
+                    max = max.max((a - b).abs());
+                    sum += (a - b).abs();
+                    count += 1;
+                }
+            }
+        }
+        assert!(count > 3000);
+        assert!(max < 0.04 as Float, "{}", max);
+        assert!(sum / (count as Float) < 0.003 as Float, "{}", sum / count as Float);
+    }
+}
+
+#[cfg(test)]
+mod test_adapters {
+    use super::*;
+    use super::prelude::*;
+    use spectrum::*;
+    use prelude::*;
+    use component::ComponentPointer;
+    use test_utils::{self, RendererKind, TestScene};
+    use filming::film::Image;
+    use tobj;
+
+    // a 2x2 white and black checker over the unit uv square,
+    // white at the origin
+    struct Checker;
+
+    impl Texture for Checker {
+        type Texel = RGBSpectrumf;
+
+        fn evaluate(&self, si: &SurfaceInteraction, _dxy: &DxyInfo) -> RGBSpectrumf {
+            let cell = (si.uv.x * 2. as Float).floor() + (si.uv.y * 2. as Float).floor();
+            RGBSpectrumf::grey_scale(if cell as isize % 2 == 0 { 1. as Float } else { 0. as Float })
+        }
+
+        fn mean(&self) -> RGBSpectrumf {
+            RGBSpectrumf::grey_scale(0.5 as Float)
+        }
+    }
+
+    fn si(uv: Point2f) -> SurfaceInteraction<'static> {
+        SurfaceInteraction::new(
+            Point3f::new(0. as Float, 0., 0.), Vector3f::zero(), Vector3f::new(0. as Float, 0., 1.), uv,
+            DuvInfo{
+                dpdu: Vector3f::new(1. as Float, 0., 0.),
+                dpdv: Vector3f::new(0. as Float, 1., 0.),
+                dndu: Vector3f::zero(),
+                dndv: Vector3f::zero(),
+            }
+        )
+    }
+
+    #[test]
+    fn test_scale_and_clamp() {
+        let constant = ConstantTexture{value: RGBSpectrumf::new(0.2 as Float, 0.4 as Float, 0.8 as Float)};
+        let scaled = ScaleTexture{inner: constant, scale: 2. as Float};
+        let si = si(Point2f::new(0.3 as Float, 0.7 as Float));
+        let dxy = DxyInfo::from_duv(&si.duv);
+        assert_eq!(scaled.evaluate(&si, &dxy), RGBSpectrumf::new(0.4 as Float, 0.8 as Float, 1.6 as Float));
+        assert_eq!(scaled.mean(), scaled.evaluate(&si, &dxy));
+        let clamped = ClampTexture{inner: scaled, min: 0.5 as Float, max: 1. as Float};
+        assert_eq!(clamped.evaluate(&si, &dxy), RGBSpectrumf::new(0.5 as Float, 0.8 as Float, 1. as Float));
+        let gray = ClampTexture{inner: ScaleTexture{inner: ConstantTexture{value: 3. as Float}, scale: -1. as Float}, min: -2. as Float, max: 0. as Float};
+        assert_eq!(gray.evaluate(&si, &dxy), -2. as Float);
+    }
+
+    // `Checker` rotated by `angle` around the center of the uv square,
+    // rendered as the emission of a quad filling the view
+    fn render_rotated(angle: Float) -> Image {
+        let center = Vector2f::new(0.5 as Float, 0.5 as Float);
+        let (sin, cos) = angle.sin_cos();
+        let rotated = Vector2f::new(cos * center.x - sin * center.y, sin * center.x + cos * center.y);
+        let texture: Arc<Texture<Texel=RGBSpectrumf>> = Arc::new(UvTransformTexture{
+            inner: Checker,
+            scaling: Vector2f::new(1. as Float, 1. as Float),
+            shifting: center - rotated,
+            rotation: angle,
+        });
+        render_emission(texture)
+    }
+
+    // `texture` as the emission of a quad filling the view, its uv
+    // square spanning $[-1, 1]^2$ in the view
+    fn render_emission(texture: Arc<Texture<Texel=RGBSpectrumf>>) -> Image {
+        let model = tobj::Model::new(
+            tobj::Mesh::new(
+                vec![-1., -1., 0., 1., -1., 0., 1., 1., 0., -1., 1., 0.],
+                Vec::new(),
+                vec![0., 0., 1., 0., 1., 1., 0., 1.],
+                vec![0, 1, 2, 0, 2, 3],
+                None
+            ),
+            "quad".to_owned()
+        );
+        let mut mesh = TriangleMesh::from_model(
+            model, test_utils::matte(0. as Float, 0. as Float, 0. as Float), Some(texture)
+        );
+        mesh.set_emission_side(EmissionSide::Both);
+        let mesh = Arc::new(mesh);
+        let components: Vec<ComponentPointer> = TriangleMesh::instances(&mesh)
+            .map(|t| t.into()).collect();
+        let lights: Vec<Arc<Light>> = vec![Arc::new(MeshLight::new(mesh))];
+        let scene = TestScene{
+            scene: Scene::new(lights, Arc::new(BVH::new(&components, BVHStrategy::SAH))),
+            camera: test_utils::camera(
+                32, Point3f::new(0. as Float, 0., -1.), Point3f::new(0. as Float, 0., 0.), float::frac_pi_2()
+            ),
+        };
+        test_utils::render(&scene, RendererKind::Whitted, 1)
+    }
+
+    // the u coordinate, a linear gradient over the uv square
+    struct Gradient;
+
+    impl Texture for Gradient {
+        type Texel = Float;
+
+        fn evaluate(&self, si: &SurfaceInteraction, _dxy: &DxyInfo) -> Float {
+            si.uv.x
+        }
+
+        fn mean(&self) -> Float {
+            0.5 as Float
+        }
+    }
+
+    #[test]
+    fn test_ramp_bands() {
+        let (red, blue) = (RGBSpectrumf::new(1. as Float, 0., 0.), RGBSpectrumf::new(0. as Float, 0., 1.));
+        let ramp = ColorRamp::new(vec![(0.25 as Float, red), (0.75, blue)]);
+        assert!(ramp.is_valid());
+        let image = render_emission(Arc::new(RampTexture{driver: Gradient, ramp: ramp.clone()}));
+        let n = 32;
+        for y in 0..n {
+            for x in 0..n {
+                let u = (x as Float + 0.5 as Float) / n as Float;
+                let pixel = image[(x, y)];
+                if u < 0.2 as Float {
+                    assert_eq!(pixel, red, "({}, {})", x, y);
+                } else if u > 0.8 as Float {
+                    assert_eq!(pixel, blue, "({}, {})", x, y);
+                } else {
+                    let expected = ramp.evaluate(u);
+                    // samples jitter by half a pixel, over which the ramp
+                    // changes by $1/32$
+                    let tolerance = 1. as Float / n as Float + 0.001 as Float;
+                    assert!((pixel.r() - expected.r()).abs() < tolerance && (pixel.b() - expected.b()).abs() < tolerance,
+                        "({}, {}): {:?} against {:?}", x, y, pixel, expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_uv_rotation() {
+        let n = 32;
+        // pixel centers in $[-1, 1]^2$, the quad's extent
+        let coord = |i: u32| (i as Float + 0.5 as Float) / n as Float * 2. as Float - 1. as Float;
+        let bright = |image: &Image, x: u32, y: u32| image[(x, y)].r() > 0.5 as Float;
+
+        // rotated by a right angle, the checker is inverted
+        let (upright, quarter) = (render_rotated(0. as Float), render_rotated(float::frac_pi_2()));
+        for y in 0..n {
+            for x in 0..n {
+                if coord(x).abs() < 0.1 || coord(y).abs() < 0.1 { continue; }
+                assert!(bright(&upright, x, y) != bright(&quarter, x, y), "({}, {})", x, y);
+            }
+        }
+
+        // rotated by half of that, diagonals of the cells align with the
+        // axes, with wedges around either axis sharing a color inside
+        // the diamond bounded by the neighbouring cells
+        let eighth = render_rotated(float::frac_pi_4());
+        let on_x_axis = bright(&eighth, n * 3 / 4, n / 2);
+        assert_eq!(on_x_axis, bright(&eighth, n / 4, n / 2));
+        assert!(on_x_axis != bright(&eighth, n / 2, n / 4));
+        for y in 0..n {
+            for x in 0..n {
+                let (u, v) = (coord(x).abs(), coord(y).abs());
+                if (u - v).abs() < 0.15 || u + v > 1.2 { continue; }
+                assert_eq!(bright(&eighth, x, y), (u > v) == on_x_axis, "({}, {})", x, y);
+            }
+        }
+    }
+}
+
+mod test_curve {
+    use super::*;
+    use super::prelude::*;
+
+    fn rising() -> Vec<(Float, Float)> {
+        // a steep step between flat stretches, where cubics overshoot
+        vec![(0. as Float, 0. as Float), (1., 0.), (1.2, 1.), (2., 1.), (3., 1.5)]
+    }
+
+    #[test]
+    fn test_monotone() {
+        let monotone = Spline::new(SplineMode::Monotone, rising());
+        let catmull_rom = Spline::new(SplineMode::CatmullRom, rising());
+        assert!(monotone.is_valid());
+        let mut overshot = false;
+        let mut last = monotone.evaluate(0. as Float);
+        for i in 0..3001 {
+            let x = i as Float / 1000. as Float;
+            let y = monotone.evaluate(x);
+            assert!(y >= last, "{} decreasing at {}", y, x);
+            last = y;
+            // each segment stays between its control values
+            let points = rising();
+            let segment = points.windows(2).find(|w| x >= w[0].0 && x <= w[1].0).unwrap();
+            assert!(y >= segment[0].1 && y <= segment[1].1, "{} overshot at {}", y, x);
+            let y = catmull_rom.evaluate(x);
+            overshot |= y < 0. as Float || (y > 1. as Float && x < 2. as Float);
+        }
+        assert!(overshot);
+        for &(x, y) in &rising() {
+            assert_eq!(monotone.evaluate(x), y);
+            assert_eq!(catmull_rom.evaluate(x), y);
+        }
+    }
+
+    #[test]
+    fn test_endpoints_clamp() {
+        for &mode in &[SplineMode::Monotone, SplineMode::CatmullRom] {
+            let spline = Spline::new(mode, rising());
+            assert_eq!(spline.evaluate(-5. as Float), 0. as Float);
+            assert_eq!(spline.evaluate(10. as Float), 1.5 as Float);
+            assert_eq!(spline.evaluate(float::nan()), 0. as Float);
+        }
+        let single = Spline::new(SplineMode::Monotone, vec![(0.5 as Float, 0.25 as Float)]);
+        assert_eq!(single.evaluate(0. as Float), 0.25 as Float);
+        assert_eq!(single.evaluate(1. as Float), 0.25 as Float);
+        assert!(!Spline::new(SplineMode::Monotone, vec![(1. as Float, 0. as Float), (0., 1.)]).is_valid());
+        assert!(!Spline::new(SplineMode::Monotone, Vec::new()).is_valid());
+
+        let gamma = CurveTexture{
+            inner: ConstantTexture{value: 2. as Float},
+            curve: Spline::new(SplineMode::Monotone, vec![(0. as Float, 0. as Float), (1., 1.)]),
+        };
+        assert_eq!(gamma.mean(), 1. as Float);
+    }
+}
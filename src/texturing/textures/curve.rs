@@ -0,0 +1,205 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Curves remapping the values of other textures.
+
+use super::*;
+use spectrum::RGBSpectrumf;
+
+/// How a `Spline` interpolates between its control points
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SplineMode {
+    /// monotone cubic, never overshooting the control values
+    Monotone,
+    /// Catmull-Rom, smoother but possibly overshooting
+    CatmullRom,
+}
+
+impl Default for SplineMode {
+    fn default() -> SplineMode {
+        SplineMode::Monotone
+    }
+}
+
+/// A 1D curve through control points, flat beyond the first and last ones
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Spline {
+    #[serde(default)]
+    pub mode: SplineMode,
+    /// control points as `(x, y)`, ordered by `x`
+    pub points: Vec<(Float, Float)>,
+}
+
+impl Spline {
+    /// construction
+    #[inline]
+    pub fn new(mode: SplineMode, points: Vec<(Float, Float)>) -> Spline {
+        Spline{ mode: mode, points: points }
+    }
+
+    /// test if the curve is well defined, namely with finite
+    /// control points strictly increasing in `x`
+    pub fn is_valid(&self) -> bool {
+        !self.points.is_empty()
+            && self.points.iter().all(|&(x, y)| x.is_finite() && y.is_finite())
+            && self.points.windows(2).all(|w| w[0].0 < w[1].0)
+    }
+
+    /// value of the curve at `x`
+    pub fn evaluate(&self, x: Float) -> Float {
+        let n = self.points.len();
+        // also taking NaNs to the first point
+        if !(x > self.points[0].0) { return self.points[0].1; }
+        if x >= self.points[n - 1].0 { return self.points[n - 1].1; }
+        // the segment `[i, i + 1]` containing `x`
+        let i = match self.points.binary_search_by(|p| p.0.partial_cmp(&x).unwrap()) {
+            Ok(i) => return self.points[i].1,
+            Err(i) => i - 1,
+        };
+        let (x0, y0) = self.points[i];
+        let (x1, y1) = self.points[i + 1];
+        let h = x1 - x0;
+        let t = (x - x0) / h;
+        let (t2, t3) = (t * t, t * t * t);
+        // cubic hermite basis
+        let h00 = 2. as Float * t3 - 3. as Float * t2 + 1. as Float;
+        let h10 = t3 - 2. as Float * t2 + t;
+        let h01 = -2. as Float * t3 + 3. as Float * t2;
+        let h11 = t3 - t2;
+        h00 * y0 + h10 * h * self.tangent(i) + h01 * y1 + h11 * h * self.tangent(i + 1)
+    }
+
+    // slope of the secant from point `i` to point `i + 1`
+    #[inline]
+    fn secant(&self, i: usize) -> Float {
+        let (x0, y0) = self.points[i];
+        let (x1, y1) = self.points[i + 1];
+        (y1 - y0) / (x1 - x0)
+    }
+
+    // slope of the curve at point `i`
+    fn tangent(&self, i: usize) -> Float {
+        let n = self.points.len();
+        if i == 0 { return self.secant(0); }
+        if i == n - 1 { return self.secant(n - 2); }
+        match self.mode {
+            SplineMode::CatmullRom => {
+                let (x0, y0) = self.points[i - 1];
+                let (x1, y1) = self.points[i + 1];
+                (y1 - y0) / (x1 - x0)
+            },
+            // Fritsch-Carlson, with tangents limited to three times the
+            // adjacent secants so that each is computed locally
+            SplineMode::Monotone => {
+                let (d0, d1) = (self.secant(i - 1), self.secant(i));
+                if d0 * d1 <= 0. as Float {
+                    0. as Float
+                } else {
+                    let m = (d0 + d1) * 0.5 as Float;
+                    let limit = 3. as Float * d0.abs().min(d1.abs());
+                    m.signum() * m.abs().min(limit)
+                }
+            },
+        }
+    }
+}
+
+/// Texture adapter remapping the values of `inner` through `curve`
+#[derive(Clone, Debug, PartialEq)]
+pub struct CurveTexture<T> {
+    pub inner: T,
+    pub curve: Spline,
+}
+
+impl<T> Texture for CurveTexture<T>
+    where T: Texture<Texel=Float>
+{
+    type Texel = Float;
+
+    #[inline]
+    fn evaluate(&self, si: &SurfaceInteraction, dxy: &DxyInfo) -> Float {
+        self.curve.evaluate(self.inner.evaluate(si, dxy))
+    }
+
+    /// approximated by the curve at the mean of `inner`, exact
+    /// only if the curve is linear over the values of `inner`
+    #[inline]
+    fn mean(&self) -> Float {
+        self.curve.evaluate(self.inner.mean())
+    }
+
+    #[inline]
+    fn footprint_level(&self, si: &SurfaceInteraction, dxy: &DxyInfo) -> Float {
+        self.inner.footprint_level(si, dxy)
+    }
+}
+
+/// A color gradient, interpolated linearly between its stops and
+/// flat beyond the first and last ones
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ColorRamp {
+    /// positions and colors, ordered by position
+    pub stops: Vec<(Float, RGBSpectrumf)>,
+}
+
+impl ColorRamp {
+    /// construction
+    #[inline]
+    pub fn new(stops: Vec<(Float, RGBSpectrumf)>) -> ColorRamp {
+        ColorRamp{ stops: stops }
+    }
+
+    /// test if the gradient is well defined, namely with finite
+    /// positions, non-decreasing
+    pub fn is_valid(&self) -> bool {
+        !self.stops.is_empty()
+            && self.stops.iter().all(|s| s.0.is_finite())
+            && self.stops.windows(2).all(|w| w[0].0 <= w[1].0)
+    }
+
+    /// color of the gradient at `x`
+    pub fn evaluate(&self, x: Float) -> RGBSpectrumf {
+        let n = self.stops.len();
+        if !(x > self.stops[0].0) { return self.stops[0].1; }
+        if x >= self.stops[n - 1].0 { return self.stops[n - 1].1; }
+        let i = self.stops.iter().position(|s| s.0 > x).unwrap();
+        let (x0, c0) = self.stops[i - 1];
+        let (x1, c1) = self.stops[i];
+        let t = (x - x0) / (x1 - x0);
+        c0 * (1. as Float - t) + c1 * t
+    }
+}
+
+/// Texture mapping the values of `driver` through `ramp`
+#[derive(Clone, Debug, PartialEq)]
+pub struct RampTexture<T> {
+    pub driver: T,
+    pub ramp: ColorRamp,
+}
+
+impl<T> Texture for RampTexture<T>
+    where T: Texture<Texel=Float>
+{
+    type Texel = RGBSpectrumf;
+
+    #[inline]
+    fn evaluate(&self, si: &SurfaceInteraction, dxy: &DxyInfo) -> RGBSpectrumf {
+        self.ramp.evaluate(self.driver.evaluate(si, dxy))
+    }
+
+    /// approximated by the color at the mean of `driver`
+    #[inline]
+    fn mean(&self) -> RGBSpectrumf {
+        self.ramp.evaluate(self.driver.mean())
+    }
+
+    #[inline]
+    fn footprint_level(&self, si: &SurfaceInteraction, dxy: &DxyInfo) -> Float {
+        self.driver.footprint_level(si, dxy)
+    }
+}
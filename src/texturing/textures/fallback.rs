@@ -0,0 +1,171 @@
+// Copyright 2017 Dasein Phaos aka. Luxko
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Substitutes for image textures failing to load
+
+use super::*;
+use super::image::TextureError;
+use spectrum::{Spectrum, RGBSpectrumf};
+use std::collections::HashSet;
+use std::ops;
+use std::sync::Arc;
+
+/// checks along each of `u` and `v` of a `PlaceholderTexture`
+pub const PLACEHOLDER_CHECKS: Float = 8. as Float;
+
+/// What becomes of image textures failing to load
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FallbackMode {
+    /// the constant given alongside the texture, e.g. `Kd` for `map_Kd`
+    /// in `.mtl` files, or nothing where there's no such constant
+    Parameter,
+    /// fail with the path of the texture and the reason why
+    Error,
+    /// a constant of this value on each channel
+    Constant(Float),
+    /// a magenta and black checkerboard, standing out in renders
+    CheckerPlaceholder,
+}
+
+impl Default for FallbackMode {
+    #[inline]
+    fn default() -> FallbackMode {
+        FallbackMode::Parameter
+    }
+}
+
+/// A checkerboard of `on` and `off` over the `uv` of the surface,
+/// `PLACEHOLDER_CHECKS` along each direction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlaceholderTexture<T> {
+    pub on: T,
+    pub off: T,
+}
+
+impl PlaceholderTexture<RGBSpectrumf> {
+    /// magenta and black
+    #[inline]
+    pub fn rgb() -> PlaceholderTexture<RGBSpectrumf> {
+        PlaceholderTexture{
+            on: RGBSpectrumf::new(1. as Float, 0. as Float, 1. as Float),
+            off: RGBSpectrumf::black(),
+        }
+    }
+}
+
+impl PlaceholderTexture<Float> {
+    /// white and black
+    #[inline]
+    pub fn gray() -> PlaceholderTexture<Float> {
+        PlaceholderTexture{
+            on: 1. as Float,
+            off: 0. as Float,
+        }
+    }
+}
+
+impl<T> Texture for PlaceholderTexture<T>
+    where T: Clone + Send + Sync + ops::Add<Output=T> + ops::Mul<Float, Output=T>
+{
+    type Texel = T;
+
+    #[inline]
+    fn evaluate(&self, si: &SurfaceInteraction, _dxy: &DxyInfo) -> T {
+        let u = (si.uv.x * PLACEHOLDER_CHECKS).floor() as i64;
+        let v = (si.uv.y * PLACEHOLDER_CHECKS).floor() as i64;
+        if (u + v) % 2 == 0 { self.on.clone() } else { self.off.clone() }
+    }
+
+    #[inline]
+    fn mean(&self) -> T {
+        (self.on.clone() + self.off.clone()) * (0.5 as Float)
+    }
+}
+
+/// Substitutes image textures failing to load as its mode says,
+/// warning once per path substituted
+#[derive(Clone, Debug, Default)]
+pub struct TextureFallback {
+    mode: FallbackMode,
+    substituted: HashSet<String>,
+}
+
+impl TextureFallback {
+    /// construction substituting as `mode` says
+    #[inline]
+    pub fn new(mode: FallbackMode) -> TextureFallback {
+        TextureFallback{
+            mode: mode,
+            substituted: HashSet::new(),
+        }
+    }
+
+    #[inline]
+    pub fn mode(&self) -> FallbackMode {
+        self.mode
+    }
+
+    /// substitute as `mode` says from now on, keeping the paths
+    /// already warned about
+    #[inline]
+    pub fn set_mode(&mut self, mode: FallbackMode) {
+        self.mode = mode;
+    }
+
+    /// number of paths substituted or left out so far, each counted once
+    #[inline]
+    pub fn substitutions(&self) -> usize {
+        self.substituted.len()
+    }
+
+    /// substitute for an rgb texture failing with `error`, whose
+    /// constant is `parameter` if any. Fails under `FallbackMode::Error`,
+    /// and gives nothing if asked to substitute a missing parameter
+    pub fn rgb(
+        &mut self, error: TextureError, parameter: Option<RGBSpectrumf>
+    ) -> Result<Option<Arc<Texture<Texel=RGBSpectrumf>>>, TextureError> {
+        let ret: Option<Arc<Texture<Texel=RGBSpectrumf>>> = match self.mode {
+            FallbackMode::Error => return Err(error),
+            FallbackMode::Parameter => parameter.map(|value| {
+                Arc::new(ConstantTexture{value}) as Arc<Texture<Texel=RGBSpectrumf>>
+            }),
+            FallbackMode::Constant(value) => Some(Arc::new(ConstantTexture{
+                value: RGBSpectrumf::grey_scale(value)
+            })),
+            FallbackMode::CheckerPlaceholder => Some(Arc::new(PlaceholderTexture::rgb())),
+        };
+        self.note(&error, ret.is_some());
+        Ok(ret)
+    }
+
+    /// substitute for a gray texture failing with `error`, as `rgb` does
+    pub fn gray(
+        &mut self, error: TextureError, parameter: Option<Float>
+    ) -> Result<Option<Arc<Texture<Texel=Float>>>, TextureError> {
+        let ret: Option<Arc<Texture<Texel=Float>>> = match self.mode {
+            FallbackMode::Error => return Err(error),
+            FallbackMode::Parameter => parameter.map(|value| {
+                Arc::new(ConstantTexture{value}) as Arc<Texture<Texel=Float>>
+            }),
+            FallbackMode::Constant(value) => Some(Arc::new(ConstantTexture{value})),
+            FallbackMode::CheckerPlaceholder => Some(Arc::new(PlaceholderTexture::gray())),
+        };
+        self.note(&error, ret.is_some());
+        Ok(ret)
+    }
+
+    fn note(&mut self, error: &TextureError, substituted: bool) {
+        if self.substituted.insert(error.path().to_owned()) {
+            if substituted {
+                warn!("{}, substituting {:?}", error, self.mode);
+            } else {
+                warn!("{}, leaving it out", error);
+            }
+        }
+    }
+}
@@ -16,6 +16,7 @@ use std::hash::{Hash, Hasher};
 use std::sync::{Arc, Weak};
 use std::collections::HashMap;
 use std::collections::hash_map::Entry;
+use std::fmt;
 extern crate image;
 use self::image::GenericImage;
 use self::image::Pixel;
@@ -23,6 +24,7 @@ use self::image::Luma;
 use spectrum::{RGBSpectrum, ToNorm};
 use num_traits::NumCast;
 use sample::distribution::Distribution2D;
+use component::progress::{self, LoadPhase, LoadProgress};
 
 /// an image texture
 pub struct ImageTexture<TM, TP, M>
@@ -39,6 +41,36 @@ pub type LumaImageTexture<TM, M> = ImageTexture<TM, Luma<TM>, M>;
 pub type RGBMipMapHashTable<TM> = HashMap<ImageInfo, Weak<MipMap<TM, RGBSpectrum<TM>>>>;
 pub type LumaMipMapHashTable<TM> = HashMap<ImageInfo, Weak<MipMap<TM, Luma<TM>>>>;
 
+/// Reasons an image texture can't be constructed
+#[derive(Debug)]
+pub enum TextureError {
+    /// the image at the path can't be opened or decoded
+    Open{path: String, error: image::ImageError},
+    /// the image at the path, or under the id, has no pixel
+    Empty{path: String},
+}
+
+impl TextureError {
+    /// path of the image, or id of the buffer, failing to load
+    #[inline]
+    pub fn path(&self) -> &str {
+        match *self {
+            TextureError::Open{ref path, ..} | TextureError::Empty{ref path} => path,
+        }
+    }
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TextureError::Open{ref path, ref error} => write!(
+                f, "can't load texture image {:?}: {}", path, error
+            ),
+            TextureError::Empty{ref path} => write!(f, "texture image {:?} is empty", path),
+        }
+    }
+}
+
 impl<TM, TP, M> ImageTexture<TM, TP, M>
     where TM: BaseNum + image::Primitive + 'static,
           TP: Pixel<Subpixel=TM> + 'static,
@@ -52,6 +84,39 @@ impl<TM, TP, M> ImageTexture<TM, TP, M>
         let floats: Vec<_> = self.mipmap.pyramid[0].pixels().map(f).collect();
         Distribution2D::new(&floats, u as usize)
     }
+
+    // a texture of the mipmap alive under `info` in `ref_table`,
+    // or of the one `load`ed and registered there in its absence
+    fn cached<F>(
+        info: ImageInfo,
+        mapping: M,
+        ref_table: &mut HashMap<ImageInfo, Weak<MipMap<TM, TP>>>,
+        load: F
+    ) -> Result<Self, TextureError>
+        where F: FnOnce(ImageInfo) -> Result<MipMap<TM, TP>, TextureError>
+    {
+        let try_strong = match ref_table.entry(info.clone()) {
+            Entry::Occupied(oe) => {
+                oe.get().clone().upgrade()
+            },
+            Entry::Vacant(_) => {
+                None
+            },
+        };
+        if let Some(mipmap) = try_strong {
+            Ok(ImageTexture{
+                mapping: mapping,
+                mipmap: mipmap,
+            })
+        } else {
+            let mipmap = Arc::new(load(info.clone())?);
+            ref_table.insert(info, Arc::downgrade(&mipmap));
+            Ok(ImageTexture{
+                mapping: mapping,
+                mipmap: mipmap,
+            })
+        }
+    }
 }
 
 // unsafe impl<T: BaseNum + image::Primitive, M> Sync for ImageTexture<T, M> { }
@@ -73,6 +138,12 @@ impl<TM, M> Texture for ImageTexture<TM, RGBSpectrum<TM>, M>
     fn mean(&self) -> Self::Texel {
         self.mipmap.mean
     }
+
+    #[inline]
+    fn footprint_level(&self, si: &SurfaceInteraction, dxy: &DxyInfo) -> Float {
+        let t2dinfo = self.mapping.map(si, dxy);
+        self.mipmap.footprint_level(t2dinfo.dpdx, t2dinfo.dpdy)
+    }
 }
 
 impl<TM, M> Texture for ImageTexture<TM, Luma<TM>, M>
@@ -91,6 +162,12 @@ impl<TM, M> Texture for ImageTexture<TM, Luma<TM>, M>
     fn mean(&self) -> Self::Texel {
         self.mipmap.mean.data[0]
     }
+
+    #[inline]
+    fn footprint_level(&self, si: &SurfaceInteraction, dxy: &DxyInfo) -> Float {
+        let t2dinfo = self.mapping.map(si, dxy);
+        self.mipmap.footprint_level(t2dinfo.dpdx, t2dinfo.dpdy)
+    }
 }
 
 impl<TM, M> ImageTexture<TM, RGBSpectrum<TM>, M>
@@ -100,51 +177,41 @@ impl<TM, M> ImageTexture<TM, RGBSpectrum<TM>, M>
     /// Contructing a new texture with image described by `info`.
     /// The actual image would be looked up from `ref_table`.
     /// If the `ref_table` don't contain such an image, an attempt
-    /// to construct one would be made. If succeed, the texture would
-    /// be returned.
+    /// to construct one would be made, failing with the path of the image
+    /// and the reason why.
     pub fn new(
         info: ImageInfo,
         mapping: M, 
         ref_table: &mut RGBMipMapHashTable<TM>
-    ) -> Option<Self> {
-        let try_strong = match ref_table.entry(info.clone()) {
-            Entry::Occupied(oe) => {
-                oe.get().clone().upgrade()
-            },
-            Entry::Vacant(_) => {
-                None
-            },
-        };
-        if let Some(mipmap) = try_strong {
-            Some(ImageTexture{
-                mapping: mapping,
-                mipmap: mipmap,
-            })
-        } else {
-            let mipmap = MipMap::<TM, RGBSpectrum<TM>>::new(info.clone());
-            if let Some(mipmap) = mipmap {
-                let mipmap = Arc::new(mipmap);
-                ref_table.insert(info, Arc::downgrade(&mipmap));
-                Some(ImageTexture{
-                    mapping: mapping,
-                    mipmap: mipmap,
-                })
-            } else {
-                None
-            }
-        }
+    ) -> Result<Self, TextureError> {
+        ImageTexture::cached(info, mapping, ref_table, MipMap::<TM, RGBSpectrum<TM>>::new)
+    }
+
+    /// Constructing a new texture from the pixels of `buffer`, cached
+    /// in `ref_table` under `id` in place of a filename, which replaces
+    /// `info.name`. If a mipmap is still alive under `id`, it would be
+    /// shared and `buffer` ignored, so changing pixels need new ids.
+    pub fn from_buffer(
+        id: &str,
+        buffer: image::RgbImage,
+        mut info: ImageInfo,
+        mapping: M,
+        ref_table: &mut RGBMipMapHashTable<TM>
+    ) -> Result<Self, TextureError> {
+        info.name = id.to_owned();
+        ImageTexture::cached(info, mapping, ref_table, |info| {
+            let path = info.name.clone();
+            MipMap::<TM, RGBSpectrum<TM>>::from_image(info, image::DynamicImage::ImageRgb8(buffer))
+                .ok_or(TextureError::Empty{path})
+        })
     }
 
     pub fn new_as_arc(
         info: ImageInfo,
         mapping: M, 
         ref_table: &mut RGBMipMapHashTable<TM>
-    ) -> Option<Arc<Texture<Texel=RGBSpectrum<TM>>>> {
-        if let Some(i) = RGBImageTexture::new(info, mapping, ref_table) {
-            Some(Arc::new(i))
-        } else {
-            None
-        }
+    ) -> Result<Arc<Texture<Texel=RGBSpectrum<TM>>>, TextureError> {
+        RGBImageTexture::new(info, mapping, ref_table).map(|i| Arc::new(i) as Arc<Texture<Texel=RGBSpectrum<TM>>>)
     }
 }
 
@@ -155,51 +222,41 @@ impl<TM, M> ImageTexture<TM, Luma<TM>, M>
     /// Contructing a new texture with image described by `info`.
     /// The actual image would be looked up from `ref_table`.
     /// If the `ref_table` don't contain such an image, an attempt
-    /// to construct one would be made. If succeed, the texture would
-    /// be returned.
+    /// to construct one would be made, failing with the path of the image
+    /// and the reason why.
     pub fn new(
         info: ImageInfo,
         mapping: M, 
         ref_table: &mut LumaMipMapHashTable<TM>
-    ) -> Option<Self> {
-        let try_strong = match ref_table.entry(info.clone()) {
-            Entry::Occupied(oe) => {
-                oe.get().clone().upgrade()
-            },
-            Entry::Vacant(_) => {
-                None
-            },
-        };
-        if let Some(mipmap) = try_strong {
-            Some(ImageTexture{
-                mapping: mapping,
-                mipmap: mipmap,
-            })
-        } else {
-            let mipmap = MipMap::<TM, Luma<TM>>::new(info.clone());
-            if let Some(mipmap) = mipmap {
-                let mipmap = Arc::new(mipmap);
-                ref_table.insert(info, Arc::downgrade(&mipmap));
-                Some(ImageTexture{
-                    mapping: mapping,
-                    mipmap: mipmap,
-                })
-            } else {
-                None
-            }
-        }
+    ) -> Result<Self, TextureError> {
+        ImageTexture::cached(info, mapping, ref_table, MipMap::<TM, Luma<TM>>::new)
+    }
+
+    /// Constructing a new texture from the pixels of `buffer`, cached
+    /// in `ref_table` under `id` in place of a filename, which replaces
+    /// `info.name`. If a mipmap is still alive under `id`, it would be
+    /// shared and `buffer` ignored, so changing pixels need new ids.
+    pub fn from_buffer(
+        id: &str,
+        buffer: image::GrayImage,
+        mut info: ImageInfo,
+        mapping: M,
+        ref_table: &mut LumaMipMapHashTable<TM>
+    ) -> Result<Self, TextureError> {
+        info.name = id.to_owned();
+        ImageTexture::cached(info, mapping, ref_table, |info| {
+            let path = info.name.clone();
+            MipMap::<TM, Luma<TM>>::from_image(info, image::DynamicImage::ImageLuma8(buffer))
+                .ok_or(TextureError::Empty{path})
+        })
     }
 
     pub fn new_as_arc(
         info: ImageInfo,
         mapping: M, 
         ref_table: &mut LumaMipMapHashTable<TM>
-    ) -> Option<Arc<Texture<Texel=TM>>> {
-        if let Some(i) = LumaImageTexture::new(info, mapping, ref_table) {
-            Some(Arc::new(i))
-        } else {
-            None
-        }
+    ) -> Result<Arc<Texture<Texel=TM>>, TextureError> {
+        LumaImageTexture::new(info, mapping, ref_table).map(|i| Arc::new(i) as Arc<Texture<Texel=TM>>)
     }
 }
 
@@ -213,55 +270,73 @@ impl<T> MipMap<T, RGBSpectrum<T>>
     where T: BaseNum + image::Primitive + ToNorm + Zero + Copy + 'static,
 {
     /// load a new mipmap with infomation given by `info`
-    fn new(info: ImageInfo) -> Option<MipMap<T, RGBSpectrum<T>>> {
+    pub fn new(info: ImageInfo) -> Result<MipMap<T, RGBSpectrum<T>>, TextureError> {
+        MipMap::<T, RGBSpectrum<T>>::new_with_progress(info, &mut progress::ignore_progress)
+    }
+
+    /// load a new mipmap as `new` does, reporting to `progress`
+    /// the levels of the pyramid built
+    pub fn new_with_progress(
+        info: ImageInfo, progress: &mut FnMut(LoadProgress)
+    ) -> Result<MipMap<T, RGBSpectrum<T>>, TextureError> {
         // treat `info.name` as filename in this case
-        if let Ok(opened) = image::open(info.name.clone()) {
-            let (nx, ny) = opened.dimensions();
-            let np2x = nx.next_power_of_two();
-            let np2y = ny.next_power_of_two();
+        let path = info.name.clone();
+        let opened = image::open(&path).map_err(|error| TextureError::Open{path: path.clone(), error})?;
+        MipMap::<T, RGBSpectrum<T>>::from_image_with_progress(info, opened, progress)
+            .ok_or(TextureError::Empty{path})
+    }
 
-            let miplevels = if np2x > np2y {
-                np2x.trailing_zeros() + 1
+    /// build a new mipmap from the pixels of `opened`, described by `info`.
+    /// `None` if `opened` is empty
+    pub fn from_image(info: ImageInfo, opened: image::DynamicImage) -> Option<MipMap<T, RGBSpectrum<T>>> {
+        MipMap::<T, RGBSpectrum<T>>::from_image_with_progress(info, opened, &mut progress::ignore_progress)
+    }
+
+    /// build a new mipmap as `from_image` does, reporting to `progress`
+    /// the levels of the pyramid built
+    pub fn from_image_with_progress(
+        info: ImageInfo, opened: image::DynamicImage, progress: &mut FnMut(LoadProgress)
+    ) -> Option<MipMap<T, RGBSpectrum<T>>> {
+        let (nx, ny) = opened.dimensions();
+        if nx == 0 || ny == 0 {
+            return None;
+        }
+        let dimensions = pyramid_dimensions(nx, ny);
+        let mut pyramid = Vec::with_capacity(dimensions.len());
+        progress(LoadProgress::new(LoadPhase::TextureLoad, 0, dimensions.len(), 0));
+        for (i, &(dx, dy)) in dimensions.iter().enumerate() {
+            let level = if i == 0 {
+                opened.to_rgb()
             } else {
-                np2y.trailing_zeros() + 1
+                opened.resize_exact(dx, dy, image::FilterType::Lanczos3).to_rgb()
             };
+            let cb: Vec<T> = level.into_raw().into_iter().map(|x| {
+                MipMap::convert_in(info.gamma, info.scale, x)
+            }).collect();
+            pyramid.push(image::ImageBuffer::from_raw(dx, dy, cb).unwrap());
+            progress(LoadProgress::new(LoadPhase::TextureLoad, i + 1, dimensions.len(), 0));
+        }
 
-            let mut pyramid = Vec::with_capacity(miplevels as usize);
-            
-            for i in 0..miplevels {
-                let dx = cmp::max(np2x/(1<<i), 1);
-                let dy = cmp::max(np2y/(1<<i), 1);
-                let cb: Vec<T> = opened.resize_exact(
-                    dx, dy, image::FilterType::Lanczos3
-                ).to_rgb().into_raw().into_iter().map(|x| {
-                    MipMap::convert_in(info.gamma, info.scale, x)
-                }).collect();
-                pyramid.push(image::ImageBuffer::from_raw(dx, dy, cb).unwrap());
-            }
-
-            let z = <T as Zero>::zero();
-            let slice = [z, z, z, z];
-            let mut sum = *RGBSpectrum::from_slice(&slice);
-            let mut count = 0u32;
-            for p in pyramid[0].pixels() {
-                sum = add_two(sum, p);
-                count += 1;
-            }
-            let inv_count = 1. as Float / count as Float;
-
-            Some(MipMap{
-                info: info,
-                pyramid: pyramid,
-                mean: mul_float(sum, inv_count),
-            })
-        } else {
-            None
+        let z = <T as Zero>::zero();
+        let slice = [z, z, z, z];
+        let mut sum = *RGBSpectrum::from_slice(&slice);
+        let mut count = 0u32;
+        for p in pyramid[0].pixels() {
+            sum = add_two(sum, p);
+            count += 1;
         }
+        let inv_count = 1. as Float / count as Float;
+
+        Some(MipMap{
+            info: info,
+            pyramid: pyramid,
+            mean: mul_float(sum, inv_count),
+        })
     }
 
     #[inline]
     fn convert_in<R: ToNorm>(gamma: bool, scale: Float, f: R) -> T {
-        let f = f.to_norm();
+        let f = f.to_norm_checked();
         if gamma {
             <T as ToNorm>::from_norm(inverse_gamma_correct(f)*scale)
         } else {
@@ -275,8 +350,7 @@ impl<T> MipMap<T, RGBSpectrum<T>>
         let buf = buf.into_raw();
         let mut target: Vec<u8> = Vec::with_capacity(buf.len());
         for i in buf {
-            let inorm = i.to_norm();
-            target.push(<u8 as ToNorm>::from_norm(inorm));
+            target.push(<u8 as ToNorm>::from_norm_clamped(i.to_norm()));
         }
         let target = image::RgbImage::from_raw(dim.0, dim.1, target).unwrap();
         target.save(name).unwrap();
@@ -287,49 +361,67 @@ impl<T> MipMap<T, Luma<T>>
     where T: BaseNum + image::Primitive + ToNorm + Zero + Copy + 'static,
 {
     /// load a new mipmap with infomation given by `info`
-    fn new(info: ImageInfo) -> Option<MipMap<T, Luma<T>>> {
+    pub fn new(info: ImageInfo) -> Result<MipMap<T, Luma<T>>, TextureError> {
+        MipMap::<T, Luma<T>>::new_with_progress(info, &mut progress::ignore_progress)
+    }
+
+    /// load a new mipmap as `new` does, reporting to `progress`
+    /// the levels of the pyramid built
+    pub fn new_with_progress(
+        info: ImageInfo, progress: &mut FnMut(LoadProgress)
+    ) -> Result<MipMap<T, Luma<T>>, TextureError> {
         // treat `info.name` as filename in this case
-        if let Ok(opened) = image::open(info.name.clone()) {
-            let (nx, ny) = opened.dimensions();
-            let np2x = nx.next_power_of_two();
-            let np2y = ny.next_power_of_two();
+        let path = info.name.clone();
+        let opened = image::open(&path).map_err(|error| TextureError::Open{path: path.clone(), error})?;
+        MipMap::<T, Luma<T>>::from_image_with_progress(info, opened, progress)
+            .ok_or(TextureError::Empty{path})
+    }
 
-            let miplevels = if np2x > np2y {
-                np2x.trailing_zeros() + 1
+    /// build a new mipmap from the pixels of `opened`, described by `info`.
+    /// `None` if `opened` is empty
+    pub fn from_image(info: ImageInfo, opened: image::DynamicImage) -> Option<MipMap<T, Luma<T>>> {
+        MipMap::<T, Luma<T>>::from_image_with_progress(info, opened, &mut progress::ignore_progress)
+    }
+
+    /// build a new mipmap as `from_image` does, reporting to `progress`
+    /// the levels of the pyramid built
+    pub fn from_image_with_progress(
+        info: ImageInfo, opened: image::DynamicImage, progress: &mut FnMut(LoadProgress)
+    ) -> Option<MipMap<T, Luma<T>>> {
+        let (nx, ny) = opened.dimensions();
+        if nx == 0 || ny == 0 {
+            return None;
+        }
+        let dimensions = pyramid_dimensions(nx, ny);
+        let mut pyramid = Vec::with_capacity(dimensions.len());
+        progress(LoadProgress::new(LoadPhase::TextureLoad, 0, dimensions.len(), 0));
+        for (i, &(dx, dy)) in dimensions.iter().enumerate() {
+            let level = if i == 0 {
+                opened.to_luma()
             } else {
-                np2y.trailing_zeros() + 1
+                opened.resize_exact(dx, dy, image::FilterType::Lanczos3).to_luma()
             };
+            let cb: Vec<T> = level.into_raw().into_iter().map(|x| {
+                MipMap::convert_in(info.gamma, info.scale, x)
+            }).collect();
+            pyramid.push(image::ImageBuffer::from_raw(dx, dy, cb).unwrap());
+            progress(LoadProgress::new(LoadPhase::TextureLoad, i + 1, dimensions.len(), 0));
+        }
 
-            let mut pyramid = Vec::with_capacity(miplevels as usize);
-            
-            for i in 0..miplevels {
-                let dx = cmp::max(np2x/(1<<i), 1);
-                let dy = cmp::max(np2y/(1<<i), 1);
-                let cb: Vec<T> = opened.resize_exact(
-                    dx, dy, image::FilterType::Lanczos3
-                ).to_luma().into_raw().into_iter().map(|x| {
-                    MipMap::convert_in(info.gamma, info.scale, x)
-                }).collect();
-                pyramid.push(image::ImageBuffer::from_raw(dx, dy, cb).unwrap());
-            }
-
-            let z = <T as Zero>::zero();
-            let mut sum = Luma{data:[z]};
-            let mut count = 0u32;
-            for p in pyramid[0].pixels() {
-                sum = add_two(sum, p);
-                count += 1;
-            }
-            let inv_count = 1. as Float / count as Float;
-
-            Some(MipMap{
-                info: info,
-                pyramid: pyramid,
-                mean: mul_float(sum, inv_count),
-            })
-        } else {
-            None
+        let z = <T as Zero>::zero();
+        let mut sum = Luma{data:[z]};
+        let mut count = 0u32;
+        for p in pyramid[0].pixels() {
+            sum = add_two(sum, p);
+            count += 1;
         }
+        let inv_count = 1. as Float / count as Float;
+
+        Some(MipMap{
+            info: info,
+            pyramid: pyramid,
+            mean: mul_float(sum, inv_count),
+        })
     }
 
     pub fn save(&self, idx: usize, name: &str) {
@@ -338,8 +430,7 @@ impl<T> MipMap<T, Luma<T>>
         let buf = buf.into_raw();
         let mut target: Vec<u8> = Vec::with_capacity(buf.len());
         for i in buf {
-            let inorm = i.to_norm();
-            target.push(<u8 as ToNorm>::from_norm(inorm));
+            target.push(<u8 as ToNorm>::from_norm_clamped(i.to_norm()));
         }
         let target = image::GrayImage::from_raw(dim.0, dim.1, target).unwrap();
         target.save(name).unwrap();
@@ -350,32 +441,48 @@ impl<T, TP> MipMap<T, TP>
     where T: BaseNum + image::Primitive + ToNorm + Zero + Copy + 'static,
           TP: Pixel<Subpixel=T> + 'static
 {
+    /// count of levels in the pyramid
+    #[inline]
+    pub fn levels(&self) -> usize {
+        self.pyramid.len()
+    }
+
+    /// dimensions of the `miplevel`th level
+    #[inline]
+    pub fn level_dimensions(&self, miplevel: usize) -> (u32, u32) {
+        self.pyramid[miplevel].dimensions()
+    }
+
     #[inline]
     fn texel_isize(&self, miplevel: usize, p: Point2<isize>) -> TP {
         let frame = &self.pyramid[miplevel];
         let (dx, dy) = frame.dimensions();
-        let (dx, dy) = (dx as usize, dy as usize);
-        let p = if p.x as usize >= dx || p.y as usize >= dy {
+        let (dx, dy) = (dx as isize, dy as isize);
+        let p = if p.x < 0 || p.y < 0 || p.x >= dx || p.y >= dy {
             match self.info.wrapping {
                 ImageWrapMode::Black => {
-                    let z = <T as Zero>::zero();
-                    let slice = [z, z, z, z];
-                    return *TP::from_slice(&slice);
+                    return black();
                 },
                 ImageWrapMode::Clamp => {
                     (
-                        if p.x as usize >= dx {dx-1} else {p.x as usize},
-                        if p.y as usize >= dy {dy-1} else {p.y as usize}
+                        cmp::min(cmp::max(p.x, 0), dx-1),
+                        cmp::min(cmp::max(p.y, 0), dy-1)
                     )
                 },
                 ImageWrapMode::Repeat => {
                     (
-                        (p.x % dx as isize).abs() as usize,
-                        (p.y % dy as isize).abs() as usize
+                        (p.x % dx + dx) % dx,
+                        (p.y % dy + dy) % dy
                     )
                 },
+                ImageWrapMode::LatLong => {
+                    // rows past a pole continue down the opposite meridian
+                    let y = (p.y % (2 * dy) + 2 * dy) % (2 * dy);
+                    let (x, y) = if y >= dy { (p.x + dx / 2, 2 * dy - 1 - y) } else { (p.x, y) };
+                    ((x % dx + dx) % dx, y)
+                },
             }
-        } else { (p.x as usize, p.y as usize) };
+        } else { (p.x, p.y) };
         *frame.get_pixel(p.0 as u32, p.1 as u32)
     }
 
@@ -387,9 +494,7 @@ impl<T, TP> MipMap<T, TP>
         let p = if p.x >= dx || p.y >= dy {
             match self.info.wrapping {
                 ImageWrapMode::Black => {
-                    let z = <T as Zero>::zero();
-                    let slice = [z, z, z, z];
-                    return *TP::from_slice(&slice);
+                    return black();
                 },
                 ImageWrapMode::Clamp => {
                     (
@@ -403,6 +508,9 @@ impl<T, TP> MipMap<T, TP>
                         p.y % dy
                     )
                 },
+                ImageWrapMode::LatLong => {
+                    return self.texel_isize(miplevel, Point2::new(p.x as isize, p.y as isize));
+                },
             }
         } else { (p.x, p.y) };
         *frame.get_pixel(p.0 as u32, p.1 as u32)
@@ -428,24 +536,25 @@ impl<T, TP> MipMap<T, TP>
         let (nx, ny) = self.pyramid[miplevel].dimensions();
         let s = st.x * nx as Float - 0.5 as Float;
         let t = st.y * ny as Float - 0.5 as Float;
-        let s0 = s.floor() as usize;
-        let t0 = t.floor() as usize;
+        let s0 = s.floor() as isize;
+        let t0 = t.floor() as isize;
         let ds = s - s.floor();
         let dt = t - t.floor();
         let one = 1.0 as Float;
         add_two(
             add_two(
-                mul_float(self.texel(miplevel, Point2::new(s0, t0)), (one - ds) * (one - dt)),
-                &mul_float(self.texel(miplevel, Point2::new(s0, t0 + 1)), (one - ds) * dt)
+                mul_float(self.texel_isize(miplevel, Point2::new(s0, t0)), (one - ds) * (one - dt)),
+                &mul_float(self.texel_isize(miplevel, Point2::new(s0, t0 + 1)), (one - ds) * dt)
             ),
             &add_two(
-                mul_float(self.texel(miplevel, Point2::new(s0+1, t0)), ds * (one - dt)),
-                &mul_float(self.texel(miplevel, Point2::new(s0+1, t0+1)), ds * dt)
+                mul_float(self.texel_isize(miplevel, Point2::new(s0+1, t0)), ds * (one - dt)),
+                &mul_float(self.texel_isize(miplevel, Point2::new(s0+1, t0+1)), ds * dt)
             )
         )
     }
 
-    fn look_up(&self, st: Point2f, dst0: Vector2f, dst1: Vector2f) -> TP {
+    /// look up the texel at `st` with footprint given by `dst0` and `dst1`
+    pub fn look_up(&self, st: Point2f, dst0: Vector2f, dst1: Vector2f) -> TP {
         if self.info.trilinear {
             let width = dst0.x.max(dst0.y).max(dst1.x).max(dst1.y);
             self.look_up_tri(st, width)
@@ -457,11 +566,17 @@ impl<T, TP> MipMap<T, TP>
             };
             let mut minor = dstmin.magnitude();
             let major = dstmaj.magnitude();
-            if minor == 0.0 as Float {
+            if !major.is_finite() {
+                // footprints of degenerate parameterizations cover everything
+                self.texel(self.pyramid.len() - 1, Point2::new(0, 0))
+            } else if minor == 0.0 as Float {
                 self.triangle_filter(0, st)
             } else {
-                if minor * self.info.max_aniso < major {
-                    let scale = major / (minor * self.info.max_aniso);
+                // lengthen the minor axis only, and never past the major
+                // one, so that the axes keep their roles in the filter
+                let max_aniso = self.info.max_aniso.max(1. as Float);
+                if minor * max_aniso < major {
+                    let scale = major / (minor * max_aniso);
                     minor *= scale;
                     dstmin *= scale;
                 }
@@ -476,6 +591,30 @@ impl<T, TP> MipMap<T, TP>
         }
     }
 
+    /// level `look_up` filters at given the footprint `dst0` and
+    /// `dst1`, in $[0, levels - 1]$, with fractions in between levels
+    pub fn footprint_level(&self, dst0: Vector2f, dst1: Vector2f) -> Float {
+        let top = (self.pyramid.len() - 1) as Float;
+        let level = if self.info.trilinear {
+            self.find_level(dst0.x.max(dst0.y).max(dst1.x).max(dst1.y))
+        } else {
+            let (minor, major) = if dst0.magnitude2() < dst1.magnitude2() {
+                (dst0.magnitude(), dst1.magnitude())
+            } else {
+                (dst1.magnitude(), dst0.magnitude())
+            };
+            if !major.is_finite() {
+                top
+            } else if minor == 0.0 as Float {
+                0.0 as Float
+            } else {
+                let max_aniso = self.info.max_aniso.max(1. as Float);
+                self.find_level(minor.max(major / max_aniso))
+            }
+        };
+        level.max(0.0 as Float).min(top)
+    }
+
     fn ewa_filter(&self, miplevel: usize, st: Point2f, dstmaj: Vector2f, dstmin: Vector2f) -> TP {
         if miplevel >= self.pyramid.len() {
             return self.texel(self.pyramid.len() -1, Point2::new(0, 0));
@@ -498,7 +637,9 @@ impl<T, TP> MipMap<T, TP>
         b *= inv_f;
         c *= inv_f;
 
-        // compute bounding box
+        // compute bounding box, which is left unclamped such that
+        // footprints straddling a border get wrapped around it by
+        // `texel_isize` under `ImageWrapMode::Repeat` or `LatLong`
         let det = -b * b + 4. as Float * a * c;
         let inv2_det = 1.0 as Float / det * 2.0 as Float;
         let usqrt = (det*c).sqrt();
@@ -507,9 +648,7 @@ impl<T, TP> MipMap<T, TP>
         let s1 = (s + inv2_det * usqrt).ceil() as isize;
         let t0 = (t - inv2_det * vsqrt).ceil() as isize;
         let t1 = (t + inv2_det * vsqrt).ceil() as isize;
-        let z = <T as Zero>::zero();
-        let slice = [z, z, z, z];
-        let mut sum = *TP::from_slice(&slice);
+        let mut sum: TP = black();
         let mut sumwt = 0.0 as Float;
         for it in t0..t1+1 {
             let tt = it as Float - t;
@@ -534,10 +673,34 @@ impl<T, TP> MipMap<T, TP>
     #[inline]
     fn find_level(&self, width: Float) -> Float {
         // find an level such that $width\times width$ covers about
-        // four texels
-        let width = width.max(1e-8 as Float).log2();
-        (self.pyramid.len() - 1) as Float * width
+        // four texels. The top level covers the whole texture
+        let width = width.max(float::consts::FILTER_WIDTH_MIN).log2();
+        (self.pyramid.len() - 1) as Float + width
+    }
+}
+
+/// dimensions of each level of the pyramid built from an `nx` by `ny`
+/// image, each halving the last one's dimensions independently
+fn pyramid_dimensions(nx: u32, ny: u32) -> Vec<(u32, u32)> {
+    let mut ret = vec![(nx, ny)];
+    let (mut dx, mut dy) = (nx, ny);
+    while dx > 1 || dy > 1 {
+        dx = cmp::max((dx + 1) / 2, 1);
+        dy = cmp::max((dy + 1) / 2, 1);
+        ret.push((dx, dy));
     }
+    ret
+}
+
+// a pixel with all its channels zeroed, however many there are
+#[inline]
+fn black<TM, TP>() -> TP
+    where TP: Pixel<Subpixel=TM>,
+          TM: BaseNum + image::Primitive + Copy,
+{
+    let z = <TM as Zero>::zero();
+    let slice = [z, z, z, z];
+    *TP::from_slice(&slice[..TP::channel_count() as usize])
 }
 
 #[inline]
@@ -571,11 +734,24 @@ fn add_two<TM, TP>(pix0: TP, pix1: &TP) -> TP
     pix0.map2(&pix1, |a, b| a+b)
 }
 
+/// default bound on the eccentricity of EWA footprints
+pub const DEFAULT_MAX_ANISO: Float = 16. as Float;
+
+fn default_max_aniso() -> Float {
+    DEFAULT_MAX_ANISO
+}
+
 /// Information abount an image
 #[derive(PartialEq, Clone, Deserialize, Serialize)]
 pub struct ImageInfo {
     pub name: String,
+    /// filter with a trilinear filter instead of EWA
+    #[serde(default)]
     pub trilinear: bool,
+    /// bound on the ratio between the major and minor axes of EWA
+    /// footprints, beyond which the minor axis is lengthened. Values
+    /// below `1` are treated as `1`
+    #[serde(default = "default_max_aniso")]
     pub max_aniso: Float,
     pub wrapping: ImageWrapMode,
     pub gamma: bool,
@@ -606,6 +782,9 @@ pub enum ImageWrapMode {
     Black,
     /// clamp to the boundary texel 
     Clamp,
+    /// repeat along `s`, and continue across the poles along `t`,
+    /// as for latitude-longitude maps
+    LatLong,
 }
 
 // TODO:
@@ -9,6 +9,7 @@
 //! Commonly used implementations of `Texture`.
 
 use super::*;
+use spectrum::{Spectrum, RGBSpectrumf};
 use std::ops;
 
 /// A constant texture
@@ -54,6 +55,12 @@ impl<T0: Send + Sync, T1: Send + Sync> Texture for ProductTexture<T0, T1>
     fn mean(&self) -> Self::Texel {
         self.t0.mean() * self.t1.mean()
     }
+
+    /// the coarser of both
+    #[inline]
+    fn footprint_level(&self, si: &SurfaceInteraction, dxy: &DxyInfo) -> Float {
+        self.t0.footprint_level(si, dxy).max(self.t1.footprint_level(si, dxy))
+    }
 }
 
 /// Texture adapter that takes two textures, and an additional `Float` texture,
@@ -91,6 +98,153 @@ impl<T0: Send + Sync, T1: Send + Sync, L: Send + Sync> Texture for MixTexture<T0
         let t1l = self.t1.mean() * lerp;
         t0l + t1l
     }
+
+    /// the coarsest of all three
+    #[inline]
+    fn footprint_level(&self, si: &SurfaceInteraction, dxy: &DxyInfo) -> Float {
+        self.t0.footprint_level(si, dxy)
+            .max(self.t1.footprint_level(si, dxy))
+            .max(self.l.footprint_level(si, dxy))
+    }
+}
+
+/// Texture adapter that scales the values of `inner` by a constant
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScaleTexture<T> {
+    pub inner: T,
+    pub scale: Float,
+}
+
+impl<T> Texture for ScaleTexture<T>
+    where T: Texture,
+          T::Texel: ops::Mul<Float>,
+{
+    type Texel = <T::Texel as ops::Mul<Float>>::Output;
+
+    #[inline]
+    fn evaluate(&self, si: &SurfaceInteraction, dxy: &DxyInfo) -> Self::Texel {
+        self.inner.evaluate(si, dxy) * self.scale
+    }
+
+    #[inline]
+    fn mean(&self) -> Self::Texel {
+        self.inner.mean() * self.scale
+    }
+
+    #[inline]
+    fn footprint_level(&self, si: &SurfaceInteraction, dxy: &DxyInfo) -> Float {
+        self.inner.footprint_level(si, dxy)
+    }
+}
+
+/// Texture adapter that looks up `inner` at transformed uv-coordinates.
+///
+/// The coordinates are scaled by `scaling`, rotated counterclockwise
+/// by `rotation` in radians around the origin, then shifted by `shifting`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UvTransformTexture<T> {
+    pub inner: T,
+    pub scaling: Vector2f,
+    pub shifting: Vector2f,
+    pub rotation: Float,
+}
+
+impl<T> UvTransformTexture<T> {
+    #[inline]
+    fn transform_vector(&self, v: Vector2f) -> Vector2f {
+        let v = v.mul_element_wise(self.scaling);
+        let (sin, cos) = self.rotation.sin_cos();
+        Vector2f::new(cos * v.x - sin * v.y, sin * v.x + cos * v.y)
+    }
+
+    // `si` and `dxy` as seen by `inner`
+    #[inline]
+    fn transform<'b>(&self, si: &SurfaceInteraction<'b>, dxy: &DxyInfo) -> (SurfaceInteraction<'b>, DxyInfo) {
+        let mut si = *si;
+        si.uv = Point2f::from_vec(self.transform_vector(si.uv.to_vec()) + self.shifting);
+        let mut dxy = *dxy;
+        let dx = self.transform_vector(Vector2f::new(dxy.dudx, dxy.dvdx));
+        let dy = self.transform_vector(Vector2f::new(dxy.dudy, dxy.dvdy));
+        dxy.dudx = dx.x;
+        dxy.dvdx = dx.y;
+        dxy.dudy = dy.x;
+        dxy.dvdy = dy.y;
+        (si, dxy)
+    }
+}
+
+impl<T: Texture> Texture for UvTransformTexture<T> {
+    type Texel = T::Texel;
+
+    #[inline]
+    fn evaluate(&self, si: &SurfaceInteraction, dxy: &DxyInfo) -> T::Texel {
+        let (si, dxy) = self.transform(si, dxy);
+        self.inner.evaluate(&si, &dxy)
+    }
+
+    #[inline]
+    fn mean(&self) -> T::Texel {
+        self.inner.mean()
+    }
+
+    #[inline]
+    fn footprint_level(&self, si: &SurfaceInteraction, dxy: &DxyInfo) -> Float {
+        let (si, dxy) = self.transform(si, dxy);
+        self.inner.footprint_level(&si, &dxy)
+    }
+}
+
+/// Texels that can be clamped into a range
+pub trait ClampTexel {
+    /// clamp every channel into $[min, max]$
+    fn clamp_texel(self, min: Float, max: Float) -> Self;
+}
+
+impl ClampTexel for Float {
+    #[inline]
+    fn clamp_texel(self, min: Float, max: Float) -> Float {
+        float::clamp(self, min, max)
+    }
+}
+
+impl ClampTexel for RGBSpectrumf {
+    #[inline]
+    fn clamp_texel(self, min: Float, max: Float) -> RGBSpectrumf {
+        self.clamp(min, max)
+    }
+}
+
+/// Texture adapter that clamps the values of `inner` into $[min, max]$
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClampTexture<T> {
+    pub inner: T,
+    pub min: Float,
+    pub max: Float,
+}
+
+impl<T> Texture for ClampTexture<T>
+    where T: Texture,
+          T::Texel: ClampTexel,
+{
+    type Texel = T::Texel;
+
+    #[inline]
+    fn evaluate(&self, si: &SurfaceInteraction, dxy: &DxyInfo) -> T::Texel {
+        self.inner.evaluate(si, dxy).clamp_texel(self.min, self.max)
+    }
+
+    #[inline]
+    // TODO: inappropriate. fix this
+    fn mean(&self) -> T::Texel {
+        self.inner.mean().clamp_texel(self.min, self.max)
+    }
+
+    #[inline]
+    fn footprint_level(&self, si: &SurfaceInteraction, dxy: &DxyInfo) -> Float {
+        self.inner.footprint_level(si, dxy)
+    }
 }
 
 pub mod image;
+pub mod curve;
+pub mod fallback;